@@ -0,0 +1,284 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::config::Config;
+use crate::date;
+use crate::metrics;
+use crate::protocol::pktline::PacketWriter;
+use crate::push_cert::push_cert_nonce;
+use crate::refs::{self, RefValue};
+use crate::repo::git_path;
+use crate::store::ObjectId;
+
+/// The side of a transfer refs are advertised for, `git fetch` talks to
+/// upload-pack and `git push` to receive-pack
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Service {
+    UploadPack,
+    ReceivePack,
+}
+
+impl Service {
+    /// The name the service goes by, eg. in "git-upload-pack"
+    pub fn name(self) -> &'static str {
+        match self {
+            Service::UploadPack => "upload-pack",
+            Service::ReceivePack => "receive-pack",
+        }
+    }
+
+    /// The config section with the service's own settings
+    fn section(self) -> &'static str {
+        match self {
+            Service::UploadPack => "uploadpack",
+            Service::ReceivePack => "receivepack",
+        }
+    }
+}
+
+/// Refs kept out of the advertisement, from `transfer.hideRefs` and
+/// `<service>.hideRefs`.
+///
+/// Each value hides the ref it names and every ref below it, eg.
+/// "refs/pull" hides "refs/pull/1/head", while a leading '!' shows them
+/// again. The last value matching a ref decides.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HideRefs {
+    patterns: Vec<String>,
+}
+
+impl HideRefs {
+    pub fn load(config: &Config, service: Service) -> HideRefs {
+        let keys = ["transfer.hiderefs".to_string(), format!("{}.hiderefs", service.section())];
+
+        let patterns = config.entries()
+            .filter(|entry| keys.contains(&entry.key))
+            .filter_map(|entry| entry.value.as_deref())
+            .map(|value| value.trim_end_matches('/').to_string())
+            .collect();
+
+        HideRefs { patterns }
+    }
+
+    pub fn is_hidden(&self, name: &str) -> bool {
+        self.patterns.iter().rev()
+            .find_map(|pattern| {
+                let (shown, pattern) = match pattern.strip_prefix('!') {
+                    Some(pattern) => (true, pattern),
+                    None => (false, pattern.as_str()),
+                };
+
+                // A leading '^' matches the name before any namespace is
+                // stripped, which without namespaces is the same thing
+                let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+
+                let matches = name.strip_prefix(pattern).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+
+                matches.then_some(!shown)
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// The repositories borrowed from through `objects/info/alternates`, as
+/// the git dirs holding each alternate object directory
+fn alternate_repos() -> Vec<PathBuf> {
+    let objects = git_path("objects");
+
+    let Ok(contents) = fs::read_to_string(objects.join("info/alternates")) else {
+        return Vec::new();
+    };
+
+    contents.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| objects.join(line).canonicalize().ok())
+        .filter(|path| path.ends_with("objects"))
+        .filter_map(|path| path.parent().map(PathBuf::from))
+        .collect()
+}
+
+/// The tips of the refs in alternate repositories, which a pushing client
+/// may assume are already here.
+///
+/// They're listed by core.alternateRefsCommand, run with the alternate
+/// repository as its argument, or failing that by `for-each-ref` in the
+/// alternate limited to core.alternateRefsPrefixes.
+pub fn alternate_tips(config: &Config) -> Vec<ObjectId> {
+    let mut tips = Vec::new();
+
+    for repo in alternate_repos() {
+        let mut command = match config.get("core.alternaterefscommand") {
+            Some(alternate_refs_command) => {
+                let mut command = Command::new("sh");
+                command.arg("-c")
+                    .arg(format!("{alternate_refs_command} \"$@\""))
+                    .arg(alternate_refs_command)
+                    .arg(&repo);
+                command
+            }
+            None => {
+                let Ok(gitty) = env::current_exe() else {
+                    continue;
+                };
+
+                let prefixes = config.get("core.alternaterefsprefixes").unwrap_or_default();
+
+                let mut command = Command::new(gitty);
+                command.arg("for-each-ref")
+                    .arg("--format=%(objectname)")
+                    .args(prefixes.split_whitespace())
+                    .env("GIT_DIR", &repo)
+                    .env_remove("GIT_COMMON_DIR");
+                command
+            }
+        };
+
+        let Ok(output) = command.stdin(Stdio::null()).stderr(Stdio::inherit()).output() else {
+            eprintln!("unable to list the refs of {}", repo.display());
+            continue;
+        };
+
+        // Each line is an object id alone, and anything else ends the list
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let Some(id) = (line.len() == 40).then(|| ObjectId::try_from(line.to_string()).ok()).flatten() else {
+                eprintln!("warning: invalid line while parsing alternate refs: {line}");
+                break;
+            };
+
+            tips.push(id);
+        }
+    }
+
+    tips
+}
+
+/// Writes the ref advertisement a server opens a transfer with:
+///
+///   <id> ' ' <refname> '\0' <capabilities> '\n' (first ref only)
+///   <id> ' ' <refname> '\n'
+///   ...
+///   "0000"
+///
+/// each as a pkt-line. upload-pack starts with HEAD and follows annotated
+/// tags with what they peel to as "<tag>^{}", while receive-pack ends
+/// with the alternate tips it doesn't advertise already as ".have" lines,
+/// and with nothing to show gives its capabilities on a "capabilities^{}"
+/// line for the null id. Hidden refs are left out.
+///
+/// With `receive.certNonceSeed` set, receive-pack offers "push-cert" with
+/// a nonce for signed pushes to sign over, which is given back to check
+/// their certificates against, and with `uploadpack.allowFilter`
+/// upload-pack offers "filter" for partial clones.
+pub fn advertise_refs<W: Write>(out: &mut W, service: Service, config: &Config) -> io::Result<Option<String>> {
+    let mut lines = advertised_refs(config, service);
+
+    if service == Service::ReceivePack {
+        let mut seen: HashSet<ObjectId> = lines.iter().map(|(id, _)| *id).collect();
+
+        for tip in alternate_tips(config) {
+            if seen.insert(tip) {
+                lines.push((tip, ".have".to_string()));
+            }
+        }
+
+        if lines.is_empty() {
+            lines.push((ObjectId::NULL, "capabilities^{}".to_string()));
+        }
+    }
+
+    let mut capabilities = Vec::new();
+
+    if lines.first().is_some_and(|(_, name)| name == "HEAD") {
+        if let Some(RefValue::Symbolic(target)) = refs::read_ref("HEAD") {
+            capabilities.push(format!("symref=HEAD:{target}"));
+        }
+    }
+
+    let mut nonce = None;
+
+    if service == Service::ReceivePack {
+        capabilities.extend(["report-status", "delete-refs", "ofs-delta"].map(str::to_string));
+
+        // Pushes are asked to be signed over a nonce, when there's a seed
+        // to make one with
+        nonce = push_cert_nonce(config, &push_cert_path(), date::now());
+
+        if let Some(nonce) = &nonce {
+            capabilities.push(format!("push-cert={nonce}"));
+        }
+    }
+
+    if service == Service::UploadPack {
+        capabilities.extend(["side-band-64k", "side-band", "ofs-delta", "no-progress"].map(str::to_string));
+
+        if config.get_bool("uploadpack.allowFilter").unwrap_or(false) {
+            capabilities.push("filter".to_string());
+        }
+    }
+
+    capabilities.push("object-format=sha1".to_string());
+    capabilities.push(format!("agent=gitty/{}", env!("CARGO_PKG_VERSION")));
+
+    let mut packets = PacketWriter::new(Vec::new());
+
+    for (i, (id, name)) in lines.iter().enumerate() {
+        match i {
+            0 => packets.write_line(&format!("{id} {name}\0{}", capabilities.join(" ")))?,
+            _ => packets.write_line(&format!("{id} {name}"))?,
+        }
+    }
+
+    packets.write_flush()?;
+
+    let advertisement = packets.into_inner();
+    out.write_all(&advertisement)?;
+
+    let labels = [("service", service.name())];
+    let refs = lines.iter().filter(|(_, name)| name != "capabilities^{}").count();
+
+    metrics::count("gitty_advertisements_total", &labels, 1);
+    metrics::count("gitty_advertised_refs_total", &labels, refs as u64);
+    metrics::count("gitty_advertisement_bytes_total", &labels, advertisement.len() as u64);
+
+    Ok(nonce)
+}
+
+/// The refs `service` advertises, as (id, name), less receive-pack's
+/// ".have" lines. upload-pack starts with HEAD and follows annotated tags
+/// with what they peel to as "<tag>^{}". Hidden refs are left out.
+pub fn advertised_refs(config: &Config, service: Service) -> Vec<(ObjectId, String)> {
+    let hidden = HideRefs::load(config, service);
+
+    let mut lines = Vec::new();
+
+    let head = refs::resolve_ref("HEAD").filter(|_| service == Service::UploadPack && !hidden.is_hidden("HEAD"));
+
+    if let Some(head) = head {
+        lines.push((head, "HEAD".to_string()));
+    }
+
+    for (name, id) in refs::list_refs("refs/") {
+        if hidden.is_hidden(&name) {
+            continue;
+        }
+
+        lines.push((id, name.clone()));
+
+        if service == Service::UploadPack {
+            if let Some(peeled) = refs::peel_ref(&name, id).filter(|&peeled| peeled != id) {
+                lines.push((peeled, format!("{name}^{{}}")));
+            }
+        }
+    }
+
+    lines
+}
+
+/// The path receive-pack makes its nonces for, the directory it's serving
+pub fn push_cert_path() -> String {
+    env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_default()
+}
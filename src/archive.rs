@@ -0,0 +1,553 @@
+use std::io::{self, Read, Write};
+
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+
+use crate::attributes::Attributes;
+use crate::date::{civil_from_days, local_offset};
+use crate::pathspec::Pathspec;
+use crate::pretty::{format_subst, PrettyOptions};
+use crate::store::tree::{MODE_GITLINK, MODE_SYMLINK, MODE_TREE};
+use crate::store::util::hash_object;
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// Tar output is written in records of this many bytes, 20 blocks
+const TAR_RECORD_SIZE: usize = TAR_BLOCK_SIZE * 20;
+
+/// The largest size and mtime the octal fields of a ustar header hold
+const USTAR_MAX_SIZE: u64 = 0o77777777777;
+const USTAR_MAX_MTIME: i64 = 0o77777777777;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub const ALL: [&'static str; 2] = ["tar", "zip"];
+
+    pub fn parse(name: &str) -> Option<ArchiveFormat> {
+        match name {
+            "tar" => Some(ArchiveFormat::Tar),
+            "zip" => Some(ArchiveFormat::Zip),
+            _ => None,
+        }
+    }
+
+    /// The format a file's name suggests, eg. "out.zip"
+    pub fn from_file_name(name: &str) -> Option<ArchiveFormat> {
+        let (_, extension) = name.rsplit_once('.')?;
+        ArchiveFormat::parse(extension)
+    }
+}
+
+/// What goes into an archive besides the tree's files
+#[derive(Debug, Clone)]
+pub struct ArchiveOptions {
+    pub format: ArchiveFormat,
+
+    /// Put before every path, eg. "project/"
+    pub prefix: String,
+
+    /// Only what these paths match goes in
+    pub pathspec: Pathspec,
+
+    /// The commit the tree is from, recorded in the archive's comment
+    pub commit: Option<ObjectId>,
+
+    /// The time every entry is given, seconds since the epoch: the commit
+    /// time, or failing a commit, the time the archive was made, unless
+    /// another is asked for
+    pub mtime: i64,
+
+    /// Files from outside the tree, put in after it
+    pub extra_files: Vec<ExtraFile>,
+
+    /// Permission bits taken from entries in a tar archive, tar.umask
+    pub tar_umask: u32,
+}
+
+/// A file added to an archive as well as the tree's, as `--add-file`
+/// adds them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtraFile {
+    /// Where it goes, before the prefix
+    pub path: String,
+
+    /// `MODE_BLOB`, or `MODE_EXECUTABLE` for an executable
+    pub mode: u32,
+
+    pub data: Vec<u8>,
+}
+
+/// An entry as it goes into the archive
+struct Entry<'a> {
+    /// With the prefix, and a trailing '/' for directories
+    path: &'a str,
+    id: ObjectId,
+    mode: u32,
+    data: &'a [u8],
+}
+
+/// Writes an archive of `tree` to `out`, as `git archive` would: each
+/// directory before what's in it, in tree order, and submodules as empty
+/// directories, then the extra files. Paths with the `export-ignore`
+/// attribute are left out, and files with `export-subst` have
+/// "$Format:...$" replaced.
+pub fn write_archive(out: impl Write, tree: ObjectId, options: &ArchiveOptions, attributes: &mut Attributes)
+    -> io::Result<()>
+{
+    let mut writer: Box<dyn ArchiveWriter> = match options.format {
+        ArchiveFormat::Tar => Box::new(TarWriter::new(out, options)?),
+        ArchiveFormat::Zip => Box::new(ZipWriter::new(out, options)),
+    };
+
+    // With a prefix that's a directory, the directory goes in first
+    let prefix_dir = options.prefix.trim_end_matches('/');
+
+    if options.prefix.ends_with('/') && !prefix_dir.is_empty() {
+        let path = format!("{prefix_dir}/");
+        writer.write_entry(&Entry { path: &path, id: tree, mode: MODE_TREE | 0o777, data: &[] })?;
+    }
+
+    // What a file marked export-subst shows
+    let commit = options.commit.and_then(|id| Some((id, GitObjectStore::get(id)?.data)));
+
+    TreeWalk { options, attributes, commit, writer: writer.as_mut(), pending_dirs: Vec::new() }.walk(tree, "")?;
+
+    for file in &options.extra_files {
+        let path = format!("{}{}", options.prefix, file.path);
+        writer.write_entry(&Entry { path: &path, id: hash_object("blob", &file.data), mode: file.mode, data: &file.data })?;
+    }
+
+    writer.finish()
+}
+
+/// Goes through a tree, writing what's selected. A directory is only
+/// written once something in it is, unless it's selected as a whole.
+struct TreeWalk<'a> {
+    options: &'a ArchiveOptions,
+    attributes: &'a mut Attributes,
+    commit: Option<(ObjectId, GitObjectData)>,
+    writer: &'a mut dyn ArchiveWriter,
+
+    /// Directories found on the way down, but not yet written
+    pending_dirs: Vec<(String, ObjectId, u32)>,
+}
+
+impl TreeWalk<'_> {
+    fn walk(&mut self, tree: ObjectId, base: &str) -> io::Result<()> {
+        let Some(GitObjectData::Tree { entries }) = GitObjectStore::get(tree).map(|object| object.data) else {
+            return Err(io::Error::other(format!("unable to read tree ({tree})")));
+        };
+
+        for entry in entries {
+            let path = format!("{base}{}", String::from_utf8_lossy(&entry.path));
+
+            match entry.mode {
+                MODE_TREE | MODE_GITLINK => {
+                    let pending = self.pending_dirs.len();
+
+                    if !self.options.pathspec.matches(&path) && !self.options.pathspec.matches_dir(&path) {
+                        continue;
+                    }
+
+                    if self.attributes.attributes_for(&format!("{path}/")).is_set("export-ignore") {
+                        continue;
+                    }
+
+                    self.pending_dirs.push((format!("{path}/"), entry.id, entry.mode));
+
+                    if self.options.pathspec.matches(&path) {
+                        self.write_pending()?;
+                    }
+
+                    if entry.mode == MODE_TREE {
+                        self.walk(entry.id, &format!("{path}/"))?;
+                    }
+
+                    self.pending_dirs.truncate(pending);
+                }
+                _ if self.options.pathspec.matches(&path) => {
+                    let attrs = self.attributes.attributes_for(&path);
+
+                    if attrs.is_set("export-ignore") {
+                        continue;
+                    }
+
+                    self.write_pending()?;
+
+                    let Some(GitObjectData::Blob { mut data }) = GitObjectStore::get(entry.id).map(|object| object.data)
+                    else {
+                        return Err(io::Error::other(format!("unable to read blob ({})", entry.id)));
+                    };
+
+                    // Only regular files, and only with a commit to show
+                    if let Some((id, commit)) = &self.commit {
+                        if entry.mode != MODE_SYMLINK && attrs.is_set("export-subst") {
+                            data = format_subst(&data, *id, commit, &PrettyOptions::default());
+                        }
+                    }
+
+                    let path = format!("{}{path}", self.options.prefix);
+                    self.writer.write_entry(&Entry { path: &path, id: entry.id, mode: entry.mode, data: &data })?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        for (path, id, mode) in self.pending_dirs.drain(..) {
+            let path = format!("{}{path}", self.options.prefix);
+            self.writer.write_entry(&Entry { path: &path, id, mode, data: &[] })?;
+        }
+
+        Ok(())
+    }
+}
+
+trait ArchiveWriter {
+    fn write_entry(&mut self, entry: &Entry) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Writes a POSIX (pax) tar archive, owned by root, with a global header
+/// recording the commit. Anything too long for its ustar header field
+/// goes in an extended header before the entry.
+struct TarWriter<W: Write> {
+    out: W,
+
+    /// Bytes written, to pad the last record
+    written: usize,
+
+    mtime: i64,
+    umask: u32,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn new(out: W, options: &ArchiveOptions) -> io::Result<TarWriter<W>> {
+        let mut writer = TarWriter { out, written: 0, mtime: options.mtime, umask: options.tar_umask };
+
+        let mut global = Vec::new();
+
+        if let Some(commit) = options.commit {
+            append_pax_record(&mut global, "comment", commit.to_string().as_bytes());
+        }
+
+        if options.mtime > USTAR_MAX_MTIME {
+            append_pax_record(&mut global, "mtime", options.mtime.to_string().as_bytes());
+            writer.mtime = USTAR_MAX_MTIME;
+        }
+
+        if !global.is_empty() {
+            let mut header = [0; TAR_BLOCK_SIZE];
+            set_field(&mut header, 0, 100, b"pax_global_header");
+            writer.write_header(&mut header, b'g', 0o100666, global.len() as u64)?;
+            writer.write_blocked(&global)?;
+        }
+
+        Ok(writer)
+    }
+
+    /// Writes `data` padded with NULs to a whole number of blocks
+    fn write_blocked(&mut self, data: &[u8]) -> io::Result<()> {
+        let padding = data.len().next_multiple_of(TAR_BLOCK_SIZE) - data.len();
+
+        self.out.write_all(data)?;
+        self.out.write_all(&[0; TAR_BLOCK_SIZE][..padding])?;
+        self.written += data.len() + padding;
+
+        Ok(())
+    }
+
+    /// Fills in the rest of a header with its name (and maybe prefix and
+    /// link name) set, and writes it
+    fn write_header(&mut self, header: &mut [u8; TAR_BLOCK_SIZE], kind: u8, mode: u32, size: u64)
+        -> io::Result<()>
+    {
+        set_field(header, 100, 8, format!("{:07o}", mode & 0o7777).as_bytes());
+        set_field(header, 108, 8, b"0000000");
+        set_field(header, 116, 8, b"0000000");
+        set_field(header, 124, 12, format!("{size:011o}").as_bytes());
+        set_field(header, 136, 12, format!("{:011o}", self.mtime).as_bytes());
+        header[156] = kind;
+        set_field(header, 257, 6, b"ustar\0");
+        set_field(header, 263, 2, b"00");
+        set_field(header, 265, 32, b"root");
+        set_field(header, 297, 32, b"root");
+        set_field(header, 329, 8, b"0000000");
+        set_field(header, 337, 8, b"0000000");
+
+        // Summed with the checksum itself as spaces
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+        header[148..156].fill(0);
+        set_field(header, 148, 8, format!("{checksum:07o}").as_bytes());
+
+        self.write_blocked(&header[..])
+    }
+}
+
+impl<W: Write> ArchiveWriter for TarWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> io::Result<()> {
+        let mut header = [0; TAR_BLOCK_SIZE];
+        let mut extended = Vec::new();
+
+        let (kind, mode) = match entry.mode & 0o170000 {
+            MODE_TREE | MODE_GITLINK => (b'5', (entry.mode | 0o777) & !self.umask),
+            MODE_SYMLINK => (b'2', entry.mode | 0o777),
+            _ if entry.mode & 0o100 != 0 => (b'0', (entry.mode | 0o777) & !self.umask),
+            _ => (b'0', (entry.mode | 0o666) & !self.umask),
+        };
+
+        let path = entry.path.as_bytes();
+
+        if path.len() > 100 {
+            let prefix_len = path_prefix_len(path, 155);
+            let rest = path.len() - prefix_len - 1;
+
+            match prefix_len > 0 && rest <= 100 {
+                true => {
+                    set_field(&mut header, 345, 155, &path[..prefix_len]);
+                    set_field(&mut header, 0, 100, &path[prefix_len + 1..]);
+                }
+                false => {
+                    set_field(&mut header, 0, 100, format!("{}.data", entry.id).as_bytes());
+                    append_pax_record(&mut extended, "path", path);
+                }
+            }
+        } else {
+            set_field(&mut header, 0, 100, path);
+        }
+
+        if kind == b'2' {
+            match entry.data.len() > 100 {
+                true => {
+                    set_field(&mut header, 157, 100, format!("see {}.paxheader", entry.id).as_bytes());
+                    append_pax_record(&mut extended, "linkpath", entry.data);
+                }
+                false => set_field(&mut header, 157, 100, entry.data),
+            }
+        }
+
+        let mut size = match kind {
+            b'0' => entry.data.len() as u64,
+            _ => 0,
+        };
+
+        if size > USTAR_MAX_SIZE {
+            append_pax_record(&mut extended, "size", size.to_string().as_bytes());
+            size = 0;
+        }
+
+        if !extended.is_empty() {
+            let mut extended_header = [0; TAR_BLOCK_SIZE];
+            set_field(&mut extended_header, 0, 100, format!("{}.paxheader", entry.id).as_bytes());
+            self.write_header(&mut extended_header, b'x', 0o100666, extended.len() as u64)?;
+            self.write_blocked(&extended)?;
+        }
+
+        self.write_header(&mut header, kind, mode, size)?;
+
+        if kind == b'0' && !entry.data.is_empty() {
+            self.write_blocked(entry.data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Ends the archive with at least two zero blocks, padded to a whole
+    /// record
+    fn finish(&mut self) -> io::Result<()> {
+        let end = (self.written + 2 * TAR_BLOCK_SIZE).next_multiple_of(TAR_RECORD_SIZE);
+
+        io::copy(&mut io::repeat(0).take((end - self.written) as u64), &mut self.out)?;
+        self.written = end;
+
+        self.out.flush()
+    }
+}
+
+/// How much of `path` goes in a ustar header's prefix field, at most
+/// `max` bytes up to a '/', which isn't included. 0 if none can.
+fn path_prefix_len(path: &[u8], max: usize) -> usize {
+    let mut i = path.len();
+
+    if i > 1 && path[i - 1] == b'/' {
+        i -= 1;
+    }
+
+    i = i.min(max);
+
+    loop {
+        i = i.saturating_sub(1);
+
+        if i == 0 || path[i] == b'/' {
+            return i;
+        }
+    }
+}
+
+/// Copies `value` into a header field, truncating it to fit
+fn set_field(header: &mut [u8], offset: usize, len: usize, value: &[u8]) {
+    let len = value.len().min(len);
+    header[offset..offset + len].copy_from_slice(&value[..len]);
+}
+
+/// Appends a pax extended header record:
+///   <length> " " <keyword> "=" <value> "\n"
+///
+/// where the length is of the whole record, its own digits included
+fn append_pax_record(records: &mut Vec<u8>, keyword: &str, value: &[u8]) {
+    let mut len = keyword.len() + value.len() + 3;
+    let mut digits = len.to_string().len();
+
+    while (len + digits).to_string().len() != digits {
+        digits += 1;
+    }
+
+    len += digits;
+
+    records.extend_from_slice(format!("{len} {keyword}=").as_bytes());
+    records.extend_from_slice(value);
+    records.push(b'\n');
+}
+
+/// Writes a zip archive, deflating files unless that makes them bigger,
+/// with the commit as its comment. Unix permissions are recorded, and
+/// times both as MS-DOS local times and as Unix times.
+struct ZipWriter<W: Write> {
+    out: W,
+    offset: u64,
+
+    /// The central directory, written at the end
+    directory: Vec<u8>,
+    entries: u16,
+
+    comment: String,
+    dos_time: u16,
+    dos_date: u16,
+    mtime: u32,
+}
+
+impl<W: Write> ZipWriter<W> {
+    fn new(out: W, options: &ArchiveOptions) -> ZipWriter<W> {
+        let local = options.mtime + local_offset(options.mtime) as i64 * 60;
+        let (year, month, day) = civil_from_days(local.div_euclid(86400));
+        let seconds = local.rem_euclid(86400) as u16;
+
+        ZipWriter {
+            out,
+            offset: 0,
+            directory: Vec::new(),
+            entries: 0,
+            comment: options.commit.map(|commit| commit.to_string()).unwrap_or_default(),
+            dos_time: (seconds / 3600) << 11 | (seconds / 60 % 60) << 5 | (seconds % 60 / 2),
+            dos_date: ((year - 1980).clamp(0, 127) as u16) << 9 | (month as u16) << 5 | day as u16,
+            mtime: options.mtime.clamp(0, u32::MAX as i64) as u32,
+        }
+    }
+}
+
+impl<W: Write> ArchiveWriter for ZipWriter<W> {
+    fn write_entry(&mut self, entry: &Entry) -> io::Result<()> {
+        let is_dir = entry.path.ends_with('/');
+
+        let mut crc = Crc::new();
+        crc.update(entry.data);
+
+        let deflated = match entry.mode & 0o170000 == 0o100000 && !entry.data.is_empty() {
+            true => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(entry.data)?;
+                Some(encoder.finish()?).filter(|deflated| deflated.len() < entry.data.len())
+            }
+            false => None,
+        };
+
+        let (method, version, data): (u16, u16, &[u8]) = match &deflated {
+            Some(deflated) => (8, 20, deflated),
+            None => (0, 10, entry.data),
+        };
+
+        let mode = match entry.mode & 0o170000 {
+            MODE_TREE | MODE_GITLINK => 0o040755,
+            MODE_SYMLINK => MODE_SYMLINK | 0o777,
+            _ if entry.mode & 0o100 != 0 => 0o100755,
+            _ => 0o100644,
+        };
+
+        let attributes = mode << 16 | if is_dir { 0x10 } else { 0 };
+
+        // Marked as UTF-8 where that matters
+        let flags: u16 = match entry.path.is_ascii() {
+            true => 0,
+            false => 1 << 11,
+        };
+
+        let extra = [&0x5455u16.to_le_bytes()[..], &5u16.to_le_bytes(), &[1], &self.mtime.to_le_bytes()].concat();
+
+        // What the local header and the central directory entry share
+        let mut common = Vec::new();
+        common.extend_from_slice(&version.to_le_bytes());
+        common.extend_from_slice(&flags.to_le_bytes());
+        common.extend_from_slice(&method.to_le_bytes());
+        common.extend_from_slice(&self.dos_time.to_le_bytes());
+        common.extend_from_slice(&self.dos_date.to_le_bytes());
+        common.extend_from_slice(&crc.sum().to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(entry.path.len() as u16).to_le_bytes());
+        common.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+
+        let mut local = Vec::new();
+        local.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        local.extend_from_slice(&common);
+        local.extend_from_slice(entry.path.as_bytes());
+        local.extend_from_slice(&extra);
+
+        self.directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        // Made on Unix, so the attributes hold its permissions
+        self.directory.extend_from_slice(&0x0317u16.to_le_bytes());
+        self.directory.extend_from_slice(&common);
+        self.directory.extend_from_slice(&0u16.to_le_bytes());
+        self.directory.extend_from_slice(&0u16.to_le_bytes());
+        self.directory.extend_from_slice(&0u16.to_le_bytes());
+        self.directory.extend_from_slice(&attributes.to_le_bytes());
+        self.directory.extend_from_slice(&(self.offset as u32).to_le_bytes());
+        self.directory.extend_from_slice(entry.path.as_bytes());
+        self.directory.extend_from_slice(&extra);
+
+        self.out.write_all(&local)?;
+        self.out.write_all(data)?;
+
+        self.offset += (local.len() + data.len()) as u64;
+        self.entries += 1;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let mut end = Vec::new();
+        end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&(self.directory.len() as u32).to_le_bytes());
+        end.extend_from_slice(&(self.offset as u32).to_le_bytes());
+        end.extend_from_slice(&(self.comment.len() as u16).to_le_bytes());
+        end.extend_from_slice(self.comment.as_bytes());
+
+        self.out.write_all(&self.directory)?;
+        self.out.write_all(&end)?;
+
+        self.out.flush()
+    }
+}
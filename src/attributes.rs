@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{xdg_config_path, Config};
+use crate::ignore::IgnorePattern;
+use crate::repo::git_path;
+use crate::store::index::Index;
+use crate::store::tree::flatten_tree;
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+use crate::wildmatch::WildmatchFlags;
+
+/// Macros every repository has, defined before anything it reads
+const BUILTIN_MACROS: &str = "[attr]binary -diff -merge -text";
+
+/// The state an attribute is in for a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttrValue {
+    /// "attr"
+    Set,
+
+    /// "-attr"
+    Unset,
+
+    /// "attr=value"
+    Value(String),
+
+    /// "!attr", or no line matching the path mentions it
+    Unspecified,
+}
+
+/// As `check-attr` shows it
+impl fmt::Display for AttrValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttrValue::Set => write!(f, "set"),
+            AttrValue::Unset => write!(f, "unset"),
+            AttrValue::Value(value) => write!(f, "{value}"),
+            AttrValue::Unspecified => write!(f, "unspecified"),
+        }
+    }
+}
+
+/// The attributes a path has, those which aren't unspecified
+#[derive(Debug, Clone, Default)]
+pub struct AttrState {
+    /// In the order the attributes were first read
+    attrs: Vec<(String, AttrValue)>,
+}
+
+impl AttrState {
+    pub fn get(&self, name: &str) -> &AttrValue {
+        self.attrs.iter()
+            .find(|(attr, _)| attr == name)
+            .map_or(&AttrValue::Unspecified, |(_, value)| value)
+    }
+
+    pub fn is_set(&self, name: &str) -> bool {
+        *self.get(name) == AttrValue::Set
+    }
+
+    pub fn is_unset(&self, name: &str) -> bool {
+        *self.get(name) == AttrValue::Unset
+    }
+
+    /// The value given with "attr=value"
+    pub fn value(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            AttrValue::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &AttrValue)> {
+        self.attrs.iter().map(|(name, value)| (name.as_str(), value))
+    }
+}
+
+/// One line of a `.gitattributes` style file, the states it gives the
+/// attributes (by number) of the paths its pattern matches
+#[derive(Debug, Clone)]
+struct AttrLine {
+    pattern: IgnorePattern,
+    states: Vec<(usize, AttrValue)>,
+}
+
+/// What's been read from one attributes file
+#[derive(Debug, Clone, Default)]
+struct AttrFile {
+    lines: Vec<AttrLine>,
+
+    /// "[attr]name ..." lines: the attribute the macro sets when it's set
+    /// itself, and what it sets them to
+    macros: Vec<(usize, Vec<(usize, AttrValue)>)>,
+}
+
+/// Where the `.gitattributes` in each directory are read from
+#[derive(Debug, Clone)]
+enum Source {
+    /// The worktree, or the index for those which aren't checked out
+    Worktree(HashMap<String, ObjectId>),
+
+    /// Blobs, keyed by their path, from a tree or the index
+    Blobs(HashMap<String, ObjectId>),
+}
+
+/// Decides the attributes of paths, from the `.gitattributes` in each
+/// directory, `.git/info/attributes` and core.attributesFile. Each line
+/// is a pattern followed by the attributes it sets ("attr"), unsets
+/// ("-attr"), gives a value ("attr=value") or leaves unspecified ("!attr")
+/// for the paths it matches. Lines starting "[attr]<name>" define macros,
+/// attributes which set others when they're set.
+///
+/// `.git/info/attributes` takes precedence over the `.gitattributes`
+/// files, those in directories over those in their parents, and all of
+/// them over core.attributesFile. Within a file the last matching line
+/// wins, and on a line the last mention of an attribute.
+#[derive(Debug, Clone)]
+pub struct Attributes {
+    flags: WildmatchFlags,
+    source: Source,
+
+    /// Every attribute read so far, numbered by position
+    names: Vec<String>,
+
+    /// Macros built in, then core.attributesFile
+    global: Vec<AttrFile>,
+
+    /// `.git/info/attributes`
+    info: AttrFile,
+
+    /// Each directory's `.gitattributes`, loaded as they're needed
+    per_dir: HashMap<String, AttrFile>,
+}
+
+impl Attributes {
+    /// The attributes of worktree paths, where a `.gitattributes` missing
+    /// from the worktree is read from the index
+    pub fn load(config: &Config) -> Attributes {
+        let index = Index::read().map(|index| attribute_blobs(&index)).unwrap_or_default();
+        Attributes::new(config, Source::Worktree(index))
+    }
+
+    /// The attributes of paths as the index has them, `check-attr --cached`
+    pub fn from_index(config: &Config, index: &Index) -> Attributes {
+        Attributes::new(config, Source::Blobs(attribute_blobs(index)))
+    }
+
+    /// The attributes of the paths in `tree`, as `archive` uses them.
+    /// None if the tree can't be read.
+    pub fn from_tree(config: &Config, tree: ObjectId) -> Option<Attributes> {
+        let blobs = flatten_tree(tree)?
+            .into_iter()
+            .filter(|(path, _)| is_attributes_file(path))
+            .filter_map(|(path, (_, id))| Some((String::from_utf8(path).ok()?, id)))
+            .collect();
+
+        Some(Attributes::new(config, Source::Blobs(blobs)))
+    }
+
+    fn new(config: &Config, source: Source) -> Attributes {
+        let mut attributes = Attributes {
+            flags: WildmatchFlags {
+                pathname: true,
+                casefold: config.get_bool("core.ignorecase").unwrap_or(false),
+            },
+            source,
+            names: Vec::new(),
+            global: Vec::new(),
+            info: AttrFile::default(),
+            per_dir: HashMap::new(),
+        };
+
+        let builtin = attributes.parse(BUILTIN_MACROS, "", "[builtin]", true);
+        attributes.global.push(builtin);
+
+        if let Some(path) = attributes_file(config) {
+            let file = attributes.read_file(&path, "");
+            attributes.global.push(file);
+        }
+
+        attributes.info = attributes.read_file(&git_path("info/attributes"), "");
+        attributes
+    }
+
+    /// The attributes `path` (relative to the repository root) has, a
+    /// directory if it ends in '/'
+    pub fn attributes_for(&mut self, path: &str) -> AttrState {
+        let is_dir = path.ends_with('/');
+        let path = path.trim_end_matches('/');
+
+        let dirs: Vec<&str> = std::iter::once("")
+            .chain(path.match_indices('/').map(|(pos, _)| &path[..=pos]))
+            .collect();
+
+        for dir in &dirs {
+            if !self.per_dir.contains_key(*dir) {
+                let file = self.read_dir(dir);
+                self.per_dir.insert(dir.to_string(), file);
+            }
+        }
+
+        // Highest precedence first
+        let files: Vec<&AttrFile> = std::iter::once(&self.info)
+            .chain(dirs.iter().rev().map(|dir| &self.per_dir[*dir]))
+            .chain(self.global.iter().rev())
+            .collect();
+
+        // The first definition of a macro found is the one used
+        let mut macros = HashMap::new();
+
+        for file in &files {
+            for (attr, states) in file.macros.iter().rev() {
+                macros.entry(*attr).or_insert(states.as_slice());
+            }
+        }
+
+        let mut values = vec![None; self.names.len()];
+
+        for file in &files {
+            for line in file.lines.iter().rev() {
+                if line.pattern.matches(path, is_dir, self.flags) {
+                    fill(&mut values, &line.states, &macros);
+                }
+            }
+        }
+
+        let attrs = values.into_iter()
+            .enumerate()
+            .filter_map(|(attr, value)| match value? {
+                AttrValue::Unspecified => None,
+                value => Some((self.names[attr].clone(), value)),
+            })
+            .collect();
+
+        AttrState { attrs }
+    }
+
+    /// The `.gitattributes` in `dir`, "" or "dir/"
+    fn read_dir(&mut self, dir: &str) -> AttrFile {
+        let path = format!("{dir}.gitattributes");
+
+        let blob = match &self.source {
+            Source::Worktree(index) => match fs::read(&path) {
+                Ok(data) => return self.parse(&String::from_utf8_lossy(&data), dir, &path, dir.is_empty()),
+                Err(_) => index.get(&path),
+            },
+            Source::Blobs(blobs) => blobs.get(&path),
+        };
+
+        match blob.and_then(|&id| GitObjectStore::get(id)).map(|object| object.data) {
+            Some(GitObjectData::Blob { data }) => self.parse(&String::from_utf8_lossy(&data), dir, &path, dir.is_empty()),
+            _ => AttrFile::default(),
+        }
+    }
+
+    fn read_file(&mut self, path: &Path, base: &str) -> AttrFile {
+        match fs::read(path) {
+            Ok(data) => self.parse(&String::from_utf8_lossy(&data), base, &path.display().to_string(), true),
+            Err(_) => AttrFile::default(),
+        }
+    }
+
+    /// Parses the lines of a file, named `source` in warnings, found in
+    /// the directory `base`. Only some files may define macros.
+    fn parse(&mut self, contents: &str, base: &str, source: &str, macros_allowed: bool) -> AttrFile {
+        let mut file = AttrFile::default();
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim_start_matches([' ', '\t', '\r']);
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (pattern, rest) = line.split_once([' ', '\t', '\r']).unwrap_or((line, ""));
+
+            if let Some(name) = pattern.strip_prefix("[attr]") {
+                if !macros_allowed {
+                    eprintln!("warning: {pattern} not allowed: {source}:{}", i + 1);
+                    continue;
+                }
+
+                if !is_valid_name(name) {
+                    eprintln!("warning: {name} is not a valid attribute name: {source}:{}", i + 1);
+                    continue;
+                }
+
+                let attr = self.intern(name);
+                let states = self.parse_states(rest, source, i + 1);
+                file.macros.push((attr, states));
+                continue;
+            }
+
+            let Some(pattern) = IgnorePattern::parse(pattern, base) else {
+                continue;
+            };
+
+            if pattern.is_negated() {
+                eprintln!("warning: Negative patterns are ignored in git attributes");
+                eprintln!("Use '\\!' for literal leading exclamation.");
+                continue;
+            }
+
+            let states = self.parse_states(rest, source, i + 1);
+            file.lines.push(AttrLine { pattern, states });
+        }
+
+        file
+    }
+
+    fn parse_states(&mut self, states: &str, source: &str, line: usize) -> Vec<(usize, AttrValue)> {
+        let mut parsed = Vec::new();
+
+        for state in states.split([' ', '\t', '\r']).filter(|state| !state.is_empty()) {
+            let (name, value) = if let Some(name) = state.strip_prefix('-') {
+                (name, AttrValue::Unset)
+            } else if let Some(name) = state.strip_prefix('!') {
+                (name, AttrValue::Unspecified)
+            } else if let Some((name, value)) = state.split_once('=') {
+                (name, AttrValue::Value(value.to_string()))
+            } else {
+                (state, AttrValue::Set)
+            };
+
+            if !is_valid_name(name) {
+                eprintln!("warning: {name} is not a valid attribute name: {source}:{line}");
+                continue;
+            }
+
+            parsed.push((self.intern(name), value));
+        }
+
+        parsed
+    }
+
+    /// The attribute's number, given one if it's new
+    fn intern(&mut self, name: &str) -> usize {
+        match self.names.iter().position(|known| known == name) {
+            Some(attr) => attr,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        }
+    }
+}
+
+/// Gives the attributes in `states` which aren't yet decided their state,
+/// the last mention winning. A macro which is set sets its attributes
+/// the same way.
+fn fill(values: &mut [Option<AttrValue>], states: &[(usize, AttrValue)], macros: &HashMap<usize, &[(usize, AttrValue)]>) {
+    for (attr, value) in states.iter().rev() {
+        if values[*attr].is_some() {
+            continue;
+        }
+
+        values[*attr] = Some(value.clone());
+
+        if let (AttrValue::Set, Some(expansion)) = (value, macros.get(attr)) {
+            fill(values, expansion, macros);
+        }
+    }
+}
+
+/// Attribute names are made of letters, digits, '-', '.' and '_', and
+/// don't start with '-'
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"-._".contains(&byte))
+}
+
+fn is_attributes_file(path: &[u8]) -> bool {
+    path == b".gitattributes" || path.ends_with(b"/.gitattributes")
+}
+
+/// The `.gitattributes` files staged in `index`
+fn attribute_blobs(index: &Index) -> HashMap<String, ObjectId> {
+    index.entries.iter()
+        .filter(|entry| entry.stage == 0 && is_attributes_file(&entry.path))
+        .filter_map(|entry| Some((String::from_utf8(entry.path.clone()).ok()?, entry.id)))
+        .collect()
+}
+
+/// The file core.attributesFile names, by default `git/attributes` in the
+/// XDG config directory
+fn attributes_file(config: &Config) -> Option<PathBuf> {
+    config.get_path("core.attributesfile").or_else(|| xdg_config_path("attributes"))
+}
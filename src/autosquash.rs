@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use crate::message::subject;
+use crate::store::ObjectId;
+
+/// How a commit made to be squashed into an earlier one changes it, as
+/// the prefix of its subject says
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SquashKind {
+    /// "fixup! ", the changes are squashed in and the message dropped
+    Fixup,
+
+    /// "amend! ", the changes are squashed in and the message replaces
+    /// the earlier commit's
+    Amend,
+
+    /// "squash! ", the changes are squashed in and the messages combined
+    Squash,
+}
+
+impl SquashKind {
+    pub fn prefix(self) -> &'static str {
+        match self {
+            SquashKind::Fixup => "fixup! ",
+            SquashKind::Amend => "amend! ",
+            SquashKind::Squash => "squash! ",
+        }
+    }
+
+    /// The kind `subject` starts with, if any
+    fn of(subject: &str) -> Option<SquashKind> {
+        [SquashKind::Fixup, SquashKind::Amend, SquashKind::Squash].into_iter()
+            .find(|kind| subject.starts_with(kind.prefix()))
+    }
+}
+
+/// The message a commit squashing into the commit with `target_message`
+/// starts with, eg. "fixup! <subject>".
+///
+/// An "amend! " commit's message is followed by the whole of the earlier
+/// one, to be edited into the message that replaces it.
+pub fn squash_message(kind: SquashKind, target_message: &[u8]) -> Vec<u8> {
+    let mut message = format!("{}{}\n\n", kind.prefix(), subject(target_message)).into_bytes();
+
+    if kind == SquashKind::Amend {
+        message.extend_from_slice(target_message);
+    }
+
+    message
+}
+
+/// The kind of squash `subject` asks for and the commit it names, past
+/// any further prefixes, eg. "fixup! fixup! Add x" gives (Fixup, "Add x")
+pub fn parse_squash_subject(subject: &str) -> Option<(SquashKind, &str)> {
+    let kind = SquashKind::of(subject)?;
+    let mut rest = subject;
+
+    while let Some(kind) = SquashKind::of(rest) {
+        rest = rest[kind.prefix().len()..].trim_start();
+    }
+
+    Some((kind, rest))
+}
+
+/// Reorders `commits`, as (id, subject) oldest first, for a rebase with
+/// --autosquash: each commit squashing into an earlier one is moved to
+/// just after it and the commits already squashing into it, and given
+/// its kind. The rest are left as they are, with no kind.
+///
+/// The commit squashed into is the first earlier one with exactly the
+/// subject named, failing that one whose id starts with it, failing that
+/// the first whose subject starts with it. Subjects naming none are left
+/// where they are, to be picked like any other.
+pub fn autosquash(commits: &[(ObjectId, String)]) -> Vec<(ObjectId, Option<SquashKind>)> {
+    let mut kinds = vec![None; commits.len()];
+
+    // A list threaded through the commits, each moved one following the
+    // last already following its target
+    let mut next: Vec<Option<usize>> = vec![None; commits.len()];
+    let mut tail: Vec<Option<usize>> = vec![None; commits.len()];
+
+    // Only the first commit with a subject is found by it
+    let mut by_subject: HashMap<&str, usize> = HashMap::new();
+
+    for (i, (_, subject)) in commits.iter().enumerate() {
+        if let Some((kind, name)) = parse_squash_subject(subject) {
+            let by_id = || {
+                let abbrev = name.len() >= 4 && name.chars().all(|c| c.is_ascii_hexdigit());
+                let name = name.to_ascii_lowercase();
+
+                abbrev.then(|| commits[..i].iter().position(|(id, _)| id.to_string().starts_with(&name))).flatten()
+            };
+
+            let target = by_subject.get(name).copied()
+                .or_else(by_id)
+                .or_else(|| commits[..i].iter().position(|(_, subject)| subject.starts_with(name)));
+
+            if let Some(target) = target {
+                let after = tail[target].unwrap_or(target);
+
+                next[i] = next[after];
+                next[after] = Some(i);
+                tail[target] = Some(i);
+                kinds[i] = Some(kind);
+            }
+        }
+
+        by_subject.entry(subject.as_str()).or_insert(i);
+    }
+
+    let mut todo = Vec::with_capacity(commits.len());
+
+    for start in (0..commits.len()).filter(|&i| kinds[i].is_none()) {
+        let mut current = Some(start);
+
+        while let Some(i) = current {
+            todo.push((commits[i].0, kinds[i]));
+            current = next[i];
+        }
+    }
+
+    todo
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+
+use crate::checkout::{checkout_tree, remove_worktree_file, write_worktree_file};
+use crate::refs;
+use crate::repo::git_path;
+use crate::stash::{commit_files, create_stash, staged_entry, store_stash, StashOptions};
+use crate::store::index::Index;
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+
+/// What became of an autostash once the operation it made way for is done
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AutostashOutcome {
+    /// The changes are back in the index and worktree
+    Applied,
+
+    /// The operation changed some of the same paths, so rather than merge
+    /// them the changes were kept in refs/stash
+    Stashed,
+}
+
+/// Sets aside the changes to tracked files in the index and worktree, as
+/// a stash commit like `git stash create` makes, and resets both to HEAD.
+///
+/// The stash is remembered in the file `state` (eg. "MERGE_AUTOSTASH")
+/// until `apply_autostash` is given the same name. Returns the stash
+/// commit, or `None` if there was nothing to stash.
+pub fn create_autostash(state: &str) -> io::Result<Option<ObjectId>> {
+    let options = StashOptions { message: Some("autostash".to_string()), ..StashOptions::default() };
+
+    let Some(stash) = create_stash(&options)? else {
+        return Ok(None);
+    };
+
+    fs::write(git_path(state), format!("{}\n", stash.id))?;
+
+    let head = refs::resolve_ref("HEAD").ok_or_else(|| io::Error::other("unable to resolve HEAD"))?;
+    let head_files = commit_files(head)?;
+
+    checkout_tree(&head_files, &head_files, true).ok_or_else(|| io::Error::other("unable to reset to HEAD"))?;
+
+    Ok(Some(stash.id))
+}
+
+/// Puts back the changes `create_autostash` set aside under `state`,
+/// `None` if there aren't any.
+///
+/// Only paths the operation left as they were stashed from can be put
+/// back as they are. If it changed any of the same paths, the stash is
+/// saved to refs/stash instead, as merging the two isn't possible yet.
+pub fn apply_autostash(state: &str) -> io::Result<Option<AutostashOutcome>> {
+    let path = git_path(state);
+
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+
+    let stash = ObjectId::try_from(contents.trim().to_string())
+        .map_err(|_| io::Error::other(format!("invalid autostash in {state}")))?;
+
+    // The stash's tree is the worktree, its first parent the commit it
+    // was made on, and its second the index
+    let (base, index_commit) = match GitObjectStore::get(stash).map(|object| object.data) {
+        Some(GitObjectData::Commit { parents, .. }) if parents.len() == 2 => (parents[0], parents[1]),
+        _ => return Err(io::Error::other(format!("{stash} is not a stash"))),
+    };
+
+    let base_files = commit_files(base)?;
+    let index_files = commit_files(index_commit)?;
+    let work_files = commit_files(stash)?;
+
+    let changed: BTreeSet<&Vec<u8>> = base_files.keys()
+        .chain(index_files.keys())
+        .chain(work_files.keys())
+        .filter(|path| index_files.get(*path) != base_files.get(*path) || work_files.get(*path) != base_files.get(*path))
+        .collect();
+
+    let mut index = Index::read().ok_or_else(|| io::Error::other("unable to read the index"))?;
+
+    let untouched = changed.iter().all(|path| {
+        index.get(path).map(|entry| (entry.mode, entry.id)) == base_files.get(*path).copied()
+    });
+
+    let outcome = match untouched {
+        true => {
+            for path in changed {
+                match work_files.get(path) {
+                    Some(&(mode, id)) => write_worktree_file(path, mode, id)?,
+                    None => remove_worktree_file(path)?,
+                }
+
+                match index_files.get(path) {
+                    Some(&(mode, id)) => index.add(staged_entry(path, mode, id, work_files.get(path) == Some(&(mode, id)))?),
+                    None => {
+                        index.remove(path);
+                    }
+                }
+            }
+
+            index.write()?;
+
+            AutostashOutcome::Applied
+        }
+        false => {
+            store_stash(stash, "autostash")?;
+
+            AutostashOutcome::Stashed
+        }
+    };
+
+    fs::remove_file(&path)?;
+
+    Ok(Some(outcome))
+}
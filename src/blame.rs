@@ -0,0 +1,464 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::diff::{detect_renames, diff_lines, diff_trees, split_lines, DEFAULT_RENAME_SCORE};
+use crate::graph::Graph;
+use crate::store::bloom::{BloomKey, BloomSettings};
+use crate::store::{util::hash_object, GitObjectData, GitObjectStore, ObjectId};
+
+/// A version of the file being blamed: the commit and the path the file
+/// had there. The null commit stands for the working tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Origin {
+    pub commit: ObjectId,
+    pub path: String,
+}
+
+/// A run of lines of the blamed file and the origin they came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameEntry {
+    /// The first line, counting from 0, in the blamed file
+    pub lno: usize,
+
+    pub num_lines: usize,
+
+    /// Where those lines start in the origin's version of the file
+    pub s_lno: usize,
+
+    pub origin: Origin,
+}
+
+/// Every line of a file, blamed on the commit which introduced it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blame {
+    /// The blamed file's contents
+    pub contents: Vec<u8>,
+
+    /// Covering the whole file in order, adjacent lines from the same
+    /// origin sharing an entry
+    pub entries: Vec<BlameEntry>,
+
+    /// For each origin, the first parent's version it was compared with
+    pub previous: HashMap<Origin, Origin>,
+
+    /// Commits history stopped at, the root commits
+    pub boundaries: HashSet<ObjectId>,
+}
+
+/// A run of lines as soon as it's settled which commit they're blamed on
+#[derive(Debug, Copy, Clone)]
+pub struct Found<'a> {
+    pub entry: &'a BlameEntry,
+
+    /// The first parent's version the entry's origin was compared with
+    pub previous: Option<&'a Origin>,
+
+    /// Whether history stopped at the entry's commit
+    pub boundary: bool,
+}
+
+/// The state of a blame while lines are passed back through history
+struct Scoreboard {
+    graph: Graph,
+
+    /// The working tree's version of the file, if that's what's blamed
+    working: Option<(Origin, ObjectId)>,
+
+    /// The parent the working tree is compared against, ie. HEAD
+    head: ObjectId,
+
+    /// Lines which are yet to be passed on, by the origin they're at
+    suspects: HashMap<Origin, Vec<BlameEntry>>,
+
+    /// Origins with suspects, newest commit first
+    queue: BinaryHeap<(u64, Reverse<Origin>)>,
+
+    blobs: HashMap<ObjectId, Rc<[u8]>>,
+
+    /// The blob each origin's version of the file is, and the entry at a
+    /// path under a tree, as they're looked up. Commits mostly share
+    /// subtrees, so few trees need reading.
+    blob_ids: HashMap<Origin, Option<ObjectId>>,
+    tree_paths: HashMap<(ObjectId, String), Option<ObjectId>>,
+
+    /// How the commit-graph's changed-path filters were built, and keys
+    /// into them for each path the file has had. None without filters.
+    bloom: Option<(BloomSettings, HashMap<String, BloomKey>)>,
+
+    finished: Vec<BlameEntry>,
+    previous: HashMap<Origin, Origin>,
+    boundaries: HashSet<ObjectId>,
+}
+
+/// Blames every line of `path` as of `commit`, or with `working` given,
+/// of those contents as a working tree version of the file on top of
+/// `commit`.
+///
+/// Lines are blamed the way git does, working back from newer commits to
+/// older ones: a commit is blamed for the lines its version of the file
+/// doesn't share with any of its parents', the rest are passed to the
+/// first parent they're found unchanged in. A parent with an identical
+/// version takes all of them. Where a commit's parent lacks the path,
+/// the file is followed to where it was renamed from, if it was.
+pub fn blame(commit: ObjectId, path: &str, working: Option<Vec<u8>>) -> Option<Blame> {
+    blame_incremental(commit, path, working, |_| {})
+}
+
+/// Like `blame`, calling `found` with each run of lines as soon as it's
+/// blamed, rather than only once the whole file is.
+///
+/// Commits are visited newest first. Where the commit-graph has
+/// changed-path Bloom filters, a commit they show didn't touch the path is
+/// passed over without reading its tree.
+pub fn blame_incremental(
+    commit: ObjectId,
+    path: &str,
+    working: Option<Vec<u8>>,
+    mut found: impl FnMut(Found),
+) -> Option<Blame> {
+    let graph = Graph::new();
+    let bloom = graph.bloom_settings().map(|settings| (settings, HashMap::new()));
+
+    let mut scoreboard = Scoreboard {
+        graph,
+        working: None,
+        head: commit,
+        suspects: HashMap::new(),
+        queue: BinaryHeap::new(),
+        blobs: HashMap::new(),
+        blob_ids: HashMap::new(),
+        tree_paths: HashMap::new(),
+        bloom,
+        finished: Vec::new(),
+        previous: HashMap::new(),
+        boundaries: HashSet::new(),
+    };
+
+    let origin = match working {
+        Some(contents) => {
+            let origin = Origin { commit: ObjectId::NULL, path: path.to_string() };
+            let id = hash_object("blob", &contents);
+
+            scoreboard.blobs.insert(id, contents.into());
+            scoreboard.working = Some((origin.clone(), id));
+            origin
+        }
+        None => Origin { commit, path: path.to_string() },
+    };
+
+    let blob = scoreboard.blob_id(&origin).or_else(|| {
+        eprintln!("no such path {path} in {commit}");
+        None
+    })?;
+
+    let contents = scoreboard.read_blob(blob)?.to_vec();
+    let num_lines = split_lines(&contents).len();
+
+    if num_lines > 0 {
+        scoreboard.add_suspects(origin.clone(), vec![BlameEntry { lno: 0, num_lines, s_lno: 0, origin }]);
+    }
+
+    while let Some((_, Reverse(origin))) = scoreboard.queue.pop() {
+        let Some(suspects) = scoreboard.suspects.remove(&origin) else {
+            continue;
+        };
+
+        let mut guilty = scoreboard.pass_blame(&origin, suspects)?;
+        guilty.sort_by_key(|entry| entry.s_lno);
+
+        for entry in &guilty {
+            found(Found {
+                entry,
+                previous: scoreboard.previous.get(&origin),
+                boundary: scoreboard.boundaries.contains(&origin.commit),
+            });
+        }
+
+        scoreboard.finished.extend(guilty);
+    }
+
+    let Scoreboard { mut finished, previous, boundaries, .. } = scoreboard;
+
+    finished.sort_by_key(|entry| entry.lno);
+
+    Some(Blame {
+        contents,
+        entries: coalesce(finished),
+        previous,
+        boundaries,
+    })
+}
+
+impl Scoreboard {
+    fn parents(&mut self, commit: ObjectId) -> Vec<ObjectId> {
+        match commit.is_null() {
+            true => vec![self.head],
+            false => self.graph.parents(commit),
+        }
+    }
+
+    /// The blob the origin's version of the file is
+    fn blob_id(&mut self, origin: &Origin) -> Option<ObjectId> {
+        if let Some((working, id)) = &self.working {
+            if working == origin {
+                return Some(*id);
+            }
+        }
+
+        if let Some(&id) = self.blob_ids.get(origin) {
+            return id;
+        }
+
+        let id = self.graph.tree(origin.commit).and_then(|tree| self.tree_path(tree, &origin.path));
+        self.blob_ids.insert(origin.clone(), id);
+
+        id
+    }
+
+    /// The entry at `path` under `tree`, a subtree at a time
+    fn tree_path(&mut self, tree: ObjectId, path: &str) -> Option<ObjectId> {
+        if let Some(&id) = self.tree_paths.get(&(tree, path.to_string())) {
+            return id;
+        }
+
+        let (name, rest) = path.split_once('/').unwrap_or((path, ""));
+
+        let entry = match GitObjectStore::get(tree)?.data {
+            GitObjectData::Tree { entries } => entries.into_iter().find(|entry| entry.path == name.as_bytes()),
+            _ => None,
+        };
+
+        let id = match (entry, rest) {
+            (Some(entry), "") => Some(entry.id),
+            (Some(entry), rest) => self.tree_path(entry.id, rest),
+            (None, _) => None,
+        };
+
+        self.tree_paths.insert((tree, path.to_string()), id);
+
+        id
+    }
+
+    fn read_blob(&mut self, id: ObjectId) -> Option<Rc<[u8]>> {
+        let data = match self.blobs.entry(id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let GitObjectData::Blob { data } = GitObjectStore::get(id)?.data else {
+                    eprintln!("{id} is not a blob");
+                    return None;
+                };
+
+                entry.insert(data.into())
+            }
+        };
+
+        Some(data.clone())
+    }
+
+    /// Whether the commit-graph's filters leave it possible that `commit`
+    /// changed `path` from its first parent
+    fn maybe_changed(&mut self, commit: ObjectId, path: &str) -> bool {
+        let Some((settings, keys)) = &mut self.bloom else {
+            return true;
+        };
+
+        let key = keys.entry(path.to_string()).or_insert_with(|| BloomKey::new(path, settings));
+
+        self.graph.maybe_changed_path(commit, key)
+    }
+
+    fn add_suspects(&mut self, origin: Origin, entries: Vec<BlameEntry>) {
+        let time = match origin.commit.is_null() {
+            true => u64::MAX,
+            false => self.graph.commit_time(origin.commit),
+        };
+
+        let suspects = self.suspects.entry(origin.clone()).or_default();
+
+        // Only an origin without suspects might be missing from the queue
+        if suspects.is_empty() {
+            self.queue.push((time, Reverse(origin)));
+        }
+
+        suspects.extend(entries);
+    }
+
+    /// The version of the file in `parent` that `origin` came from, as the
+    /// origin and its blob: the same path, or failing that, the path it
+    /// was renamed from
+    fn parent_origin(&mut self, origin: &Origin, blob: ObjectId, parent: ObjectId) -> Option<(Origin, ObjectId)> {
+        let same_path = Origin { commit: parent, path: origin.path.clone() };
+
+        // Filters are only kept against the first parent
+        let first_parent = !origin.commit.is_null() && self.graph.parents(origin.commit).first() == Some(&parent);
+
+        if first_parent && !self.maybe_changed(origin.commit, &origin.path) {
+            self.blob_ids.insert(same_path.clone(), Some(blob));
+            return Some((same_path, blob));
+        }
+
+        if let Some(id) = self.blob_id(&same_path) {
+            return Some((same_path, id));
+        }
+
+        if origin.commit.is_null() {
+            return None;
+        }
+
+        let parent_tree = self.graph.tree(parent)?;
+        let tree = self.graph.tree(origin.commit)?;
+
+        let renamed = detect_renames(&diff_trees(Some(parent_tree), Some(tree))?, DEFAULT_RENAME_SCORE)?
+            .into_iter()
+            .find(|rename| rename.to == origin.path)?;
+
+        let parent_origin = Origin { commit: parent, path: renamed.from };
+        let id = self.blob_id(&parent_origin)?;
+
+        Some((parent_origin, id))
+    }
+
+    /// Passes the lines `origin` shares with its parents on to them,
+    /// returning the rest, which it's to blame for
+    fn pass_blame(&mut self, origin: &Origin, mut suspects: Vec<BlameEntry>) -> Option<Vec<BlameEntry>> {
+        let blob = self.blob_id(origin)?;
+        let parents = self.parents(origin.commit);
+
+        if parents.is_empty() {
+            self.boundaries.insert(origin.commit);
+        }
+
+        let mut parent_origins = Vec::new();
+
+        for parent in parents {
+            let Some((parent_origin, parent_blob)) = self.parent_origin(origin, blob, parent) else {
+                continue;
+            };
+
+            if parent_blob == blob {
+                for entry in &mut suspects {
+                    entry.origin = parent_origin.clone();
+                }
+
+                self.add_suspects(parent_origin, suspects);
+                return Some(Vec::new());
+            }
+
+            parent_origins.push((parent_origin, parent_blob));
+        }
+
+        for (parent_origin, parent_blob) in parent_origins {
+            self.previous.entry(origin.clone()).or_insert_with(|| parent_origin.clone());
+
+            let old = self.read_blob(parent_blob)?;
+            let new = self.read_blob(blob)?;
+
+            let (passed, kept) = pass_unchanged(&split_lines(&old), &split_lines(&new), suspects, &parent_origin);
+
+            if !passed.is_empty() {
+                self.add_suspects(parent_origin, passed);
+            }
+
+            suspects = kept;
+
+            if suspects.is_empty() {
+                break;
+            }
+        }
+
+        Some(suspects)
+    }
+}
+
+/// Splits `entries` into the lines which are unchanged between `old` and
+/// `new`, renumbered for `old` and blamed on `parent`, and those which
+/// aren't
+fn pass_unchanged(
+    old: &[&[u8]],
+    new: &[&[u8]],
+    entries: Vec<BlameEntry>,
+    parent: &Origin,
+) -> (Vec<BlameEntry>, Vec<BlameEntry>) {
+    // Runs of unchanged lines, as (start in new, start in old, length)
+    let mut unchanged = Vec::new();
+    let (mut old_pos, mut new_pos) = (0, 0);
+
+    for change in diff_lines(old, new) {
+        unchanged.push((new_pos, old_pos, change.new_start - new_pos));
+
+        old_pos = change.old_start + change.old_len;
+        new_pos = change.new_start + change.new_len;
+    }
+
+    unchanged.push((new_pos, old_pos, new.len() - new_pos));
+
+    let mut passed = Vec::new();
+    let mut kept = Vec::new();
+
+    for entry in entries {
+        let end = entry.s_lno + entry.num_lines;
+        let mut pos = entry.s_lno;
+
+        for &(new_start, old_start, len) in &unchanged {
+            let start = new_start.max(pos);
+            let stop = (new_start + len).min(end);
+
+            if start >= stop {
+                continue;
+            }
+
+            if start > pos {
+                kept.push(BlameEntry {
+                    lno: entry.lno + (pos - entry.s_lno),
+                    num_lines: start - pos,
+                    s_lno: pos,
+                    origin: entry.origin.clone(),
+                });
+            }
+
+            passed.push(BlameEntry {
+                lno: entry.lno + (start - entry.s_lno),
+                num_lines: stop - start,
+                s_lno: old_start + (start - new_start),
+                origin: parent.clone(),
+            });
+
+            pos = stop;
+        }
+
+        if pos < end {
+            kept.push(BlameEntry {
+                lno: entry.lno + (pos - entry.s_lno),
+                num_lines: end - pos,
+                s_lno: pos,
+                origin: entry.origin,
+            });
+        }
+    }
+
+    (passed, kept)
+}
+
+/// Merges entries, sorted by line, which continue one another in both the
+/// blamed file and their origin
+fn coalesce(entries: Vec<BlameEntry>) -> Vec<BlameEntry> {
+    let mut coalesced: Vec<BlameEntry> = Vec::new();
+
+    for entry in entries {
+        if let Some(last) = coalesced.last_mut() {
+            if last.origin == entry.origin
+                && last.lno + last.num_lines == entry.lno
+                && last.s_lno + last.num_lines == entry.s_lno
+            {
+                last.num_lines += entry.num_lines;
+                continue;
+            }
+        }
+
+        coalesced.push(entry);
+    }
+
+    coalesced
+}
@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::graph::Graph;
+use crate::refs;
+use crate::revparse::peel_to_commit;
+use crate::store::{pack_stream::PackStream, GitObjectData, GitObjectStore, ObjectId};
+
+/// A commit the bundle's pack is based on, which has to be in any
+/// repository the bundle is unpacked into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prerequisite {
+    pub id: ObjectId,
+
+    /// Usually the commit's subject, purely informational
+    pub comment: String,
+}
+
+/// A bundle's header: what it holds and what it needs, followed by the
+/// offset of the pack holding the objects.
+#[derive(Debug)]
+pub struct Bundle {
+    pub path: PathBuf,
+    pub version: u32,
+
+    /// v3 capabilities, eg. ("object-format", Some("sha1"))
+    pub capabilities: Vec<(String, Option<String>)>,
+
+    pub prerequisites: Vec<Prerequisite>,
+
+    /// The refs the bundle contains, as (name, id)
+    pub refs: Vec<(String, ObjectId)>,
+
+    /// Where the pack starts in the file
+    pub pack_offset: u64,
+}
+
+/// What's wrong with a bundle, as far as this repository is concerned
+#[derive(Debug, Default)]
+pub struct BundleProblems {
+    /// Prerequisites which aren't in the object store
+    pub missing: Vec<Prerequisite>,
+
+    /// Prerequisites which are in the object store, but can't be reached
+    /// from any ref. Their history may well be incomplete.
+    pub unconnected: Vec<Prerequisite>,
+
+    /// Problems with the pack itself
+    pub pack: Vec<String>,
+}
+
+impl BundleProblems {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unconnected.is_empty() && self.pack.is_empty()
+    }
+}
+
+impl Bundle {
+    /// Bundle header format:
+    ///   "# v2 git bundle" \n  or  "# v3 git bundle" \n
+    ///   (v3 only) '@' <capability> [ '=' <value> ] \n, any number of times
+    ///   '-' <prerequisite-id> [ ' ' <comment> ] \n, any number of times
+    ///   <ref-id> ' ' <refname> \n, any number of times
+    ///   \n
+    ///   <pack>
+    pub fn open<P: AsRef<Path>>(path: P) -> Option<Bundle> {
+        let path = path.as_ref();
+
+        let file = File::open(path)
+            .map_err(|err| eprintln!("could not open '{}': {err}", path.display()))
+            .ok()?;
+
+        let mut reader = BufReader::new(file);
+        let mut offset = 0;
+
+        let mut next_line = || -> Option<String> {
+            let mut line = Vec::new();
+            offset += reader.read_until(b'\n', &mut line).ok()? as u64;
+
+            if line.pop() != Some(b'\n') {
+                eprintln!("'{}' is truncated", path.display());
+                return None;
+            }
+
+            String::from_utf8(line).ok()
+        };
+
+        let version = match next_line()?.as_str() {
+            "# v2 git bundle" => 2,
+            "# v3 git bundle" => 3,
+            _ => {
+                eprintln!("'{}' does not look like a v2 or v3 bundle file", path.display());
+                return None;
+            }
+        };
+
+        let mut bundle = Bundle {
+            path: path.to_path_buf(),
+            version,
+            capabilities: Vec::new(),
+            prerequisites: Vec::new(),
+            refs: Vec::new(),
+            pack_offset: 0,
+        };
+
+        loop {
+            let line = next_line()?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(capability) = line.strip_prefix('@').filter(|_| version == 3) {
+                let (key, value) = match capability.split_once('=') {
+                    Some((key, value)) => (key, Some(value.to_string())),
+                    None => (capability, None),
+                };
+
+                bundle.capabilities.push((key.to_string(), value));
+                continue;
+            }
+
+            let (id, rest) = match line.strip_prefix('-') {
+                Some(prerequisite) => prerequisite.split_once(' ').unwrap_or((prerequisite, "")),
+                None => line.split_once(' ').unwrap_or((&line, "")),
+            };
+
+            let Ok(id) = ObjectId::try_from(id.to_string()) else {
+                eprintln!("unrecognized header: {line}");
+                return None;
+            };
+
+            if line.starts_with('-') {
+                bundle.prerequisites.push(Prerequisite { id, comment: rest.to_string() });
+            } else {
+                bundle.refs.push((rest.to_string(), id));
+            }
+        }
+
+        for (key, value) in &bundle.capabilities {
+            match (key.as_str(), value.as_deref()) {
+                ("object-format", Some("sha1")) | ("filter", Some(_)) => {}
+                ("object-format", value) => {
+                    eprintln!("unsupported object format '{}'", value.unwrap_or_default());
+                    return None;
+                }
+                _ => {
+                    eprintln!("unknown capability '{key}'");
+                    return None;
+                }
+            }
+        }
+
+        bundle.pack_offset = offset;
+
+        Some(bundle)
+    }
+
+    /// The hash algorithm the bundle's ids use
+    pub fn object_format(&self) -> &str {
+        self.capability("object-format").unwrap_or("sha1")
+    }
+
+    /// The object filter the bundle's pack was made with, if it's partial
+    pub fn filter(&self) -> Option<&str> {
+        self.capability("filter")
+    }
+
+    fn capability(&self, key: &str) -> Option<&str> {
+        self.capabilities.iter()
+            .find(|(name, _)| name == key)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// Checks the bundle could be unpacked here: every prerequisite must be
+    /// a commit in the store, reachable from one of our refs, and the pack
+    /// must be intact.
+    pub fn verify(&self) -> BundleProblems {
+        let mut problems = BundleProblems::default();
+
+        for prerequisite in &self.prerequisites {
+            let is_commit = GitObjectStore::get(prerequisite.id)
+                .is_some_and(|object| matches!(object.data, GitObjectData::Commit { .. }));
+
+            if !is_commit {
+                problems.missing.push(prerequisite.clone());
+            }
+        }
+
+        if problems.missing.is_empty() {
+            problems.unconnected = self.unconnected_prerequisites();
+        }
+
+        problems.pack = self.verify_pack();
+
+        problems
+    }
+
+    /// Walks back from every ref until each prerequisite has been seen,
+    /// returning those which weren't
+    fn unconnected_prerequisites(&self) -> Vec<Prerequisite> {
+        let mut remaining: HashSet<ObjectId> = self.prerequisites.iter()
+            .map(|prerequisite| prerequisite.id)
+            .collect();
+
+        let mut graph = Graph::new();
+        let mut seen = HashSet::new();
+
+        let mut queue: Vec<ObjectId> = refs::list_refs("refs/")
+            .into_iter()
+            .map(|(_, id)| id)
+            .chain(refs::resolve_ref("HEAD"))
+            .filter_map(peel_to_commit)
+            .collect();
+
+        while !remaining.is_empty() {
+            let Some(id) = queue.pop() else {
+                break;
+            };
+
+            if !seen.insert(id) {
+                continue;
+            }
+
+            remaining.remove(&id);
+            queue.extend(graph.parents(id));
+        }
+
+        self.prerequisites.iter()
+            .filter(|prerequisite| remaining.contains(&prerequisite.id))
+            .cloned()
+            .collect()
+    }
+
+    /// Reads the whole pack, checking every entry inflates and the pack's
+    /// trailing checksum matches. Deltas aren't resolved, their bases
+    /// may be prerequisites.
+    fn verify_pack(&self) -> Vec<String> {
+        let Some(mut stream) = PackStream::open_at(&self.path, self.pack_offset) else {
+            return vec!["unable to read the pack".to_string()];
+        };
+
+        let count = stream.by_ref().count();
+
+        if stream.is_complete() {
+            return Vec::new();
+        }
+
+        vec![format!("pack is truncated or corrupt, read {count} of {} objects", stream.object_count())]
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{symlink, OpenOptionsExt};
+
+use crate::config::Config;
+use crate::ignore::IgnoreRules;
+use crate::status::{index_mtime, worktree_change};
+use crate::store::{
+    index::Index,
+    tree::{MODE_EXECUTABLE, MODE_GITLINK, MODE_SYMLINK},
+    GitObjectData,
+    GitObjectStore,
+    ObjectId,
+};
+use crate::worktree;
+
+/// A flattened tree, path -> (mode, id)
+pub type TreeFiles = BTreeMap<Vec<u8>, (u32, ObjectId)>;
+
+/// Writes the blob `id` to the worktree at `path`, replacing whatever file
+/// is there. Symlinks (mode 120000) are created from the blob's contents,
+/// and submodules (160000) just get an empty directory.
+pub fn write_worktree_file<P: AsRef<[u8]>>(path: P, mode: u32, id: ObjectId) -> io::Result<()> {
+    let path = worktree::os_path(path.as_ref());
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if mode == MODE_GITLINK {
+        return fs::create_dir_all(path);
+    }
+
+    let data = match GitObjectStore::get(id).map(|object| object.data) {
+        Some(GitObjectData::Blob { data }) => data,
+        _ => return Err(io::Error::other(format!("unable to read blob {id}"))),
+    };
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(_) => {}
+    }
+
+    if mode == MODE_SYMLINK {
+        let target = String::from_utf8(data)
+            .map_err(|_| io::Error::other(format!("symlink target of {} isn't UTF-8", path.display())))?;
+
+        return symlink(target, path);
+    }
+
+    // The umask decides the rest, as it does for git
+    let permissions = if mode == MODE_EXECUTABLE { 0o777 } else { 0o666 };
+
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(permissions)
+        .open(path)?
+        .write_all(&data)
+}
+
+/// Removes a file from the worktree, along with any directories that
+/// leaves empty
+pub fn remove_worktree_file<P: AsRef<[u8]>>(path: P) -> io::Result<()> {
+    let path = worktree::os_path(path.as_ref());
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            // A submodule, only removed if it was never populated
+            let _ = fs::remove_dir(path);
+        }
+        Ok(_) => fs::remove_file(path)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    let mut dir = path;
+
+    while let Some(parent) = dir.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        if fs::remove_dir(parent).is_err() {
+            break;
+        }
+
+        dir = parent;
+    }
+
+    Ok(())
+}
+
+/// Moves the index and worktree from the tree `old` (what HEAD has now) to
+/// `new`, as switching branches does.
+///
+/// Paths which are the same in both trees are left alone, keeping any
+/// local changes to them. Unless `force`, the switch is refused if it
+/// would lose local changes to any other path, or overwrite an untracked
+/// file. With `force` the index and worktree are reset to `new` entirely.
+pub fn checkout_tree(old: &TreeFiles, new: &TreeFiles, force: bool) -> Option<()> {
+    let mut index = Index::read()?;
+
+    if !force {
+        check_safe(&index, old, new)?;
+    }
+
+    let index_mtime = index_mtime();
+
+    // With force every tracked path is fair game, otherwise only those
+    // which differ between the trees
+    let paths: BTreeSet<Vec<u8>> = if force {
+        index.entries.iter()
+            .map(|entry| entry.path.clone())
+            .chain(old.keys().cloned())
+            .chain(new.keys().cloned())
+            .collect()
+    } else {
+        old.keys()
+            .chain(new.keys())
+            .filter(|path| old.get(*path) != new.get(*path))
+            .cloned()
+            .collect()
+    };
+
+    // Removals first, so a file can replace a directory and vice versa
+    for path in paths.iter().filter(|path| !new.contains_key(*path)) {
+        if let Err(err) = remove_worktree_file(path) {
+            eprintln!("error: unable to remove '{}': {err}", String::from_utf8_lossy(path));
+            return None;
+        }
+
+        index.remove(path);
+    }
+
+    for path in paths.iter().filter(|path| new.contains_key(*path)) {
+        let (mode, id) = new[path];
+
+        // Forcing still needn't touch files which are already right
+        let up_to_date = index.get(path).is_some_and(|entry| {
+            entry.mode == mode && entry.id == id && worktree_change(entry, index_mtime).is_none()
+        });
+
+        if up_to_date {
+            continue;
+        }
+
+        if let Err(err) = write_worktree_file(path, mode, id) {
+            eprintln!("error: unable to write '{}': {err}", String::from_utf8_lossy(path));
+            return None;
+        }
+
+        let metadata = fs::symlink_metadata(worktree::os_path(path)).ok()?;
+
+        let mut entry = worktree::index_entry(path, &metadata, id);
+        entry.mode = mode;
+
+        index.add(entry);
+    }
+
+    if let Err(err) = index.write() {
+        eprintln!("error: unable to write the index: {err}");
+        return None;
+    }
+
+    Some(())
+}
+
+/// Checks switching from `old` to `new` won't lose anything, listing what
+/// would be lost if it would
+fn check_safe(index: &Index, old: &TreeFiles, new: &TreeFiles) -> Option<()> {
+    if index.has_conflicts() {
+        eprintln!("error: you need to resolve your current index first");
+        return None;
+    }
+
+    let index_mtime = index_mtime();
+    let mut rules = IgnoreRules::load(&Config::load());
+
+    let mut modified = Vec::new();
+    let mut untracked: Vec<String> = Vec::new();
+
+    let changed = old.keys()
+        .chain(new.keys().filter(|path| !old.contains_key(*path)))
+        .filter(|path| old.get(*path) != new.get(*path));
+
+    for path in changed {
+        let old = old.get(path).copied();
+        let new = new.get(path).copied();
+
+        match index.get(path) {
+            Some(entry) => {
+                let staged = Some((entry.mode, entry.id));
+
+                // Staged changes are fine as long as they're what we're
+                // switching to anyway, unstaged changes never are
+                if (staged != old && staged != new) || worktree_change(entry, index_mtime).is_some() {
+                    modified.push(String::from_utf8_lossy(path));
+                }
+            }
+            None if old.is_some() => {
+                // A staged deletion
+                if new.is_some() {
+                    modified.push(String::from_utf8_lossy(path));
+                }
+            }
+            None => {
+                if let Some(blocker) = untracked_blocker(&String::from_utf8_lossy(path), index, &mut rules) {
+                    untracked.push(blocker);
+                }
+            }
+        }
+    }
+
+    if !modified.is_empty() {
+        eprintln!("error: Your local changes to the following files would be overwritten by checkout:");
+
+        for path in &modified {
+            eprintln!("\t{path}");
+        }
+
+        eprintln!("Please commit your changes or stash them before you switch branches.");
+    }
+
+    if !untracked.is_empty() {
+        eprintln!("error: The following untracked working tree files would be overwritten by checkout:");
+
+        for path in &untracked {
+            eprintln!("\t{path}");
+        }
+
+        eprintln!("Please move or remove them before you switch branches.");
+    }
+
+    (modified.is_empty() && untracked.is_empty()).then_some(())
+}
+
+/// An untracked file which would be overwritten by writing `path`: the
+/// file itself, one in its place as a directory, or one where a parent
+/// directory needs to go. Ignored files are expendable.
+fn untracked_blocker(path: &str, index: &Index, rules: &mut IgnoreRules) -> Option<String> {
+    for (pos, _) in path.match_indices('/') {
+        let parent = &path[..pos];
+
+        let blocks = fs::symlink_metadata(parent)
+            .is_ok_and(|metadata| !metadata.is_dir());
+
+        if blocks && index.get(parent).is_none() && !rules.is_ignored(parent, false) {
+            return Some(parent.to_string());
+        }
+    }
+
+    let metadata = fs::symlink_metadata(path).ok()?;
+
+    if !metadata.is_dir() {
+        return (!rules.is_ignored(path, false)).then(|| path.to_string());
+    }
+
+    // A directory is fine to replace if everything in it is tracked, the
+    // tracked files have been checked already
+    let mut files = Vec::new();
+    worktree::list_files_in(path, &mut files);
+
+    files.into_iter()
+        .find(|file| index.get(file).is_none() && !rules.is_ignored(file, false))
+}
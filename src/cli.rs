@@ -1,15 +1,97 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand, Args};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+// Output options accepted by every subcommand
+#[derive(Args)]
+pub struct GlobalArgs {
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print extra detail about what is being done
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Show progress on stderr even when it isn't a terminal
+    #[arg(long, global = true, overrides_with = "no_progress")]
+    pub progress: bool,
+
+    /// Never show progress
+    #[arg(long, global = true, overrides_with = "progress")]
+    pub no_progress: bool,
+
+    /// Print how long the command took to stderr
+    #[arg(long, global = true)]
+    pub timing: bool,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
-    CatFile(CatFileArgs)
+    CatFile(CatFileArgs),
+    LsFiles(LsFilesArgs),
+    ShowBranch(ShowBranchArgs),
+    Status(StatusArgs),
+    Branch(BranchArgs),
+    Tag(TagArgs),
+    ForEachRef(ForEachRefArgs),
+    Reflog(ReflogArgs),
+    Init(InitArgs),
+    WriteTree(WriteTreeArgs),
+    CommitTree(CommitTreeArgs),
+    Commit(CommitArgs),
+    Fsck(FsckArgs),
+    Add(AddArgs),
+    Switch(SwitchArgs),
+    Checkout(CheckoutArgs),
+    Stripspace(StripspaceArgs),
+    VerifyBundle(VerifyBundleArgs),
+    Remote(RemoteArgs),
+    Show(ShowArgs),
+    Log(LogArgs),
+    RevList(RevListArgs),
+    GraphExport(GraphExportArgs),
+    CheckIgnore(CheckIgnoreArgs),
+    UploadPack(ServeArgs),
+    ReceivePack(ServeArgs),
+    Config(ConfigArgs),
+    ZstdStore(ZstdStoreArgs),
+    UpdateRef(UpdateRefArgs),
+    Annotate(AnnotateArgs),
+    SymbolicRef(SymbolicRefArgs),
+    Merge(MergeArgs),
+    VerifyPack(VerifyPackArgs),
+    CountObjects(CountObjectsArgs),
+    Pull(PullArgs),
+    Submodule(SubmoduleArgs),
+    HashObject(HashObjectArgs),
+    MergeBase(MergeBaseArgs),
+    MergeFile(MergeFileArgs),
+    LsTree(LsTreeArgs),
+    Worktree(WorktreeArgs),
+    CheckMailmap(CheckMailmapArgs),
+    Blame(BlameArgs),
+    Grep(GrepArgs),
+    Archive(ArchiveArgs),
+    CheckAttr(CheckAttrArgs),
+    Diff(DiffArgs),
+    Shortlog(ShortlogArgs),
+    Fetch(FetchArgs),
+    Stash(StashArgs),
+    Clone(CloneArgs),
+    Restore(RestoreArgs),
+    LsRemote(LsRemoteArgs),
+    Push(PushArgs),
 }
 
 #[derive(Args)]
@@ -28,4 +110,1466 @@ pub struct CatFileMode {
 
     #[arg(short = 't')]
     pub kind: bool,
+
+    /// Exit with status 0 if the object exists, 1 if it doesn't, printing
+    /// nothing
+    #[arg(short = 'e')]
+    pub exists: bool,
+}
+
+#[derive(Args)]
+pub struct LsFilesArgs {
+    /// Show the files in the index, the default without --others
+    #[arg(short, long)]
+    pub cached: bool,
+
+    /// Show untracked files in the worktree
+    #[arg(short, long)]
+    pub others: bool,
+
+    /// Leave out untracked files .gitignore, .git/info/exclude and
+    /// core.excludesFile ignore
+    #[arg(long)]
+    pub exclude_standard: bool,
+
+    /// Show a directory with nothing tracked in it as "dir/", rather than
+    /// the files in it
+    #[arg(long)]
+    pub directory: bool,
+
+    /// With --directory, leave out directories with nothing untracked in them
+    #[arg(long)]
+    pub no_empty_directory: bool,
+
+    /// Show staged contents' mode bits, object name and stage number
+    #[arg(short, long)]
+    pub stage: bool,
+
+    /// End each path with NUL instead of a newline, and don't quote them
+    #[arg(short = 'z')]
+    pub nul: bool,
+}
+
+#[derive(Args)]
+pub struct ShowBranchArgs {
+    /// Go <n> more commits beyond the common ancestor
+    #[arg(long, default_value_t = 0)]
+    pub more: usize,
+
+    /// Print the merge bases of the given revs instead
+    #[arg(long, conflicts_with = "independent")]
+    pub merge_base: bool,
+
+    /// Print only the revs which can't be reached from any other
+    #[arg(long)]
+    pub independent: bool,
+
+    /// Revs to compare, defaults to all local branches
+    pub revs: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct StatusArgs {
+    /// Give the output in an easy-to-parse format for scripts
+    #[arg(long)]
+    pub porcelain: bool,
+
+    /// End each porcelain entry with NUL instead of a newline, and don't
+    /// quote paths. Implies --porcelain.
+    #[arg(short = 'z')]
+    pub nul: bool,
+
+    /// Which untracked files to show: "no", "normal", which shows a
+    /// directory with nothing tracked in it rather than its files, or "all"
+    #[arg(short = 'u', long, value_name = "mode", num_args = 0..=1, default_missing_value = "all")]
+    pub untracked_files: Option<String>,
+
+    /// Which changes to submodules to leave out: "none", "untracked",
+    /// "dirty", which leaves only their checked out commit, or "all"
+    #[arg(long, value_name = "when", num_args = 0..=1, require_equals = true, default_missing_value = "all")]
+    pub ignore_submodules: Option<String>,
+}
+
+#[derive(Args)]
+pub struct RefFilterArgs {
+    /// Only list refs which contain the commit (HEAD if not specified)
+    #[arg(long, value_name = "commit", num_args = 0..=1, default_missing_value = "HEAD")]
+    pub contains: Vec<String>,
+
+    /// Only list refs which don't contain the commit (HEAD if not specified)
+    #[arg(long, value_name = "commit", num_args = 0..=1, default_missing_value = "HEAD")]
+    pub no_contains: Vec<String>,
+
+    /// Only list refs whose tips are reachable from the commit (HEAD if not specified)
+    #[arg(long, value_name = "commit", num_args = 0..=1, default_missing_value = "HEAD")]
+    pub merged: Vec<String>,
+
+    /// Only list refs whose tips are not reachable from the commit (HEAD if not specified)
+    #[arg(long, value_name = "commit", num_args = 0..=1, default_missing_value = "HEAD")]
+    pub no_merged: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct BranchArgs {
+    /// List branches, optionally only those matching the patterns
+    #[arg(short, long)]
+    pub list: bool,
+
+    /// List remote-tracking branches
+    #[arg(short, long)]
+    pub remotes: bool,
+
+    /// List both local and remote-tracking branches
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Delete branches, which must be merged into HEAD
+    #[arg(short, long)]
+    pub delete: bool,
+
+    /// Delete branches, even if they aren't merged
+    #[arg(short = 'D')]
+    pub force_delete: bool,
+
+    /// Reset the branch if it already exists, or with -d delete it even if
+    /// it isn't merged
+    #[arg(short, long)]
+    pub force: bool,
+
+    #[command(flatten)]
+    pub filter: RefFilterArgs,
+
+    /// Patterns to list, the branch to create and optionally its start
+    /// point, or the branches to delete
+    pub names: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct TagArgs {
+    /// List tags, optionally only those matching the patterns
+    #[arg(short, long)]
+    pub list: bool,
+
+    /// Create an annotated tag object
+    #[arg(short, long)]
+    pub annotate: bool,
+
+    /// A paragraph of the tag message, may be given more than once.
+    /// Implies -a
+    #[arg(short = 'm', long = "message", value_name = "MESSAGE", conflicts_with = "file")]
+    pub messages: Vec<String>,
+
+    /// Read the tag message from a file, "-" for stdin. Implies -a
+    #[arg(short = 'F', long, value_name = "FILE")]
+    pub file: Option<PathBuf>,
+
+    /// Replace an existing tag
+    #[arg(short, long)]
+    pub force: bool,
+
+    #[command(flatten)]
+    pub filter: RefFilterArgs,
+
+    /// Patterns to list, or the tag to create and optionally the object
+    /// it should point at
+    pub names: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ForEachRefArgs {
+    /// Format string interpolating %(fieldname) from each ref
+    #[arg(long)]
+    pub format: Option<String>,
+
+    #[command(flatten)]
+    pub filter: RefFilterArgs,
+
+    pub patterns: Vec<String>,
+}
+
+#[derive(Args)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct ReflogArgs {
+    #[command(subcommand)]
+    pub command: Option<ReflogCommands>,
+
+    /// Without a subcommand, the reflog is shown
+    #[command(flatten)]
+    pub show: ReflogShowArgs,
+}
+
+#[derive(Subcommand)]
+pub enum ReflogCommands {
+    Show(ReflogShowArgs),
+    Expire(ReflogExpireArgs),
+}
+
+#[derive(Args)]
+pub struct ReflogShowArgs {
+    /// Show at most this many entries
+    #[arg(short = 'n', long)]
+    pub max_count: Option<usize>,
+
+    /// The ref whose reflog to show, defaults to HEAD
+    pub refname: Option<String>,
+}
+
+#[derive(Args)]
+pub struct ReflogExpireArgs {
+    /// Prune entries older than this, defaults to gc.reflogExpire
+    #[arg(long)]
+    pub expire: Option<String>,
+
+    /// Prune entries older than this which aren't reachable from the ref,
+    /// defaults to gc.reflogExpireUnreachable
+    #[arg(long)]
+    pub expire_unreachable: Option<String>,
+
+    /// Expire the reflogs of every ref
+    #[arg(long)]
+    pub all: bool,
+
+    /// Only show what would be pruned
+    #[arg(long)]
+    pub dry_run: bool,
+
+    pub refs: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct InitArgs {
+    /// Create a bare repository, without a working tree
+    #[arg(long)]
+    pub bare: bool,
+
+    /// Name of the branch HEAD initially points at
+    #[arg(short = 'b', long)]
+    pub initial_branch: Option<String>,
+
+    /// Where to create the repository, defaults to the current directory
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct WriteTreeArgs {
+    /// Don't check that the objects in the index exist
+    #[arg(long)]
+    pub missing_ok: bool,
+}
+
+#[derive(Args)]
+pub struct CommitTreeArgs {
+    /// The tree to commit
+    pub tree: String,
+
+    /// A parent commit, may be given more than once
+    #[arg(short = 'p', value_name = "PARENT")]
+    pub parents: Vec<String>,
+
+    /// A paragraph of the commit message, may be given more than once
+    #[arg(short = 'm', value_name = "MESSAGE", conflicts_with = "file")]
+    pub messages: Vec<String>,
+
+    /// Read the commit message from a file, "-" for stdin
+    #[arg(short = 'F', value_name = "FILE")]
+    pub file: Option<PathBuf>,
+
+    /// Override the author, given as "Name <email>"
+    #[arg(long, value_name = "AUTHOR")]
+    pub author: Option<String>,
+
+    /// Override the author date
+    #[arg(long, value_name = "DATE")]
+    pub date: Option<String>,
+}
+
+#[derive(Args)]
+pub struct CommitArgs {
+    /// A paragraph of the commit message, may be given more than once
+    #[arg(short = 'm', long = "message", value_name = "MESSAGE", conflicts_with = "file")]
+    pub messages: Vec<String>,
+
+    /// Read the commit message from a file, "-" for stdin
+    #[arg(short = 'F', long, value_name = "FILE")]
+    pub file: Option<PathBuf>,
+
+    /// Edit the message given by -m or -F before committing
+    #[arg(short, long, overrides_with = "no_edit")]
+    pub edit: bool,
+
+    /// Commit without editing the message, with --amend the one it has
+    #[arg(long, overrides_with = "edit")]
+    pub no_edit: bool,
+
+    /// Start the message in the editor from this file rather than
+    /// commit.template
+    #[arg(short, long, value_name = "FILE")]
+    pub template: Option<PathBuf>,
+
+    /// Allow a commit with the same tree as its parent
+    #[arg(long)]
+    pub allow_empty: bool,
+
+    /// Override the author, given as "Name <email>"
+    #[arg(long, value_name = "AUTHOR")]
+    pub author: Option<String>,
+
+    /// Override the author date
+    #[arg(long, value_name = "DATE")]
+    pub date: Option<String>,
+
+    /// Replace HEAD with a new commit, keeping its parents, author and,
+    /// unless another is given, its message
+    #[arg(long, conflicts_with_all = ["fixup", "squash"])]
+    pub amend: bool,
+
+    /// With --amend, make the committer the author, as of now
+    #[arg(long, requires = "amend")]
+    pub reset_author: bool,
+
+    /// Make a "fixup! " commit for a rebase with --autosquash to fold into
+    /// <COMMIT>. "amend:<COMMIT>" also replaces its message, and
+    /// "reword:<COMMIT>" only replaces its message.
+    #[arg(long, value_name = "COMMIT", conflicts_with = "squash")]
+    pub fixup: Option<String>,
+
+    /// Make a "squash! " commit for a rebase with --autosquash to fold into
+    /// <COMMIT>, combining their messages
+    #[arg(long, value_name = "COMMIT")]
+    pub squash: Option<String>,
+}
+
+#[derive(Args)]
+pub struct FsckArgs {
+    /// Treat warnings as errors
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Also check every object re-serializes to exactly the same bytes
+    #[arg(long)]
+    pub round_trip: bool,
+
+    /// Report every unreachable object, not just the dangling ones
+    #[arg(long)]
+    pub unreachable: bool,
+
+    /// Don't report dangling objects
+    #[arg(long)]
+    pub no_dangling: bool,
+
+    /// Don't count objects only reflogs refer to as reachable
+    #[arg(long)]
+    pub no_reflogs: bool,
+}
+
+#[derive(Args)]
+pub struct AddArgs {
+    /// Also add files which are ignored
+    #[arg(short, long)]
+    pub force: bool,
+
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct SwitchArgs {
+    /// Create a new branch at the start point and switch to it
+    #[arg(short, long, value_name = "BRANCH", conflicts_with = "force_create")]
+    pub create: Option<String>,
+
+    /// Like --create, but resets the branch to the start point if it exists
+    #[arg(short = 'C', long, value_name = "BRANCH")]
+    pub force_create: Option<String>,
+
+    /// Switch to a commit, HEAD by default, detaching HEAD
+    #[arg(short, long, conflicts_with_all = ["create", "force_create"])]
+    pub detach: bool,
+
+    /// Don't create a branch for a remote-tracking branch of the same name
+    /// when there's no such branch
+    #[arg(long)]
+    pub no_guess: bool,
+
+    /// Switch even if it loses local changes, resetting the index and
+    /// worktree
+    #[arg(short, long, alias = "discard-changes")]
+    pub force: bool,
+
+    /// The branch to switch to, or with -c or --detach, a commit
+    pub target: Option<String>,
+}
+
+#[derive(Args)]
+pub struct CheckoutArgs {
+    /// Create a new branch at the commit and switch to it
+    #[arg(short = 'b', value_name = "BRANCH")]
+    pub create: Option<String>,
+
+    /// Switch even if it loses local changes, resetting the index and
+    /// worktree
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// The branch or commit to switch to, or with paths, to take the
+    /// files from (the index if not given)
+    pub rev: Option<String>,
+
+    /// Restore these paths instead of switching
+    #[arg(last = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct StripspaceArgs {
+    /// Also remove lines starting with the comment character
+    #[arg(short, long, conflicts_with = "comment_lines")]
+    pub strip_comments: bool,
+
+    /// Prefix every line with the comment character instead
+    #[arg(short, long)]
+    pub comment_lines: bool,
+}
+
+#[derive(Args)]
+pub struct VerifyBundleArgs {
+    /// The bundle file to check
+    pub file: PathBuf,
+}
+
+#[derive(Args)]
+pub struct RemoteArgs {
+    /// Without a subcommand the remotes are listed, with -v along with
+    /// their URLs
+    #[command(subcommand)]
+    pub command: Option<RemoteCommands>,
+}
+
+#[derive(Subcommand)]
+pub enum RemoteCommands {
+    Add(RemoteAddArgs),
+    GetUrl(RemoteGetUrlArgs),
+}
+
+#[derive(Args)]
+pub struct RemoteAddArgs {
+    /// Fetch from the remote once it's added
+    #[arg(short, long)]
+    pub fetch: bool,
+
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Args)]
+pub struct RemoteGetUrlArgs {
+    /// Show the URLs pushes go to rather than those fetches come from
+    #[arg(long)]
+    pub push: bool,
+
+    /// Show every URL rather than just the first
+    #[arg(long)]
+    pub all: bool,
+
+    pub name: String,
+}
+
+#[derive(Args)]
+pub struct ShowArgs {
+    /// Don't show the patches of commits
+    #[arg(short = 's', long)]
+    pub no_patch: bool,
+
+    #[command(flatten)]
+    pub pretty: PrettyArgs,
+
+    /// The objects to show, HEAD if none are given
+    pub objects: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct PrettyArgs {
+    /// How to show commits: oneline, short, medium, full, fuller, raw,
+    /// "format:<string>", "tformat:<string>" or a format string
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, require_equals = true, default_missing_value = "medium")]
+    pub pretty: Option<String>,
+
+    /// Same as --pretty
+    #[arg(long, value_name = "FORMAT", conflicts_with = "pretty")]
+    pub format: Option<String>,
+
+    /// Shorthand for --pretty=oneline --abbrev-commit
+    #[arg(long)]
+    pub oneline: bool,
+
+    /// Abbreviate commit ids in the commit line
+    #[arg(long)]
+    pub abbrev_commit: bool,
+
+    /// Show the names of refs pointing at commits
+    #[arg(long)]
+    pub decorate: bool,
+
+    /// How to show dates, eg. iso, rfc2822, relative or human
+    #[arg(long, value_name = "FORMAT")]
+    pub date: Option<String>,
+
+    /// Show names and emails as the mailmap maps them, the default for log
+    /// and show unless log.mailmap is false
+    #[arg(long, visible_alias = "use-mailmap", overrides_with = "no_mailmap")]
+    pub mailmap: bool,
+
+    /// Show names and emails as they were committed
+    #[arg(long, visible_alias = "no-use-mailmap", overrides_with = "mailmap")]
+    pub no_mailmap: bool,
+}
+
+#[derive(Args)]
+pub struct LogArgs {
+    /// Show the patch each commit introduces
+    #[arg(short, long)]
+    pub patch: bool,
+
+    /// Show at most this many commits
+    #[arg(short = 'n', long, value_name = "NUMBER")]
+    pub max_count: Option<usize>,
+
+    /// Show which of the starting points each commit was reached from
+    #[arg(long)]
+    pub source: bool,
+
+    /// Walk the reflogs of the refs given, newest entry first, rather than
+    /// the commits' ancestry
+    #[arg(short = 'g', long)]
+    pub walk_reflogs: bool,
+
+    /// Only show reflog entries whose message matches this regular
+    /// expression, any of them if given more than once
+    #[arg(long, value_name = "PATTERN", requires = "walk_reflogs")]
+    pub grep_reflog: Vec<String>,
+
+    #[command(flatten)]
+    pub pretty: PrettyArgs,
+
+    /// Where to walk from, HEAD if nothing is given
+    #[command(flatten)]
+    pub revisions: RevisionArgs,
+}
+
+#[derive(Args)]
+pub struct RevListArgs {
+    /// Show at most this many commits
+    #[arg(short = 'n', long, value_name = "NUMBER")]
+    pub max_count: Option<usize>,
+
+    /// Also list the trees and blobs the commits refer to, with their paths
+    #[arg(long)]
+    pub objects: bool,
+
+    /// Leave objects out of those listed, eg. "blob:none", "blob:limit=1m"
+    /// or "tree:0". Given more than once, what any leaves out is left out.
+    #[arg(long, value_name = "FILTER-SPEC", requires = "objects")]
+    pub filter: Vec<String>,
+
+    /// List what the filter left out too, each as "~" <id>
+    #[arg(long, requires = "filter")]
+    pub filter_print_omitted: bool,
+
+    /// What to do about trees and blobs which can't be found, as in a
+    /// partial clone: "error" (the default), "allow-any" to carry on, or
+    /// "print" to carry on and list them at the end, each as "?" <id>
+    #[arg(long, value_name = "ACTION", requires = "objects")]
+    pub missing: Option<String>,
+
+    #[command(flatten)]
+    pub pretty: PrettyArgs,
+
+    #[command(flatten)]
+    pub revisions: RevisionArgs,
+}
+
+#[derive(Args)]
+pub struct RevisionArgs {
+    /// Walk from every ref, and HEAD
+    #[arg(long)]
+    pub all: bool,
+
+    /// Walk from the branches, or those matching the pattern
+    #[arg(long, value_name = "PATTERN", num_args = 0..=1, require_equals = true, default_missing_value = "*")]
+    pub branches: Vec<String>,
+
+    /// Walk from the tags, or those matching the pattern
+    #[arg(long, value_name = "PATTERN", num_args = 0..=1, require_equals = true, default_missing_value = "*")]
+    pub tags: Vec<String>,
+
+    /// Walk from the remote-tracking branches, or those matching the pattern
+    #[arg(long, value_name = "PATTERN", num_args = 0..=1, require_equals = true, default_missing_value = "*")]
+    pub remotes: Vec<String>,
+
+    /// Drop merges which bring no changes to the paths from the full
+    /// history, and walk in topological order
+    #[arg(long)]
+    pub simplify_merges: bool,
+
+    /// Revisions and ranges to walk, eg. "main", "^v1", "v1..main" or
+    /// "a...b"
+    pub revisions: Vec<String>,
+
+    /// Only walk the commits which change these paths
+    #[arg(last = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct GraphExportArgs {
+    /// Export a JSON graph rather than a Graphviz digraph
+    #[arg(long)]
+    pub json: bool,
+
+    /// Export at most this many commits
+    #[arg(short = 'n', long, value_name = "NUMBER")]
+    pub max_count: Option<usize>,
+
+    /// Where to walk from, HEAD if nothing is given
+    #[command(flatten)]
+    pub revisions: RevisionArgs,
+}
+
+#[derive(Args)]
+pub struct CheckIgnoreArgs {
+    /// Show the pattern which matched each path, including those which
+    /// re-include it
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// With --verbose, also list the paths which no pattern matched
+    #[arg(short, long, requires = "verbose")]
+    pub non_matching: bool,
+
+    /// Check tracked paths as well, which are otherwise never ignored
+    #[arg(long)]
+    pub no_index: bool,
+
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Only advertise the refs and stop, as a smart HTTP server does
+    /// first
+    #[arg(long)]
+    pub advertise_refs: bool,
+
+    /// The repository to serve, its worktree or git dir
+    pub directory: PathBuf,
+}
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(flatten)]
+    pub action: ConfigAction,
+
+    /// Read this file alone, without following its includes, rather than
+    /// the system, global and repository config
+    #[arg(short, long)]
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct ConfigAction {
+    /// Print the value of a key, the last one if it's set more than once
+    #[arg(long, value_name = "NAME")]
+    pub get: Option<String>,
+
+    /// Print every value of a key
+    #[arg(long, value_name = "NAME")]
+    pub get_all: Option<String>,
+
+    /// List every key and its value
+    #[arg(short, long)]
+    pub list: bool,
+}
+
+/// Moves loose objects in and out of the experimental zstd layout, which
+/// git can't read
+#[derive(Args)]
+pub struct ZstdStoreArgs {
+    #[command(subcommand)]
+    pub command: ZstdStoreCommands,
+}
+
+#[derive(Subcommand)]
+pub enum ZstdStoreCommands {
+    /// Turn on the zstd layout and rewrite the loose objects in it
+    Import,
+
+    /// Turn off the zstd layout and rewrite the loose objects as git does
+    Export,
+}
+
+#[derive(Args)]
+pub struct UpdateRefArgs {
+    /// The reason for the update, recorded in the reflog
+    #[arg(short = 'm', value_name = "REASON")]
+    pub message: Option<String>,
+
+    /// Delete the ref rather than update it
+    #[arg(short = 'd')]
+    pub delete: bool,
+
+    /// Update a symbolic ref itself rather than the ref it points to
+    #[arg(long)]
+    pub no_deref: bool,
+
+    /// The ref to update
+    pub refname: String,
+
+    /// The new value followed by the old one the ref must be at, or with
+    /// -d just the old one. An empty old value means the ref mustn't exist.
+    #[arg(value_name = "VALUE")]
+    pub values: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct AnnotateArgs {
+    /// Show blame in a format for scripts, each commit's details only the
+    /// first time it's shown
+    #[arg(short = 'p', long)]
+    pub porcelain: bool,
+
+    /// Like --porcelain, with each commit's details after every line
+    #[arg(long)]
+    pub line_porcelain: bool,
+
+    /// Show each run of lines as soon as it's blamed, in a format for
+    /// scripts, without the lines themselves
+    #[arg(long, conflicts_with_all = ["porcelain", "line_porcelain"])]
+    pub incremental: bool,
+
+    /// Show authors as they were committed, rather than as the mailmap
+    /// maps them
+    #[arg(long)]
+    pub no_mailmap: bool,
+
+    /// [<rev>] <file>, the working tree's version of the file if no
+    /// revision is given
+    #[arg(required = true, value_name = "ARGS")]
+    pub args: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct BlameArgs {
+    /// Show blame in a format for scripts, each commit's details only the
+    /// first time it's shown
+    #[arg(short = 'p', long)]
+    pub porcelain: bool,
+
+    /// Like --porcelain, with each commit's details after every line
+    #[arg(long)]
+    pub line_porcelain: bool,
+
+    /// Show each run of lines as soon as it's blamed, in a format for
+    /// scripts, without the lines themselves
+    #[arg(long, conflicts_with_all = ["porcelain", "line_porcelain"])]
+    pub incremental: bool,
+
+    /// Show full commit ids rather than abbreviating them
+    #[arg(short = 'l')]
+    pub long: bool,
+
+    /// Show blanks rather than the ids of boundary commits
+    #[arg(short = 'b')]
+    pub blank_boundary: bool,
+
+    /// Don't treat root commits as boundaries
+    #[arg(long)]
+    pub root: bool,
+
+    /// Show the path the lines had in the commit they're blamed on
+    #[arg(short = 'f', long)]
+    pub show_name: bool,
+
+    /// Show the line number the lines had in the commit they're blamed on
+    #[arg(short = 'n', long)]
+    pub show_number: bool,
+
+    /// Show the author's email rather than their name
+    #[arg(short = 'e', long)]
+    pub show_email: bool,
+
+    /// Leave out the author and date
+    #[arg(short = 's')]
+    pub suppress: bool,
+
+    /// Show dates as seconds since the epoch and a timezone
+    #[arg(short = 't')]
+    pub raw_time: bool,
+
+    /// Show authors as they were committed, rather than as the mailmap
+    /// maps them
+    #[arg(long)]
+    pub no_mailmap: bool,
+
+    /// [<rev>] <file>, the working tree's version of the file if no
+    /// revision is given
+    #[arg(required = true, value_name = "ARGS")]
+    pub args: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct SymbolicRefArgs {
+    /// The reason for the update, recorded in the reflog
+    #[arg(short = 'm', value_name = "REASON")]
+    pub message: Option<String>,
+
+    /// Delete the symbolic ref
+    #[arg(short, long, conflicts_with = "target")]
+    pub delete: bool,
+
+    /// Print the ref pointed to shortened, eg. "main" for "refs/heads/main"
+    #[arg(long)]
+    pub short: bool,
+
+    /// Print the ref the symbolic ref points to, rather than the one a
+    /// chain of symbolic refs ends at
+    #[arg(long)]
+    pub no_recurse: bool,
+
+    /// The symbolic ref, usually HEAD
+    pub name: String,
+
+    /// The ref to point it to
+    pub target: Option<String>,
+}
+
+#[derive(Args)]
+pub struct MergeArgs {
+    /// Fast-forward when possible, otherwise make a merge commit, the
+    /// default unless merge.ff says otherwise
+    #[arg(long, overrides_with_all = ["no_ff", "ff_only"])]
+    pub ff: bool,
+
+    /// Make a merge commit even when a fast-forward is possible
+    #[arg(long, overrides_with_all = ["ff", "ff_only"])]
+    pub no_ff: bool,
+
+    /// Only fast-forward, refusing to merge otherwise
+    #[arg(long, overrides_with_all = ["ff", "no_ff"])]
+    pub ff_only: bool,
+
+    /// Update the index and worktree as merging would, but leave the
+    /// commit to be made, as an ordinary one, with SQUASH_MSG as its message
+    #[arg(long, conflicts_with = "no_ff")]
+    pub squash: bool,
+
+    /// A paragraph of the merge commit's message, may be given more than once
+    #[arg(short = 'm', long = "message", value_name = "MESSAGE")]
+    pub messages: Vec<String>,
+
+    /// Stash local changes before merging and reapply them after, the
+    /// default if merge.autoStash is set
+    #[arg(long, overrides_with = "no_autostash")]
+    pub autostash: bool,
+
+    /// Don't stash, whatever merge.autoStash says
+    #[arg(long, overrides_with = "autostash")]
+    pub no_autostash: bool,
+
+    /// Refuse to merge unless the commit has a good signature from a
+    /// trusted key, the default if merge.verifySignatures is set
+    #[arg(long, overrides_with = "no_verify_signatures")]
+    pub verify_signatures: bool,
+
+    /// Don't check signatures, whatever merge.verifySignatures says
+    #[arg(long, overrides_with = "verify_signatures")]
+    pub no_verify_signatures: bool,
+
+    /// The commit to merge into HEAD
+    pub commit: String,
+}
+
+#[derive(Args)]
+pub struct VerifyPackArgs {
+    /// List every object, how the objects' delta chains are spread, and
+    /// whether each pack is ok
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Only show how the objects' delta chains are spread
+    #[arg(short, long)]
+    pub stat_only: bool,
+
+    /// The packs to check, by their .idx or .pack file
+    #[arg(required = true)]
+    pub packs: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct CountObjectsArgs {
+    /// Also count packed objects and garbage, and report the size of packs
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Show sizes in human-readable units rather than kilobytes
+    #[arg(short = 'H', long)]
+    pub human_readable: bool,
+}
+
+#[derive(Args)]
+pub struct PullArgs {
+    /// Rebase onto what's fetched rather than merging it, the default if
+    /// branch.<name>.rebase or pull.rebase says so
+    #[arg(short, long, overrides_with = "no_rebase")]
+    pub rebase: bool,
+
+    /// Merge what's fetched, whatever pull.rebase says
+    #[arg(long, overrides_with = "rebase")]
+    pub no_rebase: bool,
+
+    /// Fast-forward when possible, otherwise make a merge commit
+    #[arg(long, overrides_with_all = ["no_ff", "ff_only"])]
+    pub ff: bool,
+
+    /// Make a merge commit even when a fast-forward is possible
+    #[arg(long, overrides_with_all = ["ff", "ff_only"])]
+    pub no_ff: bool,
+
+    /// Only fast-forward, refusing to merge otherwise
+    #[arg(long, overrides_with_all = ["ff", "no_ff"])]
+    pub ff_only: bool,
+
+    /// Stash local changes before merging or rebasing and reapply them
+    /// after, the default if merge.autoStash, or rebase.autoStash when
+    /// rebasing, is set
+    #[arg(long, overrides_with = "no_autostash")]
+    pub autostash: bool,
+
+    /// Don't stash, whatever merge.autoStash or rebase.autoStash says
+    #[arg(long, overrides_with = "autostash")]
+    pub no_autostash: bool,
+
+    /// Refuse to merge unless the commit has a good signature from a
+    /// trusted key, the default if merge.verifySignatures is set
+    #[arg(long, overrides_with = "no_verify_signatures")]
+    pub verify_signatures: bool,
+
+    /// Don't check signatures, whatever merge.verifySignatures says
+    #[arg(long, overrides_with = "verify_signatures")]
+    pub no_verify_signatures: bool,
+
+    /// The remote to pull from, the current branch's upstream remote if
+    /// not given
+    pub repository: Option<String>,
+
+    /// The branch to pull, the current branch's upstream if not given
+    #[arg(requires = "repository")]
+    pub refspec: Option<String>,
+}
+
+#[derive(Args)]
+pub struct SubmoduleArgs {
+    #[command(subcommand)]
+    pub command: SubmoduleCommands,
+}
+
+#[derive(Subcommand)]
+pub enum SubmoduleCommands {
+    Status(SubmoduleStatusArgs),
+}
+
+#[derive(Args)]
+pub struct SubmoduleStatusArgs {
+    /// Show the commit the superproject records rather than the one
+    /// checked out
+    #[arg(long)]
+    pub cached: bool,
+
+    /// Only show the submodules at these paths
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct HashObjectArgs {
+    /// The type of object to make
+    #[arg(short = 't', default_value = "blob")]
+    pub kind: String,
+
+    /// Write the objects into the object database, not just hash them
+    #[arg(short)]
+    pub write: bool,
+
+    /// Hash what's read from standard input, before any files given
+    #[arg(long, conflicts_with = "stdin_paths")]
+    pub stdin: bool,
+
+    /// Read the paths of the files to hash from standard input, one per
+    /// line, rather than from the command line
+    #[arg(long, conflicts_with = "files")]
+    pub stdin_paths: bool,
+
+    /// Allow any type and contents, without checking they make a valid
+    /// object
+    #[arg(long)]
+    pub literally: bool,
+
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct MergeBaseArgs {
+    /// Print every best common ancestor, not just one
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Find the best common ancestors of all the commits at once, as an
+    /// octopus merge of them would
+    #[arg(long)]
+    pub octopus: bool,
+
+    /// The first commit, then those to consider merged together to
+    /// compare it against
+    #[arg(required = true)]
+    pub commits: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct MergeFileArgs {
+    /// Labels for the conflict markers instead of the file names, given
+    /// once for each file in order
+    #[arg(short = 'L', action = clap::ArgAction::Append)]
+    pub labels: Vec<String>,
+
+    /// Print the merge rather than overwriting the current file
+    #[arg(short = 'p', long)]
+    pub stdout: bool,
+
+    /// Show the base version in conflicts too
+    #[arg(long, conflicts_with = "zdiff3")]
+    pub diff3: bool,
+
+    /// Show the base version in conflicts too, less the lines both sides
+    /// share at the start and end
+    #[arg(long)]
+    pub zdiff3: bool,
+
+    /// Resolve conflicts in favour of our side
+    #[arg(long, conflicts_with_all = ["theirs", "union"])]
+    pub ours: bool,
+
+    /// Resolve conflicts in favour of their side
+    #[arg(long, conflicts_with = "union")]
+    pub theirs: bool,
+
+    /// Resolve conflicts by keeping both sides
+    #[arg(long)]
+    pub union: bool,
+
+    #[arg(long, default_value_t = gitty::merge::DEFAULT_MARKER_SIZE)]
+    pub marker_size: usize,
+
+    pub current: PathBuf,
+    pub base: PathBuf,
+    pub other: PathBuf,
+}
+
+#[derive(Args)]
+pub struct LsTreeArgs {
+    /// Recurse into subtrees
+    #[arg(short)]
+    pub recursive: bool,
+
+    /// Show only trees
+    #[arg(short = 'd')]
+    pub trees_only: bool,
+
+    /// Show trees as well as recursing into them
+    #[arg(short = 't')]
+    pub show_trees: bool,
+
+    /// Show the size of each blob
+    #[arg(short, long)]
+    pub long: bool,
+
+    /// End each entry with NUL rather than a newline and don't quote paths
+    #[arg(short = 'z')]
+    pub nul: bool,
+
+    /// Show only each entry's path
+    #[arg(long, conflicts_with_all = ["object_only", "long", "format"])]
+    pub name_only: bool,
+
+    /// Show only each entry's object id
+    #[arg(long, conflicts_with_all = ["long", "format"])]
+    pub object_only: bool,
+
+    /// Show each entry as this format, with %(objectmode), %(objecttype),
+    /// %(objectname), %(objectsize), %(objectsize:padded) and %(path)
+    /// expanded
+    #[arg(long, conflicts_with = "long")]
+    pub format: Option<String>,
+
+    /// The tree, or the commit or tag whose tree to list
+    pub tree: String,
+
+    /// Only list these paths, or what's in them
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct WorktreeArgs {
+    #[command(subcommand)]
+    pub command: WorktreeCommands,
+}
+
+#[derive(Subcommand)]
+pub enum WorktreeCommands {
+    Prune(WorktreePruneArgs),
+    Repair(WorktreeRepairArgs),
+}
+
+#[derive(Args)]
+pub struct WorktreePruneArgs {
+    /// Only show what would be pruned
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+
+    /// Only prune missing worktrees which haven't been used since this,
+    /// by default however recently they were
+    #[arg(long)]
+    pub expire: Option<String>,
+}
+
+#[derive(Args)]
+pub struct WorktreeRepairArgs {
+    /// The worktrees to repair the links to, which may have moved, defaults
+    /// to the current one
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct CheckMailmapArgs {
+    /// Read contacts from standard input, one per line, after those given
+    #[arg(long)]
+    pub stdin: bool,
+
+    /// Read this mailmap file too, taking precedence over mailmap.file
+    #[arg(long, value_name = "FILE")]
+    pub mailmap_file: Option<PathBuf>,
+
+    /// Read the mailmap in this blob too, eg. "HEAD:.mailmap"
+    #[arg(long, value_name = "BLOB")]
+    pub mailmap_blob: Option<String>,
+
+    /// Contacts to look up, as "Name <email>" or "<email>"
+    #[arg(required_unless_present = "stdin")]
+    pub contacts: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct GrepArgs {
+    /// Prefix each line found with its line number, the default if
+    /// grep.lineNumber is set
+    #[arg(short = 'n', long)]
+    pub line_number: bool,
+
+    /// Match regardless of case
+    #[arg(short, long)]
+    pub ignore_case: bool,
+
+    /// Find the lines which don't match
+    #[arg(short = 'v', long)]
+    pub invert_match: bool,
+
+    /// Print extra detail about what is being done, only as --verbose
+    /// here, since -v is --invert-match as it is for grep
+    #[arg(long)]
+    pub verbose: bool,
+
+    /// Only list the files with lines found
+    #[arg(short = 'l', long, visible_alias = "name-only", conflicts_with = "count")]
+    pub files_with_matches: bool,
+
+    /// List how many lines are found in each file
+    #[arg(short, long)]
+    pub count: bool,
+
+    /// Search the files as they're staged rather than in the worktree
+    #[arg(long, conflicts_with = "trees")]
+    pub cached: bool,
+
+    /// How many threads to read files on, by default grep.threads or
+    /// failing that, one per CPU
+    #[arg(long)]
+    pub threads: Option<usize>,
+
+    /// The regular expression to look for
+    pub pattern: String,
+
+    /// Trees to search instead of the worktree, eg. "HEAD" or "v1:src"
+    pub trees: Vec<String>,
+
+    /// Only search these paths
+    #[arg(last = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ArchiveArgs {
+    /// "tar" or "zip", by default what the output file's name ends with,
+    /// failing that tar
+    #[arg(long)]
+    pub format: Option<String>,
+
+    /// Put before every path in the archive, eg. "project/"
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+
+    /// Write the archive to this file rather than stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// List the formats there are
+    #[arg(short, long, conflicts_with = "tree_ish")]
+    pub list: bool,
+
+    /// Read attributes from the worktree rather than the tree archived
+    #[arg(long)]
+    pub worktree_attributes: bool,
+
+    /// Add this file from outside the tree too, under the prefix by its
+    /// name alone. May be given more than once.
+    #[arg(long = "add-file", value_name = "FILE")]
+    pub add_files: Vec<PathBuf>,
+
+    /// Give the entries this time, eg. "@1700000000" or "2 weeks ago",
+    /// rather than the commit's
+    #[arg(long)]
+    pub mtime: Option<String>,
+
+    /// The tree, or commit or tag of one, to archive
+    #[arg(required_unless_present = "list")]
+    pub tree_ish: Option<String>,
+
+    /// Only archive these paths
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct CheckAttrArgs {
+    /// List every attribute each path has
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Read `.gitattributes` from the index, not the worktree
+    #[arg(long)]
+    pub cached: bool,
+
+    /// The attributes to show then, without "--", the paths
+    pub args: Vec<String>,
+
+    /// The paths, after "--"
+    #[arg(last = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// Compare two paths on the filesystem, which needn't be in a
+    /// repository. The only mode there is yet.
+    #[arg(long, required = true)]
+    pub no_index: bool,
+
+    /// A file or directory
+    pub old: String,
+
+    /// What `old` is compared with, a directory holding a file of the same
+    /// name if `old` is a file
+    pub new: String,
+}
+
+#[derive(Args)]
+pub struct ShortlogArgs {
+    /// Only show how many commits each author has
+    #[arg(short, long)]
+    pub summary: bool,
+
+    /// Sort by how many commits each author has, rather than by name
+    #[arg(short, long)]
+    pub numbered: bool,
+
+    /// Show each author's email too
+    #[arg(short, long)]
+    pub email: bool,
+
+    /// Group commits by committer rather than author, as --group=committer
+    #[arg(short, long)]
+    pub committer: bool,
+
+    /// What to group commits by: "author", "committer", or "trailer:<key>"
+    /// for the values of that trailer, eg. "trailer:co-authored-by". Can be
+    /// given more than once to list a commit under each, once per person.
+    #[arg(long, value_name = "TYPE")]
+    pub group: Vec<String>,
+
+    /// Group commits by names and emails as they were committed, rather
+    /// than as the mailmap maps them
+    #[arg(long)]
+    pub no_mailmap: bool,
+
+    /// Wrap subjects, as "-w<width>[,<indent1>[,<indent2>]]", by default
+    /// at 76 columns indented by 6 then 9
+    #[arg(short = 'w', value_name = "WIDTH", num_args = 0..=1, default_missing_value = "")]
+    pub wrap: Option<String>,
+
+    /// Where to walk from, HEAD if nothing is given
+    #[command(flatten)]
+    pub revisions: RevisionArgs,
+}
+
+#[derive(Args)]
+pub struct FetchArgs {
+    /// A remote's name or a URL: http:// (plain http, https isn't
+    /// supported), git://, ssh:// or "<host>:<path>"
+    pub repository: String,
+
+    /// What to fetch and where to store it, eg. "main" or
+    /// "+refs/heads/*:refs/remotes/origin/*". A remote's configured
+    /// refspecs if none are given, else HEAD.
+    pub refspecs: Vec<String>,
+}
+
+#[derive(Args)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct StashArgs {
+    #[command(subcommand)]
+    pub command: Option<StashCommands>,
+
+    /// Without a subcommand, the changes are pushed
+    #[command(flatten)]
+    pub push: StashPushArgs,
+}
+
+#[derive(Subcommand)]
+pub enum StashCommands {
+    Push(StashPushArgs),
+}
+
+#[derive(Args)]
+pub struct StashPushArgs {
+    /// Leave what's staged in the index and worktree
+    #[arg(short, long, overrides_with = "no_keep_index")]
+    pub keep_index: bool,
+
+    /// Reset the index along with the worktree, the default
+    #[arg(long, overrides_with = "keep_index")]
+    pub no_keep_index: bool,
+
+    /// Stash untracked files too, and remove them
+    #[arg(short = 'u', long)]
+    pub include_untracked: bool,
+
+    /// Describe the stash with this, rather than HEAD's subject
+    #[arg(short, long)]
+    pub message: Option<String>,
+
+    /// Only stash changes to these paths
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct CloneArgs {
+    /// An http://, git://, ssh:// or "<host>:<path>" URL, or the path of a
+    /// local repository. Only plain http is spoken, not https.
+    pub repository: String,
+
+    /// Where to clone to, by default the repository's name less any ".git"
+    pub directory: Option<PathBuf>,
+
+    /// Check out this branch rather than the one the remote's HEAD points to
+    #[arg(short, long)]
+    pub branch: Option<String>,
+
+    /// Don't check out HEAD after cloning
+    #[arg(short, long)]
+    pub no_checkout: bool,
+}
+
+#[derive(Args)]
+pub struct RestoreArgs {
+    /// Take the files from this commit or tree, by default the index, or
+    /// HEAD with --staged
+    #[arg(short, long)]
+    pub source: Option<String>,
+
+    /// Restore the index
+    #[arg(short = 'S', long)]
+    pub staged: bool,
+
+    /// Restore the worktree, the default unless --staged is given
+    #[arg(short = 'W', long)]
+    pub worktree: bool,
+
+    /// The paths to restore
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct LsRemoteArgs {
+    /// Only list branches
+    #[arg(long)]
+    pub heads: bool,
+
+    /// Only list tags
+    #[arg(short, long)]
+    pub tags: bool,
+
+    /// Leave out peeled tags and HEAD
+    #[arg(long)]
+    pub refs: bool,
+
+    /// Show what HEAD points to, as "ref: <target>"
+    #[arg(long)]
+    pub symref: bool,
+
+    /// Exit with status 2 if no refs are listed
+    #[arg(long)]
+    pub exit_code: bool,
+
+    /// A remote's name or a URL, by default the current branch's remote
+    /// or "origin"
+    pub repository: Option<String>,
+
+    /// Only list refs whose names end with one of these, after a slash,
+    /// which may use glob syntax
+    pub patterns: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct PushArgs {
+    /// Update refs even if it isn't a fast-forward, as if every refspec
+    /// had a '+'
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Show what would be pushed without pushing anything
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+
+    /// Sign the push with a certificate of the updates asked for, which
+    /// the remote keeps for its hooks to check
+    #[arg(long)]
+    pub signed: bool,
+
+    /// A remote's name or a URL, by default the current branch's remote
+    /// or "origin"
+    pub repository: Option<String>,
+
+    /// What to push and where, eg. "main", "HEAD:refs/heads/topic" or
+    /// ":old" to delete "old". The remote's configured push refspecs if
+    /// none are given, else the current branch to the branch of the same
+    /// name.
+    pub refspecs: Vec<String>,
 }
@@ -0,0 +1,173 @@
+use std::fs::{self, Metadata};
+use std::path::Path;
+
+use gitty::config::Config;
+use gitty::ignore::IgnoreRules;
+use gitty::status::index_mtime;
+use gitty::store::index::{Index, IndexEntry};
+use gitty::store::tree::MODE_GITLINK;
+use gitty::store::GitObjectStore;
+use gitty::worktree::{self, in_pathspec, normalize_path};
+use crate::cli::AddArgs;
+use crate::context::Context;
+
+pub fn run(AddArgs { force, paths }: AddArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let mut index = Index::read().ok_or("Unable to read index")?;
+    let mut rules = IgnoreRules::load(&Config::load());
+
+    let pathspecs = paths.iter()
+        .map(|path| normalize_path(path).ok_or_else(|| format!("'{path}' is outside repository")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Check everything up front, so a typo doesn't leave a partial update
+    for (path, pathspec) in paths.iter().zip(&pathspecs) {
+        let exists = fs::symlink_metadata(pathspec_file(pathspec)).is_ok();
+        let tracked = index.entries.iter().any(|entry| in_pathspec(&entry.path, pathspec));
+
+        if !exists && !tracked {
+            return Err(format!("pathspec '{path}' did not match any files").into());
+        }
+    }
+
+    // Entries modified at or after the index was written may have changed
+    // without their stat data changing, so are always re-hashed
+    let index_mtime = index_mtime();
+
+    let mut ignored = Vec::new();
+
+    for pathspec in &pathspecs {
+        // Tracked files are updated, or removed if they've been deleted,
+        // whether or not they're ignored
+        let tracked: Vec<IndexEntry> = index.entries.iter()
+            .filter(|entry| in_pathspec(&entry.path, pathspec))
+            .cloned()
+            .collect();
+
+        for entry in &tracked {
+            match fs::symlink_metadata(worktree::os_path(&entry.path)) {
+                // A submodule's commit is recorded by the submodule
+                Ok(metadata) if entry.mode == MODE_GITLINK && metadata.is_dir() => {}
+                Ok(metadata) if !metadata.is_dir() => {
+                    let racy = index_mtime.is_none_or(|index_mtime| {
+                        (entry.mtime.secs as i64, entry.mtime.nsecs as i64) >= index_mtime
+                    });
+
+                    if racy || entry.stage != 0 || !worktree::stat_matches(entry, &metadata) {
+                        add_file(&mut index, &entry.path, &metadata, ctx)?;
+                    }
+                }
+                _ => {
+                    if index.remove(&entry.path) && ctx.verbose {
+                        println!("remove '{}'", String::from_utf8_lossy(&entry.path));
+                    }
+                }
+            }
+        }
+
+        let Ok(metadata) = fs::symlink_metadata(pathspec_file(pathspec)) else {
+            continue;
+        };
+
+        // A directory tracked as a submodule is left to it
+        if metadata.is_dir() && index.get(pathspec).is_some() {
+            continue;
+        }
+
+        if metadata.is_dir() {
+            let mut files = Vec::new();
+            untracked_files(pathspec, &index, &mut rules, force, &mut files);
+
+            for path in files {
+                let metadata = fs::symlink_metadata(&path)?;
+                add_file(&mut index, path.as_bytes(), &metadata, ctx)?;
+            }
+        } else if !tracked.iter().any(|entry| entry.path == pathspec.as_bytes()) {
+            if !force && rules.is_ignored(pathspec, false) {
+                ignored.push(pathspec.clone());
+                continue;
+            }
+
+            add_file(&mut index, pathspec.as_bytes(), &metadata, ctx)?;
+        }
+    }
+
+    index.write()?;
+
+    if !ignored.is_empty() {
+        eprintln!("The following paths are ignored by one of your .gitignore files:");
+
+        for path in &ignored {
+            eprintln!("{path}");
+        }
+
+        return Err("Use -f if you really want to add them.".into());
+    }
+
+    Ok(())
+}
+
+/// Stores the file's contents as a blob and stages it
+fn add_file(index: &mut Index, path: &[u8], metadata: &Metadata, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let data = worktree::read_file(worktree::os_path(path))
+        .ok_or_else(|| format!("unable to read '{}'", String::from_utf8_lossy(path)))?;
+    let id = GitObjectStore::write("blob", &data)
+        .ok_or_else(|| format!("unable to add '{}' to the database", String::from_utf8_lossy(path)))?;
+
+    let entry = worktree::index_entry(path, metadata, id);
+
+    let changed = index.get(path)
+        .is_none_or(|existing| existing.id != entry.id || existing.mode != entry.mode);
+
+    if changed && ctx.verbose {
+        println!("add '{}'", String::from_utf8_lossy(path));
+    }
+
+    index.add(entry);
+
+    Ok(())
+}
+
+/// Untracked, and unless `force` unignored, files inside `dir`
+fn untracked_files(dir: &str, index: &Index, rules: &mut IgnoreRules, force: bool, files: &mut Vec<String>) {
+    let Ok(contents) = fs::read_dir(pathspec_file(dir)) else {
+        return;
+    };
+
+    for entry in contents.flatten() {
+        let Ok(filename) = entry.file_name().into_string() else {
+            eprintln!("Skipping non UTF-8 path in {}", pathspec_file(dir).display());
+            continue;
+        };
+
+        if filename == ".git" {
+            continue;
+        }
+
+        let path = if dir.is_empty() { filename } else { format!("{dir}/{filename}") };
+
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if !force && rules.is_ignored(&path, file_type.is_dir()) {
+            continue;
+        }
+
+        if file_type.is_dir() {
+            // A submodule's files are its own
+            if index.get(&path).is_none() {
+                untracked_files(&path, index, rules, force, files);
+            }
+        } else if index.get(&path).is_none() {
+            files.push(path);
+        }
+    }
+}
+
+fn pathspec_file(pathspec: &str) -> &Path {
+    if pathspec.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(pathspec)
+    }
+}
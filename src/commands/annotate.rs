@@ -0,0 +1,344 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use gitty::blame::{self, Blame, Found, Origin};
+use gitty::config::Config;
+use gitty::date::{self, DateFormat, DateMode};
+use gitty::diff::split_lines;
+use gitty::mailmap::Mailmap;
+use gitty::quote::{quote_non_ascii, quote_path};
+use gitty::refs;
+use gitty::revparse::{self, peel_to_commit};
+use gitty::store::object::Identity;
+use gitty::store::{GitObjectData, GitObjectStore, ObjectId};
+use gitty::worktree::{self, normalize_path};
+use crate::cli::AnnotateArgs;
+use crate::context::Context;
+
+/// What's shown about the commit a line is blamed on
+pub struct CommitInfo {
+    pub author: Identity,
+    pub committer: Identity,
+    pub summary: String,
+}
+
+impl CommitInfo {
+    /// The details of the commit `origin` is in, with its author and
+    /// committer as `mailmap` maps them
+    fn load(origin: &Origin, mailmap: &Mailmap) -> Result<CommitInfo, Box<dyn std::error::Error>> {
+        let id = origin.commit;
+
+        // Lines from the working tree are blamed on a commit yet to be made
+        if id.is_null() {
+            let now = date::now();
+
+            let ident = Identity {
+                name: "Not Committed Yet".to_string(),
+                email: "not.committed.yet".to_string(),
+                timestamp: now,
+                tz_offset: date::local_offset(now),
+            };
+
+            return Ok(CommitInfo {
+                author: ident.clone(),
+                committer: ident,
+                summary: format!("Version of {0} from {0}", origin.path),
+            });
+        }
+
+        let object = GitObjectStore::get(id).ok_or_else(|| format!("could not read commit {id}"))?;
+
+        let GitObjectData::Commit { author, committer, message, .. } = object.data else {
+            return Err(format!("{id} is not a commit").into());
+        };
+
+        let parse = |ident: &str| {
+            Identity::parse(&mailmap.map_ident(ident)).ok_or_else(|| format!("malformed identity in commit {id}"))
+        };
+
+        // Only the first line of the message, unlike log's subject
+        let summary = split_lines(&message).into_iter()
+            .map(|line| String::from_utf8_lossy(line).trim_end_matches('\n').to_string())
+            .find(|line| !line.is_empty())
+            .unwrap_or_else(|| format!("({id})"));
+
+        Ok(CommitInfo {
+            author: parse(&author)?,
+            committer: parse(&committer)?,
+            summary,
+        })
+    }
+}
+
+pub fn run(AnnotateArgs { porcelain, line_porcelain, incremental, no_mailmap, args }: AnnotateArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let Target { commit, path, working } = target(&args, "annotate")?;
+    let mailmap = load_mailmap(no_mailmap);
+
+    if incremental {
+        return run_incremental(commit, &path, working, &mailmap);
+    }
+
+    let blame = blame::blame(commit, &path, working).ok_or_else(|| format!("could not blame {path}"))?;
+    let infos = load_infos(&blame, &mailmap)?;
+
+    let mut stdout = io::stdout().lock();
+
+    match porcelain || line_porcelain {
+        true => write_porcelain(&mut stdout, &blame, &infos, line_porcelain)?,
+        false => write_annotate(&mut stdout, &blame, &infos)?,
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// What `[<rev>] <file>` asks to be blamed
+pub struct Target {
+    pub commit: ObjectId,
+    pub path: String,
+
+    /// Without a revision, the working tree's version of the file, which
+    /// is blamed on top of HEAD
+    pub working: Option<Vec<u8>>,
+}
+
+/// Finds what `command` was asked to blame
+pub fn target(args: &[String], command: &str) -> Result<Target, Box<dyn std::error::Error>> {
+    let (rev, file) = match args {
+        [file] => (None, file),
+        [rev, file] => (Some(rev), file),
+        _ => return Err(format!("usage: gitty {command} [<rev>] <file>").into()),
+    };
+
+    let path = normalize_path(file).ok_or_else(|| format!("'{file}' is outside repository"))?;
+
+    match rev {
+        Some(rev) => {
+            let commit = revparse::resolve(rev)
+                .and_then(peel_to_commit)
+                .ok_or_else(|| format!("bad revision '{rev}'"))?;
+
+            Ok(Target { commit, path, working: None })
+        }
+        None => {
+            let head = refs::resolve_ref("HEAD").ok_or("no such ref: HEAD")?;
+            let contents = worktree::read_file(&path).ok_or_else(|| format!("cannot stat path '{path}'"))?;
+
+            Ok(Target { commit: head, path, working: Some(contents) })
+        }
+    }
+}
+
+/// The mailmap authors are shown through, an empty one with `no_mailmap`
+pub fn load_mailmap(no_mailmap: bool) -> Mailmap {
+    match no_mailmap {
+        true => Mailmap::default(),
+        false => Mailmap::load(&Config::load()),
+    }
+}
+
+/// Blames the file, writing each run of lines as soon as it's blamed
+pub fn run_incremental(commit: ObjectId, path: &str, working: Option<Vec<u8>>, mailmap: &Mailmap)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let quote_non_ascii = quote_non_ascii(&Config::load());
+    let mut infos = HashMap::new();
+    let mut shown = HashSet::new();
+    let mut error = None;
+    let mut stdout = io::stdout().lock();
+
+    blame::blame_incremental(commit, path, working, |found| {
+        if error.is_none() {
+            error = write_incremental(&mut stdout, found, &mut infos, &mut shown, mailmap, quote_non_ascii).err();
+        }
+    })
+    .ok_or_else(|| format!("could not blame {path}"))?;
+
+    if let Some(err) = error {
+        return Err(err);
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// The details of every commit lines are blamed on
+pub fn load_infos(blame: &Blame, mailmap: &Mailmap) -> Result<HashMap<ObjectId, CommitInfo>, Box<dyn std::error::Error>> {
+    let mut infos = HashMap::new();
+
+    for entry in &blame.entries {
+        if let Entry::Vacant(vacant) = infos.entry(entry.origin.commit) {
+            vacant.insert(CommitInfo::load(&entry.origin, mailmap)?);
+        }
+    }
+
+    Ok(infos)
+}
+
+/// Writes a run of lines as soon as it's blamed:
+///
+///   <id> ' ' <origin-line> ' ' <final-line> ' ' <count>
+///   <details, the first time the commit is shown>
+///   [ "previous " <id> ' ' <path> ]
+///   "filename " <path>
+///
+/// Runs are found newest commit first, not in the order of the file.
+fn write_incremental<W: Write>(
+    out: &mut W,
+    found: Found,
+    infos: &mut HashMap<ObjectId, CommitInfo>,
+    shown: &mut HashSet<ObjectId>,
+    mailmap: &Mailmap,
+    quote_non_ascii: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Found { entry, previous, boundary } = found;
+    let origin = &entry.origin;
+
+    writeln!(out, "{} {} {} {}", origin.commit, entry.s_lno + 1, entry.lno + 1, entry.num_lines)?;
+
+    if shown.insert(origin.commit) {
+        if let Entry::Vacant(vacant) = infos.entry(origin.commit) {
+            vacant.insert(CommitInfo::load(origin, mailmap)?);
+        }
+
+        write_details(out, &infos[&origin.commit], boundary)?;
+    }
+
+    write_filename(out, origin, previous, quote_non_ascii)?;
+
+    Ok(())
+}
+
+/// Writes each line as
+///
+///   <id> '\t(' <author> '\t' <date> '\t' <line-number> ')' <line>
+///
+/// with the id abbreviated to 8 digits, and the author and date padded
+/// to 10 characters.
+fn write_annotate<W: Write>(out: &mut W, blame: &Blame, infos: &HashMap<ObjectId, CommitInfo>) -> io::Result<()> {
+    let lines = split_lines(&blame.contents);
+    let mode = DateMode { format: DateFormat::Iso, local: false };
+    let now = date::now();
+
+    for entry in &blame.entries {
+        let info = &infos[&entry.origin.commit];
+        let date = date::format_date(info.author.timestamp, info.author.tz_offset, mode, now);
+
+        for (lno, line) in lines.iter().enumerate().skip(entry.lno).take(entry.num_lines) {
+            write!(out, "{}\t({:>10}\t{date:>10}\t{})", &entry.origin.commit.to_string()[..8], info.author.name, lno + 1)?;
+            write_line(out, line)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes blame in git's porcelain format, each group of lines blamed on
+/// the same commit starting
+///
+///   <id> ' ' <origin-line> ' ' <final-line> ' ' <group-size>
+///
+/// and each line after the first just
+///
+///   <id> ' ' <origin-line> ' ' <final-line>
+///
+/// Lines are numbered from 1 and each is followed by itself, prefixed by
+/// a tab. A commit's details (author, committer, summary, "boundary" for
+/// a root commit, "previous" with the version it was compared against,
+/// and "filename") follow its first line, or with `line_porcelain`, every
+/// line. A commit blamed for lines at more than one path repeats
+/// "previous" and "filename" at the start of each group.
+pub fn write_porcelain<W: Write>(
+    out: &mut W,
+    blame: &Blame,
+    infos: &HashMap<ObjectId, CommitInfo>,
+    line_porcelain: bool,
+) -> io::Result<()> {
+    let quote_non_ascii = quote_non_ascii(&Config::load());
+    let lines = split_lines(&blame.contents);
+
+    // A commit the file had several paths in names its path every time
+    let mut paths: HashMap<ObjectId, HashSet<&str>> = HashMap::new();
+
+    for entry in &blame.entries {
+        paths.entry(entry.origin.commit).or_default().insert(&entry.origin.path);
+    }
+
+    let mut shown = HashSet::new();
+
+    for entry in &blame.entries {
+        let origin = &entry.origin;
+
+        for i in 0..entry.num_lines {
+            write!(out, "{} {} {}", origin.commit, entry.s_lno + i + 1, entry.lno + i + 1)?;
+
+            match i {
+                0 => writeln!(out, " {}", entry.num_lines)?,
+                _ => writeln!(out)?,
+            }
+
+            let details = line_porcelain || (i == 0 && shown.insert(origin.commit));
+
+            if details {
+                write_details(out, &infos[&origin.commit], blame.boundaries.contains(&origin.commit))?;
+            }
+
+            if details || (i == 0 && paths[&origin.commit].len() > 1) {
+                write_filename(out, origin, blame.previous.get(origin), quote_non_ascii)?;
+            }
+
+            write!(out, "\t")?;
+            write_line(out, lines[entry.lno + i])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The author, committer and summary of a commit, then "boundary" if
+/// history stopped there
+fn write_details<W: Write>(out: &mut W, info: &CommitInfo, boundary: bool) -> io::Result<()> {
+    write_ident(out, "author", &info.author)?;
+    write_ident(out, "committer", &info.committer)?;
+    writeln!(out, "summary {}", info.summary)?;
+
+    if boundary {
+        writeln!(out, "boundary")?;
+    }
+
+    Ok(())
+}
+
+/// The path of the file at an origin, after "previous" with the version
+/// it was compared against
+fn write_filename<W: Write>(out: &mut W, origin: &Origin, previous: Option<&Origin>, quote_non_ascii: bool) -> io::Result<()> {
+    if let Some(previous) = previous {
+        writeln!(out, "previous {} {}", previous.commit, quote_path(previous.path.as_bytes(), quote_non_ascii))?;
+    }
+
+    writeln!(out, "filename {}", quote_path(origin.path.as_bytes(), quote_non_ascii))
+}
+
+fn write_ident<W: Write>(out: &mut W, role: &str, ident: &Identity) -> io::Result<()> {
+    writeln!(out, "{role} {}", ident.name)?;
+    writeln!(out, "{role}-mail <{}>", ident.email)?;
+    writeln!(out, "{role}-time {}", ident.timestamp)?;
+    writeln!(out, "{role}-tz {}", date::format_offset(ident.tz_offset))
+}
+
+/// Writes a line of the file, ending it with a newline if it's the last
+/// and lacks one
+pub fn write_line<W: Write>(out: &mut W, line: &[u8]) -> io::Result<()> {
+    out.write_all(line)?;
+
+    if !line.ends_with(b"\n") {
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use gitty::archive::{write_archive, ArchiveFormat, ArchiveOptions, ExtraFile};
+use gitty::attributes::Attributes;
+use gitty::config::Config;
+use gitty::date;
+use gitty::graph::ident_time;
+use gitty::pathspec::Pathspec;
+use gitty::revparse::{self, peel_to_commit, peel_to_tree};
+use gitty::store::tree::{flatten_tree, MODE_BLOB, MODE_EXECUTABLE};
+use gitty::store::{GitObjectData, GitObjectStore};
+use crate::cli::ArchiveArgs;
+use crate::context::Context;
+
+pub fn run(ArchiveArgs { format, prefix, output, list, worktree_attributes, add_files, mtime, tree_ish, paths }: ArchiveArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    if list {
+        for format in ArchiveFormat::ALL {
+            println!("{format}");
+        }
+
+        return Ok(());
+    }
+
+    let config = Config::load();
+    let tree_ish = tree_ish.unwrap_or_default();
+
+    let format = match format {
+        Some(format) => ArchiveFormat::parse(&format).ok_or_else(|| format!("Unknown archive format '{format}'"))?,
+        None => output.as_ref()
+            .and_then(|output| ArchiveFormat::from_file_name(&output.to_string_lossy()))
+            .unwrap_or(ArchiveFormat::Tar),
+    };
+
+    let object = revparse::resolve(&tree_ish).ok_or_else(|| revparse::missing_path(&tree_ish).unwrap_or_else(|| format!("not a valid object name: {tree_ish}")))?;
+    let tree = peel_to_tree(object).ok_or_else(|| format!("not a tree object: {tree_ish}"))?;
+
+    // Only a commit has a time to give the entries, a bare tree gets now,
+    // unless there's a time asked for
+    let commit = peel_to_commit(object);
+
+    let mtime = match (mtime, commit.and_then(GitObjectStore::get).map(|object| object.data)) {
+        (Some(mtime), _) => date::parse_date(&mtime).map(|(time, _)| time)
+            .or_else(|| date::approxidate(&mtime, date::now()))
+            .ok_or_else(|| format!("invalid --mtime: '{mtime}'"))?,
+        (None, Some(GitObjectData::Commit { committer, .. })) => ident_time(&committer).unwrap_or(0) as i64,
+        (None, _) => date::now(),
+    };
+
+    let extra_files = add_files.iter().map(|path| extra_file(path)).collect::<Result<Vec<_>, _>>()?;
+
+    // As an octal mask, which is all of tar.umask there is to follow
+    let tar_umask = config.get("tar.umask")
+        .and_then(|umask| u32::from_str_radix(umask, 8).ok())
+        .unwrap_or(0o002);
+
+    // Checked before anything's written, as the archive is streamed
+    let files = flatten_tree(tree).ok_or_else(|| format!("unable to read tree ({tree})"))?;
+
+    for path in &paths {
+        let pathspec = Pathspec::parse(std::slice::from_ref(path)).ok_or("invalid pathspec")?;
+
+        if !files.keys().any(|file| pathspec.matches(file)) {
+            return Err(format!("pathspec '{path}' did not match any files").into());
+        }
+    }
+
+    let options = ArchiveOptions {
+        format,
+        prefix,
+        pathspec: Pathspec::parse(&paths).ok_or("invalid pathspec")?,
+        commit,
+        mtime,
+        extra_files,
+        tar_umask,
+    };
+
+    // export-ignore and export-subst, as the tree archived has them
+    let mut attributes = match worktree_attributes {
+        true => Attributes::load(&config),
+        false => Attributes::from_tree(&config, tree).ok_or_else(|| format!("unable to read tree ({tree})"))?,
+    };
+
+    let result = match output {
+        Some(output) => {
+            let file = File::create(&output)
+                .map_err(|err| format!("could not create archive file '{}': {err}", output.display()))?;
+
+            write_archive(BufWriter::new(file), tree, &options, &mut attributes)
+        }
+        None => write_archive(BufWriter::new(io::stdout().lock()), tree, &options, &mut attributes),
+    };
+
+    Ok(result.map_err(|err| err.to_string())?)
+}
+
+/// A file to add to the archive, by its name alone, as executable if
+/// anyone can execute it
+fn extra_file(path: &Path) -> Result<ExtraFile, String> {
+    let unreadable = |err: io::Error| format!("unable to read '{}': {err}", path.display());
+
+    let metadata = fs::metadata(path).map_err(unreadable)?;
+
+    if !metadata.is_file() {
+        return Err(format!("'{}' isn't a regular file", path.display()));
+    }
+
+    let name = path.file_name().ok_or_else(|| format!("'{}' has no file name", path.display()))?;
+
+    Ok(ExtraFile {
+        path: name.to_string_lossy().into_owned(),
+        mode: match metadata.permissions().mode() & 0o111 {
+            0 => MODE_BLOB,
+            _ => MODE_EXECUTABLE,
+        },
+        data: fs::read(path).map_err(unreadable)?,
+    })
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use gitty::blame::{self, Blame};
+use gitty::date::{self, DateFormat, DateMode};
+use gitty::diff::split_lines;
+use gitty::store::ObjectId;
+use crate::cli::BlameArgs;
+use crate::commands::annotate::{load_infos, load_mailmap, run_incremental, target, write_line, write_porcelain, CommitInfo, Target};
+use crate::context::Context;
+
+/// How each line is shown, from blame's options
+struct BlameFormat {
+    long: bool,
+    blank_boundary: bool,
+    show_name: bool,
+    show_number: bool,
+    show_email: bool,
+    show_author: bool,
+    raw_time: bool,
+}
+
+pub fn run(args: BlameArgs, _ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let BlameArgs {
+        porcelain,
+        line_porcelain,
+        incremental,
+        long,
+        blank_boundary,
+        root,
+        show_name,
+        show_number,
+        show_email,
+        suppress,
+        raw_time,
+        no_mailmap,
+        args,
+    } = args;
+
+    let Target { commit, path, working } = target(&args, "blame")?;
+    let mailmap = load_mailmap(no_mailmap);
+
+    if incremental {
+        return run_incremental(commit, &path, working, &mailmap);
+    }
+
+    let mut blame = blame::blame(commit, &path, working).ok_or_else(|| format!("could not blame {path}"))?;
+
+    // Root commits are only boundaries as history stops there
+    if root {
+        blame.boundaries.clear();
+    }
+
+    let infos = load_infos(&blame, &mailmap)?;
+
+    let mut stdout = io::stdout().lock();
+
+    if porcelain || line_porcelain {
+        write_porcelain(&mut stdout, &blame, &infos, line_porcelain)?;
+    } else {
+        let format = BlameFormat {
+            long,
+            blank_boundary,
+            // Names are shown anyway when lines come from a file's old name
+            show_name: show_name || blame.entries.iter().any(|entry| entry.origin.path != path),
+            show_number,
+            show_email,
+            show_author: !suppress,
+            raw_time,
+        };
+
+        write_blame(&mut stdout, &blame, &infos, &format)?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// Writes each line as
+///
+///   [ '^' ] <id> [ ' ' <path> ] [ ' ' <origin-line> ] [ ' (' <author> ' ' <date> ] ' ' <line-number> ') ' <line>
+///
+/// with the id abbreviated to 8 digits, one fewer after the '^' marking
+/// the boundary commits history stopped at. Paths, authors and line
+/// numbers are padded to the widest of them.
+fn write_blame<W: Write>(out: &mut W, blame: &Blame, infos: &HashMap<ObjectId, CommitInfo>, format: &BlameFormat) -> io::Result<()> {
+    let lines = split_lines(&blame.contents);
+    let mode = DateMode { format: DateFormat::Iso, local: false };
+    let now = date::now();
+
+    let author = |info: &CommitInfo| match format.show_email {
+        true => format!("<{}>", info.author.email),
+        false => info.author.name.clone(),
+    };
+
+    let longest_path = blame.entries.iter().map(|entry| entry.origin.path.chars().count()).max().unwrap_or(0);
+    let longest_author = infos.values().map(|info| author(info).chars().count()).max().unwrap_or(0);
+    let max_digits = lines.len().to_string().len();
+
+    let max_orig_digits = blame.entries.iter()
+        .map(|entry| (entry.s_lno + entry.num_lines).to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    for entry in &blame.entries {
+        let origin = &entry.origin;
+        let info = &infos[&origin.commit];
+        let boundary = blame.boundaries.contains(&origin.commit);
+
+        let hex = origin.commit.to_string();
+        let length = if format.long { hex.len() } else { 8 };
+
+        let id = match boundary {
+            true if format.blank_boundary => " ".repeat(length),
+            true => format!("^{}", &hex[..length - 1]),
+            false => hex[..length].to_string(),
+        };
+
+        let time = match format.raw_time {
+            true => format!("{} {}", info.author.timestamp, date::format_offset(info.author.tz_offset)),
+            false => date::format_date(info.author.timestamp, info.author.tz_offset, mode, now),
+        };
+
+        for i in 0..entry.num_lines {
+            write!(out, "{id}")?;
+
+            if format.show_name {
+                write!(out, " {:<longest_path$}", origin.path)?;
+            }
+
+            if format.show_number {
+                write!(out, " {:>max_orig_digits$}", entry.s_lno + i + 1)?;
+            }
+
+            if format.show_author {
+                write!(out, " ({:<longest_author$} {time:>10}", author(info))?;
+            }
+
+            write!(out, " {:>max_digits$}) ", entry.lno + i + 1)?;
+            write_line(out, lines[entry.lno + i])?;
+        }
+    }
+
+    Ok(())
+}
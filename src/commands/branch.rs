@@ -0,0 +1,232 @@
+use std::io::Write;
+
+use gitty::config::{self, Config};
+use gitty::graph::Graph;
+use gitty::ident::reflog_ident;
+use gitty::refs::{self, RefTransaction};
+use gitty::ref_filter::PatternMode;
+use gitty::reflog::{self, DetachedFrom, ReflogEntry};
+use gitty::remote::Remote;
+use gitty::repo::git_path;
+use gitty::revparse::{self, peel_to_commit};
+use gitty::store::ObjectId;
+use crate::cli::{BranchArgs, RefFilterArgs};
+use crate::context::Context;
+use crate::commands::for_each_ref::ref_filter;
+
+pub fn run(BranchArgs { list, remotes, all, delete, force_delete, force, filter, names }: BranchArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    // Like git, filtering options imply listing
+    let filtering = !filter.contains.is_empty()
+        || !filter.no_contains.is_empty()
+        || !filter.merged.is_empty()
+        || !filter.no_merged.is_empty();
+
+    if delete || force_delete {
+        delete_branches(&names, force || force_delete, ctx)
+    } else if list || remotes || all || filtering || names.is_empty() {
+        list_branches(remotes, all, filter, names)
+    } else {
+        match &names[..] {
+            [name, ..] if force && refs::head_branch() == Some(format!("refs/heads/{name}")) => {
+                Err(format!("cannot force update the branch '{name}' used by HEAD").into())
+            }
+            [name] => create_branch(name, "HEAD", force, ctx),
+            [name, start] => create_branch(name, start, force, ctx),
+            _ => Err("too many arguments for a create operation".into()),
+        }
+    }
+}
+
+fn list_branches(remotes: bool, all: bool, filter: RefFilterArgs, patterns: Vec<String>)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let filter = ref_filter(filter, patterns, PatternMode::ShortName)?;
+
+    let mut candidates = Vec::new();
+
+    if !remotes || all {
+        candidates.extend(refs::list_refs("refs/heads/"));
+    }
+
+    if remotes || all {
+        candidates.extend(refs::list_refs("refs/remotes/"));
+    }
+
+    let head_branch = refs::head_branch();
+
+    let mut stdout = std::io::stdout().lock();
+
+    if head_branch.is_none() && !remotes {
+        let head = refs::resolve_ref("HEAD").map(|head| ("HEAD".to_string(), head));
+
+        for item in filter.filter(head.into_iter().collect()) {
+            match reflog::detached_from(item.id) {
+                Some(DetachedFrom { name, at: true }) => writeln!(stdout, "* (HEAD detached at {name})")?,
+                Some(DetachedFrom { name, at: false }) => writeln!(stdout, "* (HEAD detached from {name})")?,
+                None => writeln!(stdout, "* (no branch)")?,
+            }
+        }
+    }
+
+    for item in filter.filter(candidates) {
+        let mark = if head_branch.as_deref() == Some(item.name.as_str()) { '*' } else { ' ' };
+
+        // With --all, remote-tracking branches are qualified to tell them apart
+        let name = match item.name.strip_prefix("refs/") {
+            Some(name) if all && name.starts_with("remotes/") => name,
+            _ => refs::shorten_ref(&item.name),
+        };
+
+        writeln!(stdout, "{mark} {name}")?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// The full ref name for a new branch called `name`, if it's a valid name
+pub fn branch_refname(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let refname = format!("refs/heads/{name}");
+
+    if name == "HEAD" || name.starts_with('-') || !refs::check_ref_format(&refname) {
+        return Err(format!("'{name}' is not a valid branch name").into());
+    }
+
+    Ok(refname)
+}
+
+/// Creates the branch `name` at `start`, or with `force` resets it there if
+/// it exists. A branch started from a remote-tracking branch tracks it,
+/// unless branch.autoSetupMerge is false.
+pub fn create_branch(name: &str, start: &str, force: bool, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let refname = branch_refname(name)?;
+
+    let current = refs::resolve_ref(&refname);
+
+    if current.is_some() && !force {
+        return Err(format!("a branch named '{name}' already exists").into());
+    }
+
+    let commit = revparse::resolve(start)
+        .and_then(peel_to_commit)
+        .ok_or_else(|| format!("not a valid object name: '{start}'"))?;
+
+    let config = Config::load();
+
+    // Looked up first, so a missing identity doesn't leave the branch
+    // created without its reflog entry
+    let entry = if reflog::should_log(&config, &refname) {
+        Some(ReflogEntry {
+            old: current.unwrap_or(ObjectId::NULL),
+            new: commit,
+            committer: reflog_ident(&config).to_string(),
+            message: match current {
+                Some(_) => format!("branch: Reset to {start}"),
+                None => format!("branch: Created from {start}"),
+            },
+        })
+    } else {
+        None
+    };
+
+    let mut transaction = RefTransaction::new();
+    transaction.update(&refname, commit, Some(current.unwrap_or(ObjectId::NULL))).reflog = entry;
+    transaction.commit()?;
+
+    if let Some((remote, branch)) = upstream_of(&config, start) {
+        let config_path = git_path("config");
+
+        config::set_value(&config_path, &format!("branch.{name}.remote"), Some(&remote))?;
+        config::set_value(&config_path, &format!("branch.{name}.merge"), Some(&format!("refs/heads/{branch}")))?;
+
+        if !ctx.quiet {
+            println!("branch '{name}' set up to track '{remote}/{branch}'.");
+        }
+    }
+
+    Ok(())
+}
+
+/// The remote and branch a new branch started at `start` tracks, if
+/// `start` is a remote-tracking branch of a configured remote
+fn upstream_of(config: &Config, start: &str) -> Option<(String, String)> {
+    if config.get_bool("branch.autosetupmerge") == Some(false) {
+        return None;
+    }
+
+    let full = refs::expand_ref(start)?;
+    let (remote, branch) = full.strip_prefix("refs/remotes/")?.split_once('/')?;
+
+    if branch == "HEAD" || Remote::get(config, remote).is_none() {
+        return None;
+    }
+
+    Some((remote.to_string(), branch.to_string()))
+}
+
+/// Deletes each branch, carrying on past any which can't be deleted.
+///
+/// Unless `force`, a branch must be merged into HEAD so no commits are lost.
+fn delete_branches(names: &[String], force: bool, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    if names.is_empty() {
+        return Err("branch name required".into());
+    }
+
+    let head_branch = refs::head_branch();
+    let head = refs::resolve_ref("HEAD").and_then(peel_to_commit);
+
+    let mut graph = Graph::new();
+    let mut failed = 0;
+
+    for name in names {
+        let result = delete_branch(name, head_branch.as_deref(), head, force, &mut graph);
+
+        match result {
+            Ok(id) => {
+                if !ctx.quiet {
+                    println!("Deleted branch {name} (was {}).", &id.to_string()[..7]);
+                }
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("unable to delete {failed} branch(es)").into());
+    }
+
+    Ok(())
+}
+
+fn delete_branch(name: &str, head_branch: Option<&str>, head: Option<ObjectId>, force: bool, graph: &mut Graph)
+    -> Result<ObjectId, Box<dyn std::error::Error>>
+{
+    let refname = format!("refs/heads/{name}");
+
+    if head_branch == Some(refname.as_str()) {
+        let worktree = std::env::current_dir()?;
+        return Err(format!("Cannot delete branch '{name}' checked out at '{}'", worktree.display()).into());
+    }
+
+    let id = refs::resolve_ref(&refname).ok_or_else(|| format!("branch '{name}' not found"))?;
+
+    let merged = match (peel_to_commit(id), head) {
+        (Some(commit), Some(head)) => graph.is_ancestor(commit, head),
+        _ => false,
+    };
+
+    if !force && !merged {
+        return Err(format!("The branch '{name}' is not fully merged.\n\
+            If you are sure you want to delete it, run 'gitty branch -D {name}'").into());
+    }
+
+    refs::delete_ref(&refname, Some(id))?;
+
+    Ok(id)
+}
@@ -0,0 +1,69 @@
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::quote::{quote_non_ascii, quote_path};
+use gitty::store::{GitObjectData, GitObjectStore, ObjectId};
+use gitty::store::util::resolve_id;
+use crate::cli::CatFileArgs;
+use crate::context::Context;
+
+pub fn run(CatFileArgs { mode, id }: CatFileArgs, _ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    // A full id is checked for as it is, only abbreviations are searched for
+    if mode.exists {
+        let id = match ObjectId::try_from(id.clone()) {
+            Ok(id) => id,
+            Err(_) => resolve_id(&id).ok_or("Invalid Object Id")?,
+        };
+
+        if !GitObjectStore::exists(id) {
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    let id = resolve_id(&id).ok_or("Invalid Object Id")?;
+
+    // Blobs can be huge, so they're streamed rather than read whole
+    if let Some(mut blob) = GitObjectStore::open_blob(id) {
+        if mode.print {
+            let mut stdout = std::io::stdout().lock();
+            std::io::copy(&mut blob, &mut stdout)?;
+            stdout.flush()?;
+        }
+
+        if mode.kind {
+            println!("blob");
+        }
+
+        return Ok(());
+    }
+
+    let obj = match GitObjectStore::get(id) {
+        Some(obj) => obj,
+        None => return Err("Unable to retrive object".into())
+    };
+
+    let mut stdout = std::io::stdout();
+
+    if mode.print {
+        match &obj.data {
+            GitObjectData::Tree { entries } => {
+                let quote_non_ascii = quote_non_ascii(&Config::load());
+
+                for entry in entries {
+                    println!("{:06o} {} {} {}", entry.mode, entry.kind, entry.id, quote_path(&entry.path, quote_non_ascii));
+                }
+            }
+            _ => print!("{}", obj),
+        }
+    }
+
+    if mode.kind {
+        println!("{}", obj.type_str());
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
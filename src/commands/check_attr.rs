@@ -0,0 +1,61 @@
+use std::io::Write;
+
+use gitty::attributes::Attributes;
+use gitty::config::Config;
+use gitty::quote::{quote_non_ascii, quote_path};
+use gitty::store::index::Index;
+use gitty::worktree::normalize_path;
+use crate::cli::CheckAttrArgs;
+use crate::context::Context;
+
+pub fn run(CheckAttrArgs { all, cached, args, paths }: CheckAttrArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    // Without "--", the first argument is the attribute and the rest paths
+    let (names, paths) = match (all, paths.is_empty()) {
+        (true, true) => (Vec::new(), args),
+        (true, false) if !args.is_empty() => return Err("Attributes and --all both specified".into()),
+        (true, false) => (Vec::new(), paths),
+        (false, false) => (args, paths),
+        (false, true) => match args.split_first() {
+            Some((name, paths)) => (vec![name.clone()], paths.to_vec()),
+            None => return Err("No attribute specified".into()),
+        },
+    };
+
+    if paths.is_empty() {
+        return Err("No file specified".into());
+    }
+
+    let config = Config::load();
+    let quote_non_ascii = quote_non_ascii(&config);
+
+    let mut attributes = match cached {
+        true => Attributes::from_index(&config, &Index::read().ok_or("Unable to read index")?),
+        false => Attributes::load(&config),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+
+    for path in &paths {
+        let normalized = normalize_path(path).ok_or_else(|| format!("'{path}' is outside repository"))?;
+        let attrs = attributes.attributes_for(&normalized);
+        let quoted = quote_path(path.as_bytes(), quote_non_ascii);
+
+        match all {
+            true => {
+                for (name, value) in attrs.iter() {
+                    writeln!(stdout, "{quoted}: {name}: {value}")?;
+                }
+            }
+            false => {
+                for name in &names {
+                    writeln!(stdout, "{quoted}: {name}: {}", attrs.get(name))?;
+                }
+            }
+        }
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
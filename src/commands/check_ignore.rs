@@ -0,0 +1,64 @@
+use std::io::Write;
+use std::path::Path;
+
+use gitty::config::Config;
+use gitty::ignore::IgnoreRules;
+use gitty::quote::{quote_non_ascii, quote_path};
+use gitty::store::index::Index;
+use gitty::worktree::normalize_path;
+use crate::cli::CheckIgnoreArgs;
+use crate::context::Context;
+
+pub fn run(CheckIgnoreArgs { verbose, non_matching, no_index, paths }: CheckIgnoreArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let config = Config::load();
+    let quote_non_ascii = quote_non_ascii(&config);
+    let mut rules = IgnoreRules::load(&config);
+
+    let index = match no_index {
+        true => None,
+        false => Some(Index::read().ok_or("Unable to read index")?),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    let mut matched = 0;
+
+    for path in &paths {
+        let normalized = normalize_path(path).ok_or_else(|| format!("'{path}' is outside repository"))?;
+        let is_dir = path.ends_with('/') || Path::new(&normalized).is_dir();
+
+        // Tracked files are never ignored
+        let tracked = index.as_ref().is_some_and(|index| index.get(&normalized).is_some());
+
+        let pattern = match tracked {
+            true => None,
+            false => rules.matching(&normalized, is_dir),
+        };
+
+        // Paths a negated pattern re-includes aren't ignored, but verbose
+        // output shows the pattern anyway
+        let pattern = pattern.filter(|pattern| verbose || !pattern.is_negated());
+        let quoted = quote_path(path.as_bytes(), quote_non_ascii);
+
+        match pattern {
+            Some(pattern) if verbose => writeln!(stdout, "{pattern}\t{quoted}")?,
+            Some(_) => writeln!(stdout, "{quoted}")?,
+            None if non_matching => writeln!(stdout, "::\t{quoted}")?,
+            None => {},
+        }
+
+        if pattern.is_some() {
+            matched += 1;
+        }
+    }
+
+    stdout.flush()?;
+
+    // Like grep, the exit status says whether anything matched
+    if matched == 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use std::io::{BufRead, Write};
+
+use gitty::config::Config;
+use gitty::mailmap::Mailmap;
+use crate::cli::CheckMailmapArgs;
+use crate::context::Context;
+
+/// Prints the canonical form of each contact, as
+///
+///   [ <name> ' ' ] '<' <email> '>'
+///
+/// which is the contact itself if the mailmap doesn't map it
+pub fn run(CheckMailmapArgs { stdin, mailmap_file, mailmap_blob, contacts }: CheckMailmapArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut mailmap = Mailmap::load(&Config::load());
+
+    if let Some(blob) = mailmap_blob {
+        mailmap.read_blob(&blob);
+    }
+
+    if let Some(file) = mailmap_file {
+        mailmap.read_file(file);
+    }
+
+    let mut stdout = std::io::stdout().lock();
+
+    for contact in &contacts {
+        writeln!(stdout, "{}", check(&mailmap, contact)?)?;
+    }
+
+    if stdin {
+        // Flushed as each is answered, for scripts taking turns with us
+        for line in std::io::stdin().lock().lines() {
+            writeln!(stdout, "{}", check(&mailmap, &line?)?)?;
+            stdout.flush()?;
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn check(mailmap: &Mailmap, contact: &str) -> Result<String, String> {
+    let parse = || {
+        let (name, rest) = contact.split_once('<')?;
+        let (email, rest) = rest.split_once('>')?;
+
+        rest.trim().is_empty().then_some((name.trim(), email.trim()))
+    };
+
+    let (name, email) = parse().ok_or_else(|| format!("unable to parse contact: {contact}"))?;
+    let (name, email) = mailmap.canonicalize(name, email);
+
+    Ok(match name.is_empty() {
+        true => format!("<{email}>"),
+        false => format!("{name} <{email}>"),
+    })
+}
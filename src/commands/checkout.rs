@@ -0,0 +1,97 @@
+use gitty::checkout::{write_worktree_file, TreeFiles};
+use gitty::refs;
+use gitty::revparse::{self, peel_to_tree};
+use gitty::store::index::Index;
+use gitty::store::tree::flatten_tree;
+use gitty::worktree::{self, in_pathspec, normalize_path};
+use crate::cli::CheckoutArgs;
+use crate::commands::switch::{switch_to, SwitchTarget};
+use crate::context::Context;
+
+pub fn run(CheckoutArgs { create, force, rev, paths }: CheckoutArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    if !paths.is_empty() {
+        return restore_paths(rev.as_deref(), &paths, ctx);
+    }
+
+    let target = match (create, rev) {
+        (Some(name), start) => SwitchTarget::NewBranch {
+            name,
+            start: start.unwrap_or_else(|| "HEAD".to_string()),
+            reset: false,
+        },
+        // Unlike switch, anything which isn't a branch detaches HEAD
+        (None, Some(rev)) if refs::read_ref(&format!("refs/heads/{rev}")).is_some() => SwitchTarget::Branch(rev),
+        (None, Some(rev)) => SwitchTarget::Detached(rev),
+        (None, None) => return Err("nothing to check out, give a branch or commit".into()),
+    };
+
+    switch_to(target, force, ctx)
+}
+
+/// Overwrites the paths in the worktree with their contents in `rev`, also
+/// staging them, or with their staged contents if there's no `rev`
+fn restore_paths(rev: Option<&str>, paths: &[String], ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let mut index = Index::read().ok_or("Unable to read index")?;
+
+    let (source, source_name): (TreeFiles, String) = match rev {
+        Some(rev) => {
+            let id = revparse::resolve(rev).ok_or_else(|| format!("invalid reference: {rev}"))?;
+            let files = peel_to_tree(id)
+                .and_then(flatten_tree)
+                .ok_or_else(|| format!("reference is not a tree: {rev}"))?;
+
+            (files, id.to_string()[..7].to_string())
+        }
+        None => {
+            let files = index.entries.iter()
+                .filter(|entry| entry.stage == 0)
+                .map(|entry| (entry.path.clone(), (entry.mode, entry.id)))
+                .collect();
+
+            (files, "the index".to_string())
+        }
+    };
+
+    let mut selected = Vec::new();
+
+    for path in paths {
+        let pathspec = normalize_path(path).ok_or_else(|| format!("'{path}' is outside repository"))?;
+
+        let matches: Vec<_> = source.iter()
+            .filter(|(path, _)| in_pathspec(path, &pathspec))
+            .collect();
+
+        if matches.is_empty() {
+            return Err(format!("pathspec '{path}' did not match any file(s) known to git").into());
+        }
+
+        selected.extend(matches);
+    }
+
+    selected.sort();
+    selected.dedup();
+
+    for (path, &(mode, id)) in &selected {
+        write_worktree_file(path, mode, id)?;
+
+        let metadata = std::fs::symlink_metadata(worktree::os_path(path))?;
+
+        let mut entry = worktree::index_entry(path, &metadata, id);
+        entry.mode = mode;
+
+        index.add(entry);
+    }
+
+    index.write()?;
+
+    if !ctx.quiet {
+        let count = selected.len();
+        let paths = if count == 1 { "path" } else { "paths" };
+
+        eprintln!("Updated {count} {paths} from {source_name}");
+    }
+
+    Ok(())
+}
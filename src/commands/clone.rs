@@ -0,0 +1,301 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gitty::checkout::{checkout_tree, TreeFiles};
+use gitty::config::{self, Config};
+use gitty::ident::reflog_ident;
+use gitty::init::{init, DEFAULT_BRANCH};
+use gitty::protocol::fetch::{is_url, Connection, FetchOptions, ProtocolVersion};
+use gitty::protocol::local::local_git_dir;
+use gitty::refs::{self, Head, RefTransaction};
+use gitty::reflog::{self, ReflogEntry};
+use gitty::remote::{add_remote, rewrite_url};
+use gitty::repo::git_path;
+use gitty::revparse::peel_to_tree;
+use gitty::store::tree::flatten_tree;
+use gitty::store::ObjectId;
+use crate::cli::CloneArgs;
+use crate::context::Context;
+
+const REMOTE: &str = "origin";
+
+/// What the remote has that a clone takes
+struct RemoteRefs {
+    /// Its branches and tags, by full name
+    refs: Vec<(String, ObjectId)>,
+
+    /// The branch its HEAD points to, if it's on one
+    head_target: Option<String>,
+
+    /// Where its HEAD is, if it's anywhere
+    head: Option<ObjectId>,
+}
+
+pub fn run(CloneArgs { repository, directory, branch, no_checkout }: CloneArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let url = rewrite_url(&Config::load(), &repository);
+    let local = local_git_dir(&url);
+
+    if local.is_none() && !is_url(&url) {
+        return Err(format!("repository '{repository}' does not exist").into());
+    }
+
+    let directory = directory.unwrap_or_else(|| PathBuf::from(default_directory(&url)));
+
+    if fs::read_dir(&directory).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(format!("destination path '{}' already exists and is not an empty directory.", directory.display()).into());
+    }
+
+    // The remote's URL is remembered absolute, so it works from anywhere
+    let url = match &local {
+        Some(_) => fs::canonicalize(url.strip_prefix("file://").unwrap_or(&url))?.display().to_string(),
+        None => url,
+    };
+
+    if !ctx.quiet {
+        eprintln!("Cloning into '{}'...", directory.display());
+    }
+
+    let created = !directory.exists();
+    let previous = env::current_dir()?;
+
+    let cloned = (|| {
+        init(&directory, false, DEFAULT_BRANCH)?;
+        env::set_current_dir(&directory).map_err(|err| err.to_string())?;
+
+        clone(&url, local.as_deref(), branch.as_deref(), no_checkout, ctx)
+    })();
+
+    // Nothing is left of a failed clone, other than a directory that was
+    // there already
+    if cloned.is_err() {
+        let _ = env::set_current_dir(&previous);
+
+        let _ = match created {
+            true => fs::remove_dir_all(&directory),
+            false => fs::remove_dir_all(directory.join(".git")),
+        };
+    }
+
+    cloned
+}
+
+/// Everything after the repository's initialized, from inside it
+fn clone(url: &str, local: Option<&Path>, branch: Option<&str>, no_checkout: bool, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let config_path = git_path("config");
+
+    add_remote(REMOTE, url)?;
+
+    let remote = match local {
+        Some(git_dir) => copy_local(git_dir)?,
+        None => fetch_remote(url, ctx)?,
+    };
+
+    if local.is_some() && !ctx.quiet {
+        eprintln!("done.");
+    }
+
+    let config = Config::load();
+    let message = format!("clone: from {url}");
+
+    let committer = reflog_ident(&config).to_string();
+
+    let mut transaction = RefTransaction::new();
+
+    for (name, id) in &remote.refs {
+        let name = match name.strip_prefix("refs/heads/") {
+            Some(branch) => format!("refs/remotes/{REMOTE}/{branch}"),
+            None => name.clone(),
+        };
+
+        let update = transaction.update(&name, *id, Some(ObjectId::NULL));
+
+        if reflog::should_log(&config, &name) {
+            update.reflog = Some(ReflogEntry { old: ObjectId::NULL, new: *id, committer: committer.clone(), message: message.clone() });
+        }
+    }
+
+    transaction.commit().map_err(|err| err.to_string())?;
+
+    let head = remote_head(&remote, branch)?;
+
+    let Some(head) = head else {
+        eprintln!("warning: You appear to have cloned an empty repository.");
+        return Ok(());
+    };
+
+    let mut transaction = RefTransaction::new();
+
+    let (name, id) = match &head {
+        Head::Branch(name) => {
+            let branch = refs::shorten_ref(name);
+            let id = refs::resolve_ref(&format!("refs/remotes/{REMOTE}/{branch}")).ok_or("remote branch vanished")?;
+
+            refs::write_symbolic_ref(&format!("refs/remotes/{REMOTE}/HEAD"), &format!("refs/remotes/{REMOTE}/{branch}"))?;
+            refs::write_symbolic_ref("HEAD", name)?;
+
+            config::set_value(&config_path, &format!("branch.{branch}.remote"), Some(REMOTE))?;
+            config::set_value(&config_path, &format!("branch.{branch}.merge"), Some(name))?;
+
+            (name.as_str(), id)
+        }
+        Head::Detached(id) => ("HEAD", *id),
+    };
+
+    // HEAD's reflog is added to along with the branch's
+    let update = transaction.update(name, id, Some(ObjectId::NULL));
+
+    if reflog::should_log(&config, name) {
+        update.reflog = Some(ReflogEntry { old: ObjectId::NULL, new: id, committer, message });
+    }
+
+    transaction.commit().map_err(|err| err.to_string())?;
+
+    if no_checkout {
+        return Ok(());
+    }
+
+    let files = peel_to_tree(id).and_then(flatten_tree).ok_or("unable to read HEAD's tree")?;
+
+    checkout_tree(&TreeFiles::new(), &files, true).ok_or("unable to checkout working tree")?;
+
+    Ok(())
+}
+
+/// Where to check out: the branch asked for, else whatever the remote's
+/// HEAD is on. None for an empty repository.
+fn remote_head(remote: &RemoteRefs, branch: Option<&str>) -> Result<Option<Head>, Box<dyn std::error::Error>> {
+    let has_branch = |name: &str| remote.refs.iter().any(|(remote_name, _)| remote_name == name);
+
+    if let Some(branch) = branch {
+        let name = format!("refs/heads/{branch}");
+
+        if !has_branch(&name) {
+            return Err(format!("Remote branch {branch} not found in upstream {REMOTE}").into());
+        }
+
+        return Ok(Some(Head::Branch(name)));
+    }
+
+    if let Some(target) = remote.head_target.as_deref().filter(|target| has_branch(target)) {
+        return Ok(Some(Head::Branch(target.to_string())));
+    }
+
+    // Without a symref the branch HEAD is on is guessed from where it
+    // points, the default branch's name being the best guess
+    let Some(head) = remote.head else {
+        return Ok(None);
+    };
+
+    let guess = remote.refs.iter()
+        .filter(|(name, id)| *id == head && name.starts_with("refs/heads/"))
+        .min_by_key(|(name, _)| refs::shorten_ref(name) != DEFAULT_BRANCH)
+        .map(|(name, _)| Head::Branch(name.clone()));
+
+    Ok(Some(guess.unwrap_or(Head::Detached(head))))
+}
+
+/// Fetches every branch and tag of the repository at `url`
+fn fetch_remote(url: &str, ctx: &Context) -> Result<RemoteRefs, Box<dyn std::error::Error>> {
+    let version = ProtocolVersion::from_config(&Config::load()).ok_or("bad protocol.version")?;
+    let connection = Connection::connect(url, version).map_err(|err| err.to_string())?;
+
+    let prefixes = ["HEAD", "refs/heads/", "refs/tags/"].map(str::to_string);
+
+    let mut refs: Vec<(String, ObjectId)> = connection.list_refs(&prefixes)
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .filter(|(name, _)| !name.ends_with("^{}"))
+        .collect();
+
+    let head = refs.iter().position(|(name, _)| name == "HEAD").map(|i| refs.remove(i).1);
+    let head_target = connection.head_target().map_err(|err| err.to_string())?;
+
+    let mut wants: Vec<ObjectId> = Vec::new();
+
+    for &(_, id) in &refs {
+        if !wants.contains(&id) {
+            wants.push(id);
+        }
+    }
+
+    if !wants.is_empty() {
+        let options = FetchOptions { progress: ctx.progress, ..FetchOptions::default() };
+        connection.fetch_pack(&wants, &options).map_err(|err| err.to_string())?;
+    }
+
+    Ok(RemoteRefs { refs, head_target, head })
+}
+
+/// Takes every object of the local repository at `git_dir`, hardlinked
+/// where possible, and reads its branches and tags
+fn copy_local(git_dir: &Path) -> Result<RemoteRefs, Box<dyn std::error::Error>> {
+    link_dir(&git_dir.join("objects"), &git_path("objects")).map_err(|err| err.to_string())?;
+
+    let (refs, head) = in_repository(git_dir, || {
+        let mut refs = refs::list_refs("refs/heads/");
+        refs.extend(refs::list_refs("refs/tags/"));
+
+        (refs, refs::read_head())
+    });
+
+    let (head_target, head) = match head {
+        Some(Head::Branch(name)) => {
+            let id = refs.iter().find(|(ref_name, _)| *ref_name == name).map(|&(_, id)| id);
+            (Some(name), id)
+        }
+        Some(Head::Detached(id)) => (None, Some(id)),
+        None => (None, None),
+    };
+
+    Ok(RemoteRefs { refs, head_target, head })
+}
+
+/// Runs `f` with the repository at `git_dir` as the current one
+fn in_repository<T>(git_dir: &Path, f: impl FnOnce() -> T) -> T {
+    let previous = env::var_os("GIT_DIR");
+    env::set_var("GIT_DIR", git_dir);
+
+    let result = f();
+
+    match previous {
+        Some(previous) => env::set_var("GIT_DIR", previous),
+        None => env::remove_var("GIT_DIR"),
+    }
+
+    result
+}
+
+/// Hardlinks the files under `src` into `dst`, copying them if they can't
+/// be linked, eg. across filesystems
+fn link_dir(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let dst = dst.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            link_dir(&entry.path(), &dst)?;
+        } else if fs::hard_link(entry.path(), &dst).is_err() {
+            fs::copy(entry.path(), &dst)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The directory a clone of `url` goes in by default, its last component
+/// less any ".git", eg. "repo" for "https://host/owner/repo.git"
+fn default_directory(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix("/.git").unwrap_or(url);
+    let name = url.rsplit(['/', ':']).next().unwrap_or(url);
+
+    name.strip_suffix(".git").unwrap_or(name).to_string()
+}
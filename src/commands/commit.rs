@@ -0,0 +1,290 @@
+use std::fs;
+use std::path::PathBuf;
+
+use gitty::autosquash::{squash_message, SquashKind};
+use gitty::config::Config;
+use gitty::date::format_offset;
+use gitty::ident::{ident, ident_with, IdentRole};
+use gitty::editor::launch_editor;
+use gitty::message::{comment_char_for, comment_lines, stripspace};
+use gitty::quote::quote_non_ascii;
+use gitty::refs::{self, RefTransaction};
+use gitty::reflog::ReflogEntry;
+use gitty::repo::git_path;
+use gitty::status::{status, UntrackedFiles};
+use gitty::store::index::Index;
+use gitty::revparse::{self, peel_to_commit, peel_to_tree};
+use gitty::store::object::{serialize_commit, Identity};
+use gitty::store::{GitObjectData, GitObjectStore, ObjectId};
+use crate::cli::CommitArgs;
+use crate::commands::commit_tree::read_message;
+use crate::commands::status::print_long;
+use crate::context::Context;
+
+pub fn run(
+    CommitArgs { messages, file, edit, no_edit, template, allow_empty, author, date, amend, reset_author, fixup, squash }: CommitArgs,
+    ctx: &Context,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load();
+
+    let head_branch = refs::head_branch();
+    let head = refs::resolve_ref("HEAD");
+
+    // The parents, author and message of the commit being replaced
+    let amended = match amend {
+        true => {
+            let head = head.ok_or("You have nothing to amend.")?;
+
+            match GitObjectStore::get(head).map(|object| object.data) {
+                Some(GitObjectData::Commit { parents, author, message, .. }) => Some((parents, author, message)),
+                _ => return Err(format!("could not read commit {head}").into()),
+            }
+        }
+        false => None,
+    };
+
+    // A merge that stopped for conflicts to be resolved is concluded by
+    // committing, with the commits merged as further parents
+    let merge_heads = match amend {
+        true => Vec::new(),
+        false => read_merge_heads()?,
+    };
+
+    // A reword only replaces the message, so changes nothing else
+    let reword = fixup.as_deref().is_some_and(|fixup| fixup.starts_with("reword:"));
+
+    let squash_into = match (fixup.as_deref(), squash.as_deref()) {
+        (Some(fixup), _) => match fixup.split_once(':') {
+            Some(("amend" | "reword", rev)) => Some((SquashKind::Amend, rev)),
+            _ => Some((SquashKind::Fixup, fixup)),
+        },
+        (None, Some(rev)) => Some((SquashKind::Squash, rev)),
+        (None, None) => None,
+    };
+
+    let given = if !messages.is_empty() {
+        Some(messages.join("\n\n").into_bytes())
+    } else if file.is_some() {
+        Some(read_message(file.as_deref())?)
+    } else {
+        None
+    };
+
+    let message = match (given, squash_into) {
+        (given, Some((kind, rev))) => {
+            let target = revparse::resolve(rev)
+                .and_then(peel_to_commit)
+                .ok_or_else(|| format!("could not lookup commit '{rev}'"))?;
+
+            let mut message = squash_message(kind, &commit_message(target)?);
+
+            // A fixup's message is complete, an amend's is to be edited into
+            // the replacement, and a squash's combined with what's given
+            let edit = match kind {
+                SquashKind::Fixup => edit,
+                SquashKind::Amend => !no_edit,
+                SquashKind::Squash => edit || (given.is_none() && !no_edit),
+            };
+
+            message.extend(given.unwrap_or_default());
+
+            match edit {
+                true => edit_message(Some(message), None, &config)?,
+                false => stripspace(&message, None),
+            }
+        }
+        (Some(message), None) if !edit => stripspace(&message, None),
+        (Some(message), None) => edit_message(Some(message), None, &config)?,
+        (None, None) => {
+            // Amending starts from HEAD's message, and after a merge or
+            // squash merge the user starts from its message
+            let start = match &amended {
+                Some((_, _, message)) => Some(message.clone()),
+                None if !merge_heads.is_empty() && git_path("MERGE_MSG").is_file() => Some(fs::read(git_path("MERGE_MSG"))?),
+                None if git_path("SQUASH_MSG").is_file() => Some(fs::read(git_path("SQUASH_MSG"))?),
+                None => None,
+            };
+
+            match (start, no_edit) {
+                (Some(start), true) => stripspace(&start, None),
+                (Some(start), false) => edit_message(Some(start), None, &config)?,
+                (None, true) => Vec::new(),
+                (None, false) => edit_message(None, template.or_else(|| config.get_path("commit.template")), &config)?,
+            }
+        }
+    };
+
+    if message.is_empty() {
+        return Err("Aborting commit due to empty commit message.".into());
+    }
+
+    let tree = match reword {
+        true => head.and_then(peel_to_tree).ok_or("You have nothing to reword.")?,
+        false => {
+            let index = Index::read().ok_or("Unable to read index")?;
+            index.write_tree(false).ok_or("Unable to write tree")?
+        }
+    };
+
+    let parents: Vec<ObjectId> = match &amended {
+        Some((parents, _, _)) => parents.clone(),
+        None => head.into_iter().chain(merge_heads.iter().copied()).collect(),
+    };
+
+    // Merges are made to be the same as one of their parents, so that's
+    // never taken to be empty
+    if !allow_empty && !reword && parents.len() <= 1 {
+        let parent_tree = parents.first().copied().and_then(peel_to_tree);
+
+        if parent_tree == Some(tree) {
+            return Err(match amend {
+                true => "amending the commit would make it empty, use --allow-empty to amend anyway",
+                false => "nothing to commit, use --allow-empty to commit anyway",
+            }.into());
+        }
+    }
+
+    let author = match &amended {
+        Some((_, original, _)) if !reset_author => amended_author(original, &config, author.as_deref(), date.as_deref())?,
+        _ => ident_with(IdentRole::Author, &config, author.as_deref(), date.as_deref()).ok_or("unable to determine the author")?,
+    };
+
+    let committer = ident(IdentRole::Committer, &config).ok_or("unable to determine the committer")?;
+
+    let data = serialize_commit(&tree, &parents, &author.to_string(), &committer.to_string(), None, None, &message);
+    let commit = GitObjectStore::write("commit", &data).ok_or("unable to write commit")?;
+
+    let subject = String::from_utf8_lossy(&message)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    // A detached HEAD is updated itself, otherwise the branch it's on,
+    // which is logged in HEAD's reflog too
+    let target = head_branch.as_deref().unwrap_or("HEAD");
+
+    let mut transaction = RefTransaction::new();
+
+    transaction.update(target, commit, Some(head.unwrap_or(ObjectId::NULL))).reflog = Some(ReflogEntry {
+        old: head.unwrap_or(ObjectId::NULL),
+        new: commit,
+        committer: committer.to_string(),
+        message: match (amend, head) {
+            (true, _) => format!("commit (amend): {subject}"),
+            (false, Some(_)) if !merge_heads.is_empty() => format!("commit (merge): {subject}"),
+            (false, Some(_)) => format!("commit: {subject}"),
+            (false, None) => format!("commit (initial): {subject}"),
+        },
+    });
+
+    transaction.commit()?;
+
+    // The merge or squash merge, if any, has been committed
+    for file in ["SQUASH_MSG", "MERGE_HEAD", "MERGE_MSG", "MERGE_MODE"] {
+        let _ = fs::remove_file(git_path(file));
+    }
+
+    if !ctx.quiet {
+        let branch = match &head_branch {
+            Some(branch) => refs::shorten_ref(branch),
+            None => "detached HEAD",
+        };
+
+        let root = if parents.is_empty() { " (root-commit)" } else { "" };
+
+        println!("[{branch}{root} {}] {subject}", &commit.to_string()[..7]);
+    }
+
+    Ok(())
+}
+
+/// The author of a commit replacing one by `original`: the same, with
+/// the name and email or date replaced if --author or --date are given
+fn amended_author(original: &str, config: &Config, author: Option<&str>, date: Option<&str>)
+    -> Result<Identity, Box<dyn std::error::Error>>
+{
+    let original = Identity::parse(original).ok_or("malformed author in the commit being amended")?;
+
+    let name_email = author.map_or_else(|| format!("{} <{}>", original.name, original.email), str::to_string);
+    let date = date.map_or_else(|| format!("@{} {}", original.timestamp, format_offset(original.tz_offset)), str::to_string);
+
+    ident_with(IdentRole::Author, config, Some(&name_email), Some(&date)).ok_or_else(|| "unable to determine the author".into())
+}
+
+/// The commits in MERGE_HEAD, one per line, if a merge is in progress
+fn read_merge_heads() -> Result<Vec<ObjectId>, Box<dyn std::error::Error>> {
+    let Ok(contents) = fs::read_to_string(git_path("MERGE_HEAD")) else {
+        return Ok(Vec::new());
+    };
+
+    contents.lines()
+        .map(|line| ObjectId::try_from(line.trim().to_string()).map_err(|_| format!("Corrupt MERGE_HEAD file ({line})").into()))
+        .collect()
+}
+
+fn commit_message(commit: ObjectId) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match GitObjectStore::get(commit).map(|object| object.data) {
+        Some(GitObjectData::Commit { message, .. }) => Ok(message),
+        _ => Err(format!("could not read commit {commit}").into()),
+    }
+}
+
+/// Has the user write the message in their editor, starting from `message`
+/// or the template, followed by commented out help and status.
+///
+/// Comment lines are stripped from the result. Leaving a template as it
+/// is aborts the commit, as that's almost certainly a mistake.
+fn edit_message(message: Option<Vec<u8>>, template: Option<PathBuf>, config: &Config)
+    -> Result<Vec<u8>, Box<dyn std::error::Error>>
+{
+    let template = match (&message, template) {
+        (None, Some(path)) => {
+            let contents = fs::read(&path)
+                .map_err(|err| format!("could not read commit message template '{}': {err}", path.display()))?;
+
+            Some(contents)
+        }
+        _ => None,
+    };
+
+    let initial = message.or_else(|| template.clone()).unwrap_or_default();
+
+    let comment = comment_char_for(config, &initial)
+        .ok_or("unable to select a comment character that is not used in the current commit message")?;
+
+    let mut buffer = initial;
+
+    if !buffer.is_empty() && !buffer.ends_with(b"\n") {
+        buffer.push(b'\n');
+    }
+
+    let mut help = format!(
+        "Please enter the commit message for your changes. Lines starting\n\
+         with '{comment}' will be ignored, and an empty message aborts the commit.\n"
+    ).into_bytes();
+
+    if let Some(status) = status(UntrackedFiles::from_config(config).unwrap_or_default(), None) {
+        help.push(b'\n');
+        print_long(&mut help, &status, quote_non_ascii(config))?;
+    }
+
+    buffer.push(b'\n');
+    buffer.extend(comment_lines(&help, &comment));
+
+    let path = git_path("COMMIT_EDITMSG");
+    fs::write(&path, &buffer)?;
+
+    launch_editor(&path, config)?;
+
+    let message = stripspace(&fs::read(&path)?, Some(&comment));
+
+    // An empty message gets its own error from the caller
+    let untouched = template.is_some_and(|template| stripspace(&template, Some(&comment)) == message);
+
+    if untouched && !message.is_empty() {
+        return Err("Aborting commit; you did not edit the message.".into());
+    }
+
+    Ok(message)
+}
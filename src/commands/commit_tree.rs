@@ -0,0 +1,70 @@
+use std::io::Read;
+use std::path::Path;
+
+use gitty::config::Config;
+use gitty::ident::{ident, ident_with, IdentRole};
+use gitty::revparse;
+use gitty::store::object::serialize_commit;
+use gitty::store::GitObjectStore;
+use crate::cli::CommitTreeArgs;
+use crate::context::Context;
+
+pub fn run(CommitTreeArgs { tree, parents, messages, file, author, date }: CommitTreeArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let tree = revparse::resolve(&tree)
+        .and_then(revparse::peel_to_tree)
+        .ok_or_else(|| format!("not a valid tree object: {tree}"))?;
+
+    let mut parent_ids = Vec::new();
+
+    for parent in parents {
+        let id = revparse::resolve(&parent)
+            .and_then(revparse::peel_to_commit)
+            .ok_or_else(|| format!("not a valid commit object: {parent}"))?;
+
+        if parent_ids.contains(&id) {
+            eprintln!("duplicate parent {id} ignored");
+            continue;
+        }
+
+        parent_ids.push(id);
+    }
+
+    let message = if !messages.is_empty() {
+        // Each -m is its own paragraph
+        messages.iter()
+            .map(|message| match message.ends_with('\n') {
+                true => message.clone(),
+                false => format!("{message}\n"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into_bytes()
+    } else {
+        read_message(file.as_deref())?
+    };
+
+    let config = Config::load();
+    let author = ident_with(IdentRole::Author, &config, author.as_deref(), date.as_deref()).ok_or("unable to determine the author")?;
+    let committer = ident(IdentRole::Committer, &config).ok_or("unable to determine the committer")?;
+
+    let data = serialize_commit(&tree, &parent_ids, &author.to_string(), &committer.to_string(), None, None, &message);
+    let commit = GitObjectStore::write("commit", &data).ok_or("unable to write commit")?;
+
+    println!("{commit}");
+
+    Ok(())
+}
+
+/// Reads the message from `file`, or stdin if it's "-" or not given
+pub fn read_message(file: Option<&Path>) -> std::io::Result<Vec<u8>> {
+    match file {
+        Some(path) if path != Path::new("-") => std::fs::read(path),
+        _ => {
+            let mut message = Vec::new();
+            std::io::stdin().read_to_end(&mut message)?;
+            Ok(message)
+        }
+    }
+}
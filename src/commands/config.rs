@@ -0,0 +1,66 @@
+use std::io::{self, Write};
+
+use gitty::config::{canonical_key, Config, ConfigEntry};
+use crate::cli::{ConfigAction, ConfigArgs};
+use crate::context::Context;
+
+pub fn run(ConfigArgs { action, file }: ConfigArgs, _ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let config = match &file {
+        Some(file) => Config::from_file(file).map_err(|err| match err.kind() {
+            io::ErrorKind::InvalidData => err.to_string(),
+            _ => format!("unable to read config file '{}'", file.display()),
+        })?,
+        None => Config::load(),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+
+    let ConfigAction { get, get_all, list } = action;
+
+    if list {
+        for ConfigEntry { key, value } in config.entries() {
+            match value {
+                Some(value) => writeln!(stdout, "{key}={value}")?,
+                None => writeln!(stdout, "{key}")?,
+            }
+        }
+
+        stdout.flush()?;
+
+        return Ok(());
+    }
+
+    let (name, all) = match (get, get_all) {
+        (Some(name), _) => (name, false),
+        (_, Some(name)) => (name, true),
+        _ => unreachable!("clap requires an action"),
+    };
+
+    if !name.contains('.') {
+        return Err(format!("key does not contain a section: {name}").into());
+    }
+
+    let key = canonical_key(&name);
+
+    let mut values: Vec<&str> = config.entries()
+        .filter(|entry| entry.key == key)
+        .map(|entry| entry.value.as_deref().unwrap_or_default())
+        .collect();
+
+    // A key which isn't set is a failure, without an error message
+    if values.is_empty() {
+        std::process::exit(1);
+    }
+
+    if !all {
+        values.drain(..values.len() - 1);
+    }
+
+    for value in values {
+        writeln!(stdout, "{value}")?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::fs;
+
+use gitty::repo::git_path;
+use gitty::store::pack_stream::{list_packs, pack_garbage};
+use gitty::store::util::{visit_loose_files, visit_pack_ids, LooseFileDesc};
+use crate::cli::CountObjectsArgs;
+use crate::context::Context;
+
+pub fn run(CountObjectsArgs { verbose, human_readable }: CountObjectsArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let size = |bytes: u64| match human_readable {
+        true => humanise_bytes(bytes),
+        false => (bytes / 1024).to_string(),
+    };
+
+    let mut loose = 0;
+    let mut loose_size = 0;
+    let mut loose_garbage = Vec::new();
+    let mut loose_ids = Vec::new();
+
+    visit_loose_files(None, true, |LooseFileDesc { oid, path, disk_size }| match oid {
+        Some(oid) => {
+            loose += 1;
+            loose_size += disk_size.unwrap_or(0);
+            loose_ids.push(oid);
+        }
+        None => loose_garbage.push((path, "garbage found")),
+    });
+
+    if !verbose {
+        match human_readable {
+            true => println!("{loose} objects, {}", humanise_bytes(loose_size)),
+            false => println!("{loose} objects, {} kilobytes", loose_size / 1024),
+        }
+
+        return Ok(());
+    }
+
+    let mut in_pack = 0;
+    let mut packed = HashSet::new();
+
+    // Objects in more than one pack are counted in each, as git does
+    visit_pack_ids(false, |desc| {
+        in_pack += 1;
+        packed.insert(desc.oid);
+    });
+
+    let packs = list_packs();
+    let mut pack_size = 0;
+
+    for pack in &packs {
+        for ext in ["pack", "idx"] {
+            pack_size += fs::metadata(git_path(format!("objects/pack/{pack}.{ext}"))).map_or(0, |metadata| metadata.len());
+        }
+    }
+
+    let mut garbage = pack_garbage();
+    garbage.extend(loose_garbage);
+
+    let mut garbage_size = 0;
+
+    for (path, reason) in &garbage {
+        eprintln!("warning: {reason}: {}", path.display());
+        garbage_size += fs::symlink_metadata(path).map_or(0, |metadata| metadata.len());
+    }
+
+    // Loose objects that a prune after packing would remove
+    let prune_packable = loose_ids.iter().filter(|id| packed.contains(id)).count();
+
+    println!("count: {loose}");
+    println!("size: {}", size(loose_size));
+    println!("in-pack: {in_pack}");
+    println!("packs: {}", packs.len());
+    println!("size-pack: {}", size(pack_size));
+    println!("prune-packable: {prune_packable}");
+    println!("garbage: {}", garbage.len());
+    println!("size-garbage: {}", size(garbage_size));
+
+    Ok(())
+}
+
+/// A size in the largest unit it's more than one of, with two decimal
+/// places, eg. "1.50 MiB", rounded the way git rounds it
+fn humanise_bytes(bytes: u64) -> String {
+    if bytes > 1 << 30 {
+        format!("{}.{:02} GiB", bytes >> 30, (bytes & ((1 << 30) - 1)) / 10737419)
+    } else if bytes > 1 << 20 {
+        let rounded = bytes + 5243;
+        format!("{}.{:02} MiB", rounded >> 20, ((rounded & ((1 << 20) - 1)) * 100) >> 20)
+    } else if bytes > 1 << 10 {
+        let rounded = bytes + 5;
+        format!("{}.{:02} KiB", rounded >> 10, ((rounded & ((1 << 10) - 1)) * 100) >> 10)
+    } else if bytes == 1 {
+        "1 byte".to_string()
+    } else {
+        format!("{bytes} bytes")
+    }
+}
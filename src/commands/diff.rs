@@ -0,0 +1,132 @@
+use std::collections::BTreeSet;
+use std::fs::{self, Metadata};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use gitty::config::Config;
+use gitty::diff::{write_file_patch, PatchFile};
+use gitty::quote::quote_non_ascii;
+use gitty::store::util::hash_object;
+use gitty::worktree::{file_mode, read_file};
+use crate::cli::DiffArgs;
+use crate::context::Context;
+
+pub fn run(DiffArgs { no_index: _, old, new }: DiffArgs, _ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load();
+    let quote_non_ascii = quote_non_ascii(&config);
+
+    let is_dir = |path: &str| Path::new(path).is_dir();
+
+    // A file is compared with the file of the same name in a directory
+    let (old, new) = match (is_dir(&old), is_dir(&new)) {
+        (false, true) => {
+            let path = join(&new, basename(&old));
+            (old, path)
+        }
+        (true, false) => {
+            let path = join(&old, basename(&new));
+            (path, new)
+        }
+        _ => (old, new),
+    };
+
+    for path in [&old, &new] {
+        if fs::symlink_metadata(path).is_err() {
+            return Err(format!("Could not access '{path}'").into());
+        }
+    }
+
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    let differs = diff_paths(&mut stdout, Some(&old), Some(&new), quote_non_ascii)?;
+    stdout.flush()?;
+
+    // As with --exit-code, which --no-index implies
+    if differs {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Writes the patches turning `old` into `new`, each a file, a directory
+/// or None if it's missing. Directories are compared file by file, in
+/// name order. Whether there's any difference.
+fn diff_paths(out: &mut impl Write, old: Option<&str>, new: Option<&str>, quote_non_ascii: bool) -> io::Result<bool> {
+    let old_metadata = old.map(fs::symlink_metadata).transpose()?;
+    let new_metadata = new.map(fs::symlink_metadata).transpose()?;
+
+    let is_dir = |metadata: &Option<Metadata>| metadata.as_ref().is_some_and(Metadata::is_dir);
+
+    // A directory replacing a file or the other way around is one deleted
+    // and the other created
+    if old.is_some() && new.is_some() && is_dir(&old_metadata) != is_dir(&new_metadata) {
+        let deleted = diff_paths(out, old, None, quote_non_ascii)?;
+        let created = diff_paths(out, None, new, quote_non_ascii)?;
+
+        return Ok(deleted || created);
+    }
+
+    if is_dir(&old_metadata) || is_dir(&new_metadata) {
+        let mut names = BTreeSet::new();
+
+        for dir in old.iter().chain(&new) {
+            for entry in fs::read_dir(dir)? {
+                names.insert(entry?.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        let mut differs = false;
+
+        for name in &names {
+            let old = old.map(|dir| join(dir, name)).filter(|path| fs::symlink_metadata(path).is_ok());
+            let new = new.map(|dir| join(dir, name)).filter(|path| fs::symlink_metadata(path).is_ok());
+
+            differs |= diff_paths(out, old.as_deref(), new.as_deref(), quote_non_ascii)?;
+        }
+
+        return Ok(differs);
+    }
+
+    let read = |path: Option<&str>, metadata: Option<Metadata>| -> io::Result<Option<(String, u32, Vec<u8>)>> {
+        let (Some(path), Some(metadata)) = (path, metadata) else {
+            return Ok(None);
+        };
+
+        let data = read_file(path).ok_or_else(|| io::Error::other(format!("Could not read '{path}'")))?;
+
+        // Shown after "a/" and "b/" even when absolute
+        Ok(Some((path.trim_start_matches('/').to_string(), file_mode(&metadata), data)))
+    };
+
+    let (old, new) = (read(old, old_metadata)?, read(new, new_metadata)?);
+
+    let old = old.as_ref().map(patch_file);
+    let new = new.as_ref().map(patch_file);
+
+    match (&old, &new) {
+        (Some(old), Some(new)) if old.mode == new.mode && old.id == new.id => Ok(false),
+        // A file becoming a symlink or the other way around
+        (Some(old_file), Some(new_file)) if old_file.mode & 0o170000 != new_file.mode & 0o170000 => {
+            write_file_patch(out, old.as_ref(), None, quote_non_ascii)?;
+            write_file_patch(out, None, new.as_ref(), quote_non_ascii)?;
+            Ok(true)
+        }
+        _ => {
+            write_file_patch(out, old.as_ref(), new.as_ref(), quote_non_ascii)?;
+            Ok(true)
+        }
+    }
+}
+
+fn patch_file((path, mode, data): &(String, u32, Vec<u8>)) -> PatchFile<'_> {
+    PatchFile { path, mode: *mode, id: hash_object("blob", data), data }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{name}", dir.trim_end_matches('/'))
+}
+
+fn basename(path: &str) -> &str {
+    let path = path.trim_end_matches('/');
+    path.rsplit('/').next().unwrap_or(path)
+}
@@ -0,0 +1,294 @@
+use std::fs;
+
+use gitty::config::Config;
+use gitty::graph::Graph;
+use gitty::ident::reflog_ident;
+use gitty::protocol::fetch::{is_url, Connection, FetchOptions, ProtocolVersion};
+use gitty::protocol::local::local_git_dir;
+use gitty::refs::{self, RefTransaction};
+use gitty::reflog::{self, ReflogEntry};
+use gitty::refspec::{RefMapping, RefSpecKind, RefSpecs};
+use gitty::remote::{rewrite_url, Remote};
+use gitty::repo::git_path;
+use gitty::store::ObjectId;
+use crate::cli::FetchArgs;
+use crate::context::Context;
+
+/// How wide the first column of the ref updates is, enough for two
+/// abbreviated ids and the "..." between them
+const SUMMARY_WIDTH: usize = 17;
+
+/// What became of a fetched ref
+struct Update {
+    /// The flag shown before the summary: '*' for something new, ' ' for
+    /// a fast-forward, '+' for a forced update, '!' for a rejection and
+    /// '=' for nothing to do
+    flag: char,
+    summary: String,
+    src: String,
+    dst: String,
+
+    /// Why the update was forced or rejected, after the ref names
+    note: Option<&'static str>,
+}
+
+pub fn run(FetchArgs { repository, refspecs }: FetchArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    fetch(&repository, &refspecs, ctx)?;
+
+    Ok(())
+}
+
+/// Fetches `refspecs` from `repository`, a remote's name or a URL, or
+/// without any the remote's configured refspecs, else its HEAD. Records
+/// what was fetched in FETCH_HEAD and updates the refs the refspecs have
+/// destinations for, giving what was fetched.
+pub fn fetch(repository: &str, refspecs: &[String], ctx: &Context) -> Result<Vec<RefMapping>, Box<dyn std::error::Error>> {
+    let config = Config::load();
+    let remote = Remote::get(&config, repository);
+
+    let url = match &remote {
+        Some(remote) => remote.urls[0].clone(),
+        None => rewrite_url(&config, repository),
+    };
+
+    if !is_url(&url) && local_git_dir(&url).is_none() {
+        return Err(format!("'{url}' does not appear to be a git repository").into());
+    }
+
+    // Without refspecs a remote's configured ones are used, else HEAD
+    let configured = refspecs.is_empty() && remote.is_some();
+
+    let specs = match &remote {
+        Some(remote) if configured => RefSpecs::for_remote(&config, &remote.name, RefSpecKind::Fetch),
+        _ => RefSpecs::parse(refspecs, RefSpecKind::Fetch),
+    };
+
+    let mut specs = specs.ok_or("invalid refspec")?;
+
+    if specs.iter().next().is_none() {
+        specs = RefSpecs::parse(&["HEAD"], RefSpecKind::Fetch).ok_or("invalid refspec")?;
+    }
+
+    let version = ProtocolVersion::from_config(&config).ok_or("bad protocol.version")?;
+    let connection = Connection::connect(&url, version).map_err(|err| err.to_string())?;
+
+    // Peeled tags are only there to save asking for them
+    let advertised: Vec<(String, ObjectId)> = connection.list_refs(&specs.ref_prefixes())
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .filter(|(name, _)| !name.ends_with("^{}"))
+        .collect();
+
+    let mappings = specs.map_refs(&advertised).ok_or("couldn't find what to fetch")?;
+
+    let mut wants: Vec<ObjectId> = Vec::new();
+
+    for mapping in &mappings {
+        if !wants.contains(&mapping.id) {
+            wants.push(mapping.id);
+        }
+    }
+
+    let options = FetchOptions { progress: ctx.progress, ..FetchOptions::default() };
+
+    connection.fetch_pack(&wants, &options).map_err(|err| err.to_string())?;
+
+    let display_url = display_url(&connection.url);
+
+    write_fetch_head(&config, &mappings, &display_url, configured.then_some(repository))?;
+
+    let message = format!("fetch {}", [repository].into_iter()
+        .chain(refspecs.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" "));
+
+    let updates = update_refs(&config, &mappings, &message)?;
+
+    if !ctx.quiet {
+        print_updates(&updates, &mappings, &display_url, ctx.verbose);
+    }
+
+    if updates.iter().any(|update| update.flag == '!') {
+        return Err("some local refs could not be updated".into());
+    }
+
+    Ok(mappings)
+}
+
+/// The URL as shown and recorded in FETCH_HEAD, less any trailing slashes
+/// and ".git"
+fn display_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url).to_string()
+}
+
+/// The kind of ref `name` is, as FETCH_HEAD describes it, and its name
+/// less the prefix that says so
+fn describe(name: &str) -> (&'static str, &str) {
+    if name == "HEAD" {
+        return ("", "");
+    }
+
+    [("refs/heads/", "branch"), ("refs/tags/", "tag"), ("refs/remotes/", "remote-tracking branch")].into_iter()
+        .find_map(|(prefix, kind)| name.strip_prefix(prefix).map(|short| (kind, short)))
+        .unwrap_or(("", name))
+}
+
+/// Records what was fetched in FETCH_HEAD, one line each:
+///
+///   <id> TAB [not-for-merge] TAB <description>
+///
+/// eg. "branch 'main' of <url>". Everything given on the command line is
+/// for merging, but of a remote's configured refspecs only the current
+/// branch's upstream is, if it's from `remote`. Those for merging come
+/// first.
+fn write_fetch_head(config: &Config, mappings: &[RefMapping], url: &str, remote: Option<&str>) -> std::io::Result<()> {
+    let upstream = refs::head_branch()
+        .map(|branch| refs::shorten_ref(&branch).to_string())
+        .filter(|branch| config.get(&format!("branch.{branch}.remote")) == remote)
+        .and_then(|branch| config.get(&format!("branch.{branch}.merge")).map(str::to_string));
+
+    let for_merge = |mapping: &RefMapping| remote.is_none() || upstream.as_deref() == Some(mapping.src.as_str());
+
+    let mut contents = String::new();
+
+    for merge in [true, false] {
+        for mapping in mappings.iter().filter(|mapping| for_merge(mapping) == merge) {
+            let description = match describe(&mapping.src) {
+                (_, "") => url.to_string(),
+                ("", name) => format!("'{name}' of {url}"),
+                (kind, name) => format!("{kind} '{name}' of {url}"),
+            };
+
+            let not_for_merge = if merge { "" } else { "not-for-merge" };
+
+            contents.push_str(&format!("{}\t{not_for_merge}\t{description}\n", mapping.id));
+        }
+    }
+
+    fs::write(git_path("FETCH_HEAD"), contents)
+}
+
+/// Updates the refs the mappings have destinations for, where that's
+/// allowed: a branch has to be fast-forwarded and a tag can't be moved,
+/// unless the refspec is forced.
+fn update_refs(config: &Config, mappings: &[RefMapping], message: &str) -> Result<Vec<Update>, Box<dyn std::error::Error>> {
+    let mut graph = Graph::new();
+    let mut transaction = RefTransaction::new();
+    let mut updates = Vec::new();
+
+    for mapping in mappings {
+        let (kind, short) = describe(&mapping.src);
+
+        let Some(dst) = &mapping.dst else {
+            updates.push(Update {
+                flag: '*',
+                summary: match kind {
+                    "" => "branch".to_string(),
+                    kind => kind.to_string(),
+                },
+                src: match short {
+                    "" => "HEAD".to_string(),
+                    short => short.to_string(),
+                },
+                dst: "FETCH_HEAD".to_string(),
+                note: None,
+            });
+
+            continue;
+        };
+
+        // A destination that isn't a full ref name is a branch
+        let dst = match dst.starts_with("refs/") {
+            true => dst.clone(),
+            false => format!("refs/heads/{dst}"),
+        };
+
+        let old = refs::resolve_ref(&dst);
+        let new = mapping.id;
+        let is_tag = dst.starts_with("refs/tags/");
+
+        let (flag, summary, note, reason) = match old {
+            Some(old) if old == new => ('=', "[up to date]".to_string(), None, None),
+            None => {
+                let (summary, reason) = match kind {
+                    "tag" => ("[new tag]", "storing tag"),
+                    "branch" => ("[new branch]", "storing head"),
+                    _ => ("[new ref]", "storing ref"),
+                };
+
+                ('*', summary.to_string(), None, Some(reason))
+            }
+            Some(_) if is_tag && !mapping.force => ('!', "[rejected]".to_string(), Some("would clobber existing tag"), None),
+            Some(old) if !is_tag && graph.is_ancestor(old, new) => {
+                (' ', format!("{}..{}", abbrev(old), abbrev(new)), None, Some("fast-forward"))
+            }
+            Some(old) if mapping.force => {
+                ('+', format!("{}...{}", abbrev(old), abbrev(new)), Some("forced update"), Some("forced-update"))
+            }
+            Some(_) => ('!', "[rejected]".to_string(), Some("non-fast-forward"), None),
+        };
+
+        if let Some(reason) = reason {
+            let update = transaction.update(&dst, new, Some(old.unwrap_or(ObjectId::NULL)));
+
+            if reflog::should_log(config, &dst) {
+                update.reflog = Some(ReflogEntry {
+                    old: old.unwrap_or(ObjectId::NULL),
+                    new,
+                    committer: reflog_ident(config).to_string(),
+                    message: format!("{message}: {reason}"),
+                });
+            }
+        }
+
+        updates.push(Update {
+            flag,
+            summary,
+            src: short.to_string(),
+            dst: refs::shorten_ref(&dst).to_string(),
+            note,
+        });
+    }
+
+    transaction.commit().map_err(|err| format!("some local refs could not be updated: {err}"))?;
+
+    Ok(updates)
+}
+
+/// Shows the updates as git does, eg.
+///
+///   From <url>
+///    * branch            main       -> FETCH_HEAD
+///    * [new branch]      topic      -> origin/topic
+///
+/// Refs already up to date are only shown when `verbose`.
+fn print_updates(updates: &[Update], mappings: &[RefMapping], url: &str, verbose: bool) {
+    let shown: Vec<&Update> = updates.iter()
+        .filter(|update| verbose || update.flag != '=')
+        .collect();
+
+    if shown.is_empty() {
+        return;
+    }
+
+    // Every fetched ref is counted, shown or not
+    let width = mappings.iter()
+        .map(|mapping| refs::shorten_ref(&mapping.src).chars().count())
+        .fold(10, usize::max);
+
+    eprintln!("From {url}");
+
+    for update in shown {
+        let note = update.note.map(|note| format!("  ({note})")).unwrap_or_default();
+
+        eprintln!(
+            " {} {:<SUMMARY_WIDTH$} {:<width$} -> {}{note}",
+            update.flag, update.summary, update.src, update.dst,
+        );
+    }
+}
+
+fn abbrev(id: ObjectId) -> String {
+    id.to_string()[..7].to_string()
+}
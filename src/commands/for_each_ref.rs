@@ -0,0 +1,67 @@
+use std::io::Write;
+
+use gitty::refs;
+use gitty::revparse;
+use gitty::ref_filter::{add_ahead_behind, ahead_behind_bases, format_ref, PatternMode, RefFilter};
+use gitty::store::ObjectId;
+use crate::cli::{ForEachRefArgs, RefFilterArgs};
+use crate::context::Context;
+
+const DEFAULT_FORMAT: &str = "%(objectname) %(objecttype)\t%(refname)";
+
+pub fn run(ForEachRefArgs { format, filter, patterns }: ForEachRefArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let filter = ref_filter(filter, patterns, PatternMode::Path)?;
+    let format = format.as_deref().unwrap_or(DEFAULT_FORMAT);
+
+    let bases = ahead_behind_bases(format).into_iter()
+        .map(|name| {
+            let commit = revparse::resolve(&name)
+                .and_then(revparse::peel_to_commit)
+                .ok_or_else(|| format!("failed to find '{name}'"))?;
+
+            Ok((name, commit))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut items = filter.filter(refs::list_refs("refs/"));
+
+    if !bases.is_empty() {
+        add_ahead_behind(&mut items, &bases);
+    }
+
+    let mut stdout = std::io::stdout().lock();
+
+    for item in items {
+        writeln!(stdout, "{}", format_ref(format, &item))?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// Resolves the commits named by the filter options
+pub fn ref_filter(args: RefFilterArgs, patterns: Vec<String>, pattern_mode: PatternMode)
+    -> Result<RefFilter, Box<dyn std::error::Error>>
+{
+    let resolve_all = |revs: Vec<String>| -> Result<Vec<ObjectId>, String> {
+        revs.iter()
+            .map(|rev| {
+                revparse::resolve(rev)
+                    .and_then(revparse::peel_to_commit)
+                    .ok_or_else(|| format!("malformed object name {rev}"))
+            })
+            .collect()
+    };
+
+    Ok(RefFilter {
+        patterns,
+        pattern_mode,
+        contains: resolve_all(args.contains)?,
+        no_contains: resolve_all(args.no_contains)?,
+        merged: resolve_all(args.merged)?,
+        no_merged: resolve_all(args.no_merged)?,
+    })
+}
@@ -0,0 +1,166 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::fsck::{check_connectivity, check_object, check_round_trip, FsckOptions, FsckSeverity, ObjectLinks};
+use gitty::refs;
+use gitty::reflog;
+use gitty::store::index::Index;
+use gitty::store::pack_stream::{list_packs, verify_pack};
+use gitty::store::util::{visit_loose_ids, visit_pack_ids};
+use gitty::store::{GitObjectStore, ObjectId};
+use crate::cli::FsckArgs;
+use crate::context::Context;
+
+pub fn run(FsckArgs { strict, round_trip, unreachable, no_dangling, no_reflogs }: FsckArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let config = Config::load();
+
+    let mut options = FsckOptions::for_fsck(&config);
+    options.strict = strict;
+
+    let mut ids = BTreeSet::new();
+    let mut loose = HashSet::new();
+    visit_loose_ids(None, |id| { ids.insert(id); loose.insert(id); });
+    visit_pack_ids(false, |desc| { ids.insert(desc.oid); });
+
+    let mut progress = ctx.progress("Checking objects", Some(ids.len()));
+    let mut errors = 0;
+
+    let ids: Vec<ObjectId> = ids.into_iter().collect();
+    let mut links = HashMap::new();
+
+    for (&id, object) in ids.iter().zip(GitObjectStore::get_many(&ids)) {
+        progress.tick();
+
+        // Packed objects are re-hashed when their packs are verified
+        if loose.contains(&id) {
+            match GitObjectStore::hash_loose(id) {
+                Some(actual) if actual != id => {
+                    eprintln!("error: {id}: hash mismatch, contents hash to {actual}");
+                    errors += 1;
+                }
+                _ => {}
+            }
+        }
+
+        let Some(object) = object else {
+            eprintln!("error: {id}: object corrupt or missing");
+            errors += 1;
+            continue;
+        };
+
+        for message in check_object(&object, &options) {
+            if message.severity == FsckSeverity::Error {
+                errors += 1;
+            }
+
+            eprintln!("{message}");
+        }
+
+        if round_trip {
+            if let Err(problem) = check_round_trip(&object) {
+                eprintln!("error in {} {id}: round trip: {problem}", object.type_str());
+                errors += 1;
+            }
+        }
+
+        links.insert(id, ObjectLinks::new(&object));
+    }
+
+    progress.finish();
+
+    let packs = list_packs();
+    let mut progress = ctx.progress("Verifying packs", Some(packs.len()));
+
+    for pack in packs {
+        progress.tick();
+
+        for problem in verify_pack(&pack) {
+            eprintln!("error: {pack}.pack: {problem}");
+            errors += 1;
+        }
+    }
+
+    progress.finish();
+
+    let mut roots = Vec::new();
+
+    for (name, id) in reachability_roots(no_reflogs) {
+        match links.contains_key(&id) {
+            true => roots.push(id),
+            false => {
+                eprintln!("error: {name}: invalid sha1 pointer {id}");
+                errors += 1;
+            }
+        }
+    }
+
+    let connectivity = check_connectivity(&links, roots);
+    let mut stdout = std::io::stdout().lock();
+
+    for link in &connectivity.broken_links {
+        writeln!(stdout, "broken link from {:>7} {}", link.from_kind, link.from)?;
+        writeln!(stdout, "              to {:>7} {}", link.to_kind, link.to)?;
+    }
+
+    for (id, kind) in &connectivity.missing {
+        writeln!(stdout, "missing {kind} {id}")?;
+        errors += 1;
+    }
+
+    if unreachable {
+        for (id, kind) in &connectivity.unreachable {
+            writeln!(stdout, "unreachable {kind} {id}")?;
+        }
+    } else if !no_dangling {
+        for (id, kind) in &connectivity.dangling {
+            writeln!(stdout, "dangling {kind} {id}")?;
+        }
+    }
+
+    stdout.flush()?;
+
+    if errors > 0 {
+        return Err(format!("{errors} error(s) found").into());
+    }
+
+    Ok(())
+}
+
+/// Where reachability is walked from, each with a name to report it by
+/// should it not exist: HEAD, every ref, every object the reflogs have
+/// recorded unless `no_reflogs`, and the blobs staged in the index
+fn reachability_roots(no_reflogs: bool) -> Vec<(String, ObjectId)> {
+    let mut roots = Vec::new();
+
+    if let Some(head) = refs::resolve_ref("HEAD") {
+        roots.push(("HEAD".to_string(), head));
+    }
+
+    roots.extend(refs::list_refs("refs/"));
+
+    if !no_reflogs {
+        for refname in reflog::list_reflogs() {
+            for entry in reflog::read_reflog(&refname).unwrap_or_default() {
+                for id in [entry.old, entry.new] {
+                    if !id.is_null() {
+                        roots.push((format!("{refname} reflog"), id));
+                    }
+                }
+            }
+        }
+    }
+
+    // Submodule commits and intent-to-add placeholders aren't objects here
+    if let Some(index) = Index::read() {
+        for entry in index.entries {
+            if entry.mode != 0o160000 && !entry.intent_to_add {
+                roots.push((format!(":{}", String::from_utf8_lossy(&entry.path)), entry.id));
+            }
+        }
+    }
+
+    roots
+}
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use gitty::message::subject;
+use gitty::pretty::Decorations;
+use gitty::store::object::Identity;
+use gitty::store::{GitObjectData, GitObjectStore, ObjectId};
+use crate::cli::GraphExportArgs;
+use crate::commands::log::walk;
+use crate::context::Context;
+
+/// A commit as it's exported
+struct Node {
+    id: ObjectId,
+    parents: Vec<ObjectId>,
+    subject: String,
+    author: Option<Identity>,
+    refs: Vec<String>,
+}
+
+pub fn run(GraphExportArgs { json, max_count, revisions }: GraphExportArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let decorations = Decorations::load();
+    let mut nodes = Vec::new();
+
+    let mut walk = walk(revisions, false)?;
+
+    while let Some(id) = walk.next().filter(|_| max_count.is_none_or(|max_count| nodes.len() < max_count)) {
+        let object = GitObjectStore::get(id).ok_or_else(|| format!("could not read commit {id}"))?;
+
+        let GitObjectData::Commit { author, message, .. } = object.data else {
+            return Err(format!("{id} is not a commit").into());
+        };
+
+        // Limited to paths, commits which don't change them are skipped
+        // over
+        nodes.push(Node {
+            id,
+            parents: walk.parents(id),
+            subject: subject(&message),
+            author: Identity::parse(&author),
+            refs: decorations.list(id),
+        });
+    }
+
+    // Edges only go to commits which are exported themselves, so a range
+    // is cut off where it starts
+    let exported: HashSet<ObjectId> = nodes.iter().map(|node| node.id).collect();
+
+    let edges: Vec<(ObjectId, ObjectId)> = nodes.iter()
+        .flat_map(|node| node.parents.iter().map(move |&parent| (node.id, parent)))
+        .filter(|(_, parent)| exported.contains(parent))
+        .collect();
+
+    let mut stdout = io::stdout().lock();
+
+    match json {
+        true => write_json(&mut stdout, &nodes, &edges)?,
+        false => write_dot(&mut stdout, &nodes, &edges)?,
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// Writes the commits as a Graphviz digraph, each labelled with its
+/// abbreviated id, subject and refs, with edges from children to parents
+fn write_dot<W: Write>(out: &mut W, nodes: &[Node], edges: &[(ObjectId, ObjectId)]) -> io::Result<()> {
+    writeln!(out, "digraph commits {{")?;
+    writeln!(out, "\tnode [shape=box];")?;
+
+    for node in nodes {
+        let mut label = format!("{} {}", &node.id.to_string()[..7], node.subject);
+
+        if !node.refs.is_empty() {
+            label.push_str(&format!("\n({})", node.refs.join(", ")));
+        }
+
+        writeln!(out, "\t\"{}\" [label=\"{}\"];", node.id, escape_dot(&label))?;
+    }
+
+    for (child, parent) in edges {
+        writeln!(out, "\t\"{child}\" -> \"{parent}\";")?;
+    }
+
+    writeln!(out, "}}")
+}
+
+/// Writes the commits in the JSON Graph Format:
+///
+///   {"graph": {"directed": true,
+///              "nodes": {"<id>": {"label": ..., "metadata": {...}}, ...},
+///              "edges": [{"source": "<child>", "target": "<parent>"}, ...]}}
+fn write_json<W: Write>(out: &mut W, nodes: &[Node], edges: &[(ObjectId, ObjectId)]) -> io::Result<()> {
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"graph\": {{")?;
+    writeln!(out, "    \"directed\": true,")?;
+    writeln!(out, "    \"nodes\": {{")?;
+
+    for (i, node) in nodes.iter().enumerate() {
+        let refs: Vec<String> = node.refs.iter().map(|name| format!("\"{}\"", escape_json(name))).collect();
+
+        let (author, time) = match &node.author {
+            Some(author) => (format!("{} <{}>", author.name, author.email), author.timestamp),
+            None => (String::new(), 0),
+        };
+
+        write!(
+            out,
+            "      \"{}\": {{\"label\": \"{}\", \"metadata\": {{\"author\": \"{}\", \"time\": {}, \"refs\": [{}]}}}}",
+            node.id,
+            escape_json(&node.subject),
+            escape_json(&author),
+            time,
+            refs.join(", "),
+        )?;
+
+        writeln!(out, "{}", if i + 1 < nodes.len() { "," } else { "" })?;
+    }
+
+    writeln!(out, "    }},")?;
+    writeln!(out, "    \"edges\": [")?;
+
+    for (i, (child, parent)) in edges.iter().enumerate() {
+        write!(out, "      {{\"source\": \"{child}\", \"target\": \"{parent}\"}}")?;
+        writeln!(out, "{}", if i + 1 < edges.len() { "," } else { "" })?;
+    }
+
+    writeln!(out, "    ]")?;
+    writeln!(out, "  }}")?;
+    writeln!(out, "}}")
+}
+
+/// Escapes a string for a double quoted DOT label, where "\n" is a line
+/// break
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Escapes a string for a JSON string
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
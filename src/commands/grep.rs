@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+use std::thread;
+
+use gitty::config::Config;
+use gitty::grep::{index_files, is_binary, read_files, tree_files, Grep, GrepFile};
+use gitty::pathspec::Pathspec;
+use gitty::quote::{quote_non_ascii, quote_path};
+use gitty::revparse::{self, peel_to_tree};
+use gitty::store::index::Index;
+use crate::cli::GrepArgs;
+use crate::context::Context;
+
+/// How what's found is shown
+struct Output {
+    line_number: bool,
+    files_with_matches: bool,
+    count: bool,
+    quiet: bool,
+    quote_non_ascii: bool,
+}
+
+impl Output {
+    /// Shows what `grep` finds in the file at `path`, named with `prefix`
+    /// when it's from a tree, eg. "HEAD:". Whether anything was found.
+    fn show(&self, out: &mut impl Write, grep: &Grep, prefix: &str, path: &[u8], data: &[u8]) -> io::Result<bool> {
+        if self.quiet {
+            return Ok(grep.is_found(data));
+        }
+
+        let name = format!("{prefix}{}", quote_path(path, self.quote_non_ascii));
+
+        if self.count {
+            let count = grep.lines(data).count();
+
+            if count > 0 {
+                writeln!(out, "{name}:{count}")?;
+            }
+
+            return Ok(count > 0);
+        }
+
+        if self.files_with_matches || is_binary(data) {
+            let found = grep.is_found(data);
+
+            match found {
+                true if self.files_with_matches => writeln!(out, "{name}")?,
+                true => writeln!(out, "Binary file {name} matches")?,
+                false => {}
+            }
+
+            return Ok(found);
+        }
+
+        let mut found = false;
+
+        for (number, line) in grep.lines(data) {
+            found = true;
+
+            match self.line_number {
+                true => write!(out, "{name}:{number}:")?,
+                false => write!(out, "{name}:")?,
+            }
+
+            out.write_all(line)?;
+            out.write_all(b"\n")?;
+        }
+
+        Ok(found)
+    }
+}
+
+pub fn run(args: GrepArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load();
+
+    let grep = Grep::new(&args.pattern, args.ignore_case, args.invert_match)
+        .map_err(|err| format!("invalid pattern '{}': {err}", args.pattern))?;
+
+    let pathspec = Pathspec::parse(&args.paths).ok_or("invalid pathspec")?;
+
+    // As for git, 0 threads is one per CPU
+    let threads = match args.threads.or_else(|| config.get("grep.threads")?.parse().ok()) {
+        Some(0) | None => thread::available_parallelism().map_or(1, |n| n.get()),
+        Some(threads) => threads,
+    };
+
+    let output = Output {
+        line_number: args.line_number || config.get_bool("grep.lineNumber").unwrap_or(false),
+        files_with_matches: args.files_with_matches,
+        count: args.count,
+        quiet: ctx.quiet,
+        quote_non_ascii: quote_non_ascii(&config),
+    };
+
+    // Each tree with the prefix its files are shown with
+    let mut searches: Vec<(String, Vec<GrepFile>)> = Vec::new();
+
+    for name in &args.trees {
+        let tree = revparse::resolve(name)
+            .and_then(peel_to_tree)
+            .ok_or_else(|| revparse::missing_path(name).unwrap_or_else(|| format!("unable to resolve revision: {name}")))?;
+
+        let files = tree_files(tree, &pathspec).ok_or_else(|| format!("unable to read tree ({tree})"))?;
+
+        searches.push((format!("{name}:"), files));
+    }
+
+    if args.trees.is_empty() {
+        let index = Index::read().ok_or("unable to read the index")?;
+        searches.push((String::new(), index_files(&index, &pathspec, args.cached)));
+    }
+
+    let mut stdout = io::stdout().lock();
+    let mut found = false;
+
+    for (prefix, files) in &searches {
+        read_files(files, threads, |file, data| {
+            // A file that's gone from the worktree has nothing to find
+            if let Some(data) = data {
+                found |= output.show(&mut stdout, &grep, prefix, &file.path, &data)?;
+            }
+
+            Ok(())
+        })?;
+    }
+
+    stdout.flush()?;
+
+    if !found {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+use gitty::store::object::parse_object;
+use gitty::store::util::hash_object;
+use gitty::store::{GitObjectStore, ObjectId};
+use crate::cli::HashObjectArgs;
+use crate::context::Context;
+
+/// How an object is made from its contents
+struct Options {
+    kind: String,
+    write: bool,
+    literally: bool,
+}
+
+/// The objects being written, so two workers given the same contents
+/// don't both write it, and neither reports it before it's there
+#[derive(Default)]
+struct Writing {
+    ids: Mutex<HashSet<ObjectId>>,
+    done: Condvar,
+}
+
+pub fn run(HashObjectArgs { kind, write, stdin, stdin_paths, literally, files }: HashObjectArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    if !literally && !matches!(kind.as_str(), "blob" | "tree" | "commit" | "tag") {
+        return Err(format!("invalid object type \"{kind}\"").into());
+    }
+
+    let options = Options { kind, write, literally };
+    let writing = Writing::default();
+
+    if stdin {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+
+        println!("{}", hash(&data, &options, &writing)?);
+    }
+
+    match stdin_paths {
+        true => hash_files(io::stdin().lock().lines().map(|line| line.map(PathBuf::from)), &options, &writing),
+        false => hash_files(files.into_iter().map(Ok), &options, &writing),
+    }
+}
+
+/// Hashes the files at `paths` on as many threads as there are cores,
+/// printing each id in order as soon as it and those before it are done.
+///
+/// Each path is taken as it's read, so paths can be fed in one at a time
+/// by a program waiting for each id, as with git.
+fn hash_files<I>(paths: I, options: &Options, writing: &Writing) -> Result<(), Box<dyn std::error::Error>>
+where
+    I: Iterator<Item = io::Result<PathBuf>>,
+{
+    let workers = thread::available_parallelism().map_or(1, |workers| workers.get());
+
+    let (job_sender, jobs) = mpsc::sync_channel::<(usize, PathBuf)>(workers * 4);
+    let (result_sender, results) = mpsc::channel::<(usize, Result<ObjectId, String>)>();
+
+    let jobs = Mutex::new(jobs);
+    let failed = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let result_sender = result_sender.clone();
+            let jobs = &jobs;
+
+            scope.spawn(move || loop {
+                let job = jobs.lock().unwrap_or_else(|err| err.into_inner()).recv();
+
+                let Ok((n, path)) = job else {
+                    break;
+                };
+
+                let _ = result_sender.send((n, hash_file(&path, options, writing)));
+            });
+        }
+
+        drop(result_sender);
+
+        let failed = &failed;
+
+        let printer = scope.spawn(move || -> Result<(), String> {
+            let mut stdout = io::stdout().lock();
+            let mut done = BTreeMap::new();
+            let mut next = 0;
+
+            for (n, result) in results {
+                done.insert(n, result);
+
+                while let Some(result) = done.remove(&next) {
+                    let id = result.inspect_err(|_| failed.store(true, Ordering::Relaxed))?;
+
+                    writeln!(stdout, "{id}").map_err(|err| err.to_string())?;
+                    next += 1;
+                }
+
+                stdout.flush().map_err(|err| err.to_string())?;
+            }
+
+            Ok(())
+        });
+
+        for (n, path) in paths.enumerate() {
+            if failed.load(Ordering::Relaxed) {
+                break;
+            }
+
+            if job_sender.send((n, path?)).is_err() {
+                break;
+            }
+        }
+
+        drop(job_sender);
+
+        printer.join().expect("printer panicked").map_err(Into::into)
+    })
+}
+
+fn hash_file(path: &Path, options: &Options, writing: &Writing) -> Result<ObjectId, String> {
+    let data = fs::read(path).map_err(|err| format!("could not open '{}' for reading: {err}", path.display()))?;
+
+    hash(&data, options, writing)
+}
+
+/// The id of the object with `data` as its contents, written to the store
+/// if asked to be
+fn hash(data: &[u8], options: &Options, writing: &Writing) -> Result<ObjectId, String> {
+    let kind = options.kind.as_str();
+
+    if !options.literally && kind != "blob" && parse_object(kind, data).is_none() {
+        return Err(format!("corrupt {kind}"));
+    }
+
+    let id = hash_object(kind, data);
+
+    if !options.write {
+        return Ok(id);
+    }
+
+    {
+        let mut ids = writing.ids.lock().unwrap_or_else(|err| err.into_inner());
+
+        while ids.contains(&id) {
+            ids = writing.done.wait(ids).unwrap_or_else(|err| err.into_inner());
+        }
+
+        ids.insert(id);
+    }
+
+    let written = GitObjectStore::write(kind, data);
+
+    writing.ids.lock().unwrap_or_else(|err| err.into_inner()).remove(&id);
+    writing.done.notify_all();
+
+    written.ok_or_else(|| format!("unable to write {kind} object {id}"))
+}
@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+use gitty::init::{init, DEFAULT_BRANCH};
+use crate::cli::InitArgs;
+use crate::context::Context;
+
+pub fn run(InitArgs { bare, initial_branch, path }: InitArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.unwrap_or_else(|| PathBuf::from("."));
+    let initial_branch = initial_branch.as_deref().unwrap_or(DEFAULT_BRANCH);
+
+    let result = init(&path, bare, initial_branch)?;
+
+    if ctx.quiet {
+        return Ok(());
+    }
+
+    if result.reinitialized {
+        println!("Reinitialized existing Git repository in {}/", result.git_dir.display());
+    } else {
+        println!("Initialized empty Git repository in {}/", result.git_dir.display());
+    }
+
+    Ok(())
+}
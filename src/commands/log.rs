@@ -0,0 +1,337 @@
+use std::io::{self, IsTerminal, Write};
+
+use gitty::config::Config;
+use gitty::date::{self, DateMode};
+use gitty::diff::{diff_trees_in, write_patch, TreeChange};
+use gitty::mailmap::Mailmap;
+use gitty::pathspec::Pathspec;
+use gitty::pretty::{format_commit, Decorations, PrettyFormat, PrettyOptions, ReflogInfo};
+use gitty::quote::quote_non_ascii;
+use gitty::reflog::{self, ReflogSelector};
+use gitty::revparse;
+use gitty::revwalk::RevWalk;
+use gitty::store::object::Identity;
+use gitty::store::{GitObjectData, GitObjectStore, ObjectId};
+use regex::Regex;
+use crate::cli::{LogArgs, PrettyArgs, RevisionArgs};
+use crate::context::Context;
+
+pub fn run(LogArgs { patch, max_count, source, walk_reflogs, grep_reflog, pretty, revisions }: LogArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let pretty = Pretty::from_args(pretty, true)?;
+    let pathspec = Pathspec::parse(&revisions.paths).ok_or("invalid pathspec")?;
+
+    let mut printer = CommitPrinter {
+        pretty: &pretty,
+        patch,
+        pathspec,
+        combined_merges: false,
+        quote_non_ascii: quote_non_ascii(&Config::load()),
+        shown_one: false,
+        reflog: None,
+    };
+
+    let mut stdout = io::stdout().lock();
+
+    if walk_reflogs {
+        let patterns = grep_reflog.iter()
+            .map(|pattern| Regex::new(pattern).map_err(|err| format!("invalid --grep-reflog pattern: {err}")))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut shown = 0;
+
+        for (info, id) in reflog_entries(revisions, pretty.date_mode)? {
+            if max_count.is_some_and(|max_count| shown >= max_count) {
+                break;
+            }
+
+            if !patterns.is_empty() && !patterns.iter().any(|pattern| pattern.is_match(&info.entry.message)) {
+                continue;
+            }
+
+            // Entries for commits since pruned, or for the ref's deletion,
+            // have nothing to show
+            let Some(commit) = GitObjectStore::get(id).filter(|commit| matches!(commit.data, GitObjectData::Commit { .. })) else {
+                continue;
+            };
+
+            printer.reflog = Some(info);
+            printer.print(&mut stdout, id, &commit.data, None)?;
+            shown += 1;
+        }
+
+        stdout.flush()?;
+
+        return Ok(());
+    }
+
+    let mut walk = walk(revisions, source)?;
+    let mut shown = 0;
+
+    while let Some(id) = walk.next().filter(|_| max_count.is_none_or(|max_count| shown < max_count)) {
+        let commit = GitObjectStore::get(id).ok_or_else(|| format!("could not read commit {id}"))?;
+        printer.print(&mut stdout, id, &commit.data, walk.source(id))?;
+        shown += 1;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// A walk from the starting points given, or from HEAD without any,
+/// recording which one each commit is reached from with `sources`
+pub fn walk(args: RevisionArgs, sources: bool) -> Result<RevWalk, Box<dyn std::error::Error>> {
+    let RevisionArgs { all, branches, tags, remotes, simplify_merges, mut revisions, paths } = args;
+
+    let mut walk = RevWalk::new();
+    walk.limit_to_paths(Pathspec::parse(&paths).ok_or("invalid pathspec")?);
+
+    if simplify_merges {
+        walk.simplify_merges();
+    }
+
+    if sources {
+        walk.record_sources();
+    }
+
+    if !all && branches.is_empty() && tags.is_empty() && remotes.is_empty() && revisions.is_empty() {
+        revisions.push("HEAD".to_string());
+    }
+
+    if all {
+        walk.push_all();
+    }
+
+    for (prefix, patterns) in [("refs/heads/", branches), ("refs/tags/", tags), ("refs/remotes/", remotes)] {
+        for pattern in patterns {
+            walk.push_refs(prefix, Some(&pattern));
+        }
+    }
+
+    for revision in revisions {
+        walk.push_range(&revision).ok_or_else(|| format!("bad revision '{revision}'"))?;
+    }
+
+    Ok(walk)
+}
+
+/// The reflog entries of the refs given, HEAD's without any, newest first,
+/// with the commit each is for. A ref may be given with a reflog selector,
+/// eg. "main@{2}" or "main@{1.week.ago}", to start from that entry, whose
+/// entries are then shown by date if the selector is one.
+fn reflog_entries(args: RevisionArgs, date_mode: DateMode) -> Result<Vec<(ReflogInfo, ObjectId)>, Box<dyn std::error::Error>> {
+    let RevisionArgs { all, branches, tags, remotes, mut revisions, .. } = args;
+
+    if all || !branches.is_empty() || !tags.is_empty() || !remotes.is_empty() {
+        return Err("--walk-reflogs walks the reflogs of the refs given, not --all, --branches, --tags or --remotes".into());
+    }
+
+    if revisions.is_empty() {
+        revisions.push("HEAD".to_string());
+    }
+
+    let now = date::now();
+    let mut shown = Vec::new();
+
+    for revision in revisions {
+        let (name, selector) = match revision.strip_suffix('}').and_then(|rest| rest.split_once("@{")) {
+            Some((name, selector)) => {
+                let selector = ReflogSelector::parse(selector, now).ok_or_else(|| format!("bad revision '{revision}'"))?;
+                (name, Some(selector))
+            }
+            None if revision.contains("..") || revision.starts_with('^') => {
+                return Err("cannot combine --walk-reflogs with history-limiting options".into());
+            }
+            None => (revision.as_str(), None),
+        };
+
+        let refname = reflog::reflog_name(name).ok_or_else(|| format!("bad revision '{revision}'"))?;
+        let entries = reflog::read_reflog(&refname).ok_or_else(|| format!("reflog could not be found: '{revision}'"))?;
+
+        // "@{...}" on its own is the current branch's reflog, shown by its
+        // full name
+        let name = match name {
+            "" => refname.as_str(),
+            name => name,
+        };
+
+        for (i, entry) in entries.iter().rev().enumerate() {
+            let selector = match selector {
+                Some(ReflogSelector::Nth(n)) if i < n => continue,
+                Some(ReflogSelector::Date(time)) if entry.timestamp().unwrap_or(0) > time => continue,
+                Some(ReflogSelector::Date(_)) => {
+                    let date = Identity::parse(&entry.committer)
+                        .map(|ident| date::format_date(ident.timestamp, ident.tz_offset, date_mode, now))
+                        .unwrap_or_default();
+
+                    format!("{name}@{{{date}}}")
+                }
+                _ => format!("{name}@{{{i}}}"),
+            };
+
+            shown.push((ReflogInfo { selector, entry: entry.clone() }, entry.new));
+        }
+    }
+
+    Ok(shown)
+}
+
+/// How commits are shown, from `--pretty` and its friends
+pub struct Pretty {
+    pub format: PrettyFormat,
+    pub date_mode: DateMode,
+    pub abbrev_commit: bool,
+    pub decorations: Option<Decorations>,
+    pub color: bool,
+    pub mailmap: Mailmap,
+    pub use_mailmap: bool,
+}
+
+impl Pretty {
+    /// How `args` say to show commits, with identities mapped through the
+    /// mailmap by default if `log_mailmap`, as log.mailmap allows
+    pub fn from_args(args: PrettyArgs, log_mailmap: bool) -> Result<Pretty, Box<dyn std::error::Error>> {
+        let PrettyArgs { pretty, format, oneline, abbrev_commit, decorate, date, mailmap, no_mailmap } = args;
+        let config = Config::load();
+
+        let format = match pretty.or(format) {
+            Some(spec) => PrettyFormat::parse(&spec).ok_or_else(|| format!("invalid --pretty format: {spec}"))?,
+            None if oneline => PrettyFormat::Oneline,
+            None => PrettyFormat::default(),
+        };
+
+        let date_mode = match date {
+            Some(date) => DateMode::parse(&date).ok_or_else(|| format!("unknown date format {date}"))?,
+            None => DateMode::default(),
+        };
+
+        // Decorations are only read when something will show them, as that
+        // means reading every ref
+        let decorations = match (decorate && !format.is_user_format()) || format.uses_decorations() {
+            true => Some(Decorations::load()),
+            false => None,
+        };
+
+        let use_mailmap = match (mailmap, no_mailmap) {
+            (true, _) => true,
+            (_, true) => false,
+            _ => log_mailmap && config.get_bool("log.mailmap").unwrap_or(true),
+        };
+
+        Ok(Pretty {
+            format,
+            date_mode,
+            abbrev_commit: abbrev_commit || oneline,
+            decorations,
+            color: io::stdout().is_terminal(),
+            mailmap: Mailmap::load(&config),
+            use_mailmap,
+        })
+    }
+
+    pub fn options<'a>(&'a self, source: Option<&'a str>) -> PrettyOptions<'a> {
+        PrettyOptions {
+            date_mode: self.date_mode,
+            abbrev_commit: self.abbrev_commit,
+            decorations: self.decorations.as_ref(),
+            source,
+            color: self.color,
+            mailmap: Some(&self.mailmap),
+            use_mailmap: self.use_mailmap,
+            reflog: None,
+        }
+    }
+}
+
+/// Prints commits one after another as `git log` does. Commits in the
+/// multi-line formats are separated by a blank line, "format:" strings by
+/// a newline, while oneline and "tformat:" strings end with one.
+pub struct CommitPrinter<'a> {
+    pub pretty: &'a Pretty,
+
+    /// Follow each commit with the patch it introduces
+    pub patch: bool,
+
+    /// The paths patches are limited to
+    pub pathspec: Pathspec,
+
+    /// Set merges apart from what follows as if they had a patch, the
+    /// combined diff `git show` has for them, which is empty for a clean
+    /// merge. `git log -p` shows nothing for merges.
+    pub combined_merges: bool,
+
+    pub quote_non_ascii: bool,
+    pub shown_one: bool,
+
+    /// The reflog entry the next commit is shown for, when walking reflogs
+    pub reflog: Option<ReflogInfo>,
+}
+
+impl CommitPrinter<'_> {
+    /// Prints `commit`, with the starting point it was reached from if
+    /// there's one
+    pub fn print<W: Write>(&mut self, out: &mut W, id: ObjectId, commit: &GitObjectData, source: Option<&str>)
+        -> io::Result<()>
+    {
+        let format = &self.pretty.format;
+
+        if self.shown_one && !format.is_terminated() {
+            writeln!(out)?;
+        }
+
+        let options = PrettyOptions { reflog: self.reflog.as_ref(), ..self.pretty.options(source) };
+
+        out.write_all(&format_commit(format, id, commit, &options))?;
+
+        if format.is_terminated() {
+            writeln!(out)?;
+        }
+
+        self.shown_one = true;
+
+        if !self.patch {
+            return Ok(());
+        }
+
+        let (changes, is_merge) = match commit_changes(commit, &self.pathspec)? {
+            Some(changes) => (changes, false),
+            None => (Vec::new(), true),
+        };
+
+        // The patch is set apart from the message, unless each commit is
+        // shown on one line
+        if (!changes.is_empty() || is_merge && self.combined_merges) && *format != PrettyFormat::Oneline {
+            writeln!(out)?;
+        }
+
+        for change in &changes {
+            write_patch(out, change, self.quote_non_ascii)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The changes between a commit's first parent and the commit in the
+/// paths `pathspec` matches, each file against the parent tree, or against
+/// nothing for a root commit. None for merges, whose changes are against
+/// every parent.
+pub fn commit_changes(commit: &GitObjectData, pathspec: &Pathspec) -> io::Result<Option<Vec<TreeChange>>> {
+    let GitObjectData::Commit { tree, parents, .. } = commit else {
+        return Ok(Some(Vec::new()));
+    };
+
+    if parents.len() > 1 {
+        return Ok(None);
+    }
+
+    let parent_tree = parents.first()
+        .map(|&parent| revparse::peel_to_tree(parent).ok_or_else(|| io::Error::other(format!("unable to read {parent}"))))
+        .transpose()?;
+
+    diff_trees_in(parent_tree, Some(*tree), pathspec)
+        .ok_or_else(|| io::Error::other(format!("unable to diff {tree}")))
+        .map(Some)
+}
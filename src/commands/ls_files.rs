@@ -0,0 +1,51 @@
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::ignore::IgnoreRules;
+use gitty::quote::{quote_non_ascii, quote_path};
+use gitty::store::index::Index;
+use gitty::worktree::list_untracked;
+use crate::cli::LsFilesArgs;
+use crate::context::Context;
+
+pub fn run(LsFilesArgs { cached, others, exclude_standard, directory, no_empty_directory, stage, nul }: LsFilesArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let index = Index::read().ok_or("Unable to read index")?;
+
+    let config = Config::load();
+    let quote_non_ascii = quote_non_ascii(&config);
+    let terminator = if nul { '\0' } else { '\n' };
+
+    let format_path = |path: &[u8]| match nul {
+        true => String::from_utf8_lossy(path).into_owned(),
+        false => quote_path(path, quote_non_ascii),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+
+    // Untracked files come before the index's, as with git
+    if others {
+        let mut rules = exclude_standard.then(|| IgnoreRules::load(&config));
+
+        for path in list_untracked(&index, rules.as_mut(), directory, no_empty_directory) {
+            write!(stdout, "{}{terminator}", format_path(path.as_bytes()))?;
+        }
+    }
+
+    if cached || stage || !others {
+        for entry in &index.entries {
+            let path = format_path(&entry.path);
+
+            if stage {
+                write!(stdout, "{:06o} {} {}\t{path}{terminator}", entry.mode, entry.id, entry.stage)?;
+            } else {
+                write!(stdout, "{path}{terminator}")?;
+            }
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
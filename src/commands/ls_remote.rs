@@ -0,0 +1,94 @@
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::protocol::fetch::{is_url, Connection, ProtocolVersion};
+use gitty::protocol::local::local_git_dir;
+use gitty::refs;
+use gitty::remote::{rewrite_url, Remote};
+use gitty::wildmatch::{wildmatch, WildmatchFlags};
+use crate::cli::LsRemoteArgs;
+use crate::context::Context;
+
+/// Lists a remote's refs as it advertises them, one "<id> TAB <name>" line
+/// each, without fetching anything
+pub fn run(LsRemoteArgs { heads, tags, refs: refs_only, symref, exit_code, repository, patterns }: LsRemoteArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let config = Config::load();
+    let defaulted = repository.is_none();
+
+    // The current branch's remote, failing that origin
+    let repository = repository.unwrap_or_else(|| {
+        refs::head_branch()
+            .and_then(|branch| config.get(&format!("branch.{}.remote", refs::shorten_ref(&branch))).map(str::to_string))
+            .unwrap_or_else(|| "origin".to_string())
+    });
+
+    let url = match Remote::get(&config, &repository) {
+        Some(remote) => remote.urls[0].clone(),
+        None => rewrite_url(&config, &repository),
+    };
+
+    if !is_url(&url) && local_git_dir(&url).is_none() {
+        return Err(format!("'{url}' does not appear to be a git repository").into());
+    }
+
+    // Which remote was picked is only worth saying when it wasn't given
+    if defaulted && !ctx.quiet {
+        eprintln!("From {url}");
+    }
+
+    let mut prefixes = Vec::new();
+
+    if heads {
+        prefixes.push("refs/heads/".to_string());
+    }
+
+    if tags {
+        prefixes.push("refs/tags/".to_string());
+    }
+
+    let version = ProtocolVersion::from_config(&config).ok_or("bad protocol.version")?;
+    let connection = Connection::connect(&url, version).map_err(|err| err.to_string())?;
+
+    let advertised = connection.list_refs(&prefixes).map_err(|err| err.to_string())?;
+
+    let head_target = match symref {
+        true => connection.head_target().map_err(|err| err.to_string())?,
+        false => None,
+    };
+
+    // A pattern matches a whole name, or its last components
+    let matches = |name: &str| patterns.is_empty() || patterns.iter().any(|pattern| {
+        wildmatch(pattern, name, WildmatchFlags::default())
+            || wildmatch(&format!("*/{pattern}"), name, WildmatchFlags::default())
+    });
+
+    let mut stdout = std::io::stdout().lock();
+    let mut listed = false;
+
+    for (name, id) in &advertised {
+        if refs_only && (name.ends_with("^{}") || !name.starts_with("refs/")) {
+            continue;
+        }
+
+        if !matches(name) {
+            continue;
+        }
+
+        if let Some(target) = head_target.as_ref().filter(|_| name == "HEAD") {
+            writeln!(stdout, "ref: {target}\tHEAD")?;
+        }
+
+        writeln!(stdout, "{id}\t{name}")?;
+        listed = true;
+    }
+
+    stdout.flush()?;
+
+    if exit_code && !listed {
+        std::process::exit(2);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::quote::{quote_non_ascii, quote_path};
+use gitty::ref_filter::expand_format;
+use gitty::revparse;
+use gitty::store::tree::MODE_TREE;
+use gitty::store::{GitObjectData, GitObjectStore, ObjectId, TreeEntry};
+use crate::cli::LsTreeArgs;
+use crate::context::Context;
+
+const DEFAULT_FORMAT: &str = "%(objectmode) %(objecttype) %(objectname)%x09%(path)";
+const LONG_FORMAT: &str = "%(objectmode) %(objecttype) %(objectname) %(objectsize:padded)%x09%(path)";
+
+/// What's listed and how
+struct Listing {
+    format: String,
+    paths: Vec<String>,
+    recursive: bool,
+    trees_only: bool,
+    show_trees: bool,
+    nul: bool,
+    quote_non_ascii: bool,
+}
+
+impl Listing {
+    /// Whether to list what's in the tree at `path` rather than the tree:
+    /// always when recursing, otherwise when a path asked for is in it,
+    /// eg. "src/" or "src/main.rs" but not "src"
+    fn recurses_into(&self, path: &str) -> bool {
+        self.recursive || self.paths.iter().any(|spec| {
+            spec.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+}
+
+pub fn run(args: LsTreeArgs, _ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let tree = revparse::resolve(&args.tree)
+        .ok_or_else(|| revparse::missing_path(&args.tree).unwrap_or_else(|| format!("Not a valid object name {}", args.tree)))?;
+    let tree = revparse::peel_to_tree(tree).ok_or("not a tree object")?;
+
+    let format = match args.format {
+        Some(format) => format,
+        None if args.name_only => "%(path)".to_string(),
+        None if args.object_only => "%(objectname)".to_string(),
+        None if args.long => LONG_FORMAT.to_string(),
+        None => DEFAULT_FORMAT.to_string(),
+    };
+
+    let listing = Listing {
+        format,
+        paths: args.paths,
+        recursive: args.recursive,
+        trees_only: args.trees_only,
+        // Listing only trees recursively would list nothing otherwise
+        show_trees: args.show_trees || args.trees_only && args.recursive,
+        nul: args.nul,
+        quote_non_ascii: quote_non_ascii(&Config::load()),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    list(&listing, tree, "", &mut stdout)?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn list(listing: &Listing, tree: ObjectId, base: &str, out: &mut impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(GitObjectData::Tree { entries }) = GitObjectStore::get(tree).map(|object| object.data) else {
+        return Err(format!("not a tree object: {tree}").into());
+    };
+
+    for entry in &entries {
+        let path = format!("{base}{}", String::from_utf8_lossy(&entry.path));
+        let is_tree = entry.mode == MODE_TREE;
+
+        if !listing.paths.is_empty() && !listing.paths.iter().any(|spec| matches(spec, &path, is_tree)) {
+            continue;
+        }
+
+        if is_tree && listing.recurses_into(&path) {
+            if listing.show_trees {
+                show(listing, entry, &path, out)?;
+            }
+
+            list(listing, entry.id, &format!("{path}/"), out)?;
+            continue;
+        }
+
+        if !listing.trees_only || is_tree {
+            show(listing, entry, &path, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the entry at `path` is what the path `spec` asks for, or is in
+/// it, or is a tree it's in. Paths are matched literally, not as globs.
+fn matches(spec: &str, path: &str, is_tree: bool) -> bool {
+    let dir = spec.trim_end_matches('/');
+
+    path == dir
+        || path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+        || is_tree && spec.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn show(listing: &Listing, entry: &TreeEntry, path: &str, out: &mut impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let line = expand_format(&listing.format, |atom| {
+        Some(match atom {
+            "objectmode" => format!("{:06o}", entry.mode),
+            "objecttype" => entry.kind.to_string(),
+            "objectname" => entry.id.to_string(),
+            "objectsize" => object_size(entry),
+            "objectsize:padded" => format!("{:>7}", object_size(entry)),
+            "path" if listing.nul => path.to_string(),
+            "path" => quote_path(path.as_bytes(), listing.quote_non_ascii),
+            _ => return None,
+        })
+    });
+
+    let line = line.ok_or_else(|| format!("bad ls-tree format: {}", listing.format))?;
+
+    out.write_all(line.as_bytes())?;
+    out.write_all(if listing.nul { b"\0" } else { b"\n" })?;
+
+    Ok(())
+}
+
+/// The size of a blob, '-' for anything else
+fn object_size(entry: &TreeEntry) -> String {
+    match entry.kind {
+        "blob" => GitObjectStore::open_blob(entry.id)
+            .map(|blob| blob.size().to_string())
+            .unwrap_or_else(|| "-".to_string()),
+        _ => "-".to_string(),
+    }
+}
@@ -0,0 +1,459 @@
+use std::fs;
+use std::io;
+
+use gitty::autostash::{apply_autostash, create_autostash, AutostashOutcome};
+use gitty::checkout::{checkout_tree, TreeFiles};
+use gitty::config::Config;
+use gitty::graph::Graph;
+use gitty::ident::{ident, IdentRole};
+use gitty::merge::{merge_commits, record_conflicts, ConflictStyle, TreeMerge, TreeMergeOptions};
+use gitty::message::stripspace;
+use gitty::pretty::{format_commit, PrettyFormat, PrettyOptions};
+use gitty::refs::{self, RefTransaction};
+use gitty::reflog::ReflogEntry;
+use gitty::repo::git_path;
+use gitty::revparse::{self, peel_to_commit, peel_to_tree};
+use gitty::revwalk::RevWalk;
+use gitty::signature::{check_commit_signature, check_tag_signature, SignatureCheck};
+use gitty::store::index::Index;
+use gitty::store::object::serialize_commit;
+use gitty::store::tree::TreeBuilder;
+use gitty::store::{GitObjectData, GitObjectStore, ObjectId};
+use crate::cli::MergeArgs;
+use crate::commands::switch::commit_files;
+use crate::context::Context;
+
+const AUTOSTASH: &str = "MERGE_AUTOSTASH";
+
+/// Whether a merge may, or must, fast-forward
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FastForward {
+    /// Fast-forward when possible, otherwise make a merge commit
+    Allow,
+
+    /// Always make a merge commit
+    Never,
+
+    /// Only fast-forward
+    Only,
+}
+
+impl FastForward {
+    /// From `key`, eg. merge.ff, a boolean or "only". None if it's unset.
+    pub fn configured(config: &Config, key: &str) -> Option<FastForward> {
+        if config.get(key).is_some_and(|value| value.eq_ignore_ascii_case("only")) {
+            return Some(FastForward::Only);
+        }
+
+        match config.get_bool(key)? {
+            true => Some(FastForward::Allow),
+            false => Some(FastForward::Never),
+        }
+    }
+}
+
+/// How a merge is carried out, whether by merge itself or for pull
+pub struct MergeOptions {
+    pub fast_forward: FastForward,
+    pub squash: bool,
+
+    /// Paragraphs of the merge commit's message, the default message if
+    /// there are none
+    pub messages: Vec<String>,
+
+    pub autostash: bool,
+
+    /// Refuse to merge a commit without a good, trusted signature
+    pub verify_signatures: bool,
+
+    /// What the reflog says made the update, eg. "merge topic"
+    pub reflog_action: String,
+}
+
+/// A merge found to be possible: a fast-forward, one that makes a commit
+/// with the same tree as `theirs`, or, where the histories have diverged,
+/// one that merges the trees
+struct Merge<'a> {
+    /// The commit as it was named on the command line
+    name: &'a str,
+    theirs: ObjectId,
+    head: Option<ObjectId>,
+    head_branch: Option<String>,
+    options: &'a MergeOptions,
+    committer: String,
+}
+
+pub fn run(args: MergeArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let MergeArgs {
+        ff, no_ff, ff_only, squash, messages, autostash, no_autostash, verify_signatures, no_verify_signatures, commit,
+    } = args;
+
+    let config = Config::load();
+
+    // A squash makes no merge commit, so merge.ff has no say in it
+    let fast_forward = match (ff || squash, no_ff, ff_only) {
+        (true, _, _) => FastForward::Allow,
+        (_, true, _) => FastForward::Never,
+        (_, _, true) => FastForward::Only,
+        _ => FastForward::configured(&config, "merge.ff").unwrap_or(FastForward::Allow),
+    };
+
+    let autostash = match (autostash, no_autostash) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => config.get_bool("merge.autoStash").unwrap_or(false),
+    };
+
+    let verify_signatures = match (verify_signatures, no_verify_signatures) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => config.get_bool("merge.verifySignatures").unwrap_or(false),
+    };
+
+    let named = revparse::resolve(&commit);
+
+    let theirs = named
+        .and_then(peel_to_commit)
+        .ok_or_else(|| format!("merge: {commit} - not something we can merge"))?;
+
+    // A signed tag is checked as well as the commit it points to
+    if let Some(tag) = named.filter(|&named| verify_signatures && is_tag(named)) {
+        let check = check_tag_signature(&config, tag)?;
+
+        if check.result != 'N' {
+            verify_signature("Tag", tag, &check, ctx)?;
+        }
+    }
+
+    let options = MergeOptions {
+        fast_forward,
+        squash,
+        messages,
+        autostash,
+        verify_signatures,
+        reflog_action: format!("merge {commit}"),
+    };
+
+    merge(&commit, theirs, &options, ctx)
+}
+
+/// Merges `theirs`, named `name` (eg. "topic"), into HEAD
+pub fn merge(name: &str, theirs: ObjectId, options: &MergeOptions, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load();
+
+    if git_path("MERGE_HEAD").exists() {
+        return Err("You have not concluded your merge (MERGE_HEAD exists).".into());
+    }
+
+    if options.verify_signatures {
+        verify_signature("Commit", theirs, &check_commit_signature(&config, theirs)?, ctx)?;
+    }
+
+    let head_branch = refs::head_branch();
+    let head = refs::resolve_ref("HEAD");
+
+    // Looked up first, so a missing identity doesn't leave the worktree
+    // updated without the commit or reflog entry to go with it
+    let committer = ident(IdentRole::Committer, &config).ok_or("unable to determine the committer")?;
+
+    let mut graph = Graph::new();
+
+    let diverged = match head {
+        Some(head) if graph.is_ancestor(theirs, head) => {
+            match options.squash {
+                true => println!("Already up to date. (nothing to squash)"),
+                false => println!("Already up to date."),
+            }
+
+            return Ok(());
+        }
+        Some(head) if !graph.is_ancestor(head, theirs) => {
+            if options.fast_forward == FastForward::Only {
+                return Err("Not possible to fast-forward, aborting.".into());
+            }
+
+            true
+        }
+        None if options.squash => return Err("Squash commit into empty head not supported yet".into()),
+        None if options.fast_forward == FastForward::Never => {
+            return Err("Non-fast-forward commit does not make sense into an empty head".into());
+        }
+        _ => false,
+    };
+
+    // The stash is put back however the merge goes, even if it fails
+    if options.autostash && head.is_some() {
+        stash_aside(AUTOSTASH)?;
+    }
+
+    let merge = Merge { name, theirs, head, head_branch, options, committer: committer.to_string() };
+
+    let result = match diverged {
+        true => update_diverged(&merge, &mut graph, &config, ctx),
+        false => update(&merge, &config, ctx),
+    };
+
+    put_back_stash(AUTOSTASH)?;
+
+    result
+}
+
+/// Stashes the local changes under `state` with `create_autostash`, if
+/// there are any, saying so
+pub fn stash_aside(state: &str) -> io::Result<()> {
+    if let Some(stash) = create_autostash(state)? {
+        println!("Created autostash: {}", &stash.to_string()[..7]);
+    }
+
+    Ok(())
+}
+
+/// Puts back what `stash_aside` stashed under `state`, saying how that went
+pub fn put_back_stash(state: &str) -> io::Result<()> {
+    match apply_autostash(state)? {
+        Some(AutostashOutcome::Applied) => eprintln!("Applied autostash."),
+        Some(AutostashOutcome::Stashed) => {
+            eprintln!("Applying autostash resulted in conflicts.");
+            eprintln!("Your changes are safe in the stash, refs/stash.");
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+fn is_tag(id: ObjectId) -> bool {
+    matches!(GitObjectStore::get(id).map(|object| object.data), Some(GitObjectData::Tag { .. }))
+}
+
+/// Refuses to go on unless `check` found a good signature on `id`, a
+/// "Commit" or "Tag", one from a trusted key
+fn verify_signature(what: &str, id: ObjectId, check: &SignatureCheck, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let abbrev = &id.to_string()[..7];
+    let signer = check.signer.as_deref().unwrap_or_default();
+
+    match check.result {
+        'G' => {
+            if !ctx.quiet {
+                println!("{what} {abbrev} has a good GPG signature by {signer}");
+            }
+
+            Ok(())
+        }
+        'U' => Err(format!("{what} {abbrev} has an untrusted GPG signature, allegedly by {signer}.").into()),
+        'B' => Err(format!("{what} {abbrev} has a bad GPG signature allegedly by {signer}.").into()),
+        _ => Err(format!("{what} {abbrev} does not have a GPG signature.").into()),
+    }
+}
+
+/// Carries out a merge found to be possible, updating the index and
+/// worktree and then HEAD
+fn update(merge: &Merge, config: &Config, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let &Merge { theirs, head, options, .. } = merge;
+    let MergeOptions { fast_forward, squash, .. } = *options;
+
+    let old_files = match head {
+        Some(head) => {
+            refs::write_ref("ORIG_HEAD", head, None)?;
+
+            if !ctx.quiet && fast_forward != FastForward::Never {
+                println!("Updating {}..{}", &head.to_string()[..7], &theirs.to_string()[..7]);
+            }
+
+            commit_files(head)?
+        }
+        None => TreeFiles::new(),
+    };
+
+    checkout_tree(&old_files, &commit_files(theirs)?, false).ok_or("merge aborted")?;
+
+    if squash {
+        let head = head.expect("unborn HEAD refused above");
+        fs::write(git_path("SQUASH_MSG"), squash_message(head, theirs)?)?;
+
+        if !ctx.quiet {
+            println!("Fast-forward");
+            println!("Squash commit -- not updating HEAD");
+        }
+
+        return Ok(());
+    }
+
+    let (new, how) = match fast_forward {
+        FastForward::Never => {
+            let tree = peel_to_tree(theirs).ok_or_else(|| format!("unable to read the tree of {theirs}"))?;
+
+            (commit_merge(merge, tree, config)?, "Merge made by the 'ort' strategy.")
+        }
+        _ if head.is_none() => (theirs, "initial pull"),
+        _ => (theirs, "Fast-forward"),
+    };
+
+    update_head(merge, new, how, ctx)
+}
+
+/// Carries out a merge of diverged histories, merging HEAD's tree with
+/// that of `theirs`. Paths that can't be merged are left conflicted in the
+/// index and worktree, and the merge stops for them to be resolved and
+/// committed.
+fn update_diverged(merge: &Merge, graph: &mut Graph, config: &Config, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let &Merge { name, theirs, head, ref head_branch, options, .. } = merge;
+    let head = head.expect("only a HEAD can have diverged");
+
+    refs::write_ref("ORIG_HEAD", head, None)?;
+
+    let tree_options = TreeMergeOptions {
+        ours_label: "HEAD",
+        theirs_label: name,
+        base_label: "",
+        style: ConflictStyle::from_config(config).ok_or("bad merge.conflictStyle")?,
+    };
+
+    let TreeMerge { files, conflicts, messages } = merge_commits(graph, head, theirs, &tree_options).ok_or("merge failed")?;
+
+    checkout_tree(&commit_files(head)?, &files, false).ok_or("merge aborted")?;
+
+    if !ctx.quiet {
+        for message in &messages {
+            println!("{message}");
+        }
+    }
+
+    if !conflicts.is_empty() {
+        let mut index = Index::read().ok_or("Unable to read index")?;
+        record_conflicts(&mut index, &conflicts);
+        index.write()?;
+
+        // A squash merge is concluded by committing, as any other changes
+        if options.squash {
+            fs::write(git_path("SQUASH_MSG"), squash_message(head, theirs)?)?;
+        } else {
+            let mut message = match options.messages.is_empty() {
+                true => merge_message(name, head_branch.as_deref()),
+                false => String::from_utf8_lossy(&stripspace(options.messages.join("\n\n").as_bytes(), None)).into_owned(),
+            };
+
+            message.push_str("\n# Conflicts:\n");
+
+            for conflict in &conflicts {
+                message.push_str(&format!("#\t{}\n", String::from_utf8_lossy(&conflict.path)));
+            }
+
+            fs::write(git_path("MERGE_HEAD"), format!("{theirs}\n"))?;
+            fs::write(git_path("MERGE_MSG"), message)?;
+            fs::write(git_path("MERGE_MODE"), "")?;
+        }
+
+        return Err("Automatic merge failed; fix conflicts and then commit the result.".into());
+    }
+
+    if options.squash {
+        fs::write(git_path("SQUASH_MSG"), squash_message(head, theirs)?)?;
+
+        if !ctx.quiet {
+            println!("Squash commit -- not updating HEAD");
+        }
+
+        return Ok(());
+    }
+
+    let mut tree = TreeBuilder::new();
+
+    for (path, &(mode, id)) in &files {
+        tree.insert(mode, path, id)
+            .ok_or_else(|| format!("unable to add {} to the merged tree", String::from_utf8_lossy(path)))?;
+    }
+
+    let tree = tree.write().ok_or("unable to write the merged tree")?;
+
+    update_head(merge, commit_merge(merge, tree, config)?, "Merge made by the 'ort' strategy.", ctx)
+}
+
+/// Makes the merge commit of HEAD and `theirs`, with `tree` as its tree
+fn commit_merge(merge: &Merge, tree: ObjectId, config: &Config) -> Result<ObjectId, Box<dyn std::error::Error>> {
+    let &Merge { name, theirs, head, ref head_branch, options, ref committer, .. } = merge;
+    let head = head.expect("unborn HEAD refused above");
+
+    let message = match options.messages.is_empty() {
+        true => merge_message(name, head_branch.as_deref()).into_bytes(),
+        false => stripspace(options.messages.join("\n\n").as_bytes(), None),
+    };
+
+    let author = ident(IdentRole::Author, config).ok_or("unable to determine the author")?;
+
+    let data = serialize_commit(&tree, &[head, theirs], &author.to_string(), committer, None, None, &message);
+
+    GitObjectStore::write("commit", &data).ok_or_else(|| "unable to write commit".into())
+}
+
+/// Moves HEAD, or the branch it's on, to `new`, logging `how`
+fn update_head(merge: &Merge, new: ObjectId, how: &str, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let &Merge { head, ref head_branch, options, ref committer, .. } = merge;
+    let reflog_action = &options.reflog_action;
+
+    // A detached HEAD is updated itself, otherwise the branch it's on
+    let target = head_branch.as_deref().unwrap_or("HEAD");
+
+    let mut transaction = RefTransaction::new();
+
+    transaction.update(target, new, Some(head.unwrap_or(ObjectId::NULL))).reflog = Some(ReflogEntry {
+        old: head.unwrap_or(ObjectId::NULL),
+        new,
+        committer: committer.clone(),
+        message: match head {
+            Some(_) => format!("{reflog_action}: {how}"),
+            None => how.to_string(),
+        },
+    });
+
+    transaction.commit()?;
+
+    if !ctx.quiet && head.is_some() {
+        println!("{how}");
+    }
+
+    Ok(())
+}
+
+/// The default message of a merge commit, eg. "Merge branch 'topic'",
+/// naming the branch merged into unless it's main or master
+fn merge_message(name: &str, head_branch: Option<&str>) -> String {
+    let refname = refs::expand_ref(name).unwrap_or_default();
+
+    let merged = if let Some(branch) = refname.strip_prefix("refs/heads/") {
+        format!("branch '{branch}'")
+    } else if let Some(tag) = refname.strip_prefix("refs/tags/") {
+        format!("tag '{tag}'")
+    } else if let Some(branch) = refname.strip_prefix("refs/remotes/") {
+        format!("remote-tracking branch '{branch}'")
+    } else {
+        format!("commit '{name}'")
+    };
+
+    match head_branch.map(refs::shorten_ref) {
+        Some("main" | "master") => format!("Merge {merged}\n"),
+        Some(branch) => format!("Merge {merged} into {branch}\n"),
+        None => format!("Merge {merged} into HEAD\n"),
+    }
+}
+
+/// SQUASH_MSG, the commits being squashed as `log` shows them, newest
+/// first
+fn squash_message(head: ObjectId, theirs: ObjectId) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut walk = RevWalk::new();
+    walk.push(theirs);
+    walk.hide(head);
+
+    let mut message = b"Squashed commit of the following:\n".to_vec();
+
+    for id in walk {
+        let commit = GitObjectStore::get(id).ok_or_else(|| format!("could not read commit {id}"))?;
+
+        message.push(b'\n');
+        message.extend(format_commit(&PrettyFormat::Medium, id, &commit.data, &PrettyOptions::default()));
+    }
+
+    Ok(message)
+}
@@ -0,0 +1,44 @@
+use std::io::Write;
+
+use gitty::graph::Graph;
+use gitty::revparse;
+use gitty::store::ObjectId;
+use crate::cli::MergeBaseArgs;
+use crate::context::Context;
+
+pub fn run(MergeBaseArgs { all, octopus, commits }: MergeBaseArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    if !octopus && commits.len() < 2 {
+        return Err("merge-base needs at least two commits".into());
+    }
+
+    let commits = commits.iter()
+        .map(|rev| {
+            revparse::resolve(rev)
+                .and_then(revparse::peel_to_commit)
+                .ok_or_else(|| format!("Not a valid object name {rev}"))
+        })
+        .collect::<Result<Vec<ObjectId>, String>>()?;
+
+    let mut graph = Graph::new();
+
+    let bases = match octopus {
+        true => graph.octopus_merge_bases(&commits),
+        false => graph.merge_bases_many(commits[0], &commits[1..]),
+    };
+
+    // As with git, having no common ancestor is a failure, not an error
+    if bases.is_empty() {
+        std::process::exit(1);
+    }
+
+    let shown = if all { bases.len() } else { 1 };
+    let mut stdout = std::io::stdout().lock();
+
+    for base in &bases[..shown] {
+        writeln!(stdout, "{base}")?;
+    }
+
+    Ok(())
+}
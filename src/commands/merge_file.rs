@@ -0,0 +1,76 @@
+use std::fs;
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::diff::is_binary;
+use gitty::merge::{merge, ConflictStyle, Favor, MergeOptions};
+use crate::cli::MergeFileArgs;
+use crate::context::Context;
+
+/// Merges the changes from `base` to `other` into `current`, exiting with
+/// the number of conflicts left, as git does
+pub fn run(MergeFileArgs { labels, stdout, diff3, zdiff3, ours, theirs, union, marker_size, current, base, other }: MergeFileArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    if labels.len() > 3 {
+        return Err("too many labels on the command line".into());
+    }
+
+    let style = match (diff3, zdiff3) {
+        (true, _) => ConflictStyle::Diff3,
+        (_, true) => ConflictStyle::ZealousDiff3,
+        _ => ConflictStyle::from_config(&Config::load()).ok_or("bad merge.conflictStyle")?,
+    };
+
+    let favor = match (ours, theirs, union) {
+        (true, _, _) => Some(Favor::Ours),
+        (_, true, _) => Some(Favor::Theirs),
+        (_, _, true) => Some(Favor::Union),
+        _ => None,
+    };
+
+    let mut contents = Vec::new();
+
+    for path in [&current, &base, &other] {
+        let data = fs::read(path).map_err(|err| format!("Could not stat {}: {err}", path.display()))?;
+
+        if is_binary(&data) {
+            return Err(format!("Cannot merge binary files: {}", path.display()).into());
+        }
+
+        contents.push(data);
+    }
+
+    // Sides without a label go by their file names
+    let names: Vec<String> = [&current, &base, &other].iter()
+        .enumerate()
+        .map(|(i, path)| labels.get(i).cloned().unwrap_or_else(|| path.display().to_string()))
+        .collect();
+
+    let options = MergeOptions {
+        style,
+        favor,
+        marker_size,
+        join_unreadable: true,
+        ours_label: Some(&names[0]),
+        base_label: Some(&names[1]),
+        theirs_label: Some(&names[2]),
+    };
+
+    let result = merge(&contents[1], &contents[0], &contents[2], &options);
+
+    match stdout {
+        true => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&result.data)?;
+            stdout.flush()?;
+        }
+        false => fs::write(&current, &result.data)?,
+    }
+
+    if result.conflicts > 0 {
+        std::process::exit(result.conflicts.min(127) as i32);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+pub mod cat_file;
+pub mod ls_files;
+pub mod show_branch;
+pub mod status;
+pub mod branch;
+pub mod tag;
+pub mod for_each_ref;
+pub mod reflog;
+pub mod init;
+pub mod write_tree;
+pub mod commit_tree;
+pub mod commit;
+pub mod fsck;
+pub mod add;
+pub mod switch;
+pub mod checkout;
+pub mod stripspace;
+pub mod verify_bundle;
+pub mod remote;
+pub mod show;
+pub mod log;
+pub mod rev_list;
+pub mod graph_export;
+pub mod check_ignore;
+pub mod upload_pack;
+pub mod receive_pack;
+pub mod config;
+pub mod zstd_store;
+pub mod update_ref;
+pub mod annotate;
+pub mod symbolic_ref;
+pub mod merge;
+pub mod verify_pack;
+pub mod count_objects;
+pub mod pull;
+pub mod submodule;
+pub mod hash_object;
+pub mod merge_base;
+pub mod merge_file;
+pub mod ls_tree;
+pub mod worktree;
+pub mod check_mailmap;
+pub mod blame;
+pub mod grep;
+pub mod archive;
+pub mod check_attr;
+pub mod diff;
+pub mod shortlog;
+pub mod fetch;
+pub mod stash;
+pub mod clone;
+pub mod restore;
+pub mod ls_remote;
+pub mod push;
@@ -0,0 +1,294 @@
+use std::fs;
+
+use gitty::checkout::checkout_tree;
+use gitty::config::Config;
+use gitty::graph::Graph;
+use gitty::ident::{ident, IdentRole};
+use gitty::refs::{self, RefTransaction};
+use gitty::reflog::ReflogEntry;
+use gitty::repo::git_path;
+use gitty::revparse::peel_to_commit;
+use gitty::revwalk::RevWalk;
+use gitty::sequencer::{self, Action, Progress, Sequencer};
+use gitty::store::ObjectId;
+use crate::cli::PullArgs;
+use crate::commands::fetch;
+use crate::commands::merge::{merge, put_back_stash, stash_aside, FastForward, MergeOptions};
+use crate::commands::switch::commit_files;
+use crate::context::Context;
+
+/// Where a rebasing pull's autostash is kept while it's rebasing
+const AUTOSTASH: &str = "REBASE_AUTOSTASH";
+
+/// Where a pull comes from
+struct Source {
+    remote: String,
+
+    /// The ref to fetch and merge, eg. "refs/heads/main"
+    refname: String,
+}
+
+pub fn run(PullArgs { rebase, no_rebase, ff, no_ff, ff_only, autostash, no_autostash, verify_signatures, no_verify_signatures, repository, refspec }: PullArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let config = Config::load();
+    let head_branch = refs::head_branch();
+
+    let source = source(&config, head_branch.as_deref(), repository.as_deref(), refspec.as_deref())?;
+    let theirs = fetch(&source, refspec.as_deref(), ctx)?;
+
+    // Rebasing is asked for with a boolean or how to rebase, eg. "merges"
+    let rebase_value = |key: &str| config.get(key).map(|_| config.get_bool(key) != Some(false));
+
+    let rebase = match (rebase, no_rebase) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => head_branch.as_deref()
+            .map(refs::shorten_ref)
+            .and_then(|branch| rebase_value(&format!("branch.{branch}.rebase")))
+            .or_else(|| rebase_value("pull.rebase")),
+    };
+
+    let fast_forward = match (ff, no_ff, ff_only) {
+        (true, _, _) => Some(FastForward::Allow),
+        (_, true, _) => Some(FastForward::Never),
+        (_, _, true) => Some(FastForward::Only),
+        _ => FastForward::configured(&config, "pull.ff"),
+    };
+
+    let mut graph = Graph::new();
+
+    let diverged = refs::resolve_ref("HEAD")
+        .is_some_and(|head| !graph.is_ancestor(theirs, head) && !graph.is_ancestor(head, theirs));
+
+    if diverged {
+        match (rebase, fast_forward) {
+            (_, Some(FastForward::Only)) => return Err("Not possible to fast-forward, aborting.".into()),
+            (None, None) => {
+                print_divergent_advice();
+                return Err("Need to specify how to reconcile divergent branches.".into());
+            }
+            _ => {}
+        }
+    }
+
+    let autostash = match (autostash, no_autostash) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => match rebase {
+            Some(true) => config.get_bool("rebase.autoStash"),
+            _ => config.get_bool("merge.autoStash"),
+        }.unwrap_or(false),
+    };
+
+    if diverged && rebase == Some(true) {
+        return rebase_onto(theirs, autostash, &reflog_action(repository.as_deref(), refspec.as_deref()));
+    }
+
+    let verify_signatures = match (verify_signatures, no_verify_signatures) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => config.get_bool("merge.verifySignatures").unwrap_or(false),
+    };
+
+    let options = MergeOptions {
+        fast_forward: fast_forward
+            .or_else(|| FastForward::configured(&config, "merge.ff"))
+            .unwrap_or(FastForward::Allow),
+        squash: false,
+        messages: Vec::new(),
+        autostash,
+        verify_signatures,
+        reflog_action: reflog_action(repository.as_deref(), refspec.as_deref()),
+    };
+
+    let name = refs::shorten_ref(&source.refname).to_string();
+
+    merge(&name, theirs, &options, ctx)
+}
+
+/// Rebases HEAD's commits which `onto` doesn't have onto it, as `pull
+/// --rebase` does: HEAD moves to `onto`, then the sequencer picks the
+/// commits on top of it, oldest first, merges left out. Nothing carries
+/// on a rebase which stops at conflicts yet, so one that does is undone.
+fn rebase_onto(onto: ObjectId, autostash: bool, reflog_action: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let head = refs::resolve_ref("HEAD").ok_or("unable to resolve HEAD")?;
+    let target = refs::head_branch().unwrap_or_else(|| "HEAD".to_string());
+
+    let mut graph = Graph::new();
+    let mut walk = RevWalk::new();
+    walk.push(head);
+    walk.hide(onto);
+
+    let mut commits: Vec<ObjectId> = walk.filter(|&commit| graph.parents(commit).len() < 2).collect();
+    commits.reverse();
+
+    let todo = sequencer::todo(Action::Pick, &commits).ok_or("unable to read the commits to rebase")?;
+    let committer = ident(IdentRole::Committer, &Config::load()).ok_or("unable to determine the committer")?;
+
+    if autostash {
+        stash_aside(AUTOSTASH)?;
+    }
+
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let mut sequencer = Sequencer::start(todo, &format!("{reflog_action} (pick)"))?;
+
+        checkout_tree(&commit_files(head)?, &commit_files(onto)?, false).ok_or("rebase aborted")?;
+
+        let mut transaction = RefTransaction::new();
+
+        transaction.update(&target, onto, Some(head)).reflog = Some(ReflogEntry {
+            old: head,
+            new: onto,
+            committer: committer.to_string(),
+            message: format!("{reflog_action} (start): checkout {onto}"),
+        });
+
+        transaction.commit()?;
+
+        sequencer.last_head = onto;
+        sequencer.save()?;
+
+        match sequencer.run() {
+            Ok(Progress::Done) => {
+                eprintln!("Successfully rebased and updated {target}.");
+                Ok(())
+            }
+            Ok(Progress::Stopped { instruction, conflicts }) => {
+                for path in &conflicts {
+                    eprintln!("CONFLICT (content): Merge conflict in {}", String::from_utf8_lossy(path));
+                }
+
+                sequencer.abort()?;
+
+                Err(format!("could not apply {}... {}, so the rebase was undone", &instruction.commit.to_string()[..7], instruction.subject).into())
+            }
+            Err(err) => {
+                sequencer.abort()?;
+                Err(err.into())
+            }
+        }
+    })();
+
+    if autostash {
+        put_back_stash(AUTOSTASH)?;
+    }
+
+    result
+}
+
+/// Where to pull from: the remote and ref given, or the current branch's
+/// upstream, `branch.<name>.remote` and `branch.<name>.merge`
+fn source(config: &Config, head_branch: Option<&str>, repository: Option<&str>, refspec: Option<&str>)
+    -> Result<Source, Box<dyn std::error::Error>>
+{
+    let branch = head_branch.map(refs::shorten_ref);
+
+    let upstream_remote = branch
+        .and_then(|branch| config.get(&format!("branch.{branch}.remote")))
+        .unwrap_or("origin");
+
+    let upstream_ref = branch.and_then(|branch| config.get(&format!("branch.{branch}.merge")));
+
+    let remote = repository.unwrap_or(upstream_remote).to_string();
+
+    // Only a refspec's source matters, its destination is where fetch
+    // would store it
+    if let Some(refspec) = refspec {
+        let src = refspec.trim_start_matches('+').split(':').next().unwrap_or_default();
+
+        let refname = match src.starts_with("refs/") {
+            true => src.to_string(),
+            false => format!("refs/heads/{src}"),
+        };
+
+        return Ok(Source { remote, refname });
+    }
+
+    match upstream_ref {
+        Some(refname) if remote == upstream_remote => Ok(Source { remote, refname: refname.to_string() }),
+        _ if repository.is_some() => {
+            eprintln!("You asked to pull from the remote '{remote}', but did not specify");
+            eprintln!("a branch. Because this is not the default configured remote");
+            eprintln!("for your current branch, you must specify a branch on the command line.");
+
+            Err("no branch to pull".into())
+        }
+        _ => {
+            print_no_upstream_advice(branch);
+            Err("no branch to pull".into())
+        }
+    }
+}
+
+/// Fetches what `source` names, recording it in FETCH_HEAD, as `fetch`
+/// does with `refspec` if given, otherwise with the remote's configured
+/// refspecs. The remote ".", the repository itself, is only looked in.
+fn fetch(source: &Source, refspec: Option<&str>, ctx: &Context) -> Result<ObjectId, Box<dyn std::error::Error>> {
+    if source.remote != "." {
+        let refspecs: Vec<String> = refspec.into_iter().map(str::to_string).collect();
+        let mappings = fetch::fetch(&source.remote, &refspecs, ctx)?;
+
+        return mappings.iter()
+            .find(|mapping| mapping.src == source.refname)
+            .and_then(|mapping| peel_to_commit(mapping.id))
+            .ok_or_else(|| format!("couldn't find remote ref {}", source.refname).into());
+    }
+
+    let theirs = refs::resolve_ref(&source.refname)
+        .and_then(peel_to_commit)
+        .ok_or_else(|| format!("couldn't find remote ref {}", source.refname))?;
+
+    let description = match source.refname.strip_prefix("refs/heads/") {
+        Some(branch) => format!("branch '{branch}' of ."),
+        None => format!("'{}' of .", source.refname),
+    };
+
+    fs::write(git_path("FETCH_HEAD"), format!("{theirs}\t\t{description}\n"))?;
+
+    Ok(theirs)
+}
+
+/// What the reflog says made the update, "pull" followed by what was
+/// asked for, if anything
+fn reflog_action(repository: Option<&str>, refspec: Option<&str>) -> String {
+    [Some("pull"), repository, refspec].into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn print_no_upstream_advice(branch: Option<&str>) {
+    let Some(branch) = branch else {
+        eprintln!("You are not currently on a branch.");
+        eprintln!("Please specify which branch you want to merge with.");
+        eprintln!();
+        eprintln!("    gitty pull <remote> <branch>");
+        eprintln!();
+        return;
+    };
+
+    eprintln!("There is no tracking information for the current branch.");
+    eprintln!("Please specify which branch you want to merge with.");
+    eprintln!();
+    eprintln!("    gitty pull <remote> <branch>");
+    eprintln!();
+    eprintln!("If you wish to set tracking information for this branch you can do so with:");
+    eprintln!();
+    eprintln!("    git config branch.{branch}.remote <remote>");
+    eprintln!("    git config branch.{branch}.merge refs/heads/<branch>");
+    eprintln!();
+}
+
+fn print_divergent_advice() {
+    eprintln!("hint: You have divergent branches and need to specify how to reconcile them.");
+    eprintln!("hint: You can do so by running one of the following commands sometime before");
+    eprintln!("hint: your next pull:");
+    eprintln!("hint:");
+    eprintln!("hint:   git config pull.rebase false  # merge");
+    eprintln!("hint:   git config pull.rebase true   # rebase");
+    eprintln!("hint:   git config pull.ff only       # fast-forward only");
+    eprintln!("hint:");
+    eprintln!("hint: You can also pass --rebase, --no-rebase, or --ff-only on the command");
+    eprintln!("hint: line to override the configured default per invocation.");
+}
@@ -0,0 +1,407 @@
+use gitty::config::Config;
+use gitty::graph::Graph;
+use gitty::ident::reflog_ident;
+use gitty::protocol::fetch::{is_url, Connection};
+use gitty::protocol::local::local_git_dir;
+use gitty::protocol::push::PushUpdate;
+use gitty::push_cert::{anonymize_url, PushCertificate};
+use gitty::refs::{self, refname_match, RefTransaction};
+use gitty::reflog::{self, ReflogEntry};
+use gitty::refspec::{RefSpecKind, RefSpecs};
+use gitty::remote::{rewrite_url, Remote};
+use gitty::revparse::{self, peel_to_commit};
+use gitty::store::{GitObjectData, GitObjectStore, ObjectId};
+use crate::cli::PushArgs;
+use crate::context::Context;
+
+/// How wide the first column of the ref updates is, enough for two
+/// abbreviated ids and the "..." between them
+const SUMMARY_WIDTH: usize = 17;
+
+/// A ref to push, and what became of it
+struct Update {
+    /// The source as shown, eg. "main" or "HEAD", empty for a deletion
+    src: String,
+
+    /// The remote ref, in full
+    dst: String,
+    old: Option<ObjectId>,
+
+    /// The null id to delete `dst`
+    new: ObjectId,
+    force: bool,
+
+    /// The flag shown before the summary: '*' for something new, ' ' for
+    /// a fast-forward, '+' for a forced update, '-' for a deletion, '!'
+    /// for a rejection and '=' for nothing to do
+    flag: char,
+    summary: String,
+    note: Option<String>,
+}
+
+impl Update {
+    /// Whether the remote is asked to make the update
+    fn is_sent(&self) -> bool {
+        matches!(self.flag, '*' | ' ' | '+' | '-')
+    }
+}
+
+pub fn run(PushArgs { force, dry_run, signed, repository, refspecs }: PushArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load();
+
+    // The current branch's remote, failing that origin
+    let repository = repository.unwrap_or_else(|| {
+        refs::head_branch()
+            .and_then(|branch| config.get(&format!("branch.{}.remote", refs::shorten_ref(&branch))).map(str::to_string))
+            .unwrap_or_else(|| "origin".to_string())
+    });
+
+    let remote = Remote::get(&config, &repository);
+
+    let urls = match &remote {
+        Some(remote) => remote.push_urls().to_vec(),
+        None => vec![rewrite_url(&config, &repository)],
+    };
+
+    // Without refspecs a remote's configured ones are used, else the
+    // current branch is pushed to the branch of the same name
+    let specs = match &remote {
+        Some(remote) if refspecs.is_empty() => RefSpecs::for_remote(&config, &remote.name, RefSpecKind::Push),
+        _ => RefSpecs::parse(&refspecs, RefSpecKind::Push),
+    };
+
+    let mut specs = specs.ok_or("invalid refspec")?;
+
+    if specs.iter().next().is_none() {
+        let branch = refs::head_branch().ok_or("You are not currently on a branch.")?;
+        specs = RefSpecs::parse(&[branch], RefSpecKind::Push).ok_or("invalid refspec")?;
+    }
+
+    let mut failed = Vec::new();
+
+    for url in urls {
+        if !is_url(&url) && local_git_dir(&url).is_none() {
+            return Err(format!("'{url}' does not appear to be a git repository").into());
+        }
+
+        let shown_url = anonymize_url(&url);
+
+        if ctx.verbose {
+            eprintln!("Pushing to {shown_url}");
+        }
+
+        let connection = Connection::connect_for_push(&url).map_err(|err| err.to_string())?;
+
+        let remote_refs: Vec<(String, ObjectId)> = connection.advertisement.refs.iter()
+            .filter(|(name, _)| name.starts_with("refs/"))
+            .cloned()
+            .collect();
+
+        let mut updates = select(&specs, &remote_refs, force)?;
+        let mut graph = Graph::new();
+
+        for update in &mut updates {
+            check(update, &mut graph);
+        }
+
+        let sent: Vec<PushUpdate> = updates.iter()
+            .filter(|update| update.is_sent())
+            .map(|update| PushUpdate { name: update.dst.clone(), old: update.old.unwrap_or(ObjectId::NULL), new: update.new })
+            .collect();
+
+        if !dry_run && !sent.is_empty() {
+            let certificate = match signed {
+                true => Some(sign_push(&config, &connection, &url, &sent)?),
+                false => None,
+            };
+
+            let report = connection.send_pack(&sent, certificate.as_ref(), ctx.progress).map_err(|err| err.to_string())?;
+
+            if let Some(error) = &report.unpack_error {
+                eprintln!("error: remote unpack failed: {error}");
+            }
+
+            for update in updates.iter_mut().filter(|update| update.is_sent()) {
+                let reason = match &report.unpack_error {
+                    Some(_) => Some("unpacker error".to_string()),
+                    None => report.rejected.iter().find(|(name, _)| *name == update.dst).map(|(_, reason)| reason.clone()),
+                };
+
+                if let Some(reason) = reason {
+                    update.flag = '!';
+                    update.summary = "[remote rejected]".to_string();
+                    update.note = Some(reason);
+                }
+            }
+
+            if let Some(remote) = &remote {
+                update_tracking_refs(&config, &remote.name, &updates)?;
+            }
+        }
+
+        if !ctx.quiet {
+            print_updates(&updates, &shown_url, ctx.verbose);
+        }
+
+        if updates.iter().any(|update| update.flag == '!') {
+            failed.push(shown_url);
+        } else if !ctx.quiet && !updates.iter().any(Update::is_sent) {
+            eprintln!("Everything up-to-date");
+        }
+    }
+
+    match failed.first() {
+        Some(url) => Err(format!("failed to push some refs to '{url}'").into()),
+        None => Ok(()),
+    }
+}
+
+/// A signed certificate of the updates, over the nonce the remote gave
+/// with its push-cert capability
+fn sign_push(config: &Config, connection: &Connection, url: &str, updates: &[PushUpdate]) -> Result<PushCertificate, Box<dyn std::error::Error>> {
+    let nonce = connection.advertisement.capability("push-cert").ok_or("the receiving end does not support --signed push")?;
+
+    let commands = updates.iter().map(|update| (update.old, update.new, update.name.clone())).collect();
+
+    let mut certificate = PushCertificate::new(config, url, Some(nonce).filter(|nonce| !nonce.is_empty()), commands)
+        .ok_or("unable to determine the pusher")?;
+
+    certificate.sign(config)?;
+
+    Ok(certificate)
+}
+
+/// The refs the refspecs select to push, and where to. A source may be
+/// any revision, with a destination given unless it's a ref.
+fn select(specs: &RefSpecs, remote_refs: &[(String, ObjectId)], force: bool) -> Result<Vec<Update>, Box<dyn std::error::Error>> {
+    let local_refs = refs::list_refs("refs/");
+    let mut updates: Vec<Update> = Vec::new();
+
+    let mut add = |src: String, dst: String, new: ObjectId, spec_force: bool| -> Result<(), String> {
+        match updates.iter().find(|update| update.dst == dst) {
+            Some(update) if update.new == new => return Ok(()),
+            Some(update) => return Err(format!("multiple updates for ref '{dst}' not allowed, from '{}' and '{src}'", update.src)),
+            None => {}
+        }
+
+        let old = remote_refs.iter().find(|(name, _)| *name == dst).map(|(_, id)| *id);
+
+        updates.push(Update {
+            src,
+            dst,
+            old,
+            new,
+            force: force || spec_force,
+            flag: ' ',
+            summary: String::new(),
+            note: None,
+        });
+
+        Ok(())
+    };
+
+    for spec in specs.iter().filter(|spec| !spec.negative) {
+        if spec.pattern {
+            for (name, id) in &local_refs {
+                if !spec.matches(name) || specs.is_excluded(name) {
+                    continue;
+                }
+
+                let dst = spec.dst_for(name).unwrap_or_else(|| name.clone());
+                add(refs::shorten_ref(name).to_string(), dst, *id, spec.force)?;
+            }
+
+            continue;
+        }
+
+        // ":<dst>" deletes dst, which has to be there
+        if spec.src.is_empty() {
+            let dst = spec.dst.as_deref().unwrap_or_default();
+
+            let dst = remote_dst(dst, None, ObjectId::NULL, remote_refs)
+                .map_err(|_| format!("unable to delete '{dst}': remote ref does not exist"))?;
+
+            add(String::new(), dst, ObjectId::NULL, spec.force)?;
+            continue;
+        }
+
+        let local = local_refs.iter()
+            .filter_map(|(name, id)| Some((refname_match(&spec.src, name)?, name, *id)))
+            .min_by_key(|(rank, _, _)| *rank);
+
+        // What the source is as a ref, which gives the destination if
+        // there's none, and how it's shown
+        let (shown, src_ref, new) = match local {
+            Some((_, name, id)) => (refs::shorten_ref(name).to_string(), Some(name.clone()), id),
+            None if spec.src == "HEAD" => {
+                let head = refs::resolve_ref("HEAD").ok_or("src refspec HEAD does not match any")?;
+                ("HEAD".to_string(), refs::head_branch(), head)
+            }
+            None => {
+                let id = revparse::resolve(&spec.src).ok_or_else(|| format!("src refspec {} does not match any", spec.src))?;
+                (spec.src.clone(), None, id)
+            }
+        };
+
+        if src_ref.as_ref().is_some_and(|name| specs.is_excluded(name)) {
+            continue;
+        }
+
+        let dst = match &spec.dst {
+            Some(dst) => remote_dst(dst, src_ref.as_deref(), new, remote_refs)?,
+            None => src_ref.ok_or_else(|| format!("the source '{}' isn't a ref, so needs a destination, as <src>:<dst>", spec.src))?,
+        };
+
+        add(shown, dst, new, spec.force)?;
+    }
+
+    Ok(updates)
+}
+
+/// The remote ref `dst` names: as it is if it's a full ref name, else the
+/// remote ref it abbreviates, else a ref of the same kind as the source,
+/// or failing that of what `new` is, a branch for a commit and a tag for a
+/// tag
+fn remote_dst(dst: &str, src_ref: Option<&str>, new: ObjectId, remote_refs: &[(String, ObjectId)]) -> Result<String, String> {
+    if dst.starts_with("refs/") {
+        return Ok(dst.to_string());
+    }
+
+    let existing = remote_refs.iter()
+        .filter_map(|(name, _)| Some((refname_match(dst, name)?, name)))
+        .min_by_key(|(rank, _)| *rank);
+
+    if let Some((_, name)) = existing {
+        return Ok(name.clone());
+    }
+
+    let prefix = match src_ref {
+        Some(src) if src.starts_with("refs/heads/") => Some("refs/heads/"),
+        Some(src) if src.starts_with("refs/tags/") => Some("refs/tags/"),
+        _ => match GitObjectStore::get(new).map(|object| object.data) {
+            Some(GitObjectData::Commit { .. }) => Some("refs/heads/"),
+            Some(GitObjectData::Tag { .. }) => Some("refs/tags/"),
+            _ => None,
+        },
+    };
+
+    match prefix {
+        Some(prefix) if !new.is_null() => Ok(format!("{prefix}{dst}")),
+        _ => Err(format!("the destination '{dst}' isn't a full ref name, starting with \"refs/\", or a ref the remote has")),
+    }
+}
+
+/// Decides what's done about the update: a branch has to be
+/// fast-forwarded and a tag can't be moved, unless the update is forced,
+/// and a deletion needs something to delete
+fn check(update: &mut Update, graph: &mut Graph) {
+    let (flag, summary, note) = match update.old {
+        Some(old) if old == update.new => ('=', "[up to date]".to_string(), None),
+        None if update.new.is_null() => ('!', "[rejected]".to_string(), Some("remote ref does not exist")),
+        None => {
+            let summary = match update.dst.as_str() {
+                dst if dst.starts_with("refs/tags/") => "[new tag]",
+                dst if dst.starts_with("refs/heads/") => "[new branch]",
+                _ => "[new reference]",
+            };
+
+            ('*', summary.to_string(), None)
+        }
+        Some(_) if update.new.is_null() => ('-', "[deleted]".to_string(), None),
+        Some(_) if update.dst.starts_with("refs/tags/") && !update.force => {
+            ('!', "[rejected]".to_string(), Some("already exists"))
+        }
+        Some(old) => {
+            let fast_forward = GitObjectStore::exists(old)
+                && peel_to_commit(update.new).is_some_and(|new| graph.is_ancestor(old, new));
+
+            match fast_forward {
+                true => (' ', format!("{}..{}", abbrev(old), abbrev(update.new)), None),
+                false if update.force => ('+', format!("{}...{}", abbrev(old), abbrev(update.new)), Some("forced update")),
+                // Without the remote's commit we can't tell, so it has to
+                // be fetched first
+                false if !GitObjectStore::exists(old) => ('!', "[rejected]".to_string(), Some("fetch first")),
+                false => ('!', "[rejected]".to_string(), Some("non-fast-forward")),
+            }
+        }
+    };
+
+    update.flag = flag;
+    update.summary = summary;
+    update.note = note.map(str::to_string);
+}
+
+/// Moves the remote-tracking refs the remote's fetch refspecs map the
+/// pushed refs to, as a fetch straight after would
+fn update_tracking_refs(config: &Config, remote: &str, updates: &[Update]) -> Result<(), Box<dyn std::error::Error>> {
+    let specs = RefSpecs::for_remote(config, remote, RefSpecKind::Fetch).ok_or("invalid refspec")?;
+    let mut transaction = RefTransaction::new();
+
+    for update in updates.iter().filter(|update| update.is_sent()) {
+        if specs.is_excluded(&update.dst) {
+            continue;
+        }
+
+        let Some(tracking) = specs.iter().filter(|spec| !spec.negative).find_map(|spec| spec.dst_for(&update.dst)) else {
+            continue;
+        };
+
+        let old = refs::resolve_ref(&tracking);
+
+        if update.new.is_null() {
+            if old.is_some() {
+                transaction.delete(&tracking, None);
+            }
+
+            continue;
+        }
+
+        let entry = transaction.update(&tracking, update.new, None);
+
+        if reflog::should_log(config, &tracking) {
+            entry.reflog = Some(ReflogEntry {
+                old: old.unwrap_or(ObjectId::NULL),
+                new: update.new,
+                committer: reflog_ident(config).to_string(),
+                message: "update by push".to_string(),
+            });
+        }
+    }
+
+    transaction.commit().map_err(|err| format!("unable to update remote-tracking refs: {err}").into())
+}
+
+/// Shows the updates as git does, eg.
+///
+///   To <url>
+///      1a2b3c4..5d6e7f8  main -> main
+///    * [new branch]      topic -> topic
+///
+/// Refs already up to date are only shown when `verbose`, first, then
+/// those updated and then those rejected, eg. "! [rejected]" with why.
+fn print_updates(updates: &[Update], url: &str, verbose: bool) {
+    let shown: Vec<&Update> = [updates.iter().filter(|update| verbose && update.flag == '=').collect::<Vec<_>>(),
+        updates.iter().filter(|update| update.is_sent()).collect(),
+        updates.iter().filter(|update| update.flag == '!').collect()]
+        .concat();
+
+    if shown.is_empty() {
+        return;
+    }
+
+    eprintln!("To {url}");
+
+    for update in shown {
+        let note = update.note.as_ref().map(|note| format!(" ({note})")).unwrap_or_default();
+        let dst = refs::shorten_ref(&update.dst);
+
+        match update.src.is_empty() {
+            true => eprintln!(" {} {:<SUMMARY_WIDTH$} {dst}{note}", update.flag, update.summary),
+            false => eprintln!(" {} {:<SUMMARY_WIDTH$} {} -> {dst}{note}", update.flag, update.summary, update.src),
+        }
+    }
+}
+
+fn abbrev(id: ObjectId) -> String {
+    id.to_string()[..7].to_string()
+}
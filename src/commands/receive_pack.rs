@@ -0,0 +1,372 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::os::fd::AsFd;
+use std::process::{Command, Stdio};
+
+use gitty::advertise::{advertise_refs, push_cert_path, Service};
+use gitty::config::Config;
+use gitty::fsck::{check_object, FsckOptions, FsckSeverity};
+use gitty::graph::Graph;
+use gitty::ident::reflog_ident;
+use gitty::metrics;
+use gitty::protocol::pktline::{Packet, PacketReader, PacketWriter};
+use gitty::push_cert::{CertCheck, PushCertificate};
+use gitty::refs::{self, RefTransaction};
+use gitty::reflog::{self, ReflogEntry};
+use gitty::repo::git_path;
+use gitty::revparse::peel_to_commit;
+use gitty::store::index_pack::store_pack;
+use gitty::store::util::visit_pack_ids;
+use gitty::store::{GitObjectStore, ObjectId};
+use crate::cli::ServeArgs;
+use crate::commands::upload_pack::{enter_repository, export_metrics};
+use crate::context::Context;
+
+/// An update the pusher asked for, and why it wasn't made if it wasn't
+struct PushCommand {
+    old: ObjectId,
+    new: ObjectId,
+    name: String,
+    error: Option<String>,
+}
+
+/// What the pusher sent before its pack
+struct Request {
+    commands: Vec<PushCommand>,
+    capabilities: Vec<String>,
+
+    /// The push certificate of a signed push, as sent
+    certificate: Option<Vec<u8>>,
+}
+
+pub fn run(ServeArgs { advertise_refs: only_advertise, directory }: ServeArgs, _ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    enter_repository(&directory)?;
+
+    let config = Config::load();
+
+    let mut stdout = io::stdout().lock();
+    let nonce = advertise_refs(&mut stdout, Service::ReceivePack, &config)?;
+    stdout.flush()?;
+
+    if !only_advertise {
+        receive(&config, nonce.as_deref(), &mut stdout)?;
+    }
+
+    export_metrics(&config);
+
+    Ok(())
+}
+
+/// Serves a push after the advertisement: reads the commands, and the
+/// pack unless they're all deletions, then makes the updates the
+/// pre-receive hook and the checks allow and reports on them
+fn receive(config: &Config, nonce: Option<&str>, out: &mut impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let mut input = BufReader::new(io::stdin().lock());
+
+    // Having nothing to push, the pusher just hangs up
+    let Some(Request { mut commands, capabilities, certificate }) = read_request(&mut PacketReader::new(&mut input))? else {
+        return Ok(());
+    };
+
+    let unpack_error = match commands.iter().any(|command| !command.new.is_null()) {
+        true => match store_pack(&mut input) {
+            Ok(name) => {
+                // The pack's in, whether or not it can be counted
+                let _ = count_pack(&name);
+
+                let fsck = config.get_bool("receive.fsckObjects").or_else(|| config.get_bool("transfer.fsckObjects"));
+
+                match fsck.unwrap_or(false) {
+                    true => check_pack(config, &name).err(),
+                    false => None,
+                }
+            }
+            Err(err) => Some(err.to_string()),
+        },
+        false => None,
+    };
+
+    let mut hook_env = Vec::new();
+
+    if let Some(certificate) = &certificate {
+        let check = CertCheck::new(certificate, config, nonce, &push_cert_path(), false)?;
+        hook_env = check.hook_env();
+    }
+
+    match &unpack_error {
+        Some(_) => {
+            for command in &mut commands {
+                command.error = Some("unpacker error".to_string());
+            }
+        }
+        None => {
+            let declined = !run_hook("pre-receive", &commands.iter().collect::<Vec<_>>(), &hook_env)?;
+
+            for command in &mut commands {
+                command.error = match declined {
+                    true => Some("pre-receive hook declined".to_string()),
+                    false => update(config, command).err(),
+                };
+            }
+
+            let updated: Vec<&PushCommand> = commands.iter().filter(|command| command.error.is_none()).collect();
+
+            if !updated.is_empty() {
+                run_hook("post-receive", &updated, &hook_env)?;
+            }
+        }
+    }
+
+    if capabilities.iter().any(|capability| capability == "report-status") {
+        let mut report = PacketWriter::new(out);
+
+        report.write_line(&format!("unpack {}", unpack_error.as_deref().unwrap_or("ok")))?;
+
+        for command in &commands {
+            match &command.error {
+                Some(error) => report.write_line(&format!("ng {} {error}", command.name))?,
+                None => report.write_line(&format!("ok {}", command.name))?,
+            }
+        }
+
+        report.write_flush()?;
+        report.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Counts the pack stored as `name`, its size and how many objects it has
+fn count_pack(name: &str) -> io::Result<()> {
+    let mut header = [0u8; 12];
+    let mut pack = File::open(git_path(format!("objects/pack/{name}.pack")))?;
+    pack.read_exact(&mut header)?;
+
+    let objects = u32::from_be_bytes(header[8..].try_into().expect("four bytes"));
+
+    metrics::count("gitty_received_packs_total", &[], 1);
+    metrics::count("gitty_received_objects_total", &[], objects.into());
+    metrics::count("gitty_received_pack_bytes_total", &[], pack.metadata()?.len());
+
+    Ok(())
+}
+
+/// Checks the objects of the pack stored as `name` as fsck does, as
+/// `receive.fsck.<msg-id>` and `receive.fsck.skipList` tune it, giving the
+/// first error. A pack with errors is removed, so none of it's kept.
+fn check_pack(config: &Config, name: &str) -> Result<(), String> {
+    let options = FsckOptions::for_receive(config);
+    let pack = git_path(format!("objects/pack/{name}"));
+
+    let mut ids = Vec::new();
+
+    visit_pack_ids(true, |desc| {
+        if desc.pack_name.as_deref() == Some(name) {
+            ids.push(desc.oid);
+        }
+    });
+
+    let mut error = None;
+
+    for (&id, object) in ids.iter().zip(GitObjectStore::get_many(&ids)) {
+        let Some(object) = object else {
+            error.get_or_insert_with(|| format!("unable to read {id}"));
+            continue;
+        };
+
+        for message in check_object(&object, &options) {
+            eprintln!("{message}");
+
+            if message.severity == FsckSeverity::Error {
+                error.get_or_insert_with(|| message.to_string());
+            }
+        }
+    }
+
+    let Some(error) = error else {
+        return Ok(());
+    };
+
+    for extension in ["pack", "idx"] {
+        let _ = fs::remove_file(pack.with_extension(extension));
+    }
+
+    Err(error)
+}
+
+/// Reads the commands up to the flush-pkt, with the capabilities after a
+/// NUL on the first:
+///
+///   <old> SP <new> SP <name> [NUL <capabilities>]
+///   ...
+///
+/// or for a signed push the certificate listing them, between a
+/// "push-cert" line with the capabilities and a "push-cert-end" one. None
+/// if the pusher sent nothing.
+fn read_request<R: Read>(packets: &mut PacketReader<R>) -> io::Result<Option<Request>> {
+    let protocol_error = |what: &str| io::Error::other(format!("protocol error: {what}"));
+
+    let mut request = Request { commands: Vec::new(), capabilities: Vec::new(), certificate: None };
+
+    loop {
+        let line = match packets.read_packet()? {
+            None | Some(Packet::Flush) => break,
+            Some(Packet::Data(line)) => line,
+            Some(_) => return Err(protocol_error("expected a command")),
+        };
+
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+
+        let line = match line.iter().position(|&byte| byte == 0) {
+            Some(nul) if request.commands.is_empty() && request.certificate.is_none() => {
+                request.capabilities = String::from_utf8_lossy(&line[nul + 1..]).split(' ').map(str::to_string).collect();
+                &line[..nul]
+            }
+            _ => line,
+        };
+
+        // Shallow clones say which of their commits have no parents, which
+        // doesn't matter here
+        if line.starts_with(b"shallow ") {
+            continue;
+        }
+
+        if line == b"push-cert" {
+            let mut certificate = Vec::new();
+
+            loop {
+                match packets.read_packet()? {
+                    Some(Packet::Data(line)) if line.strip_suffix(b"\n").unwrap_or(&line) == b"push-cert-end" => break,
+                    Some(Packet::Data(line)) => certificate.extend_from_slice(&line),
+                    _ => return Err(protocol_error("the push certificate has no end")),
+                }
+            }
+
+            let parsed = PushCertificate::parse(&certificate).ok_or_else(|| protocol_error("malformed push certificate"))?;
+
+            request.commands.extend(parsed.commands.into_iter().map(|(old, new, name)| PushCommand { old, new, name, error: None }));
+            request.certificate = Some(certificate);
+
+            continue;
+        }
+
+        let line = String::from_utf8_lossy(line);
+        let mut fields = line.splitn(3, ' ');
+
+        let command = (|| {
+            let old = ObjectId::try_from(fields.next()?.to_string()).ok()?;
+            let new = ObjectId::try_from(fields.next()?.to_string()).ok()?;
+
+            Some(PushCommand { old, new, name: fields.next()?.to_string(), error: None })
+        })();
+
+        request.commands.push(command.ok_or_else(|| protocol_error(&format!("malformed command '{line}'")))?);
+    }
+
+    Ok((!request.commands.is_empty()).then_some(request))
+}
+
+/// Makes the update if it's allowed, else says why not. As with git, the
+/// branch checked out in a repository with a worktree can't be updated,
+/// unless `receive.denyCurrentBranch` says otherwise, and deletions and
+/// updates which aren't fast-forwards can be denied.
+fn update(config: &Config, command: &PushCommand) -> Result<(), String> {
+    let PushCommand { old, new, name, .. } = command;
+
+    if !name.starts_with("refs/") || !refs::check_ref_format(name) {
+        return Err("funny refname".to_string());
+    }
+
+    if !new.is_null() && !GitObjectStore::exists(*new) {
+        return Err("missing necessary objects".to_string());
+    }
+
+    let bare = config.get_bool("core.bare").unwrap_or(false);
+    let current = !bare && refs::head_branch().as_deref() == Some(name.as_str());
+
+    let denied = |key: &str| !matches!(config.get(key), Some("ignore" | "warn" | "false"));
+
+    if current && new.is_null() && denied("receive.denyDeleteCurrent") {
+        return Err("deletion of the current branch prohibited".to_string());
+    }
+
+    if current && !new.is_null() && denied("receive.denyCurrentBranch") {
+        return Err("branch is currently checked out".to_string());
+    }
+
+    if new.is_null() && name.starts_with("refs/heads/") && config.get_bool("receive.denyDeletes").unwrap_or(false) {
+        return Err("deletion prohibited".to_string());
+    }
+
+    if !old.is_null() && !new.is_null() && config.get_bool("receive.denyNonFastForwards").unwrap_or(false) {
+        let fast_forward = match (peel_to_commit(*old), peel_to_commit(*new)) {
+            (Some(old), Some(new)) => Graph::new().is_ancestor(old, new),
+            _ => false,
+        };
+
+        if !fast_forward {
+            return Err("non-fast-forward".to_string());
+        }
+    }
+
+    let mut transaction = RefTransaction::new();
+
+    match new.is_null() {
+        true => {
+            transaction.delete(name, Some(*old));
+        }
+        false => {
+            let update = transaction.update(name, *new, Some(*old));
+
+            if reflog::should_log(config, name) {
+                update.reflog = Some(ReflogEntry {
+                    old: *old,
+                    new: *new,
+                    committer: reflog_ident(config).to_string(),
+                    message: "push".to_string(),
+                });
+            }
+        }
+    }
+
+    transaction.commit().map_err(|err| {
+        eprintln!("error: unable to update {name}: {err}");
+        "failed to update ref".to_string()
+    })
+}
+
+/// Runs the repository's `name` hook if it has one, giving it a line per
+/// command on its stdin:
+///
+///   <old> SP <new> SP <name>
+///
+/// along with `env`, eg. the `GIT_PUSH_CERT*` variables. Its output goes
+/// to stderr, as stdout is the pusher's. Whether it succeeded, which it
+/// has without a hook.
+fn run_hook(name: &str, commands: &[&PushCommand], env: &[(&str, String)]) -> io::Result<bool> {
+    let path = git_path(format!("hooks/{name}"));
+
+    if !path.is_file() {
+        return Ok(true);
+    }
+
+    let stderr = io::stderr().as_fd().try_clone_to_owned()?;
+
+    let mut child = Command::new(&path)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::from(stderr))
+        .spawn()
+        .map_err(|err| io::Error::other(format!("unable to run the {name} hook: {err}")))?;
+
+    let input: String = commands.iter()
+        .map(|command| format!("{} {} {}\n", command.old, command.new, command.name))
+        .collect();
+
+    // A hook needn't read what it's given
+    let mut stdin = child.stdin.take().expect("piped");
+    let _ = stdin.write_all(input.as_bytes());
+    drop(stdin);
+
+    Ok(child.wait()?.success())
+}
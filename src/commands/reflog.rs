@@ -0,0 +1,103 @@
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::date;
+use gitty::graph::Graph;
+use gitty::refs;
+use gitty::reflog::{self, ExpireOptions};
+use crate::cli::{ReflogArgs, ReflogCommands, ReflogExpireArgs, ReflogShowArgs};
+use crate::context::Context;
+
+pub fn run(ReflogArgs { command, show: show_args }: ReflogArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Some(ReflogCommands::Show(args)) => show(args),
+        Some(ReflogCommands::Expire(args)) => expire(args, ctx),
+        None => show(show_args),
+    }
+}
+
+/// Lists the reflog newest entry first, each as
+///
+///   <abbrev-id> ' ' <ref> '@{' <n> '}: ' <message>
+fn show(ReflogShowArgs { max_count, refname }: ReflogShowArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let name = refname.unwrap_or_else(|| "HEAD".to_string());
+
+    let entries = reflog::reflog_name(&name)
+        .and_then(|refname| reflog::read_reflog(&refname))
+        .ok_or_else(|| format!("reflog could not be found: '{name}'"))?;
+
+    let mut stdout = std::io::stdout().lock();
+
+    for (i, entry) in entries.iter().rev().enumerate().take(max_count.unwrap_or(usize::MAX)) {
+        writeln!(stdout, "{} {name}@{{{i}}}: {}", &entry.new.to_string()[..7], entry.message)?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn expire(ReflogExpireArgs { expire, expire_unreachable, all, dry_run, refs }: ReflogExpireArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let config = Config::load();
+    let now = date::now();
+
+    let parse_date = |value: &str| {
+        date::approxidate(value, now).ok_or_else(|| format!("invalid expiry date '{value}'"))
+    };
+
+    let expire = expire.as_deref().map(parse_date).transpose()?;
+    let expire_unreachable = expire_unreachable.as_deref().map(parse_date).transpose()?;
+
+    let refnames = if all {
+        reflog::list_reflogs()
+    } else {
+        refs.iter()
+            .map(|name| {
+                if name == "HEAD" || reflog::reflog_path(name).is_file() {
+                    return Ok(name.clone());
+                }
+
+                refs::expand_ref(name)
+                    .filter(|name| reflog::reflog_path(name).is_file())
+                    .ok_or_else(|| format!("reflog could not be found: '{name}'"))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let mut graph = Graph::new();
+    let mut stdout = std::io::stdout().lock();
+    let mut progress = ctx.progress("Expiring reflogs", Some(refnames.len()));
+
+    for refname in refnames {
+        progress.tick();
+
+        let mut options = ExpireOptions::from_config(&config, &refname, now);
+
+        if let Some(expire) = expire {
+            options.expire_total = expire;
+        }
+
+        if let Some(expire_unreachable) = expire_unreachable {
+            options.expire_unreachable = expire_unreachable;
+        }
+
+        let Some(pruned) = reflog::expire_reflog(&refname, &options, &mut graph, dry_run) else {
+            continue;
+        };
+
+        if ctx.verbose {
+            let action = if dry_run { "would prune" } else { "prune" };
+
+            for entry in pruned {
+                writeln!(stdout, "{action} {}", entry.message)?;
+            }
+        }
+    }
+
+    progress.finish();
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::remote::{add_remote, is_valid_remote_name, remote_names, Remote};
+use crate::cli::{FetchArgs, RemoteAddArgs, RemoteArgs, RemoteCommands, RemoteGetUrlArgs};
+use crate::commands::fetch;
+use crate::context::Context;
+
+pub fn run(RemoteArgs { command }: RemoteArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Some(RemoteCommands::Add(args)) => add(args, ctx),
+        Some(RemoteCommands::GetUrl(args)) => get_url(args, ctx),
+        None => list(ctx),
+    }
+}
+
+/// Lists the remotes by name, or with -v a line for each URL they're
+/// fetched from and pushed to:
+///
+///   origin TAB <url> (fetch)
+///   origin TAB <url> (push)
+///
+/// Only the first URL is fetched from, while pushes go to all of them.
+fn list(ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load();
+    let mut stdout = std::io::stdout().lock();
+
+    for name in remote_names(&config) {
+        if !ctx.verbose {
+            writeln!(stdout, "{name}")?;
+            continue;
+        }
+
+        let remote = Remote::get(&config, &name).ok_or_else(|| format!("No such remote '{name}'"))?;
+
+        // A remote with no URL of its own has nothing to show
+        if config.get_all(&format!("remote.{name}.url")).is_empty() && remote.push_urls.is_empty() {
+            writeln!(stdout, "{name}\t")?;
+            continue;
+        }
+
+        writeln!(stdout, "{name}\t{} (fetch)", remote.urls[0])?;
+
+        for url in remote.push_urls() {
+            writeln!(stdout, "{name}\t{url} (push)")?;
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn add(RemoteAddArgs { fetch, name, url }: RemoteAddArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    if !is_valid_remote_name(&name) {
+        return Err(format!("'{name}' is not a valid remote name").into());
+    }
+
+    if remote_names(&Config::load()).contains(&name) {
+        return Err(format!("remote {name} already exists.").into());
+    }
+
+    add_remote(&name, &url)?;
+
+    if fetch {
+        if !ctx.quiet {
+            eprintln!("Updating {name}");
+        }
+
+        fetch::run(FetchArgs { repository: name, refspecs: Vec::new() }, ctx)?;
+    }
+
+    Ok(())
+}
+
+fn get_url(RemoteGetUrlArgs { push, all, name }: RemoteGetUrlArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let remote = Remote::get(&Config::load(), &name).ok_or_else(|| format!("No such remote '{name}'"))?;
+
+    let urls = match push {
+        true => remote.push_urls(),
+        false => &remote.urls,
+    };
+
+    let urls = match all {
+        true => urls,
+        false => &urls[..urls.len().min(1)],
+    };
+
+    let mut stdout = std::io::stdout().lock();
+
+    for url in urls {
+        writeln!(stdout, "{url}")?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+use std::fs;
+
+use gitty::checkout::{remove_worktree_file, write_worktree_file, TreeFiles};
+use gitty::pathspec::Pathspec;
+use gitty::revparse::{self, peel_to_tree};
+use gitty::store::index::{Index, IndexEntry};
+use gitty::store::tree::flatten_tree;
+use gitty::worktree;
+use crate::cli::RestoreArgs;
+use crate::context::Context;
+
+/// Sets the paths back to how they are in the source, in the worktree,
+/// the index or both, leaving HEAD where it is. Paths the source doesn't
+/// have are removed.
+pub fn run(RestoreArgs { source, staged, worktree, paths }: RestoreArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let worktree = worktree || !staged;
+    let pathspec = Pathspec::parse(&paths).ok_or("invalid pathspec")?;
+
+    let mut index = Index::read().ok_or("Unable to read index")?;
+
+    // The index restores the worktree unless something else is given, but
+    // can't restore itself
+    let source = source.or_else(|| staged.then(|| "HEAD".to_string()));
+
+    let files: TreeFiles = match &source {
+        Some(rev) => {
+            let id = revparse::resolve(rev).ok_or_else(|| format!("could not resolve {rev}"))?;
+
+            peel_to_tree(id)
+                .and_then(flatten_tree)
+                .ok_or_else(|| format!("reference is not a tree: {rev}"))?
+        }
+        None => index.entries.iter()
+            .filter(|entry| entry.stage == 0)
+            .map(|entry| (entry.path.clone(), (entry.mode, entry.id)))
+            .collect(),
+    };
+
+    let known: BTreeSet<Vec<u8>> = files.keys()
+        .chain(index.entries.iter().map(|entry| &entry.path))
+        .cloned()
+        .collect();
+
+    for path in &paths {
+        let item = Pathspec::parse(std::slice::from_ref(path)).ok_or("invalid pathspec")?;
+
+        if !known.iter().any(|known| item.matches(known)) {
+            eprintln!("error: pathspec '{path}' did not match any file(s) known to git");
+            std::process::exit(1);
+        }
+    }
+
+    let selected: Vec<&Vec<u8>> = known.iter().filter(|path| pathspec.matches(path)).collect();
+
+    // Conflicts can be resolved from a commit, but the index has nothing
+    // to give them
+    if source.is_none() {
+        if let Some(path) = selected.iter().find(|path| index.conflict(path).is_some()) {
+            eprintln!("error: path '{}' is unmerged", String::from_utf8_lossy(path));
+            std::process::exit(1);
+        }
+    }
+
+    for path in selected {
+        let Some(&(mode, id)) = files.get(path) else {
+            if worktree {
+                remove_worktree_file(path)?;
+            }
+
+            if staged {
+                index.remove(path);
+            }
+
+            continue;
+        };
+
+        if worktree {
+            write_worktree_file(path, mode, id)?;
+        }
+
+        // A worktree file restored to what's staged is refreshed in the
+        // index too. Staged without the worktree, the entry has no stat
+        // data, so the file is compared by content.
+        let restage = staged || index.get(path).is_some_and(|entry| (entry.mode, entry.id) == (mode, id));
+
+        let entry = match (worktree, restage) {
+            (true, true) => {
+                let mut entry = worktree::index_entry(path, &fs::symlink_metadata(worktree::os_path(path))?, id);
+                entry.mode = mode;
+
+                entry
+            }
+            (false, true) => IndexEntry::new(path, mode, id),
+            (_, false) => continue,
+        };
+
+        index.add(entry);
+    }
+
+    index.write()?;
+
+    Ok(())
+}
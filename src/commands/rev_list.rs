@@ -0,0 +1,116 @@
+use std::io::{self, Write};
+
+use gitty::filter::{FilterSpec, ObjectFilter};
+use gitty::graph::Graph;
+use gitty::list_objects::{MissingAction, ObjectWalk};
+use gitty::pretty::format_commit;
+use gitty::store::GitObjectStore;
+use crate::cli::{RevListArgs, RevisionArgs};
+use crate::commands::log::{walk, Pretty};
+use crate::context::Context;
+
+pub fn run(RevListArgs { max_count, objects, filter, filter_print_omitted, missing, pretty, revisions }: RevListArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    // Without a format only the ids are listed
+    let formatted = pretty.pretty.is_some() || pretty.format.is_some() || pretty.oneline;
+    let pretty = Pretty::from_args(pretty, false)?;
+    let RevisionArgs { all, branches, tags, remotes, revisions: revs, .. } = &revisions;
+
+    if !all && branches.is_empty() && tags.is_empty() && remotes.is_empty() && revs.is_empty() {
+        return Err("no revisions given".into());
+    }
+
+    let filter = FilterSpec::combine(filter.iter().map(|spec| FilterSpec::parse(spec)).collect::<Result<_, _>>()?)
+        .map(|spec| ObjectFilter::new(&spec))
+        .transpose()?;
+
+    let missing = match missing {
+        Some(action) => MissingAction::parse(&action).ok_or_else(|| format!("invalid value for '--missing': '{action}'"))?,
+        None => MissingAction::default(),
+    };
+
+    let mut walk = walk(revisions, false)?;
+    let commits: Vec<_> = walk.by_ref().take(max_count.unwrap_or(usize::MAX)).collect();
+
+    let mut stdout = io::stdout().lock();
+
+    for &id in &commits {
+        if !formatted {
+            writeln!(stdout, "{id}")?;
+            continue;
+        }
+
+        let commit = GitObjectStore::get(id).ok_or_else(|| format!("could not read commit {id}"))?;
+
+        // Unlike log, every commit is followed by a newline, and a format
+        // string comes after a commit line of its own
+        if pretty.format.is_user_format() {
+            writeln!(stdout, "commit {id}")?;
+        }
+
+        stdout.write_all(&format_commit(&pretty.format, id, &commit.data, &pretty.options(None)))?;
+
+        writeln!(stdout)?;
+    }
+
+    if !objects {
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    let mut graph = Graph::new();
+    let mut objects = ObjectWalk::new(filter);
+
+    if filter_print_omitted {
+        objects.record_omitted();
+    }
+
+    objects.set_missing_action(missing);
+
+    // What the hidden commits at the edge of the walk have is taken to be
+    // had already
+    for &id in &commits {
+        for parent in graph.parents(id).into_iter().filter(|&parent| walk.is_hidden(parent)) {
+            let tree = graph.tree(parent).ok_or_else(|| format!("could not read commit {parent}"))?;
+            objects.hide_tree(tree);
+        }
+    }
+
+    let mut result = Ok(());
+
+    // The tags the starting points were given as come before the trees
+    for (id, name) in walk.tags() {
+        objects.walk_tag(id, name, &mut |id, name| {
+            if result.is_ok() {
+                result = writeln!(stdout, "{id} {name}");
+            }
+        });
+    }
+
+    for &id in &commits {
+        let tree = graph.tree(id).ok_or_else(|| format!("could not read commit {id}"))?;
+
+        objects.walk_tree(tree, &mut |id, path| {
+            if result.is_ok() {
+                result = writeln!(stdout, "{id} {path}");
+            }
+        }).map_err(|missing| missing.to_string())?;
+    }
+
+    result?;
+
+    if filter_print_omitted {
+        for id in objects.omitted() {
+            writeln!(stdout, "~{id}")?;
+        }
+    }
+
+    for id in objects.missing_objects() {
+        writeln!(stdout, "?{id}")?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
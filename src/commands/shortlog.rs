@@ -0,0 +1,190 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use gitty::config::Config;
+use gitty::mailmap::Mailmap;
+use gitty::message::{subject, trailers, wrap_text};
+use gitty::store::object::Identity;
+use gitty::store::{GitObjectData, GitObjectStore};
+use crate::cli::ShortlogArgs;
+use crate::commands::log::walk;
+use crate::context::Context;
+
+/// What a bare -w wraps subjects with: the width and the indents of the
+/// first line and the rest
+const DEFAULT_WRAP: (usize, usize, usize) = (76, 6, 9);
+
+/// What commits are listed under
+enum Group {
+    Author,
+    Committer,
+
+    /// The values of the trailer with this key, any case
+    Trailer(String),
+}
+
+impl Group {
+    fn parse(spec: &str) -> Result<Group, String> {
+        match spec {
+            "author" => Ok(Group::Author),
+            "committer" => Ok(Group::Committer),
+            _ => match spec.strip_prefix("trailer:") {
+                Some(key) => Ok(Group::Trailer(key.to_string())),
+                None => Err(format!("unknown group type: {spec}")),
+            },
+        }
+    }
+}
+
+pub fn run(ShortlogArgs { summary, numbered, email, committer, group, no_mailmap, wrap, mut revisions }: ShortlogArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    // As git only takes -w's numbers attached, what follows a bare -w is
+    // the first revision
+    let wrap = wrap.map(|wrap| match parse_wrap(&wrap) {
+        Some(wrap) => wrap,
+        None if wrap.is_empty() => DEFAULT_WRAP,
+        None => {
+            revisions.revisions.insert(0, wrap);
+            DEFAULT_WRAP
+        }
+    });
+
+    let mailmap = match no_mailmap {
+        true => Mailmap::default(),
+        false => Mailmap::load(&Config::load()),
+    };
+
+    let mut groups = group.iter().map(|spec| Group::parse(spec)).collect::<Result<Vec<_>, _>>()?;
+
+    if committer {
+        groups.push(Group::Committer);
+    }
+
+    if groups.is_empty() {
+        groups.push(Group::Author);
+    }
+
+    // An ident as it's listed, mailmapped
+    let key = |ident: &Identity| {
+        let (name, mail) = mailmap.canonicalize(&ident.name, &ident.email);
+
+        match email {
+            true => format!("{name} <{mail}>"),
+            false => name,
+        }
+    };
+
+    // Each author's subjects, newest first as they're walked
+    let mut authors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for id in walk(revisions, false)? {
+        let object = GitObjectStore::get(id).ok_or_else(|| format!("could not read commit {id}"))?;
+
+        let GitObjectData::Commit { author, committer, message, .. } = object.data else {
+            return Err(format!("{id} is not a commit").into());
+        };
+
+        // Whoever the commit is listed under, once each
+        let mut keys: Vec<String> = Vec::new();
+
+        for group in &groups {
+            let found = match group {
+                Group::Author | Group::Committer => {
+                    let ident = match group {
+                        Group::Committer => &committer,
+                        _ => &author,
+                    };
+
+                    let ident = Identity::parse(ident).ok_or_else(|| format!("malformed ident in commit {id}"))?;
+                    vec![key(&ident)]
+                }
+                // Values that aren't idents are listed as they are
+                Group::Trailer(name) => trailers(&message).into_iter()
+                    .filter(|(trailer, _)| trailer.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| match Identity::parse(&value) {
+                        Some(ident) => key(&ident),
+                        None => value,
+                    })
+                    .collect(),
+            };
+
+            for found in found {
+                if !keys.contains(&found) {
+                    keys.push(found);
+                }
+            }
+        }
+
+        let subject = shortlog_subject(&message);
+
+        for key in keys {
+            authors.entry(key).or_default().push(subject.clone());
+        }
+    }
+
+    let mut authors: Vec<(String, Vec<String>)> = authors.into_iter().collect();
+
+    if numbered {
+        authors.sort_by_key(|(_, subjects)| Reverse(subjects.len()));
+    }
+
+    let mut stdout = io::stdout().lock();
+
+    for (author, subjects) in &authors {
+        if summary {
+            writeln!(stdout, "{:6}\t{author}", subjects.len())?;
+            continue;
+        }
+
+        writeln!(stdout, "{author} ({}):", subjects.len())?;
+
+        // Oldest first
+        for subject in subjects.iter().rev() {
+            match wrap {
+                Some((width, indent1, indent2)) => {
+                    let mut out = Vec::new();
+                    wrap_text(&mut out, subject.as_bytes(), width, indent1, indent2);
+                    out.push(b'\n');
+                    stdout.write_all(&out)?;
+                }
+                None => writeln!(stdout, "      {subject}")?,
+            }
+        }
+
+        writeln!(stdout)?;
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+/// The subject as shortlog lists it, without a "[PATCH...]" prefix
+fn shortlog_subject(message: &[u8]) -> String {
+    let subject = subject(message);
+
+    let subject = match subject.strip_prefix("[PATCH").and_then(|rest| rest.split_once(']')) {
+        Some((_, rest)) => rest,
+        None => &subject,
+    };
+
+    subject.trim_start().to_string()
+}
+
+/// "<width>[,<indent1>[,<indent2>]]", the indents being the default ones
+/// if left out
+fn parse_wrap(spec: &str) -> Option<(usize, usize, usize)> {
+    let mut numbers = spec.split(',').map(|number| number.parse().ok());
+    let (_, default_indent1, default_indent2) = DEFAULT_WRAP;
+
+    let width = numbers.next()??;
+    let indent1 = numbers.next().unwrap_or(Some(default_indent1))?;
+    let indent2 = numbers.next().unwrap_or(Some(default_indent2))?;
+
+    if numbers.next().is_some() {
+        return None;
+    }
+
+    Some((width, indent1, indent2))
+}
@@ -0,0 +1,103 @@
+use std::io::{self, Write};
+
+use gitty::config::Config;
+use gitty::pathspec::Pathspec;
+use gitty::date::{self, DateMode};
+use gitty::quote::quote_non_ascii;
+use gitty::revparse;
+use gitty::store::object::Identity;
+use gitty::store::tree::MODE_TREE;
+use gitty::store::{GitObjectData, GitObjectStore};
+use crate::cli::ShowArgs;
+use crate::commands::log::{CommitPrinter, Pretty};
+use crate::context::Context;
+
+pub fn run(ShowArgs { no_patch, pretty, objects }: ShowArgs, _ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let pretty = Pretty::from_args(pretty, true)?;
+
+    let objects = match objects.is_empty() {
+        true => vec!["HEAD".to_string()],
+        false => objects,
+    };
+
+    let mut commits = CommitPrinter {
+        pretty: &pretty,
+        patch: !no_patch,
+        pathspec: Pathspec::default(),
+        combined_merges: true,
+        quote_non_ascii: quote_non_ascii(&Config::load()),
+        shown_one: false,
+        reflog: None,
+    };
+
+    let mut stdout = io::stdout().lock();
+
+    // Trees and tags are separated by a blank line from whatever came
+    // before them, commits as their format has them, and blobs are printed
+    // as they are
+    let mut shown_one = false;
+
+    for name in &objects {
+        let mut id = revparse::resolve(name).ok_or_else(|| revparse::missing_path(name).unwrap_or_else(|| format!("bad revision '{name}'")))?;
+
+        // A tag is shown followed by the object it points at
+        loop {
+            let object = GitObjectStore::get(id).ok_or_else(|| format!("could not read object {id}"))?;
+
+            match object.data {
+                GitObjectData::Blob { data } => {
+                    stdout.write_all(&data)?;
+                    break;
+                }
+                GitObjectData::Tree { entries } => {
+                    if shown_one {
+                        writeln!(stdout)?;
+                    }
+
+                    writeln!(stdout, "tree {name}\n")?;
+
+                    for entry in entries {
+                        stdout.write_all(&entry.path)?;
+                        writeln!(stdout, "{}", if entry.mode == MODE_TREE { "/" } else { "" })?;
+                    }
+
+                    shown_one = true;
+                    break;
+                }
+                GitObjectData::Commit { .. } => {
+                    commits.shown_one = shown_one;
+                    commits.print(&mut stdout, id, &object.data, None)?;
+
+                    shown_one = true;
+                    break;
+                }
+                GitObjectData::Tag { object, tag, tagger, message, .. } => {
+                    if shown_one {
+                        writeln!(stdout)?;
+                    }
+
+                    writeln!(stdout, "tag {tag}")?;
+
+                    if let Some(tagger) = tagger.as_deref().and_then(Identity::parse) {
+                        writeln!(stdout, "Tagger: {} <{}>", tagger.name, tagger.email)?;
+                        writeln!(stdout, "Date:   {}", format_ident_date(&tagger, pretty.date_mode))?;
+                    }
+
+                    writeln!(stdout)?;
+                    stdout.write_all(&message)?;
+
+                    shown_one = true;
+                    id = object;
+                }
+            }
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn format_ident_date(ident: &Identity, mode: DateMode) -> String {
+    date::format_date(ident.timestamp, ident.tz_offset, mode, date::now())
+}
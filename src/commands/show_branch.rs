@@ -0,0 +1,362 @@
+use std::collections::{BinaryHeap, HashMap};
+use std::io::Write;
+
+use gitty::graph::Graph;
+use gitty::message::commit_subject;
+use gitty::refs;
+use gitty::revparse;
+use gitty::store::ObjectId;
+use crate::cli::ShowBranchArgs;
+use crate::context::Context;
+
+// One bit per rev, the top bit marks commits every rev can reach
+const MAX_REVS: usize = 63;
+const UNINTERESTING: u64 = 1 << 63;
+
+/// A display name for a commit, relative to one of the revs
+#[derive(Clone)]
+struct CommitName {
+    head_name: String,
+    generation: usize,
+}
+
+impl CommitName {
+    fn pretty(&self) -> String {
+        match self.generation {
+            0 => self.head_name.clone(),
+            1 => format!("{}^", self.head_name),
+            n => format!("{}~{}", self.head_name, n),
+        }
+    }
+}
+
+pub fn run(ShowBranchArgs { more, merge_base, independent, revs }: ShowBranchArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let (names, tips) = resolve_revs(revs)?;
+
+    if tips.is_empty() {
+        return Err("No revs to be shown".into());
+    }
+
+    if tips.len() > MAX_REVS {
+        return Err(format!("Cannot handle more than {MAX_REVS} revs").into());
+    }
+
+    let mut graph = Graph::new();
+    let mut stdout = std::io::stdout().lock();
+
+    if merge_base {
+        let mut bases = vec![tips[0]];
+
+        for &tip in &tips[1..] {
+            bases = bases.iter()
+                .flat_map(|&base| graph.merge_bases(base, tip))
+                .collect();
+            bases = graph.independent(&bases);
+        }
+
+        for base in bases {
+            writeln!(stdout, "{base}")?;
+        }
+
+        return Ok(());
+    }
+
+    if independent {
+        for tip in graph.independent(&tips) {
+            writeln!(stdout, "{tip}")?;
+        }
+
+        return Ok(());
+    }
+
+    let head_at = current_rev(&names, &tips);
+
+    let (seen, flags) = join_revs(&mut graph, &tips, more);
+    let seen = sort_topologically(&mut graph, seen);
+    let commit_names = name_commits(&mut graph, &seen, &names, &tips);
+
+    let num_revs = tips.len();
+    let all_revs = (1u64 << num_revs) - 1;
+
+    if num_revs > 1 {
+        for (i, (name, tip)) in names.iter().zip(&tips).enumerate() {
+            let mark = if head_at == Some(i) { '*' } else { '!' };
+            writeln!(stdout, "{:indent$}{mark} [{name}] {}", "", commit_subject(*tip), indent = i)?;
+        }
+
+        writeln!(stdout, "{}", "-".repeat(num_revs))?;
+    }
+
+    let mut extra = more as isize;
+    let mut shown_merge_point = false;
+
+    for id in seen {
+        let commit_flags = flags[&id];
+        let is_merge_point = commit_flags & all_revs == all_revs;
+
+        shown_merge_point |= is_merge_point;
+
+        if num_revs > 1 {
+            let is_merge = graph.parents(id).len() > 1;
+
+            // Merges only reachable from a single rev aren't interesting
+            let reachable_from = (commit_flags & all_revs).count_ones();
+            if is_merge && reachable_from == 1 && !tips.contains(&id) {
+                continue;
+            }
+
+            for i in 0..num_revs {
+                let mark = if commit_flags & (1 << i) == 0 {
+                    ' '
+                } else if is_merge {
+                    '-'
+                } else if head_at == Some(i) {
+                    '*'
+                } else {
+                    '+'
+                };
+
+                write!(stdout, "{mark}")?;
+            }
+
+            write!(stdout, " ")?;
+        }
+
+        match commit_names.get(&id) {
+            Some(name) => writeln!(stdout, "[{}] {}", name.pretty(), commit_subject(id))?,
+            None => writeln!(stdout, "{}", commit_subject(id))?,
+        }
+
+        if shown_merge_point {
+            extra -= 1;
+            if extra < 0 {
+                break;
+            }
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// The revs to show, defaulting to every local branch
+fn resolve_revs(revs: Vec<String>) -> Result<(Vec<String>, Vec<ObjectId>), Box<dyn std::error::Error>> {
+    let mut names = Vec::new();
+    let mut tips = Vec::new();
+
+    if revs.is_empty() {
+        for (name, id) in refs::list_refs("refs/heads/") {
+            names.push(refs::shorten_ref(&name).to_string());
+            tips.push(id);
+        }
+
+        return Ok((names, tips));
+    }
+
+    for rev in revs {
+        let id = revparse::resolve(&rev)
+            .and_then(revparse::peel_to_commit)
+            .ok_or_else(|| format!("bad sha1 reference {rev}"))?;
+
+        names.push(rev);
+        tips.push(id);
+    }
+
+    Ok((names, tips))
+}
+
+/// Which of the revs is the checked out branch, if any
+fn current_rev(names: &[String], tips: &[ObjectId]) -> Option<usize> {
+    let head_branch = refs::head_branch()?;
+    let head = refs::resolve_ref("HEAD")?;
+
+    names.iter().zip(tips).position(|(name, tip)| {
+        *tip == head && refs::expand_ref(name).as_deref() == Some(head_branch.as_str())
+    })
+}
+
+/// Paints every commit with the set of revs which can reach it, walking
+/// newest first until only commits reachable from every rev remain.
+///
+/// Returns the commits in the order they were first seen, and their flags.
+fn join_revs(graph: &mut Graph, tips: &[ObjectId], mut extra: usize)
+    -> (Vec<ObjectId>, HashMap<ObjectId, u64>)
+{
+    let all_revs = (1u64 << tips.len()) - 1;
+
+    let mut flags: HashMap<ObjectId, u64> = HashMap::new();
+    let mut seen = Vec::new();
+    let mut queue = BinaryHeap::new();
+
+    for (i, &tip) in tips.iter().enumerate() {
+        if !flags.contains_key(&tip) {
+            seen.push(tip);
+        }
+
+        *flags.entry(tip).or_default() |= 1 << i;
+        queue.push((graph.commit_time(tip), tip));
+    }
+
+    while !queue.is_empty() {
+        let still_interesting = queue.iter().any(|(_, id)| flags[id] & UNINTERESTING == 0);
+
+        if !still_interesting && extra == 0 {
+            break;
+        }
+
+        let Some((_, id)) = queue.pop() else {
+            break;
+        };
+
+        let mut commit_flags = flags[&id];
+
+        if commit_flags & all_revs == all_revs {
+            commit_flags |= UNINTERESTING;
+        }
+
+        for parent in graph.parents(id) {
+            let parent_flags = flags.entry(parent).or_default();
+
+            if *parent_flags & commit_flags == commit_flags {
+                continue;
+            }
+
+            if *parent_flags == 0 {
+                seen.push(parent);
+
+                if !still_interesting {
+                    extra = extra.saturating_sub(1);
+                }
+            }
+
+            *parent_flags |= commit_flags;
+            queue.push((graph.commit_time(parent), parent));
+        }
+    }
+
+    (seen, flags)
+}
+
+/// Children before parents, newest first otherwise
+fn sort_topologically(graph: &mut Graph, commits: Vec<ObjectId>) -> Vec<ObjectId> {
+    let mut children: HashMap<ObjectId, usize> = commits.iter().map(|&id| (id, 0)).collect();
+
+    for &id in &commits {
+        for parent in graph.parents(id) {
+            if let Some(count) = children.get_mut(&parent) {
+                *count += 1;
+            }
+        }
+    }
+
+    let mut ready: BinaryHeap<(u64, ObjectId)> = commits.iter()
+        .filter(|id| children[id] == 0)
+        .map(|&id| (graph.commit_time(id), id))
+        .collect();
+
+    let mut sorted = Vec::with_capacity(commits.len());
+
+    while let Some((_, id)) = ready.pop() {
+        sorted.push(id);
+
+        for parent in graph.parents(id) {
+            let Some(count) = children.get_mut(&parent) else {
+                continue;
+            };
+
+            *count -= 1;
+
+            if *count == 0 {
+                ready.push((graph.commit_time(parent), parent));
+            }
+        }
+    }
+
+    sorted
+}
+
+/// Names commits relative to the revs, eg. "main~2" or "topic^2", first
+/// following first parent chains and then naming the remaining parents.
+fn name_commits(
+    graph: &mut Graph,
+    commits: &[ObjectId],
+    names: &[String],
+    tips: &[ObjectId],
+) -> HashMap<ObjectId, CommitName> {
+    let mut commit_names: HashMap<ObjectId, CommitName> = HashMap::new();
+
+    for (name, tip) in names.iter().zip(tips) {
+        commit_names.entry(*tip).or_insert(CommitName {
+            head_name: name.clone(),
+            generation: 0,
+        });
+    }
+
+    let name_first_parent_chain = |graph: &mut Graph, commit_names: &mut HashMap<ObjectId, CommitName>, mut id: ObjectId| {
+        let mut named = 0;
+
+        while let Some(name) = commit_names.get(&id).cloned() {
+            let Some(&parent) = graph.parents(id).first() else {
+                break;
+            };
+
+            if commit_names.contains_key(&parent) {
+                break;
+            }
+
+            commit_names.insert(parent, CommitName {
+                head_name: name.head_name,
+                generation: name.generation + 1,
+            });
+
+            named += 1;
+            id = parent;
+        }
+
+        named
+    };
+
+    while commits.iter().map(|&id| name_first_parent_chain(graph, &mut commit_names, id)).sum::<usize>() > 0 {}
+
+    loop {
+        let mut named = 0;
+
+        for &id in commits {
+            let Some(name) = commit_names.get(&id).cloned() else {
+                continue;
+            };
+
+            for (nth, parent) in graph.parents(id).into_iter().enumerate().skip(1) {
+                if commit_names.contains_key(&parent) {
+                    continue;
+                }
+
+                let nth = nth + 1;
+
+                let head_name = match name.generation {
+                    0 => format!("{}^{}", name.head_name, nth),
+                    1 => format!("{}^^{}", name.head_name, nth),
+                    n => format!("{}~{}^{}", name.head_name, n, nth),
+                };
+
+                commit_names.insert(parent, CommitName {
+                    head_name,
+                    generation: 0,
+                });
+
+                named += 1;
+                named += name_first_parent_chain(graph, &mut commit_names, parent);
+            }
+        }
+
+        if named == 0 {
+            break;
+        }
+    }
+
+    commit_names
+}
+
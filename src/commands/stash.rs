@@ -0,0 +1,64 @@
+use gitty::config::Config;
+use gitty::ignore::IgnoreRules;
+use gitty::pathspec::Pathspec;
+use gitty::stash::{push_stash, StashOptions};
+use gitty::store::index::Index;
+use gitty::worktree::list_untracked;
+use crate::cli::{StashArgs, StashCommands, StashPushArgs};
+use crate::context::Context;
+
+pub fn run(StashArgs { command, push: push_args }: StashArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Some(StashCommands::Push(args)) => push(args, ctx),
+        None => push(push_args, ctx),
+    }
+}
+
+fn push(StashPushArgs { keep_index, no_keep_index: _, include_untracked, message, paths }: StashPushArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let pathspec = Pathspec::parse(&paths).ok_or("invalid pathspec")?;
+
+    check_pathspec(&paths, include_untracked)?;
+
+    let options = StashOptions { keep_index, include_untracked, pathspec, message };
+
+    match push_stash(&options).map_err(|err| err.to_string())? {
+        Some(stash) if !ctx.quiet => println!("Saved working directory and index state {}", stash.description),
+        Some(_) => {}
+        None if !ctx.quiet => println!("No local changes to save"),
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Each path has to match something tracked, or untracked when those are
+/// being stashed too, as a typo would otherwise stash nothing
+fn check_pathspec(paths: &[String], include_untracked: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let index = Index::read().ok_or("Unable to read index")?;
+
+    let mut known: Vec<Vec<u8>> = index.entries.iter().map(|entry| entry.path.clone()).collect();
+
+    if include_untracked {
+        let mut rules = IgnoreRules::load(&Config::load());
+        known.extend(list_untracked(&index, Some(&mut rules), false, false).into_iter().map(String::into_bytes));
+    }
+
+    for path in paths {
+        let pathspec = Pathspec::parse(std::slice::from_ref(path)).ok_or("invalid pathspec")?;
+
+        if !known.iter().any(|known| pathspec.matches(known)) {
+            eprintln!("error: pathspec '{path}' did not match any file(s) known to git");
+            eprintln!("Did you forget to 'git add'?");
+
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::quote::{quote_non_ascii, quote_path};
+use gitty::refs;
+use gitty::reflog::{self, DetachedFrom};
+use gitty::status::{status, Status, UntrackedFiles};
+use gitty::submodule::IgnoreSubmodules;
+use crate::cli::StatusArgs;
+use crate::context::Context;
+
+pub fn run(StatusArgs { porcelain, nul, untracked_files, ignore_submodules }: StatusArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let config = Config::load();
+
+    let untracked_files = match untracked_files {
+        Some(mode) => UntrackedFiles::parse(&mode).ok_or_else(|| format!("Invalid untracked files mode '{mode}'"))?,
+        None => UntrackedFiles::from_config(&config).ok_or("bad config variable 'status.showuntrackedfiles'")?,
+    };
+
+    let ignore_submodules = ignore_submodules
+        .map(|when| IgnoreSubmodules::parse(&when).ok_or_else(|| format!("bad --ignore-submodules argument: {when}")))
+        .transpose()?;
+
+    let status = status(untracked_files, ignore_submodules).ok_or("Unable to compute status")?;
+    let quote_non_ascii = quote_non_ascii(&config);
+
+    let mut stdout = std::io::stdout().lock();
+
+    if nul {
+        print_porcelain(&mut stdout, &status, |path| String::from_utf8_lossy(path).into_owned(), '\0')?;
+    } else if porcelain {
+        print_porcelain(&mut stdout, &status, |path| quote_path(path, quote_non_ascii), '\n')?;
+    } else {
+        print_long(&mut stdout, &status, quote_non_ascii)?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// Porcelain format:
+///   <X><Y> ' ' <path>
+///
+/// X is the status of the index against HEAD, Y of the worktree against the
+/// index, conflicted paths use the two letter unmerged codes and untracked
+/// files are listed last as "??". Each entry ends with `terminator`.
+fn print_porcelain<F>(out: &mut impl Write, status: &Status, format_path: F, terminator: char) -> std::io::Result<()>
+where
+    F: Fn(&[u8]) -> String,
+{
+    for entry in &status.entries {
+        let path = format_path(&entry.path);
+
+        match entry.conflict {
+            Some(conflict) => write!(out, "{} {path}{terminator}", conflict.code())?,
+            None => {
+                let staged = entry.staged.map_or(' ', |change| change.code());
+                let unstaged = entry.unstaged.map_or(' ', |change| change.code());
+                write!(out, "{staged}{unstaged} {path}{terminator}")?;
+            }
+        }
+    }
+
+    for path in &status.untracked {
+        write!(out, "?? {}{terminator}", format_path(path.as_bytes()))?;
+    }
+
+    Ok(())
+}
+
+pub fn print_long(out: &mut impl Write, status: &Status, quote_non_ascii: bool) -> std::io::Result<()> {
+    let quote = |path: &[u8]| quote_path(path, quote_non_ascii);
+
+    match (&status.branch, status.head) {
+        (Some(branch), _) => writeln!(out, "On branch {}", refs::shorten_ref(branch))?,
+        (None, Some(head)) => match reflog::detached_from(head) {
+            Some(DetachedFrom { name, at: true }) => writeln!(out, "HEAD detached at {name}")?,
+            Some(DetachedFrom { name, at: false }) => writeln!(out, "HEAD detached from {name}")?,
+            None => writeln!(out, "Not currently on any branch.")?,
+        },
+        (None, None) => {},
+    }
+
+    if status.head.is_none() {
+        writeln!(out, "\nNo commits yet")?;
+    }
+
+    let staged: Vec<_> = status.entries.iter()
+        .filter_map(|entry| Some((entry.staged?, &entry.path)))
+        .collect();
+
+    let conflicted: Vec<_> = status.entries.iter()
+        .filter_map(|entry| Some((entry.conflict?, &entry.path)))
+        .collect();
+
+    let unstaged: Vec<_> = status.entries.iter()
+        .filter_map(|entry| Some((entry.unstaged?, &entry.path, entry.submodule)))
+        .collect();
+
+    if !staged.is_empty() {
+        writeln!(out, "\nChanges to be committed:")?;
+        for (change, path) in &staged {
+            writeln!(out, "\t{:<12}{}", format!("{}:", change.description()), quote(path))?;
+        }
+    }
+
+    if !conflicted.is_empty() {
+        writeln!(out, "\nUnmerged paths:")?;
+        for (conflict, path) in &conflicted {
+            writeln!(out, "\t{:<17}{}", format!("{}:", conflict.description()), quote(path))?;
+        }
+    }
+
+    if !unstaged.is_empty() {
+        writeln!(out, "\nChanges not staged for commit:")?;
+        for (change, path, submodule) in &unstaged {
+            write!(out, "\t{:<12}{}", format!("{}:", change.description()), quote(path))?;
+
+            match submodule {
+                Some(submodule) => writeln!(out, " ({})", submodule.description())?,
+                None => writeln!(out)?,
+            }
+        }
+    }
+
+    if !status.untracked.is_empty() {
+        writeln!(out, "\nUntracked files:")?;
+        for path in &status.untracked {
+            writeln!(out, "\t{}", quote(path.as_bytes()))?;
+        }
+    } else if status.untracked_files == UntrackedFiles::No && !staged.is_empty() {
+        writeln!(out, "\nUntracked files not listed")?;
+    }
+
+    if status.entries.is_empty() && status.untracked_files == UntrackedFiles::No {
+        writeln!(out, "nothing to commit")?;
+    } else if status.entries.is_empty() && status.untracked.is_empty() {
+        writeln!(out, "nothing to commit, working tree clean")?;
+    } else if staged.is_empty() && conflicted.is_empty() {
+        writeln!(out, "\nno changes added to commit")?;
+    }
+
+    Ok(())
+}
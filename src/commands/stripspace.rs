@@ -0,0 +1,28 @@
+use std::io::{Read, Write};
+
+use gitty::config::Config;
+use gitty::message::{comment_char, comment_lines, stripspace};
+use crate::cli::StripspaceArgs;
+use crate::context::Context;
+
+pub fn run(StripspaceArgs { strip_comments, comment_lines: comment }: StripspaceArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut input = Vec::new();
+    std::io::stdin().read_to_end(&mut input)?;
+
+    // Outside a repository there's no config, and '#' is used
+    let comment_char = comment_char(&Config::load());
+
+    let output = if comment {
+        comment_lines(&input, &comment_char)
+    } else {
+        stripspace(&input, strip_comments.then_some(comment_char.as_str()))
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&output)?;
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use std::io::Write;
+
+use gitty::config::Config;
+use gitty::pathspec::Pathspec;
+use gitty::store::index::Index;
+use gitty::store::tree::MODE_GITLINK;
+use gitty::store::ObjectId;
+use gitty::submodule::load_gitmodules;
+use crate::cli::{SubmoduleArgs, SubmoduleCommands, SubmoduleStatusArgs};
+use crate::context::Context;
+
+pub fn run(SubmoduleArgs { command }: SubmoduleArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        SubmoduleCommands::Status(args) => status(args, ctx),
+    }
+}
+
+/// Lists each submodule the index records as its commit and path, with a
+/// prefix saying how the checkout compares: '-' not initialized or not
+/// checked out, '+' on another commit, 'U' conflicted, ' ' up to date
+fn status(SubmoduleStatusArgs { cached, paths }: SubmoduleStatusArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let config = Config::load();
+    let submodules = load_gitmodules();
+    let pathspec = Pathspec::parse(&paths).ok_or("invalid pathspec")?;
+    let index = Index::read().ok_or("Unable to read index")?;
+
+    let mut stdout = std::io::stdout().lock();
+    let mut last_path = None;
+
+    for entry in index.entries.iter().filter(|entry| entry.mode == MODE_GITLINK) {
+        // Each stage of a conflicted submodule has an entry of its own
+        if last_path == Some(&entry.path) || !pathspec.matches(&entry.path) {
+            continue;
+        }
+
+        last_path = Some(&entry.path);
+
+        let path = String::from_utf8_lossy(&entry.path);
+
+        let submodule = submodules.iter()
+            .find(|submodule| submodule.path.as_bytes() == entry.path)
+            .ok_or_else(|| format!("no submodule mapping found in .gitmodules for path '{path}'"))?;
+
+        if entry.stage != 0 {
+            writeln!(stdout, "U{} {path}", ObjectId::NULL)?;
+            continue;
+        }
+
+        let head = match submodule.is_active(&config) {
+            true => submodule.head(),
+            false => None,
+        };
+
+        match head {
+            None => writeln!(stdout, "-{} {path}", entry.id)?,
+            Some(head) if head == entry.id => {
+                writeln!(stdout, " {} {path} ({})", entry.id, submodule.describe(entry.id))?;
+            }
+            Some(head) => {
+                let shown = if cached { entry.id } else { head };
+
+                writeln!(stdout, "+{shown} {path} ({})", submodule.describe(shown))?;
+            }
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
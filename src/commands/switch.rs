@@ -0,0 +1,169 @@
+use gitty::checkout::{checkout_tree, TreeFiles};
+use gitty::config::Config;
+use gitty::ident::reflog_ident;
+use gitty::message::commit_subject;
+use gitty::refs;
+use gitty::reflog::{self, ReflogEntry};
+use gitty::revparse::{self, peel_to_commit, peel_to_tree};
+use gitty::store::tree::flatten_tree;
+use gitty::store::ObjectId;
+use crate::cli::SwitchArgs;
+use crate::commands::branch::{branch_refname, create_branch};
+use crate::context::Context;
+
+/// Where HEAD should end up
+pub enum SwitchTarget {
+    Branch(String),
+
+    /// A branch created at `start`, or with `reset` reset there if it
+    /// exists already
+    NewBranch { name: String, start: String, reset: bool },
+
+    Detached(String),
+}
+
+pub fn run(SwitchArgs { create, force_create, detach, no_guess, force, target }: SwitchArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let new_branch = match (create, force_create) {
+        (Some(name), _) => Some((name, false)),
+        (None, Some(name)) => Some((name, true)),
+        (None, None) => None,
+    };
+
+    let target = match (new_branch, target) {
+        (Some((name, reset)), start) => SwitchTarget::NewBranch {
+            name,
+            start: start.unwrap_or_else(|| "HEAD".to_string()),
+            reset,
+        },
+        (None, target) if detach => SwitchTarget::Detached(target.unwrap_or_else(|| "HEAD".to_string())),
+        (None, Some(target)) if refs::read_ref(&format!("refs/heads/{target}")).is_some() => SwitchTarget::Branch(target),
+        (None, Some(target)) => {
+            // A remote-tracking branch of the same name is taken to be
+            // what's meant, if there's just the one
+            if let Some(start) = guess_remote_branch(&target).filter(|_| !no_guess) {
+                SwitchTarget::NewBranch { name: target, start, reset: false }
+            } else {
+                return match revparse::resolve(&target).and_then(peel_to_commit) {
+                    Some(_) => Err(format!("a branch is expected, got commit '{target}', use --detach").into()),
+                    None => Err(format!("invalid reference: {target}").into()),
+                };
+            }
+        }
+        (None, None) => return Err("missing branch or commit argument".into()),
+    };
+
+    switch_to(target, force, ctx)
+}
+
+/// The only remote-tracking branch named `name`, eg. "origin/<name>", None
+/// if there's none or more than one
+fn guess_remote_branch(name: &str) -> Option<String> {
+    let suffix = format!("/{name}");
+
+    let mut candidates = refs::list_refs("refs/remotes/").into_iter()
+        .map(|(refname, _)| refs::shorten_ref(&refname).to_string())
+        .filter(|short| short.strip_suffix(&suffix).is_some_and(|remote| !remote.is_empty() && !remote.contains('/')));
+
+    match (candidates.next(), candidates.next()) {
+        (Some(candidate), None) => Some(candidate),
+        _ => None,
+    }
+}
+
+/// Checks out the target's tree and moves HEAD to it, recording the move
+/// in HEAD's reflog
+pub fn switch_to(target: SwitchTarget, force: bool, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let (rev, to_name) = match &target {
+        SwitchTarget::Branch(name) => (format!("refs/heads/{name}"), name.as_str()),
+        SwitchTarget::NewBranch { name, start, reset } => {
+            let refname = branch_refname(name)?;
+
+            if !reset && refs::read_ref(&refname).is_some() {
+                return Err(format!("a branch named '{name}' already exists").into());
+            }
+
+            (start.clone(), name.as_str())
+        }
+        SwitchTarget::Detached(rev) => (rev.clone(), rev.as_str()),
+    };
+
+    let commit = revparse::resolve(&rev)
+        .and_then(peel_to_commit)
+        .ok_or_else(|| format!("invalid reference: {rev}"))?;
+
+    let head_branch = refs::head_branch();
+    let old_head = refs::resolve_ref("HEAD");
+
+    let reset_existing = match &target {
+        SwitchTarget::NewBranch { name, .. } => refs::read_ref(&format!("refs/heads/{name}")).is_some(),
+        _ => false,
+    };
+
+    let config = Config::load();
+
+    let committer = reflog::should_log(&config, "HEAD").then(|| reflog_ident(&config));
+
+    let old_files = match old_head {
+        Some(head) => commit_files(head)?,
+        None => TreeFiles::new(),
+    };
+
+    checkout_tree(&old_files, &commit_files(commit)?, force).ok_or("checkout aborted")?;
+
+    let from_name = match (&head_branch, old_head) {
+        (Some(branch), _) => refs::shorten_ref(branch).to_string(),
+        (None, Some(head)) => head.to_string(),
+        (None, None) => "HEAD".to_string(),
+    };
+
+    match &target {
+        SwitchTarget::Branch(name) => refs::write_symbolic_ref("HEAD", &format!("refs/heads/{name}"))?,
+        SwitchTarget::NewBranch { name, start, reset } => {
+            create_branch(name, start, *reset, ctx)?;
+            refs::write_symbolic_ref("HEAD", &format!("refs/heads/{name}"))?;
+        }
+        SwitchTarget::Detached(_) => refs::write_ref("HEAD", commit, None)?,
+    }
+
+    if let Some(committer) = committer {
+        let entry = ReflogEntry {
+            old: old_head.unwrap_or(ObjectId::NULL),
+            new: commit,
+            committer: committer.to_string(),
+            message: format!("checkout: moving from {from_name} to {to_name}"),
+        };
+
+        reflog::append_reflog("HEAD", &entry)?;
+    }
+
+    if !ctx.quiet {
+        // Leaving a detached HEAD, say where it was so it can be found again
+        if let (None, Some(old_head)) = (&head_branch, old_head) {
+            if old_head != commit {
+                eprintln!("Previous HEAD position was {} {}", &old_head.to_string()[..7], commit_subject(old_head));
+            }
+        }
+
+        match &target {
+            SwitchTarget::Branch(name) if head_branch.as_deref() == Some(format!("refs/heads/{name}").as_str()) => {
+                eprintln!("Already on '{name}'");
+            }
+            SwitchTarget::Branch(name) => eprintln!("Switched to branch '{name}'"),
+            SwitchTarget::NewBranch { name, .. } if reset_existing => eprintln!("Switched to and reset branch '{name}'"),
+            SwitchTarget::NewBranch { name, .. } => eprintln!("Switched to a new branch '{name}'"),
+            SwitchTarget::Detached(_) => eprintln!("HEAD is now at {} {}", &commit.to_string()[..7], commit_subject(commit)),
+        }
+    }
+
+    Ok(())
+}
+
+/// The files in the tree of `commit`, as `checkout_tree` takes them
+pub fn commit_files(commit: ObjectId) -> Result<TreeFiles, Box<dyn std::error::Error>> {
+    peel_to_tree(commit)
+        .and_then(flatten_tree)
+        .ok_or_else(|| format!("unable to read the tree of {commit}").into())
+}
+
@@ -0,0 +1,85 @@
+use gitty::config::Config;
+use gitty::ident::reflog_ident;
+use gitty::refs::{self, RefValue};
+use gitty::reflog::{self, ReflogEntry};
+use gitty::store::ObjectId;
+use crate::cli::SymbolicRefArgs;
+use crate::context::Context;
+
+pub fn run(SymbolicRefArgs { message, delete, short, no_recurse, name, target }: SymbolicRefArgs, ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    if let Some(target) = target {
+        return point(&name, &target, message);
+    }
+
+    let pointed_to = match refs::read_ref(&name) {
+        Some(RefValue::Symbolic(target)) => target,
+        Some(RefValue::Direct(_)) if delete => return Err(format!("Cannot delete {name}, not a symbolic ref").into()),
+        // Not being a symbolic ref is a quiet failure with -q
+        Some(RefValue::Direct(_)) if ctx.quiet => std::process::exit(1),
+        Some(RefValue::Direct(_)) => return Err(format!("ref {name} is not a symbolic ref").into()),
+        None => return Err(format!("No such ref: {name}").into()),
+    };
+
+    if delete {
+        if name == "HEAD" {
+            return Err("deleting 'HEAD' is not allowed".into());
+        }
+
+        refs::delete_ref(&name, None)?;
+
+        return Ok(());
+    }
+
+    // The ref pointed to needn't exist, as for HEAD on an unborn branch
+    let refname = match no_recurse {
+        true => pointed_to,
+        false => refs::resolve_ref_name(&name),
+    };
+
+    match short {
+        true => println!("{}", refs::shorten_ref(&refname)),
+        false => println!("{refname}"),
+    }
+
+    Ok(())
+}
+
+/// Points the symbolic ref `name` to `target`, logging the change with
+/// `message` if there is one and `target` exists
+fn point(name: &str, target: &str, message: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    if name == "HEAD" && !target.starts_with("refs/") {
+        return Err("Refusing to point HEAD outside of refs/".into());
+    }
+
+    if !refs::check_ref_format(target) {
+        return Err(format!("Refusing to set '{name}' to invalid ref '{target}'").into());
+    }
+
+    if !refs::check_ref_format(name) {
+        return Err(format!("refusing to update ref with bad name '{name}'").into());
+    }
+
+    let config = Config::load();
+
+    // Looked up first, so a missing identity doesn't leave the ref moved
+    // without its reflog entry
+    let entry = match (message, refs::resolve_ref(target)) {
+        (Some(message), Some(new)) if reflog::should_log(&config, name) => Some(ReflogEntry {
+            old: refs::resolve_ref(name).unwrap_or(ObjectId::NULL),
+            new,
+            committer: reflog_ident(&config).to_string(),
+            message,
+        }),
+        _ => None,
+    };
+
+    refs::write_symbolic_ref(name, target)?;
+
+    if let Some(entry) = entry {
+        reflog::append_reflog(name, &entry)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::io::Write;
+use std::path::Path;
+
+use gitty::config::Config;
+use gitty::ident::{ident, IdentRole};
+use gitty::message::stripspace;
+use gitty::refs::{self, RefTransaction};
+use gitty::ref_filter::PatternMode;
+use gitty::revparse;
+use gitty::store::object::serialize_tag;
+use gitty::store::{GitObjectStore, ObjectId};
+use crate::cli::{RefFilterArgs, TagArgs};
+use crate::commands::commit_tree::read_message;
+use crate::context::Context;
+use crate::commands::for_each_ref::ref_filter;
+
+pub fn run(TagArgs { list, annotate, messages, file, force, filter, names }: TagArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    // Like git, filtering options imply listing
+    let filtering = !filter.contains.is_empty()
+        || !filter.no_contains.is_empty()
+        || !filter.merged.is_empty()
+        || !filter.no_merged.is_empty();
+
+    if list || filtering || names.is_empty() {
+        return list_tags(filter, names);
+    }
+
+    let (name, target) = match &names[..] {
+        [name] => (name, "HEAD"),
+        [name, target] => (name, target.as_str()),
+        _ => return Err("too many arguments".into()),
+    };
+
+    let message = if annotate || !messages.is_empty() || file.is_some() {
+        Some(tag_message(messages, file.as_deref())?)
+    } else {
+        None
+    };
+
+    create_tag(name, target, message, force)
+}
+
+fn list_tags(filter: RefFilterArgs, patterns: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = ref_filter(filter, patterns, PatternMode::ShortName)?;
+
+    let mut stdout = std::io::stdout().lock();
+
+    for item in filter.filter(refs::list_refs("refs/tags/")) {
+        writeln!(stdout, "{}", refs::shorten_ref(&item.name))?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn tag_message(messages: Vec<String>, file: Option<&Path>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let message = if !messages.is_empty() {
+        messages.join("\n\n").into_bytes()
+    } else if file.is_some() {
+        read_message(file)?
+    } else {
+        return Err("no tag message given, use -m or -F".into());
+    };
+
+    Ok(stripspace(&message, None))
+}
+
+/// Points `refs/tags/<name>` at `target`, or with a `message` at a new tag
+/// object for it
+fn create_tag(name: &str, target: &str, message: Option<Vec<u8>>, force: bool)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let refname = format!("refs/tags/{name}");
+
+    if name.starts_with('-') || !refs::check_ref_format(&refname) {
+        return Err(format!("'{name}' is not a valid tag name").into());
+    }
+
+    let current = refs::resolve_ref(&refname);
+
+    if current.is_some() && !force {
+        return Err(format!("tag '{name}' already exists").into());
+    }
+
+    let target_id = revparse::resolve(target)
+        .ok_or_else(|| format!("Failed to resolve '{target}' as a valid ref"))?;
+
+    let id = match message {
+        Some(message) => {
+            let kind = GitObjectStore::get(target_id)
+                .ok_or_else(|| format!("unable to read {target_id}"))?
+                .type_str();
+
+            let tagger = ident(IdentRole::Committer, &Config::load()).ok_or("unable to determine the tagger")?;
+
+            let data = serialize_tag(&target_id, kind, name, Some(&tagger.to_string()), &message);
+            GitObjectStore::write("tag", &data).ok_or("unable to write tag")?
+        }
+        None => target_id,
+    };
+
+    let mut transaction = RefTransaction::new();
+    transaction.update(&refname, id, Some(current.unwrap_or(ObjectId::NULL)));
+    transaction.commit()?;
+
+    if let Some(old) = current.filter(|&old| old != id) {
+        println!("Updated tag '{name}' (was {})", &old.to_string()[..7]);
+    }
+
+    Ok(())
+}
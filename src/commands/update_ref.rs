@@ -0,0 +1,66 @@
+use gitty::config::Config;
+use gitty::ident::reflog_ident;
+use gitty::refs::{self, RefTransaction};
+use gitty::reflog::{self, ReflogEntry};
+use gitty::revparse;
+use gitty::store::ObjectId;
+use crate::cli::UpdateRefArgs;
+use crate::context::Context;
+
+pub fn run(UpdateRefArgs { message, delete, no_deref, refname, values }: UpdateRefArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let (new, old) = match (delete, values.as_slice()) {
+        (false, [new]) => (Some(new), None),
+        (false, [new, old]) => (Some(new), Some(old)),
+        (true, []) => (None, None),
+        (true, [old]) => (None, Some(old)),
+        _ => return Err("usage: gitty update-ref [-m <reason>] (-d <ref> [<old>] | <ref> <new> [<old>])".into()),
+    };
+
+    let new = new
+        .map(|new| revparse::resolve(new).ok_or_else(|| format!("{new}: not a valid SHA1")))
+        .transpose()?;
+
+    // An old value needn't exist, least of all the null id, which says the
+    // ref mustn't exist yet as an empty value does
+    let old = old
+        .map(|old| match old.is_empty() {
+            true => Ok(ObjectId::NULL),
+            false => ObjectId::try_from(old.clone()).ok()
+                .or_else(|| revparse::resolve(old))
+                .ok_or_else(|| format!("{old}: not a valid old SHA1")),
+        })
+        .transpose()?;
+
+    // Updating HEAD updates the branch it's on, unless told otherwise
+    let name = match no_deref {
+        true => refname.clone(),
+        false => refs::resolve_ref_name(&refname),
+    };
+
+    let mut transaction = RefTransaction::new();
+
+    match new {
+        Some(new) => {
+            let config = Config::load();
+            let update = transaction.update(&name, new, old);
+
+            if reflog::should_log(&config, &name) {
+                update.reflog = Some(ReflogEntry {
+                    old: refs::resolve_ref(&name).unwrap_or(ObjectId::NULL),
+                    new,
+                    committer: reflog_ident(&config).to_string(),
+                    message: message.unwrap_or_default(),
+                });
+            }
+        }
+        None => {
+            transaction.delete(&name, old);
+        }
+    }
+
+    transaction.commit().map_err(|err| format!("update_ref failed for ref '{name}': {err}"))?;
+
+    Ok(())
+}
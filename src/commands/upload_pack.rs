@@ -0,0 +1,261 @@
+use std::collections::HashSet;
+use std::env;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use gitty::advertise::{advertise_refs, advertised_refs, Service};
+use gitty::config::Config;
+use gitty::filter::{FilterSpec, ObjectFilter};
+use gitty::list_objects::objects_to_send;
+use gitty::metrics;
+use gitty::protocol::pktline::{Packet, PacketReader, PacketWriter, SidebandWriter, MAX_PACKET_LEN};
+use gitty::store::pack_write::write_pack;
+use gitty::store::{GitObjectStore, ObjectId};
+use crate::cli::ServeArgs;
+use crate::context::Context;
+
+/// What the fetcher asked for before negotiating
+struct Request {
+    wants: Vec<ObjectId>,
+    capabilities: Vec<String>,
+
+    /// The objects to leave out, eg. "blob:none", as sent
+    filter: Option<String>,
+}
+
+pub fn run(ServeArgs { advertise_refs: only_advertise, directory }: ServeArgs, _ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    enter_repository(&directory)?;
+
+    let config = Config::load();
+
+    let mut stdout = io::stdout().lock();
+    advertise_refs(&mut stdout, Service::UploadPack, &config)?;
+    stdout.flush()?;
+
+    let served = match only_advertise {
+        true => Ok(()),
+        false => serve(&config, &mut stdout),
+    };
+
+    export_metrics(&config);
+
+    served
+}
+
+/// Serves a fetch after the advertisement: reads the wants, negotiates
+/// what we have in common over the haves, and sends the pack of what the
+/// fetcher is missing. Errors the fetcher should hear of go to it as an
+/// "ERR" line too.
+fn serve(config: &Config, out: &mut impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut packets = PacketReader::new(&mut input);
+    let mut response = PacketWriter::new(out);
+
+    // Having all it wants, the fetcher just hangs up
+    let request = match read_request(&mut packets) {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(()),
+        Err(err) => return Err(refuse(&mut response, err.to_string())),
+    };
+
+    let tips: HashSet<ObjectId> = advertised_refs(config, Service::UploadPack).into_iter().map(|(id, _)| id).collect();
+
+    if let Some(want) = request.wants.iter().find(|want| !tips.contains(want)) {
+        return Err(refuse(&mut response, format!("not our ref {want}")));
+    }
+
+    let filter = match &request.filter {
+        None => None,
+        Some(_) if !config.get_bool("uploadpack.allowFilter").unwrap_or(false) => {
+            return Err(refuse(&mut response, "filtering is not allowed".to_string()));
+        }
+        Some(spec) => match FilterSpec::parse(spec).and_then(|spec| ObjectFilter::new(&spec)) {
+            Ok(filter) => Some(filter),
+            Err(err) => return Err(refuse(&mut response, err)),
+        },
+    };
+
+    let Some(common) = negotiate(&mut packets, &mut response)? else {
+        return Ok(());
+    };
+
+    let objects = match objects_to_send(&request.wants, &common, filter) {
+        Ok(objects) => objects,
+        Err(missing) => return Err(refuse(&mut response, missing.to_string())),
+    };
+
+    let has = |capability: &str| request.capabilities.iter().any(|offered| offered == capability);
+
+    // The pack goes on the side-band if it was asked for, in the biggest
+    // packets the fetcher takes
+    let sideband = match (has("side-band-64k"), has("side-band")) {
+        (true, _) => Some(MAX_PACKET_LEN),
+        (false, true) => Some(1000),
+        (false, false) => None,
+    };
+
+    let sent = match sideband {
+        Some(max_len) => {
+            let mut pack = CountingWriter { inner: SidebandWriter::new(response, max_len), count: 0 };
+            write_pack(&mut pack, &objects)?;
+            pack.inner.finish()?;
+            pack.count
+        }
+        None => {
+            let mut pack = CountingWriter { inner: response.into_inner(), count: 0 };
+            write_pack(&mut pack, &objects)?;
+            pack.count
+        }
+    };
+
+    metrics::count("gitty_objects_served_total", &[], objects.len() as u64);
+    metrics::count("gitty_pack_bytes_sent_total", &[], sent);
+
+    Ok(())
+}
+
+/// Tells the fetcher why it isn't being served, giving the error to fail
+/// with
+fn refuse<W: Write>(response: &mut PacketWriter<W>, message: String) -> Box<dyn std::error::Error> {
+    let _ = response.write_line(&format!("ERR upload-pack: {message}"));
+    let _ = response.flush();
+
+    message.into()
+}
+
+/// Reads the wants up to the flush-pkt, with the capabilities after the
+/// first:
+///
+///   "want" SP <id> [SP <capabilities>]
+///   ...
+///   ["filter" SP <spec>]
+///
+/// None if the fetcher sent none. Shallow clones aren't served.
+fn read_request<R: Read>(packets: &mut PacketReader<R>) -> io::Result<Option<Request>> {
+    let protocol_error = |what: String| io::Error::other(format!("protocol error: {what}"));
+
+    let mut request = Request { wants: Vec::new(), capabilities: Vec::new(), filter: None };
+
+    loop {
+        let line = match packets.read_packet()? {
+            None | Some(Packet::Flush) => break,
+            Some(packet) => packet.line().map(|line| String::from_utf8_lossy(line).into_owned()),
+        };
+
+        let Some(line) = line else {
+            return Err(protocol_error("expected a want".to_string()));
+        };
+
+        if let Some(spec) = line.strip_prefix("filter ") {
+            request.filter = Some(spec.to_string());
+            continue;
+        }
+
+        if line.starts_with("shallow ") || line.starts_with("deepen") {
+            return Err(io::Error::other("shallow fetches aren't served"));
+        }
+
+        let mut fields = line.split(' ');
+
+        let want = match (fields.next(), fields.next().map(|id| ObjectId::try_from(id.to_string()))) {
+            (Some("want"), Some(Ok(id))) => id,
+            _ => return Err(protocol_error(format!("unexpected '{line}'"))),
+        };
+
+        if request.wants.is_empty() {
+            request.capabilities = fields.map(str::to_string).collect();
+        }
+
+        request.wants.push(want);
+    }
+
+    Ok((!request.wants.is_empty()).then_some(request))
+}
+
+/// Reads the haves until the fetcher's "done", answering as git does
+/// without multi_ack: "ACK <id>" for the first have we have too, and at
+/// the end of each round, a flush-pkt or "done", "NAK" if we haven't had
+/// one yet. Gives the haves we have, None if the fetcher hangs up first.
+fn negotiate<R: Read, W: Write>(packets: &mut PacketReader<R>, response: &mut PacketWriter<W>) -> io::Result<Option<Vec<ObjectId>>> {
+    let mut common = Vec::new();
+
+    loop {
+        let packet = match packets.read_packet()? {
+            Some(packet) => packet,
+            None => return Ok(None),
+        };
+
+        let line = packet.line();
+
+        if packet == Packet::Flush || line == Some(b"done") {
+            metrics::count("gitty_negotiation_rounds_total", &[], 1);
+
+            if common.is_empty() {
+                response.write_line("NAK")?;
+            }
+
+            response.flush()?;
+
+            match packet {
+                Packet::Flush => continue,
+                _ => return Ok(Some(common)),
+            }
+        }
+
+        let have = line
+            .and_then(|line| line.strip_prefix(b"have "))
+            .and_then(|id| ObjectId::try_from(String::from_utf8_lossy(id).into_owned()).ok())
+            .ok_or_else(|| io::Error::other(format!("protocol error: expected have, got '{}'", String::from_utf8_lossy(line.unwrap_or_default()))))?;
+
+        if GitObjectStore::exists(have) {
+            common.push(have);
+
+            if common.len() == 1 {
+                response.write_line(&format!("ACK {have}"))?;
+            }
+        }
+    }
+}
+
+/// Passes everything through to `inner`, counting the bytes
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Moves into the repository a server was started for, which is a bare
+/// one if it has no `.git`
+pub fn enter_repository(directory: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    env::set_current_dir(directory).map_err(|err| format!("'{}' does not appear to be a git repository: {err}", directory.display()))?;
+
+    if Path::new(".git").exists() {
+        return Ok(());
+    }
+
+    if !Path::new("objects").is_dir() || !Path::new("refs").is_dir() {
+        return Err(format!("'{}' does not appear to be a git repository", directory.display()).into());
+    }
+
+    env::set_var("GIT_DIR", ".");
+
+    Ok(())
+}
+
+/// Exports what a server counted, which mustn't fail what it served
+pub fn export_metrics(config: &Config) {
+    if let Err(err) = metrics::export(config) {
+        eprintln!("warning: unable to export metrics: {err}");
+    }
+}
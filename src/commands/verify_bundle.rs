@@ -0,0 +1,65 @@
+use std::io::Write;
+
+use gitty::bundle::Bundle;
+use crate::cli::VerifyBundleArgs;
+use crate::context::Context;
+
+pub fn run(VerifyBundleArgs { file }: VerifyBundleArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let bundle = Bundle::open(&file).ok_or("Unable to read bundle")?;
+    let problems = bundle.verify();
+
+    let prerequisites = problems.missing.iter().chain(&problems.unconnected);
+
+    for (i, prerequisite) in prerequisites.enumerate() {
+        if i == 0 {
+            eprintln!("error: Repository lacks these prerequisite commits:");
+        }
+
+        eprintln!("error: {} {}", prerequisite.id, prerequisite.comment);
+    }
+
+    for problem in &problems.pack {
+        eprintln!("error: {}: {problem}", file.display());
+    }
+
+    if !problems.is_empty() {
+        return Err(format!("{} is not usable", file.display()).into());
+    }
+
+    eprintln!("{} is okay", file.display());
+
+    if ctx.quiet {
+        return Ok(());
+    }
+
+    let mut stdout = std::io::stdout().lock();
+
+    match bundle.refs.len() {
+        1 => writeln!(stdout, "The bundle contains this ref:")?,
+        count => writeln!(stdout, "The bundle contains these {count} refs:")?,
+    }
+
+    for (name, id) in &bundle.refs {
+        writeln!(stdout, "{id} {name}")?;
+    }
+
+    match bundle.prerequisites.len() {
+        0 => writeln!(stdout, "The bundle records a complete history.")?,
+        1 => writeln!(stdout, "The bundle requires this ref:")?,
+        count => writeln!(stdout, "The bundle requires these {count} refs:")?,
+    }
+
+    for prerequisite in &bundle.prerequisites {
+        writeln!(stdout, "{} {}", prerequisite.id, prerequisite.comment)?;
+    }
+
+    writeln!(stdout, "The bundle uses this hash algorithm: {}", bundle.object_format())?;
+
+    if let Some(filter) = bundle.filter() {
+        writeln!(stdout, "The bundle uses this filter: {filter}")?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use gitty::store::pack_stream::{verify_pack_files, VerifiedEntry};
+use crate::cli::VerifyPackArgs;
+use crate::context::Context;
+
+pub fn run(VerifyPackArgs { verbose, stat_only, packs }: VerifyPackArgs, _ctx: &Context)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut stdout = std::io::stdout().lock();
+    let mut bad = false;
+
+    for pack in packs {
+        // Either file of the pair may be named, or neither's extension
+        let base = match pack.extension().and_then(|ext| ext.to_str()) {
+            Some("idx" | "pack") => pack.with_extension(""),
+            _ => pack,
+        };
+
+        let pack_path = PathBuf::from(format!("{}.pack", base.display()));
+        let verification = verify_pack_files(format!("{}.idx", base.display()), &pack_path);
+
+        for problem in &verification.problems {
+            eprintln!("error: {problem}");
+        }
+
+        if verbose && !stat_only {
+            for entry in &verification.entries {
+                write_entry(&mut stdout, entry)?;
+            }
+        }
+
+        if verbose || stat_only {
+            write_chain_lengths(&mut stdout, &verification.entries)?;
+        }
+
+        if verbose {
+            match verification.problems.is_empty() {
+                true => writeln!(stdout, "{}: ok", pack_path.display())?,
+                false => writeln!(stdout, "{}: bad", pack_path.display())?,
+            }
+        }
+
+        bad |= !verification.problems.is_empty();
+    }
+
+    stdout.flush()?;
+
+    if bad {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Writes an entry as
+///
+///   <id> ' ' <type> ' ' <size> ' ' <size-in-pack> ' ' <offset> [ ' ' <depth> ' ' <base> ]
+///
+/// with the type padded to 6 characters.
+fn write_entry<W: Write>(out: &mut W, entry: &VerifiedEntry) -> std::io::Result<()> {
+    write!(out, "{} {:<6} {} {} {}", entry.id, entry.kind, entry.size, entry.packed_size, entry.offset)?;
+
+    if let Some((depth, base)) = entry.delta {
+        write!(out, " {depth} {base}")?;
+    }
+
+    writeln!(out)
+}
+
+/// How many entries are undeltified, and how many have each length of
+/// delta chain
+fn write_chain_lengths<W: Write>(out: &mut W, entries: &[VerifiedEntry]) -> std::io::Result<()> {
+    let mut lengths: BTreeMap<u32, usize> = BTreeMap::new();
+
+    for entry in entries {
+        *lengths.entry(entry.delta.map_or(0, |(depth, _)| depth)).or_default() += 1;
+    }
+
+    let plural = |count: usize| if count == 1 { "object" } else { "objects" };
+
+    for (length, count) in lengths {
+        match length {
+            0 => writeln!(out, "non delta: {count} {}", plural(count))?,
+            _ => writeln!(out, "chain length = {length}: {count} {}", plural(count))?,
+        }
+    }
+
+    Ok(())
+}
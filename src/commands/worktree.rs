@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+
+use gitty::date;
+use gitty::linked_worktree::{prune_worktrees, repair_worktree_at, repair_worktrees, Pruned, Repair};
+use crate::cli::{WorktreeArgs, WorktreeCommands, WorktreePruneArgs, WorktreeRepairArgs};
+use crate::context::Context;
+
+pub fn run(WorktreeArgs { command }: WorktreeArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        WorktreeCommands::Prune(args) => prune(args, ctx),
+        WorktreeCommands::Repair(args) => repair(args, ctx),
+    }
+}
+
+fn prune(WorktreePruneArgs { dry_run, expire }: WorktreePruneArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let expire = match expire {
+        Some(expire) => date::approxidate(&expire, date::now()).ok_or_else(|| format!("invalid expiry date '{expire}'"))?,
+        None => i64::MAX,
+    };
+
+    for Pruned { id, reason } in prune_worktrees(expire, dry_run)? {
+        if dry_run || ctx.verbose {
+            eprintln!("Removing worktrees/{id}: {reason}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Repairs the links between the main worktree and linked worktrees, which
+/// break when either is moved, reporting each as
+///
+///   "repair: " <path> ": " <what was wrong>
+fn repair(WorktreeRepairArgs { paths }: WorktreeRepairArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let paths = match paths.is_empty() {
+        true => vec![PathBuf::from(".")],
+        false => paths,
+    };
+
+    let mut repairs = repair_worktrees()?;
+
+    for path in &paths {
+        repairs.extend(repair_worktree_at(path)?);
+    }
+
+    let mut failed = false;
+
+    for Repair { path, message, error } in repairs {
+        if error {
+            eprintln!("error: {}: {message}", path.display());
+            failed = true;
+        } else if !ctx.quiet {
+            eprintln!("repair: {}: {message}", path.display());
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
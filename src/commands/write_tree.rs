@@ -0,0 +1,13 @@
+use gitty::store::index::Index;
+use crate::cli::WriteTreeArgs;
+use crate::context::Context;
+
+pub fn run(WriteTreeArgs { missing_ok }: WriteTreeArgs, _ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let index = Index::read().ok_or("Unable to read index")?;
+
+    let tree = index.write_tree(missing_ok).ok_or("Unable to write tree")?;
+
+    println!("{tree}");
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use gitty::config::{self, Config};
+use gitty::repo::git_path;
+use gitty::store::{GitObjectStore, LooseFormat, ZSTD_EXTENSION};
+use crate::cli::{ZstdStoreArgs, ZstdStoreCommands};
+use crate::context::Context;
+
+pub fn run(ZstdStoreArgs { command }: ZstdStoreArgs, ctx: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = git_path("config");
+
+    // The extension is set before converting and unset before converting
+    // back, so objects written meanwhile end up in the new format. Either
+    // can be read at any point.
+    let format = match command {
+        ZstdStoreCommands::Import => {
+            // Extensions are only honoured from version 1, which also keeps
+            // git from using a repository it can't read
+            config::set_value(&config_path, "core.repositoryformatversion", Some("1"))?;
+            config::set_value(&config_path, ZSTD_EXTENSION, Some("true"))?;
+            LooseFormat::Zstd
+        }
+        ZstdStoreCommands::Export => {
+            config::set_value(&config_path, ZSTD_EXTENSION, None)?;
+
+            let config = Config::from_file(&config_path).unwrap_or_default();
+
+            if !config.entries().any(|entry| entry.key.starts_with("extensions.")) {
+                config::set_value(&config_path, "core.repositoryformatversion", Some("0"))?;
+            }
+
+            LooseFormat::Zlib
+        }
+    };
+
+    let converted = GitObjectStore::convert_loose_objects(format)?;
+
+    if !ctx.quiet {
+        eprintln!("Converted {converted} loose objects");
+    }
+
+    Ok(())
+}
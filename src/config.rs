@@ -0,0 +1,568 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::lockfile::LockFile;
+use crate::repo::{git_path, RepoPaths};
+use crate::wildmatch::{wildmatch, WildmatchFlags};
+
+// How deeply included files may include others, which stops include loops
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// A parsed git config file.
+///
+/// Entries are kept in file order so that "last one wins" lookups and
+/// multivalued keys behave the same way they do in git.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    entries: Vec<ConfigEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigEntry {
+    /// Canonical key, eg. "core.bare" or "remote.origin.url".
+    ///
+    /// The section and variable name are lowercased, the subsection
+    /// (if any) is kept as written since it is case sensitive.
+    pub key: String,
+
+    /// `None` for a bare boolean key such as `[core] bare`
+    pub value: Option<String>,
+}
+
+impl Config {
+    /// Loads the config git sees in the repository: the system config,
+    /// then the global config, then `.git/config`, later files taking
+    /// precedence. The files each include follow the including entry.
+    ///
+    /// A missing config file is treated as an empty config. One which
+    /// can't be read or parsed is fatal, as it is in git, rather than
+    /// carrying on without the settings in it; `try_load` gives the error.
+    pub fn load() -> Config {
+        Config::try_load().unwrap_or_else(|err| {
+            eprintln!("fatal: {err}");
+            process::exit(128)
+        })
+    }
+
+    /// Loads the config as `load` does, or says which file, and where,
+    /// couldn't be read
+    pub fn try_load() -> io::Result<Config> {
+        let mut config = Config::default();
+
+        for path in config_files() {
+            config.read_with_includes(&path, 0)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Reads a single config file, without following its includes. A
+    /// file which can't be parsed is an `InvalidData` error holding a
+    /// `ConfigError`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+
+        Config::parse(&String::from_utf8_lossy(&data))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, ConfigError { path: Some(path.to_path_buf()), ..err }))
+    }
+
+    /// Appends the entries of the file at `path`, and those of the files
+    /// it includes, `depth` deep in includes already. Missing files are
+    /// skipped.
+    fn read_with_includes(&mut self, path: &Path, depth: usize) -> io::Result<()> {
+        let config = match Config::from_file(path) {
+            Ok(config) => config,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        for entry in config.entries {
+            let included = included_path(&entry, dir);
+            self.entries.push(entry);
+
+            let Some(included) = included else {
+                continue;
+            };
+
+            if depth == MAX_INCLUDE_DEPTH {
+                eprintln!("config: exceeded maximum include depth ({MAX_INCLUDE_DEPTH}) including {}", included.display());
+                continue;
+            }
+
+            self.read_with_includes(&included, depth + 1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Config file format:
+    ///   '[' <section> [ ' ' '"' <subsection> '"' ] ']'
+    ///   <name> [ '=' <value> ]
+    ///
+    /// '#' and ';' start a comment which runs until the end of the line,
+    /// unless they appear inside a quoted value.
+    pub fn parse(data: &str) -> Result<Config, ConfigError> {
+        let mut entries = Vec::new();
+        let mut section: Option<String> = None;
+
+        let mut chars = data.chars().peekable();
+
+        loop {
+            skip_whitespace(&mut chars);
+
+            let c = match chars.peek() {
+                Some(c) => *c,
+                None => break,
+            };
+
+            // Where this line starts, should it turn out to be bad
+            let rest: usize = chars.clone().map(char::len_utf8).sum();
+            let bad_line = || ConfigError { line: data[..data.len() - rest].matches('\n').count() + 1, path: None };
+
+            match c {
+                '\n' => { chars.next(); },
+                '#' | ';' => skip_line(&mut chars),
+                '[' => {
+                    chars.next();
+                    section = Some(parse_section_header(&mut chars).ok_or_else(bad_line)?);
+                },
+                _ => {
+                    let Some(section) = &section else {
+                        return Err(bad_line());
+                    };
+
+                    let (name, value) = parse_variable(&mut chars).ok_or_else(bad_line)?;
+
+                    entries.push(ConfigEntry {
+                        key: format!("{section}.{name}"),
+                        value,
+                    });
+                }
+            }
+        }
+
+        Ok(Config {
+            entries
+        })
+    }
+
+    /// The last value set for `key`.
+    ///
+    /// Bare boolean keys are returned as "true".
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = canonical_key(key);
+
+        self.entries.iter()
+            .rev()
+            .find(|entry| entry.key == key)
+            .map(|entry| entry.value.as_deref().unwrap_or("true"))
+    }
+
+    /// Every value set for `key`, in file order.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let key = canonical_key(key);
+
+        self.entries.iter()
+            .filter(|entry| entry.key == key)
+            .map(|entry| entry.value.as_deref().unwrap_or("true"))
+            .collect()
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        parse_bool(self.get(key)?)
+    }
+
+    /// A path valued key, with a leading "~/" expanded to `$HOME`
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        Some(expand_home(self.get(key)?))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &ConfigEntry> {
+        self.entries.iter()
+    }
+}
+
+/// A config file which couldn't be parsed, and the first line which
+/// didn't make sense
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub line: usize,
+    pub path: Option<PathBuf>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bad config line {}", self.line)?;
+
+        match &self.path {
+            Some(path) => write!(f, " in file {}", path.display()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Sets `key` in the config file at `path` to `value`, or with None
+/// removes every line setting it.
+///
+/// The last line setting the key is replaced, otherwise the key is added
+/// at the end of the last section it belongs in, which is added to the end
+/// of the file if there's none. The rest of the file is left as written.
+pub fn set_value(path: &Path, key: &str, value: Option<&str>) -> io::Result<()> {
+    let Some((_, written_name)) = key.rsplit_once('.') else {
+        return Err(io::Error::other(format!("key does not contain a section: {key}")));
+    };
+
+    // The name is written as given, though matched case insensitively
+    let key = canonical_key(key);
+    let (section, name) = key.rsplit_once('.').unwrap_or_default();
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+
+    // Lines setting the key, and the last line of the last section it
+    // belongs in
+    let mut matches = Vec::new();
+    let mut section_end = None;
+    let mut in_section = false;
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+
+        if let Some(header) = trimmed.strip_prefix('[') {
+            in_section = parse_section_header(&mut header.chars().peekable()).is_some_and(|header| header == section);
+        } else if in_section && variable_name(trimmed).is_some_and(|variable| variable == name) {
+            matches.push(i);
+        }
+
+        if in_section && !trimmed.is_empty() {
+            section_end = Some(i);
+        }
+    }
+
+    match (value, matches.last(), section_end) {
+        (Some(value), Some(&last), _) => lines[last] = format!("\t{written_name} = {}", quote_value(value)),
+        (Some(value), None, Some(end)) => lines.insert(end + 1, format!("\t{written_name} = {}", quote_value(value))),
+        (Some(value), None, None) => {
+            lines.push(section_header(section));
+            lines.push(format!("\t{written_name} = {}", quote_value(value)));
+        }
+        (None, _, _) => {
+            for &i in matches.iter().rev() {
+                lines.remove(i);
+            }
+        }
+    }
+
+    let mut lock = LockFile::acquire(path)?;
+
+    for line in &lines {
+        writeln!(lock, "{line}")?;
+    }
+
+    lock.commit()
+}
+
+/// The name of the variable a line sets, lowercased, None if it doesn't
+/// start with one
+fn variable_name(line: &str) -> Option<String> {
+    let end = line.find(|c: char| !c.is_ascii_alphanumeric() && c != '-').unwrap_or(line.len());
+    let rest = line[end..].trim_start();
+
+    let sets = end > 0 && (rest.is_empty() || rest.starts_with(['=', '#', ';']));
+    sets.then(|| line[..end].to_ascii_lowercase())
+}
+
+/// `[section]` or `[section "subsection"]` for a canonical section name
+fn section_header(section: &str) -> String {
+    match section.split_once('.') {
+        Some((section, subsection)) => format!("[{section} \"{}\"]", subsection.replace('\\', "\\\\").replace('"', "\\\"")),
+        None => format!("[{section}]"),
+    }
+}
+
+/// Quotes and escapes a value so it parses back as written
+fn quote_value(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+
+    let needs_quotes = value.starts_with([' ', '\t']) || value.ends_with([' ', '\t']) || value.contains(['#', ';']);
+
+    match needs_quotes {
+        true => format!("\"{escaped}\""),
+        false => escaped,
+    }
+}
+
+/// The config files `Config::load` reads, lowest precedence first.
+///
+/// The system config is `$GIT_CONFIG_SYSTEM` or `/etc/gitconfig`, skipped
+/// with `$GIT_CONFIG_NOSYSTEM`. The global config is `$GIT_CONFIG_GLOBAL`,
+/// or otherwise both `git/config` in the XDG config directory and
+/// `~/.gitconfig`.
+pub fn config_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    let no_system = env::var("GIT_CONFIG_NOSYSTEM").ok().and_then(|value| parse_bool(&value));
+
+    if no_system != Some(true) {
+        files.push(env::var_os("GIT_CONFIG_SYSTEM").map_or_else(|| PathBuf::from("/etc/gitconfig"), PathBuf::from));
+    }
+
+    match env::var_os("GIT_CONFIG_GLOBAL") {
+        Some(global) => files.push(PathBuf::from(global)),
+        None => {
+            files.extend(xdg_config_path("config"));
+            files.extend(env::var_os("HOME").map(|home| Path::new(&home).join(".gitconfig")));
+        }
+    }
+
+    files.push(git_path("config"));
+
+    files
+}
+
+/// `git/<name>` in `$XDG_CONFIG_HOME`, by default `~/.config`
+pub fn xdg_config_path(name: &str) -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(Path::new(&dir).join("git").join(name)),
+        None => Some(Path::new(&env::var_os("HOME")?).join(".config/git").join(name)),
+    }
+}
+
+/// A path with a leading "~/" expanded to `$HOME`
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The file an `include.path` entry includes, or an
+/// `includeIf.<condition>.path` entry whose condition holds, relative to
+/// `dir`, the directory of the file the entry is in
+fn included_path(entry: &ConfigEntry, dir: &Path) -> Option<PathBuf> {
+    let included = match entry.key.as_str() {
+        "include.path" => true,
+        key => key.strip_prefix("includeif.")
+            .and_then(|key| key.strip_suffix(".path"))
+            .is_some_and(|condition| condition_holds(condition, dir)),
+    };
+
+    if !included {
+        return None;
+    }
+
+    Some(dir.join(expand_home(entry.value.as_deref()?)))
+}
+
+/// Whether an includeIf condition holds. Only "gitdir:<pattern>" and its
+/// case insensitive "gitdir/i:<pattern>" are known, matching the git dir.
+///
+/// The pattern is a wildmatch pattern where "./" is relative to `dir`, a
+/// pattern not starting with '/' can match at any depth and one ending in
+/// '/' matches everything inside.
+fn condition_holds(condition: &str, dir: &Path) -> bool {
+    let (pattern, casefold) = match (condition.strip_prefix("gitdir:"), condition.strip_prefix("gitdir/i:")) {
+        (Some(pattern), _) => (pattern, false),
+        (_, Some(pattern)) => (pattern, true),
+        _ => return false,
+    };
+
+    let mut pattern = match pattern.strip_prefix("./") {
+        Some(rest) => dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()).join(rest),
+        None => expand_home(pattern),
+    }.to_string_lossy().into_owned();
+
+    if !pattern.starts_with('/') {
+        pattern.insert_str(0, "**/");
+    }
+
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+
+    let git_dir = RepoPaths::discover().git_dir;
+    let absolute = env::current_dir().map(|cwd| cwd.join(&git_dir)).unwrap_or_else(|_| git_dir.clone());
+
+    // Either the git dir as found or with symlinks resolved may match
+    [git_dir.canonicalize().ok(), Some(absolute)].into_iter()
+        .flatten()
+        .any(|git_dir| wildmatch(&pattern, git_dir.to_string_lossy().as_bytes(), WildmatchFlags { pathname: true, casefold }))
+}
+
+/// Git's notion of a boolean config value
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Lowercases the section and variable name of a dotted key, leaving
+/// the subsection untouched.
+pub fn canonical_key(key: &str) -> String {
+    let (Some(first_dot), Some(last_dot)) = (key.find('.'), key.rfind('.')) else {
+        return key.to_lowercase();
+    };
+
+    let section = &key[..first_dot];
+    let name = &key[last_dot + 1..];
+
+    if first_dot == last_dot {
+        return format!("{}.{}", section.to_lowercase(), name.to_lowercase());
+    }
+
+    let subsection = &key[first_dot + 1..last_dot];
+
+    format!("{}.{}.{}", section.to_lowercase(), subsection, name.to_lowercase())
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Chars) {
+    while chars.next_if(|&c| c == ' ' || c == '\t' || c == '\r').is_some() {}
+}
+
+fn skip_line(chars: &mut Chars) {
+    for c in chars.by_ref() {
+        if c == '\n' {
+            break;
+        }
+    }
+}
+
+// Parses everything after the opening '['
+fn parse_section_header(chars: &mut Chars) -> Option<String> {
+    let mut section = String::new();
+
+    loop {
+        match chars.next()? {
+            ']' => {
+                skip_whitespace(chars);
+                return Some(section.to_lowercase());
+            },
+            c if c.is_ascii_alphanumeric() || c == '-' || c == '.' => section.push(c),
+            ' ' | '\t' => break,
+            _ => return None,
+        }
+    }
+
+    skip_whitespace(chars);
+
+    // [section "subsection"]
+    if chars.next()? != '"' {
+        return None;
+    }
+
+    let mut subsection = String::new();
+
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => subsection.push(chars.next()?),
+            '\n' => return None,
+            c => subsection.push(c),
+        }
+    }
+
+    if chars.next()? != ']' {
+        return None;
+    }
+
+    Some(format!("{}.{}", section.to_lowercase(), subsection))
+}
+
+fn parse_variable(chars: &mut Chars) -> Option<(String, Option<String>)> {
+    let mut name = String::new();
+
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '-') {
+        name.push(c.to_ascii_lowercase());
+    }
+
+    if name.is_empty() {
+        return None;
+    }
+
+    skip_whitespace(chars);
+
+    match chars.peek() {
+        Some('=') => { chars.next(); },
+        Some('#') | Some(';') => {
+            skip_line(chars);
+            return Some((name, None));
+        },
+        Some('\n') | None => {
+            chars.next();
+            return Some((name, None));
+        },
+        Some(_) => return None,
+    }
+
+    Some((name, Some(parse_value(chars)?)))
+}
+
+fn parse_value(chars: &mut Chars) -> Option<String> {
+    let mut value = String::new();
+    let mut quoted = false;
+
+    // Whitespace is only kept if something other than whitespace follows it
+    let mut pending_space = String::new();
+
+    skip_whitespace(chars);
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => break,
+            '#' | ';' if !quoted => {
+                skip_line(chars);
+                break;
+            },
+            ' ' | '\t' | '\r' if !quoted => pending_space.push(c),
+            '"' => quoted = !quoted,
+            '\\' => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+
+                match chars.next()? {
+                    // Line continuation
+                    '\n' => {},
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'b' => { value.pop(); },
+                    '\\' => value.push('\\'),
+                    '"' => value.push('"'),
+                    _ => return None,
+                }
+            },
+            c => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+                value.push(c);
+            }
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+
+    Some(value)
+}
@@ -0,0 +1,90 @@
+use std::io::{IsTerminal, Write};
+
+use crate::cli::GlobalArgs;
+
+/// Output settings shared by every subcommand.
+///
+/// Regular output goes to stdout and is never affected by these, `quiet`
+/// silences informational messages, `verbose` enables extra detail and
+/// progress meters are drawn on stderr.
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub quiet: bool,
+    pub verbose: bool,
+    pub progress: bool,
+    pub timing: bool,
+}
+
+impl Context {
+    pub fn new(args: &GlobalArgs) -> Context {
+        // Like git, progress is shown by default only to a terminal
+        let progress = if args.progress {
+            true
+        } else if args.no_progress || args.quiet {
+            false
+        } else {
+            std::io::stderr().is_terminal()
+        };
+
+        Context {
+            quiet: args.quiet,
+            verbose: args.verbose,
+            progress,
+            timing: args.timing,
+        }
+    }
+
+    /// Starts a progress meter, `total` is the expected number of ticks if
+    /// it's known up front
+    pub fn progress(&self, title: &str, total: Option<usize>) -> Progress {
+        Progress {
+            title: title.to_string(),
+            total,
+            current: 0,
+            enabled: self.progress,
+        }
+    }
+}
+
+/// A progress meter, in the style of git's:
+///   "<title>: <percent>% (<current>/<total>)" or "<title>: <current>"
+pub struct Progress {
+    title: String,
+    total: Option<usize>,
+    current: usize,
+    enabled: bool,
+}
+
+impl Progress {
+    pub fn tick(&mut self) {
+        self.set(self.current + 1);
+    }
+
+    pub fn set(&mut self, current: usize) {
+        self.current = current;
+        self.display("\r");
+    }
+
+    pub fn finish(mut self) {
+        self.display(", done.\n");
+        self.enabled = false;
+    }
+
+    fn display(&self, end: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut stderr = std::io::stderr().lock();
+
+        let _ = match self.total {
+            Some(total) => {
+                let percent = (self.current * 100).checked_div(total).unwrap_or(100);
+                write!(stderr, "{}: {:3}% ({}/{}){}", self.title, percent, self.current, total, end)
+            },
+            None => write!(stderr, "{}: {}{}", self.title, self.current, end),
+        };
+
+        let _ = stderr.flush();
+    }
+}
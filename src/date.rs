@@ -0,0 +1,821 @@
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the epoch
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Counts approxidate takes as words, each at its value's index
+const NUMBER_NAMES: [&str; 11] = ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten"];
+
+/// Length of a relative date unit in seconds, as git approximates them
+fn unit_seconds(unit: &str) -> Option<i64> {
+    let unit = unit.strip_suffix('s').unwrap_or(unit);
+
+    match unit {
+        "second" | "sec" => Some(1),
+        "minute" | "min" => Some(60),
+        "hour" => Some(60 * 60),
+        "day" => Some(24 * 60 * 60),
+        "week" => Some(7 * 24 * 60 * 60),
+        "month" => Some(30 * 24 * 60 * 60),
+        "year" => Some(365 * 24 * 60 * 60),
+        _ => None,
+    }
+}
+
+/// Parses the relative dates used by expiry settings, relative to `now`.
+///
+/// Accepted forms:
+///   "now" / "all"                  everything up until now
+///   "never" / "false"              the epoch, ie. nothing is older
+///   "@" <seconds>                  a raw timestamp
+///   "yesterday"                    a day ago
+///   <n> ("." | " ") <unit> [ ("." | " ") "ago" ]
+///                                  eg. "90.days.ago", "2 weeks", where
+///                                  <n> may be a word up to ten, as in
+///                                  "two.days.ago"
+pub fn approxidate(date: &str, now: i64) -> Option<i64> {
+    let date = date.trim().to_lowercase();
+
+    match date.as_str() {
+        "now" | "all" => return Some(now),
+        "never" | "false" => return Some(0),
+        "yesterday" => return Some(now - unit_seconds("day")?),
+        _ => {}
+    }
+
+    if let Some(seconds) = date.strip_prefix('@') {
+        return seconds.parse().ok();
+    }
+
+    let mut words = date.split(['.', ' ']).filter(|word| !word.is_empty());
+
+    let count = words.next()?;
+    let count = match NUMBER_NAMES.iter().position(|&name| name == count) {
+        Some(count) => count as i64,
+        None => count.parse().ok()?,
+    };
+    let unit = unit_seconds(words.next()?)?;
+
+    match words.next() {
+        None | Some("ago") => {},
+        Some(_) => return None,
+    }
+
+    if words.next().is_some() {
+        return None;
+    }
+
+    Some(now - count * unit)
+}
+
+/// A relative date, as `approxidate` parses them, as (seconds since the
+/// epoch, offset east of UTC in seconds) in the local timezone
+pub fn parse_relative_date(date: &str, now: i64) -> Option<(i64, i32)> {
+    let time = approxidate(date, now)?;
+
+    Some((time, local_offset(time)))
+}
+
+/// Days since the epoch of a date in the proleptic Gregorian calendar
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Count from March so the leap day is at the end of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// The (year, month, day) of a number of days since the epoch
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+
+    (era * 400 + year_of_era + (month <= 2) as i64, month, day)
+}
+
+/// Day of the week, 0 is Sunday
+pub fn weekday(days: i64) -> u32 {
+    // The epoch was a Thursday
+    (days + 4).rem_euclid(7) as u32
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    (days_from_civil(year + (month == 12) as i64, month % 12 + 1, 1) - days_from_civil(year, month, 1)) as u32
+}
+
+/// How dates are shown, as given to `--date=<format>`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DateFormat {
+    /// "Thu Apr 7 15:13:13 2005 -0700"
+    Default,
+
+    /// "2005-04-07 15:13:13 -0700"
+    Iso,
+
+    /// "2005-04-07T15:13:13-07:00"
+    IsoStrict,
+
+    /// "Thu, 7 Apr 2005 15:13:13 -0700"
+    Rfc2822,
+
+    /// "2005-04-07"
+    Short,
+
+    /// "1112911993 -0700", as dates are stored
+    Raw,
+
+    /// "1112911993"
+    Unix,
+
+    /// "2 hours ago"
+    Relative,
+
+    /// Like Default, leaving out whatever is the same as now, eg. the year
+    /// for dates this year. Dates from today are shown relative.
+    Human,
+}
+
+/// A date format, and whether to show dates in the local timezone rather
+/// than the one they were recorded in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DateMode {
+    pub format: DateFormat,
+    pub local: bool,
+}
+
+impl DateMode {
+    /// Parses a `--date` value: a format name, optionally with a "-local"
+    /// suffix. "local" on its own is "default-local".
+    pub fn parse(name: &str) -> Option<DateMode> {
+        let (name, local) = match name.strip_suffix("-local") {
+            Some(name) => (name, true),
+            None if name == "local" => ("default", true),
+            None => (name, false),
+        };
+
+        let format = match name {
+            "default" => DateFormat::Default,
+            "iso" | "iso8601" => DateFormat::Iso,
+            "iso-strict" | "iso8601-strict" => DateFormat::IsoStrict,
+            "rfc" | "rfc2822" => DateFormat::Rfc2822,
+            "short" => DateFormat::Short,
+            "raw" => DateFormat::Raw,
+            "unix" => DateFormat::Unix,
+            "relative" => DateFormat::Relative,
+            "human" => DateFormat::Human,
+            _ => return None,
+        };
+
+        Some(DateMode { format, local })
+    }
+}
+
+impl Default for DateMode {
+    fn default() -> DateMode {
+        DateMode {
+            format: DateFormat::Default,
+            local: false,
+        }
+    }
+}
+
+const WEEKDAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun",
+    "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A time split into its calendar fields, in some timezone
+struct CivilTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    weekday: u32,
+    hour: i64,
+    minute: i64,
+    second: i64,
+}
+
+impl CivilTime {
+    fn new(time: i64, offset: i32) -> CivilTime {
+        let local = time + offset as i64;
+        let days = local.div_euclid(86400);
+        let seconds = local.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+
+        CivilTime {
+            year,
+            month,
+            day,
+            weekday: weekday(days),
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+            second: seconds % 60,
+        }
+    }
+}
+
+/// Formats `time`, recorded with the offset `offset`, as `mode` says.
+/// Relative and human dates are relative to `now`.
+pub fn format_date(time: i64, offset: i32, mode: DateMode, now: i64) -> String {
+    let offset = if mode.local { local_offset(time) } else { offset };
+    let tm = CivilTime::new(time, offset);
+
+    match mode.format {
+        DateFormat::Default => format_normal(time, offset, None, mode.local, now),
+        DateFormat::Human => format_normal(time, offset, Some(local_offset(now)), mode.local, now),
+        DateFormat::Iso => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+            tm.year, tm.month, tm.day, tm.hour, tm.minute, tm.second, format_offset(offset),
+        ),
+        DateFormat::IsoStrict => {
+            let zone = format_offset(offset);
+
+            format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}:{}",
+                tm.year, tm.month, tm.day, tm.hour, tm.minute, tm.second, &zone[..3], &zone[3..],
+            )
+        }
+        DateFormat::Rfc2822 => format!(
+            "{}, {} {} {} {:02}:{:02}:{:02} {}",
+            WEEKDAY_NAMES[tm.weekday as usize], tm.day, MONTH_NAMES[tm.month as usize - 1], tm.year,
+            tm.hour, tm.minute, tm.second, format_offset(offset),
+        ),
+        DateFormat::Short => format!("{:04}-{:02}-{:02}", tm.year, tm.month, tm.day),
+        DateFormat::Raw => format!("{time} {}", format_offset(offset)),
+        DateFormat::Unix => time.to_string(),
+        DateFormat::Relative => format_relative(time, now),
+    }
+}
+
+/// The default format, "Thu Apr 7 15:13:13 2005 -0700". The timezone is
+/// left out of local dates.
+///
+/// For human dates, `human_offset` is the local offset now, and fields
+/// which match the current date are left out: the year for dates this
+/// year, the date for those in the last few days (keeping the weekday),
+/// and everything for today, which is shown relative instead. Seconds are
+/// always left out, the timezone whenever the date is shown, and the
+/// weekday and time whenever the year is.
+fn format_normal(time: i64, offset: i32, human_offset: Option<i32>, local: bool, now: i64) -> String {
+    let tm = CivilTime::new(time, offset);
+    let mut hide_tz = local;
+    let mut hide_year = false;
+    let mut hide_date = false;
+    let mut hide_weekday = false;
+    let mut hide_time = false;
+    let mut hide_seconds = false;
+
+    if let Some(human_offset) = human_offset {
+        let today = CivilTime::new(now, human_offset);
+
+        hide_tz |= offset == human_offset;
+        hide_year = tm.year == today.year;
+
+        // Future dates are shown in full, they're likely a timezone
+        // difference rather than actually in the future
+        if hide_year && tm.month == today.month && tm.day <= today.day {
+            hide_date = tm.day + 5 > today.day;
+            hide_weekday = tm.day == today.day;
+        }
+
+        if hide_weekday {
+            return format_relative(time, now);
+        }
+
+        hide_seconds = true;
+        hide_tz |= !hide_date;
+        hide_weekday = !hide_year;
+        hide_time = !hide_year;
+    }
+
+    let mut out = Vec::new();
+
+    if !hide_weekday {
+        out.push(WEEKDAY_NAMES[tm.weekday as usize].to_string());
+    }
+
+    if !hide_date {
+        out.push(format!("{} {}", MONTH_NAMES[tm.month as usize - 1], tm.day));
+    }
+
+    if !hide_time {
+        match hide_seconds {
+            true => out.push(format!("{:02}:{:02}", tm.hour, tm.minute)),
+            false => out.push(format!("{:02}:{:02}:{:02}", tm.hour, tm.minute, tm.second)),
+        }
+    }
+
+    if !hide_year {
+        out.push(tm.year.to_string());
+    }
+
+    if !hide_tz {
+        out.push(format_offset(offset));
+    }
+
+    out.join(" ")
+}
+
+/// "<n> <unit>s ago", with each unit rounded as git does. Dates over a
+/// year but under five years old get months too, eg. "2 years, 3 months
+/// ago".
+pub fn format_relative(time: i64, now: i64) -> String {
+    if time > now {
+        return "in the future".to_string();
+    }
+
+    let plural = |count: i64, unit: &str| match count {
+        1 => format!("1 {unit}"),
+        count => format!("{count} {unit}s"),
+    };
+
+    let seconds = now - time;
+
+    if seconds < 90 {
+        return format!("{} ago", plural(seconds, "second"));
+    }
+
+    let minutes = (seconds + 30) / 60;
+
+    if minutes < 90 {
+        return format!("{} ago", plural(minutes, "minute"));
+    }
+
+    let hours = (minutes + 30) / 60;
+
+    if hours < 36 {
+        return format!("{} ago", plural(hours, "hour"));
+    }
+
+    let days = (hours + 12) / 24;
+
+    if days < 14 {
+        return format!("{} ago", plural(days, "day"));
+    }
+
+    if days < 70 {
+        return format!("{} ago", plural((days + 3) / 7, "week"));
+    }
+
+    if days < 365 {
+        return format!("{} ago", plural((days + 15) / 30, "month"));
+    }
+
+    if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+
+        return match months {
+            0 => format!("{} ago", plural(years, "year")),
+            months => format!("{}, {} ago", plural(years, "year"), plural(months, "month")),
+        };
+    }
+
+    format!("{} ago", plural((days + 183) / 365, "year"))
+}
+
+/// Formats an offset east of UTC in seconds as git does, eg. "+0130"
+pub fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.unsigned_abs() / 60;
+
+    format!("{sign}{:02}{:02}", minutes / 60, minutes % 60)
+}
+
+/// Parses "+hhmm", "-hh:mm" or "Z" to seconds east of UTC
+pub fn parse_offset(offset: &str) -> Option<i32> {
+    if offset == "Z" {
+        return Some(0);
+    }
+
+    let sign = match offset.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+
+    let digits = offset[1..].replace(':', "");
+
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun",
+    "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Parses an absolute date, as accepted in GIT_AUTHOR_DATE and friends,
+/// to (seconds since the epoch, offset east of UTC in seconds).
+///
+/// Accepted forms:
+///   <seconds> <offset>                 git's internal format
+///   "@" <seconds> [ <offset> ]
+///   YYYY-MM-DD [ ("T" | " ") hh:mm[:ss] ] [ <offset> ]     ISO 8601
+///   [ <weekday> ", " ] DD <month> YYYY hh:mm:ss <offset>   RFC 2822
+///
+/// Dates without an offset are taken to be in the local timezone.
+pub fn parse_date(date: &str) -> Option<(i64, i32)> {
+    let date = date.trim();
+
+    if let Some(rest) = date.strip_prefix('@') {
+        let mut fields = rest.split_whitespace();
+        let time = fields.next()?.parse().ok()?;
+
+        let offset = match fields.next() {
+            Some(offset) => parse_offset(offset)?,
+            None => local_offset(time),
+        };
+
+        return Some((time, offset));
+    }
+
+    if let Some((time, offset)) = date.split_once(' ') {
+        if let (Ok(time), Some(offset)) = (time.parse::<i64>(), parse_offset(offset)) {
+            return Some((time, offset));
+        }
+    }
+
+    parse_iso8601(date).or_else(|| parse_rfc2822(date))
+}
+
+fn parse_iso8601(date: &str) -> Option<(i64, i32)> {
+    let mut date = date.to_string();
+
+    // Split off a trailing "Z" or offset, which may or may not be spaced
+    let mut offset = None;
+
+    if let Some(stripped) = date.strip_suffix('Z') {
+        offset = Some(0);
+        date = stripped.to_string();
+    } else if let Some(pos) = date.rfind(['+', '-']).filter(|&pos| pos > 10) {
+        offset = Some(parse_offset(&date[pos..])?);
+        date = date[..pos].trim_end().to_string();
+    }
+
+    let (day, time) = match date.split_once(['T', ' ']) {
+        Some((day, time)) => (day, Some(time)),
+        None => (date.as_str(), None),
+    };
+
+    let mut day_fields = day.split('-');
+    let year: i64 = day_fields.next()?.parse().ok()?;
+    let month: u32 = day_fields.next()?.parse().ok()?;
+    let day: u32 = day_fields.next()?.parse().ok()?;
+
+    if day_fields.next().is_some() || !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let seconds = match time {
+        Some(time) => parse_time_of_day(time)?,
+        None => 0,
+    };
+
+    let local = days_from_civil(year, month, day) * 86400 + seconds;
+
+    Some(match offset {
+        Some(offset) => (local - offset as i64, offset),
+        None => local_to_utc(local),
+    })
+}
+
+fn parse_rfc2822(date: &str) -> Option<(i64, i32)> {
+    let date = match date.split_once(", ") {
+        Some((_weekday, rest)) => rest,
+        None => date,
+    };
+
+    let fields: Vec<&str> = date.split_whitespace().collect();
+    let [day, month, year, time, offset] = fields[..] else {
+        return None;
+    };
+
+    let month = MONTHS.iter().position(|m| month.to_lowercase().starts_with(m))? as u32 + 1;
+    let day: u32 = day.parse().ok()?;
+    let year: i64 = year.parse().ok()?;
+    let offset = parse_offset(offset)?;
+
+    let local = days_from_civil(year, month, day) * 86400 + parse_time_of_day(time)?;
+
+    Some((local - offset as i64, offset))
+}
+
+/// "hh:mm[:ss]" to seconds since midnight
+fn parse_time_of_day(time: &str) -> Option<i64> {
+    let mut fields = time.split(':');
+
+    let hours: i64 = fields.next()?.parse().ok()?;
+    let minutes: i64 = fields.next()?.parse().ok()?;
+    let seconds: i64 = fields.next().map_or(Some(0), |s| s.parse().ok())?;
+
+    if fields.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Converts a local wall clock time to (UTC time, offset)
+fn local_to_utc(local: i64) -> (i64, i32) {
+    // The offset at a time close enough to the answer is almost always the
+    // right one, except within an hour of a DST change
+    let offset = local_offset(local);
+    let offset = local_offset(local - offset as i64);
+
+    (local - offset as i64, offset)
+}
+
+/// The local timezone's offset east of UTC in seconds at `time`.
+///
+/// Like libc, this follows $TZ when set, either as a zoneinfo file name or
+/// as a POSIX TZ rule, and /etc/localtime otherwise. Anything that can't be
+/// understood is taken to be UTC.
+pub fn local_offset(time: i64) -> i32 {
+    let tz = std::env::var("TZ").ok();
+
+    let zoneinfo = match tz.as_deref() {
+        None => fs::read("/etc/localtime").ok(),
+        Some(tz) => {
+            let name = tz.strip_prefix(':').unwrap_or(tz);
+
+            if name.is_empty() {
+                return 0;
+            }
+
+            if name.starts_with('/') {
+                fs::read(name).ok()
+            } else if !name.contains("..") {
+                fs::read(Path::new("/usr/share/zoneinfo").join(name)).ok()
+            } else {
+                None
+            }
+        }
+    };
+
+    let offset = match (zoneinfo, tz) {
+        (Some(data), _) => tzif_offset(&data, time),
+        (None, Some(tz)) => PosixTz::parse(&tz).map(|rule| rule.offset(time)),
+        (None, None) => None,
+    };
+
+    offset.unwrap_or(0)
+}
+
+/// Looks up the offset at `time` in a compiled zoneinfo (TZif) file.
+///
+/// TZif format:
+///   44-byte header:
+///     "TZif", 1-byte version, 15 reserved bytes
+///     six 32-bit counts: isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt
+///   timecnt transition times (4 bytes each, 8 in the v2+ block)
+///   timecnt 1-byte indices into the local time types
+///   typecnt 6-byte local time types: 32-bit utoff, isdst, abbreviation index
+///   ...abbreviations, leap seconds and indicators
+///
+/// Version 2+ files repeat the header and data with 64-bit times, followed
+/// by a POSIX TZ rule for times after the last transition.
+fn tzif_offset(data: &[u8], time: i64) -> Option<i32> {
+    let header = |start: usize| -> Option<[usize; 6]> {
+        if data.get(start..start + 4)? != b"TZif" {
+            return None;
+        }
+
+        let mut counts = [0; 6];
+
+        for (i, count) in counts.iter_mut().enumerate() {
+            let at = start + 20 + i * 4;
+            *count = u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?) as usize;
+        }
+
+        Some(counts)
+    };
+
+    let block_len = |[isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt]: [usize; 6], time_size: usize| {
+        timecnt * time_size + timecnt + typecnt * 6 + charcnt + leapcnt * (time_size + 4) + isstdcnt + isutcnt
+    };
+
+    let v1_counts = header(0)?;
+    let version = *data.get(4)?;
+
+    let (start, counts, time_size) = if version >= b'2' {
+        let start = 44 + block_len(v1_counts, 4);
+        (start, header(start)?, 8)
+    } else {
+        (0, v1_counts, 4)
+    };
+
+    let [_, _, _, timecnt, typecnt, _] = counts;
+    let times_at = start + 44;
+    let indices_at = times_at + timecnt * time_size;
+    let types_at = indices_at + timecnt;
+
+    let transition = |i: usize| -> Option<i64> {
+        let at = times_at + i * time_size;
+        let bytes = data.get(at..at + time_size)?;
+
+        Some(match time_size {
+            8 => i64::from_be_bytes(bytes.try_into().ok()?),
+            _ => i32::from_be_bytes(bytes.try_into().ok()?) as i64,
+        })
+    };
+
+    let utoff = |index: usize| -> Option<i32> {
+        let at = types_at + index * 6;
+        Some(i32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+    };
+
+    if typecnt == 0 {
+        return None;
+    }
+
+    // Times after the last transition follow the footer rule, if any
+    let after_last = timecnt == 0 || transition(timecnt - 1)? <= time;
+
+    if after_last && version >= b'2' {
+        let footer_at = start + 44 + block_len(counts, 8);
+        let footer = data.get(footer_at..)?;
+        let footer = String::from_utf8_lossy(footer);
+
+        if let Some(rule) = PosixTz::parse(footer.trim_matches('\n')) {
+            return Some(rule.offset(time));
+        }
+    }
+
+    let mut current = None;
+
+    for i in 0..timecnt {
+        if transition(i)? > time {
+            break;
+        }
+
+        current = Some(i);
+    }
+
+    let type_index = match current {
+        Some(i) => *data.get(indices_at + i)? as usize,
+        None => 0,
+    };
+
+    utoff(type_index)
+}
+
+/// A DST transition rule, "M<month>.<week>.<weekday>[/<time>]": the
+/// weekday of the week-th week of the month (week 5 is the last one), at
+/// the given local time
+#[derive(Debug, Clone, Copy)]
+struct TransitionRule {
+    month: u32,
+    week: u32,
+    weekday: u32,
+    time: i64,
+}
+
+impl TransitionRule {
+    fn parse(rule: &str) -> Option<TransitionRule> {
+        let (date, time) = match rule.split_once('/') {
+            Some((date, time)) => (date, parse_posix_offset(time)? as i64),
+            None => (rule, 2 * 3600),
+        };
+
+        let mut fields = date.strip_prefix('M')?.split('.');
+        let mut field = |range: std::ops::RangeInclusive<u32>| fields.next()?.parse().ok().filter(|value| range.contains(value));
+
+        Some(TransitionRule {
+            month: field(1..=12)?,
+            week: field(1..=5)?,
+            weekday: field(0..=6)?,
+            time,
+        })
+    }
+
+    /// The local time of the transition in `year`, in seconds since the epoch
+    fn local_time(&self, year: i64) -> i64 {
+        let first = days_from_civil(year, self.month, 1);
+        let mut day = 1 + (self.weekday + 7 - weekday(first)) % 7 + (self.week - 1) * 7;
+
+        while day > days_in_month(year, self.month) {
+            day -= 7;
+        }
+
+        (first + day as i64 - 1) * 86400 + self.time
+    }
+}
+
+/// A POSIX TZ rule, eg. "CET-1CEST,M3.5.0,M10.5.0/3"
+#[derive(Debug, Clone, Copy)]
+struct PosixTz {
+    /// Offsets east of UTC, the rule itself counts west
+    std_offset: i32,
+    dst: Option<(i32, TransitionRule, TransitionRule)>,
+}
+
+impl PosixTz {
+    fn parse(tz: &str) -> Option<PosixTz> {
+        let (rest, rules) = match tz.split_once(',') {
+            Some((rest, rules)) => (rest, Some(rules)),
+            None => (tz, None),
+        };
+
+        let rest = skip_tz_name(rest)?;
+        let offset_len = rest.find(|c: char| c.is_ascii_alphabetic() || c == '<').unwrap_or(rest.len());
+        let std_offset = -parse_posix_offset(&rest[..offset_len])?;
+        let rest = &rest[offset_len..];
+
+        if rest.is_empty() {
+            return Some(PosixTz {
+                std_offset,
+                dst: None,
+            });
+        }
+
+        let rest = skip_tz_name(rest)?;
+
+        let dst_offset = if rest.is_empty() {
+            std_offset + 3600
+        } else {
+            -parse_posix_offset(rest)?
+        };
+
+        let (start, end) = rules?.split_once(',')?;
+
+        Some(PosixTz {
+            std_offset,
+            dst: Some((dst_offset, TransitionRule::parse(start)?, TransitionRule::parse(end)?)),
+        })
+    }
+
+    fn offset(&self, time: i64) -> i32 {
+        let Some((dst_offset, start, end)) = self.dst else {
+            return self.std_offset;
+        };
+
+        let (year, _, _) = civil_from_days((time + self.std_offset as i64).div_euclid(86400));
+
+        // Transition times are given in the local time in effect before them
+        let start = start.local_time(year) - self.std_offset as i64;
+        let end = end.local_time(year) - dst_offset as i64;
+
+        let in_dst = if start < end {
+            start <= time && time < end
+        } else {
+            // Southern hemisphere, DST spans the new year
+            time < end || start <= time
+        };
+
+        if in_dst { dst_offset } else { self.std_offset }
+    }
+}
+
+/// Skips a zone abbreviation, either alphabetic or quoted in <>
+fn skip_tz_name(tz: &str) -> Option<&str> {
+    if let Some(rest) = tz.strip_prefix('<') {
+        return Some(&rest[rest.find('>')? + 1..]);
+    }
+
+    let len = tz.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(tz.len());
+
+    (len >= 3).then(|| &tz[len..])
+}
+
+/// "[+-]hh[:mm[:ss]]" to seconds
+fn parse_posix_offset(offset: &str) -> Option<i32> {
+    let (sign, offset) = match offset.strip_prefix('-') {
+        Some(offset) => (-1, offset),
+        None => (1, offset.strip_prefix('+').unwrap_or(offset)),
+    };
+
+    let mut seconds = 0;
+    let mut unit = 3600;
+
+    for field in offset.split(':') {
+        seconds += field.parse::<i32>().ok()? * unit;
+        unit /= 60;
+    }
+
+    Some(sign * seconds)
+}
@@ -0,0 +1,1042 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Write};
+
+use crate::pathspec::Pathspec;
+use crate::quote::quote_path;
+use crate::store::{
+    tree::{MODE_BLOB, MODE_EXECUTABLE, MODE_GITLINK, MODE_TREE},
+    GitObjectData,
+    GitObjectStore,
+    ObjectId,
+};
+
+/// Lines of context around each hunk of a patch
+pub const CONTEXT_LINES: usize = 3;
+
+/// A path which differs between two trees, with its (mode, id) on each
+/// side, None on the side it's missing from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeChange {
+    pub path: String,
+    pub old: Option<(u32, ObjectId)>,
+    pub new: Option<(u32, ObjectId)>,
+}
+
+/// Every path which differs between the trees `old` and `new`, in path
+/// order. A missing tree is empty, eg. the parent of a root commit.
+pub fn diff_trees(old: Option<ObjectId>, new: Option<ObjectId>) -> Option<Vec<TreeChange>> {
+    diff_trees_in(old, new, &Pathspec::default())
+}
+
+/// Like `diff_trees`, but only the paths `pathspec` matches. Subtrees
+/// which are the same on both sides, or which the pathspec can't match
+/// anything in, aren't read.
+pub fn diff_trees_in(old: Option<ObjectId>, new: Option<ObjectId>, pathspec: &Pathspec) -> Option<Vec<TreeChange>> {
+    let mut changes = Vec::new();
+    diff_trees_into(old, new, "", pathspec, &mut changes)?;
+
+    // Whole paths sort the way trees are ordered, with a directory's
+    // entries where its name would be with a trailing '/'
+    changes.sort_by(|a: &TreeChange, b| a.path.cmp(&b.path));
+
+    Some(changes)
+}
+
+fn diff_trees_into(
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+    prefix: &str,
+    pathspec: &Pathspec,
+    changes: &mut Vec<TreeChange>,
+) -> Option<()> {
+    let (old, new) = (tree_entries(old)?, tree_entries(new)?);
+
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    for name in names {
+        let (old, new) = (old.get(name).copied(), new.get(name).copied());
+
+        if old == new {
+            continue;
+        }
+
+        let path = format!("{prefix}{name}");
+
+        // A tree on either side is compared with whatever tree is on the
+        // other, and a file with nothing
+        let is_tree = |entry: Option<(u32, ObjectId)>| entry.is_some_and(|(mode, _)| mode == MODE_TREE);
+        let tree = |entry: Option<(u32, ObjectId)>| entry.filter(|_| is_tree(entry)).map(|(_, id)| id);
+        let file = |entry: Option<(u32, ObjectId)>| entry.filter(|_| !is_tree(entry));
+
+        if (is_tree(old) || is_tree(new)) && pathspec.matches_dir(&path) {
+            diff_trees_into(tree(old), tree(new), &format!("{path}/"), pathspec, changes)?;
+        }
+
+        if (file(old).is_some() || file(new).is_some()) && pathspec.matches(&path) {
+            changes.push(TreeChange { path, old: file(old), new: file(new) });
+        }
+    }
+
+    Some(())
+}
+
+/// A tree's entries by name, as (mode, id) pairs, none for a missing tree
+fn tree_entries(tree: Option<ObjectId>) -> Option<BTreeMap<String, (u32, ObjectId)>> {
+    let Some(tree) = tree else {
+        return Some(BTreeMap::new());
+    };
+
+    let GitObjectData::Tree { entries } = GitObjectStore::get(tree)?.data else {
+        eprintln!("{tree} is not a tree");
+        return None;
+    };
+
+    let mut named = BTreeMap::new();
+
+    for entry in entries {
+        // Paths are compared as UTF-8 elsewhere, so anything else can't be
+        // represented alongside them
+        let Ok(name) = String::from_utf8(entry.path) else {
+            eprintln!("Skipping non UTF-8 path in tree {tree}");
+            continue;
+        };
+
+        named.insert(name, (entry.mode, entry.id));
+    }
+
+    Some(named)
+}
+
+/// Similarity scores are out of this, as in git
+pub const MAX_SCORE: u64 = 60000;
+
+/// How similar a file must be to a deleted one to be taken as a rename of
+/// it, git's default of 50%
+pub const DEFAULT_RENAME_SCORE: u64 = MAX_SCORE / 2;
+
+// The span hashes are bucketed modulo this prime
+const HASHBASE: u32 = 107927;
+
+/// A path which `detect_renames` found to have been moved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+    pub score: u64,
+}
+
+/// Pairs the paths `changes` delete with those they add where the added
+/// file looks like the deleted one moved, as git's rename detection does.
+///
+/// Identical files are paired first, then the rest by how similar they
+/// are, most similar first, as long as they score `min_score` or more.
+/// Each path is only used once.
+pub fn detect_renames(changes: &[TreeChange], min_score: u64) -> Option<Vec<Rename>> {
+    let is_file = |mode: u32| mode == MODE_BLOB || mode == MODE_EXECUTABLE;
+
+    let mut deleted: Vec<(&str, ObjectId)> = changes.iter()
+        .filter(|change| change.new.is_none())
+        .filter_map(|change| change.old.filter(|&(mode, _)| is_file(mode)).map(|(_, id)| (change.path.as_str(), id)))
+        .collect();
+
+    let mut added: Vec<(&str, ObjectId)> = changes.iter()
+        .filter(|change| change.old.is_none())
+        .filter_map(|change| change.new.filter(|&(mode, _)| is_file(mode)).map(|(_, id)| (change.path.as_str(), id)))
+        .collect();
+
+    let mut renames = Vec::new();
+
+    added.retain(|&(to, id)| {
+        let Some(i) = deleted.iter().position(|&(_, from_id)| from_id == id) else {
+            return true;
+        };
+
+        let (from, _) = deleted.remove(i);
+        renames.push(Rename { from: from.to_string(), to: to.to_string(), score: MAX_SCORE });
+        false
+    });
+
+    if added.is_empty() || deleted.is_empty() {
+        return Some(renames);
+    }
+
+    let read = |id: ObjectId| -> Option<Vec<u8>> {
+        match GitObjectStore::get(id)?.data {
+            GitObjectData::Blob { data } => Some(data),
+            _ => None,
+        }
+    };
+
+    let deleted_data = deleted.iter().map(|&(_, id)| read(id)).collect::<Option<Vec<_>>>()?;
+    let added_data = added.iter().map(|&(_, id)| read(id)).collect::<Option<Vec<_>>>()?;
+
+    let basename = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+
+    // (score, same basename, added, deleted), best first
+    let mut candidates = Vec::new();
+
+    for (i, new) in added_data.iter().enumerate() {
+        for (j, old) in deleted_data.iter().enumerate() {
+            let score = similarity(old, new, min_score);
+
+            if score >= min_score {
+                candidates.push((score, basename(added[i].0) == basename(deleted[j].0), i, j));
+            }
+        }
+    }
+
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)).then((a.2, a.3).cmp(&(b.2, b.3))));
+
+    let mut added_used = vec![false; added.len()];
+    let mut deleted_used = vec![false; deleted.len()];
+
+    for (score, _, i, j) in candidates {
+        if added_used[i] || deleted_used[j] {
+            continue;
+        }
+
+        added_used[i] = true;
+        deleted_used[j] = true;
+
+        renames.push(Rename { from: deleted[j].0.to_string(), to: added[i].0.to_string(), score });
+    }
+
+    Some(renames)
+}
+
+/// How much of `dst` could have been copied from `src`, out of
+/// `MAX_SCORE`, estimated as git does: both are cut into lines, or 64 byte
+/// spans of longer ones, and the bytes in spans they share are counted
+/// against the size of the larger file.
+///
+/// Files whose sizes alone rule out reaching `min_score` score 0.
+pub fn similarity(src: &[u8], dst: &[u8], min_score: u64) -> u64 {
+    let max_size = src.len().max(dst.len()) as u64;
+    let delta_size = max_size - src.len().min(dst.len()) as u64;
+
+    if dst.is_empty() || max_size * (MAX_SCORE - min_score) < delta_size * MAX_SCORE {
+        return 0;
+    }
+
+    let src_spans = span_hashes(src);
+    let dst_spans = span_hashes(dst);
+
+    let copied: u64 = src_spans.iter()
+        .filter_map(|(hash, &src_count)| dst_spans.get(hash).map(|&dst_count| src_count.min(dst_count)))
+        .sum();
+
+    copied * MAX_SCORE / max_size
+}
+
+/// The number of bytes in each span of `data`, by the span's hash. A CR
+/// before a LF isn't counted in text.
+fn span_hashes(data: &[u8]) -> HashMap<u32, u64> {
+    let is_text = !is_binary(data);
+    let mut spans = HashMap::new();
+
+    let (mut accum1, mut accum2, mut n) = (0u32, 0u32, 0u64);
+
+    for (i, &c) in data.iter().enumerate() {
+        if is_text && c == b'\r' && data.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+
+        let old_1 = accum1;
+        accum1 = (accum1 << 7) ^ (accum2 >> 25);
+        accum2 = (accum2 << 7) ^ (old_1 >> 25);
+        accum1 = accum1.wrapping_add(c as u32);
+        n += 1;
+
+        if n < 64 && c != b'\n' {
+            continue;
+        }
+
+        *spans.entry(accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASHBASE).or_default() += n;
+
+        (accum1, accum2, n) = (0, 0, 0);
+    }
+
+    if n > 0 {
+        *spans.entry(accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASHBASE).or_default() += n;
+    }
+
+    spans
+}
+
+/// A run of changed lines: `old_len` lines of the old file from
+/// `old_start` were replaced by `new_len` lines of the new one from
+/// `new_start`. Either side may be empty.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+}
+
+/// Splits `data` into lines, each keeping its newline. The last line
+/// lacks one if the data doesn't end with a newline.
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&byte| byte == b'\n').collect()
+}
+
+/// Whether `data` looks binary, as git decides it: a NUL in the first
+/// 8000 bytes
+pub fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(8000)].contains(&0)
+}
+
+/// The changes turning the lines `old` into `new`.
+///
+/// This is a minimal diff, found with Myers' algorithm, after which runs
+/// of changes are slid to where they read best, as git does: next to a
+/// change in the other file if they can be, otherwise where the indent
+/// heuristic scores highest.
+pub fn diff_lines<'a>(old: &[&'a [u8]], new: &[&'a [u8]]) -> Vec<Change> {
+    diff_lines_sliding(old, new, true)
+}
+
+/// Like `diff_lines`, but without the indent heuristic, so runs of
+/// changes which could go anywhere are slid as far down as they go. This
+/// is how git diffs for a merge.
+pub fn diff_lines_without_indent_heuristic<'a>(old: &[&'a [u8]], new: &[&'a [u8]]) -> Vec<Change> {
+    diff_lines_sliding(old, new, false)
+}
+
+fn diff_lines_sliding<'a>(old: &[&'a [u8]], new: &[&'a [u8]], indent_heuristic: bool) -> Vec<Change> {
+    // Lines are compared by number, equal lines get the same one
+    let mut numbers: HashMap<&'a [u8], usize> = HashMap::new();
+
+    let mut number = |line: &'a [u8]| -> usize {
+        let next = numbers.len();
+        *numbers.entry(line).or_insert(next)
+    };
+
+    let a: Vec<usize> = old.iter().map(|line| number(line)).collect();
+    let b: Vec<usize> = new.iter().map(|line| number(line)).collect();
+
+    let mut changed_a = vec![false; a.len()];
+    let mut changed_b = vec![false; b.len()];
+
+    compare(&a, &b, &mut changed_a, &mut changed_b);
+
+    compact(old, &a, &mut changed_a, &mut changed_b, indent_heuristic);
+    compact(new, &b, &mut changed_b, &mut changed_a, indent_heuristic);
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && !changed_a[i] && !changed_b[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let (old_start, new_start) = (i, j);
+
+        while i < a.len() && changed_a[i] {
+            i += 1;
+        }
+
+        while j < b.len() && changed_b[j] {
+            j += 1;
+        }
+
+        changes.push(Change {
+            old_start,
+            old_len: i - old_start,
+            new_start,
+            new_len: j - new_start,
+        });
+    }
+
+    changes
+}
+
+/// Marks the lines of `a` and `b` which aren't part of a longest common
+/// subsequence, splitting the problem in two at the middle of an optimal
+/// edit path until what's left is trivial
+fn compare(a: &[usize], b: &[usize], changed_a: &mut [bool], changed_b: &mut [bool]) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let (changed_a, changed_b) = (&mut changed_a[prefix..], &mut changed_b[prefix..]);
+
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+    let (changed_a, changed_b) = (&mut changed_a[..a.len()], &mut changed_b[..b.len()]);
+
+    if a.is_empty() || b.is_empty() {
+        changed_a.fill(true);
+        changed_b.fill(true);
+        return;
+    }
+
+    // A split at either end wouldn't make the problem any smaller
+    let Some((x, y)) = middle_snake(a, b).filter(|&split| split != (0, 0) && split != (a.len(), b.len())) else {
+        changed_a.fill(true);
+        changed_b.fill(true);
+        return;
+    };
+
+    let (changed_a_head, changed_a_tail) = changed_a.split_at_mut(x);
+    let (changed_b_head, changed_b_tail) = changed_b.split_at_mut(y);
+
+    compare(&a[..x], &b[..y], changed_a_head, changed_b_head);
+    compare(&a[x..], &b[y..], changed_a_tail, changed_b_tail);
+}
+
+/// A point (x, y) on an optimal edit path between `a` and `b`, found by
+/// searching forwards from the start and backwards from the end at once
+/// until the two searches overlap. None if nothing is in common.
+fn middle_snake(a: &[usize], b: &[usize]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let len = 2 * max_d as usize + 2;
+
+    // Furthest x reached on each diagonal k = x - y, forwards and backwards
+    let mut forward = vec![-1isize; len];
+    let mut backward = vec![-1isize; len];
+    forward[offset as usize + 1] = 0;
+    backward[offset as usize + 1] = 0;
+
+    let delta = n - m;
+    let front = delta % 2 != 0;
+
+    // Diagonals which have run off the edges aren't searched further
+    let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+
+    for d in 0..max_d {
+        let mut k1 = -d + k1_start;
+
+        while k1 <= d - k1_end {
+            let k1_offset = (offset + k1) as usize;
+
+            let mut x1 = if k1 == -d || (k1 != d && forward[k1_offset - 1] < forward[k1_offset + 1]) {
+                forward[k1_offset + 1]
+            } else {
+                forward[k1_offset - 1] + 1
+            };
+
+            let mut y1 = x1 - k1;
+
+            while x1 < n && y1 < m && a[x1 as usize] == b[y1 as usize] {
+                x1 += 1;
+                y1 += 1;
+            }
+
+            forward[k1_offset] = x1;
+
+            if x1 > n {
+                k1_end += 2;
+            } else if y1 > m {
+                k1_start += 2;
+            } else if front {
+                let k2_offset = offset + delta - k1;
+
+                if k2_offset >= 0 && k2_offset < len as isize && backward[k2_offset as usize] != -1 {
+                    let x2 = n - backward[k2_offset as usize];
+
+                    if x1 >= x2 {
+                        return Some((x1 as usize, y1 as usize));
+                    }
+                }
+            }
+
+            k1 += 2;
+        }
+
+        let mut k2 = -d + k2_start;
+
+        while k2 <= d - k2_end {
+            let k2_offset = (offset + k2) as usize;
+
+            let mut x2 = if k2 == -d || (k2 != d && backward[k2_offset - 1] < backward[k2_offset + 1]) {
+                backward[k2_offset + 1]
+            } else {
+                backward[k2_offset - 1] + 1
+            };
+
+            let mut y2 = x2 - k2;
+
+            while x2 < n && y2 < m && a[(n - x2 - 1) as usize] == b[(m - y2 - 1) as usize] {
+                x2 += 1;
+                y2 += 1;
+            }
+
+            backward[k2_offset] = x2;
+
+            if x2 > n {
+                k2_end += 2;
+            } else if y2 > m {
+                k2_start += 2;
+            } else if !front {
+                let k1_offset = offset + delta - k2;
+
+                if k1_offset >= 0 && k1_offset < len as isize && forward[k1_offset as usize] != -1 {
+                    let x1 = forward[k1_offset as usize];
+                    let y1 = offset + x1 - k1_offset;
+
+                    if x1 >= n - x2 {
+                        return Some((x1 as usize, y1 as usize));
+                    }
+                }
+            }
+
+            k2 += 2;
+        }
+    }
+
+    None
+}
+
+/// A run of changed lines in one file, `start..end`, as the sliding in
+/// `compact` moves them about
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl Group {
+    fn first(changed: &[bool]) -> Group {
+        let mut group = Group { start: 0, end: 0 };
+        group.extend_end(changed);
+        group
+    }
+
+    fn extend_end(&mut self, changed: &[bool]) {
+        while self.end < changed.len() && changed[self.end] {
+            self.end += 1;
+        }
+    }
+
+    fn extend_start(&mut self, changed: &[bool]) {
+        while self.start > 0 && changed[self.start - 1] {
+            self.start -= 1;
+        }
+    }
+
+    /// Moves on to the next group, which may be empty. False at the end.
+    fn next(&mut self, changed: &[bool]) -> bool {
+        if self.end == changed.len() {
+            return false;
+        }
+
+        self.start = self.end + 1;
+        self.end = self.start;
+        self.extend_end(changed);
+
+        true
+    }
+
+    fn previous(&mut self, changed: &[bool]) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+
+        self.end = self.start - 1;
+        self.start = self.end;
+        self.extend_start(changed);
+
+        true
+    }
+
+    /// Slides the group down a line, if the line after it matches its first
+    fn slide_down(&mut self, lines: &[usize], changed: &mut [bool]) -> bool {
+        if self.end >= lines.len() || lines[self.start] != lines[self.end] {
+            return false;
+        }
+
+        changed[self.start] = false;
+        changed[self.end] = true;
+        self.start += 1;
+        self.end += 1;
+        self.extend_end(changed);
+
+        true
+    }
+
+    /// Slides the group up a line, if the line before it matches its last
+    fn slide_up(&mut self, lines: &[usize], changed: &mut [bool]) -> bool {
+        if self.start == 0 || lines[self.start - 1] != lines[self.end - 1] {
+            return false;
+        }
+
+        self.start -= 1;
+        self.end -= 1;
+        changed[self.start] = true;
+        changed[self.end] = false;
+        self.extend_start(changed);
+
+        true
+    }
+}
+
+// Past this, sliding a group for the indent heuristic isn't worth it
+const INDENT_HEURISTIC_MAX_SLIDING: usize = 100;
+
+/// Slides each run of changes in one file as git's xdiff does: merging it
+/// with any runs it can be slid into, then placing it next to a change in
+/// the other file if possible, and where the indent heuristic scores best
+/// otherwise, if it's used. Runs in `other` are kept in step.
+fn compact(text: &[&[u8]], lines: &[usize], changed: &mut [bool], other: &mut [bool], indent_heuristic: bool) {
+    let mut group = Group::first(changed);
+    let mut other_group = Group::first(other);
+
+    loop {
+        if group.end != group.start {
+            let mut earliest_end;
+            let mut end_matching_other;
+            let mut size;
+
+            loop {
+                size = group.end - group.start;
+                end_matching_other = None;
+
+                while group.slide_up(lines, changed) {
+                    other_group.previous(other);
+                }
+
+                earliest_end = group.end;
+
+                if other_group.end > other_group.start {
+                    end_matching_other = Some(group.end);
+                }
+
+                while group.slide_down(lines, changed) {
+                    other_group.next(other);
+
+                    if other_group.end > other_group.start {
+                        end_matching_other = Some(group.end);
+                    }
+                }
+
+                if size == group.end - group.start {
+                    break;
+                }
+            }
+
+            if group.end == earliest_end {
+                // The group can't move
+            } else if end_matching_other.is_some() {
+                while other_group.end == other_group.start {
+                    group.slide_up(lines, changed);
+                    other_group.previous(other);
+                }
+            } else if indent_heuristic {
+                let mut shift = earliest_end
+                    .max(group.end.saturating_sub(size + 1))
+                    .max(group.end.saturating_sub(INDENT_HEURISTIC_MAX_SLIDING));
+
+                let mut best: Option<(usize, SplitScore)> = None;
+
+                while shift <= group.end {
+                    let mut score = SplitScore::default();
+                    score.add(&SplitMeasurement::new(text, shift));
+                    score.add(&SplitMeasurement::new(text, shift - size));
+
+                    if best.as_ref().is_none_or(|(_, best)| score.cmp(best) <= 0) {
+                        best = Some((shift, score));
+                    }
+
+                    shift += 1;
+                }
+
+                if let Some((best_shift, _)) = best {
+                    while group.end > best_shift {
+                        group.slide_up(lines, changed);
+                        other_group.previous(other);
+                    }
+                }
+            }
+        }
+
+        if !group.next(changed) {
+            break;
+        }
+
+        other_group.next(other);
+    }
+}
+
+// The indent heuristic's weights, as tuned for git
+const MAX_INDENT: i32 = 200;
+const MAX_BLANKS: i32 = 20;
+const START_OF_FILE_PENALTY: i32 = 1;
+const END_OF_FILE_PENALTY: i32 = 21;
+const TOTAL_BLANK_WEIGHT: i32 = -30;
+const POST_BLANK_WEIGHT: i32 = 6;
+const RELATIVE_INDENT_PENALTY: i32 = -4;
+const RELATIVE_INDENT_WITH_BLANK_PENALTY: i32 = 10;
+const RELATIVE_OUTDENT_PENALTY: i32 = 24;
+const RELATIVE_OUTDENT_WITH_BLANK_PENALTY: i32 = 17;
+const RELATIVE_DEDENT_PENALTY: i32 = 23;
+const RELATIVE_DEDENT_WITH_BLANK_PENALTY: i32 = 17;
+const INDENT_WEIGHT: i32 = 60;
+
+/// The indent of a line, counting tabs to the next multiple of 8. None if
+/// it's blank.
+fn indent(line: &[u8]) -> Option<i32> {
+    let mut indent = 0;
+
+    for &byte in line {
+        if !byte.is_ascii_whitespace() && byte != 0x0b {
+            return Some(indent);
+        }
+
+        match byte {
+            b' ' => indent += 1,
+            b'\t' => indent += 8 - indent % 8,
+            _ => {}
+        }
+
+        if indent >= MAX_INDENT {
+            return Some(MAX_INDENT);
+        }
+    }
+
+    None
+}
+
+/// The surroundings of a split between lines, just before `split`
+struct SplitMeasurement {
+    end_of_file: bool,
+    indent: Option<i32>,
+    pre_blank: i32,
+    pre_indent: Option<i32>,
+    post_blank: i32,
+    post_indent: Option<i32>,
+}
+
+impl SplitMeasurement {
+    fn new(text: &[&[u8]], split: usize) -> SplitMeasurement {
+        let mut measurement = SplitMeasurement {
+            end_of_file: split >= text.len(),
+            indent: text.get(split).and_then(|line| indent(line)),
+            pre_blank: 0,
+            pre_indent: None,
+            post_blank: 0,
+            post_indent: None,
+        };
+
+        for line in text[..split.min(text.len())].iter().rev() {
+            measurement.pre_indent = indent(line);
+
+            if measurement.pre_indent.is_some() {
+                break;
+            }
+
+            measurement.pre_blank += 1;
+
+            if measurement.pre_blank == MAX_BLANKS {
+                measurement.pre_indent = Some(0);
+                break;
+            }
+        }
+
+        for line in text.iter().skip(split + 1) {
+            measurement.post_indent = indent(line);
+
+            if measurement.post_indent.is_some() {
+                break;
+            }
+
+            measurement.post_blank += 1;
+
+            if measurement.post_blank == MAX_BLANKS {
+                measurement.post_indent = Some(0);
+                break;
+            }
+        }
+
+        measurement
+    }
+}
+
+#[derive(Default)]
+struct SplitScore {
+    effective_indent: i32,
+    penalty: i32,
+}
+
+impl SplitScore {
+    fn add(&mut self, m: &SplitMeasurement) {
+        if m.pre_indent.is_none() && m.pre_blank == 0 {
+            self.penalty += START_OF_FILE_PENALTY;
+        }
+
+        if m.end_of_file {
+            self.penalty += END_OF_FILE_PENALTY;
+        }
+
+        let post_blank = match m.indent {
+            None => 1 + m.post_blank,
+            Some(_) => 0,
+        };
+
+        let total_blank = m.pre_blank + post_blank;
+
+        self.penalty += TOTAL_BLANK_WEIGHT * total_blank;
+        self.penalty += POST_BLANK_WEIGHT * post_blank;
+
+        let indent = m.indent.or(m.post_indent);
+        let any_blanks = total_blank != 0;
+
+        self.effective_indent += indent.unwrap_or(-1);
+
+        let (Some(indent), Some(pre_indent)) = (indent, m.pre_indent) else {
+            return;
+        };
+
+        if indent > pre_indent {
+            self.penalty += match any_blanks {
+                true => RELATIVE_INDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_INDENT_PENALTY,
+            };
+        } else if indent < pre_indent {
+            let outdent = m.post_indent.is_some_and(|post_indent| post_indent > indent);
+
+            self.penalty += match (outdent, any_blanks) {
+                (true, true) => RELATIVE_OUTDENT_WITH_BLANK_PENALTY,
+                (true, false) => RELATIVE_OUTDENT_PENALTY,
+                (false, true) => RELATIVE_DEDENT_WITH_BLANK_PENALTY,
+                (false, false) => RELATIVE_DEDENT_PENALTY,
+            };
+        }
+    }
+
+    /// Negative if `self` is the better split
+    fn cmp(&self, other: &SplitScore) -> i32 {
+        let indents = (self.effective_indent > other.effective_indent) as i32
+            - (self.effective_indent < other.effective_indent) as i32;
+
+        INDENT_WEIGHT * indents + (self.penalty - other.penalty)
+    }
+}
+
+/// Writes the unified diff hunks turning `old` into `new`, with
+/// `CONTEXT_LINES` lines of context. Changes close enough for their
+/// context to touch share a hunk.
+pub fn write_hunks<W: Write>(out: &mut W, old: &[&[u8]], new: &[&[u8]]) -> io::Result<()> {
+    let changes = diff_lines(old, new);
+    let mut i = 0;
+
+    while i < changes.len() {
+        // Changes at most this far apart are joined into one hunk
+        let mut last = i;
+
+        while last + 1 < changes.len() {
+            let gap = changes[last + 1].old_start - (changes[last].old_start + changes[last].old_len);
+
+            if gap > 2 * CONTEXT_LINES {
+                break;
+            }
+
+            last += 1;
+        }
+
+        let (first, end) = (&changes[i], &changes[last]);
+
+        let old_start = first.old_start.saturating_sub(CONTEXT_LINES);
+        let new_start = first.new_start.saturating_sub(CONTEXT_LINES);
+        let old_end = (end.old_start + end.old_len + CONTEXT_LINES).min(old.len());
+        let new_end = (end.new_start + end.new_len + CONTEXT_LINES).min(new.len());
+
+        write!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start),
+        )?;
+
+        if let Some(function) = function_line(&old[..old_start]) {
+            out.write_all(b" ")?;
+            out.write_all(function)?;
+        }
+
+        out.write_all(b"\n")?;
+
+        let mut old_line = old_start;
+
+        for change in &changes[i..=last] {
+            for line in &old[old_line..change.old_start] {
+                write_line(out, b' ', line)?;
+            }
+
+            for line in &old[change.old_start..change.old_start + change.old_len] {
+                write_line(out, b'-', line)?;
+            }
+
+            for line in &new[change.new_start..change.new_start + change.new_len] {
+                write_line(out, b'+', line)?;
+            }
+
+            old_line = change.old_start + change.old_len;
+        }
+
+        for line in &old[old_line..old_end] {
+            write_line(out, b' ', line)?;
+        }
+
+        i = last + 1;
+    }
+
+    Ok(())
+}
+
+/// A hunk header's "<start>,<count>", 1-based. A count of 1 is left out,
+/// and an empty range starts at the line before it.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        count => format!("{},{count}", start + 1),
+    }
+}
+
+/// The last of `lines` which looks like the start of a function, ie.
+/// starts with a letter, '_' or '$', cut to 80 bytes and with trailing
+/// whitespace removed
+fn function_line<'a>(lines: &[&'a [u8]]) -> Option<&'a [u8]> {
+    let line = lines.iter()
+        .rev()
+        .find(|line| line.first().is_some_and(|&byte| byte.is_ascii_alphabetic() || byte == b'_' || byte == b'$'))?;
+
+    let line = &line[..line.len().min(80)];
+    let end = line.iter().rposition(|byte| !byte.is_ascii_whitespace()).map_or(0, |pos| pos + 1);
+
+    Some(&line[..end])
+}
+
+fn write_line<W: Write>(out: &mut W, prefix: u8, line: &[u8]) -> io::Result<()> {
+    out.write_all(&[prefix])?;
+    out.write_all(line)?;
+
+    if !line.ends_with(b"\n") {
+        out.write_all(b"\n\\ No newline at end of file\n")?;
+    }
+
+    Ok(())
+}
+
+/// Writes the patch for one changed path, as `git diff` shows it: the
+/// "diff --git" header, any mode lines, the blob ids and the hunks.
+///
+/// A change of file type, eg. a file becoming a symlink, is shown as the
+/// old file's deletion and the new one's creation.
+pub fn write_patch<W: Write>(out: &mut W, change: &TreeChange, quote_non_ascii: bool) -> io::Result<()> {
+    if let (Some(old), Some(new)) = (change.old, change.new) {
+        if old.0 & 0o170000 != new.0 & 0o170000 {
+            let deletion = TreeChange { new: None, ..change.clone() };
+            let creation = TreeChange { old: None, ..change.clone() };
+
+            write_patch(out, &deletion, quote_non_ascii)?;
+            return write_patch(out, &creation, quote_non_ascii);
+        }
+    }
+
+    // Contents are only needed when they differ
+    let same = change.old.map(|(_, id)| id) == change.new.map(|(_, id)| id);
+
+    let contents = |side: Option<(u32, ObjectId)>| match side {
+        Some(side) if !same => side_contents(side),
+        _ => Ok(Vec::new()),
+    };
+
+    let (old_data, new_data) = (contents(change.old)?, contents(change.new)?);
+
+    let file = |(mode, id), data| PatchFile { path: &change.path, mode, id, data };
+    let old = change.old.map(|side| file(side, &old_data[..]));
+    let new = change.new.map(|side| file(side, &new_data[..]));
+
+    write_file_patch(out, old.as_ref(), new.as_ref(), quote_non_ascii)
+}
+
+/// One side of a file's patch
+#[derive(Debug, Clone)]
+pub struct PatchFile<'a> {
+    /// Shown after "a/" or "b/"
+    pub path: &'a str,
+
+    pub mode: u32,
+    pub id: ObjectId,
+
+    /// Only read if the ids on the two sides differ
+    pub data: &'a [u8],
+}
+
+/// Writes the patch turning `old` into `new`, None for the side of a
+/// file being created or deleted. A file's deletion names it on both
+/// sides of the header, as does its creation.
+pub fn write_file_patch<W: Write>(out: &mut W, old: Option<&PatchFile>, new: Option<&PatchFile>, quote_non_ascii: bool)
+    -> io::Result<()>
+{
+    let (old_path, new_path) = match (old, new) {
+        (Some(old), Some(new)) => (old.path, new.path),
+        (Some(file), None) | (None, Some(file)) => (file.path, file.path),
+        (None, None) => return Ok(()),
+    };
+
+    let quote = |prefix: &str, path: &str| quote_path(format!("{prefix}{path}").as_bytes(), quote_non_ascii);
+    let (a, b) = (quote("a/", old_path), quote("b/", new_path));
+
+    writeln!(out, "diff --git {a} {b}")?;
+
+    match (old, new) {
+        (None, Some(new)) => writeln!(out, "new file mode {:06o}", new.mode)?,
+        (Some(old), None) => writeln!(out, "deleted file mode {:06o}", old.mode)?,
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            writeln!(out, "old mode {:06o}", old.mode)?;
+            writeln!(out, "new mode {:06o}", new.mode)?;
+        }
+        _ => {}
+    }
+
+    let old_id = old.map_or(ObjectId::NULL, |file| file.id);
+    let new_id = new.map_or(ObjectId::NULL, |file| file.id);
+
+    if old_id == new_id {
+        return Ok(());
+    }
+
+    write!(out, "index {}..{}", &old_id.to_string()[..7], &new_id.to_string()[..7])?;
+
+    match (old, new) {
+        (Some(old), Some(new)) if old.mode == new.mode => writeln!(out, " {:06o}", old.mode)?,
+        _ => writeln!(out)?,
+    }
+
+    let old_data = old.map_or(&[][..], |file| file.data);
+    let new_data = new.map_or(&[][..], |file| file.data);
+
+    let old_name = old.map_or("/dev/null".to_string(), |_| a);
+    let new_name = new.map_or("/dev/null".to_string(), |_| b);
+
+    if is_binary(old_data) || is_binary(new_data) {
+        return writeln!(out, "Binary files {old_name} and {new_name} differ");
+    }
+
+    // Creating or deleting an empty file has nothing to show
+    if old_data.is_empty() && new_data.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "--- {old_name}")?;
+    writeln!(out, "+++ {new_name}")?;
+
+    write_hunks(out, &split_lines(old_data), &split_lines(new_data))
+}
+
+/// What one side of a change is diffed as: a blob's contents, or for a
+/// submodule the commit it's at
+fn side_contents((mode, id): (u32, ObjectId)) -> io::Result<Vec<u8>> {
+    if mode == MODE_GITLINK {
+        return Ok(format!("Subproject commit {id}\n").into_bytes());
+    }
+
+    match GitObjectStore::get(id).map(|object| object.data) {
+        Some(GitObjectData::Blob { data }) => Ok(data),
+        _ => Err(io::Error::other(format!("unable to read blob {id}"))),
+    }
+}
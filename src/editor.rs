@@ -0,0 +1,49 @@
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::Config;
+
+/// The editor to use for messages: `$GIT_EDITOR`, `core.editor`,
+/// `$VISUAL`, `$EDITOR`, falling back to vi. None on a dumb terminal with
+/// nothing configured, as vi would be unusable there.
+pub fn git_editor(config: &Config) -> Option<String> {
+    let configured = env::var("GIT_EDITOR").ok()
+        .or_else(|| config.get("core.editor").map(str::to_string))
+        .or_else(|| env::var("VISUAL").ok().filter(|_| env::var("TERM").is_ok_and(|term| term != "dumb")))
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.is_empty());
+
+    if configured.is_none() && env::var("TERM").is_ok_and(|term| term == "dumb") {
+        return None;
+    }
+
+    Some(configured.unwrap_or_else(|| "vi".to_string()))
+}
+
+/// Opens `path` in the user's editor and waits for them to finish.
+///
+/// The editor is run by the shell, so it may include arguments. ":" is
+/// taken to mean leave the file as it is.
+pub fn launch_editor(path: &Path, config: &Config) -> io::Result<()> {
+    let editor = git_editor(config)
+        .ok_or_else(|| io::Error::other("Terminal is dumb, but EDITOR unset"))?;
+
+    if editor == ":" {
+        return Ok(());
+    }
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(&editor)
+        .arg(path)
+        .status()?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!("There was a problem with the editor '{editor}'.")));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use std::fmt;
+
+use crate::ignore::IgnorePattern;
+use crate::revparse;
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+use crate::wildmatch::WildmatchFlags;
+
+/// Which objects a walk leaves out, as `--filter=<spec>` gives it for
+/// partial clones:
+///
+///   "blob:none"                     every blob
+///   "blob:limit=" <n> [ k | m | g ] blobs of at least n bytes
+///   "tree:" <depth>                 trees and blobs at least depth below
+///                                   the root tree, "tree:0" is all of them
+///   "sparse:oid=" <blob>            blobs outside the sparse-checkout
+///                                   patterns in the blob
+///   "combine:" <spec> { '+' <spec> } what any of the specs leaves out,
+///                                   each URL encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterSpec {
+    BlobNone,
+    BlobLimit(u64),
+    TreeDepth(u64),
+
+    /// The blob as it was named, which is only looked up once the filter
+    /// is used, as the repository sending the objects may have it where
+    /// the one asking doesn't
+    SparseOid(String),
+
+    Combine(Vec<FilterSpec>),
+}
+
+// The characters a spec in "combine:" must have encoded, besides
+// whitespace and control characters
+const RESERVED: &str = "~`!@#$^&*()[]{}\\;'\",<>?+";
+
+impl FilterSpec {
+    pub fn parse(spec: &str) -> Result<FilterSpec, String> {
+        let invalid = || format!("invalid filter-spec '{spec}'");
+
+        if spec == "blob:none" {
+            return Ok(FilterSpec::BlobNone);
+        }
+
+        if let Some(limit) = spec.strip_prefix("blob:limit=") {
+            return parse_size(limit).map(FilterSpec::BlobLimit).ok_or_else(invalid);
+        }
+
+        if let Some(depth) = spec.strip_prefix("tree:") {
+            return depth.parse().map(FilterSpec::TreeDepth).map_err(|_| "expected 'tree:<depth>'".to_string());
+        }
+
+        if let Some(name) = spec.strip_prefix("sparse:oid=") {
+            return Ok(FilterSpec::SparseOid(name.to_string()));
+        }
+
+        if spec.starts_with("sparse:path=") {
+            return Err("sparse:path filters support has been dropped".to_string());
+        }
+
+        if let Some(specs) = spec.strip_prefix("combine:") {
+            if specs.is_empty() {
+                return Err("expected something after combine:".to_string());
+            }
+
+            return specs.split('+').map(parse_sub_spec).collect::<Result<_, _>>().map(FilterSpec::Combine);
+        }
+
+        Err(invalid())
+    }
+
+    /// Combines several `--filter` options into the one spec they amount
+    /// to, None if there are none
+    pub fn combine(mut specs: Vec<FilterSpec>) -> Option<FilterSpec> {
+        match specs.len() {
+            0 => None,
+            1 => specs.pop(),
+            _ => Some(FilterSpec::Combine(specs)),
+        }
+    }
+}
+
+/// A spec inside "combine:", which has its reserved characters encoded as
+/// "%XX", so that none can be taken for the '+' between specs
+fn parse_sub_spec(spec: &str) -> Result<FilterSpec, String> {
+    if let Some(c) = spec.chars().find(|&c| RESERVED.contains(c) || c.is_whitespace() || c.is_control()) {
+        return Err(format!("must escape char in sub-filter-spec: '{c}'"));
+    }
+
+    let mut decoded = Vec::new();
+    let mut bytes = spec.bytes();
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+
+        let hex = [bytes.next(), bytes.next()];
+        let hex = hex.iter().flatten().map(|&b| b as char).collect::<String>();
+
+        decoded.push(u8::from_str_radix(&hex, 16).map_err(|_| format!("invalid filter-spec '{spec}'"))?);
+    }
+
+    FilterSpec::parse(&String::from_utf8_lossy(&decoded))
+}
+
+/// A size with an optional 'k', 'm' or 'g' suffix, in either case
+fn parse_size(size: &str) -> Option<u64> {
+    let (number, unit) = match size.char_indices().last()? {
+        (i, 'k' | 'K') => (&size[..i], 1 << 10),
+        (i, 'm' | 'M') => (&size[..i], 1 << 20),
+        (i, 'g' | 'G') => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// The spec as git sends it, with sizes in bytes
+impl fmt::Display for FilterSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterSpec::BlobNone => write!(f, "blob:none"),
+            FilterSpec::BlobLimit(limit) => write!(f, "blob:limit={limit}"),
+            FilterSpec::TreeDepth(depth) => write!(f, "tree:{depth}"),
+            FilterSpec::SparseOid(name) => write!(f, "sparse:oid={name}"),
+            FilterSpec::Combine(specs) => {
+                write!(f, "combine:")?;
+
+                for (i, spec) in specs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "+")?;
+                    }
+
+                    for c in spec.to_string().chars() {
+                        match RESERVED.contains(c) || c == '%' || c.is_whitespace() || c.is_control() {
+                            true => write!(f, "%{:02x}", c as u32)?,
+                            false => write!(f, "{c}")?,
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A `FilterSpec` ready to decide which objects to leave out of a walk,
+/// with any sparse-checkout patterns it names read
+#[derive(Debug, Clone)]
+pub enum ObjectFilter {
+    BlobNone,
+    BlobLimit(u64),
+    TreeDepth(u64),
+    Sparse(Vec<IgnorePattern>),
+    Combine(Vec<ObjectFilter>),
+}
+
+/// What a filter decides about a tree
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TreeDecision {
+    pub show: bool,
+
+    /// Whether anything inside it might be shown
+    pub recurse: bool,
+}
+
+impl ObjectFilter {
+    pub fn new(spec: &FilterSpec) -> Result<ObjectFilter, String> {
+        Ok(match spec {
+            FilterSpec::BlobNone => ObjectFilter::BlobNone,
+            FilterSpec::BlobLimit(limit) => ObjectFilter::BlobLimit(*limit),
+            FilterSpec::TreeDepth(depth) => ObjectFilter::TreeDepth(*depth),
+            FilterSpec::SparseOid(name) => {
+                let data = revparse::resolve(name)
+                    .and_then(GitObjectStore::get)
+                    .and_then(|object| match object.data {
+                        GitObjectData::Blob { data } => Some(data),
+                        _ => None,
+                    })
+                    .ok_or_else(|| format!("unable to access sparse blob in '{name}'"))?;
+
+                let patterns = String::from_utf8_lossy(&data)
+                    .lines()
+                    .filter_map(|line| IgnorePattern::parse(line, ""))
+                    .collect();
+
+                ObjectFilter::Sparse(patterns)
+            }
+            FilterSpec::Combine(specs) => {
+                ObjectFilter::Combine(specs.iter().map(ObjectFilter::new).collect::<Result<_, _>>()?)
+            }
+        })
+    }
+
+    /// Decides about a tree `depth` below the root tree, which is 0
+    pub fn tree(&self, depth: u64) -> TreeDecision {
+        match self {
+            ObjectFilter::TreeDepth(max) => TreeDecision { show: depth < *max, recurse: depth < *max },
+            ObjectFilter::Combine(filters) => {
+                filters.iter()
+                    .map(|filter| filter.tree(depth))
+                    .fold(TreeDecision { show: true, recurse: true }, |a, b| TreeDecision {
+                        show: a.show && b.show,
+                        recurse: a.recurse && b.recurse,
+                    })
+            }
+            _ => TreeDecision { show: true, recurse: true },
+        }
+    }
+
+    /// Whether to show the blob `id`, at `path` `depth` below the root tree
+    pub fn blob(&self, id: ObjectId, path: &str, depth: u64) -> bool {
+        match self {
+            ObjectFilter::BlobNone => false,
+            // A blob whose size can't be found is shown, for the walk to
+            // find it missing
+            ObjectFilter::BlobLimit(limit) => {
+                GitObjectStore::open_blob(id).is_none_or(|blob| blob.size() < *limit)
+            }
+            ObjectFilter::TreeDepth(max) => depth < *max,
+            ObjectFilter::Sparse(patterns) => in_sparse_checkout(patterns, path),
+            ObjectFilter::Combine(filters) => filters.iter().all(|filter| filter.blob(id, path, depth)),
+        }
+    }
+}
+
+/// Whether `path` is checked out by sparse-checkout `patterns`: the last
+/// pattern matching it decides, or failing that the last matching its
+/// nearest directory that one does. What none match isn't.
+fn in_sparse_checkout(patterns: &[IgnorePattern], path: &str) -> bool {
+    let flags = WildmatchFlags { pathname: true, casefold: false };
+
+    let dirs = path.rmatch_indices('/').map(|(pos, _)| (&path[..pos], true));
+
+    std::iter::once((path, false))
+        .chain(dirs)
+        .find_map(|(path, is_dir)| patterns.iter().rev().find(|pattern| pattern.matches(path, is_dir, flags)))
+        .is_some_and(|pattern| !pattern.is_negated())
+}
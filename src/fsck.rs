@@ -0,0 +1,576 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+
+use crate::config::Config;
+use crate::store::{
+    object::parse_object,
+    util::hash_object,
+    GitObject,
+    GitObjectData,
+    ObjectId,
+    TreeEntry,
+};
+
+/// How a problem found by fsck is reported.
+///
+/// Only `Error`, `Warn` and `Ignore` can be set from config, `Info`
+/// messages are reported as warnings unless configured otherwise.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FsckSeverity {
+    Error,
+    Warn,
+    Info,
+    Ignore,
+}
+
+impl FsckSeverity {
+    pub fn from_config_value(value: &str) -> Option<FsckSeverity> {
+        match value.to_lowercase().as_str() {
+            "error" => Some(FsckSeverity::Error),
+            "warn" => Some(FsckSeverity::Warn),
+            "ignore" => Some(FsckSeverity::Ignore),
+            _ => None,
+        }
+    }
+}
+
+impl Display for FsckSeverity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FsckSeverity::Error => write!(f, "error"),
+            FsckSeverity::Warn | FsckSeverity::Info => write!(f, "warning"),
+            FsckSeverity::Ignore => write!(f, "ignore"),
+        }
+    }
+}
+
+macro_rules! fsck_msg_ids {
+    ($($variant:ident => $name:literal, $severity:ident;)*) => {
+        /// The problems fsck knows how to detect, named as in git so that
+        /// existing `fsck.<msg-id>` settings carry over.
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub enum FsckMsgId {
+            $($variant,)*
+        }
+
+        impl FsckMsgId {
+            pub const ALL: &'static [FsckMsgId] = &[$(FsckMsgId::$variant,)*];
+
+            /// The camelCase id used in config and in reports
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(FsckMsgId::$variant => $name,)*
+                }
+            }
+
+            pub fn default_severity(&self) -> FsckSeverity {
+                match self {
+                    $(FsckMsgId::$variant => FsckSeverity::$severity,)*
+                }
+            }
+        }
+    };
+}
+
+fsck_msg_ids! {
+    BadDate => "badDate", Error;
+    BadDateOverflow => "badDateOverflow", Error;
+    BadEmail => "badEmail", Error;
+    BadFilemode => "badFilemode", Warn;
+    BadName => "badName", Error;
+    BadTagName => "badTagName", Info;
+    BadTimezone => "badTimezone", Error;
+    BadType => "badType", Error;
+    DuplicateEntries => "duplicateEntries", Error;
+    EmptyName => "emptyName", Warn;
+    FullPathname => "fullPathname", Warn;
+    HasDot => "hasDot", Warn;
+    HasDotdot => "hasDotdot", Warn;
+    HasDotgit => "hasDotgit", Warn;
+    MissingEmail => "missingEmail", Error;
+    MissingNameBeforeEmail => "missingNameBeforeEmail", Error;
+    MissingSpaceBeforeDate => "missingSpaceBeforeDate", Error;
+    MissingSpaceBeforeEmail => "missingSpaceBeforeEmail", Error;
+    MissingTaggerEntry => "missingTaggerEntry", Info;
+    NullSha1 => "nullSha1", Warn;
+    TreeNotSorted => "treeNotSorted", Error;
+    ZeroPaddedDate => "zeroPaddedDate", Error;
+}
+
+impl FsckMsgId {
+    /// Looks up a message id, config keys are case insensitive.
+    pub fn from_name(name: &str) -> Option<FsckMsgId> {
+        FsckMsgId::ALL.iter()
+            .find(|id| id.name().eq_ignore_ascii_case(name))
+            .copied()
+    }
+}
+
+/// A single problem found in an object
+#[derive(Debug)]
+pub struct FsckMessage {
+    pub id: FsckMsgId,
+    pub severity: FsckSeverity,
+    pub object: ObjectId,
+    pub kind: &'static str,
+    pub detail: String,
+}
+
+impl Display for FsckMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} in {} {}: {}: {}",
+            self.severity, self.kind, self.object, self.id.name(), self.detail)
+    }
+}
+
+/// Per-repository fsck tuning.
+///
+/// Loaded from `fsck.<msg-id>` and `fsck.skipList` for fsck itself, or
+/// the `receive.fsck.*` variants when checking objects pushed to us. As
+/// in git, the receive variants don't fall back to the plain `fsck.*`
+/// settings.
+#[derive(Debug, Default)]
+pub struct FsckOptions {
+    severities: HashMap<FsckMsgId, FsckSeverity>,
+
+    /// Objects which are known to be broken and should never be reported
+    skip_list: HashSet<ObjectId>,
+
+    /// Treat warnings as errors
+    pub strict: bool,
+}
+
+impl FsckOptions {
+    /// Options for `gitty fsck`
+    pub fn for_fsck(config: &Config) -> FsckOptions {
+        FsckOptions::from_config(config, "fsck")
+    }
+
+    /// Options for checking objects received from a push
+    pub fn for_receive(config: &Config) -> FsckOptions {
+        FsckOptions::from_config(config, "receive.fsck")
+    }
+
+    pub fn from_config(config: &Config, prefix: &str) -> FsckOptions {
+        let mut options = FsckOptions::default();
+        let prefix = format!("{}.", prefix.to_lowercase());
+
+        for entry in config.entries() {
+            let Some(name) = entry.key.strip_prefix(&prefix) else {
+                continue;
+            };
+
+            // Only direct children, eg. not "receive.fsck.x" for "fsck"
+            if name.contains('.') {
+                continue;
+            }
+
+            let value = entry.value.as_deref().unwrap_or("");
+
+            if name == "skiplist" {
+                if options.load_skip_list(value).is_none() {
+                    eprintln!("Could not read fsck skip list: {value}");
+                }
+                continue;
+            }
+
+            let Some(id) = FsckMsgId::from_name(name) else {
+                eprintln!("Unhandled fsck message id: {name}");
+                continue;
+            };
+
+            match FsckSeverity::from_config_value(value) {
+                Some(severity) => options.set_severity(id, severity),
+                None => eprintln!("Unknown fsck message type: '{value}'"),
+            }
+        }
+
+        options
+    }
+
+    pub fn set_severity(&mut self, id: FsckMsgId, severity: FsckSeverity) {
+        self.severities.insert(id, severity);
+    }
+
+    /// The severity `id` will be reported with, after applying config
+    /// and strict mode.
+    pub fn severity(&self, id: FsckMsgId) -> FsckSeverity {
+        if let Some(severity) = self.severities.get(&id) {
+            return *severity;
+        }
+
+        match id.default_severity() {
+            FsckSeverity::Warn if self.strict => FsckSeverity::Error,
+            FsckSeverity::Info => FsckSeverity::Warn,
+            severity => severity,
+        }
+    }
+
+    pub fn skip(&mut self, id: ObjectId) {
+        self.skip_list.insert(id);
+    }
+
+    pub fn is_skipped(&self, id: &ObjectId) -> bool {
+        self.skip_list.contains(id)
+    }
+
+    /// Skip list format:
+    ///   one hex object name per line, '#' starts a comment
+    fn load_skip_list(&mut self, path: &str) -> Option<()> {
+        let contents = fs::read_to_string(path).ok()?;
+
+        for line in contents.lines() {
+            let line = line.split('#').next()?.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            match ObjectId::try_from(line.to_string()) {
+                Ok(id) => self.skip(id),
+                Err(_) => eprintln!("Invalid object name in fsck skip list: {line}"),
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Collects messages for one object, dropping ignored ones
+struct Reporter<'a> {
+    options: &'a FsckOptions,
+    object: &'a GitObject,
+    messages: Vec<FsckMessage>,
+}
+
+impl Reporter<'_> {
+    fn report(&mut self, id: FsckMsgId, detail: impl Into<String>) {
+        let severity = self.options.severity(id);
+
+        if severity == FsckSeverity::Ignore {
+            return;
+        }
+
+        self.messages.push(FsckMessage {
+            id,
+            severity,
+            object: self.object.id,
+            kind: self.object.type_str(),
+            detail: detail.into(),
+        });
+    }
+}
+
+/// An object's type and the objects it links to, enough to check
+/// connectivity without keeping every object in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectLinks {
+    pub kind: &'static str,
+
+    /// Each object linked to, with the type the link says it has
+    pub links: Vec<(ObjectId, &'static str)>,
+}
+
+impl ObjectLinks {
+    /// The links of `object`, leaving out submodule commits, which live
+    /// in another repository, and tags of an unknown type
+    pub fn new(object: &GitObject) -> ObjectLinks {
+        let links = match &object.data {
+            GitObjectData::Blob { .. } => Vec::new(),
+            GitObjectData::Tree { entries } => entries.iter()
+                .filter(|entry| !entry.is_gitlink())
+                .map(|entry| (entry.id, entry.kind))
+                .collect(),
+            GitObjectData::Commit { tree, parents, .. } => std::iter::once((*tree, "tree"))
+                .chain(parents.iter().map(|parent| (*parent, "commit")))
+                .collect(),
+            GitObjectData::Tag { object, kind, .. } => match kind.as_str() {
+                "blob" => vec![(*object, "blob")],
+                "tree" => vec![(*object, "tree")],
+                "commit" => vec![(*object, "commit")],
+                "tag" => vec![(*object, "tag")],
+                _ => Vec::new(),
+            },
+        };
+
+        ObjectLinks { kind: object.type_str(), links }
+    }
+}
+
+/// A link from a reachable object to one which isn't in the store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenLink {
+    pub from: ObjectId,
+    pub from_kind: &'static str,
+    pub to: ObjectId,
+    pub to_kind: &'static str,
+}
+
+/// What walking the objects reachable from a repository's roots found
+#[derive(Debug, Default)]
+pub struct Connectivity {
+    /// Links from reachable objects to objects which aren't in the store,
+    /// in the order they were walked
+    pub broken_links: Vec<BrokenLink>,
+
+    /// The objects broken links lead to, sorted by id
+    pub missing: Vec<(ObjectId, &'static str)>,
+
+    /// Objects in the store which can't be reached, sorted by id
+    pub unreachable: Vec<(ObjectId, &'static str)>,
+
+    /// The unreachable objects no object links to at all, the tips of
+    /// whatever history has been left behind
+    pub dangling: Vec<(ObjectId, &'static str)>,
+}
+
+/// Walks the objects reachable from `roots`, given the links of every
+/// object in the store. Roots which aren't in the store are skipped, it's
+/// for the caller to say where they came from.
+pub fn check_connectivity(objects: &HashMap<ObjectId, ObjectLinks>, roots: impl IntoIterator<Item = ObjectId>)
+    -> Connectivity
+{
+    let mut connectivity = Connectivity::default();
+
+    let mut reachable = HashSet::new();
+    let mut pending: Vec<ObjectId> = roots.into_iter().filter(|id| objects.contains_key(id)).collect();
+
+    while let Some(id) = pending.pop() {
+        if !reachable.insert(id) {
+            continue;
+        }
+
+        let object = &objects[&id];
+
+        for &(to, to_kind) in &object.links {
+            match objects.contains_key(&to) {
+                true => pending.push(to),
+                false => connectivity.broken_links.push(BrokenLink { from: id, from_kind: object.kind, to, to_kind }),
+            }
+        }
+    }
+
+    let mut missing: Vec<_> = connectivity.broken_links.iter().map(|link| (link.to, link.to_kind)).collect();
+    missing.sort();
+    missing.dedup_by_key(|(id, _)| *id);
+    connectivity.missing = missing;
+
+    // Objects linked to by anything, even other unreachable objects
+    let used: HashSet<ObjectId> = objects.values()
+        .flat_map(|object| object.links.iter().map(|(id, _)| *id))
+        .collect();
+
+    let mut unreachable: Vec<_> = objects.iter()
+        .filter(|(id, _)| !reachable.contains(*id))
+        .map(|(id, object)| (*id, object.kind))
+        .collect();
+
+    unreachable.sort();
+
+    connectivity.dangling = unreachable.iter().filter(|(id, _)| !used.contains(id)).copied().collect();
+    connectivity.unreachable = unreachable;
+
+    connectivity
+}
+
+/// Checks that re-serializing `object` reproduces it exactly: its
+/// encoding must hash back to its id and parse back to the same data.
+/// A failure means the parser dropped or altered part of the object.
+pub fn check_round_trip(object: &GitObject) -> Result<(), String> {
+    let kind = object.type_str();
+    let data = object.data.serialize();
+
+    let reparsed = parse_object(kind, &data)
+        .ok_or_else(|| "re-serialized object doesn't parse".to_string())?;
+
+    if reparsed != object.data {
+        return Err("re-serialized object parses differently".to_string());
+    }
+
+    let id = hash_object(kind, &data);
+
+    if id != object.id {
+        return Err(format!("re-serialized object hashes to {id}"));
+    }
+
+    Ok(())
+}
+
+/// Validates the syntax of a single object, returning every problem
+/// whose configured severity isn't `ignore`.
+pub fn check_object(object: &GitObject, options: &FsckOptions) -> Vec<FsckMessage> {
+    if options.is_skipped(&object.id) {
+        return Vec::new();
+    }
+
+    let mut reporter = Reporter {
+        options,
+        object,
+        messages: Vec::new(),
+    };
+
+    match &object.data {
+        GitObjectData::Blob { .. } => {},
+        GitObjectData::Tree { entries } => check_tree(entries, &mut reporter),
+        GitObjectData::Commit { author, committer, .. } => {
+            check_ident(author, &mut reporter);
+            check_ident(committer, &mut reporter);
+        },
+        GitObjectData::Tag { kind, tag, tagger, .. } => {
+            if !matches!(kind.as_str(), "blob" | "tree" | "commit" | "tag") {
+                reporter.report(FsckMsgId::BadType, format!("invalid 'type' value: {kind}"));
+            }
+
+            if !is_valid_tag_name(tag) {
+                reporter.report(FsckMsgId::BadTagName, format!("invalid 'tag' name: {tag}"));
+            }
+
+            match tagger {
+                Some(tagger) => check_ident(tagger, &mut reporter),
+                None => reporter.report(FsckMsgId::MissingTaggerEntry,
+                    "invalid format - expected 'tagger' line"),
+            }
+        },
+    }
+
+    reporter.messages
+}
+
+fn check_tree(entries: &[TreeEntry], reporter: &mut Reporter) {
+    let mut previous: Option<&TreeEntry> = None;
+
+    for entry in entries {
+        let path = entry.path.as_slice();
+
+        match path {
+            b"" => reporter.report(FsckMsgId::EmptyName, "contains empty pathname"),
+            b"." => reporter.report(FsckMsgId::HasDot, "contains '.'"),
+            b".." => reporter.report(FsckMsgId::HasDotdot, "contains '..'"),
+            _ if path.eq_ignore_ascii_case(b".git") =>
+                reporter.report(FsckMsgId::HasDotgit, "contains '.git'"),
+            _ if path.contains(&b'/') =>
+                reporter.report(FsckMsgId::FullPathname, "contains full pathnames"),
+            _ => {},
+        }
+
+        if entry.id.is_null() {
+            reporter.report(FsckMsgId::NullSha1, "contains entries pointing to null sha1");
+        }
+
+        if !matches!(entry.mode, 0o100644 | 0o100755 | 0o120000 | 0o040000 | 0o160000) {
+            reporter.report(FsckMsgId::BadFilemode, "contains bad file modes");
+        }
+
+        if let Some(previous) = previous {
+            match tree_entry_order(previous, entry) {
+                std::cmp::Ordering::Equal =>
+                    reporter.report(FsckMsgId::DuplicateEntries, "contains duplicate file entries"),
+                std::cmp::Ordering::Greater =>
+                    reporter.report(FsckMsgId::TreeNotSorted, "not properly sorted"),
+                std::cmp::Ordering::Less => {},
+            }
+        }
+
+        previous = Some(entry);
+    }
+}
+
+/// Trees are sorted as if directory names had a trailing '/'
+fn tree_entry_order(a: &TreeEntry, b: &TreeEntry) -> std::cmp::Ordering {
+    if a.path == b.path {
+        return std::cmp::Ordering::Equal;
+    }
+
+    let sort_key = |entry: &TreeEntry| {
+        let mut key = entry.path.clone();
+        if entry.mode == 0o040000 {
+            key.push(b'/');
+        }
+        key
+    };
+
+    sort_key(a).cmp(&sort_key(b))
+}
+
+fn is_valid_tag_name(tag: &str) -> bool {
+    !tag.is_empty()
+        && !tag.starts_with('-')
+        && !tag.starts_with('.')
+        && !tag.ends_with(".lock")
+        && !tag.contains("..")
+        && !tag.contains("@{")
+        && !tag.chars().any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+}
+
+/// Identity format:
+///   <name> ' ' '<' <email> '>' ' ' <timestamp> ' ' <tz>
+///
+/// eg. "A U Thor <author@example.com> 1112911993 -0700"
+fn check_ident(ident: &str, reporter: &mut Reporter) {
+    if ident.starts_with('<') {
+        reporter.report(FsckMsgId::MissingNameBeforeEmail, "invalid author/committer line - missing space before email");
+        return;
+    }
+
+    let Some(email_start) = ident.find(['<', '>']) else {
+        reporter.report(FsckMsgId::MissingEmail, "invalid author/committer line - missing email");
+        return;
+    };
+
+    if ident.as_bytes()[email_start] == b'>' {
+        reporter.report(FsckMsgId::BadName, "invalid author/committer line - bad name");
+        return;
+    }
+
+    if !ident[..email_start].ends_with(' ') {
+        reporter.report(FsckMsgId::MissingSpaceBeforeEmail, "invalid author/committer line - missing space before email");
+        return;
+    }
+
+    let rest = &ident[email_start + 1..];
+
+    let Some(email_end) = rest.find(['<', '>']) else {
+        reporter.report(FsckMsgId::BadEmail, "invalid author/committer line - bad email");
+        return;
+    };
+
+    if rest.as_bytes()[email_end] == b'<' {
+        reporter.report(FsckMsgId::BadEmail, "invalid author/committer line - bad email");
+        return;
+    }
+
+    let Some(date) = rest[email_end + 1..].strip_prefix(' ') else {
+        reporter.report(FsckMsgId::MissingSpaceBeforeDate, "invalid author/committer line - missing space before date");
+        return;
+    };
+
+    let Some((timestamp, tz)) = date.split_once(' ') else {
+        reporter.report(FsckMsgId::BadDate, "invalid author/committer line - bad date");
+        return;
+    };
+
+    if timestamp.len() > 1 && timestamp.starts_with('0') {
+        reporter.report(FsckMsgId::ZeroPaddedDate, "invalid author/committer line - zero-padded date");
+        return;
+    }
+
+    if timestamp.is_empty() || !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+        reporter.report(FsckMsgId::BadDate, "invalid author/committer line - bad date");
+        return;
+    }
+
+    if timestamp.parse::<u64>().is_err() {
+        reporter.report(FsckMsgId::BadDateOverflow, "invalid author/committer line - date causes integer overflow");
+        return;
+    }
+
+    let tz = tz.as_bytes();
+    let valid_tz = tz.len() == 5
+        && (tz[0] == b'+' || tz[0] == b'-')
+        && tz[1..].iter().all(|b| b.is_ascii_digit());
+
+    if !valid_tz {
+        reporter.report(FsckMsgId::BadTimezone, "invalid author/committer line - bad time zone");
+    }
+}
@@ -0,0 +1,599 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::metrics;
+use crate::store::{
+    bloom::{BloomKey, BloomSettings},
+    commit_graph::CommitGraph,
+    GitObjectData,
+    GitObjectStore,
+    ObjectId,
+};
+
+/// Generation of commits which aren't in the commit-graph.
+///
+/// The commit-graph is closed under reachability, so a commit missing from
+/// it can never be the ancestor of one that's in it.
+pub const GENERATION_NUMBER_INFINITY: u64 = u64::MAX;
+
+const PARENT1: u8 = 1 << 0;
+const PARENT2: u8 = 1 << 1;
+const STALE: u8 = 1 << 2;
+const RESULT: u8 = 1 << 3;
+
+/// The parts of a commit needed to walk history
+#[derive(Debug, Clone)]
+pub struct CommitNode {
+    pub id: ObjectId,
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    pub generation: u64,
+
+    /// Committer time, seconds since the epoch
+    pub commit_time: u64,
+}
+
+/// How much of a commit's history `is_ancestor` has walked, so the next
+/// query from the same commit carries on where the last one stopped.
+///
+/// The walk goes down in generation order, and stops once everything left
+/// to visit has a lower generation than the commit looked for, so `seen`
+/// holds every ancestor at or above that generation. A later query for a
+/// commit at or above the lowest generation walked so far is answered
+/// from `seen` alone.
+#[derive(Debug, Default)]
+struct Reach {
+    /// The ancestors found so far, the commit itself included
+    seen: HashSet<ObjectId>,
+
+    /// Ancestors found whose parents haven't been looked at yet, highest
+    /// generation first
+    pending: BinaryHeap<(u64, ObjectId)>,
+}
+
+/// An in-memory view of the commit DAG.
+///
+/// Commits are read from the commit-graph when there is one, which also
+/// gives us generation numbers to cut traversals short, and are otherwise
+/// parsed from the object store. Either way each commit is only loaded once.
+pub struct Graph {
+    commit_graph: Option<CommitGraph>,
+    nodes: HashMap<ObjectId, CommitNode>,
+
+    /// The walks `is_ancestor` has done, by the commit they started from
+    reach: HashMap<ObjectId, Reach>,
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Graph::new()
+    }
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph {
+            commit_graph: CommitGraph::load(),
+            nodes: HashMap::new(),
+            reach: HashMap::new(),
+        }
+    }
+
+    /// Looks up a commit, `None` if it's missing or not a commit
+    pub fn node(&mut self, id: ObjectId) -> Option<&CommitNode> {
+        if !self.nodes.contains_key(&id) {
+            let node = self.load_node(id)?;
+            self.nodes.insert(id, node);
+        }
+
+        self.nodes.get(&id)
+    }
+
+    fn load_node(&self, id: ObjectId) -> Option<CommitNode> {
+        if let Some(commit) = self.commit_graph.as_ref().and_then(|graph| graph.get(&id)) {
+            return Some(CommitNode {
+                id,
+                tree: commit.tree,
+                parents: commit.parents,
+                generation: commit.generation,
+                commit_time: commit.commit_time,
+            });
+        }
+
+        let GitObjectData::Commit { tree, parents, committer, .. } = GitObjectStore::get(id)?.data else {
+            return None;
+        };
+
+        Some(CommitNode {
+            id,
+            tree,
+            parents,
+            generation: GENERATION_NUMBER_INFINITY,
+            commit_time: ident_time(&committer).unwrap_or(0),
+        })
+    }
+
+    pub fn parents(&mut self, id: ObjectId) -> Vec<ObjectId> {
+        self.node(id)
+            .map(|node| node.parents.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn tree(&mut self, id: ObjectId) -> Option<ObjectId> {
+        self.node(id).map(|node| node.tree)
+    }
+
+    pub fn generation(&mut self, id: ObjectId) -> u64 {
+        self.node(id)
+            .map(|node| node.generation)
+            .unwrap_or(GENERATION_NUMBER_INFINITY)
+    }
+
+    pub fn commit_time(&mut self, id: ObjectId) -> u64 {
+        self.node(id)
+            .map(|node| node.commit_time)
+            .unwrap_or(0)
+    }
+
+    /// How the commit-graph's changed-path Bloom filters were built, if
+    /// there are any to consult
+    pub fn bloom_settings(&self) -> Option<BloomSettings> {
+        self.commit_graph.as_ref()?.bloom_settings()
+    }
+
+    /// Might `id` have changed the path `key` is for, compared to its first
+    /// parent? Without a Bloom filter for the commit, it might have.
+    pub fn maybe_changed_path(&self, id: ObjectId, key: &BloomKey) -> bool {
+        self.commit_graph.as_ref().is_none_or(|commit_graph| commit_graph.maybe_changed(&id, key))
+    }
+
+    /// Is `ancestor` reachable from `descendant`? A commit is its own ancestor.
+    ///
+    /// Each descendant's walk is kept, so asking about many commits against
+    /// the same few tips, as `branch --merged` does, walks each part of
+    /// their history once rather than once per query.
+    pub fn is_ancestor(&mut self, ancestor: ObjectId, descendant: ObjectId) -> bool {
+        let min_generation = self.generation(ancestor);
+
+        let mut reach = match self.reach.remove(&descendant) {
+            Some(reach) => {
+                metrics::count("gitty_ancestry_cache_hits_total", &[], 1);
+                reach
+            }
+            None => {
+                metrics::count("gitty_ancestry_cache_misses_total", &[], 1);
+
+                let mut reach = Reach::default();
+                reach.seen.insert(descendant);
+                reach.pending.push((self.generation(descendant), descendant));
+                reach
+            }
+        };
+
+        let mut found = reach.seen.contains(&ancestor);
+
+        while !found {
+            // Everything below here has a generation lower than ours
+            let Some(&(_, id)) = reach.pending.peek().filter(|(generation, _)| *generation >= min_generation) else {
+                break;
+            };
+
+            reach.pending.pop();
+
+            for parent in self.parents(id) {
+                if reach.seen.insert(parent) {
+                    reach.pending.push((self.generation(parent), parent));
+                    found |= parent == ancestor;
+                }
+            }
+        }
+
+        self.reach.insert(descendant, reach);
+
+        found
+    }
+
+    /// The best common ancestors of `a` and `b`: common ancestors which
+    /// aren't reachable from any other common ancestor.
+    pub fn merge_bases(&mut self, a: ObjectId, b: ObjectId) -> Vec<ObjectId> {
+        self.merge_bases_many(a, &[b])
+    }
+
+    /// The best common ancestors of `one` and a hypothetical merge of all
+    /// of `twos`, newest first.
+    pub fn merge_bases_many(&mut self, one: ObjectId, twos: &[ObjectId]) -> Vec<ObjectId> {
+        if twos.contains(&one) {
+            return vec![one];
+        }
+
+        let candidates = self.paint_down_to_common(one, twos);
+        let mut bases = self.independent(&candidates);
+
+        bases.sort_by_key(|&id| std::cmp::Reverse(self.commit_time(id)));
+        bases
+    }
+
+    /// The best common ancestors of all of `commits` at once, as an octopus
+    /// merge of them would use: the merge bases of the first two, then of
+    /// each of those with the third, and so on, less any reachable from
+    /// another.
+    pub fn octopus_merge_bases(&mut self, commits: &[ObjectId]) -> Vec<ObjectId> {
+        let Some((&first, rest)) = commits.split_first() else {
+            return Vec::new();
+        };
+
+        let mut bases = vec![first];
+
+        for &commit in rest {
+            let mut next = Vec::new();
+
+            for &base in &bases {
+                for found in self.merge_bases(base, commit) {
+                    if !next.contains(&found) {
+                        next.push(found);
+                    }
+                }
+            }
+
+            bases = next;
+        }
+
+        self.independent(&bases)
+    }
+
+    /// Walks down from `one` and `twos` in generation/date order, marking
+    /// each commit with who can reach it, until everything left in the
+    /// queue is already known to be below a common ancestor.
+    fn paint_down_to_common(&mut self, one: ObjectId, twos: &[ObjectId]) -> Vec<ObjectId> {
+        let mut flags: HashMap<ObjectId, u8> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        let mut results = Vec::new();
+
+        // How many times each commit is queued, and how many of the queue's
+        // entries aren't stale, kept up to date rather than going through
+        // the queue each time round. A commit only ever becomes stale.
+        let mut queued: HashMap<ObjectId, usize> = HashMap::new();
+        let mut nonstale = 0;
+
+        flags.insert(one, PARENT1);
+
+        for &two in twos {
+            *flags.entry(two).or_default() |= PARENT2;
+        }
+
+        for id in std::iter::once(one).chain(twos.iter().copied()) {
+            *queued.entry(id).or_default() += 1;
+            nonstale += 1;
+            queue.push(self.queue_key(id));
+        }
+
+        while nonstale > 0 {
+            let Some((_, _, id)) = queue.pop() else {
+                break;
+            };
+
+            *queued.get_mut(&id).expect("queued") -= 1;
+
+            if flags[&id] & STALE == 0 {
+                nonstale -= 1;
+            }
+
+            let mut commit_flags = flags[&id] & (PARENT1 | PARENT2 | STALE);
+
+            if commit_flags == PARENT1 | PARENT2 {
+                if flags[&id] & RESULT == 0 {
+                    *flags.get_mut(&id).unwrap() |= RESULT;
+                    results.push(id);
+                }
+
+                // Mark parents of a found merge stale
+                commit_flags |= STALE;
+            }
+
+            for parent in self.parents(id) {
+                let parent_flags = flags.entry(parent).or_default();
+
+                if *parent_flags & commit_flags == commit_flags {
+                    continue;
+                }
+
+                let was_stale = *parent_flags & STALE != 0;
+                *parent_flags |= commit_flags;
+                let stale = *parent_flags & STALE != 0;
+
+                let times_queued = queued.entry(parent).or_default();
+
+                if stale && !was_stale {
+                    nonstale -= *times_queued;
+                }
+
+                *times_queued += 1;
+
+                if !stale {
+                    nonstale += 1;
+                }
+
+                queue.push(self.queue_key(parent));
+            }
+        }
+
+        results.retain(|id| flags[id] & STALE == 0);
+        results
+    }
+
+    // Higher generations first, falling back to newer commits first
+    fn queue_key(&mut self, id: ObjectId) -> (u64, u64, ObjectId) {
+        (self.generation(id), self.commit_time(id), id)
+    }
+
+    /// Removes every commit which is reachable from another in `commits`.
+    pub fn independent(&mut self, commits: &[ObjectId]) -> Vec<ObjectId> {
+        let mut unique = Vec::new();
+
+        for &id in commits {
+            if !unique.contains(&id) {
+                unique.push(id);
+            }
+        }
+
+        let mut result = Vec::new();
+
+        for (i, &candidate) in unique.iter().enumerate() {
+            let redundant = unique.iter()
+                .enumerate()
+                .any(|(j, &other)| i != j && self.is_ancestor(candidate, other));
+
+            if !redundant {
+                result.push(candidate);
+            }
+        }
+
+        result
+    }
+
+    /// For each (base, tip) pair, how many commits the tip is ahead of and
+    /// behind the base, all counted in one walk down from every commit
+    /// named, as for showing the state of many branches at once.
+    ///
+    /// Each commit reached is marked with which of those commits reach it.
+    /// The walk goes in generation/date order, so a commit's marks are
+    /// mostly complete before it's counted (one found to have more later,
+    /// as where dates are skewed, is counted again), and stops once
+    /// everything left to visit is reached by all of them, where no pair
+    /// can differ.
+    pub fn ahead_behind(&mut self, pairs: &[(ObjectId, ObjectId)]) -> Vec<AheadBehind> {
+        let mut tips: HashMap<ObjectId, usize> = HashMap::new();
+
+        for &(base, tip) in pairs {
+            for id in [base, tip] {
+                let next = tips.len();
+                tips.entry(id).or_insert(next);
+            }
+        }
+
+        let words = tips.len().div_ceil(64);
+        let all = ReachBits::all(tips.len(), words);
+
+        let mut reached: HashMap<ObjectId, ReachBits> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+
+        // How many times each commit is queued, and how many of the queue's
+        // entries are still to be counted, kept up to date rather than
+        // going through the queue each time round: those not reached by
+        // all, and those counted already, which may need counting again
+        let mut queued: HashMap<ObjectId, usize> = HashMap::new();
+        let mut live = 0;
+
+        for (&id, &bit) in &tips {
+            reached.entry(id).or_insert_with(|| ReachBits::none(words)).set(bit);
+            *queued.entry(id).or_default() += 1;
+            queue.push(self.queue_key(id));
+        }
+
+        for (&id, &times_queued) in &queued {
+            if reached[&id] != all {
+                live += times_queued;
+            }
+        }
+
+        let pair_bits: Vec<(usize, usize)> = pairs.iter().map(|(base, tip)| (tips[base], tips[tip])).collect();
+        let mut counts = vec![AheadBehind::default(); pairs.len()];
+
+        // What each commit was counted as reached by, which may yet grow
+        // where dates put it before a commit above it
+        let mut counted: HashMap<ObjectId, ReachBits> = HashMap::new();
+
+        while live > 0 {
+            let Some((_, _, id)) = queue.pop() else {
+                break;
+            };
+
+            let times_queued = queued.get_mut(&id).expect("queued");
+            *times_queued -= 1;
+
+            let bits = reached[&id].clone();
+            let before = counted.insert(id, bits.clone());
+
+            match before {
+                Some(_) => live -= 1,
+                // Once counted, what's left of it in the queue is live
+                None if bits == all => live += *times_queued,
+                None => live -= 1,
+            }
+
+            if before.as_ref() == Some(&bits) {
+                continue;
+            }
+
+            for (count, &pair) in counts.iter_mut().zip(&pair_bits) {
+                count.ahead += bits.ahead(pair);
+                count.behind += bits.behind(pair);
+
+                if let Some(before) = &before {
+                    count.ahead -= before.ahead(pair);
+                    count.behind -= before.behind(pair);
+                }
+            }
+
+            for parent in self.parents(id) {
+                let parent_bits = reached.entry(parent).or_insert_with(|| ReachBits::none(words));
+                let previous = parent_bits.clone();
+
+                parent_bits.union(&bits);
+
+                if *parent_bits == previous {
+                    continue;
+                }
+
+                let times_queued = queued.entry(parent).or_default();
+                let counted_already = counted.contains_key(&parent);
+
+                // Reached by all now, and never counted, it's dead
+                if *parent_bits == all && !counted_already {
+                    live -= *times_queued;
+                }
+
+                *times_queued += 1;
+
+                if *parent_bits != all || counted_already {
+                    live += 1;
+                }
+
+                queue.push(self.queue_key(parent));
+            }
+        }
+
+        counts
+    }
+}
+
+/// How far one commit has diverged from another
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct AheadBehind {
+    /// Commits reachable from the tip but not the base
+    pub ahead: usize,
+
+    /// Commits reachable from the base but not the tip
+    pub behind: usize,
+}
+
+/// Which of the commits an ahead/behind walk starts from reach a commit,
+/// one bit each
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ReachBits(Vec<u64>);
+
+impl ReachBits {
+    fn none(words: usize) -> ReachBits {
+        ReachBits(vec![0; words])
+    }
+
+    fn all(bits: usize, words: usize) -> ReachBits {
+        let mut all = ReachBits::none(words);
+        (0..bits).for_each(|bit| all.set(bit));
+        all
+    }
+
+    fn set(&mut self, bit: usize) {
+        self.0[bit / 64] |= 1 << (bit % 64);
+    }
+
+    fn get(&self, bit: usize) -> bool {
+        self.0[bit / 64] & (1 << (bit % 64)) != 0
+    }
+
+    /// 1 if the commit counts towards the tip of the (base, tip) pair
+    /// being ahead, otherwise 0
+    fn ahead(&self, (base, tip): (usize, usize)) -> usize {
+        (self.get(tip) && !self.get(base)) as usize
+    }
+
+    fn behind(&self, (base, tip): (usize, usize)) -> usize {
+        (self.get(base) && !self.get(tip)) as usize
+    }
+
+    fn union(&mut self, other: &ReachBits) {
+        self.0.iter_mut().zip(&other.0).for_each(|(word, other)| *word |= other);
+    }
+}
+
+/// Answers "does this tip contain any of these commits" for many tips,
+/// sharing the work between them.
+///
+/// Results are memoized per commit, so after one tip has been checked the
+/// walk for every other tip stops as soon as it reaches known history.
+pub struct ContainsQuery {
+    targets: HashSet<ObjectId>,
+
+    /// Commits below this generation can't reach any target
+    min_generation: u64,
+
+    results: HashMap<ObjectId, bool>,
+}
+
+impl ContainsQuery {
+    pub fn new(graph: &mut Graph, targets: &[ObjectId]) -> ContainsQuery {
+        let min_generation = targets.iter()
+            .map(|&target| graph.generation(target))
+            .min()
+            .unwrap_or(GENERATION_NUMBER_INFINITY);
+
+        ContainsQuery {
+            targets: targets.iter().copied().collect(),
+            min_generation,
+            results: HashMap::new(),
+        }
+    }
+
+    /// Is any of the targets reachable from `tip`?
+    pub fn matches(&mut self, graph: &mut Graph, tip: ObjectId) -> bool {
+        let mut stack = vec![tip];
+
+        while let Some(&id) = stack.last() {
+            if self.results.contains_key(&id) {
+                stack.pop();
+                continue;
+            }
+
+            if self.targets.contains(&id) {
+                self.results.insert(id, true);
+                continue;
+            }
+
+            if graph.generation(id) < self.min_generation {
+                self.results.insert(id, false);
+                continue;
+            }
+
+            let parents = graph.parents(id);
+            let mut pending = false;
+            let mut found = false;
+
+            for parent in parents {
+                match self.results.get(&parent) {
+                    Some(true) => {
+                        found = true;
+                        break;
+                    },
+                    Some(false) => {},
+                    None => {
+                        pending = true;
+                        stack.push(parent);
+                    }
+                }
+            }
+
+            if found || !pending {
+                self.results.insert(id, found);
+            }
+        }
+
+        self.results[&tip]
+    }
+}
+
+/// Pulls the timestamp out of an identity line,
+/// eg. "A U Thor <author@example.com> 1112911993 -0700"
+pub fn ident_time(ident: &str) -> Option<u64> {
+    let mut fields = ident.rsplitn(3, ' ');
+    let _tz = fields.next()?;
+    fields.next()?.parse().ok()
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::pathspec::Pathspec;
+use crate::store::index::Index;
+use crate::store::tree::{flatten_tree, MODE_GITLINK};
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+use crate::worktree;
+
+/// How far into a file to look for a NUL, which makes it binary
+const BINARY_CHECK_SIZE: usize = 8000;
+
+/// A pattern to search the lines of files for
+#[derive(Debug, Clone)]
+pub struct Grep {
+    regex: Regex,
+
+    /// Look for the lines which don't match instead
+    invert: bool,
+}
+
+impl Grep {
+    pub fn new(pattern: &str, ignore_case: bool, invert: bool) -> Result<Grep, regex::Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .build()?;
+
+        Ok(Grep { regex, invert })
+    }
+
+    /// The lines of `data` found, numbered from 1, without their newlines
+    pub fn lines<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item = (usize, &'a [u8])> + 'a {
+        let data = data.strip_suffix(b"\n").unwrap_or(data);
+
+        data.split(|&byte| byte == b'\n')
+            .enumerate()
+            .filter(move |(_, line)| self.regex.is_match(line) != self.invert)
+            .map(|(i, line)| (i + 1, line))
+    }
+
+    /// Whether any line of `data` is found
+    pub fn is_found(&self, data: &[u8]) -> bool {
+        self.lines(data).next().is_some()
+    }
+}
+
+/// Whether `data` is taken to be binary rather than text, as git does: if
+/// there's a NUL near the start
+pub fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_CHECK_SIZE)].contains(&0)
+}
+
+/// A file to search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepFile {
+    /// Relative to the repository root
+    pub path: Vec<u8>,
+
+    /// Where its contents are, None for the worktree
+    pub blob: Option<ObjectId>,
+}
+
+impl GrepFile {
+    /// The file's contents, None if they can't be read
+    pub fn read(&self) -> Option<Vec<u8>> {
+        match self.blob {
+            Some(id) => match GitObjectStore::get(id)?.data {
+                GitObjectData::Blob { data } => Some(data),
+                _ => None,
+            },
+            None => worktree::read_file(worktree::os_path(&self.path)),
+        }
+    }
+}
+
+/// The files in `tree` `pathspec` matches, symlinks included, in tree order.
+/// None if the tree can't be read.
+pub fn tree_files(tree: ObjectId, pathspec: &Pathspec) -> Option<Vec<GrepFile>> {
+    let files = flatten_tree(tree)?
+        .into_iter()
+        .filter(|(path, (mode, _))| *mode != MODE_GITLINK && pathspec.matches(path))
+        .map(|(path, (_, id))| GrepFile { path, blob: Some(id) })
+        .collect();
+
+    Some(files)
+}
+
+/// The regular files in the index `pathspec` matches, as staged with
+/// `cached`, otherwise as they are in the worktree. A conflicted file is
+/// searched once in the worktree, and not at all if cached, as is one
+/// only intended to be added.
+pub fn index_files(index: &Index, pathspec: &Pathspec, cached: bool) -> Vec<GrepFile> {
+    let mut files = Vec::new();
+    let mut previous: Option<&[u8]> = None;
+
+    for entry in &index.entries {
+        // A conflict's stages follow one another
+        if previous.replace(&entry.path) == Some(entry.path.as_slice()) {
+            continue;
+        }
+
+        let regular = entry.mode & 0o170000 == 0o100000;
+
+        if !regular || !pathspec.matches(&entry.path) {
+            continue;
+        }
+
+        // Outside a sparse checkout, the worktree has no copy to search
+        if cached || entry.skip_worktree {
+            if entry.stage == 0 && !entry.intent_to_add {
+                files.push(GrepFile { path: entry.path.clone(), blob: Some(entry.id) });
+            }
+
+            continue;
+        }
+
+        files.push(GrepFile { path: entry.path.clone(), blob: None });
+    }
+
+    files
+}
+
+/// Reads `files` on up to `threads` threads, passing each one's contents
+/// (None if they can't be read) to `found` in order as soon as they and
+/// all before them are read, so the results stream. Stops at the first
+/// error `found` gives.
+pub fn read_files<F>(files: &[GrepFile], threads: usize, mut found: F) -> io::Result<()>
+where
+    F: FnMut(&GrepFile, Option<Vec<u8>>) -> io::Result<()>,
+{
+    if threads <= 1 {
+        return files.iter().try_for_each(|file| found(file, file.read()));
+    }
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads.min(files.len()) {
+            let sender = sender.clone();
+            let next = &next;
+
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+
+                let Some(file) = files.get(i) else {
+                    break;
+                };
+
+                // The receiver has gone if `found` failed
+                if sender.send((i, file.read())).is_err() {
+                    break;
+                }
+            });
+        }
+
+        drop(sender);
+
+        // What's been read ahead of the next file due, by position
+        let mut pending = BTreeMap::new();
+        let mut due = 0;
+
+        for (i, contents) in receiver {
+            pending.insert(i, contents);
+
+            while let Some(contents) = pending.remove(&due) {
+                if let Err(err) = found(&files[due], contents) {
+                    // Sends the workers home
+                    next.store(files.len(), Ordering::Relaxed);
+                    return Err(err);
+                }
+
+                due += 1;
+            }
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,159 @@
+use std::env;
+use std::fs;
+
+use crate::config::Config;
+use crate::date;
+use crate::store::object::Identity;
+
+/// Which identity of a commit (or tag) is being looked up
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IdentRole {
+    Author,
+    Committer,
+}
+
+impl IdentRole {
+    fn env_prefix(self) -> &'static str {
+        match self {
+            IdentRole::Author => "GIT_AUTHOR",
+            IdentRole::Committer => "GIT_COMMITTER",
+        }
+    }
+
+    fn config_section(self) -> &'static str {
+        match self {
+            IdentRole::Author => "author",
+            IdentRole::Committer => "committer",
+        }
+    }
+}
+
+/// Works out the identity to record, eg. "A U Thor <author@example.com> 1112911993 -0700"
+///
+/// The name and email come from GIT_{AUTHOR,COMMITTER}_{NAME,EMAIL}, then
+/// `{author,committer}.{name,email}`, then `user.{name,email}` and finally
+/// $EMAIL. The time is GIT_{AUTHOR,COMMITTER}_DATE if set, otherwise now
+/// in the local timezone.
+pub fn ident(role: IdentRole, config: &Config) -> Option<Identity> {
+    ident_with(role, config, None, None)
+}
+
+/// Like `ident`, with the name and email taken from `name_email`, as
+/// given to `--author`, and the time from `date`, as given to `--date`,
+/// when they're set.
+///
+/// Dates are anything `date::parse_date` accepts, eg. "@1112911993 -0700",
+/// or a relative date such as "2.days.ago", which is taken to be in the
+/// local timezone. The same goes for GIT_{AUTHOR,COMMITTER}_DATE.
+pub fn ident_with(role: IdentRole, config: &Config, name_email: Option<&str>, date: Option<&str>) -> Option<Identity> {
+    let prefix = role.env_prefix();
+    let lookup = |field: &str| configured(role, config, field);
+
+    let (name, email) = match name_email {
+        Some(name_email) => {
+            let Some((name, email)) = parse_name_email(name_email) else {
+                eprintln!("--author '{name_email}' is not 'Name <email>'");
+                return None;
+            };
+
+            (Some(name), Some(email))
+        }
+        None => (lookup("name"), lookup("email").or_else(|| env::var("EMAIL").ok())),
+    };
+
+    let (Some(name), Some(email)) = (name, email) else {
+        eprintln!("{role:?} identity unknown, please set user.name and user.email");
+        return None;
+    };
+
+    let date = date.map(String::from).or_else(|| env::var(format!("{prefix}_DATE")).ok());
+    let now = date::now();
+
+    let (time, offset) = match date {
+        Some(value) => match date::parse_date(&value).or_else(|| date::parse_relative_date(&value, now)) {
+            Some(date) => date,
+            None => {
+                eprintln!("invalid date format: {value}");
+                return None;
+            }
+        },
+        None => (now, date::local_offset(now)),
+    };
+
+    Some(Identity {
+        name: sanitize(&name),
+        email: sanitize(&email),
+        timestamp: time,
+        tz_offset: offset,
+    })
+}
+
+/// The identity a reflog entry is recorded with, the committer's, made up
+/// from the user's login and the host's name as far as it isn't configured.
+/// Like git, a ref update isn't refused for want of an identity, the way a
+/// commit is.
+pub fn reflog_ident(config: &Config) -> Identity {
+    let role = IdentRole::Committer;
+    let user = env::var("USER").or_else(|_| env::var("LOGNAME")).unwrap_or_else(|_| "unknown".to_string());
+
+    let name = configured(role, config, "name").unwrap_or_else(|| user.clone());
+
+    let email = configured(role, config, "email")
+        .or_else(|| env::var("EMAIL").ok())
+        .unwrap_or_else(|| format!("{user}@{}", hostname()));
+
+    // A date which won't parse is left out rather than refused
+    let now = date::now();
+
+    let (time, offset) = env::var("GIT_COMMITTER_DATE").ok()
+        .and_then(|value| date::parse_date(&value).or_else(|| date::parse_relative_date(&value, now)))
+        .unwrap_or_else(|| (now, date::local_offset(now)));
+
+    Identity {
+        name: sanitize(&name),
+        email: sanitize(&email),
+        timestamp: time,
+        tz_offset: offset,
+    }
+}
+
+/// The identity's `field` ("name" or "email") from the environment or the
+/// config, as `ident_with` looks for it
+fn configured(role: IdentRole, config: &Config, field: &str) -> Option<String> {
+    let section = role.config_section();
+
+    env::var(format!("{}_{}", role.env_prefix(), field.to_uppercase())).ok()
+        .or_else(|| config.get(&format!("{section}.{field}")).map(String::from))
+        .or_else(|| config.get(&format!("user.{field}")).map(String::from))
+}
+
+/// The host's name, "(none)" as git has it if it can't be found
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .map(|hostname| hostname.trim().to_string())
+        .ok()
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| "(none)".to_string())
+}
+
+/// Splits "Name <email>" into its name and email
+fn parse_name_email(value: &str) -> Option<(String, String)> {
+    let (name, rest) = value.split_once('<')?;
+    let (email, rest) = rest.split_once('>')?;
+
+    if !rest.trim().is_empty() {
+        return None;
+    }
+
+    Some((name.trim().to_string(), email.trim().to_string()))
+}
+
+/// Strips the characters which would make an identity line unparsable
+fn sanitize(field: &str) -> String {
+    field.chars()
+        .filter(|c| !matches!(c, '<' | '>' | '\n'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
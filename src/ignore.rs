@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{xdg_config_path, Config};
+use crate::repo::git_path;
+use crate::wildmatch::{wildmatch, WildmatchFlags};
+
+/// One line of a `.gitignore` style file
+#[derive(Debug, Clone)]
+pub struct IgnorePattern {
+    pattern: String,
+
+    /// "!pattern", re-includes paths an earlier pattern excluded
+    negated: bool,
+
+    /// "pattern/", only matches directories
+    dir_only: bool,
+
+    /// Patterns with a '/' before their end match the path relative to
+    /// `base`, others match the basename at any depth below it
+    anchored: bool,
+
+    /// The directory of the file the pattern came from, "" or "dir/"
+    base: String,
+
+    /// The file the pattern came from, its line number and the line as
+    /// written, for reporting which pattern matched
+    source: String,
+    line: usize,
+    text: String,
+}
+
+impl IgnorePattern {
+    /// Parses a line of an ignore file in directory `base`, blank lines
+    /// and comments give None.
+    pub fn parse(line: &str, base: &str) -> Option<IgnorePattern> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let text = trim_trailing_spaces(line);
+        let mut line = text;
+
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let negated = line.starts_with('!');
+
+        // A leading '!' or an escaped "\!" or "\#" is dropped
+        if negated || line.starts_with("\\!") || line.starts_with("\\#") {
+            line = &line[1..];
+        }
+
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+
+        if line.is_empty() {
+            return None;
+        }
+
+        let anchored = line.contains('/');
+        let pattern = line.strip_prefix('/').unwrap_or(line).to_string();
+
+        Some(IgnorePattern {
+            pattern,
+            negated,
+            dir_only,
+            anchored,
+            base: base.to_string(),
+            source: String::new(),
+            line: 0,
+            text: text.to_string(),
+        })
+    }
+
+    /// Does the pattern re-include what it matches?
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Does the pattern match `path` (relative to the repository root),
+    /// ignoring negation?
+    pub fn matches(&self, path: &str, is_dir: bool, flags: WildmatchFlags) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let Some(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+
+        if self.anchored {
+            wildmatch(&self.pattern, relative, flags)
+        } else {
+            let basename = relative.rsplit('/').next().unwrap_or(relative);
+            wildmatch(&self.pattern, basename, flags)
+        }
+    }
+}
+
+/// Trailing spaces are ignored unless escaped with a backslash
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+
+    while line[..end].ends_with(' ') {
+        if line[..end - 1].ends_with('\\') {
+            break;
+        }
+
+        end -= 1;
+    }
+
+    &line[..end]
+}
+
+/// `<source>:<line>:<pattern>`, as `check-ignore -v` shows it
+impl fmt::Display for IgnorePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.source, self.line, self.text)
+    }
+}
+
+fn read_patterns(path: &Path, base: &str) -> Vec<IgnorePattern> {
+    let Ok(contents) = fs::read(path) else {
+        return Vec::new();
+    };
+
+    let source = path.display().to_string();
+
+    String::from_utf8_lossy(&contents)
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let pattern = IgnorePattern::parse(line, base)?;
+            Some(IgnorePattern { source: source.clone(), line: i + 1, ..pattern })
+        })
+        .collect()
+}
+
+/// The file core.excludesFile names, by default `git/ignore` in the XDG
+/// config directory
+fn excludes_file(config: &Config) -> Option<PathBuf> {
+    config.get_path("core.excludesfile").or_else(|| xdg_config_path("ignore"))
+}
+
+/// Decides which worktree paths are ignored, from the `.gitignore` in
+/// each directory, `.git/info/exclude` and core.excludesFile.
+///
+/// A `.gitignore` takes precedence over those in its parent directories,
+/// which all take precedence over `.git/info/exclude`, and that over
+/// core.excludesFile. Within a file the last matching pattern wins.
+pub struct IgnoreRules {
+    flags: WildmatchFlags,
+
+    /// `.git/info/exclude` followed by core.excludesFile
+    exclude: Vec<Vec<IgnorePattern>>,
+
+    /// Each directory's `.gitignore`, loaded as they're needed
+    per_dir: HashMap<String, Vec<IgnorePattern>>,
+}
+
+impl IgnoreRules {
+    pub fn load(config: &Config) -> IgnoreRules {
+        IgnoreRules {
+            flags: WildmatchFlags {
+                pathname: true,
+                casefold: config.get_bool("core.ignorecase").unwrap_or(false),
+            },
+            exclude: std::iter::once(git_path("info/exclude"))
+                .chain(excludes_file(config))
+                .map(|path| read_patterns(&path, ""))
+                .collect(),
+            per_dir: HashMap::new(),
+        }
+    }
+
+    /// Is `path` (relative to the repository root) ignored? Anything
+    /// inside an ignored directory is, as git never looks inside them for
+    /// a pattern to re-include it.
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> bool {
+        self.matching(path, is_dir).is_some_and(|pattern| !pattern.negated)
+    }
+
+    /// The pattern which decides whether `path` is ignored, which is a
+    /// negated one if it's re-included. For a path inside an ignored
+    /// directory it's the one ignoring the directory.
+    pub fn matching(&mut self, path: &str, is_dir: bool) -> Option<&IgnorePattern> {
+        let path = path.trim_end_matches('/');
+
+        let ignored_parent = path.match_indices('/')
+            .map(|(pos, _)| &path[..pos])
+            .find(|parent| self.matches(parent, true).is_some_and(|pattern| !pattern.negated));
+
+        match ignored_parent {
+            Some(parent) => self.matches(parent, true),
+            None => self.matches(path, is_dir),
+        }
+    }
+
+    /// The last matching pattern with the highest precedence for `path`
+    /// itself, without considering its parents
+    fn matches(&mut self, path: &str, is_dir: bool) -> Option<&IgnorePattern> {
+        let dirs: Vec<&str> = std::iter::once("")
+            .chain(path.match_indices('/').map(|(pos, _)| &path[..=pos]))
+            .collect();
+
+        for dir in &dirs {
+            if !self.per_dir.contains_key(*dir) {
+                let patterns = read_patterns(Path::new(&format!("{dir}.gitignore")), dir);
+                self.per_dir.insert(dir.to_string(), patterns);
+            }
+        }
+
+        // Deepest first, so the first match found has the highest precedence
+        let lists = dirs.iter()
+            .rev()
+            .map(|dir| &self.per_dir[*dir])
+            .chain(&self.exclude);
+
+        let flags = self.flags;
+
+        lists.flat_map(|patterns| patterns.iter().rev())
+            .find(|pattern| pattern.matches(path, is_dir, flags))
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_BRANCH: &str = "master";
+
+/// What `init` did
+#[derive(Debug)]
+pub struct InitResult {
+    /// Absolute path to the new (or existing) git directory
+    pub git_dir: PathBuf,
+
+    /// True if there already was a repository here, which is left as is
+    pub reinitialized: bool,
+}
+
+/// Creates the skeleton of an empty repository.
+///
+/// For a bare repository `path` is the git directory itself, otherwise the
+/// git directory is `path/.git`. Initializing an existing repository only
+/// fills in whatever is missing, HEAD and the config are never overwritten.
+pub fn init(path: &Path, bare: bool, initial_branch: &str) -> io::Result<InitResult> {
+    let git_dir = if bare {
+        path.to_path_buf()
+    } else {
+        path.join(".git")
+    };
+
+    let reinitialized = git_dir.join("HEAD").is_file();
+
+    for dir in ["objects/info", "objects/pack", "refs/heads", "refs/tags", "info"] {
+        fs::create_dir_all(git_dir.join(dir))?;
+    }
+
+    if !reinitialized {
+        fs::write(git_dir.join("HEAD"), format!("ref: refs/heads/{initial_branch}\n"))?;
+    }
+
+    let config_path = git_dir.join("config");
+
+    if !config_path.exists() {
+        fs::write(config_path, format!(
+            "[core]\n\
+             \trepositoryformatversion = 0\n\
+             \tfilemode = true\n\
+             \tbare = {bare}\n\
+             \tlogallrefupdates = {}\n",
+            !bare,
+        ))?;
+    }
+
+    Ok(InitResult {
+        git_dir: fs::canonicalize(&git_dir)?,
+        reinitialized,
+    })
+}
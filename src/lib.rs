@@ -0,0 +1,53 @@
+pub mod store;
+pub mod config;
+pub mod fsck;
+pub mod refs;
+pub mod revparse;
+pub mod graph;
+pub mod worktree;
+pub mod status;
+pub mod wildmatch;
+pub mod ignore;
+pub mod ref_filter;
+pub mod lockfile;
+pub mod date;
+pub mod reflog;
+pub mod init;
+pub mod repo;
+pub mod checkout;
+pub mod ident;
+pub mod message;
+pub mod editor;
+pub mod quote;
+pub mod bundle;
+pub mod refspec;
+pub mod remote;
+pub mod pathspec;
+pub mod diff;
+pub mod revwalk;
+pub mod pretty;
+pub mod advertise;
+pub mod blame;
+pub mod autostash;
+pub mod autosquash;
+pub mod sequencer;
+pub mod submodule;
+pub mod push_cert;
+pub mod merge;
+pub mod linked_worktree;
+pub mod mailmap;
+pub mod filter;
+pub mod list_objects;
+pub mod signature;
+pub mod grep;
+pub mod archive;
+pub mod attributes;
+pub mod protocol;
+pub mod stash;
+pub mod metrics;
+
+#[cfg(feature = "testtools")]
+pub mod testtools;
+
+pub const MIN_USER_HASH_LEN: usize = 4;
+pub const SHA1_HASH_SIZE: usize = 20;
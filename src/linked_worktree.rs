@@ -0,0 +1,303 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::repo::{git_path, RepoPaths};
+
+/// A linked worktree, as the common dir records it in its administrative
+/// directory, `worktrees/<id>`. That directory is the worktree's git dir,
+/// and as well as its per-worktree state holds:
+///
+///   gitdir     the path of the worktree's ".git" file
+///   commondir  the common dir, relative to the administrative directory
+///   locked     if present the worktree is never pruned, it holds why
+///
+/// The worktree's ".git" file points back at the administrative directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedWorktree {
+    pub id: String,
+    pub admin_dir: PathBuf,
+}
+
+impl LinkedWorktree {
+    /// The worktree's ".git" file, as its gitdir file records it
+    pub fn dotgit(&self) -> Option<PathBuf> {
+        let contents = fs::read_to_string(self.admin_dir.join("gitdir")).ok()?;
+        let dotgit = contents.trim_end_matches(['\n', '\r']);
+
+        match dotgit.is_empty() {
+            true => None,
+            false => Some(self.admin_dir.join(dotgit)),
+        }
+    }
+
+    /// The root of the worktree
+    pub fn path(&self) -> Option<PathBuf> {
+        Some(self.dotgit()?.parent()?.to_path_buf())
+    }
+
+    /// Why the worktree is locked, "" if no reason was given, or None if
+    /// it isn't
+    pub fn lock_reason(&self) -> Option<String> {
+        let reason = fs::read_to_string(self.admin_dir.join("locked")).ok()?;
+        Some(reason.trim_end_matches(['\n', '\r']).to_string())
+    }
+}
+
+/// Every linked worktree of the repository, by id
+pub fn linked_worktrees() -> Vec<LinkedWorktree> {
+    let Ok(contents) = fs::read_dir(git_path("worktrees")) else {
+        return Vec::new();
+    };
+
+    let mut worktrees: Vec<LinkedWorktree> = contents.flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter_map(|entry| Some(LinkedWorktree {
+            id: entry.file_name().into_string().ok()?,
+            admin_dir: entry.path(),
+        }))
+        .collect();
+
+    worktrees.sort_by(|a, b| a.id.cmp(&b.id));
+    worktrees
+}
+
+/// An administrative directory found to be stale by `prune_worktrees`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pruned {
+    pub id: String,
+    pub reason: String,
+}
+
+/// Removes the administrative directories of linked worktrees which no
+/// longer exist, returning what was (or with `dry_run`, would have been)
+/// removed.
+///
+/// A worktree whose directory has gone is only pruned if its index was
+/// last written before `expire`, so one on removable media isn't lost for
+/// being unmounted. Locked worktrees are never pruned.
+pub fn prune_worktrees(expire: i64, dry_run: bool) -> io::Result<Vec<Pruned>> {
+    let worktrees_dir = git_path("worktrees");
+
+    let Ok(contents) = fs::read_dir(&worktrees_dir) else {
+        return Ok(Vec::new());
+    };
+
+    let mut entries: Vec<_> = contents.flatten().collect();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut pruned = Vec::new();
+
+    for entry in entries {
+        let Some(reason) = prune_reason(&entry.path(), expire) else {
+            continue;
+        };
+
+        if !dry_run {
+            match entry.file_type()?.is_dir() {
+                true => fs::remove_dir_all(entry.path())?,
+                false => fs::remove_file(entry.path())?,
+            }
+        }
+
+        pruned.push(Pruned { id: entry.file_name().to_string_lossy().into_owned(), reason });
+    }
+
+    // Nothing is left to administer
+    if !dry_run {
+        let _ = fs::remove_dir(&worktrees_dir);
+    }
+
+    Ok(pruned)
+}
+
+/// Why the administrative directory `admin_dir` is stale, if it is
+fn prune_reason(admin_dir: &Path, expire: i64) -> Option<String> {
+    if !admin_dir.is_dir() {
+        return Some("not a valid directory".to_string());
+    }
+
+    if admin_dir.join("locked").exists() {
+        return None;
+    }
+
+    let gitdir = admin_dir.join("gitdir");
+
+    if !gitdir.exists() {
+        return Some("gitdir file does not exist".to_string());
+    }
+
+    let contents = match fs::read_to_string(&gitdir) {
+        Ok(contents) => contents,
+        Err(err) => return Some(format!("unable to read gitdir file ({err})")),
+    };
+
+    let dotgit = contents.trim_end_matches(['\n', '\r']);
+
+    if dotgit.is_empty() {
+        return Some("invalid gitdir file".to_string());
+    }
+
+    if admin_dir.join(dotgit).exists() {
+        return None;
+    }
+
+    let index_mtime = fs::metadata(admin_dir.join("index")).map(|metadata| metadata.mtime());
+
+    match index_mtime {
+        Ok(mtime) if mtime > expire => None,
+        _ => Some("gitdir file points to non-existent location".to_string()),
+    }
+}
+
+/// Something `repair_worktrees` or `repair_worktree_at` found wrong with
+/// `path`, and repaired unless it's an `error`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repair {
+    pub path: PathBuf,
+    pub message: &'static str,
+    pub error: bool,
+}
+
+/// Repairs the ".git" file of each linked worktree which no longer points
+/// at its administrative directory, as after the main worktree is moved.
+/// Worktrees that have gone are left for `prune_worktrees`.
+pub fn repair_worktrees() -> io::Result<Vec<Repair>> {
+    let mut repairs = Vec::new();
+
+    for worktree in linked_worktrees() {
+        let Some(path) = worktree.path() else {
+            continue;
+        };
+
+        if !path.exists() {
+            continue;
+        }
+
+        if !path.is_dir() {
+            repairs.push(Repair { path, message: "not a directory", error: true });
+            continue;
+        }
+
+        let admin_dir = fs::canonicalize(&worktree.admin_dir)?;
+        let dotgit = path.join(".git");
+
+        let message = match read_dotgit(&dotgit) {
+            Err(DotGitError::NotAFile) => {
+                repairs.push(Repair { path, message: ".git is not a file", error: true });
+                continue;
+            }
+            Err(_) => ".git file broken",
+            Ok(backlink) if backlink != admin_dir => ".git file incorrect",
+            Ok(_) => continue,
+        };
+
+        repairs.push(Repair { path, message, error: false });
+        write_linking_files(&fs::canonicalize(&dotgit).unwrap_or(dotgit), &admin_dir)?;
+    }
+
+    Ok(repairs)
+}
+
+/// Repairs the links between the worktree at `path` and its administrative
+/// directory, as after the worktree is moved.
+///
+/// The administrative directory is found from the worktree's ".git" file,
+/// or should that not lead to one (the main worktree having moved too),
+/// from the id at the end of the path in it.
+pub fn repair_worktree_at(path: &Path) -> io::Result<Vec<Repair>> {
+    let dotgit = path.join(".git");
+
+    let Ok(dotgit) = fs::canonicalize(&dotgit) else {
+        return Ok(vec![Repair { path: path.to_path_buf(), message: "not a valid path", error: true }]);
+    };
+
+    let common_dir = fs::canonicalize(RepoPaths::discover().common_dir)?;
+
+    // The main worktree has no administrative directory
+    if dotgit == common_dir {
+        return Ok(Vec::new());
+    }
+
+    let inferred = infer_backlink(&dotgit, &common_dir);
+    let error = |message| Ok(vec![Repair { path: dotgit.clone(), message, error: true }]);
+
+    // Where the two disagree, the id is trusted over the path
+    let (admin_dir, dotgit_repair) = match (read_dotgit(&dotgit), inferred) {
+        (Err(DotGitError::NotAFile), _) => return error("unable to locate repository; .git is not a file"),
+        (Err(DotGitError::NotARepo), None) => {
+            return error("unable to locate repository; .git file does not reference a repository");
+        }
+        (Err(DotGitError::Broken), None) => return error("unable to locate repository; .git file broken"),
+        (Err(_), Some(inferred)) => (inferred, Some(".git file broken")),
+        (Ok(backlink), Some(inferred)) if backlink != inferred => (inferred, Some(".git file incorrect")),
+        (Ok(backlink), _) => (backlink, None),
+    };
+
+    let gitdir = admin_dir.join("gitdir");
+
+    let gitdir_repair = match fs::read_to_string(&gitdir) {
+        Err(_) => Some("gitdir unreadable"),
+        Ok(contents) if Path::new(contents.trim_end()) != dotgit => Some("gitdir incorrect"),
+        Ok(_) => None,
+    };
+
+    let repairs: Vec<Repair> = [(dotgit.clone(), dotgit_repair), (gitdir, gitdir_repair)]
+        .into_iter()
+        .filter_map(|(path, message)| Some(Repair { path, message: message?, error: false }))
+        .collect();
+
+    if !repairs.is_empty() {
+        write_linking_files(&dotgit, &admin_dir)?;
+    }
+
+    Ok(repairs)
+}
+
+/// Why a ".git" file couldn't be read as a link to a git dir
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DotGitError {
+    NotAFile,
+    Broken,
+    NotARepo,
+}
+
+/// The git dir a ".git" file links to:
+///   "gitdir: " <path> \n
+///
+/// The path is relative to the directory containing the file
+fn read_dotgit(dotgit: &Path) -> Result<PathBuf, DotGitError> {
+    if !dotgit.is_file() {
+        return Err(DotGitError::NotAFile);
+    }
+
+    let contents = fs::read_to_string(dotgit).map_err(|_| DotGitError::Broken)?;
+    let git_dir = contents.trim_end().strip_prefix("gitdir: ").ok_or(DotGitError::Broken)?;
+
+    let base = dotgit.parent().unwrap_or(Path::new(""));
+
+    fs::canonicalize(base.join(git_dir))
+        .ok()
+        .filter(|git_dir| git_dir.join("HEAD").exists())
+        .ok_or(DotGitError::NotARepo)
+}
+
+/// The administrative directory the ".git" file `dotgit` was made to link
+/// to, `worktrees/<id>` in the common dir, if there's one by that id
+fn infer_backlink(dotgit: &Path, common_dir: &Path) -> Option<PathBuf> {
+    let contents = fs::read_to_string(dotgit).ok()?;
+    let git_dir = contents.trim_end().strip_prefix("gitdir: ")?;
+
+    let id = Path::new(git_dir).file_name()?;
+    let admin_dir = common_dir.join("worktrees").join(id);
+
+    admin_dir.is_dir().then_some(admin_dir)
+}
+
+/// Points the ".git" file `dotgit` and the administrative directory
+/// `admin_dir` at each other, both by absolute path
+fn write_linking_files(dotgit: &Path, admin_dir: &Path) -> io::Result<()> {
+    fs::write(dotgit, format!("gitdir: {}\n", admin_dir.display()))?;
+    fs::write(admin_dir.join("gitdir"), format!("{}\n", dotgit.display()))
+}
@@ -0,0 +1,330 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+use crate::filter::{ObjectFilter, TreeDecision};
+use crate::graph::Graph;
+use crate::revparse::peel_to_commit;
+use crate::revwalk::RevWalk;
+use crate::store::tree::{MODE_GITLINK, MODE_TREE};
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+
+/// What a walk does about an object it can't find, as `--missing` says
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MissingAction {
+    /// Stops the walk
+    #[default]
+    Error,
+
+    /// Carries on as if the object wasn't there to be found
+    AllowAny,
+
+    /// Carries on, keeping a list of what was missing
+    Print,
+}
+
+impl MissingAction {
+    /// "error", "allow-any" or "print"
+    pub fn parse(action: &str) -> Option<MissingAction> {
+        match action {
+            "error" => Some(MissingAction::Error),
+            "allow-any" => Some(MissingAction::AllowAny),
+            "print" => Some(MissingAction::Print),
+            _ => None,
+        }
+    }
+}
+
+/// An object a walk stopped at for not finding it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MissingObject {
+    pub id: ObjectId,
+    pub kind: &'static str,
+}
+
+impl fmt::Display for MissingObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            "tree" => write!(f, "bad tree object {}", self.id),
+            kind => write!(f, "missing {kind} object '{}'", self.id),
+        }
+    }
+}
+
+impl std::error::Error for MissingObject {}
+
+/// Walks the trees and blobs reachable from commits' trees, and the tags
+/// of the starting points, as `rev-list --objects` lists them: each tree
+/// before what's in it, and every object only the first time it's
+/// reached. Submodule commits are never listed.
+pub struct ObjectWalk {
+    filter: Option<ObjectFilter>,
+    seen: HashSet<ObjectId>,
+
+    /// What the filter left out, less anything it showed when reached
+    /// again by another path
+    omitted: BTreeSet<ObjectId>,
+
+    /// With `record_omitted`, trees the filter leaves out are still looked
+    /// inside, so all that's left out is known
+    record_omitted: bool,
+
+    missing_action: MissingAction,
+
+    /// With `MissingAction::Print`, the objects which couldn't be found
+    missing: BTreeSet<ObjectId>,
+}
+
+impl ObjectWalk {
+    pub fn new(filter: Option<ObjectFilter>) -> ObjectWalk {
+        ObjectWalk {
+            filter,
+            seen: HashSet::new(),
+            omitted: BTreeSet::new(),
+            record_omitted: false,
+            missing_action: MissingAction::default(),
+            missing: BTreeSet::new(),
+        }
+    }
+
+    /// Decides what's done about objects which can't be found, as in a
+    /// partial clone, rather than stopping at the first
+    pub fn set_missing_action(&mut self, action: MissingAction) {
+        self.missing_action = action;
+    }
+
+    /// Finds everything the filter leaves out, not only what's reached
+    /// without going into a tree it's left out
+    pub fn record_omitted(&mut self) {
+        self.record_omitted = true;
+    }
+
+    /// Marks everything reachable from `tree` as already listed, as for
+    /// the trees of hidden commits, which the other side already has.
+    /// Trees which can't be found are passed over, as they're not wanted.
+    pub fn hide_tree(&mut self, tree: ObjectId) {
+        let mut stack = vec![tree];
+
+        while let Some(id) = stack.pop() {
+            if !self.seen.insert(id) {
+                continue;
+            }
+
+            let Some(GitObjectData::Tree { entries }) = GitObjectStore::get(id).map(|object| object.data) else {
+                continue;
+            };
+
+            for entry in entries {
+                match entry.mode {
+                    MODE_TREE => stack.push(entry.id),
+                    MODE_GITLINK => {}
+                    _ => {
+                        self.seen.insert(entry.id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lists the annotated tag `id` by its name, unless it's been listed
+    pub fn walk_tag(&mut self, id: ObjectId, name: &str, show: &mut impl FnMut(ObjectId, &str)) {
+        if self.seen.insert(id) {
+            show(id, name);
+        }
+    }
+
+    /// Lists what's reachable from the root tree `tree` and hasn't been
+    /// listed, with its path, "" for the root itself. Unless the missing
+    /// action allows it, fails at the first object which can't be found.
+    pub fn walk_tree(&mut self, tree: ObjectId, show: &mut impl FnMut(ObjectId, &str)) -> Result<(), MissingObject> {
+        self.visit_tree(tree, "", 0, show)
+    }
+
+    fn visit_tree(&mut self, id: ObjectId, path: &str, depth: u64, show: &mut impl FnMut(ObjectId, &str))
+        -> Result<(), MissingObject>
+    {
+        if self.seen.contains(&id) {
+            return Ok(());
+        }
+
+        let object = GitObjectStore::get(id);
+
+        if object.is_none() {
+            return self.missing(id, "tree");
+        }
+
+        let decision = match &self.filter {
+            Some(filter) => filter.tree(depth),
+            None => TreeDecision { show: true, recurse: true },
+        };
+
+        // A tree left out may yet be shown if it's reached nearer the root
+        let newly_omitted = match decision.show {
+            true => {
+                self.seen.insert(id);
+                self.omitted.remove(&id);
+                show(id, path);
+                false
+            }
+            false => self.omitted.insert(id),
+        };
+
+        let look_inside = decision.recurse || (self.record_omitted && newly_omitted);
+
+        if !look_inside {
+            return Ok(());
+        }
+
+        let Some(GitObjectData::Tree { entries }) = object.map(|object| object.data) else {
+            return self.missing(id, "tree");
+        };
+
+        for entry in entries {
+            let name = String::from_utf8_lossy(&entry.path);
+
+            let entry_path = match path.is_empty() {
+                true => name.into_owned(),
+                false => format!("{path}/{name}"),
+            };
+
+            match entry.mode {
+                MODE_TREE => self.visit_tree(entry.id, &entry_path, depth + 1, show)?,
+                MODE_GITLINK => {}
+                _ => self.visit_blob(entry.id, &entry_path, depth + 1, show)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_blob(&mut self, id: ObjectId, path: &str, depth: u64, show: &mut impl FnMut(ObjectId, &str))
+        -> Result<(), MissingObject>
+    {
+        if self.seen.contains(&id) {
+            return Ok(());
+        }
+
+        let shown = self.filter.as_ref().is_none_or(|filter| filter.blob(id, path, depth));
+
+        // Blobs the filter leaves out needn't be there
+        if shown && !GitObjectStore::exists(id) {
+            return self.missing(id, "blob");
+        }
+
+        match shown {
+            true => {
+                self.seen.insert(id);
+                self.omitted.remove(&id);
+                show(id, path);
+            }
+            false => {
+                self.omitted.insert(id);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn missing(&mut self, id: ObjectId, kind: &'static str) -> Result<(), MissingObject> {
+        match self.missing_action {
+            MissingAction::Error => return Err(MissingObject { id, kind }),
+            MissingAction::AllowAny => {}
+            MissingAction::Print => {
+                self.missing.insert(id);
+            }
+        }
+
+        self.seen.insert(id);
+
+        Ok(())
+    }
+
+    /// The objects the filter left out, by id
+    pub fn omitted(&self) -> &BTreeSet<ObjectId> {
+        &self.omitted
+    }
+
+    /// With `MissingAction::Print`, the objects which couldn't be found,
+    /// by id
+    pub fn missing_objects(&self) -> &BTreeSet<ObjectId> {
+        &self.missing
+    }
+}
+
+/// What the other side needs sent to have `wants` when it has `haves` and
+/// everything reachable from them, as for a push or a fetch: the commits
+/// between, their trees and blobs, and the tags on the way from a want to
+/// what it points at. Haves we don't have ourselves are passed over, and
+/// what `filter` leaves out of the commits' trees isn't sent, as for a
+/// partial clone.
+pub fn objects_to_send(wants: &[ObjectId], haves: &[ObjectId], filter: Option<ObjectFilter>) -> Result<Vec<ObjectId>, MissingObject> {
+    let mut objects = Vec::new();
+    let mut walk = RevWalk::new();
+    let mut trees = ObjectWalk::new(filter);
+
+    // Wanted trees and blobs which aren't in a commit
+    let mut roots = Vec::new();
+
+    for &have in haves {
+        if let Some(commit) = peel_to_commit(have) {
+            walk.hide(commit);
+        }
+    }
+
+    for &want in wants {
+        let mut id = want;
+
+        loop {
+            let Some(object) = GitObjectStore::get(id) else {
+                let kind = if id == want { "wanted" } else { "tagged" };
+                return Err(MissingObject { id, kind });
+            };
+
+            match object.data {
+                GitObjectData::Tag { object, .. } => {
+                    if !haves.contains(&id) && !objects.contains(&id) {
+                        objects.push(id);
+                    }
+
+                    id = object;
+                }
+                GitObjectData::Commit { .. } => {
+                    walk.push(id);
+                    break;
+                }
+                _ => {
+                    roots.push(id);
+                    break;
+                }
+            }
+        }
+    }
+
+    let commits: Vec<ObjectId> = walk.by_ref().collect();
+    let mut graph = Graph::new();
+
+    // What the hidden commits at the edge of the walk have is had already
+    for &id in &commits {
+        for parent in graph.parents(id).into_iter().filter(|&parent| walk.is_hidden(parent)) {
+            if let Some(tree) = graph.tree(parent) {
+                trees.hide_tree(tree);
+            }
+        }
+    }
+
+    objects.extend(&commits);
+
+    for id in commits {
+        let tree = graph.tree(id).ok_or(MissingObject { id, kind: "commit" })?;
+        trees.walk_tree(tree, &mut |id, _| objects.push(id))?;
+    }
+
+    for id in roots {
+        match GitObjectStore::get(id).map(|object| object.data) {
+            Some(GitObjectData::Tree { .. }) => trees.walk_tree(id, &mut |id, _| objects.push(id))?,
+            _ if !objects.contains(&id) => objects.push(id),
+            _ => {}
+        }
+    }
+
+    Ok(objects)
+}
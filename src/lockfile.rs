@@ -0,0 +1,96 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Git's lock file protocol for updating a file in place.
+///
+/// `<path>.lock` is created exclusively, so a second writer fails instead of
+/// racing us, the new contents are written to it, and it's renamed over
+/// `<path>` on commit. A lock that's dropped without being committed is
+/// removed, leaving the original untouched.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    pub fn acquire<P: AsRef<Path>>(path: P) -> io::Result<LockFile> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::AlreadyExists => io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("Unable to create '{}': File exists. \
+                        Another git process seems to be running in this repository", lock_path.display()),
+                ),
+                _ => err,
+            })?;
+
+        Ok(LockFile {
+            path,
+            lock_path,
+            file: Some(file),
+        })
+    }
+
+    /// The file being replaced
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Atomically replaces the target with what has been written. Should
+    /// that fail the lock is still released.
+    pub fn commit(mut self) -> io::Result<()> {
+        let synced = match self.file.take() {
+            Some(file) => file.sync_all(),
+            None => Ok(()),
+        };
+
+        synced.and_then(|_| fs::rename(&self.lock_path, &self.path)).inspect_err(|_| {
+            let _ = fs::remove_file(&self.lock_path);
+        })
+    }
+
+    /// Gives up on the update, leaving the target untouched
+    pub fn rollback(mut self) {
+        self.file.take();
+        let _ = fs::remove_file(&self.lock_path);
+    }
+}
+
+impl Write for LockFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.file {
+            Some(file) => file.write(buf),
+            None => Err(io::Error::other("lock file already committed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::revparse;
+use crate::store::{GitObjectData, GitObjectStore};
+
+/// Canonical names and emails for the identities people have committed
+/// under, as read from `.mailmap` files. Each line is one of:
+///
+///   <proper name> '<' <commit email> '>'
+///   '<' <proper email> '>' '<' <commit email> '>'
+///   <proper name> '<' <proper email> '>' '<' <commit email> '>'
+///   <proper name> '<' <proper email> '>' <commit name> '<' <commit email> '>'
+///
+/// the last only applying to commits under that name as well as email.
+/// Emails and names are matched case insensitively, lines starting with
+/// '#' are comments.
+#[derive(Debug, Clone, Default)]
+pub struct Mailmap {
+    /// Keyed by the lowercased commit email
+    entries: HashMap<String, MailmapEntry>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MailmapEntry {
+    /// What the email maps to whatever the name
+    proper: Mapping,
+
+    /// What it maps to under particular names, keyed by the lowercased
+    /// commit name
+    by_name: HashMap<String, Mapping>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Mapping {
+    name: Option<String>,
+    email: Option<String>,
+}
+
+impl Mailmap {
+    /// Reads the repository's mailmap, as git does: `.mailmap` at the root
+    /// of the worktree, then the blob `mailmap.blob` names (by default
+    /// "HEAD:.mailmap" in a bare repository), then the file `mailmap.file`
+    /// names. Later entries take precedence.
+    pub fn load(config: &Config) -> Mailmap {
+        let mut mailmap = Mailmap::default();
+        let bare = config.get_bool("core.bare").unwrap_or(false);
+
+        if !bare {
+            mailmap.read_file(".mailmap");
+        }
+
+        let blob = config.get("mailmap.blob").or(bare.then_some("HEAD:.mailmap"));
+
+        if let Some(blob) = blob {
+            mailmap.read_blob(blob);
+        }
+
+        if let Some(file) = config.get_path("mailmap.file") {
+            mailmap.read_file(file);
+        }
+
+        mailmap
+    }
+
+    /// Adds the entries in the file at `path`, if there is one
+    pub fn read_file<P: AsRef<Path>>(&mut self, path: P) {
+        if let Ok(data) = fs::read(path) {
+            self.add(&String::from_utf8_lossy(&data));
+        }
+    }
+
+    /// Adds the entries in the blob `rev` names, eg. "HEAD:.mailmap", if
+    /// there is one
+    pub fn read_blob(&mut self, rev: &str) {
+        let object = revparse::resolve(rev).and_then(GitObjectStore::get);
+
+        if let Some(GitObjectData::Blob { data }) = object.map(|object| object.data) {
+            self.add(&String::from_utf8_lossy(&data));
+        }
+    }
+
+    /// Adds the entries in the contents of a mailmap file, ignoring lines
+    /// that don't parse, as git does
+    pub fn add(&mut self, contents: &str) {
+        for line in contents.lines().filter(|line| !line.starts_with('#')) {
+            let Some((proper_name, proper_email, rest)) = parse_contact(line) else {
+                continue;
+            };
+
+            let commit = parse_contact(rest);
+
+            let mapping = match commit {
+                Some((commit_name, commit_email, _)) => self.mapping(commit_email, commit_name),
+                None => self.mapping(proper_email, None),
+            };
+
+            // Later entries replace only what they give
+            if let Some(proper_name) = proper_name {
+                mapping.name = Some(proper_name.to_string());
+            }
+
+            if commit.is_some() {
+                mapping.email = Some(proper_email.to_string());
+            }
+        }
+    }
+
+    fn mapping(&mut self, email: &str, name: Option<&str>) -> &mut Mapping {
+        let entry = self.entries.entry(email.to_lowercase()).or_default();
+
+        match name {
+            Some(name) => entry.by_name.entry(name.to_lowercase()).or_default(),
+            None => &mut entry.proper,
+        }
+    }
+
+    /// The canonical name and email for someone who committed as `name`
+    /// and `email`, each of which is unchanged if the mailmap doesn't say
+    /// otherwise
+    pub fn canonicalize(&self, name: &str, email: &str) -> (String, String) {
+        let mapping = self.entries.get(&email.to_lowercase()).and_then(|entry| {
+            entry.by_name.get(&name.to_lowercase())
+                .or(Some(&entry.proper))
+                .filter(|mapping| mapping.name.is_some() || mapping.email.is_some())
+        });
+
+        match mapping {
+            Some(mapping) => (
+                mapping.name.clone().unwrap_or_else(|| name.to_string()),
+                mapping.email.clone().unwrap_or_else(|| email.to_string()),
+            ),
+            None => (name.to_string(), email.to_string()),
+        }
+    }
+
+    /// An ident line, "<name> <<email>> <date>", with the name and email
+    /// canonicalized
+    pub fn map_ident(&self, ident: &str) -> String {
+        let Some((name, rest)) = ident.split_once('<') else {
+            return ident.to_string();
+        };
+
+        let Some((email, rest)) = rest.split_once('>') else {
+            return ident.to_string();
+        };
+
+        let (name, email) = self.canonicalize(name.trim(), email);
+        format!("{name} <{email}>{rest}")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Splits `[ <name> ] '<' <email> '>'` from the start of `line`, giving
+/// the name if there's one, the email, and what follows
+fn parse_contact(line: &str) -> Option<(Option<&str>, &str, &str)> {
+    let (name, rest) = line.split_once('<')?;
+    let (email, rest) = rest.split_once('>')?;
+
+    let name = name.trim();
+
+    Some((Some(name).filter(|name| !name.is_empty()), email.trim(), rest))
+}
@@ -1,46 +1,83 @@
-mod store;
 mod cli;
+mod commands;
+mod context;
 
-use cli::{Cli, Commands};
-use clap::Parser;
-use std::io::Write;
-
-use store::GitObjectStore;
-use crate::cli::CatFileArgs;
-use crate::store::util::resolve_id;
+use std::time::Instant;
 
-pub const MIN_USER_HASH_LEN: usize = 4;
-pub const SHA1_HASH_SIZE: usize = 20;
+use cli::{Cli, Commands};
+use clap::{CommandFactory, FromArgMatches};
+use context::Context;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
 
-    let cli = Cli::parse();
-
-    match cli.command {
-        Commands::CatFile(CatFileArgs {
-            mode,
-            id
-        }) => {
-            let id = resolve_id(&id).ok_or("Invalid Object Id")?;
+    let matches = Cli::command().get_matches();
+    let name = matches.subcommand_name().unwrap_or_default().to_string();
 
-            let obj = match GitObjectStore::get(id) {
-                Some(obj) => obj,
-                None => return Err("Unable to retrive object".into())
-            };
+    let cli = Cli::from_arg_matches(&matches)?;
+    let ctx = Context::new(&cli.global);
 
-            let mut stdout = std::io::stdout();
+    let start = Instant::now();
 
-            if mode.print {
-                print!("{}", obj);
-            }
-
-            if mode.kind {
-                println!("{}", obj.type_str());
-            }
-
-            stdout.flush()?;
-        }
+    match cli.command {
+        Commands::CatFile(args) => commands::cat_file::run(args, &ctx)?,
+        Commands::LsFiles(args) => commands::ls_files::run(args, &ctx)?,
+        Commands::ShowBranch(args) => commands::show_branch::run(args, &ctx)?,
+        Commands::Status(args) => commands::status::run(args, &ctx)?,
+        Commands::Branch(args) => commands::branch::run(args, &ctx)?,
+        Commands::Tag(args) => commands::tag::run(args, &ctx)?,
+        Commands::ForEachRef(args) => commands::for_each_ref::run(args, &ctx)?,
+        Commands::Reflog(args) => commands::reflog::run(args, &ctx)?,
+        Commands::Init(args) => commands::init::run(args, &ctx)?,
+        Commands::WriteTree(args) => commands::write_tree::run(args, &ctx)?,
+        Commands::CommitTree(args) => commands::commit_tree::run(args, &ctx)?,
+        Commands::Commit(args) => commands::commit::run(args, &ctx)?,
+        Commands::Fsck(args) => commands::fsck::run(args, &ctx)?,
+        Commands::Add(args) => commands::add::run(args, &ctx)?,
+        Commands::Switch(args) => commands::switch::run(args, &ctx)?,
+        Commands::Checkout(args) => commands::checkout::run(args, &ctx)?,
+        Commands::Stripspace(args) => commands::stripspace::run(args, &ctx)?,
+        Commands::VerifyBundle(args) => commands::verify_bundle::run(args, &ctx)?,
+        Commands::Remote(args) => commands::remote::run(args, &ctx)?,
+        Commands::Show(args) => commands::show::run(args, &ctx)?,
+        Commands::Log(args) => commands::log::run(args, &ctx)?,
+        Commands::RevList(args) => commands::rev_list::run(args, &ctx)?,
+        Commands::GraphExport(args) => commands::graph_export::run(args, &ctx)?,
+        Commands::CheckIgnore(args) => commands::check_ignore::run(args, &ctx)?,
+        Commands::UploadPack(args) => commands::upload_pack::run(args, &ctx)?,
+        Commands::ReceivePack(args) => commands::receive_pack::run(args, &ctx)?,
+        Commands::Config(args) => commands::config::run(args, &ctx)?,
+        Commands::ZstdStore(args) => commands::zstd_store::run(args, &ctx)?,
+        Commands::UpdateRef(args) => commands::update_ref::run(args, &ctx)?,
+        Commands::Annotate(args) => commands::annotate::run(args, &ctx)?,
+        Commands::SymbolicRef(args) => commands::symbolic_ref::run(args, &ctx)?,
+        Commands::Merge(args) => commands::merge::run(args, &ctx)?,
+        Commands::VerifyPack(args) => commands::verify_pack::run(args, &ctx)?,
+        Commands::CountObjects(args) => commands::count_objects::run(args, &ctx)?,
+        Commands::Pull(args) => commands::pull::run(args, &ctx)?,
+        Commands::Submodule(args) => commands::submodule::run(args, &ctx)?,
+        Commands::HashObject(args) => commands::hash_object::run(args, &ctx)?,
+        Commands::MergeBase(args) => commands::merge_base::run(args, &ctx)?,
+        Commands::MergeFile(args) => commands::merge_file::run(args, &ctx)?,
+        Commands::LsTree(args) => commands::ls_tree::run(args, &ctx)?,
+        Commands::Worktree(args) => commands::worktree::run(args, &ctx)?,
+        Commands::CheckMailmap(args) => commands::check_mailmap::run(args, &ctx)?,
+        Commands::Blame(args) => commands::blame::run(args, &ctx)?,
+        Commands::Grep(args) => commands::grep::run(args, &ctx)?,
+        Commands::Archive(args) => commands::archive::run(args, &ctx)?,
+        Commands::CheckAttr(args) => commands::check_attr::run(args, &ctx)?,
+        Commands::Diff(args) => commands::diff::run(args, &ctx)?,
+        Commands::Shortlog(args) => commands::shortlog::run(args, &ctx)?,
+        Commands::Fetch(args) => commands::fetch::run(args, &ctx)?,
+        Commands::Stash(args) => commands::stash::run(args, &ctx)?,
+        Commands::Clone(args) => commands::clone::run(args, &ctx)?,
+        Commands::Restore(args) => commands::restore::run(args, &ctx)?,
+        Commands::LsRemote(args) => commands::ls_remote::run(args, &ctx)?,
+        Commands::Push(args) => commands::push::run(args, &ctx)?,
     };
 
+    if ctx.timing {
+        eprintln!("{name}: {:.3}s", start.elapsed().as_secs_f64());
+    }
+
     Ok(())
 }
@@ -0,0 +1,749 @@
+use std::collections::BTreeSet;
+
+use crate::checkout::TreeFiles;
+use crate::config::Config;
+use crate::diff::{diff_lines_without_indent_heuristic as diff_lines, is_binary, split_lines, Change};
+use crate::graph::Graph;
+use crate::revparse::peel_to_tree;
+use crate::store::index::{Index, Stages};
+use crate::store::tree::{flatten_tree, MODE_BLOB, MODE_EXECUTABLE, MODE_GITLINK};
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+
+/// How many of each character make up a conflict marker, eg. "<<<<<<<"
+pub const DEFAULT_MARKER_SIZE: usize = 7;
+
+/// How a conflict is written out, as `merge.conflictStyle` names them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStyle {
+    /// Only the lines where the two sides differ, ours then theirs
+    #[default]
+    Merge,
+
+    /// Both sides in full, with the base version between them
+    Diff3,
+
+    /// As diff3, less the lines both sides begin and end with
+    ZealousDiff3,
+}
+
+impl ConflictStyle {
+    pub fn parse(name: &str) -> Option<ConflictStyle> {
+        match name {
+            "merge" => Some(ConflictStyle::Merge),
+            "diff3" => Some(ConflictStyle::Diff3),
+            "zdiff3" => Some(ConflictStyle::ZealousDiff3),
+            _ => None,
+        }
+    }
+
+    /// The style `merge.conflictStyle` asks for, the default if unset
+    pub fn from_config(config: &Config) -> Option<ConflictStyle> {
+        let Some(name) = config.get("merge.conflictstyle") else {
+            return Some(ConflictStyle::default());
+        };
+
+        ConflictStyle::parse(name).or_else(|| {
+            eprintln!("unknown style '{name}' given for 'merge.conflictstyle'");
+            None
+        })
+    }
+}
+
+/// Which side a conflict is resolved in favour of, rather than being
+/// left marked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Favor {
+    Ours,
+    Theirs,
+
+    /// Both, ours first
+    Union,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeOptions<'a> {
+    pub style: ConflictStyle,
+    pub favor: Option<Favor>,
+    pub marker_size: usize,
+
+    /// Conflicts separated only by lines with no letters or digits in
+    /// them, eg. braces, are joined into one, as `merge-file` does
+    pub join_unreadable: bool,
+
+    /// What the conflict markers call each side, eg. a branch name
+    pub ours_label: Option<&'a str>,
+    pub base_label: Option<&'a str>,
+    pub theirs_label: Option<&'a str>,
+}
+
+impl Default for MergeOptions<'_> {
+    fn default() -> Self {
+        MergeOptions {
+            style: ConflictStyle::default(),
+            favor: None,
+            marker_size: DEFAULT_MARKER_SIZE,
+            join_unreadable: false,
+            ours_label: None,
+            base_label: None,
+            theirs_label: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    pub data: Vec<u8>,
+
+    /// How many conflicts were marked up in `data`
+    pub conflicts: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Conflict,
+    Ours,
+    Theirs,
+    Union,
+
+    /// Both sides made the same change
+    Same,
+}
+
+/// A region where one or both sides changed the base, with where it
+/// starts and how many lines it spans in each version
+#[derive(Debug, Clone, Copy)]
+struct Hunk {
+    mode: Mode,
+    base: usize,
+    base_len: usize,
+    ours: usize,
+    ours_len: usize,
+    theirs: usize,
+    theirs_len: usize,
+}
+
+/// Merges the changes `ours` and `theirs` each made to `base`, line by
+/// line, as git's xdiff does.
+///
+/// Changes to the same or neighbouring lines of the base conflict, unless
+/// they're the same change. The merge is ours with their changes made to
+/// it, and each conflict marked up, eg.
+///
+///   <<<<<<< ours
+///   our lines
+///   =======
+///   their lines
+///   >>>>>>> theirs
+pub fn merge(base: &[u8], ours: &[u8], theirs: &[u8], options: &MergeOptions) -> MergeResult {
+    let base_lines = split_lines(base);
+    let ours_lines = split_lines(ours);
+    let theirs_lines = split_lines(theirs);
+
+    let ours_changes = diff_lines(&base_lines, &ours_lines);
+    let theirs_changes = diff_lines(&base_lines, &theirs_lines);
+
+    if ours_changes.is_empty() {
+        return MergeResult { data: theirs.to_vec(), conflicts: 0 };
+    }
+
+    if theirs_changes.is_empty() {
+        return MergeResult { data: ours.to_vec(), conflicts: 0 };
+    }
+
+    let sides = Sides { base: &base_lines, ours: &ours_lines, theirs: &theirs_lines };
+    let mut hunks = sides.hunks(&ours_changes, &theirs_changes);
+
+    match options.style {
+        ConflictStyle::Merge => {
+            hunks = sides.refine_conflicts(hunks);
+            hunks = sides.join_conflicts(hunks, options.join_unreadable);
+        }
+        // The base is shown as a whole, so the sides are too
+        ConflictStyle::Diff3 => {}
+        ConflictStyle::ZealousDiff3 => sides.trim_conflicts(&mut hunks),
+    }
+
+    sides.write(&hunks, options)
+}
+
+/// The lines of each version of the file
+struct Sides<'a, 'b> {
+    base: &'b [&'a [u8]],
+    ours: &'b [&'a [u8]],
+    theirs: &'b [&'a [u8]],
+}
+
+impl Sides<'_, '_> {
+    /// Walks the changes each side made to the base together, turning
+    /// them into hunks: where only one side changed things, and where
+    /// both did, which conflict unless they made the same change.
+    ///
+    /// Where a stretch of the base falls in the other side is found from
+    /// the change at hand, so can start before the file does if that side
+    /// deleted lines just before. That deletion is always the last hunk
+    /// though, which the new one then joins onto.
+    fn hunks(&self, ours: &[Change], theirs: &[Change]) -> Vec<Hunk> {
+        let signed = |change: &Change| {
+            (change.old_start as isize, change.old_len as isize, change.new_start as isize, change.new_len as isize)
+        };
+
+        let (base_len, ours_len, theirs_len) = (self.base.len() as isize, self.ours.len() as isize, self.theirs.len() as isize);
+
+        let mut hunks = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < ours.len() && j < theirs.len() {
+            let (x_base, x_base_len, x_ours, x_ours_len) = signed(&ours[i]);
+            let (y_base, y_base_len, y_theirs, y_theirs_len) = signed(&theirs[j]);
+
+            if x_base + x_base_len < y_base {
+                let theirs_start = y_theirs - y_base + x_base;
+                append(&mut hunks, Mode::Ours, [x_base, x_base_len, x_ours, x_ours_len, theirs_start, x_base_len]);
+                i += 1;
+                continue;
+            }
+
+            if y_base + y_base_len < x_base {
+                let ours_start = x_ours - x_base + y_base;
+                append(&mut hunks, Mode::Theirs, [y_base, y_base_len, ours_start, y_base_len, y_theirs, y_theirs_len]);
+                j += 1;
+                continue;
+            }
+
+            let same = x_base == y_base
+                && x_base_len == y_base_len
+                && self.ours[ours[i].new_start..][..ours[i].new_len] == self.theirs[theirs[j].new_start..][..theirs[j].new_len];
+
+            if !same {
+                // Widen each side to cover the base lines either changed
+                let start = x_base.min(y_base);
+                let end = (x_base + x_base_len).max(y_base + y_base_len);
+
+                let ours_start = x_ours - x_base + start;
+                let ours_end = x_ours + x_ours_len + end - (x_base + x_base_len);
+                let theirs_start = y_theirs - y_base + start;
+                let theirs_end = y_theirs + y_theirs_len + end - (y_base + y_base_len);
+
+                append(&mut hunks, Mode::Conflict, [
+                    start,
+                    end - start,
+                    ours_start,
+                    ours_end - ours_start,
+                    theirs_start,
+                    theirs_end - theirs_start,
+                ]);
+            }
+
+            let (ours_end, theirs_end) = (x_base + x_base_len, y_base + y_base_len);
+
+            if ours_end >= theirs_end {
+                j += 1;
+            }
+
+            if theirs_end >= ours_end {
+                i += 1;
+            }
+        }
+
+        for change in &ours[i..] {
+            let (x_base, x_base_len, x_ours, x_ours_len) = signed(change);
+            let theirs_start = x_base + theirs_len - base_len;
+            append(&mut hunks, Mode::Ours, [x_base, x_base_len, x_ours, x_ours_len, theirs_start, x_base_len]);
+        }
+
+        for change in &theirs[j..] {
+            let (y_base, y_base_len, y_theirs, y_theirs_len) = signed(change);
+            let ours_start = y_base + ours_len - base_len;
+            append(&mut hunks, Mode::Theirs, [y_base, y_base_len, ours_start, y_base_len, y_theirs, y_theirs_len]);
+        }
+
+        hunks
+    }
+
+    /// Narrows each conflict down to the lines where the two sides
+    /// actually differ, splitting it where they agree in the middle
+    fn refine_conflicts(&self, hunks: Vec<Hunk>) -> Vec<Hunk> {
+        let mut refined = Vec::new();
+
+        for hunk in hunks {
+            // Nothing to line up against an empty side
+            if hunk.mode != Mode::Conflict || hunk.ours_len == 0 || hunk.theirs_len == 0 {
+                refined.push(hunk);
+                continue;
+            }
+
+            let changes = diff_lines(&self.ours[hunk.ours..][..hunk.ours_len], &self.theirs[hunk.theirs..][..hunk.theirs_len]);
+
+            if changes.is_empty() {
+                refined.push(Hunk { mode: Mode::Same, ..hunk });
+                continue;
+            }
+
+            refined.extend(changes.iter().map(|change| Hunk {
+                ours: hunk.ours + change.old_start,
+                ours_len: change.old_len,
+                theirs: hunk.theirs + change.new_start,
+                theirs_len: change.new_len,
+                ..hunk
+            }));
+        }
+
+        refined
+    }
+
+    /// Joins conflicts no more than three lines apart, or with nothing
+    /// readable between them if `unreadable` is set, as one conflict
+    /// reads better than several close together
+    fn join_conflicts(&self, hunks: Vec<Hunk>, unreadable: bool) -> Vec<Hunk> {
+        let mut joined: Vec<Hunk> = Vec::new();
+
+        for hunk in hunks {
+            if let Some(last) = joined.last_mut() {
+                let between = &self.ours[last.ours + last.ours_len..hunk.ours];
+
+                let close = between.len() <= 3
+                    || unreadable && !between.iter().any(|line| line.iter().any(u8::is_ascii_alphanumeric));
+
+                if last.mode == Mode::Conflict && hunk.mode == Mode::Conflict && close {
+                    extend(last, &hunk);
+                    continue;
+                }
+            }
+
+            joined.push(hunk);
+        }
+
+        joined
+    }
+
+    /// Moves the lines each conflict's sides begin and end with out of it
+    fn trim_conflicts(&self, hunks: &mut [Hunk]) {
+        for hunk in hunks.iter_mut().filter(|hunk| hunk.mode == Mode::Conflict) {
+            while hunk.ours_len > 0 && hunk.theirs_len > 0 && self.ours[hunk.ours] == self.theirs[hunk.theirs] {
+                hunk.ours += 1;
+                hunk.ours_len -= 1;
+                hunk.theirs += 1;
+                hunk.theirs_len -= 1;
+            }
+
+            while hunk.ours_len > 0
+                && hunk.theirs_len > 0
+                && self.ours[hunk.ours + hunk.ours_len - 1] == self.theirs[hunk.theirs + hunk.theirs_len - 1]
+            {
+                hunk.ours_len -= 1;
+                hunk.theirs_len -= 1;
+            }
+        }
+    }
+
+    /// Writes out ours with the hunks applied to it
+    fn write(&self, hunks: &[Hunk], options: &MergeOptions) -> MergeResult {
+        let mut data = Vec::new();
+        let mut conflicts = 0;
+        let mut next = 0;
+
+        for hunk in hunks {
+            let mode = match (hunk.mode, options.favor) {
+                (Mode::Conflict, Some(Favor::Ours)) => Mode::Ours,
+                (Mode::Conflict, Some(Favor::Theirs)) => Mode::Theirs,
+                (Mode::Conflict, Some(Favor::Union)) => Mode::Union,
+                (mode, _) => mode,
+            };
+
+            // Ours already has the change
+            if mode == Mode::Same {
+                continue;
+            }
+
+            copy(&mut data, &self.ours[next..hunk.ours], false);
+
+            let ours = &self.ours[hunk.ours..][..hunk.ours_len];
+            let theirs = &self.theirs[hunk.theirs..][..hunk.theirs_len];
+
+            match mode {
+                Mode::Ours => copy(&mut data, ours, false),
+                Mode::Theirs => copy(&mut data, theirs, false),
+                Mode::Union => {
+                    copy(&mut data, ours, true);
+                    copy(&mut data, theirs, false);
+                }
+                _ => {
+                    conflicts += 1;
+
+                    marker(&mut data, b'<', options.marker_size, options.ours_label);
+                    copy(&mut data, ours, true);
+
+                    if options.style != ConflictStyle::Merge {
+                        marker(&mut data, b'|', options.marker_size, options.base_label);
+                        copy(&mut data, &self.base[hunk.base..][..hunk.base_len], true);
+                    }
+
+                    marker(&mut data, b'=', options.marker_size, None);
+                    copy(&mut data, theirs, true);
+                    marker(&mut data, b'>', options.marker_size, options.theirs_label);
+                }
+            }
+
+            next = hunk.ours + hunk.ours_len;
+        }
+
+        copy(&mut data, &self.ours[next..], false);
+
+        MergeResult { data, conflicts }
+    }
+}
+
+/// Adds a hunk, given as where it starts and how long it is in the base,
+/// ours and theirs, joining it onto the last if they touch, as a conflict
+/// unless they're from the same side
+fn append(hunks: &mut Vec<Hunk>, mode: Mode, [base, base_len, ours, ours_len, theirs, theirs_len]: [isize; 6]) {
+    let base_end = (base + base_len) as usize;
+    let ours_end = (ours + ours_len) as usize;
+    let theirs_end = (theirs + theirs_len) as usize;
+
+    if let Some(last) = hunks.last_mut() {
+        if ours <= (last.ours + last.ours_len) as isize || theirs <= (last.theirs + last.theirs_len) as isize {
+            if mode != last.mode {
+                last.mode = Mode::Conflict;
+            }
+
+            last.base_len = base_end - last.base;
+            last.ours_len = ours_end - last.ours;
+            last.theirs_len = theirs_end - last.theirs;
+            return;
+        }
+    }
+
+    hunks.push(Hunk {
+        mode,
+        base: base as usize,
+        base_len: base_len as usize,
+        ours: ours as usize,
+        ours_len: ours_len as usize,
+        theirs: theirs as usize,
+        theirs_len: theirs_len as usize,
+    });
+}
+
+/// Stretches `hunk` to the end of `to`, which follows it
+fn extend(hunk: &mut Hunk, to: &Hunk) {
+    hunk.base_len = to.base + to.base_len - hunk.base;
+    hunk.ours_len = to.ours + to.ours_len - hunk.ours;
+    hunk.theirs_len = to.theirs + to.theirs_len - hunk.theirs;
+}
+
+/// Appends `lines`, ending the last with a newline if `terminate` is set
+/// and it lacks one, so what follows starts on a line of its own
+fn copy(data: &mut Vec<u8>, lines: &[&[u8]], terminate: bool) {
+    for line in lines {
+        data.extend_from_slice(line);
+    }
+
+    if terminate && lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+        data.push(b'\n');
+    }
+}
+
+fn marker(data: &mut Vec<u8>, kind: u8, size: usize, label: Option<&str>) {
+    data.extend(std::iter::repeat_n(kind, size));
+
+    if let Some(label) = label {
+        data.push(b' ');
+        data.extend_from_slice(label.as_bytes());
+    }
+
+    data.push(b'\n');
+}
+
+/// How two trees are merged
+#[derive(Debug, Clone)]
+pub struct TreeMergeOptions<'a> {
+    /// What our side is called, eg. "HEAD"
+    pub ours_label: &'a str,
+
+    /// What their side is called, eg. the branch being merged
+    pub theirs_label: &'a str,
+
+    /// What the base is called in diff3 conflicts
+    pub base_label: &'a str,
+
+    pub style: ConflictStyle,
+}
+
+/// A path the two sides changed in ways that couldn't be merged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub path: Vec<u8>,
+
+    /// The path as the base, ours and theirs have it, as stages 1 to 3 of
+    /// the index record it. None where it's missing.
+    pub stages: Stages,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TreeMerge {
+    /// The merged tree, with each conflicted path as it's left in the
+    /// worktree: marked up, or as the side which still has it
+    pub files: TreeFiles,
+
+    pub conflicts: Vec<Conflict>,
+
+    /// What was done about each path both sides changed, in path order,
+    /// eg. "Auto-merging src/main.rs"
+    pub messages: Vec<String>,
+}
+
+/// Merges the commits `ours` and `theirs`, against their merge base.
+///
+/// Where there are several merge bases, as after criss-cross merges, they
+/// are merged together first, oldest first, into a virtual base, as git's
+/// recursive strategies do. Renames aren't detected, a renamed path is
+/// a deletion and an addition.
+pub fn merge_commits(graph: &mut Graph, ours: ObjectId, theirs: ObjectId, options: &TreeMergeOptions) -> Option<TreeMerge> {
+    let bases = graph.merge_bases(ours, theirs);
+
+    let base_label = match bases.as_slice() {
+        [] => "empty tree".to_string(),
+        [base] => base.to_string()[..7].to_string(),
+        _ => "merged common ancestors".to_string(),
+    };
+
+    let base = merge_bases(graph, &[ours], theirs, options.style)?;
+
+    let options = TreeMergeOptions { base_label: &base_label, ..options.clone() };
+
+    merge_tree_files(&base, &commit_files(ours)?, &commit_files(theirs)?, &options, false)
+}
+
+/// Merges the changes from `base` to `theirs` into `ours`, path by path:
+/// a path changed on one side only takes that side's version, and files
+/// both sides changed have their contents merged.
+pub fn merge_trees(base: &TreeFiles, ours: &TreeFiles, theirs: &TreeFiles, options: &TreeMergeOptions) -> Option<TreeMerge> {
+    merge_tree_files(base, ours, theirs, options, false)
+}
+
+/// The tree to merge `theirs` and a commit descended from all of `ours`
+/// against: the empty tree if they have no merge base, the merge base if
+/// there's one, otherwise all of them merged together
+fn merge_bases(graph: &mut Graph, ours: &[ObjectId], theirs: ObjectId, style: ConflictStyle) -> Option<TreeFiles> {
+    let mut bases = graph.merge_bases_many(theirs, ours);
+    bases.reverse();
+
+    let Some((&first, rest)) = bases.split_first() else {
+        return Some(TreeFiles::new());
+    };
+
+    let mut merged = commit_files(first)?;
+    let mut merged_from = vec![first];
+
+    for &base in rest {
+        let options = TreeMergeOptions {
+            ours_label: "Temporary merge branch 1",
+            theirs_label: "Temporary merge branch 2",
+            base_label: "merged common ancestors",
+            style,
+        };
+
+        let inner_base = merge_bases(graph, &merged_from, base, style)?;
+
+        merged = merge_tree_files(&inner_base, &merged, &commit_files(base)?, &options, true)?.files;
+        merged_from.push(base);
+    }
+
+    Some(merged)
+}
+
+/// Merges the trees. A `virtual_base` is only ever merged against, so
+/// conflicts are settled however they can be rather than recorded: with
+/// markers in the contents, or the base's version.
+fn merge_tree_files(
+    base: &TreeFiles,
+    ours: &TreeFiles,
+    theirs: &TreeFiles,
+    options: &TreeMergeOptions,
+    virtual_base: bool,
+) -> Option<TreeMerge> {
+    let paths: BTreeSet<&Vec<u8>> = base.keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+
+    let mut merge = TreeMerge::default();
+
+    for path in paths {
+        let (base, ours, theirs) = (base.get(path).copied(), ours.get(path).copied(), theirs.get(path).copied());
+
+        let result = if ours == theirs || base == theirs {
+            ours
+        } else if base == ours {
+            theirs
+        } else {
+            merge_path(path, [base, ours, theirs], options, virtual_base, &mut merge)?
+        };
+
+        if let Some(entry) = result {
+            merge.files.insert(path.clone(), entry);
+        }
+    }
+
+    // A file where the other side has a directory has nowhere to go
+    let mut files = merge.files.keys().peekable();
+
+    while let Some(path) = files.next() {
+        if files.peek().is_some_and(|next| next.strip_prefix(path.as_slice()).is_some_and(|rest| rest.starts_with(b"/"))) {
+            eprintln!(
+                "CONFLICT (file/directory): merging a file with a directory at '{}' isn't supported yet",
+                String::from_utf8_lossy(path),
+            );
+            return None;
+        }
+    }
+
+    Some(merge)
+}
+
+/// Merges a path both sides changed differently, giving what's left there
+fn merge_path(
+    path: &[u8],
+    [base, ours, theirs]: [Option<(u32, ObjectId)>; 3],
+    options: &TreeMergeOptions,
+    virtual_base: bool,
+    merge: &mut TreeMerge,
+) -> Option<Option<(u32, ObjectId)>> {
+    let (ours_label, theirs_label) = (options.ours_label, options.theirs_label);
+    let name = String::from_utf8_lossy(path);
+
+    let conflict = |merge: &mut TreeMerge, message: String| {
+        merge.messages.push(message);
+        merge.conflicts.push(Conflict { path: path.to_vec(), stages: [base, ours, theirs] });
+    };
+
+    let (ours, theirs) = match (ours, theirs) {
+        (Some(ours), Some(theirs)) => (ours, theirs),
+        (modified, _) => {
+            if virtual_base {
+                return Some(base);
+            }
+
+            let (deleted_in, modified_in) = match modified {
+                Some(_) => (theirs_label, ours_label),
+                None => (ours_label, theirs_label),
+            };
+
+            conflict(merge, format!(
+                "CONFLICT (modify/delete): {name} deleted in {deleted_in} and modified in {modified_in}.  \
+                 Version {modified_in} of {name} left in tree."
+            ));
+
+            return Some(modified.or(theirs));
+        }
+    };
+
+    let is_file = |mode: u32| mode == MODE_BLOB || mode == MODE_EXECUTABLE;
+
+    if !is_file(ours.0) || !is_file(theirs.0) {
+        let kind = match (ours.0, theirs.0) {
+            (a, b) if a != b && !(is_file(a) && is_file(b)) => "distinct types",
+            (MODE_GITLINK, _) => "submodule",
+            _ => "content",
+        };
+
+        if !virtual_base {
+            conflict(merge, format!("CONFLICT ({kind}): Merge conflict in {name}"));
+        }
+
+        return Some(Some(ours));
+    }
+
+    let base_mode = base.map(|(mode, _)| mode);
+
+    let (mode, mode_clean) = match (ours.0, theirs.0) {
+        (a, b) if a == b => (a, true),
+        (a, b) if base_mode == Some(a) => (b, true),
+        (a, b) if base_mode == Some(b) => (a, true),
+        (a, _) => (a, false),
+    };
+
+    let base_id = base.filter(|&(mode, _)| is_file(mode)).map(|(_, id)| id);
+
+    let (id, content_clean) = if ours.1 == theirs.1 {
+        (ours.1, true)
+    } else if base_id == Some(ours.1) {
+        (theirs.1, true)
+    } else if base_id == Some(theirs.1) {
+        (ours.1, true)
+    } else {
+        merge.messages.push(format!("Auto-merging {name}"));
+        merge_blobs(&name, base_id, ours.1, theirs.1, options, virtual_base)?
+    };
+
+    if !virtual_base && !content_clean {
+        let kind = if base.is_some() { "content" } else { "add/add" };
+        conflict(merge, format!("CONFLICT ({kind}): Merge conflict in {name}"));
+    } else if !virtual_base && !mode_clean {
+        conflict(merge, format!("CONFLICT (content): Merge conflict in {name}"));
+    }
+
+    Some(Some((mode, id)))
+}
+
+/// Merges the contents of a file, giving the blob the merge is written to
+/// and whether it was clean
+fn merge_blobs(
+    path: &str,
+    base: Option<ObjectId>,
+    ours: ObjectId,
+    theirs: ObjectId,
+    options: &TreeMergeOptions,
+    virtual_base: bool,
+) -> Option<(ObjectId, bool)> {
+    let base_data = match base {
+        Some(base) => read_blob(base)?,
+        None => Vec::new(),
+    };
+
+    let (ours_data, theirs_data) = (read_blob(ours)?, read_blob(theirs)?);
+
+    if is_binary(&base_data) || is_binary(&ours_data) || is_binary(&theirs_data) {
+        if virtual_base {
+            return Some((base.unwrap_or(ours), true));
+        }
+
+        eprintln!("warning: Cannot merge binary files: {path} ({} vs. {})", options.ours_label, options.theirs_label);
+        return Some((ours, false));
+    }
+
+    // Markers in a virtual base are longer, so they can't be taken for
+    // those of the merge it's the base of
+    let merge_options = MergeOptions {
+        style: options.style,
+        marker_size: DEFAULT_MARKER_SIZE + if virtual_base { 2 } else { 0 },
+        ours_label: Some(options.ours_label),
+        base_label: Some(options.base_label),
+        theirs_label: Some(options.theirs_label),
+        ..MergeOptions::default()
+    };
+
+    let result = merge(&base_data, &ours_data, &theirs_data, &merge_options);
+    let id = GitObjectStore::write("blob", &result.data)?;
+
+    Some((id, result.conflicts == 0))
+}
+
+/// Replaces each conflicted path in `index` with its stages
+pub fn record_conflicts(index: &mut Index, conflicts: &[Conflict]) {
+    for conflict in conflicts {
+        index.add_conflict(&conflict.path, conflict.stages);
+    }
+}
+
+fn commit_files(commit: ObjectId) -> Option<TreeFiles> {
+    flatten_tree(peel_to_tree(commit)?)
+}
+
+fn read_blob(id: ObjectId) -> Option<Vec<u8>> {
+    match GitObjectStore::get(id)?.data {
+        GitObjectData::Blob { data } => Some(data),
+        _ => None,
+    }
+}
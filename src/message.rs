@@ -0,0 +1,351 @@
+use crate::config::Config;
+use crate::store::object::Identity;
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+
+// What "core.commentChar = auto" picks from, in order of preference
+const AUTO_COMMENT_CHARS: &[u8] = b"#;@!$%^&|:";
+
+/// The prefix marking comment lines in messages, `core.commentChar`
+/// or '#'. "auto" gives '#', as it's only resolved when there's a
+/// message to pick a character for.
+pub fn comment_char(config: &Config) -> String {
+    match config.get("core.commentChar") {
+        Some(value) if !value.is_empty() && value != "auto" => value.to_string(),
+        _ => "#".to_string(),
+    }
+}
+
+/// Like `comment_char`, but with "auto" resolved against `message`: the
+/// first of "#;@!$%^&|:" which doesn't start any of its lines. None if
+/// every one of them does.
+pub fn comment_char_for(config: &Config, message: &[u8]) -> Option<String> {
+    if config.get("core.commentChar") != Some("auto") {
+        return Some(comment_char(config));
+    }
+
+    let line_starts: Vec<u8> = message.first()
+        .into_iter()
+        .chain(message.windows(2).filter(|pair| pair[0] == b'\n' || pair[0] == b'\r').map(|pair| &pair[1]))
+        .copied()
+        .collect();
+
+    AUTO_COMMENT_CHARS.iter()
+        .find(|candidate| !line_starts.contains(candidate))
+        .map(|&candidate| (candidate as char).to_string())
+}
+
+/// Cleans up a commit or tag message the way git does: trailing
+/// whitespace is stripped from every line, runs of blank lines are
+/// collapsed into one, and blank lines at the start and end are removed.
+/// With a `comment` prefix, lines starting with it are dropped as well.
+///
+/// The result is empty or ends with a newline.
+pub fn stripspace(message: &[u8], comment: Option<&str>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(message.len());
+    let mut blank_lines = 0;
+
+    for line in message.split_inclusive(|&byte| byte == b'\n') {
+        if comment.is_some_and(|comment| line.starts_with(comment.as_bytes())) {
+            continue;
+        }
+
+        let end = line.iter()
+            .rposition(|byte| !byte.is_ascii_whitespace())
+            .map_or(0, |pos| pos + 1);
+
+        if end == 0 {
+            blank_lines += 1;
+            continue;
+        }
+
+        // Blank lines are only kept between non-blank ones
+        if blank_lines > 0 && !out.is_empty() {
+            out.push(b'\n');
+        }
+
+        blank_lines = 0;
+
+        out.extend_from_slice(&line[..end]);
+        out.push(b'\n');
+    }
+
+    out
+}
+
+/// Prefixes every line of `message` with `comment`, followed by a space
+/// unless the line is empty or starts with a tab, as
+/// `git stripspace --comment-lines` does
+pub fn comment_lines(message: &[u8], comment: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(message.len());
+
+    for line in message.split_inclusive(|&byte| byte == b'\n') {
+        out.extend_from_slice(comment.as_bytes());
+
+        if !line.starts_with(b"\n") && !line.starts_with(b"\t") {
+            out.push(b' ');
+        }
+
+        out.extend_from_slice(line);
+    }
+
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+
+    out
+}
+
+/// The lines of the message's first paragraph, with surrounding whitespace
+/// trimmed, and where the paragraph ends
+fn subject_lines(message: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    let mut started = false;
+
+    for line in message.split_inclusive(|&byte| byte == b'\n') {
+        let trimmed = &line[..line.iter().rposition(|byte| !byte.is_ascii_whitespace()).map_or(0, |pos| pos + 1)];
+
+        if trimmed.is_empty() {
+            if started {
+                break;
+            }
+        } else {
+            started = true;
+            lines.push(trimmed);
+        }
+
+        offset += line.len();
+    }
+
+    (lines, offset)
+}
+
+/// The message's first paragraph, joined into one line
+pub fn subject(message: &[u8]) -> String {
+    let (lines, _) = subject_lines(message);
+
+    lines.iter()
+        .map(|line| String::from_utf8_lossy(line))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The subject of the commit `id`, empty if it can't be read
+pub fn commit_subject(id: ObjectId) -> String {
+    match GitObjectStore::get(id).map(|object| object.data) {
+        Some(GitObjectData::Commit { message, .. }) => subject(&message),
+        _ => String::new(),
+    }
+}
+
+/// Everything after the message's first paragraph and the blank lines
+/// following it
+pub fn body(message: &[u8]) -> &[u8] {
+    let (_, offset) = subject_lines(message);
+    let rest = &message[offset..];
+
+    let start = rest.split_inclusive(|&byte| byte == b'\n')
+        .take_while(|line| line.iter().all(u8::is_ascii_whitespace))
+        .map(|line| line.len())
+        .sum();
+
+    &rest[start..]
+}
+
+/// The message's trailers, eg. "Signed-off-by: A U Thor <author@example.com>",
+/// as their keys and values, in order. They're the lines of the last
+/// paragraph after the subject, if all of them are trailers or lines
+/// continuing one, or if one is git's own "Signed-off-by: " or "(cherry
+/// picked from commit" and at least a quarter of them are, as git reads
+/// them. A continued value is joined into one line.
+pub fn trailers(message: &[u8]) -> Vec<(String, String)> {
+    let body = String::from_utf8_lossy(body(message));
+
+    // A patch's message ends at its "---" line
+    let body = body.lines()
+        .take_while(|line| !(line.starts_with("---") && line[3..].trim().is_empty()))
+        .collect::<Vec<_>>();
+
+    let end = body.iter().rposition(|line| !line.trim().is_empty()).map_or(0, |pos| pos + 1);
+    let start = body[..end].iter().rposition(|line| line.trim().is_empty()).map_or(0, |pos| pos + 1);
+    let block = &body[start..end];
+
+    let mut trailers: Vec<(String, String)> = Vec::new();
+    let mut others = 0;
+    let mut recognized = false;
+    let mut continuing = false;
+
+    for line in block {
+        if continuing && line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = trailers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+
+            continue;
+        }
+
+        recognized |= line.starts_with("Signed-off-by: ") || line.starts_with("(cherry picked from commit ");
+
+        let trailer = line.split_once(':')
+            .map(|(key, value)| (key.trim_end(), value.trim()))
+            .filter(|(key, _)| !key.is_empty() && key.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-'));
+
+        continuing = trailer.is_some();
+
+        match trailer {
+            Some((key, value)) => trailers.push((key.to_string(), value.to_string())),
+            None => others += 1,
+        }
+    }
+
+    match !trailers.is_empty() && (others == 0 || (recognized && trailers.len() * 3 >= others)) {
+        true => trailers,
+        false => Vec::new(),
+    }
+}
+
+/// The people credited with a "Co-authored-by: " trailer, as squash merges
+/// and pairing credit them, in order. Values that aren't a name and email
+/// are left out.
+pub fn co_authors(message: &[u8]) -> Vec<Identity> {
+    trailers(message).into_iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("Co-authored-by"))
+        .filter_map(|(_, value)| Identity::parse(&value))
+        .collect()
+}
+
+/// The subject as a file name, eg. "Fix the frobnicator." becomes
+/// "Fix-the-frobnicator": runs of anything but letters, digits, '.' and
+/// '_' become one '-', runs of '.' one '.', and trailing '.' and '-' are
+/// dropped
+pub fn sanitized_subject(message: &[u8]) -> String {
+    let (lines, _) = subject_lines(message);
+    let line = lines.first().copied().unwrap_or_default();
+
+    let mut out = String::new();
+    let mut separated = false;
+    let mut previous = 0u8;
+
+    for &byte in line {
+        let title_char = byte.is_ascii_alphanumeric() || byte == b'.' || byte == b'_';
+
+        if !title_char {
+            separated = true;
+            continue;
+        }
+
+        if byte == b'.' && previous == b'.' && !separated {
+            continue;
+        }
+
+        if separated && !out.is_empty() {
+            out.push('-');
+        }
+
+        separated = false;
+        previous = byte;
+        out.push(byte as char);
+    }
+
+    out.trim_end_matches(['.', '-']).to_string()
+}
+
+/// Appends `text` to `out` wrapped at `width` columns, as git wraps
+/// `shortlog -w` and "%w(...)": the first line is indented by `indent1`
+/// spaces and the rest by `indent2`. A line break followed by a letter or
+/// digit joins the lines, any other ends a paragraph. Words longer than
+/// the width are left whole. A width of 0 only indents each line.
+pub fn wrap_text(out: &mut Vec<u8>, text: &[u8], width: usize, indent1: usize, indent2: usize) {
+    if width == 0 {
+        let mut indent = indent1;
+
+        for line in text.split_inclusive(|&byte| byte == b'\n') {
+            out.extend(std::iter::repeat_n(b' ', indent));
+            out.extend_from_slice(line);
+            indent = indent2;
+        }
+
+        return;
+    }
+
+    let is_space = |byte: u8| matches!(byte, b' ' | b'\t' | b'\n' | b'\r');
+
+    let mut pos = 0;
+    let mut line_start = 0;
+    let mut column = indent1;
+    let mut indent = indent1;
+
+    // Where the whitespace before the word being measured is, None at the
+    // start of a line
+    let mut space: Option<usize> = None;
+
+    loop {
+        let byte = text.get(pos).copied();
+
+        if byte.is_some_and(|byte| !is_space(byte)) {
+            // UTF-8 continuation bytes don't take up a column
+            if byte.is_some_and(|byte| byte & 0xc0 != 0x80) {
+                column += 1;
+            }
+
+            pos += 1;
+            continue;
+        }
+
+        let mut new_line = column > width && space.is_some();
+
+        if !new_line {
+            let start = match space {
+                Some(space) => space,
+                None => {
+                    if byte.is_none() && pos == line_start {
+                        return;
+                    }
+
+                    out.extend(std::iter::repeat_n(b' ', indent));
+                    line_start
+                }
+            };
+
+            out.extend_from_slice(&text[start..pos]);
+
+            let Some(byte) = byte else {
+                return;
+            };
+
+            space = Some(pos);
+
+            match byte {
+                b'\t' => column |= 7,
+                b'\n' => {
+                    space = Some(pos + 1);
+
+                    match text.get(pos + 1) {
+                        Some(b'\n') => {
+                            out.push(b'\n');
+                            new_line = true;
+                        }
+                        Some(next) if next.is_ascii_alphanumeric() => out.push(b' '),
+                        _ => new_line = true,
+                    }
+                }
+                _ => {}
+            }
+
+            if !new_line {
+                column += 1;
+                pos += 1;
+                continue;
+            }
+        }
+
+        out.push(b'\n');
+
+        let space = space.take().unwrap_or(pos);
+        line_start = space + text.get(space).is_some_and(|&byte| is_space(byte)) as usize;
+        pos = line_start;
+        column = indent2;
+        indent = indent2;
+    }
+}
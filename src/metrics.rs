@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use crate::config::Config;
+use crate::lockfile::LockFile;
+
+/// The counters kept, with the help text each is exported with
+const COUNTERS: &[(&str, &str)] = &[
+    ("gitty_advertisements_total", "Ref advertisements served"),
+    ("gitty_advertised_refs_total", "Refs listed in ref advertisements, peeled tags included"),
+    ("gitty_advertisement_bytes_total", "Bytes of ref advertisements sent"),
+    ("gitty_objects_served_total", "Objects in the packs sent by upload-pack"),
+    ("gitty_pack_bytes_sent_total", "Bytes of the packs sent by upload-pack"),
+    ("gitty_negotiation_rounds_total", "Rounds of haves upload-pack negotiated over"),
+    ("gitty_received_packs_total", "Packs received by receive-pack"),
+    ("gitty_received_objects_total", "Objects in the packs received by receive-pack"),
+    ("gitty_received_pack_bytes_total", "Bytes of the packs received by receive-pack"),
+    ("gitty_ancestry_cache_hits_total", "Ancestry checks carrying on from an earlier walk"),
+    ("gitty_ancestry_cache_misses_total", "Ancestry checks walking from scratch"),
+    ("gitty_packed_refs_cache_hits_total", "Reads of packed-refs answered from the cache"),
+    ("gitty_packed_refs_cache_misses_total", "Reads of packed-refs which had to parse the file"),
+    ("gitty_peel_cache_hits_total", "Objects peeled from the cache"),
+    ("gitty_peel_cache_misses_total", "Objects peeled by reading them"),
+];
+
+// What this process has counted, by series, eg.
+// `gitty_advertisements_total{service="upload-pack"}`
+static COUNTED: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+/// Adds `value` to the counter `name` with `labels`
+pub fn count(name: &'static str, labels: &[(&str, &str)], value: u64) {
+    let series = match labels.is_empty() {
+        true => name.to_string(),
+        false => format!("{name}{{{}}}", labels.iter()
+            .map(|(label, value)| format!("{label}=\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect::<Vec<_>>()
+            .join(",")),
+    };
+
+    *COUNTED.lock().unwrap_or_else(|err| err.into_inner()).entry(series).or_default() += value;
+}
+
+/// Adds what this process has counted to the file `gitty.metricsFile`
+/// names, if it's set, in Prometheus' text format. Servers run a process
+/// per request, so the file keeps the totals across them, for node
+/// exporter's textfile collector or the like to pick up.
+pub fn export(config: &Config) -> io::Result<()> {
+    let Some(path) = config.get("gitty.metricsfile") else {
+        return Ok(());
+    };
+
+    let counted = std::mem::take(&mut *COUNTED.lock().unwrap_or_else(|err| err.into_inner()));
+
+    if counted.is_empty() {
+        return Ok(());
+    }
+
+    let mut lock = LockFile::acquire(path)?;
+
+    let mut totals = match fs::read_to_string(path) {
+        Ok(contents) => parse(&contents),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(err) => return Err(err),
+    };
+
+    for (series, value) in counted {
+        *totals.entry(series).or_default() += value;
+    }
+
+    for (name, help) in COUNTERS {
+        let mut family = totals.iter()
+            .filter(|(series, _)| series.strip_prefix(name).is_some_and(|rest| rest.is_empty() || rest.starts_with('{')))
+            .peekable();
+
+        if family.peek().is_none() {
+            continue;
+        }
+
+        writeln!(lock, "# HELP {name} {help}")?;
+        writeln!(lock, "# TYPE {name} counter")?;
+
+        for (series, value) in family {
+            writeln!(lock, "{series} {value}")?;
+        }
+    }
+
+    lock.commit()
+}
+
+/// Reads the series of an exported file, skipping comments and anything
+/// that isn't a counter of ours
+fn parse(contents: &str) -> BTreeMap<String, u64> {
+    contents.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.rsplit_once(' '))
+        .filter(|(series, _)| COUNTERS.iter().any(|(name, _)| series.split('{').next() == Some(name)))
+        .filter_map(|(series, value)| Some((series.to_string(), value.parse().ok()?)))
+        .collect()
+}
@@ -0,0 +1,55 @@
+use crate::wildmatch::{is_glob, wildmatch, WildmatchFlags};
+use crate::worktree::{in_pathspec, normalize_path};
+
+/// Paths given to limit a command to part of the tree, eg. `log -- src`.
+///
+/// Each item matches the file it names, everything inside the directory it
+/// names, or with glob syntax whatever it matches as a whole, so "*.rs"
+/// matches "src/main.rs". No items at all match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pathspec {
+    items: Vec<String>,
+}
+
+impl Pathspec {
+    /// Parses pathspecs relative to the repository root. None if one is
+    /// outside the repository.
+    pub fn parse(paths: &[String]) -> Option<Pathspec> {
+        let items = paths.iter()
+            .map(|path| normalize_path(path).or_else(|| {
+                eprintln!("'{path}' is outside repository");
+                None
+            }))
+            .collect::<Option<Vec<String>>>()?;
+
+        Some(Pathspec { items })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Does the pathspec match the file at `path`?
+    pub fn matches<P: AsRef<[u8]>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+
+        self.is_empty() || self.items.iter().any(|item| {
+            in_pathspec(path, item) || is_glob(item) && wildmatch(item, path, WildmatchFlags::default())
+        })
+    }
+
+    /// Could the pathspec match anything inside the directory `dir`? Lets
+    /// tree walks skip the directories which can't.
+    pub fn matches_dir(&self, dir: &str) -> bool {
+        let dir = format!("{dir}/");
+
+        self.is_empty() || self.items.iter().any(|item| {
+            match item.find(['*', '?', '[', '\\']) {
+                // Only the part before any glob syntax has to match
+                // literally, and it may end part way through a name
+                Some(pos) => item[..pos].starts_with(&dir) || dir.starts_with(&item[..pos]),
+                None => item.is_empty() || item.starts_with(&dir) || dir.starts_with(&format!("{item}/")),
+            }
+        })
+    }
+}
@@ -0,0 +1,746 @@
+use std::collections::HashMap;
+
+use crate::date::{self, DateFormat, DateMode};
+use crate::mailmap::Mailmap;
+use crate::message::{body, sanitized_subject, subject, wrap_text};
+use crate::reflog::ReflogEntry;
+use crate::refs::{self, RefValue};
+use crate::store::{object::Identity, GitObjectData, GitObjectStore, ObjectId};
+
+/// How commits are shown, as given to `--pretty` or `--format`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PrettyFormat {
+    /// "<id> <subject>"
+    Oneline,
+
+    /// The commit line, author and subject
+    Short,
+
+    /// The commit line, author, author date and message
+    #[default]
+    Medium,
+
+    /// The commit line, author, committer and message
+    Full,
+
+    /// The commit line, author and committer with their dates, and message
+    Fuller,
+
+    /// The commit line, the commit's headers as stored, and message
+    Raw,
+
+    /// A format string with placeholders, eg. "%h %s", printed between
+    /// commits ("format:")
+    Separated(String),
+
+    /// A format string printed after each commit ("tformat:")
+    Terminated(String),
+}
+
+impl PrettyFormat {
+    /// Parses a `--pretty` value: a format name, "format:<string>",
+    /// "tformat:<string>" or a format string on its own, which is taken as
+    /// a "tformat:"
+    pub fn parse(spec: &str) -> Option<PrettyFormat> {
+        let format = match spec {
+            "oneline" => PrettyFormat::Oneline,
+            "short" => PrettyFormat::Short,
+            "medium" => PrettyFormat::Medium,
+            "full" => PrettyFormat::Full,
+            "fuller" => PrettyFormat::Fuller,
+            "raw" => PrettyFormat::Raw,
+            _ => {
+                if let Some(format) = spec.strip_prefix("format:") {
+                    PrettyFormat::Separated(format.to_string())
+                } else if let Some(format) = spec.strip_prefix("tformat:") {
+                    PrettyFormat::Terminated(format.to_string())
+                } else if spec.contains('%') {
+                    PrettyFormat::Terminated(spec.to_string())
+                } else {
+                    return None;
+                }
+            }
+        };
+
+        Some(format)
+    }
+
+    /// Whether each commit is followed by a newline, rather than commits
+    /// being separated by one. The multi-line formats end with a newline
+    /// of their own, so separating them leaves a blank line between them.
+    pub fn is_terminated(&self) -> bool {
+        matches!(self, PrettyFormat::Oneline | PrettyFormat::Terminated(_))
+    }
+
+    /// Whether this is a format string rather than a named format
+    pub fn is_user_format(&self) -> bool {
+        matches!(self, PrettyFormat::Separated(_) | PrettyFormat::Terminated(_))
+    }
+
+    /// Whether showing a commit needs its decorations, ie. "%d" or "%D"
+    pub fn uses_decorations(&self) -> bool {
+        match self {
+            PrettyFormat::Separated(format) | PrettyFormat::Terminated(format) => {
+                format.contains("%d") || format.contains("%D")
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Names of the refs pointing at each commit (or tag), as `--decorate`
+/// shows them: "HEAD", branches by their short name, "tag: <name>" for tags.
+#[derive(Debug, Default, Clone)]
+pub struct Decorations {
+    names: HashMap<ObjectId, Vec<String>>,
+
+    /// The branch HEAD is on, if any, shown as "HEAD -> <branch>"
+    head_branch: Option<String>,
+}
+
+impl Decorations {
+    /// Reads every ref. Tags decorate both the tag object and what it
+    /// peels to.
+    pub fn load() -> Decorations {
+        let mut decorations = Decorations::default();
+
+        for (name, id) in refs::list_refs("refs/") {
+            let label = match name.strip_prefix("refs/tags/") {
+                Some(tag) => format!("tag: {tag}"),
+                None => refs::shorten_ref(&name).to_string(),
+            };
+
+            decorations.add(id, label.clone());
+
+            let mut object = id;
+
+            while let Some(GitObjectData::Tag { object: target, .. }) = GitObjectStore::get(object).map(|object| object.data) {
+                decorations.add(target, label.clone());
+                object = target;
+            }
+        }
+
+        if let Some(head) = refs::resolve_ref("HEAD") {
+            decorations.add(head, "HEAD".to_string());
+        }
+
+        if let Some(RefValue::Symbolic(target)) = refs::read_ref("HEAD") {
+            decorations.head_branch = Some(refs::shorten_ref(&target).to_string());
+        }
+
+        decorations
+    }
+
+    fn add(&mut self, id: ObjectId, name: String) {
+        self.names.entry(id).or_default().push(name);
+    }
+
+    /// The decorations of `id`, eg. "HEAD -> main, tag: v1.0, origin/main".
+    /// HEAD comes first, then the refs in reverse name order, as git has
+    /// them.
+    pub fn get(&self, id: ObjectId) -> Option<String> {
+        self.names.contains_key(&id).then(|| self.list(id).join(", "))
+    }
+
+    /// The decorations of `id` one by one, as `get` joins them
+    pub fn list(&self, id: ObjectId) -> Vec<String> {
+        let Some(names) = self.names.get(&id) else {
+            return Vec::new();
+        };
+
+        let on_head_branch = |name: &String| {
+            names.contains(&"HEAD".to_string()) && self.head_branch.as_ref() == Some(name)
+        };
+
+        let mut shown = Vec::new();
+
+        for name in names.iter().rev() {
+            if name == "HEAD" {
+                match names.iter().find(|name| on_head_branch(name)) {
+                    Some(branch) => shown.push(format!("HEAD -> {branch}")),
+                    None => shown.push(name.clone()),
+                }
+            } else if !on_head_branch(name) {
+                shown.push(name.clone());
+            }
+        }
+
+        shown
+    }
+}
+
+/// Settings for showing commits which don't depend on the format
+#[derive(Debug, Clone, Default)]
+pub struct PrettyOptions<'a> {
+    pub date_mode: DateMode,
+
+    /// Abbreviate the commit's id in named formats, `--abbrev-commit`
+    pub abbrev_commit: bool,
+
+    /// Decorations to show after the commit's id in named formats, and for
+    /// "%d"
+    pub decorations: Option<&'a Decorations>,
+
+    /// The starting point the commit was reached from, shown after its
+    /// id with `--source`
+    pub source: Option<&'a str>,
+
+    /// Whether "%C" colors are used, other than "%C(always,...)"
+    pub color: bool,
+
+    /// What "%aN", "%aE" and the like map identities with
+    pub mailmap: Option<&'a Mailmap>,
+
+    /// Show the Author and Commit lines of the named formats as the mailmap
+    /// maps them
+    pub use_mailmap: bool,
+
+    /// The reflog entry the commit is shown for, when walking reflogs
+    pub reflog: Option<&'a ReflogInfo>,
+}
+
+/// A reflog entry, as `log -g` shows it with the commit it set the ref to
+#[derive(Debug, Clone)]
+pub struct ReflogInfo {
+    /// Which entry, eg. "HEAD@{2}" or "main@{<date>}"
+    pub selector: String,
+    pub entry: ReflogEntry,
+}
+
+/// The parts of a commit a format can show
+struct CommitFields<'a> {
+    id: ObjectId,
+    tree: ObjectId,
+    parents: &'a [ObjectId],
+    author: Identity,
+    committer: Identity,
+    mailmapped_author: Identity,
+    mailmapped_committer: Identity,
+    raw_author: &'a str,
+    raw_committer: &'a str,
+    encoding: Option<&'a str>,
+    message: &'a [u8],
+}
+
+/// Renders `commit` (whose id is `id`) in `format`, without anything
+/// separating it from other commits: a named format gives whole lines, a
+/// format string exactly what it expands to.
+pub fn format_commit(format: &PrettyFormat, id: ObjectId, commit: &GitObjectData, options: &PrettyOptions) -> Vec<u8> {
+    let GitObjectData::Commit { tree, parents, author, committer, encoding, message, .. } = commit else {
+        return Vec::new();
+    };
+
+    let mailmapped_author = options.mailmap.map(|mailmap| mailmap.map_ident(author));
+    let mailmapped_committer = options.mailmap.map(|mailmap| mailmap.map_ident(committer));
+
+    let mailmapped_author = mailmapped_author.as_deref().unwrap_or(author);
+    let mailmapped_committer = mailmapped_committer.as_deref().unwrap_or(committer);
+
+    let fields = CommitFields {
+        id,
+        tree: *tree,
+        parents,
+        author: Identity::parse(author).unwrap_or_else(unknown_identity),
+        committer: Identity::parse(committer).unwrap_or_else(unknown_identity),
+        mailmapped_author: Identity::parse(mailmapped_author).unwrap_or_else(unknown_identity),
+        mailmapped_committer: Identity::parse(mailmapped_committer).unwrap_or_else(unknown_identity),
+        raw_author: author,
+        raw_committer: committer,
+        encoding: encoding.as_deref(),
+        message,
+    };
+
+    match format {
+        PrettyFormat::Separated(format) | PrettyFormat::Terminated(format) => expand(format, &fields, options),
+        PrettyFormat::Oneline => {
+            let mut out = commit_line(&fields, options).into_bytes();
+            out.push(b' ');
+
+            // The entry's message stands in for the subject
+            match options.reflog {
+                Some(reflog) => out.extend_from_slice(format!("{}: {}", reflog.selector, reflog.entry.message).as_bytes()),
+                None => out.extend_from_slice(subject(message).as_bytes()),
+            }
+
+            out
+        }
+        format => named_format(format, &fields, options),
+    }
+}
+
+/// Replaces each "$Format:<format>$" in `data` with `commit` shown in
+/// that format, as `git archive` does to files marked `export-subst`
+pub fn format_subst(data: &[u8], id: ObjectId, commit: &GitObjectData, options: &PrettyOptions) -> Vec<u8> {
+    const START: &[u8] = b"$Format:";
+
+    let mut out = Vec::new();
+    let mut rest = data;
+
+    while let Some(start) = rest.windows(START.len()).position(|window| window == START) {
+        let after = &rest[start + START.len()..];
+
+        let Some(end) = after.iter().position(|&byte| byte == b'$') else {
+            break;
+        };
+
+        let format = PrettyFormat::Separated(String::from_utf8_lossy(&after[..end]).into_owned());
+
+        out.extend_from_slice(&rest[..start]);
+        out.extend_from_slice(&format_commit(&format, id, commit, options));
+        rest = &after[end + 1..];
+    }
+
+    out.extend_from_slice(rest);
+    out
+}
+
+fn unknown_identity() -> Identity {
+    Identity {
+        name: String::new(),
+        email: String::new(),
+        timestamp: 0,
+        tz_offset: 0,
+    }
+}
+
+/// "<id>[\t<source>] [(<decorations>)]", the id abbreviated with
+/// `abbrev_commit`
+fn commit_line(fields: &CommitFields, options: &PrettyOptions) -> String {
+    let mut line = match options.abbrev_commit {
+        true => abbrev(fields.id),
+        false => fields.id.to_string(),
+    };
+
+    if let Some(source) = options.source {
+        line.push('\t');
+        line.push_str(source);
+    }
+
+    if let Some(decorations) = options.decorations.and_then(|decorations| decorations.get(fields.id)) {
+        line.push_str(&format!(" ({decorations})"));
+    }
+
+    line
+}
+
+fn abbrev(id: ObjectId) -> String {
+    id.to_string()[..7].to_string()
+}
+
+fn format_ident_date(ident: &Identity, mode: DateMode) -> String {
+    date::format_date(ident.timestamp, ident.tz_offset, mode, date::now())
+}
+
+/// The multi-line formats:
+///
+///   commit <id>
+///   Reflog: <selector> (<name> <<email>>)   (when walking reflogs)
+///   Reflog message: <message>
+///   Merge: <parent> <parent>        (not for raw)
+///   <header lines>
+///   (blank line)
+///   <message, indented by four spaces>
+fn named_format(format: &PrettyFormat, fields: &CommitFields, options: &PrettyOptions) -> Vec<u8> {
+    let mut out = format!("commit {}\n", commit_line(fields, options));
+    let person = |ident: &Identity| format!("{} <{}>", ident.name, ident.email);
+
+    if let Some(reflog) = options.reflog {
+        let committer = Identity::parse(&reflog.entry.committer).unwrap_or_else(unknown_identity);
+
+        out.push_str(&format!("Reflog: {} ({})\n", reflog.selector, person(&committer)));
+        out.push_str(&format!("Reflog message: {}\n", reflog.entry.message));
+    }
+
+    if fields.parents.len() > 1 && *format != PrettyFormat::Raw {
+        let parents: Vec<String> = fields.parents.iter().map(|&parent| abbrev(parent)).collect();
+        out.push_str(&format!("Merge: {}\n", parents.join(" ")));
+    }
+
+    let date = |ident: &Identity| format_ident_date(ident, options.date_mode);
+
+    // Only these lines are mapped, "%an" and the raw headers never are
+    let (author, committer) = match options.use_mailmap {
+        true => (&fields.mailmapped_author, &fields.mailmapped_committer),
+        false => (&fields.author, &fields.committer),
+    };
+
+    match format {
+        PrettyFormat::Short => {
+            out.push_str(&format!("Author: {}\n", person(author)));
+        }
+        PrettyFormat::Medium => {
+            out.push_str(&format!("Author: {}\n", person(author)));
+            out.push_str(&format!("Date:   {}\n", date(author)));
+        }
+        PrettyFormat::Full => {
+            out.push_str(&format!("Author: {}\n", person(author)));
+            out.push_str(&format!("Commit: {}\n", person(committer)));
+        }
+        PrettyFormat::Fuller => {
+            out.push_str(&format!("Author:     {}\n", person(author)));
+            out.push_str(&format!("AuthorDate: {}\n", date(author)));
+            out.push_str(&format!("Commit:     {}\n", person(committer)));
+            out.push_str(&format!("CommitDate: {}\n", date(committer)));
+        }
+        _ => {
+            out.push_str(&format!("tree {}\n", fields.tree));
+
+            for parent in fields.parents {
+                out.push_str(&format!("parent {parent}\n"));
+            }
+
+            out.push_str(&format!("author {}\n", fields.raw_author));
+            out.push_str(&format!("committer {}\n", fields.raw_committer));
+
+            if let Some(encoding) = fields.encoding {
+                out.push_str(&format!("encoding {encoding}\n"));
+            }
+        }
+    }
+
+    out.push('\n');
+
+    let mut out = out.into_bytes();
+    indent_message(&mut out, fields.message, *format == PrettyFormat::Short);
+
+    out
+}
+
+/// Appends `message` indented by four spaces, with tabs expanded and
+/// trailing whitespace removed. Blank lines at the start and end are left
+/// out, and with `title_only` everything after the first paragraph.
+fn indent_message(out: &mut Vec<u8>, message: &[u8], title_only: bool) {
+    let lines: Vec<&[u8]> = message.split(|&byte| byte == b'\n')
+        .map(|line| &line[..line.iter().rposition(|byte| !byte.is_ascii_whitespace()).map_or(0, |pos| pos + 1)])
+        .skip_while(|line| line.is_empty())
+        .collect();
+
+    let mut end = lines.iter().rposition(|line| !line.is_empty()).map_or(0, |pos| pos + 1);
+
+    if title_only {
+        end = lines.iter().position(|line| line.is_empty()).unwrap_or(end).min(end);
+    }
+
+    for line in &lines[..end] {
+        out.extend_from_slice(b"    ");
+        out.extend_from_slice(&expand_tabs(line));
+        out.push(b'\n');
+    }
+}
+
+/// Expands tabs to the next multiple of 8 columns, counting each
+/// character as one column
+fn expand_tabs(line: &[u8]) -> Vec<u8> {
+    if !line.contains(&b'\t') {
+        return line.to_vec();
+    }
+
+    let mut out = Vec::with_capacity(line.len() + 8);
+    let mut column = 0;
+
+    for &byte in line {
+        if byte == b'\t' {
+            let width = 8 - column % 8;
+            out.extend(std::iter::repeat_n(b' ', width));
+            column += width;
+            continue;
+        }
+
+        out.push(byte);
+
+        // UTF-8 continuation bytes don't take up a column
+        if byte & 0xc0 != 0x80 {
+            column += 1;
+        }
+    }
+
+    out
+}
+
+/// Expands a format string's placeholders for one commit.
+///
+/// Placeholders:
+///   %H %h        commit id, abbreviated
+///   %T %t        tree id, abbreviated
+///   %P %p        parent ids, abbreviated
+///   %an %ae      author name and email, %cn %ce for the committer
+///   %al          the email's local part, before the '@'
+///   %aN %aE %aL  likewise, as the mailmap maps them
+///   %ad          author date in the `--date` format, %cd for the committer
+///   %aD %ar %at %ai %aI %as %ah
+///                author date as rfc2822, relative, unix, iso, strict iso,
+///                short or human, likewise for %c
+///   %s %f        subject, and as a file name
+///   %b %B        body, and the raw message
+///   %d %D        decorations, " (<refs>)" and without the parentheses
+///   %e           encoding
+///   %S           the starting point the commit was reached from
+///   %gd %gs      the reflog entry's selector and message, when walking
+///                reflogs, %gn %ge who made it
+///   %n %% %x<hh> newline, '%' and a byte in hex
+///   %w(<w>,<i1>,<i2>)
+///                wrap what follows at <w> columns, indenting its first
+///                line by <i1> and the rest by <i2>, all 0 by default
+///   %C<color>    %Cred, %Cgreen, %Cblue, %Creset or %C(<spec>), only when
+///                colors are on unless the spec starts with "always,"
+///
+/// Between '%' and the placeholder, '+' adds a newline before a non-empty
+/// expansion, ' ' a space, and '-' removes the newlines before an empty
+/// one. Anything else is kept as it is.
+fn expand(format: &str, fields: &CommitFields, options: &PrettyOptions) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut rest = format;
+
+    // Where the text "%w" last changed the wrapping of starts, and how
+    let mut wrap_start = 0;
+    let mut wrap = (0, 0, 0);
+
+    while let Some(pos) = rest.find('%') {
+        out.extend_from_slice(&rest.as_bytes()[..pos]);
+        rest = &rest[pos + 1..];
+
+        if let Some((new_wrap, len)) = wrap_placeholder(rest) {
+            if new_wrap != wrap {
+                rewrap_tail(&mut out, wrap_start, wrap);
+                wrap_start = out.len();
+                wrap = new_wrap;
+            }
+
+            rest = &rest[len..];
+            continue;
+        }
+
+        let modifier = rest.chars().next().filter(|c| matches!(c, '+' | '-' | ' '));
+        let spec = match modifier {
+            Some(_) => &rest[1..],
+            None => rest,
+        };
+
+        let Some((expansion, len)) = placeholder(spec, fields, options) else {
+            out.push(b'%');
+            continue;
+        };
+
+        rest = &spec[len..];
+
+        match modifier {
+            Some('+') if !expansion.is_empty() => out.push(b'\n'),
+            Some(' ') if !expansion.is_empty() => out.push(b' '),
+            Some('-') if expansion.is_empty() => {
+                while out.last() == Some(&b'\n') {
+                    out.pop();
+                }
+            }
+            _ => {}
+        }
+
+        out.extend_from_slice(&expansion);
+    }
+
+    out.extend_from_slice(rest.as_bytes());
+    rewrap_tail(&mut out, wrap_start, wrap);
+    out
+}
+
+/// "w(<w>,<i1>,<i2>)" after a '%', any of the numbers left out or empty
+/// being 0, and the length it took up
+fn wrap_placeholder(spec: &str) -> Option<((usize, usize, usize), usize)> {
+    let args = spec.strip_prefix("w(")?;
+    let end = args.find(')')?;
+
+    let mut numbers = [0; 3];
+
+    for (i, arg) in args[..end].split(',').enumerate() {
+        let arg = arg.trim();
+
+        if !arg.is_empty() {
+            *numbers.get_mut(i)? = arg.parse().ok()?;
+        }
+    }
+
+    Some(((numbers[0], numbers[1], numbers[2]), end + 3))
+}
+
+/// Wraps what's in `out` from `start` on as `wrap` says
+fn rewrap_tail(out: &mut Vec<u8>, start: usize, (width, indent1, indent2): (usize, usize, usize)) {
+    if (width, indent1, indent2) == (0, 0, 0) {
+        return;
+    }
+
+    let tail = out.split_off(start);
+    wrap_text(out, &tail, width, indent1, indent2);
+}
+
+/// Expands the placeholder at the start of `spec`, giving its expansion
+/// and length. None if it isn't one.
+fn placeholder(spec: &str, fields: &CommitFields, options: &PrettyOptions) -> Option<(Vec<u8>, usize)> {
+    let text = |text: String, len: usize| Some((text.into_bytes(), len));
+    let mut chars = spec.chars();
+
+    match chars.next()? {
+        'H' => text(fields.id.to_string(), 1),
+        'h' => text(abbrev(fields.id), 1),
+        'S' => text(options.source.unwrap_or_default().to_string(), 1),
+        'T' => text(fields.tree.to_string(), 1),
+        't' => text(abbrev(fields.tree), 1),
+        'P' => text(fields.parents.iter().map(ObjectId::to_string).collect::<Vec<_>>().join(" "), 1),
+        'p' => text(fields.parents.iter().map(|&parent| abbrev(parent)).collect::<Vec<_>>().join(" "), 1),
+        'a' | 'c' => {
+            let (ident, mailmapped) = match spec.starts_with('a') {
+                true => (&fields.author, &fields.mailmapped_author),
+                false => (&fields.committer, &fields.mailmapped_committer),
+            };
+
+            let mode = |format| DateMode { format, local: false };
+
+            let expansion = match chars.next()? {
+                'n' => ident.name.clone(),
+                'e' => ident.email.clone(),
+                'l' => ident.email.split('@').next().unwrap_or_default().to_string(),
+                'N' => mailmapped.name.clone(),
+                'E' => mailmapped.email.clone(),
+                'L' => mailmapped.email.split('@').next().unwrap_or_default().to_string(),
+                'd' => format_ident_date(ident, options.date_mode),
+                'D' => format_ident_date(ident, mode(DateFormat::Rfc2822)),
+                'r' => format_ident_date(ident, mode(DateFormat::Relative)),
+                't' => ident.timestamp.to_string(),
+                'i' => format_ident_date(ident, mode(DateFormat::Iso)),
+                'I' => format_ident_date(ident, mode(DateFormat::IsoStrict)),
+                's' => format_ident_date(ident, mode(DateFormat::Short)),
+                'h' => format_ident_date(ident, mode(DateFormat::Human)),
+                _ => return None,
+            };
+
+            text(expansion, 2)
+        }
+        's' => text(subject(fields.message), 1),
+        'f' => text(sanitized_subject(fields.message), 1),
+        'b' => Some((body(fields.message).to_vec(), 1)),
+        'B' => Some((fields.message.to_vec(), 1)),
+        'e' => text(fields.encoding.unwrap_or_default().to_string(), 1),
+        'g' => {
+            let committer = options.reflog.and_then(|reflog| Identity::parse(&reflog.entry.committer));
+
+            let expansion = match chars.next()? {
+                'd' | 'D' => options.reflog.map(|reflog| reflog.selector.clone()),
+                's' => options.reflog.map(|reflog| reflog.entry.message.clone()),
+                'n' => committer.map(|ident| ident.name),
+                'e' => committer.map(|ident| ident.email),
+                _ => return None,
+            };
+
+            text(expansion.unwrap_or_default(), 2)
+        }
+        'd' | 'D' => {
+            let decorations = options.decorations.and_then(|decorations| decorations.get(fields.id));
+
+            let expansion = match (decorations, spec.starts_with('d')) {
+                (Some(decorations), true) => format!(" ({decorations})"),
+                (Some(decorations), false) => decorations,
+                (None, _) => String::new(),
+            };
+
+            text(expansion, 1)
+        }
+        'n' => text("\n".to_string(), 1),
+        '%' => text("%".to_string(), 1),
+        'x' => {
+            let hex = spec.get(1..3)?;
+            let byte = u8::from_str_radix(hex, 16).ok()?;
+
+            Some((vec![byte], 3))
+        }
+        'C' => color_placeholder(&spec[1..], options.color).map(|(color, len)| (color.into_bytes(), len + 1)),
+        _ => None,
+    }
+}
+
+/// "red", "(bold red)" or "(auto,red)" after a "%C", as an ANSI escape
+/// sequence and the length it took up
+fn color_placeholder(spec: &str, color: bool) -> Option<(String, usize)> {
+    for (name, code) in [("red", "\x1b[31m"), ("green", "\x1b[32m"), ("blue", "\x1b[34m"), ("reset", "\x1b[m")] {
+        if spec.starts_with(name) {
+            let code = if color { code } else { "" };
+            return Some((code.to_string(), name.len()));
+        }
+    }
+
+    let end = spec.strip_prefix('(')?.find(')')? + 1;
+    let inner = &spec[1..end];
+
+    // "always," colors even when colors are off, "auto," is what happens
+    // anyway
+    let (always, inner) = match inner.strip_prefix("always,") {
+        Some(inner) => (true, inner),
+        None => (false, inner.strip_prefix("auto,").unwrap_or(inner)),
+    };
+
+    if !(color || always) || inner == "auto" {
+        return Some((String::new(), end + 1));
+    }
+
+    Some((parse_color(inner)?, end + 1))
+}
+
+/// Parses a git color spec, eg. "bold red" or "ul #ff0000 blue": any
+/// attributes, then a foreground and a background color
+pub fn parse_color(spec: &str) -> Option<String> {
+    const COLORS: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+    const ATTRIBUTES: [(&str, u8); 7] = [
+        ("bold", 1), ("dim", 2), ("italic", 3), ("ul", 4), ("blink", 5), ("reverse", 7), ("strike", 9),
+    ];
+
+    if spec == "reset" {
+        return Some("\x1b[m".to_string());
+    }
+
+    let mut attributes = Vec::new();
+    let mut colors: Vec<String> = Vec::new();
+
+    for word in spec.split_whitespace() {
+        let word = word.to_lowercase();
+
+        if let Some((_, code)) = ATTRIBUTES.iter().find(|(name, _)| *name == word) {
+            attributes.push(code.to_string());
+            continue;
+        }
+
+        if let Some((_, code)) = word.strip_prefix("no").and_then(|name| ATTRIBUTES.iter().find(|(attr, _)| *attr == name.trim_start_matches('-'))) {
+            // "nobold" turns bold off, which shares its code with dim
+            attributes.push(match code { 1 => 22, code => 20 + code }.to_string());
+            continue;
+        }
+
+        // The first color is the foreground, the second the background
+        let base = if colors.is_empty() { 30 } else { 40 };
+
+        let code = if word == "normal" {
+            None
+        } else if word == "default" {
+            Some((base + 9).to_string())
+        } else if let Some(index) = COLORS.iter().position(|name| *name == word) {
+            Some((base + index).to_string())
+        } else if let Some(index) = word.strip_prefix("bright").and_then(|name| COLORS.iter().position(|color| *color == name)) {
+            Some((base + 60 + index).to_string())
+        } else if let Ok(index) = word.parse::<u8>() {
+            Some(format!("{};5;{index}", base + 8))
+        } else if let Some(hex) = word.strip_prefix('#').filter(|hex| hex.len() == 6) {
+            let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+            Some(format!("{};2;{};{};{}", base + 8, channel(0)?, channel(2)?, channel(4)?))
+        } else {
+            return None;
+        };
+
+        colors.push(code.unwrap_or_default());
+    }
+
+    let codes: Vec<String> = attributes.into_iter()
+        .chain(colors.into_iter().filter(|code| !code.is_empty()))
+        .collect();
+
+    match codes.is_empty() {
+        true => Some(String::new()),
+        false => Some(format!("\x1b[{}m", codes.join(";"))),
+    }
+}
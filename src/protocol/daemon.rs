@@ -0,0 +1,80 @@
+use std::io::{self, Write};
+use std::net::TcpStream;
+
+use crate::advertise::Service;
+use crate::protocol::pktline::PacketWriter;
+
+/// The port git daemon listens on unless the URL says otherwise
+pub const DEFAULT_PORT: u16 = 9418;
+
+/// The parts of a "git://<host>[:<port>]<path>" URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GitUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl GitUrl {
+    fn parse(url: &str) -> io::Result<GitUrl> {
+        let rest = url.strip_prefix("git://").ok_or_else(|| io::Error::other(format!("'{url}' isn't a git:// URL")))?;
+
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => return Err(io::Error::other(format!("no path in '{url}'"))),
+        };
+
+        let (host, port) = match authority.rsplit_once(':') {
+            // An IPv6 address has colons of its own, inside brackets
+            Some((host, port)) if !port.contains(']') => {
+                let port = port.parse().map_err(|_| io::Error::other(format!("invalid port in '{url}'")))?;
+                (host, port)
+            }
+            _ => (authority, DEFAULT_PORT),
+        };
+
+        if host.is_empty() {
+            return Err(io::Error::other(format!("no host in '{url}'")));
+        }
+
+        Ok(GitUrl { host: host.to_string(), port, path: path.to_string() })
+    }
+}
+
+/// Connects to the git daemon serving `url` and asks for its `service`,
+/// with a request packet of
+///
+///   git-<service> <path> NUL host=<host>[:<port>] NUL [NUL version=2 NUL]
+///
+/// The extra parameters after the second NUL are ignored by daemons which
+/// don't know them, so asking for v2 is safe. What follows on the
+/// connection is the service's advertisement.
+pub fn connect(url: &str, service: Service, v2: bool) -> io::Result<TcpStream> {
+    let parsed = GitUrl::parse(url)?;
+
+    let stream = TcpStream::connect((parsed.host.trim_matches(['[', ']']), parsed.port))
+        .map_err(|err| io::Error::other(format!("unable to connect to {}: {err}", parsed.host)))?;
+
+    let host = match parsed.port {
+        DEFAULT_PORT => parsed.host.clone(),
+        port => format!("{}:{port}", parsed.host),
+    };
+
+    let mut request = format!("git-{} {}\0host={host}\0", service.name(), parsed.path);
+
+    if v2 {
+        request.push_str("\0version=2\0");
+    }
+
+    let mut packets = PacketWriter::new(&stream);
+    packets.write_data(request.as_bytes())?;
+    packets.flush()?;
+
+    Ok(stream)
+}
+
+/// Ends a session on `stream` with a flush-pkt, which tells the
+/// service there's nothing more to ask for
+pub fn disconnect(mut stream: &TcpStream) -> io::Result<()> {
+    stream.write_all(b"0000")
+}
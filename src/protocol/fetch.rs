@@ -0,0 +1,611 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+
+use crate::advertise::Service;
+use crate::config::Config;
+use crate::protocol::ssh::{self, ServiceProcess};
+use crate::protocol::{daemon, http, local};
+use crate::protocol::pktline::{Packet, PacketReader, PacketWriter, SidebandReader};
+use crate::revwalk::RevWalk;
+use crate::store::index_pack::store_pack;
+use crate::store::{GitObjectStore, ObjectId};
+
+/// How many of our commits are offered as haves, newest first. Offering
+/// too few only means being sent objects we already have.
+const MAX_HAVES: usize = 256;
+
+/// The version of git's wire protocol spoken with a remote
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ProtocolVersion {
+    /// The refs are advertised along with the capabilities straight away
+    V0,
+
+    /// Only the capabilities are advertised, the refs and the pack being
+    /// asked for with commands, `ls-refs` and `fetch`
+    #[default]
+    V2,
+}
+
+impl ProtocolVersion {
+    /// The version `protocol.version` asks for, 2 if unset. Version 1 is
+    /// version 0 with a line saying so, and is spoken as 0.
+    pub fn from_config(config: &Config) -> Option<ProtocolVersion> {
+        match config.get("protocol.version") {
+            None | Some("2") => Some(ProtocolVersion::V2),
+            Some("0" | "1") => Some(ProtocolVersion::V0),
+            Some(version) => {
+                eprintln!("unknown value for config 'protocol.version': {version}");
+                None
+            }
+        }
+    }
+}
+
+/// The refs and capabilities an upload-pack or receive-pack advertises
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Advertisement {
+    /// Each ref's name and id, as advertised. A tag's peeled id is listed
+    /// as "<tag>^{}". Always empty for protocol v2, which lists the refs
+    /// when asked.
+    pub refs: Vec<(String, ObjectId)>,
+
+    pub capabilities: Vec<String>,
+}
+
+impl Advertisement {
+    /// Reads the advertisement `advertise_refs` writes, up to its flush-pkt
+    pub fn read<R: Read>(packets: &mut PacketReader<R>) -> io::Result<Advertisement> {
+        Advertisement::parse(&packets.read_until_flush()?)
+    }
+
+    /// Parses a protocol v0 advertisement from its data packets
+    fn parse(lines: &[Vec<u8>]) -> io::Result<Advertisement> {
+        let mut advertisement = Advertisement::default();
+
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+
+            // The first line has the capabilities after a NUL
+            let (line, capabilities) = match line.iter().position(|&byte| byte == 0) {
+                Some(nul) if i == 0 => (&line[..nul], Some(&line[nul + 1..])),
+                _ => (line, None),
+            };
+
+            if let Some(capabilities) = capabilities {
+                advertisement.capabilities = String::from_utf8_lossy(capabilities)
+                    .split(' ')
+                    .filter(|capability| !capability.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+
+            let line = String::from_utf8_lossy(line);
+            let (id, name) = parse_ref_line(&line)?;
+
+            // An empty repository has only its capabilities to advertise
+            if name != "capabilities^{}" {
+                advertisement.refs.push((name.to_string(), id));
+            }
+        }
+
+        Ok(advertisement)
+    }
+
+    /// Whether `name` is offered, alone or as "<name>=<value>"
+    pub fn has_capability(&self, name: &str) -> bool {
+        self.capability(name).is_some()
+    }
+
+    /// The value `name` is offered with, empty if it's offered alone
+    pub fn capability(&self, name: &str) -> Option<&str> {
+        self.capabilities.iter().find_map(|capability| match capability.split_once('=') {
+            Some((key, value)) if key == name => Some(value),
+            None if capability == name => Some(""),
+            _ => None,
+        })
+    }
+}
+
+/// How a pack is fetched
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchOptions {
+    /// Show the remote's progress messages, else it's asked not to send
+    /// any
+    pub progress: bool,
+
+    /// Objects to leave out, eg. "blob:none", if the remote allows it
+    pub filter: Option<String>,
+}
+
+/// Whether `url` names a remote repository, rather than a local path.
+/// Either can be fetched from, see `local::local_git_dir`.
+pub fn is_url(url: &str) -> bool {
+    url.contains("://") || ssh::is_scp_like(url)
+}
+
+/// How requests reach a remote's service
+enum Transport {
+    /// Smart HTTP, each request being a POST of its own to
+    /// `<url>/git-<service>`
+    Http,
+
+    /// A git daemon, one connection carrying every request in turn
+    Daemon(TcpStream),
+
+    /// The service run through ssh or locally, one process carrying
+    /// every request in turn
+    Process(ServiceProcess),
+}
+
+/// A connection to a remote repository's upload-pack, or for a push its
+/// receive-pack, over smart HTTP (plain http://, as there's no TLS for
+/// https://), to a git daemon (git://), through ssh (ssh:// or scp-like
+/// "<host>:<path>") or run locally (a path or file://)
+pub struct Connection {
+    /// The repository's URL, after any redirect the advertisement took
+    pub url: String,
+
+    /// The version the remote answered with, which is v0 if it doesn't
+    /// know the version asked for
+    pub version: ProtocolVersion,
+
+    pub advertisement: Advertisement,
+
+    service: Service,
+    transport: Transport,
+}
+
+impl Connection {
+    /// Connects to the upload-pack of the repository at `url`, reading its
+    /// advertisement
+    pub fn connect(url: &str, version: ProtocolVersion) -> io::Result<Connection> {
+        Connection::open(url, Service::UploadPack, version)
+    }
+
+    /// Connects to the receive-pack of the repository at `url` to push to
+    /// it, reading its advertisement. Pushes only speak protocol v0.
+    pub fn connect_for_push(url: &str) -> io::Result<Connection> {
+        Connection::open(url, Service::ReceivePack, ProtocolVersion::V0)
+    }
+
+    fn open(url: &str, service: Service, version: ProtocolVersion) -> io::Result<Connection> {
+        if let Some(git_dir) = local::local_git_dir(url) {
+            Connection::connect_local(url, &git_dir, service, version)
+        } else if url.starts_with("file://") {
+            Err(io::Error::other(format!("'{url}' does not appear to be a git repository")))
+        } else if url.starts_with("git://") {
+            Connection::connect_daemon(url, service, version)
+        } else if ssh::is_ssh_url(url) {
+            Connection::connect_ssh(url, service, version)
+        } else {
+            Connection::connect_http(url, service, version)
+        }
+    }
+
+    /// Asks `url` for its capabilities, and with protocol v0 its refs,
+    /// from `<url>/info/refs?service=git-<service>`. A smart server
+    /// answers v0 with a "# service=git-<service>" line and a flush-pkt
+    /// before the ref advertisement, and v2 with a "version 2" line
+    /// before its capabilities.
+    fn connect_http(url: &str, service: Service, version: ProtocolVersion) -> io::Result<Connection> {
+        let url = url.trim_end_matches('/');
+        let headers = protocol_header(version);
+        let name = service.name();
+        let response = http::get(&format!("{url}/info/refs?service=git-{name}"), &headers)?;
+
+        if response.content_type != Some(format!("application/x-git-{name}-advertisement")) {
+            return Err(io::Error::other(format!("{url} isn't a smart HTTP repository, and only those are supported")));
+        }
+
+        // A redirect moves the whole repository
+        let url = response.url.split("/info/refs?").next().unwrap_or(url).to_string();
+
+        let mut packets = PacketReader::new(response.body);
+        let mut lines = packets.read_until_flush()?;
+        let service_line = format!("# service=git-{name}");
+
+        // Some servers leave the service line out of a v2 response
+        if lines.first().is_some_and(|line| line.strip_suffix(b"\n") == Some(service_line.as_bytes())) {
+            lines = packets.read_until_flush()?;
+        } else if version == ProtocolVersion::V0 {
+            return Err(io::Error::other(format!("protocol error: expected '{service_line}'")));
+        }
+
+        Connection::from_advertisement(url, &lines, service, Transport::Http)
+    }
+
+    /// Connects to the git daemon serving `url`, which sends the same
+    /// advertisement as HTTP without the service line
+    fn connect_daemon(url: &str, service: Service, version: ProtocolVersion) -> io::Result<Connection> {
+        let stream = daemon::connect(url, service, version == ProtocolVersion::V2)?;
+        let lines = PacketReader::new(&stream).read_until_flush()?;
+
+        Connection::from_advertisement(url.to_string(), &lines, service, Transport::Daemon(stream))
+    }
+
+    /// Runs the remote's service through ssh, which like a daemon's
+    /// starts with the advertisement
+    fn connect_ssh(url: &str, service: Service, version: ProtocolVersion) -> io::Result<Connection> {
+        let config = Config::load();
+        let session = ssh::connect(url, service, config.get("core.sshcommand"), version == ProtocolVersion::V2)?;
+        let lines = PacketReader::new(&session.output).read_until_flush()?;
+
+        Connection::from_advertisement(url.to_string(), &lines, service, Transport::Process(session))
+    }
+
+    /// Runs the service on the local repository `git_dir`, which like
+    /// ssh's starts with the advertisement
+    fn connect_local(url: &str, git_dir: &Path, service: Service, version: ProtocolVersion) -> io::Result<Connection> {
+        let process = local::connect(git_dir, service, version == ProtocolVersion::V2)?;
+        let lines = PacketReader::new(&process.output).read_until_flush()?;
+
+        Connection::from_advertisement(url.to_string(), &lines, service, Transport::Process(process))
+    }
+
+    /// Takes the version the remote answered with from its advertisement
+    fn from_advertisement(url: String, lines: &[Vec<u8>], service: Service, transport: Transport) -> io::Result<Connection> {
+        if lines.first().is_some_and(|line| line.strip_suffix(b"\n") == Some(b"version 2")) {
+            let capabilities = lines[1..].iter()
+                .map(|line| String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(line)).into_owned())
+                .collect();
+
+            return Ok(Connection {
+                url,
+                version: ProtocolVersion::V2,
+                advertisement: Advertisement { refs: Vec::new(), capabilities },
+                service,
+                transport,
+            });
+        }
+
+        Ok(Connection { url, version: ProtocolVersion::V0, advertisement: Advertisement::parse(lines)?, service, transport })
+    }
+
+    /// The remote's refs, as (name, id) with peeled tags as "<tag>^{}".
+    /// Given `prefixes`, only those with names starting with one of them
+    /// are listed, which with protocol v2 saves the remote sending the
+    /// rest.
+    pub fn list_refs(&self, prefixes: &[String]) -> io::Result<Vec<(String, ObjectId)>> {
+        let wanted = |name: &str| prefixes.is_empty() || prefixes.iter().any(|prefix| name.starts_with(prefix.as_str()));
+
+        if self.version == ProtocolVersion::V0 {
+            return Ok(self.advertisement.refs.iter()
+                .filter(|(name, _)| wanted(name.strip_suffix("^{}").unwrap_or(name)))
+                .cloned()
+                .collect());
+        }
+
+        let mut arguments = vec!["peel".to_string()];
+        arguments.extend(prefixes.iter().map(|prefix| format!("ref-prefix {prefix}")));
+
+        let mut packets = self.command("ls-refs", &arguments)?;
+        let mut refs = Vec::new();
+
+        // Each line is "<id> <name>", followed by attributes such as
+        // "peeled:<id>"
+        for line in packets.read_until_flush()? {
+            let line = String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(&line)).into_owned();
+
+            let (id, rest) = parse_ref_line(&line)?;
+            let mut fields = rest.split(' ');
+            let name = fields.next().unwrap_or_default();
+
+            let peeled = fields
+                .find_map(|attribute| attribute.strip_prefix("peeled:"))
+                .and_then(|peeled| ObjectId::try_from(peeled.to_string()).ok());
+
+            refs.push((name.to_string(), id));
+
+            if let Some(peeled) = peeled {
+                refs.push((format!("{name}^{{}}"), peeled));
+            }
+        }
+
+        Ok(refs)
+    }
+
+    /// The branch the remote's HEAD points to, eg. "refs/heads/main",
+    /// None if it's detached or the remote doesn't say. With protocol v0
+    /// that's a "symref=HEAD:<target>" capability, with v2 an attribute
+    /// of HEAD when the refs are listed with "symrefs".
+    pub fn head_target(&self) -> io::Result<Option<String>> {
+        if self.version == ProtocolVersion::V0 {
+            return Ok(self.advertisement.capabilities.iter()
+                .find_map(|capability| capability.strip_prefix("symref=HEAD:"))
+                .map(str::to_string));
+        }
+
+        let arguments = ["symrefs".to_string(), "ref-prefix HEAD".to_string()];
+        let mut packets = self.command("ls-refs", &arguments)?;
+        let mut target = None;
+
+        for line in packets.read_until_flush()? {
+            let line = String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(&line)).into_owned();
+
+            let (_, rest) = parse_ref_line(&line)?;
+            let mut fields = rest.split(' ');
+
+            if fields.next() == Some("HEAD") {
+                target = fields.find_map(|attribute| attribute.strip_prefix("symref-target:")).map(str::to_string);
+            }
+        }
+
+        Ok(target)
+    }
+
+    /// Fetches `wants` and what's reachable from them into the repository
+    /// as a new pack, giving its name. Our commits are offered as haves,
+    /// so only what we're missing is sent. Nothing is fetched if we
+    /// already have every one of `wants`.
+    pub fn fetch_pack(&self, wants: &[ObjectId], options: &FetchOptions) -> io::Result<Option<String>> {
+        if wants.iter().all(|&want| GitObjectStore::exists(want)) {
+            return Ok(None);
+        }
+
+        let filter_supported = match self.version {
+            ProtocolVersion::V0 => self.advertisement.has_capability("filter"),
+            ProtocolVersion::V2 => self.advertisement.capability("fetch")
+                .is_some_and(|features| features.split(' ').any(|feature| feature == "filter")),
+        };
+
+        let filter = options.filter.as_ref().filter(|_| {
+            if !filter_supported {
+                eprintln!("warning: filtering not recognized by server, ignoring");
+            }
+
+            filter_supported
+        });
+
+        match self.version {
+            ProtocolVersion::V0 => self.fetch_pack_v0(wants, options.progress, filter),
+            ProtocolVersion::V2 => self.fetch_pack_v2(wants, options.progress, filter),
+        }
+    }
+
+    /// Protocol v0 negotiates with capabilities on the first want, which
+    /// are what the advertisement offered
+    fn fetch_pack_v0(&self, wants: &[ObjectId], progress: bool, filter: Option<&String>) -> io::Result<Option<String>> {
+        // The pack comes multiplexed with progress messages if it can
+        let sideband = ["side-band-64k", "side-band"].into_iter()
+            .find(|capability| self.advertisement.has_capability(capability));
+
+        let mut capabilities: Vec<&str> = sideband.into_iter().collect();
+
+        if self.advertisement.has_capability("ofs-delta") {
+            capabilities.push("ofs-delta");
+        }
+
+        if !progress && self.advertisement.has_capability("no-progress") {
+            capabilities.push("no-progress");
+        }
+
+        if filter.is_some() {
+            capabilities.push("filter");
+        }
+
+        let agent = agent();
+
+        if self.advertisement.has_capability("agent") {
+            capabilities.push(&agent);
+        }
+
+        let mut request = PacketWriter::new(Vec::new());
+
+        for (i, want) in wants.iter().enumerate() {
+            match i {
+                0 => request.write_line(&format!("want {want} {}", capabilities.join(" ")))?,
+                _ => request.write_line(&format!("want {want}"))?,
+            }
+        }
+
+        if let Some(filter) = filter {
+            request.write_line(&format!("filter {filter}"))?;
+        }
+
+        request.write_flush()?;
+
+        // With "done" straight after, the remote settles on what we have
+        // in common in one round
+        for have in haves() {
+            request.write_line(&format!("have {have}"))?;
+        }
+
+        request.write_line("done")?;
+
+        let response = skip_acks(self.request(&request.into_inner(), ProtocolVersion::V0)?)?;
+        let packets = PacketReader::new(response);
+
+        let name = match sideband.is_some() {
+            true => store_pack(&mut SidebandReader::new(packets, show_progress))?,
+            false => store_pack(&mut packets.into_inner())?,
+        };
+
+        Ok(Some(name))
+    }
+
+    /// Protocol v2's fetch takes its options as arguments, and with
+    /// "done" among them answers with the pack straight away, in its
+    /// "packfile" section, always on the side-band
+    fn fetch_pack_v2(&self, wants: &[ObjectId], progress: bool, filter: Option<&String>) -> io::Result<Option<String>> {
+        let mut arguments = vec!["ofs-delta".to_string()];
+
+        if !progress {
+            arguments.push("no-progress".to_string());
+        }
+
+        arguments.extend(wants.iter().map(|want| format!("want {want}")));
+        arguments.extend(haves().map(|have| format!("have {have}")));
+
+        if let Some(filter) = filter {
+            arguments.push(format!("filter {filter}"));
+        }
+
+        arguments.push("done".to_string());
+
+        let mut packets = self.command("fetch", &arguments)?;
+
+        // Sections before the pack, eg. "wanted-refs", each end with a
+        // delim-pkt and aren't asked for
+        loop {
+            match packets.read_packet()? {
+                Some(packet) if packet.line() == Some(b"packfile") => break,
+                Some(Packet::Data(line)) if line.starts_with(b"ERR ") => {
+                    let message = String::from_utf8_lossy(&line[4..]);
+                    return Err(io::Error::other(format!("remote error: {}", message.trim_end())));
+                }
+                Some(Packet::Data(_) | Packet::Delim) => {}
+                _ => return Err(io::Error::other("protocol error: expected packfile")),
+            }
+        }
+
+        Ok(Some(store_pack(&mut SidebandReader::new(packets, show_progress))?))
+    }
+
+    /// Sends a protocol v2 command with `arguments`, giving the response
+    fn command(&self, command: &str, arguments: &[String]) -> io::Result<PacketReader<Box<dyn Read>>> {
+        let mut request = PacketWriter::new(Vec::new());
+
+        request.write_line(&format!("command={command}"))?;
+
+        if self.advertisement.has_capability("agent") {
+            request.write_line(&agent())?;
+        }
+
+        request.write_delim()?;
+
+        for argument in arguments {
+            request.write_line(argument)?;
+        }
+
+        request.write_flush()?;
+
+        Ok(PacketReader::new(self.request(&request.into_inner(), ProtocolVersion::V2)?))
+    }
+
+    /// Sends `request` to the service, giving what it answers with
+    pub(crate) fn request(&self, request: &[u8], version: ProtocolVersion) -> io::Result<Box<dyn Read>> {
+        match &self.transport {
+            Transport::Http => {
+                let name = self.service.name();
+
+                let response = http::post(
+                    &format!("{}/git-{name}", self.url),
+                    &format!("application/x-git-{name}-request"),
+                    &format!("application/x-git-{name}-result"),
+                    &protocol_header(version),
+                    request,
+                )?;
+
+                Ok(response.body)
+            }
+            Transport::Daemon(stream) => {
+                let mut writer = stream;
+                writer.write_all(request)?;
+                writer.flush()?;
+
+                Ok(Box::new(stream.try_clone()?))
+            }
+            Transport::Process(process) => {
+                let mut writer = process.child.stdin.as_ref().ok_or_else(|| io::Error::other("the service has no stdin"))?;
+                writer.write_all(request)?;
+                writer.flush()?;
+
+                Ok(Box::new(process.output.try_clone()?))
+            }
+        }
+    }
+}
+
+impl Drop for Connection {
+    /// The service is told it's done with, if it's still listening, and
+    /// its process is left to exit
+    fn drop(&mut self) {
+        match &mut self.transport {
+            Transport::Http => {}
+            Transport::Daemon(stream) => {
+                let _ = daemon::disconnect(stream);
+            }
+            Transport::Process(process) => {
+                if let Some(mut stdin) = process.child.stdin.take() {
+                    let _ = stdin.write_all(b"0000");
+                }
+
+                let _ = process.child.wait();
+            }
+        }
+    }
+}
+
+/// The header asking for `version`, none for v0
+fn protocol_header(version: ProtocolVersion) -> Vec<(&'static str, &'static str)> {
+    match version {
+        ProtocolVersion::V0 => Vec::new(),
+        ProtocolVersion::V2 => vec![("Git-Protocol", "version=2")],
+    }
+}
+
+pub(crate) fn agent() -> String {
+    format!("agent=gitty/{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// The commits offered as haves
+fn haves() -> impl Iterator<Item = ObjectId> {
+    let mut walk = RevWalk::new();
+    walk.push_all();
+
+    walk.take(MAX_HAVES)
+}
+
+/// Splits a "<id> <name>" line
+fn parse_ref_line(line: &str) -> io::Result<(ObjectId, &str)> {
+    line.split_once(' ')
+        .and_then(|(id, name)| Some((ObjectId::try_from(id.to_string()).ok()?, name)))
+        .ok_or_else(|| io::Error::other(format!("protocol error: unexpected '{line}'")))
+}
+
+/// Shows a progress message from the remote, each line prefixed as git
+/// does. Lines ending with a carriage return are redrawn in place.
+pub(crate) fn show_progress(message: &[u8]) {
+    let mut stderr = io::stderr().lock();
+
+    for line in message.split_inclusive(|&byte| byte == b'\n' || byte == b'\r') {
+        let _ = stderr.write_all(b"remote: ");
+        let _ = stderr.write_all(line);
+    }
+
+    let _ = stderr.flush();
+}
+
+/// Reads past the ACKs and NAK that come before a protocol v0 pack, giving
+/// the rest of `response`. Without multi_ack there's a NAK if nothing was
+/// in common, otherwise an ACK for the first common commit, or on a
+/// stateful connection one for each common commit as the haves were read,
+/// so how many there are isn't known until something else follows.
+fn skip_acks(mut response: Box<dyn Read>) -> io::Result<impl Read> {
+    let mut acknowledged = false;
+
+    loop {
+        // A pkt-line's length and the start of its data, or of a pack
+        let mut head = [0; 8];
+        response.read_exact(&mut head)?;
+
+        let len = std::str::from_utf8(&head[..4]).ok().and_then(|digits| usize::from_str_radix(digits, 16).ok());
+
+        match (len, &head[4..]) {
+            (Some(len), b"ACK " | b"NAK\n") if len >= head.len() => {
+                io::copy(&mut response.by_ref().take((len - head.len()) as u64), &mut io::sink())?;
+                acknowledged = true;
+            }
+            (Some(len), b"ERR ") if len >= head.len() => {
+                let mut message = vec![0; len - head.len()];
+                response.read_exact(&mut message)?;
+
+                let message = String::from_utf8_lossy(&message);
+                return Err(io::Error::other(format!("remote error: {}", message.trim_end())));
+            }
+            _ if acknowledged => return Ok(io::Cursor::new(head).chain(response)),
+            _ => return Err(io::Error::other("protocol error: expected ACK/NAK")),
+        }
+    }
+}
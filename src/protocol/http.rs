@@ -0,0 +1,257 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+/// How many redirects a GET follows before giving up
+const MAX_REDIRECTS: usize = 5;
+
+/// The parts of an "http://<host>[:<port>]<path>" URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HttpUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl HttpUrl {
+    /// Only plain http is spoken, there's no TLS to do https with
+    fn parse(url: &str) -> io::Result<HttpUrl> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            if url.starts_with("https://") {
+                return Err(io::Error::other(format!("unable to access '{url}': https isn't supported, only plain http://")));
+            }
+
+            let scheme = url.split_once("://").map_or(url, |(scheme, _)| scheme);
+            return Err(io::Error::other(format!("Unable to find remote helper for '{scheme}'")));
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+
+        if authority.contains('@') {
+            return Err(io::Error::other(format!("credentials in '{url}' aren't supported")));
+        }
+
+        let (host, port) = match authority.rsplit_once(':') {
+            // An IPv6 address has colons of its own, inside brackets
+            Some((host, port)) if !port.contains(']') => {
+                let port = port.parse().map_err(|_| io::Error::other(format!("invalid port in '{url}'")))?;
+                (host, port)
+            }
+            _ => (authority, 80),
+        };
+
+        if host.is_empty() {
+            return Err(io::Error::other(format!("no host in '{url}'")));
+        }
+
+        Ok(HttpUrl { host: host.to_string(), port, path: path.to_string() })
+    }
+
+    /// The Host header, which leaves out the default port
+    fn host_header(&self) -> String {
+        match self.port {
+            80 => self.host.clone(),
+            port => format!("{}:{port}", self.host),
+        }
+    }
+}
+
+/// A successful response
+pub struct HttpResponse {
+    /// The URL the response came from, after any redirects
+    pub url: String,
+
+    pub content_type: Option<String>,
+    pub body: Box<dyn Read>,
+}
+
+/// GETs `url` with `headers`, following redirects
+pub fn get(url: &str, headers: &[(&str, &str)]) -> io::Result<HttpResponse> {
+    let mut url = url.to_string();
+
+    for _ in 0..=MAX_REDIRECTS {
+        match request("GET", &url, headers, None)? {
+            Reply::Response(response) => return Ok(response),
+            Reply::Redirect(location) => url = location,
+        }
+    }
+
+    Err(io::Error::other(format!("unable to access '{url}': too many redirects")))
+}
+
+/// POSTs `body` as `content_type` to `url` with `headers`, accepting
+/// `accept` back. Redirects aren't followed, as git doesn't for POSTs
+/// either.
+pub fn post(url: &str, content_type: &str, accept: &str, headers: &[(&str, &str)], body: &[u8]) -> io::Result<HttpResponse> {
+    let headers: Vec<(&str, &str)> = [("Content-Type", content_type), ("Accept", accept)].into_iter()
+        .chain(headers.iter().copied())
+        .collect();
+
+    match request("POST", url, &headers, Some(body))? {
+        Reply::Response(response) => Ok(response),
+        Reply::Redirect(_) => Err(io::Error::other(format!("unable to access '{url}': unexpected redirect"))),
+    }
+}
+
+enum Reply {
+    Response(HttpResponse),
+
+    /// Where a redirect pointed, as an absolute URL
+    Redirect(String),
+}
+
+/// Makes one HTTP/1.1 request on a connection of its own, which the
+/// server is asked to close once it's answered
+fn request(method: &str, url: &str, headers: &[(&str, &str)], body: Option<&[u8]>) -> io::Result<Reply> {
+    let parsed = HttpUrl::parse(url)?;
+
+    let stream = TcpStream::connect((parsed.host.trim_matches(['[', ']']), parsed.port))
+        .map_err(|err| io::Error::other(format!("unable to access '{url}': {err}")))?;
+
+    let mut request = format!(
+        "{method} {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         User-Agent: git/gitty-{}\r\n\
+         Connection: close\r\n",
+        parsed.path,
+        parsed.host_header(),
+        env!("CARGO_PKG_VERSION"),
+    );
+
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+
+    if let Some(body) = body {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+
+    request.push_str("\r\n");
+
+    let mut writer = &stream;
+    writer.write_all(request.as_bytes())?;
+    writer.write_all(body.unwrap_or_default())?;
+    writer.flush()?;
+
+    let mut reader = BufReader::new(stream);
+
+    let status_line = read_line(&mut reader)?;
+
+    let status: u16 = status_line.split(' ').nth(1)
+        .and_then(|status| status.parse().ok())
+        .filter(|_| status_line.starts_with("HTTP/1."))
+        .ok_or_else(|| io::Error::other(format!("unable to access '{url}': bad status line '{status_line}'")))?;
+
+    let mut response_headers = Vec::new();
+
+    loop {
+        let line = read_line(&mut reader)?;
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            response_headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let header = |name: &str| response_headers.iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.clone());
+
+    if matches!(status, 301 | 302 | 303 | 307 | 308) {
+        let location = header("location")
+            .ok_or_else(|| io::Error::other(format!("unable to access '{url}': redirect without a location")))?;
+
+        let location = match location.starts_with('/') {
+            true => format!("http://{}{location}", parsed.host_header()),
+            false => location,
+        };
+
+        return Ok(Reply::Redirect(location));
+    }
+
+    if status != 200 {
+        return Err(io::Error::other(format!("unable to access '{url}': The requested URL returned error: {status}")));
+    }
+
+    let chunked = header("transfer-encoding").is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+    let length = header("content-length").and_then(|length| length.parse::<u64>().ok());
+
+    let body: Box<dyn Read> = match (chunked, length) {
+        (true, _) => Box::new(ChunkedReader { inner: reader, remaining: 0, done: false }),
+        (false, Some(length)) => Box::new(reader.take(length)),
+        (false, None) => Box::new(reader),
+    };
+
+    Ok(Reply::Response(HttpResponse {
+        url: url.to_string(),
+        content_type: header("content-type"),
+        body,
+    }))
+}
+
+/// A header line, less its CRLF
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the response ended"));
+    }
+
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Chunked transfer coding, each chunk its size in hex on a line of its
+/// own followed by that much data and a CRLF, ending with a zero sized one
+struct ChunkedReader<R> {
+    inner: R,
+
+    /// What's left of the current chunk
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 && !self.done {
+            let line = read_line(&mut self.inner)?;
+
+            // Chunk extensions follow a ';'
+            let size = line.split(';').next().unwrap_or_default().trim();
+
+            self.remaining = u64::from_str_radix(size, 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("bad chunk size '{line}'")))?;
+
+            if self.remaining == 0 {
+                self.done = true;
+
+                // Trailers, up to the empty line ending them
+                while !read_line(&mut self.inner)?.is_empty() {}
+            }
+        }
+
+        if self.done || out.is_empty() {
+            return Ok(0);
+        }
+
+        let len = out.len().min(self.remaining as usize);
+        let read = self.inner.read(&mut out[..len])?;
+
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed partway through a chunk"));
+        }
+
+        self.remaining -= read as u64;
+
+        // Each chunk's data ends with a CRLF of its own
+        if self.remaining == 0 {
+            read_line(&mut self.inner)?;
+        }
+
+        Ok(read)
+    }
+}
@@ -0,0 +1,58 @@
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::advertise::Service;
+use crate::protocol::ssh::ServiceProcess;
+
+/// The git dir of the repository `url` names if it's a local one, a path
+/// or a file:// URL to a worktree with a `.git` or a bare repository
+pub fn local_git_dir(url: &str) -> Option<PathBuf> {
+    let path = Path::new(url.strip_prefix("file://").unwrap_or(url));
+
+    if url.contains("://") && !url.starts_with("file://") {
+        return None;
+    }
+
+    [path.join(".git"), path.to_path_buf()].into_iter()
+        .find(|git_dir| git_dir.join("HEAD").is_file() && git_dir.join("objects").is_dir())
+        .and_then(|git_dir| fs::canonicalize(git_dir).ok())
+}
+
+/// Runs `service` on the local repository `git_dir` as a process of our
+/// own, as git does for paths and file:// URLs, the program being this
+/// one's `upload-pack` or `receive-pack`, so no git need be installed.
+/// Protocol v2 is asked for through `GIT_PROTOCOL`, which our services
+/// answer with v0.
+pub fn connect(git_dir: &Path, service: Service, v2: bool) -> io::Result<ServiceProcess> {
+    let program = format!("gitty {}", service.name());
+    let gitty = env::current_exe().map_err(|err| io::Error::other(format!("unable to run {program}: {err}")))?;
+
+    let mut command = Command::new(gitty);
+    command.arg(service.name());
+
+    if v2 {
+        command.env("GIT_PROTOCOL", "version=2");
+    }
+
+    // The repository's own environment mustn't leak into the service
+    let mut child = command.arg(git_dir)
+        .env_remove("GIT_DIR")
+        .env_remove("GIT_COMMON_DIR")
+        .env_remove("GIT_WORK_TREE")
+        .env_remove("GIT_INDEX_FILE")
+        .env_remove("GIT_OBJECT_DIRECTORY")
+        .env_remove("GIT_ALTERNATE_OBJECT_DIRECTORIES")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| io::Error::other(format!("unable to run {program}: {err}")))?;
+
+    let output = child.stdout.take().map(|stdout| File::from(OwnedFd::from(stdout)));
+    let output = output.ok_or_else(|| io::Error::other(format!("{program} has no stdout")))?;
+
+    Ok(ServiceProcess { child, output })
+}
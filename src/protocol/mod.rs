@@ -0,0 +1,7 @@
+pub mod pktline;
+pub mod http;
+pub mod daemon;
+pub mod ssh;
+pub mod local;
+pub mod fetch;
+pub mod push;
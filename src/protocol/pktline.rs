@@ -0,0 +1,276 @@
+use std::io::{self, Read, Write};
+
+/// The longest a pkt-line can be, its four length digits included
+pub const MAX_PACKET_LEN: usize = 65520;
+
+/// The most data a single pkt-line can carry
+pub const MAX_PACKET_DATA: usize = MAX_PACKET_LEN - 4;
+
+/// A pkt-line, which is its length as four hex digits (the digits
+/// included) followed by its data. Lengths below four are special packets
+/// with no data:
+///
+///   "0000"  flush-pkt, ending a list or a message
+///   "0001"  delim-pkt, separating the sections of a protocol v2 message
+///   "0002"  response-end-pkt, ending a protocol v2 response on a
+///           stateless connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    Flush,
+    Delim,
+    ResponseEnd,
+    Data(Vec<u8>),
+}
+
+impl Packet {
+    /// The data of a data packet less one trailing newline, as text lines
+    /// are sent with one
+    pub fn line(&self) -> Option<&[u8]> {
+        match self {
+            Packet::Data(data) => Some(data.strip_suffix(b"\n").unwrap_or(data)),
+            _ => None,
+        }
+    }
+}
+
+/// Reads pkt-lines from `inner`, reading no further than the end of each,
+/// so whatever follows them (eg. a pack) can be read from `inner` after.
+pub struct PacketReader<R> {
+    inner: R,
+}
+
+impl<R: Read> PacketReader<R> {
+    pub fn new(inner: R) -> PacketReader<R> {
+        PacketReader { inner }
+    }
+
+    /// The next packet, None if the stream ends cleanly before it. A stream
+    /// ending partway through one is an error.
+    pub fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        let mut digits = [0; 4];
+        let mut read = 0;
+
+        while read < digits.len() {
+            match self.inner.read(&mut digits[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the remote end hung up unexpectedly")),
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let len = std::str::from_utf8(&digits).ok()
+            .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .and_then(|digits| usize::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| protocol_error(format!("bad line length character: {}", String::from_utf8_lossy(&digits))))?;
+
+        match len {
+            0 => return Ok(Some(Packet::Flush)),
+            1 => return Ok(Some(Packet::Delim)),
+            2 => return Ok(Some(Packet::ResponseEnd)),
+            3 => return Err(protocol_error(format!("bad line length {len}"))),
+            len if len > MAX_PACKET_LEN => return Err(protocol_error(format!("bad line length {len}"))),
+            _ => {}
+        }
+
+        let mut data = vec![0; len - 4];
+        self.inner.read_exact(&mut data)?;
+
+        Ok(Some(Packet::Data(data)))
+    }
+
+    /// The data packets up to the next flush-pkt, which is consumed. Any
+    /// other special packet, or the stream ending, is an error.
+    pub fn read_until_flush(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut packets = Vec::new();
+
+        loop {
+            match self.read_packet()? {
+                Some(Packet::Data(data)) => packets.push(data),
+                Some(Packet::Flush) => return Ok(packets),
+                Some(_) => return Err(protocol_error("expected flush-pkt".to_string())),
+                None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the remote end hung up unexpectedly")),
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Writes pkt-lines to `inner`, unbuffered, so one wrapping a buffered
+/// writer should be flushed before waiting on the other end.
+pub struct PacketWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> PacketWriter<W> {
+    pub fn new(inner: W) -> PacketWriter<W> {
+        PacketWriter { inner }
+    }
+
+    pub fn write_packet(&mut self, packet: &Packet) -> io::Result<()> {
+        match packet {
+            Packet::Flush => self.inner.write_all(b"0000"),
+            Packet::Delim => self.inner.write_all(b"0001"),
+            Packet::ResponseEnd => self.inner.write_all(b"0002"),
+            Packet::Data(data) => self.write_data(data),
+        }
+    }
+
+    /// Writes `data` as one packet, which it has to fit in
+    pub fn write_data(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() > MAX_PACKET_DATA {
+            return Err(protocol_error(format!("packet of {} bytes is too long", data.len())));
+        }
+
+        write!(self.inner, "{:04x}", data.len() + 4)?;
+        self.inner.write_all(data)
+    }
+
+    /// Writes `line` as a packet, ending it with a newline
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.write_data(format!("{line}\n").as_bytes())
+    }
+
+    pub fn write_flush(&mut self) -> io::Result<()> {
+        self.write_packet(&Packet::Flush)
+    }
+
+    pub fn write_delim(&mut self) -> io::Result<()> {
+        self.write_packet(&Packet::Delim)
+    }
+
+    pub fn write_response_end(&mut self) -> io::Result<()> {
+        self.write_packet(&Packet::ResponseEnd)
+    }
+
+    /// Flushes `inner`, which is unrelated to writing a flush-pkt
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads the data sent on band 1 of a side-band stream, where each data
+/// packet starts with the band it's for: 1 for data, 2 for progress
+/// messages, which go to `progress`, and 3 for a fatal error. A flush-pkt
+/// ends the data.
+pub struct SidebandReader<R, P> {
+    packets: PacketReader<R>,
+    progress: P,
+
+    /// What's left of the last data packet
+    buffer: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl<R: Read, P: FnMut(&[u8])> SidebandReader<R, P> {
+    pub fn new(packets: PacketReader<R>, progress: P) -> SidebandReader<R, P> {
+        SidebandReader { packets, progress, buffer: Vec::new(), position: 0, done: false }
+    }
+}
+
+impl<R: Read, P: FnMut(&[u8])> Read for SidebandReader<R, P> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if self.done {
+                return Ok(0);
+            }
+
+            let data = match self.packets.read_packet()? {
+                Some(Packet::Data(data)) => data,
+                Some(Packet::Flush) | None => {
+                    self.done = true;
+                    continue;
+                }
+                Some(_) => return Err(protocol_error("unexpected special packet in side-band".to_string())),
+            };
+
+            match data.split_first() {
+                Some((1, data)) => {
+                    self.buffer = data.to_vec();
+                    self.position = 0;
+                }
+                Some((2, message)) => (self.progress)(message),
+                Some((3, message)) => {
+                    let message = String::from_utf8_lossy(message);
+                    return Err(io::Error::other(format!("remote error: {}", message.trim_end())));
+                }
+                _ => return Err(protocol_error("bad band in side-band".to_string())),
+            }
+        }
+
+        let len = out.len().min(self.buffer.len() - self.position);
+        out[..len].copy_from_slice(&self.buffer[self.position..self.position + len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+/// Writes data on band 1 of a side-band stream, as `SidebandReader`
+/// reads it, in packets of at most `max_len` bytes: 1000 for "side-band"
+/// and `MAX_PACKET_LEN` for "side-band-64k". What's written is held until
+/// it fills a packet or is flushed, and `finish` ends the data with a
+/// flush-pkt.
+pub struct SidebandWriter<W> {
+    packets: PacketWriter<W>,
+    max_len: usize,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> SidebandWriter<W> {
+    pub fn new(packets: PacketWriter<W>, max_len: usize) -> SidebandWriter<W> {
+        SidebandWriter { packets, max_len, buffer: vec![1] }
+    }
+
+    /// Writes what's held and the flush-pkt, giving the packets back
+    pub fn finish(mut self) -> io::Result<PacketWriter<W>> {
+        self.write_buffer()?;
+        self.packets.write_flush()?;
+        self.packets.flush()?;
+
+        Ok(self.packets)
+    }
+
+    fn write_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.len() > 1 {
+            self.packets.write_data(&self.buffer)?;
+            self.buffer.truncate(1);
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for SidebandWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        // The band and the data fit after the length
+        let room = self.max_len - 4 - self.buffer.len();
+        let len = data.len().min(room);
+
+        self.buffer.extend_from_slice(&data[..len]);
+
+        if len == room {
+            self.write_buffer()?;
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer()?;
+        self.packets.flush()
+    }
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("protocol error: {message}"))
+}
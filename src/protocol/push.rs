@@ -0,0 +1,170 @@
+use std::io;
+
+use crate::list_objects::objects_to_send;
+use crate::protocol::fetch::{agent, show_progress, Connection, ProtocolVersion};
+use crate::protocol::pktline::{PacketReader, PacketWriter, SidebandReader};
+use crate::push_cert::PushCertificate;
+use crate::store::pack_write::write_pack;
+use crate::store::ObjectId;
+
+/// A change a push asks the remote to make to one of its refs: from `old`
+/// to `new`, the null id on either side meaning it doesn't exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushUpdate {
+    pub name: String,
+    pub old: ObjectId,
+    pub new: ObjectId,
+}
+
+/// What the remote made of a push, from its report-status
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PushReport {
+    /// Why the pack couldn't be unpacked, if it couldn't
+    pub unpack_error: Option<String>,
+
+    /// The refs the remote refused to update, with why
+    pub rejected: Vec<(String, String)>,
+}
+
+impl Connection {
+    /// Pushes `updates` to the receive-pack, sending a pack of what it
+    /// needs for them that it doesn't have, as far as its advertised refs
+    /// tell. Without report-status the remote is taken to have made every
+    /// update.
+    ///
+    /// The request is a command per update, then the pack unless every
+    /// update is a deletion:
+    ///
+    ///   <old> SP <new> SP <name> [NUL <capabilities>, on the first]
+    ///   ...
+    ///   flush-pkt
+    ///   <pack>
+    ///
+    /// A signed push sends its `certificate` instead of the commands, as
+    /// it lists them itself, a pkt-line per line:
+    ///
+    ///   "push-cert" NUL <capabilities>
+    ///   <certificate>
+    ///   "push-cert-end"
+    ///   flush-pkt
+    pub fn send_pack(&self, updates: &[PushUpdate], certificate: Option<&PushCertificate>, progress: bool) -> io::Result<PushReport> {
+        if updates.is_empty() {
+            return Ok(PushReport::default());
+        }
+
+        if updates.iter().any(|update| update.new.is_null()) && !self.advertisement.has_capability("delete-refs") {
+            return Err(io::Error::other("the receiving end does not support deleting refs"));
+        }
+
+        let report_status = self.advertisement.has_capability("report-status");
+        let sideband = self.advertisement.has_capability("side-band-64k");
+
+        let mut capabilities = Vec::new();
+
+        if report_status {
+            capabilities.push("report-status");
+        }
+
+        if sideband {
+            capabilities.push("side-band-64k");
+        }
+
+        if !progress && self.advertisement.has_capability("quiet") {
+            capabilities.push("quiet");
+        }
+
+        let agent = agent();
+
+        if self.advertisement.has_capability("agent") {
+            capabilities.push(&agent);
+        }
+
+        let mut request = PacketWriter::new(Vec::new());
+
+        match certificate {
+            Some(certificate) => {
+                request.write_data(format!("push-cert\0{}", capabilities.join(" ")).as_bytes())?;
+
+                for line in certificate.to_bytes().split_inclusive(|&byte| byte == b'\n') {
+                    request.write_data(line)?;
+                }
+
+                request.write_line("push-cert-end")?;
+            }
+            None => {
+                for (i, update) in updates.iter().enumerate() {
+                    let command = format!("{} {} {}", update.old, update.new, update.name);
+
+                    match i {
+                        0 => request.write_data(format!("{command}\0{}", capabilities.join(" ")).as_bytes())?,
+                        _ => request.write_line(&command)?,
+                    }
+                }
+            }
+        }
+
+        request.write_flush()?;
+
+        let mut request = request.into_inner();
+
+        let wants: Vec<ObjectId> = updates.iter()
+            .map(|update| update.new)
+            .filter(|new| !new.is_null())
+            .collect();
+
+        if !wants.is_empty() {
+            let haves: Vec<ObjectId> = self.advertisement.refs.iter().map(|(_, id)| *id).collect();
+            let objects = objects_to_send(&wants, &haves, None).map_err(|missing| io::Error::other(missing.to_string()))?;
+
+            write_pack(&mut request, &objects)?;
+        }
+
+        let response = self.request(&request, ProtocolVersion::V0)?;
+
+        if !report_status {
+            // Anything left is progress, which is still worth showing
+            if sideband {
+                io::copy(&mut SidebandReader::new(PacketReader::new(response), show_progress), &mut io::sink())?;
+            }
+
+            return Ok(PushReport::default());
+        }
+
+        let lines = match sideband {
+            true => PacketReader::new(SidebandReader::new(PacketReader::new(response), show_progress)).read_until_flush()?,
+            false => PacketReader::new(response).read_until_flush()?,
+        };
+
+        read_report(&lines)
+    }
+}
+
+/// Reads a report-status:
+///
+///   "unpack ok" | "unpack " <error>
+///   ("ok " <name> | "ng " <name> SP <reason>)*
+fn read_report(lines: &[Vec<u8>]) -> io::Result<PushReport> {
+    let mut lines = lines.iter().map(|line| String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(line)).into_owned());
+
+    let unpack_error = match lines.next().as_deref().and_then(|line| line.strip_prefix("unpack ")) {
+        Some("ok") => None,
+        Some(error) => Some(error.to_string()),
+        None => return Err(io::Error::other("protocol error: expected an unpack status")),
+    };
+
+    let mut report = PushReport { unpack_error, rejected: Vec::new() };
+
+    for line in lines {
+        if line.starts_with("ok ") {
+            continue;
+        }
+
+        match line.strip_prefix("ng ").and_then(|rest| rest.split_once(' ')) {
+            Some((name, reason)) => report.rejected.push((name.to_string(), reason.to_string())),
+            None => return Err(io::Error::other(format!("protocol error: unexpected '{line}'"))),
+        }
+    }
+
+    Ok(report)
+}
+
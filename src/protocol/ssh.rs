@@ -0,0 +1,124 @@
+use std::env;
+use std::fs::File;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::process::{Child, Command, Stdio};
+
+use crate::advertise::Service;
+use crate::quote::sq_quote;
+
+/// Where an SSH URL points: "ssh://[<user>@]<host>[:<port>]/<path>", or
+/// scp-like, "[<user>@]<host>:<path>", where the path is relative to the
+/// user's home directory unless it starts with a slash
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SshUrl {
+    /// The host, with any user, as ssh takes it
+    host: String,
+    port: Option<u16>,
+    path: String,
+}
+
+impl SshUrl {
+    fn parse(url: &str) -> io::Result<SshUrl> {
+        let rest = ["ssh://", "git+ssh://", "ssh+git://"].iter().find_map(|scheme| url.strip_prefix(scheme));
+
+        let Some(rest) = rest else {
+            let (host, path) = url.split_once(':').ok_or_else(|| io::Error::other(format!("'{url}' isn't an ssh URL")))?;
+            return Ok(SshUrl { host: host.to_string(), port: None, path: path.to_string() });
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => return Err(io::Error::other(format!("no path in '{url}'"))),
+        };
+
+        // "/~user/..." is relative to that user's home, as in scp
+        let path = path.strip_prefix("/~").map_or(path.to_string(), |home| format!("~{home}"));
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                let port = port.parse().map_err(|_| io::Error::other(format!("invalid port in '{url}'")))?;
+                (host, Some(port))
+            }
+            _ => (authority, None),
+        };
+
+        if host.is_empty() {
+            return Err(io::Error::other(format!("no host in '{url}'")));
+        }
+
+        Ok(SshUrl { host: host.to_string(), port, path })
+    }
+}
+
+/// Whether `url` is scp-like, "[<user>@]<host>:<path>", which is told from
+/// a local path by the colon coming before any slash
+pub fn is_scp_like(url: &str) -> bool {
+    !url.contains("://") && url.split_once(':').is_some_and(|(host, _)| !host.is_empty() && !host.contains('/'))
+}
+
+/// Whether `url` is reached over SSH
+pub fn is_ssh_url(url: &str) -> bool {
+    ["ssh://", "git+ssh://", "ssh+git://"].iter().any(|scheme| url.starts_with(scheme)) || is_scp_like(url)
+}
+
+/// The ends of a service run as a process of our own, through ssh or
+/// locally
+pub struct ServiceProcess {
+    /// ssh, or the service itself, its stdin going to the service
+    pub child: Child,
+
+    /// What the service writes
+    pub output: File,
+}
+
+/// Runs `service` on the host `url` names through ssh, giving the process
+/// it's run by.
+///
+/// The ssh command is `GIT_SSH_COMMAND` or failing that `ssh_command`
+/// (core.sshCommand), either run by the shell, or the program `GIT_SSH`
+/// names, or "ssh". Protocol v2 is asked for through `GIT_PROTOCOL`, which
+/// OpenSSH is told to pass on, and which a server that won't take it
+/// ignores.
+pub fn connect(url: &str, service: Service, ssh_command: Option<&str>, v2: bool) -> io::Result<ServiceProcess> {
+    let parsed = SshUrl::parse(url)?;
+
+    let ssh_command = env::var("GIT_SSH_COMMAND").ok()
+        .filter(|command| !command.is_empty())
+        .or(ssh_command.map(str::to_string));
+
+    let mut command = match (ssh_command, env::var_os("GIT_SSH")) {
+        (Some(ssh_command), _) => shell_command(&ssh_command),
+        (None, Some(program)) => Command::new(program),
+        (None, None) => Command::new("ssh"),
+    };
+
+    if v2 {
+        command.args(["-o", "SendEnv=GIT_PROTOCOL"]).env("GIT_PROTOCOL", "version=2");
+    }
+
+    if let Some(port) = parsed.port {
+        command.args(["-p", &port.to_string()]);
+    }
+
+    let remote_command = format!("git-{} {}", service.name(), sq_quote(&parsed.path));
+
+    let mut child = command.arg(&parsed.host)
+        .arg(remote_command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| io::Error::other(format!("unable to run ssh: {err}")))?;
+
+    let output = child.stdout.take().map(|stdout| File::from(OwnedFd::from(stdout)));
+    let output = output.ok_or_else(|| io::Error::other("ssh has no stdout"))?;
+
+    Ok(ServiceProcess { child, output })
+}
+
+/// `command` run by the shell with the arguments added to it after
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(format!("{command} \"$@\"")).arg(command);
+    shell
+}
@@ -0,0 +1,494 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha1::{Digest, Sha1};
+
+use crate::config::Config;
+use crate::ident::{ident, IdentRole};
+use crate::store::object::Identity;
+use crate::store::{GitObjectStore, ObjectId};
+
+const CERT_VERSION: &str = "0.1";
+pub(crate) const SIGNATURE_START: &str = "-----BEGIN PGP SIGNATURE-----";
+
+/// What a push certificate records, signed by the pusher so the server
+/// can keep proof of who asked for which refs to be updated.
+///
+/// Certificate format:
+///   "certificate version 0.1" \n
+///   "pusher " <ident> \n
+///   "pushee " <url> \n (optional)
+///   "nonce " <nonce> \n (if the server gave one)
+///   "push-option " <option> \n (any number)
+///   \n
+///   <old-id> ' ' <new-id> ' ' <refname> \n (one per ref)
+///   <signature>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushCertificate {
+    /// Who's pushing and when, in the same form as a commit's committer
+    pub pusher: String,
+
+    /// Where to, without any credentials in the URL
+    pub pushee: Option<String>,
+
+    /// The nonce the server advertised with its push-cert capability
+    pub nonce: Option<String>,
+
+    pub push_options: Vec<String>,
+
+    /// The updates asked for, as (old, new, refname)
+    pub commands: Vec<(ObjectId, ObjectId, String)>,
+
+    /// The armored signature of everything before it, once signed
+    pub signature: Option<String>,
+}
+
+impl PushCertificate {
+    /// An unsigned certificate for pushing `commands` to `url`, from the
+    /// committer identity
+    pub fn new(config: &Config, url: &str, nonce: Option<&str>, commands: Vec<(ObjectId, ObjectId, String)>) -> Option<PushCertificate> {
+        Some(PushCertificate {
+            pusher: ident(IdentRole::Committer, config)?.to_string(),
+            pushee: Some(anonymize_url(url)),
+            nonce: nonce.map(str::to_string),
+            push_options: Vec::new(),
+            commands,
+            signature: None,
+        })
+    }
+
+    /// The text the signature is of
+    pub fn payload(&self) -> String {
+        let mut payload = format!("certificate version {CERT_VERSION}\npusher {}\n", self.pusher);
+
+        if let Some(pushee) = &self.pushee {
+            payload.push_str(&format!("pushee {pushee}\n"));
+        }
+
+        if let Some(nonce) = &self.nonce {
+            payload.push_str(&format!("nonce {nonce}\n"));
+        }
+
+        for option in &self.push_options {
+            payload.push_str(&format!("push-option {option}\n"));
+        }
+
+        payload.push('\n');
+
+        for (old, new, refname) in &self.commands {
+            payload.push_str(&format!("{old} {new} {refname}\n"));
+        }
+
+        payload
+    }
+
+    /// The certificate as sent, the payload followed by its signature
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = self.payload().into_bytes();
+
+        if let Some(signature) = &self.signature {
+            data.extend_from_slice(signature.as_bytes());
+        }
+
+        data
+    }
+
+    /// Parses a certificate in the format `to_bytes` writes. Headers this
+    /// doesn't know are skipped, as later versions may add some.
+    pub fn parse(data: &[u8]) -> Option<PushCertificate> {
+        let text = String::from_utf8_lossy(data);
+        let (headers, body) = text.split_once("\n\n")?;
+
+        let mut lines = headers.lines();
+
+        if lines.next()? != format!("certificate version {CERT_VERSION}") {
+            eprintln!("unsupported push certificate version");
+            return None;
+        }
+
+        let mut pusher = None;
+        let mut pushee = None;
+        let mut nonce = None;
+        let mut push_options = Vec::new();
+
+        for line in lines {
+            let (key, value) = line.split_once(' ')?;
+
+            match key {
+                "pusher" => pusher = Some(value.to_string()),
+                "pushee" => pushee = Some(value.to_string()),
+                "nonce" => nonce = Some(value.to_string()),
+                "push-option" => push_options.push(value.to_string()),
+                _ => {}
+            }
+        }
+
+        let (commands, signature) = match body.find(SIGNATURE_START) {
+            Some(pos) => (&body[..pos], Some(body[pos..].to_string())),
+            None => (body, None),
+        };
+
+        let commands = commands.lines()
+            .map(|line| {
+                let mut fields = line.splitn(3, ' ');
+
+                let old = ObjectId::try_from(fields.next()?.to_string()).ok()?;
+                let new = ObjectId::try_from(fields.next()?.to_string()).ok()?;
+
+                Some((old, new, fields.next()?.to_string()))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(PushCertificate {
+            pusher: pusher?,
+            pushee,
+            nonce,
+            push_options,
+            commands,
+            signature,
+        })
+    }
+
+    /// Signs the certificate with `user.signingKey`, failing that the key
+    /// for the pusher's name and email, using `gpg.program`
+    pub fn sign(&mut self, config: &Config) -> io::Result<()> {
+        let key = match config.get("user.signingkey") {
+            Some(key) => key.to_string(),
+            None => {
+                let pusher = Identity::parse(&self.pusher).ok_or_else(|| io::Error::other("malformed pusher"))?;
+                format!("{} <{}>", pusher.name, pusher.email)
+            }
+        };
+
+        let mut child = Command::new(gpg_program(config))
+            .args(["--status-fd=2", "-bsau", &key])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        child.stdin.take().expect("piped").write_all(self.payload().as_bytes())?;
+
+        let output = child.wait_with_output()?;
+        let status = String::from_utf8_lossy(&output.stderr);
+
+        if !output.status.success() || !status.lines().any(|line| line.starts_with("[GNUPG:] SIG_CREATED ")) {
+            return Err(io::Error::other("gpg failed to sign the push certificate"));
+        }
+
+        self.signature = Some(String::from_utf8_lossy(&output.stdout).to_string());
+
+        Ok(())
+    }
+}
+
+/// How a certificate's nonce compares with the one the server gave, as
+/// the `GIT_PUSH_CERT_NONCE_STATUS` hook variable says
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NonceStatus {
+    /// The server didn't ask for one, but there is one
+    Unsolicited,
+
+    /// It isn't one the server gave
+    Bad,
+
+    /// The server asked for one, but there isn't one
+    Missing,
+
+    Ok,
+
+    /// One the server gave, but in another request of a stateless
+    /// connection, longer ago than `receive.certNonceSlop` allows
+    Slop,
+}
+
+impl NonceStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NonceStatus::Unsolicited => "UNSOLICITED",
+            NonceStatus::Bad => "BAD",
+            NonceStatus::Missing => "MISSING",
+            NonceStatus::Ok => "OK",
+            NonceStatus::Slop => "SLOP",
+        }
+    }
+}
+
+/// The nonce receive-pack serving `path` gives with its push-cert
+/// capability at `timestamp`, if `receive.certNonceSeed` is set:
+///
+///   <timestamp> '-' <hex HMAC-SHA1 of the seed>
+///
+/// keyed by "<path>:<timestamp>", which is backwards but what git does,
+/// so a nonce can be checked without remembering it.
+pub fn push_cert_nonce(config: &Config, path: &str, timestamp: i64) -> Option<String> {
+    let seed = config.get("receive.certnonceseed")?;
+    let mac = hmac_sha1(format!("{path}:{timestamp}").as_bytes(), seed.as_bytes());
+
+    Some(format!("{timestamp}-{}", hex::encode(mac)))
+}
+
+/// Checks the nonce in a certificate against `issued`, the nonce given
+/// out, if any.
+///
+/// Over a stateless connection (smart HTTP) the nonce was given out by
+/// an earlier request, so any nonce this server could have made for
+/// `path` will do. It's OK if made within `receive.certNonceSlop`
+/// seconds of `issued`, otherwise the slop in seconds is returned too.
+pub fn check_nonce(config: &Config, nonce: Option<&str>, issued: Option<&str>, path: &str, stateless: bool) -> (NonceStatus, Option<i64>) {
+    let (nonce, issued) = match (nonce, issued) {
+        (None, _) => return (NonceStatus::Missing, None),
+        (Some(_), None) => return (NonceStatus::Unsolicited, None),
+        (Some(nonce), Some(issued)) => (nonce, issued),
+    };
+
+    if nonce == issued {
+        return (NonceStatus::Ok, None);
+    }
+
+    if !stateless {
+        return (NonceStatus::Bad, None);
+    }
+
+    let stamp = |nonce: &str| nonce.split_once('-').and_then(|(stamp, _)| stamp.parse::<i64>().ok());
+
+    let (Some(stamp), Some(issued_stamp)) = (stamp(nonce), stamp(issued)) else {
+        return (NonceStatus::Bad, None);
+    };
+
+    if push_cert_nonce(config, path, stamp).as_deref() != Some(nonce) {
+        return (NonceStatus::Bad, None);
+    }
+
+    let slop = issued_stamp - stamp;
+
+    let limit = config.get("receive.certnonceslop")
+        .and_then(|limit| limit.parse::<i64>().ok())
+        .unwrap_or(0);
+
+    match limit != 0 && slop.abs() <= limit {
+        true => (NonceStatus::Ok, None),
+        false => (NonceStatus::Slop, Some(slop)),
+    }
+}
+
+/// What checking a received certificate found, for the hooks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertCheck {
+    /// The certificate as stored in the repository
+    pub blob: ObjectId,
+
+    pub signer: Option<String>,
+    pub key: Option<String>,
+
+    /// gpg's verdict as git gives it: 'G' good, 'U' good but of unknown
+    /// validity, 'B' bad, 'X' expired, 'Y' by an expired key, 'R' by a
+    /// revoked key, 'E' unable to check, 'N' not signed
+    pub status: char,
+
+    pub nonce: Option<String>,
+    pub nonce_status: NonceStatus,
+    pub nonce_slop: Option<i64>,
+}
+
+impl CertCheck {
+    /// Stores the certificate `data` and checks its signature and nonce,
+    /// `issued` being the nonce this server advertised
+    pub fn new(data: &[u8], config: &Config, issued: Option<&str>, path: &str, stateless: bool) -> io::Result<CertCheck> {
+        let cert = PushCertificate::parse(data).ok_or_else(|| io::Error::other("malformed push certificate"))?;
+
+        let blob = GitObjectStore::write("blob", data).ok_or_else(|| io::Error::other("unable to store the push certificate"))?;
+
+        let (status, signer, key) = match &cert.signature {
+            Some(signature) => verify_signature(config, cert.payload().as_bytes(), signature)?,
+            None => ('N', None, None),
+        };
+
+        let (nonce_status, nonce_slop) = check_nonce(config, cert.nonce.as_deref(), issued, path, stateless);
+
+        Ok(CertCheck {
+            blob,
+            signer,
+            key,
+            status,
+            nonce: issued.map(str::to_string),
+            nonce_status,
+            nonce_slop,
+        })
+    }
+
+    /// The `GIT_PUSH_CERT*` variables the pre-receive and post-receive
+    /// hooks are run with
+    pub fn hook_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("GIT_PUSH_CERT", self.blob.to_string()),
+            ("GIT_PUSH_CERT_SIGNER", self.signer.clone().unwrap_or_default()),
+            ("GIT_PUSH_CERT_KEY", self.key.clone().unwrap_or_default()),
+            ("GIT_PUSH_CERT_STATUS", self.status.to_string()),
+        ];
+
+        // Nonce variables are only given when the server asked for one
+        if let Some(nonce) = &self.nonce {
+            env.push(("GIT_PUSH_CERT_NONCE", nonce.clone()));
+            env.push(("GIT_PUSH_CERT_NONCE_STATUS", self.nonce_status.as_str().to_string()));
+
+            if let Some(slop) = self.nonce_slop {
+                env.push(("GIT_PUSH_CERT_NONCE_SLOP", slop.to_string()));
+            }
+        }
+
+        env
+    }
+}
+
+/// Checks `signature` is of `payload` with gpg, returning its verdict,
+/// signer and key, see `CertCheck::status`
+pub(crate) fn verify_signature(config: &Config, payload: &[u8], signature: &str) -> io::Result<(char, Option<String>, Option<String>)> {
+    let signature_path = write_temp_file("gitty-verify", signature.as_bytes())?;
+
+    let output = Command::new(gpg_program(config))
+        .arg("--status-fd=1")
+        .arg("--verify")
+        .arg(&signature_path)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().expect("piped").write_all(payload)?;
+            child.wait_with_output()
+        });
+
+    let _ = fs::remove_file(&signature_path);
+    let output = output?;
+
+    let mut result = ('E', None, None);
+    let mut untrusted = false;
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some(status) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+
+        let (keyword, rest) = status.split_once(' ').unwrap_or((status, ""));
+
+        let verdict = match keyword {
+            "GOODSIG" => 'G',
+            "BADSIG" => 'B',
+            "EXPSIG" => 'X',
+            "EXPKEYSIG" => 'Y',
+            "REVKEYSIG" => 'R',
+            "ERRSIG" => 'E',
+            "TRUST_UNDEFINED" | "TRUST_NEVER" => {
+                untrusted = true;
+                continue;
+            }
+            _ => continue,
+        };
+
+        let (key, signer) = rest.split_once(' ').unwrap_or((rest, ""));
+
+        result = (
+            verdict,
+            (!signer.is_empty()).then(|| signer.to_string()),
+            (!key.is_empty()).then(|| key.to_string()),
+        );
+    }
+
+    if result.0 == 'G' && untrusted {
+        result.0 = 'U';
+    }
+
+    Ok(result)
+}
+
+/// Writes `contents` to a new file in the temporary directory, named
+/// `prefix` and something hard to guess, giving its path. The file is
+/// only ever created, never opened if something's there already, so a
+/// symlink another user plants can't have some other file written.
+fn write_temp_file(prefix: &str, contents: &[u8]) -> io::Result<PathBuf> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    for attempt in 0u32..100 {
+        let name = Sha1::new()
+            .chain_update(process::id().to_be_bytes())
+            .chain_update(now.as_nanos().to_be_bytes())
+            .chain_update(attempt.to_be_bytes())
+            .finalize();
+
+        let path = env::temp_dir().join(format!("{prefix}-{}", &hex::encode(name)[..16]));
+
+        let mut file = match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        };
+
+        if let Err(err) = file.write_all(contents) {
+            let _ = fs::remove_file(&path);
+            return Err(err);
+        }
+
+        return Ok(path);
+    }
+
+    Err(io::Error::other("unable to create a temporary file"))
+}
+
+fn gpg_program(config: &Config) -> String {
+    config.get("gpg.program").unwrap_or("gpg").to_string()
+}
+
+/// `url` without any user name or password, which don't belong in a
+/// certificate kept by the server, or in what's shown of a push
+pub fn anonymize_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        // scp-like "user@host:path"
+        return match url.split_once(':') {
+            Some((host, path)) if !host.contains('/') => {
+                format!("{}:{path}", host.rsplit_once('@').map_or(host, |(_, host)| host))
+            }
+            _ => url.to_string(),
+        };
+    };
+
+    let (authority, path) = rest.split_once('/').map_or((rest, None), |(authority, path)| (authority, Some(path)));
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+
+    match path {
+        Some(path) => format!("{scheme}://{host}/{path}"),
+        None => format!("{scheme}://{host}"),
+    }
+}
+
+/// HMAC (RFC 2104) with SHA-1
+fn hmac_sha1(key: &[u8], message: &[u8]) -> [u8; 20] {
+    const BLOCK_SIZE: usize = 64;
+
+    let mut block = [0u8; BLOCK_SIZE];
+
+    if key.len() > BLOCK_SIZE {
+        block[..20].copy_from_slice(&Sha1::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let pad = |byte: u8| block.map(|key_byte| key_byte ^ byte);
+
+    let inner = Sha1::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+
+    Sha1::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
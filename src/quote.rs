@@ -0,0 +1,74 @@
+use crate::config::Config;
+
+/// Whether paths with bytes outside ASCII are quoted, `core.quotePath`
+pub fn quote_non_ascii(config: &Config) -> bool {
+    config.get_bool("core.quotePath").unwrap_or(true)
+}
+
+/// Quotes a path for output the way git does: if it has control
+/// characters, '"', '\' or (with `quote_non_ascii`) bytes outside ASCII,
+/// it's wrapped in double quotes with those bytes escaped C style,
+/// otherwise it's printed as it is.
+///
+/// Paths which don't need quoting must be valid UTF-8 for their bytes to
+/// be kept, without `quote_non_ascii` anything else is quoted.
+pub fn quote_path(path: &[u8], quote_non_ascii: bool) -> String {
+    let utf8 = std::str::from_utf8(path).ok();
+
+    let must_quote = |byte: u8| {
+        byte < 0x20 || byte == b'"' || byte == b'\\' || byte == 0x7f
+            || (byte >= 0x80 && (quote_non_ascii || utf8.is_none()))
+    };
+
+    match utf8 {
+        Some(path) if !path.bytes().any(must_quote) => return path.to_string(),
+        _ => {}
+    }
+
+    let mut quoted = vec![b'"'];
+
+    for &byte in path {
+        let escape = match byte {
+            0x07 => b'a',
+            0x08 => b'b',
+            b'\t' => b't',
+            b'\n' => b'n',
+            0x0b => b'v',
+            0x0c => b'f',
+            b'\r' => b'r',
+            b'"' | b'\\' => byte,
+            _ if must_quote(byte) => {
+                quoted.extend_from_slice(format!("\\{byte:03o}").as_bytes());
+                continue;
+            }
+            _ => {
+                quoted.push(byte);
+                continue;
+            }
+        };
+
+        quoted.extend_from_slice(&[b'\\', escape]);
+    }
+
+    quoted.push(b'"');
+
+    // Bytes outside ASCII are only kept as they are when the path is UTF-8
+    String::from_utf8(quoted).unwrap_or_default()
+}
+
+/// Quotes `text` for the shell as git does, in single quotes, with any
+/// single quote or '!' left outside them escaped, eg. "it's" becomes
+/// 'it'\''s'
+pub fn sq_quote(text: &str) -> String {
+    let mut quoted = String::from("'");
+
+    for ch in text.chars() {
+        match ch {
+            '\'' | '!' => quoted.push_str(&format!("'\\{ch}'")),
+            _ => quoted.push(ch),
+        }
+    }
+
+    quoted.push('\'');
+    quoted
+}
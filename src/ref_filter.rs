@@ -0,0 +1,278 @@
+use crate::graph::{AheadBehind, ContainsQuery, Graph};
+use crate::refs;
+use crate::revparse::peel_to_commit;
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+use crate::wildmatch::{wildmatch, WildmatchFlags};
+
+/// How name patterns are matched against refs
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PatternMode {
+    /// for-each-ref style: "refs/heads" matches everything below it, globs
+    /// match whole path components
+    #[default]
+    Path,
+
+    /// branch/tag style: globs are matched against the short name
+    ShortName,
+}
+
+/// Selects refs by name and by their relationship to other commits,
+/// shared by `branch`, `tag` and `for-each-ref`.
+#[derive(Debug, Default)]
+pub struct RefFilter {
+    pub patterns: Vec<String>,
+    pub pattern_mode: PatternMode,
+
+    /// Keep refs whose tip contains any of these commits
+    pub contains: Vec<ObjectId>,
+    pub no_contains: Vec<ObjectId>,
+
+    /// Keep refs whose tip is reachable from any of these commits
+    pub merged: Vec<ObjectId>,
+    pub no_merged: Vec<ObjectId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RefItem {
+    pub name: String,
+    pub id: ObjectId,
+
+    /// How far the ref is ahead of and behind each commit a format's
+    /// "ahead-behind:<commit>" atoms name, keyed by the name, as
+    /// `add_ahead_behind` works them out
+    pub ahead_behind: Vec<(String, AheadBehind)>,
+}
+
+impl RefFilter {
+    pub fn filter(&self, refs: Vec<(String, ObjectId)>) -> Vec<RefItem> {
+        let needs_commits = !self.contains.is_empty()
+            || !self.no_contains.is_empty()
+            || !self.merged.is_empty()
+            || !self.no_merged.is_empty();
+
+        let mut graph = Graph::new();
+
+        let mut contains = (!self.contains.is_empty())
+            .then(|| ContainsQuery::new(&mut graph, &self.contains));
+
+        let mut no_contains = (!self.no_contains.is_empty())
+            .then(|| ContainsQuery::new(&mut graph, &self.no_contains));
+
+        let mut items = Vec::new();
+
+        for (name, id) in refs {
+            if !self.matches_name(&name) {
+                continue;
+            }
+
+            if needs_commits {
+                // Refs to trees and blobs are never selected by commit filters
+                let Some(commit) = refs::peel_ref(&name, id).and_then(peel_to_commit) else {
+                    continue;
+                };
+
+                if let Some(query) = &mut contains {
+                    if !query.matches(&mut graph, commit) {
+                        continue;
+                    }
+                }
+
+                if let Some(query) = &mut no_contains {
+                    if query.matches(&mut graph, commit) {
+                        continue;
+                    }
+                }
+
+                if !self.merged.is_empty()
+                    && !self.merged.iter().any(|&tip| graph.is_ancestor(commit, tip)) {
+                    continue;
+                }
+
+                if self.no_merged.iter().any(|&tip| graph.is_ancestor(commit, tip)) {
+                    continue;
+                }
+            }
+
+            items.push(RefItem {
+                name,
+                id,
+                ahead_behind: Vec::new(),
+            });
+        }
+
+        items
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        if self.patterns.is_empty() {
+            return true;
+        }
+
+        self.patterns.iter().any(|pattern| match self.pattern_mode {
+            PatternMode::ShortName => {
+                wildmatch(pattern, refs::shorten_ref(name), WildmatchFlags::default())
+            },
+            PatternMode::Path => {
+                let pattern = pattern.trim_end_matches('/');
+
+                name == pattern
+                    || name.strip_prefix(pattern).is_some_and(|rest| rest.starts_with('/'))
+                    || wildmatch(pattern, name, WildmatchFlags { pathname: true, ..Default::default() })
+            }
+        })
+    }
+}
+
+/// The commits named by "ahead-behind:<commit>" atoms in `format`
+pub fn ahead_behind_bases(format: &str) -> Vec<String> {
+    let mut bases = Vec::new();
+
+    for (start, _) in format.match_indices("%(ahead-behind:") {
+        let rest = &format[start + "%(ahead-behind:".len()..];
+
+        if let Some(end) = rest.find(')') {
+            bases.push(rest[..end].to_string());
+        }
+    }
+
+    bases
+}
+
+/// Works out how far each item is ahead of and behind each of `bases`,
+/// named commits, in one walk for them all. Items which aren't commits
+/// are left without.
+pub fn add_ahead_behind(items: &mut [RefItem], bases: &[(String, ObjectId)]) {
+    let commits: Vec<Option<ObjectId>> = items.iter().map(|item| peel_to_commit(item.id)).collect();
+
+    let pairs: Vec<(ObjectId, ObjectId)> = commits.iter()
+        .flatten()
+        .flat_map(|&commit| bases.iter().map(move |&(_, base)| (base, commit)))
+        .collect();
+
+    let mut counts = Graph::new().ahead_behind(&pairs).into_iter();
+
+    for (item, commit) in items.iter_mut().zip(commits) {
+        if commit.is_some() {
+            item.ahead_behind = bases.iter().map(|(name, _)| (name.clone(), counts.next().unwrap_or_default())).collect();
+        }
+    }
+}
+
+/// Expands a for-each-ref format string for one ref.
+///
+/// See `expand_format` for the syntax. Atoms:
+///   refname, refname:short, objectname, objectname:short, objecttype,
+///   HEAD ('*' if this is the checked out branch), *objectname,
+///   *objecttype for the object an annotated tag points at, and
+///   ahead-behind:<commit>, "<ahead> <behind>" as `add_ahead_behind` gave
+pub fn format_ref(format: &str, item: &RefItem) -> String {
+    expand_format(format, |atom| Some(expand_atom(atom, item))).unwrap_or_default()
+}
+
+/// Expands a format string of the kind for-each-ref and ls-tree take,
+/// with `expand_atom` giving the value of each atom. None if it doesn't
+/// know one.
+///
+/// Format syntax:
+///   "%(" <atom> ")"   a field, eg. "%(objectname)"
+///   "%%"              a literal '%'
+///   "%n"              a newline
+///   "%" <hex> <hex>   the byte with that hex value, also "%x" <hex> <hex>
+pub fn expand_format(format: &str, mut expand_atom: impl FnMut(&str) -> Option<String>) -> Option<String> {
+    let mut output = String::new();
+    let mut rest = format;
+
+    while let Some(start) = rest.find('%') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        if let Some(after) = rest.strip_prefix('%') {
+            output.push('%');
+            rest = after;
+            continue;
+        }
+
+        if let Some(after) = rest.strip_prefix('n') {
+            output.push('\n');
+            rest = after;
+            continue;
+        }
+
+        if let Some(after) = rest.strip_prefix('(') {
+            let Some(end) = after.find(')') else {
+                output.push_str("%(");
+                rest = after;
+                continue;
+            };
+
+            output.push_str(&expand_atom(&after[..end])?);
+            rest = &after[end + 1..];
+            continue;
+        }
+
+        let digits = match rest.strip_prefix('x') {
+            Some(after) if after.get(..2).is_some_and(|hex| u8::from_str_radix(hex, 16).is_ok()) => after,
+            _ => rest,
+        };
+
+        let hex = digits.get(..2).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match hex {
+            Some(byte) => {
+                output.push(byte as char);
+                rest = &digits[2..];
+            },
+            None => output.push('%'),
+        }
+    }
+
+    output.push_str(rest);
+    Some(output)
+}
+
+fn expand_atom(atom: &str, item: &RefItem) -> String {
+    let (atom, modifier) = match atom.split_once(':') {
+        Some((atom, modifier)) => (atom, Some(modifier)),
+        None => (atom, None),
+    };
+
+    let (deref, atom) = match atom.strip_prefix('*') {
+        Some(atom) => (true, atom),
+        None => (false, atom),
+    };
+
+    let id = if deref {
+        match GitObjectStore::get(item.id).map(|obj| obj.data) {
+            Some(GitObjectData::Tag { object, .. }) => object,
+            // Only tags can be dereferenced
+            _ => return String::new(),
+        }
+    } else {
+        item.id
+    };
+
+    match (atom, modifier) {
+        ("refname", None) => item.name.clone(),
+        ("refname", Some("short")) => refs::shorten_ref(&item.name).to_string(),
+        ("objectname", None) => id.to_string(),
+        ("objectname", Some("short")) => id.to_string()[..7].to_string(),
+        ("objecttype", None) => GitObjectStore::get(id)
+            .map(|obj| obj.type_str().to_string())
+            .unwrap_or_default(),
+        ("ahead-behind", Some(base)) => item.ahead_behind.iter()
+            .find(|(name, _)| name == base)
+            .map(|(_, counts)| format!("{} {}", counts.ahead, counts.behind))
+            .unwrap_or_default(),
+        ("HEAD", None) => {
+            if refs::head_branch().as_deref() == Some(item.name.as_str()) {
+                "*".to_string()
+            } else {
+                " ".to_string()
+            }
+        },
+        _ => {
+            eprintln!("Unknown field name: {atom}");
+            String::new()
+        }
+    }
+}
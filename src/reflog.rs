@@ -0,0 +1,420 @@
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::{parse_bool, Config};
+use crate::date::{self, DateFormat, DateMode};
+use crate::graph::{ident_time, Graph};
+use crate::lockfile::LockFile;
+use crate::refs;
+use crate::repo::git_path;
+use crate::revparse::peel_to_commit;
+use crate::store::object::Identity;
+use crate::store::ObjectId;
+use crate::wildmatch::{wildmatch, WildmatchFlags};
+
+const DEFAULT_REFLOG_EXPIRE: &str = "90.days.ago";
+const DEFAULT_REFLOG_EXPIRE_UNREACHABLE: &str = "30.days.ago";
+
+/// One update of a ref
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// The null id if the ref was created by this update
+    pub old: ObjectId,
+    pub new: ObjectId,
+
+    /// Who made the update and when, in the same form as a commit's committer
+    pub committer: String,
+
+    pub message: String,
+}
+
+impl ReflogEntry {
+    pub fn timestamp(&self) -> Option<i64> {
+        ident_time(&self.committer).map(|time| time as i64)
+    }
+}
+
+/// Reflog line format:
+///   <old-hex> ' ' <new-hex> ' ' <committer> [ '\t' <message> ]
+impl Display for ReflogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.old, self.new, self.committer)?;
+
+        if !self.message.is_empty() {
+            write!(f, "\t{}", self.message)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn reflog_path(refname: &str) -> PathBuf {
+    git_path(format!("logs/{refname}"))
+}
+
+/// The reflog for `refname`, oldest entry first
+pub fn read_reflog(refname: &str) -> Option<Vec<ReflogEntry>> {
+    let contents = fs::read(reflog_path(refname)).ok()?;
+    let contents = String::from_utf8_lossy(&contents);
+
+    let mut entries = Vec::new();
+
+    for line in contents.lines() {
+        match parse_reflog_line(line) {
+            Some(entry) => entries.push(entry),
+            None => eprintln!("Ignoring malformed reflog line in {refname}: {line}"),
+        }
+    }
+
+    Some(entries)
+}
+
+fn parse_reflog_line(line: &str) -> Option<ReflogEntry> {
+    let (old, rest) = line.split_once(' ')?;
+    let (new, rest) = rest.split_once(' ')?;
+
+    let (committer, message) = match rest.split_once('\t') {
+        Some((committer, message)) => (committer, message),
+        None => (rest, ""),
+    };
+
+    Some(ReflogEntry {
+        old: ObjectId::try_from(old.to_string()).ok()?,
+        new: ObjectId::try_from(new.to_string()).ok()?,
+        committer: committer.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// Which of a ref's past values to look up, as in "main@{2}" or
+/// "main@{1.hour.ago}"
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReflogSelector {
+    /// The value n updates ago, 0 being the latest
+    Nth(usize),
+
+    /// The value the ref had at this time, in seconds since the epoch
+    Date(i64),
+}
+
+impl ReflogSelector {
+    /// Parses what's between the braces of "@{...}", a count or a date
+    pub fn parse(selector: &str, now: i64) -> Option<ReflogSelector> {
+        if !selector.is_empty() && selector.chars().all(|c| c.is_ascii_digit()) {
+            return selector.parse().ok().map(ReflogSelector::Nth);
+        }
+
+        date::approxidate(selector, now)
+            .or_else(|| date::parse_date(selector).map(|(time, _)| time))
+            .map(ReflogSelector::Date)
+    }
+}
+
+/// The ref whose reflog a name given by the user refers to: the current
+/// branch for an empty name (HEAD if it's detached), or the first ref the
+/// name expands to
+pub fn reflog_name(name: &str) -> Option<String> {
+    if name.is_empty() {
+        return Some(refs::head_branch().unwrap_or_else(|| "HEAD".to_string()));
+    }
+
+    if name == "HEAD" || reflog_path(name).is_file() {
+        return Some(name.to_string());
+    }
+
+    refs::expand_ref(name)
+}
+
+/// Looks up a past value of `refname` in its reflog, where `name` is the
+/// ref as the user gave it, for messages.
+///
+/// Counting back one past the oldest entry gives the value the ref had
+/// before it, and a date older than the whole log is answered with the
+/// oldest value known, with a warning.
+pub fn lookup_reflog(refname: &str, name: &str, selector: ReflogSelector) -> Option<ObjectId> {
+    let entries = read_reflog(refname).unwrap_or_default();
+
+    let Some(oldest) = entries.first() else {
+        // With nothing logged, the ref has been as it is for all time
+        if let ReflogSelector::Date(_) = selector {
+            return refs::resolve_ref(refname);
+        }
+
+        eprintln!("log for {refname} is empty");
+        return None;
+    };
+
+    match selector {
+        ReflogSelector::Nth(n) if n < entries.len() => Some(entries[entries.len() - 1 - n].new),
+        ReflogSelector::Nth(n) if n == entries.len() && !oldest.old.is_null() => Some(oldest.old),
+        ReflogSelector::Nth(_) => {
+            eprintln!("log for '{name}' only has {} entries", entries.len());
+            None
+        }
+        ReflogSelector::Date(time) => {
+            if let Some(entry) = entries.iter().rev().find(|entry| entry.timestamp().unwrap_or(0) <= time) {
+                return Some(entry.new);
+            }
+
+            if let Some(ident) = Identity::parse(&oldest.committer) {
+                let mode = DateMode { format: DateFormat::Rfc2822, local: false };
+                let date = date::format_date(ident.timestamp, ident.tz_offset, mode, date::now());
+
+                eprintln!("warning: log for '{name}' only goes back to {date}");
+            }
+
+            match oldest.old.is_null() {
+                true => Some(oldest.new),
+                false => Some(oldest.old),
+            }
+        }
+    }
+}
+
+/// Where a detached HEAD was detached, as `status` and `branch` describe it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetachedFrom {
+    /// The ref that was checked out, or the commit abbreviated if it was
+    /// checked out by id or the ref has moved on since
+    pub name: String,
+
+    /// Whether HEAD is still at the commit it was detached at
+    pub at: bool,
+}
+
+/// Finds where HEAD, detached at `head`, was detached from the latest
+/// checkout in its reflog. `None` if no checkout was logged.
+pub fn detached_from(head: ObjectId) -> Option<DetachedFrom> {
+    let entries = read_reflog("HEAD")?;
+
+    let (entry, target) = entries.iter().rev().find_map(|entry| {
+        let moving = entry.message.strip_prefix("checkout: moving from ")?;
+        let (_, target) = moving.split_once(" to ")?;
+
+        Some((entry, target))
+    })?;
+
+    // A ref names the checkout only while it's still at the same commit
+    let refname = refs::expand_ref(target).filter(|refname| {
+        refs::resolve_ref(refname).is_some_and(|id| id == entry.new || peel_to_commit(id) == Some(entry.new))
+    });
+
+    let name = match refname {
+        Some(refname) if target != "HEAD" => {
+            let short = refname.strip_prefix("refs/tags/")
+                .or_else(|| refname.strip_prefix("refs/remotes/"))
+                .unwrap_or(&refname);
+
+            short.to_string()
+        }
+        _ => entry.new.to_string()[..7].to_string(),
+    };
+
+    Some(DetachedFrom { name, at: head == entry.new })
+}
+
+/// Adds an entry to the end of the reflog for `refname`, creating it if
+/// needed
+pub fn append_reflog(refname: &str, entry: &ReflogEntry) -> io::Result<()> {
+    let path = reflog_path(refname);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    // A single write, so concurrent appends don't interleave
+    file.write_all(format!("{entry}\n").as_bytes())
+}
+
+/// Should updates to `refname` be logged?
+///
+/// Refs which already have a reflog always are, otherwise this follows
+/// `core.logAllRefUpdates`: "always" logs every ref, true (the default
+/// outside bare repositories) logs HEAD, branches, remote-tracking
+/// branches and notes.
+pub fn should_log(config: &Config, refname: &str) -> bool {
+    if reflog_path(refname).is_file() {
+        return true;
+    }
+
+    let bare = config.get_bool("core.bare").unwrap_or(false);
+
+    match config.get("core.logallrefupdates") {
+        Some(value) if value.eq_ignore_ascii_case("always") => true,
+        Some(value) => parse_bool(value).unwrap_or(false) && is_logged_by_default(refname),
+        None => !bare && is_logged_by_default(refname),
+    }
+}
+
+fn is_logged_by_default(refname: &str) -> bool {
+    refname == "HEAD"
+        || ["refs/heads/", "refs/remotes/", "refs/notes/"].iter().any(|prefix| refname.starts_with(prefix))
+}
+
+/// Replaces the whole reflog for `refname`
+pub fn write_reflog(refname: &str, entries: &[ReflogEntry]) -> io::Result<()> {
+    let mut lock = LockFile::acquire(reflog_path(refname))?;
+
+    for entry in entries {
+        writeln!(lock, "{entry}")?;
+    }
+
+    lock.commit()
+}
+
+/// The names of every ref which has a reflog
+pub fn list_reflogs() -> Vec<String> {
+    let mut names = Vec::new();
+
+    if reflog_path("HEAD").is_file() {
+        names.push("HEAD".to_string());
+    }
+
+    visit_logs(&reflog_path("refs"), "refs", &mut names);
+
+    names.sort();
+    names
+}
+
+fn visit_logs(dir: &Path, prefix: &str, names: &mut Vec<String>) {
+    let Ok(contents) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in contents.flatten() {
+        let Ok(filename) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        let name = format!("{prefix}/{filename}");
+
+        if entry.path().is_dir() {
+            visit_logs(&entry.path(), &name, names);
+        } else if !filename.ends_with(".lock") {
+            names.push(name);
+        }
+    }
+}
+
+/// Cut-off times for `expire_reflog`, entries older than these are pruned
+#[derive(Debug, Copy, Clone)]
+pub struct ExpireOptions {
+    /// Every entry older than this is pruned
+    pub expire_total: i64,
+
+    /// Entries older than this are pruned if they're no longer reachable
+    /// from the tip of the ref
+    pub expire_unreachable: i64,
+}
+
+impl ExpireOptions {
+    /// Reads `gc.reflogExpire` and `gc.reflogExpireUnreachable`, letting
+    /// `gc.<pattern>.reflogExpire*` override them for matching refs.
+    pub fn from_config(config: &Config, refname: &str, now: i64) -> ExpireOptions {
+        let lookup = |name: &str, default: &str| -> i64 {
+            let name = name.to_lowercase();
+
+            // The last matching pattern wins, as in git
+            let per_ref = config.entries()
+                .filter_map(|entry| {
+                    let pattern = entry.key.strip_prefix("gc.")?.strip_suffix(&format!(".{name}"))?;
+                    wildmatch(pattern, refname, WildmatchFlags::default())
+                        .then_some(entry.value.as_deref().unwrap_or(""))
+                })
+                .last();
+
+            let value = per_ref
+                .or_else(|| config.get(&format!("gc.{name}")))
+                .unwrap_or(default);
+
+            date::approxidate(value, now).unwrap_or_else(|| {
+                eprintln!("Invalid expiry date '{value}' for gc.{name}");
+                date::approxidate(default, now).unwrap_or(0)
+            })
+        };
+
+        ExpireOptions {
+            expire_total: lookup("reflogExpire", DEFAULT_REFLOG_EXPIRE),
+            expire_unreachable: lookup("reflogExpireUnreachable", DEFAULT_REFLOG_EXPIRE_UNREACHABLE),
+        }
+    }
+}
+
+/// Prunes old entries from the reflog of `refname`, returning what was
+/// (or with `dry_run`, would have been) pruned.
+///
+/// For HEAD an entry is unreachable if no ref can reach it, for any other
+/// ref it must be reachable from that ref's own tip.
+pub fn expire_reflog(
+    refname: &str,
+    options: &ExpireOptions,
+    graph: &mut Graph,
+    dry_run: bool,
+) -> Option<Vec<ReflogEntry>> {
+    let entries = read_reflog(refname)?;
+
+    let tips: Vec<ObjectId> = if refname == "HEAD" {
+        refs::list_refs("refs/")
+            .into_iter()
+            .map(|(_, id)| id)
+            .chain(refs::resolve_ref("HEAD"))
+            .filter_map(peel_to_commit)
+            .collect()
+    } else {
+        refs::resolve_ref(refname)
+            .and_then(peel_to_commit)
+            .into_iter()
+            .collect()
+    };
+
+    // With nothing to be reachable from, or a cut-off that doesn't prune
+    // any more than the total one, there's no point walking history
+    let check_reachability = !tips.is_empty() && options.expire_unreachable > options.expire_total;
+
+    let unreachable = |graph: &mut Graph, id: ObjectId| -> bool {
+        if id.is_null() {
+            return false;
+        }
+
+        !tips.iter().any(|&tip| graph.is_ancestor(id, tip))
+    };
+
+    let mut kept = Vec::new();
+    let mut pruned = Vec::new();
+
+    for entry in entries {
+        let timestamp = entry.timestamp().unwrap_or(0);
+
+        let prune = if timestamp < options.expire_total {
+            true
+        } else if timestamp < options.expire_unreachable {
+            !check_reachability
+                || unreachable(graph, entry.old)
+                || unreachable(graph, entry.new)
+        } else {
+            false
+        };
+
+        if prune {
+            pruned.push(entry);
+        } else {
+            kept.push(entry);
+        }
+    }
+
+    if !dry_run && !pruned.is_empty() {
+        if let Err(err) = write_reflog(refname, &kept) {
+            eprintln!("Unable to write reflog for {refname}: {err}");
+            return None;
+        }
+    }
+
+    Some(pruned)
+}
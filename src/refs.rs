@@ -0,0 +1,667 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config::Config;
+use crate::lockfile::LockFile;
+use crate::metrics;
+use crate::reflog::{append_reflog, reflog_path, should_log, ReflogEntry};
+use crate::repo::git_path;
+use crate::revparse;
+use crate::store::{GitObjectStore, ObjectId};
+
+// How deep we follow chains of symbolic refs before giving up
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// The raw contents of a ref, before any symbolic refs are followed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefValue {
+    Direct(ObjectId),
+    Symbolic(String),
+}
+
+/// A ref from `packed-refs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub id: ObjectId,
+
+    /// For annotated tags, the object the tag ultimately points at
+    pub peeled: Option<ObjectId>,
+
+    /// Whether the file records what the ref peels to, so that `peeled`
+    /// being None means it isn't a tag
+    pub peel_known: bool,
+}
+
+// packed-refs as last read, keyed by the file's path, size, mtime and
+// inode, which a rewrite (always by renaming a new file into place)
+// changes
+type PackedKey = (PathBuf, u64, i64, i64, u64);
+static PACKED_CACHE: Mutex<Option<(PackedKey, Vec<PackedRef>)>> = Mutex::new(None);
+
+/// Reads a single ref, preferring the loose file over `packed-refs`.
+pub fn read_ref(name: &str) -> Option<RefValue> {
+    if let Some(value) = read_loose_ref(name) {
+        return Some(value);
+    }
+
+    with_packed_refs(|refs| {
+        refs.iter()
+            .find(|packed| packed.name == name)
+            .map(|packed| RefValue::Direct(packed.id))
+    })
+}
+
+/// Loose ref format, either:
+///   <hex-oid> \n
+///   "ref: " <refname> \n
+fn read_loose_ref(name: &str) -> Option<RefValue> {
+    let path = git_path(name);
+
+    if !path.is_file() {
+        return None;
+    }
+
+    let contents = fs::read_to_string(path).ok()?;
+    let contents = contents.trim_end();
+
+    if let Some(target) = contents.strip_prefix("ref:") {
+        return Some(RefValue::Symbolic(target.trim().to_string()));
+    }
+
+    let id = ObjectId::try_from(contents.to_string()).ok()?;
+
+    Some(RefValue::Direct(id))
+}
+
+/// packed-refs format:
+///   "# pack-refs with:" <traits> \n (optional)
+///   <hex-oid> ' ' <refname> \n
+///   '^' <peeled-hex-oid> \n (only following annotated tags)
+///
+/// With the "fully-peeled" trait every tag has its peeled line, with
+/// "peeled" every tag under refs/tags/ does.
+pub fn read_packed_refs() -> Vec<PackedRef> {
+    with_packed_refs(<[PackedRef]>::to_vec)
+}
+
+/// Calls `f` with the refs in packed-refs, which are only read again once
+/// the file has changed
+fn with_packed_refs<R>(f: impl FnOnce(&[PackedRef]) -> R) -> R {
+    let path = git_path("packed-refs");
+
+    let Ok(metadata) = fs::metadata(&path) else {
+        return f(&[]);
+    };
+
+    let key = (path, metadata.len(), metadata.mtime(), metadata.mtime_nsec(), metadata.ino());
+
+    let mut cache = PACKED_CACHE.lock().unwrap_or_else(|err| err.into_inner());
+
+    match cache.as_ref() {
+        Some((cached_key, refs)) if *cached_key == key => {
+            metrics::count("gitty_packed_refs_cache_hits_total", &[], 1);
+            f(refs)
+        }
+        _ => {
+            metrics::count("gitty_packed_refs_cache_misses_total", &[], 1);
+
+            let refs = parse_packed_refs(&fs::read_to_string(&key.0).unwrap_or_default());
+            let result = f(&refs);
+
+            *cache = Some((key, refs));
+            result
+        }
+    }
+}
+
+fn parse_packed_refs(contents: &str) -> Vec<PackedRef> {
+    let mut refs: Vec<PackedRef> = Vec::new();
+
+    let mut fully_peeled = false;
+    let mut tags_peeled = false;
+
+    for line in contents.lines() {
+        if let Some(traits) = line.strip_prefix("# pack-refs with:") {
+            let traits: Vec<&str> = traits.split_whitespace().collect();
+
+            fully_peeled = traits.contains(&"fully-peeled");
+            tags_peeled = traits.contains(&"peeled");
+
+            continue;
+        }
+
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+
+        if let Some(peeled) = line.strip_prefix('^') {
+            let Ok(peeled) = ObjectId::try_from(peeled.to_string()) else {
+                eprintln!("packed-refs: invalid peeled object id");
+                continue;
+            };
+
+            if let Some(last) = refs.last_mut() {
+                last.peeled = Some(peeled);
+                last.peel_known = true;
+            }
+
+            continue;
+        }
+
+        let Some((id, name)) = line.split_once(' ') else {
+            eprintln!("packed-refs: unexpected line: {line}");
+            continue;
+        };
+
+        let Ok(id) = ObjectId::try_from(id.to_string()) else {
+            eprintln!("packed-refs: invalid object id for {name}");
+            continue;
+        };
+
+        refs.push(PackedRef {
+            name: name.to_string(),
+            id,
+            peeled: None,
+            peel_known: fully_peeled || tags_peeled && name.starts_with("refs/tags/"),
+        });
+    }
+
+    refs
+}
+
+/// What the ref `name`, which is at `id`, peels to once any tags are
+/// followed, `id` itself if it isn't a tag.
+///
+/// Where packed-refs has the ref at `id` and records what it peels to,
+/// that's used without reading any tags. None if a tag can't be read.
+pub fn peel_ref(name: &str, id: ObjectId) -> Option<ObjectId> {
+    let packed = with_packed_refs(|refs| {
+        refs.iter()
+            .find(|packed| packed.name == name && packed.id == id && packed.peel_known)
+            .map(|packed| packed.peeled.unwrap_or(id))
+    });
+
+    packed.or_else(|| revparse::peel(id))
+}
+
+/// Resolves `name` to an object id, following symbolic refs.
+pub fn resolve_ref(name: &str) -> Option<ObjectId> {
+    let mut name = name.to_string();
+
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match read_ref(&name)? {
+            RefValue::Direct(id) => return Some(id),
+            RefValue::Symbolic(target) => name = target,
+        }
+    }
+
+    eprintln!("Symbolic ref loop at {name}");
+    None
+}
+
+/// The ref `name` leads to once symbolic refs are followed, eg.
+/// "refs/heads/main" for HEAD on main, whether or not it exists yet
+pub fn resolve_ref_name(name: &str) -> String {
+    let mut name = name.to_string();
+
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match read_ref(&name) {
+            Some(RefValue::Symbolic(target)) => name = target,
+            _ => break,
+        }
+    }
+
+    name
+}
+
+/// Points the loose ref `name` at `id`.
+///
+/// With `expected`, the update is refused unless the ref currently
+/// resolves to that id, the null id meaning it mustn't exist yet. The
+/// check is made while holding the ref's lock, so it can't race another
+/// writer.
+pub fn write_ref(name: &str, id: ObjectId, expected: Option<ObjectId>) -> io::Result<()> {
+    let mut transaction = RefTransaction::new();
+    transaction.update(name, id, expected);
+    transaction.commit()
+}
+
+/// Points the symbolic ref `name` (usually HEAD) at the ref `target`
+pub fn write_symbolic_ref(name: &str, target: &str) -> io::Result<()> {
+    let mut lock = LockFile::acquire(git_path(name))?;
+
+    writeln!(lock, "ref: {target}")?;
+    lock.commit()
+}
+
+/// Deletes the ref `name`, both the loose file and any entry in
+/// `packed-refs`, along with its reflog.
+///
+/// `expected` is checked as in `write_ref`.
+pub fn delete_ref(name: &str, expected: Option<ObjectId>) -> io::Result<()> {
+    let mut transaction = RefTransaction::new();
+    transaction.delete(name, expected);
+    transaction.commit()
+}
+
+/// One change to a ref in a `RefTransaction`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefUpdate {
+    pub name: String,
+
+    /// What the ref is pointed at, or `None` to delete it
+    pub new: Option<ObjectId>,
+
+    /// What the ref must resolve to for the update to go ahead, the null
+    /// id meaning it mustn't exist yet
+    pub expected: Option<ObjectId>,
+
+    /// Added to the ref's reflog, and HEAD's when HEAD is on the ref, if
+    /// their updates are logged
+    pub reflog: Option<ReflogEntry>,
+}
+
+/// Ref updates made all together or not at all.
+///
+/// Committing takes the lock of every ref and checks each is where it's
+/// expected to be before anything is changed, so a ref which can't be
+/// updated leaves them all as they were. Should writing then fail part
+/// way, what's been changed already is put back.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RefTransaction {
+    updates: Vec<RefUpdate>,
+}
+
+impl RefTransaction {
+    pub fn new() -> RefTransaction {
+        RefTransaction::default()
+    }
+
+    /// Points `name` at `new`, giving the update to add a reflog entry to
+    pub fn update(&mut self, name: &str, new: ObjectId, expected: Option<ObjectId>) -> &mut RefUpdate {
+        self.push(name, Some(new), expected)
+    }
+
+    /// Deletes `name` along with its reflog
+    pub fn delete(&mut self, name: &str, expected: Option<ObjectId>) -> &mut RefUpdate {
+        self.push(name, None, expected)
+    }
+
+    fn push(&mut self, name: &str, new: Option<ObjectId>, expected: Option<ObjectId>) -> &mut RefUpdate {
+        self.updates.push(RefUpdate { name: name.to_string(), new, expected, reflog: None });
+        self.updates.last_mut().expect("just pushed")
+    }
+
+    pub fn commit(self) -> io::Result<()> {
+        for (i, update) in self.updates.iter().enumerate() {
+            if !check_ref_format(&update.name) {
+                return Err(io::Error::other(format!("refusing to update ref with bad name '{}'", update.name)));
+            }
+
+            if self.updates[..i].iter().any(|other| other.name == update.name) {
+                return Err(io::Error::other(format!("multiple updates for ref '{}' not allowed", update.name)));
+            }
+        }
+
+        // Until every lock is held and checked nothing has changed, so
+        // returning early drops the locks taken and that's that
+        let mut locks = Vec::new();
+
+        for update in &self.updates {
+            let mut lock = self.lock(update)?;
+
+            if let Some(new) = update.new {
+                writeln!(lock, "{new}")?;
+            }
+
+            locks.push(lock);
+        }
+
+        let mut changed = Vec::new();
+
+        if let Err(err) = self.apply(locks, &mut changed) {
+            for (path, contents) in changed.into_iter().rev() {
+                let _ = match contents {
+                    Some(contents) => fs::write(&path, contents),
+                    None => fs::remove_file(&path),
+                };
+            }
+
+            return Err(err);
+        }
+
+        self.update_reflogs()
+    }
+
+    /// Takes the lock of the ref `update` changes, checking it can be made
+    fn lock(&self, update: &RefUpdate) -> io::Result<LockFile> {
+        let name = &update.name;
+
+        if let Some(new) = update.new {
+            // "refs/heads/a" and "refs/heads/a/b" can't both exist as
+            // loose refs, nor be created together
+            if read_ref(name).is_none() {
+                let created = self.updates.iter()
+                    .filter(|other| other.new.is_some() && &other.name != name)
+                    .map(|other| other.name.clone());
+
+                if let Some(existing) = conflicting_ref(name, created) {
+                    return Err(io::Error::other(format!(
+                        "cannot lock ref '{name}': '{existing}' exists; cannot create '{name}'"
+                    )));
+                }
+            }
+
+            // Branches can only point at commits
+            match GitObjectStore::get(new) {
+                None => {
+                    return Err(io::Error::other(format!("trying to write ref '{name}' with nonexistent object {new}")));
+                }
+                Some(object) if name.starts_with("refs/heads/") && object.type_str() != "commit" => {
+                    return Err(io::Error::other(format!(
+                        "cannot update ref '{name}': trying to write non-commit object {new} to branch '{name}'"
+                    )));
+                }
+                Some(_) => {}
+            }
+        }
+
+        let lock = LockFile::acquire(git_path(name))
+            .map_err(|err| io::Error::new(err.kind(), format!("cannot lock ref '{name}': {err}")))?;
+
+        check_expected(name, update.expected)?;
+
+        Ok(lock)
+    }
+
+    /// Makes the locked updates, noting each file changed along with what
+    /// it held before, if anything
+    fn apply(&self, locks: Vec<LockFile>, changed: &mut Vec<(PathBuf, Option<Vec<u8>>)>) -> io::Result<()> {
+        let packed: Vec<String> = read_packed_refs().into_iter().map(|packed| packed.name).collect();
+
+        let unpacking: Vec<&str> = self.updates.iter()
+            .filter(|update| update.new.is_none() && packed.contains(&update.name))
+            .map(|update| update.name.as_str())
+            .collect();
+
+        if !unpacking.is_empty() {
+            let path = git_path("packed-refs");
+            changed.push((path.clone(), fs::read(&path).ok()));
+
+            remove_packed_refs(&unpacking)?;
+        }
+
+        for (update, lock) in self.updates.iter().zip(locks) {
+            let path = git_path(&update.name);
+            changed.push((path.clone(), fs::read(&path).ok()));
+
+            match update.new {
+                Some(_) => lock.commit()?,
+                None => {
+                    match fs::remove_file(&path) {
+                        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                        _ => {}
+                    }
+
+                    lock.rollback();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Logs the updates made, and removes the reflogs of deleted refs
+    fn update_reflogs(&self) -> io::Result<()> {
+        let config = Config::load();
+        let head = head_branch();
+
+        for update in &self.updates {
+            if update.new.is_none() {
+                let _ = fs::remove_file(reflog_path(&update.name));
+                remove_empty_dirs(&update.name);
+                continue;
+            }
+
+            let Some(entry) = &update.reflog else {
+                continue;
+            };
+
+            // HEAD's log records the updates of whichever branch it's on
+            let mut logged = vec![update.name.as_str()];
+
+            if head.as_deref() == Some(update.name.as_str()) {
+                logged.push("HEAD");
+            }
+
+            for refname in logged {
+                if should_log(&config, refname) {
+                    append_reflog(refname, entry)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Tidies up directories left empty by deleting `name`, eg.
+/// "refs/heads/topic/" after deleting "refs/heads/topic/a", but never
+/// "refs/heads/" itself
+fn remove_empty_dirs(name: &str) {
+    for prefix in ["", "logs/"] {
+        let mut dir = name;
+
+        while let Some((parent, _)) = dir.rsplit_once('/') {
+            if parent.matches('/').count() < 2 || fs::remove_dir(git_path(format!("{prefix}{parent}"))).is_err() {
+                break;
+            }
+
+            dir = parent;
+        }
+    }
+}
+
+fn check_expected(name: &str, expected: Option<ObjectId>) -> io::Result<()> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+
+    let message = match resolve_ref(name) {
+        Some(current) if current == expected => return Ok(()),
+        Some(_) if expected == ObjectId::NULL => "reference already exists".to_string(),
+        Some(current) => format!("is at {current} but expected {expected}"),
+        None if expected == ObjectId::NULL => return Ok(()),
+        None => format!("unable to resolve reference '{name}'"),
+    };
+
+    Err(io::Error::other(format!("cannot lock ref '{name}': {message}")))
+}
+
+/// Rewrites `packed-refs` without `names` (and their peeled lines),
+/// leaving everything else as it was
+fn remove_packed_refs(names: &[&str]) -> io::Result<()> {
+    let mut lock = LockFile::acquire(git_path("packed-refs"))?;
+
+    let contents = fs::read_to_string(git_path("packed-refs"))?;
+    let mut removing = false;
+
+    for line in contents.lines() {
+        if line.starts_with('^') && removing {
+            continue;
+        }
+
+        removing = line.split_once(' ').is_some_and(|(_, refname)| names.contains(&refname))
+            && !line.starts_with('#');
+
+        if !removing {
+            writeln!(lock, "{line}")?;
+        }
+    }
+
+    lock.commit()
+}
+
+/// An existing ref, or one of `created`, which is a parent or child of
+/// `name` in the ref hierarchy, preventing `name` being created
+fn conflicting_ref(name: &str, created: impl Iterator<Item = String>) -> Option<String> {
+    list_refs("refs/")
+        .into_iter()
+        .map(|(existing, _)| existing)
+        .chain(created)
+        .find(|existing| {
+            existing.strip_prefix(name).is_some_and(|rest| rest.starts_with('/'))
+                || name.strip_prefix(existing.as_str()).is_some_and(|rest| rest.starts_with('/'))
+        })
+}
+
+/// Whether `name` is a valid ref name, following `git check-ref-format`.
+///
+/// Components can't start with '.' or end with ".lock", and the name
+/// can't contain "..", "@{", "//", control characters, spaces or any of
+/// "~^:?*[\", nor end with '/' or '.', nor be "@".
+pub fn check_ref_format(name: &str) -> bool {
+    if name.is_empty() || name == "@" || name.ends_with('.') {
+        return false;
+    }
+
+    if name.contains("..") || name.contains("@{") {
+        return false;
+    }
+
+    let bad_char = |c: char| c.is_ascii_control() || " ~^:?*[\\".contains(c);
+
+    if name.chars().any(bad_char) {
+        return false;
+    }
+
+    name.split('/').all(|component| {
+        !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock")
+    })
+}
+
+/// Where HEAD is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    /// On a branch, by its full name, which is unborn if it doesn't exist
+    /// yet
+    Branch(String),
+
+    /// Detached at a commit
+    Detached(ObjectId),
+}
+
+impl Head {
+    /// The commit HEAD is at, `None` on an unborn branch
+    pub fn commit(&self) -> Option<ObjectId> {
+        match self {
+            Head::Branch(name) => resolve_ref(name),
+            Head::Detached(id) => Some(*id),
+        }
+    }
+}
+
+/// Reads HEAD, telling a symbolic HEAD on a branch from a detached one.
+pub fn read_head() -> Option<Head> {
+    match read_ref("HEAD")? {
+        RefValue::Symbolic(target) => Some(Head::Branch(target)),
+        RefValue::Direct(id) => Some(Head::Detached(id)),
+    }
+}
+
+/// The full name of the branch HEAD points to, or `None` if HEAD is detached.
+pub fn head_branch() -> Option<String> {
+    match read_head()? {
+        Head::Branch(name) => Some(name),
+        Head::Detached(_) => None,
+    }
+}
+
+/// Every ref under `prefix` (eg. "refs/heads/"), sorted by name.
+///
+/// Loose refs shadow packed refs of the same name.
+pub fn list_refs(prefix: &str) -> Vec<(String, ObjectId)> {
+    let mut refs = BTreeMap::new();
+
+    with_packed_refs(|packed_refs| {
+        for packed in packed_refs.iter().filter(|packed| packed.name.starts_with(prefix)) {
+            refs.insert(packed.name.clone(), packed.id);
+        }
+    });
+
+    let mut loose = Vec::new();
+    visit_loose_refs(&git_path("refs"), "refs", &mut loose);
+
+    for name in loose {
+        if !name.starts_with(prefix) {
+            continue;
+        }
+
+        if let Some(id) = resolve_ref(&name) {
+            refs.insert(name, id);
+        }
+    }
+
+    refs.into_iter().collect()
+}
+
+fn visit_loose_refs(dir: &Path, prefix: &str, refs: &mut Vec<String>) {
+    let Ok(contents) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in contents.flatten() {
+        let Ok(filename) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        let name = format!("{prefix}/{filename}");
+        let path = entry.path();
+
+        if path.is_dir() {
+            visit_loose_refs(&path, &name, refs);
+        } else if !filename.ends_with(".lock") {
+            refs.push(name);
+        }
+    }
+}
+
+// The order git tries when expanding a short ref name
+const REF_RULES: &[&str] = &[
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+    "refs/remotes/{}/HEAD",
+];
+
+/// Expands a short name like "main" or "origin/main" to the full name of
+/// the first ref it matches, as git does.
+pub fn expand_ref(short: &str) -> Option<String> {
+    ref_candidates(short).find(|name| read_ref(name).is_some())
+}
+
+/// The full names a short name could be, in the order they're tried
+pub fn ref_candidates(short: &str) -> impl Iterator<Item = String> + '_ {
+    REF_RULES.iter().map(move |rule| rule.replace("{}", short))
+}
+
+/// Whether `abbrev` names the full ref `full` by one of the rules
+/// `expand_ref` tries, giving the rule's position so the earliest match
+/// can be preferred
+pub fn refname_match(abbrev: &str, full: &str) -> Option<usize> {
+    REF_RULES.iter().position(|rule| rule.replace("{}", abbrev) == full)
+}
+
+/// Shortens a full ref name for display, eg. "refs/heads/main" -> "main"
+pub fn shorten_ref(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"].iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
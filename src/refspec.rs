@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::refs::{check_ref_format, ref_candidates, refname_match};
+use crate::store::ObjectId;
+
+/// Which way a refspec maps refs, which decides what its sides may be
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RefSpecKind {
+    Fetch,
+    Push,
+}
+
+/// One refspec, eg. "+refs/heads/*:refs/remotes/origin/*", mapping refs
+/// matching its source to its destination. A negative refspec, eg.
+/// "^refs/heads/wip/*", has only a source, and excludes the refs it
+/// matches from every other refspec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefSpec {
+    /// "+", update the destination even if it isn't a fast-forward
+    pub force: bool,
+
+    /// "^", exclude what the source matches
+    pub negative: bool,
+
+    /// Both sides have a '*', matching any sequence of characters
+    pub pattern: bool,
+
+    pub src: String,
+    pub dst: Option<String>,
+}
+
+/// A ref selected by a refspec, and where it goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefMapping {
+    pub src: String,
+    pub id: ObjectId,
+
+    /// None when the refspec has no destination, eg. fetching "main" just
+    /// to FETCH_HEAD
+    pub dst: Option<String>,
+    pub force: bool,
+}
+
+impl RefSpec {
+    /// Refspec format:
+    ///   [ '+' | '^' ] <src> [ ':' <dst> ]
+    ///
+    /// A '*' on one side must be matched by one on the other, and each
+    /// side can only have one. Negative refspecs can't be forced or have
+    /// a destination.
+    pub fn parse(spec: &str, kind: RefSpecKind) -> Option<RefSpec> {
+        let (force, negative, rest) = match spec.as_bytes().first() {
+            Some(b'+') => (true, false, &spec[1..]),
+            Some(b'^') => (false, true, &spec[1..]),
+            _ => (false, false, spec),
+        };
+
+        let (src, dst) = match rest.rsplit_once(':') {
+            Some((src, dst)) => (src, Some(dst)),
+            None => (rest, None),
+        };
+
+        if negative && (dst.is_some() || src.is_empty()) {
+            return None;
+        }
+
+        let globs = |side: &str| side.matches('*').count();
+        let src_globs = globs(src);
+        let dst_globs = dst.map_or(0, globs);
+
+        if src_globs > 1 || dst_globs > 1 {
+            return None;
+        }
+
+        let pattern = src_globs == 1;
+
+        // A pattern's destination needs a '*' to put what it matched in,
+        // and only a pattern has anything to put there
+        if dst.is_some_and(|dst| !dst.is_empty()) && pattern != (dst_globs == 1) {
+            return None;
+        }
+
+        let valid = |side: &str| side.is_empty() || check_ref_format(&side.replace('*', "x"));
+
+        // Anything that resolves to a commit can be pushed, so only
+        // patterns are checked on the source side of a push
+        let src_valid = match kind {
+            RefSpecKind::Fetch => valid(src),
+            RefSpecKind::Push => !pattern || valid(src),
+        };
+
+        if !src_valid || !dst.is_none_or(valid) {
+            return None;
+        }
+
+        // ":dst" deletes dst on push, but means nothing when fetching
+        if src.is_empty() && kind == RefSpecKind::Push && dst.is_none_or(str::is_empty) {
+            return None;
+        }
+
+        // Fetching nothing means fetching HEAD, pushing it deletes
+        let src = match (src, kind) {
+            ("", RefSpecKind::Fetch) => "HEAD",
+            (src, _) => src,
+        };
+
+        Some(RefSpec {
+            force,
+            negative,
+            pattern,
+            src: src.to_string(),
+            dst: dst.filter(|dst| !dst.is_empty()).map(str::to_string),
+        })
+    }
+
+    /// Does the source side match the full ref name `name`? Exact sources
+    /// may be abbreviated, eg. "main" matches "refs/heads/main".
+    pub fn matches(&self, name: &str) -> bool {
+        match self.pattern {
+            true => glob_capture(&self.src, name).is_some(),
+            false => refname_match(&self.src, name).is_some(),
+        }
+    }
+
+    /// Where the ref `name` goes, None if the refspec doesn't match it or
+    /// has no destination
+    pub fn dst_for(&self, name: &str) -> Option<String> {
+        let dst = self.dst.as_ref()?;
+
+        if !self.pattern {
+            return self.matches(name).then(|| dst.clone());
+        }
+
+        let captured = glob_capture(&self.src, name)?;
+
+        Some(dst.replacen('*', captured, 1))
+    }
+}
+
+/// What a '*' in `pattern` matched in `name`
+fn glob_capture<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = pattern.split_once('*')?;
+
+    if name.len() < prefix.len() + suffix.len() {
+        return None;
+    }
+
+    name.strip_prefix(prefix)?.strip_suffix(suffix)
+}
+
+/// The refspecs of a remote, or given on the command line, applied
+/// together: a ref is selected by any positive refspec which matches it,
+/// unless a negative one matches it too.
+#[derive(Debug, Clone, Default)]
+pub struct RefSpecs {
+    specs: Vec<RefSpec>,
+}
+
+impl RefSpecs {
+    pub fn parse<S: AsRef<str>>(specs: &[S], kind: RefSpecKind) -> Option<RefSpecs> {
+        let mut parsed = Vec::new();
+
+        for spec in specs {
+            let spec = spec.as_ref();
+
+            let Some(refspec) = RefSpec::parse(spec, kind) else {
+                eprintln!("invalid refspec '{spec}'");
+                return None;
+            };
+
+            parsed.push(refspec);
+        }
+
+        Some(RefSpecs { specs: parsed })
+    }
+
+    /// The refspecs configured for `remote`, `remote.<name>.fetch` or
+    /// `remote.<name>.push`
+    pub fn for_remote(config: &Config, remote: &str, kind: RefSpecKind) -> Option<RefSpecs> {
+        let key = match kind {
+            RefSpecKind::Fetch => format!("remote.{remote}.fetch"),
+            RefSpecKind::Push => format!("remote.{remote}.push"),
+        };
+
+        RefSpecs::parse(&config.get_all(&key), kind)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RefSpec> {
+        self.specs.iter()
+    }
+
+    /// What the names of the refs the refspecs could select start with,
+    /// so a remote can be asked to list only those
+    pub fn ref_prefixes(&self) -> Vec<String> {
+        let mut prefixes = Vec::new();
+
+        for spec in self.specs.iter().filter(|spec| !spec.negative) {
+            let candidates: Vec<String> = match spec.src.split_once('*') {
+                Some((prefix, _)) => vec![prefix.to_string()],
+                None => ref_candidates(&spec.src).collect(),
+            };
+
+            for prefix in candidates {
+                if !prefixes.contains(&prefix) {
+                    prefixes.push(prefix);
+                }
+            }
+        }
+
+        prefixes
+    }
+
+    /// Is `name` excluded by a negative refspec?
+    pub fn is_excluded(&self, name: &str) -> bool {
+        self.specs.iter()
+            .filter(|spec| spec.negative)
+            .any(|spec| spec.matches(name))
+    }
+
+    /// Selects from `refs`, as (full name, id), what the refspecs match,
+    /// in refspec order.
+    ///
+    /// Exact refspecs must match a ref. Two different refs mapping to the
+    /// same destination is an error, the same mapping twice is kept once.
+    pub fn map_refs(&self, refs: &[(String, ObjectId)]) -> Option<Vec<RefMapping>> {
+        let mut mappings: Vec<RefMapping> = Vec::new();
+        let mut by_dst: HashMap<String, String> = HashMap::new();
+
+        for spec in self.specs.iter().filter(|spec| !spec.negative) {
+            let matched: Vec<&(String, ObjectId)> = refs.iter()
+                .filter(|(name, _)| spec.matches(name))
+                .collect();
+
+            if matched.is_empty() && !spec.pattern {
+                eprintln!("couldn't find remote ref {}", spec.src);
+                return None;
+            }
+
+            // An abbreviated source only selects the first ref it
+            // expands to, as the rules are tried in order
+            let matched = match spec.pattern {
+                true => matched,
+                false => best_match(&spec.src, matched).into_iter().collect(),
+            };
+
+            for (name, id) in matched {
+                if self.is_excluded(name) {
+                    continue;
+                }
+
+                let dst = spec.dst_for(name);
+
+                if let Some(dst) = &dst {
+                    match by_dst.get(dst) {
+                        Some(src) if src == name => continue,
+                        Some(src) => {
+                            eprintln!("{dst} tracks both {src} and {name}");
+                            return None;
+                        }
+                        None => {
+                            by_dst.insert(dst.clone(), name.clone());
+                        }
+                    }
+                }
+
+                mappings.push(RefMapping {
+                    src: name.clone(),
+                    id: *id,
+                    dst,
+                    force: spec.force,
+                });
+            }
+        }
+
+        Some(mappings)
+    }
+}
+
+/// Of the refs an abbreviated name matches, the one the earliest rule
+/// expands it to
+fn best_match<'a>(abbrev: &str, refs: Vec<&'a (String, ObjectId)>) -> Option<&'a (String, ObjectId)> {
+    refs.into_iter().min_by_key(|(name, _)| refname_match(abbrev, name))
+}
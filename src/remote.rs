@@ -0,0 +1,126 @@
+use std::io;
+
+use crate::config::{self, Config};
+use crate::refs::check_ref_format;
+use crate::repo::git_path;
+
+/// A remote configured under `[remote "<name>"]`, with its URLs rewritten
+/// by `url.<base>.insteadOf` and `url.<base>.pushInsteadOf`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub name: String,
+
+    /// Where fetches come from, `remote.<name>.url`
+    pub urls: Vec<String>,
+
+    /// Where pushes go if not to `urls`: `remote.<name>.pushurl`, or
+    /// failing that any of `urls` a pushInsteadOf rewrites
+    pub push_urls: Vec<String>,
+}
+
+impl Remote {
+    /// The remote `name`, None if nothing is configured for it
+    pub fn get(config: &Config, name: &str) -> Option<Remote> {
+        let prefix = format!("remote.{name}.");
+
+        if !config.entries().any(|entry| entry.key.starts_with(&prefix)) {
+            return None;
+        }
+
+        let mut urls = config.get_all(&format!("remote.{name}.url"));
+
+        // A remote without a URL is fetched from as if its name were one
+        if urls.is_empty() {
+            urls.push(name);
+        }
+
+        let push_urls = config.get_all(&format!("remote.{name}.pushurl"));
+
+        // pushInsteadOf only applies when no push URL is given explicitly
+        let push_urls = match push_urls.is_empty() {
+            true => urls.iter()
+                .filter_map(|url| rewrite(config, url, "pushinsteadof"))
+                .collect(),
+            false => push_urls.iter()
+                .map(|url| rewrite_url(config, url))
+                .collect(),
+        };
+
+        Some(Remote {
+            name: name.to_string(),
+            urls: urls.iter().map(|url| rewrite_url(config, url)).collect(),
+            push_urls,
+        })
+    }
+
+    /// The URLs pushes go to
+    pub fn push_urls(&self) -> &[String] {
+        match self.push_urls.is_empty() {
+            true => &self.urls,
+            false => &self.push_urls,
+        }
+    }
+}
+
+/// The names of the remotes configured, sorted
+pub fn remote_names(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = config.entries()
+        .filter_map(|entry| entry.key.strip_prefix("remote.")?.rsplit_once('.'))
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Whether `name` can name a remote, which it can if its remote-tracking
+/// refs, "refs/remotes/<name>/...", would be valid refs
+pub fn is_valid_remote_name(name: &str) -> bool {
+    !name.is_empty() && check_ref_format(&format!("refs/remotes/{name}/test"))
+}
+
+/// Configures the remote `name` in the repository's config, fetching from
+/// `url` with its branches tracked as "refs/remotes/<name>/<branch>"
+pub fn add_remote(name: &str, url: &str) -> io::Result<()> {
+    let config_path = git_path("config");
+
+    config::set_value(&config_path, &format!("remote.{name}.url"), Some(url))?;
+    config::set_value(&config_path, &format!("remote.{name}.fetch"), Some(&format!("+refs/heads/*:refs/remotes/{name}/*")))
+}
+
+/// Applies `url.<base>.insteadOf` to `url`, eg. with
+///
+///   [url "git@github.com:"]
+///       insteadOf = gh:
+///
+/// "gh:owner/repo" becomes "git@github.com:owner/repo"
+pub fn rewrite_url(config: &Config, url: &str) -> String {
+    rewrite(config, url, "insteadof").unwrap_or_else(|| url.to_string())
+}
+
+/// Rewrites `url` by the longest `url.<base>.<variable>` value it starts
+/// with, None if there's none. Of equally long values the first wins.
+fn rewrite(config: &Config, url: &str, variable: &str) -> Option<String> {
+    let mut best: Option<(&str, &str)> = None;
+
+    for entry in config.entries() {
+        let Some((base, name)) = entry.key.strip_prefix("url.").and_then(|key| key.rsplit_once('.')) else {
+            continue;
+        };
+
+        let Some(prefix) = entry.value.as_deref() else {
+            continue;
+        };
+
+        if name != variable || !url.starts_with(prefix) {
+            continue;
+        }
+
+        if best.is_none_or(|(_, best)| prefix.len() > best.len()) {
+            best = Some((base, prefix));
+        }
+    }
+
+    best.map(|(base, prefix)| format!("{base}{}", &url[prefix.len()..]))
+}
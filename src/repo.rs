@@ -0,0 +1,149 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Where a repository's files live.
+///
+/// A linked worktree (`git worktree add`) has a git dir of its own, holding
+/// HEAD, the index and other per-worktree state, with a `commondir` file
+/// pointing at the main repository's git dir, which holds everything shared
+/// between worktrees: objects, refs, config and so on. For the main worktree
+/// both are the same directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoPaths {
+    pub git_dir: PathBuf,
+    pub common_dir: PathBuf,
+}
+
+// Paths in the common dir, as (path, is a directory). Anything not under
+// one of these, eg. HEAD and the index, is per-worktree.
+const COMMON_PATHS: &[(&str, bool)] = &[
+    ("branches", true),
+    ("common", true),
+    ("config", false),
+    ("hooks", true),
+    ("info", true),
+    ("logs", true),
+    ("lost-found", true),
+    ("objects", true),
+    ("packed-refs", false),
+    ("refs", true),
+    ("remotes", true),
+    ("rr-cache", true),
+    ("shallow", false),
+    ("worktrees", true),
+];
+
+// Exceptions to the above, which are per-worktree after all
+const PER_WORKTREE_PATHS: &[(&str, bool)] = &[
+    ("info/sparse-checkout", false),
+    ("logs/HEAD", false),
+    ("logs/refs/bisect", true),
+    ("logs/refs/rewritten", true),
+    ("logs/refs/worktree", true),
+    ("refs/bisect", true),
+    ("refs/rewritten", true),
+    ("refs/worktree", true),
+];
+
+// Discovery is cached, keyed by what it depends on
+type CacheKey = (Option<PathBuf>, Option<String>, Option<String>);
+static CACHE: Mutex<Option<(CacheKey, RepoPaths)>> = Mutex::new(None);
+
+impl RepoPaths {
+    /// Finds the repository for the current directory.
+    ///
+    /// `$GIT_DIR` is used if set, otherwise `.git`, which may be a
+    /// directory, a symlink to one, or a file containing "gitdir: <path>".
+    /// The common dir is `$GIT_COMMON_DIR` if set, otherwise the path in
+    /// the git dir's `commondir` file (relative to the git dir), otherwise
+    /// the git dir itself.
+    pub fn discover() -> RepoPaths {
+        let key = (
+            env::current_dir().ok(),
+            env::var("GIT_DIR").ok(),
+            env::var("GIT_COMMON_DIR").ok(),
+        );
+
+        let mut cache = CACHE.lock().unwrap_or_else(|err| err.into_inner());
+
+        if let Some((cached_key, paths)) = cache.as_ref() {
+            if *cached_key == key {
+                return paths.clone();
+            }
+        }
+
+        let git_dir = match &key.1 {
+            Some(git_dir) => PathBuf::from(git_dir),
+            None => read_gitfile(Path::new(".git")).unwrap_or_else(|| PathBuf::from(".git")),
+        };
+
+        let common_dir = match &key.2 {
+            Some(common_dir) => PathBuf::from(common_dir),
+            None => fs::read_to_string(git_dir.join("commondir"))
+                .ok()
+                .map(|contents| git_dir.join(contents.trim_end_matches(['\n', '\r'])))
+                .unwrap_or_else(|| git_dir.clone()),
+        };
+
+        let paths = RepoPaths { git_dir, common_dir };
+        *cache = Some((key, paths.clone()));
+
+        paths
+    }
+
+    /// Whether this is a linked worktree rather than the main one
+    pub fn is_linked_worktree(&self) -> bool {
+        self.git_dir != self.common_dir
+    }
+
+    /// The location of `path` (eg. "HEAD", "refs/heads/main", "objects"),
+    /// in the git dir or the common dir as appropriate
+    pub fn path<P: AsRef<str>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+
+        if is_common_path(path) {
+            self.common_dir.join(path)
+        } else {
+            self.git_dir.join(path)
+        }
+    }
+}
+
+/// Shorthand for `RepoPaths::discover().path(path)`
+pub fn git_path<P: AsRef<str>>(path: P) -> PathBuf {
+    RepoPaths::discover().path(path)
+}
+
+/// Does `path`, relative to a git dir, belong in the common dir?
+pub fn is_common_path(path: &str) -> bool {
+    let under = |&(prefix, is_dir): &(&str, bool)| match path.strip_prefix(prefix) {
+        Some("") => true,
+        Some(rest) => is_dir && rest.starts_with('/'),
+        None => false,
+    };
+
+    COMMON_PATHS.iter().any(under) && !PER_WORKTREE_PATHS.iter().any(under)
+}
+
+/// A ".git" file format:
+///   "gitdir: " <path> \n
+///
+/// The path is relative to the directory containing the file
+fn read_gitfile(path: &Path) -> Option<PathBuf> {
+    if !fs::metadata(path).ok()?.is_file() {
+        return None;
+    }
+
+    let contents = fs::read_to_string(path).ok()?;
+
+    let Some(git_dir) = contents.trim_end().strip_prefix("gitdir: ") else {
+        eprintln!("Invalid gitfile format: {}", path.display());
+        return None;
+    };
+
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    Some(base.join(git_dir))
+}
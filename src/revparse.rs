@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::date;
+use crate::metrics;
+use crate::refs;
+use crate::reflog::{self, ReflogSelector};
+use crate::store::{
+    util::resolve_id,
+    GitObjectData,
+    GitObjectStore,
+    ObjectId,
+};
+use crate::MIN_USER_HASH_LEN;
+
+/// Resolves a revision such as "HEAD", "main~2", "v1.0^2" or an
+/// abbreviated object id to the object it names.
+///
+/// Revision syntax:
+///   <base> [ <suffix> ]*
+///
+/// <base>:
+///   a ref name, expanded like git does ("main" -> "refs/heads/main"),
+///   or a (possibly abbreviated) hex object id, either of which may be
+///   followed by a reflog selector:
+///
+///   [ <ref> ] '@{' <n> '}'     the value of the ref n updates ago
+///   [ <ref> ] '@{' <date> '}'  the value of the ref at that date, eg.
+///                              "main@{1.hour.ago}"
+///
+///   Without a ref these look at the current branch's reflog.
+///
+/// <suffix>:
+///   '^' [ <n> ]  the n-th parent (default 1), '^0' is the commit itself
+///   '~' [ <n> ]  the n-th generation first parent ancestor (default 1)
+///   '^{}'        follows tags to the object they point at
+///   '^{<type>}'  follows tags (and commits, for trees) to a <type> object
+///
+/// A revision may be followed by ':' <path>, naming the tree entry at
+/// that path in the revision's tree, eg. "HEAD:src/main.rs".
+pub fn resolve(rev: &str) -> Option<ObjectId> {
+    if let Some((rev, path)) = split_path(rev).filter(|(rev, _)| !rev.is_empty()) {
+        return tree_path(peel_to_tree(resolve(rev)?)?, path);
+    }
+
+    let base_end = rev.find(['^', '~']).unwrap_or(rev.len());
+    let (base, mut suffixes) = rev.split_at(base_end);
+
+    let mut id = resolve_base(base)?;
+
+    while let Some(op) = suffixes.chars().next() {
+        suffixes = &suffixes[1..];
+
+        if op == '^' && suffixes.starts_with('{') {
+            let end = suffixes.find('}')?;
+            let kind = &suffixes[1..end];
+            suffixes = &suffixes[end + 1..];
+
+            id = peel_to_kind(id, kind)?;
+            continue;
+        }
+
+        let digits_end = suffixes.find(|c: char| !c.is_ascii_digit()).unwrap_or(suffixes.len());
+        let (digits, rest) = suffixes.split_at(digits_end);
+        suffixes = rest;
+
+        let n = if digits.is_empty() { 1 } else { digits.parse().ok()? };
+
+        id = match op {
+            '^' => nth_parent(id, n)?,
+            '~' => nth_ancestor(id, n)?,
+            _ => return None,
+        };
+    }
+
+    Some(id)
+}
+
+/// Why `rev` doesn't resolve if it's a path missing from a revision
+/// which does, "path '<path>' does not exist in '<rev>'", for commands to
+/// give rather than their usual complaint
+pub fn missing_path(rev: &str) -> Option<String> {
+    let (rev, path) = split_path(rev).filter(|(rev, _)| !rev.is_empty())?;
+    let tree = peel_to_tree(resolve(rev)?)?;
+
+    tree_path(tree, path).is_none().then(|| format!("path '{path}' does not exist in '{rev}'"))
+}
+
+/// Splits "<rev>:<path>" at the first ':' outside of braces, which may
+/// hold a date with a time in it
+fn split_path(rev: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+
+    for (i, c) in rev.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 => return Some((&rev[..i], &rev[i + 1..])),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn resolve_base(base: &str) -> Option<ObjectId> {
+    let base = if base == "@" { "HEAD" } else { base };
+
+    if let Some((name, selector)) = base.split_once("@{").filter(|_| base.ends_with('}')) {
+        let selector = &selector[..selector.len() - 1];
+
+        let Some(selector) = ReflogSelector::parse(selector, date::now()) else {
+            eprintln!("Unknown revision: {base}");
+            return None;
+        };
+
+        let Some(refname) = reflog::reflog_name(name) else {
+            eprintln!("Unknown revision: {base}");
+            return None;
+        };
+
+        return reflog::lookup_reflog(&refname, name, selector);
+    }
+
+    if let Some(name) = refs::expand_ref(base) {
+        return refs::resolve_ref(&name);
+    }
+
+    let looks_like_id = base.len() >= MIN_USER_HASH_LEN
+        && base.chars().all(|c| c.is_ascii_hexdigit());
+
+    if looks_like_id {
+        return resolve_id(base);
+    }
+
+    eprintln!("Unknown revision: {base}");
+    None
+}
+
+// What objects have been peeled to, which never changes
+static PEELED: Mutex<BTreeMap<ObjectId, ObjectId>> = Mutex::new(BTreeMap::new());
+
+/// Follows tags until reaching something which isn't a tag. What each
+/// object peels to is remembered, so it's only worked out once.
+pub fn peel(id: ObjectId) -> Option<ObjectId> {
+    if let Some(&peeled) = PEELED.lock().unwrap_or_else(|err| err.into_inner()).get(&id) {
+        metrics::count("gitty_peel_cache_hits_total", &[], 1);
+        return Some(peeled);
+    }
+
+    metrics::count("gitty_peel_cache_misses_total", &[], 1);
+
+    let peeled = match GitObjectStore::get(id)?.data {
+        GitObjectData::Tag { object, .. } => peel(object)?,
+        _ => id,
+    };
+
+    PEELED.lock().unwrap_or_else(|err| err.into_inner()).insert(id, peeled);
+
+    Some(peeled)
+}
+
+/// Peels `id` to an object of type `kind` as `<rev>^{<kind>}` does, an
+/// empty `kind` peels tags to whatever they point at.
+pub fn peel_to_kind(id: ObjectId, kind: &str) -> Option<ObjectId> {
+    let peeled = match kind {
+        "" => peel(id),
+        "object" => Some(id),
+        "commit" => peel_to_commit(id),
+        "tree" => peel_to_tree(id),
+        "tag" => Some(id).filter(|&id| has_type(id, "tag")),
+        "blob" => peel(id).filter(|&id| has_type(id, "blob")),
+        _ => {
+            eprintln!("Unknown object type in ^{{{kind}}}");
+            return None;
+        }
+    };
+
+    if peeled.is_none() {
+        eprintln!("Unable to peel {id} to a {}", if kind.is_empty() { "non-tag" } else { kind });
+    }
+
+    peeled
+}
+
+fn has_type(id: ObjectId, kind: &str) -> bool {
+    GitObjectStore::get(id).is_some_and(|object| object.type_str() == kind)
+}
+
+/// Follows tags until reaching a commit
+pub fn peel_to_commit(id: ObjectId) -> Option<ObjectId> {
+    match GitObjectStore::get(id)?.data {
+        GitObjectData::Commit { .. } => Some(id),
+        GitObjectData::Tag { object, .. } => peel_to_commit(object),
+        _ => None,
+    }
+}
+
+/// Follows tags and commits until reaching a tree
+pub fn peel_to_tree(id: ObjectId) -> Option<ObjectId> {
+    match GitObjectStore::get(id)?.data {
+        GitObjectData::Tree { .. } => Some(id),
+        GitObjectData::Commit { tree, .. } => Some(tree),
+        GitObjectData::Tag { object, .. } => peel_to_tree(object),
+        _ => None,
+    }
+}
+
+/// The entry at the slash separated `path` under `tree`, the tree itself
+/// for an empty path
+fn tree_path(tree: ObjectId, path: &str) -> Option<ObjectId> {
+    let mut id = tree;
+
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let GitObjectData::Tree { entries } = GitObjectStore::get(id)?.data else {
+            return None;
+        };
+
+        id = entries.iter().find(|entry| entry.path == name.as_bytes())?.id;
+    }
+
+    Some(id)
+}
+
+fn commit_parents(id: ObjectId) -> Option<Vec<ObjectId>> {
+    match GitObjectStore::get(peel_to_commit(id)?)?.data {
+        GitObjectData::Commit { parents, .. } => Some(parents),
+        _ => None,
+    }
+}
+
+fn nth_parent(id: ObjectId, n: usize) -> Option<ObjectId> {
+    if n == 0 {
+        return peel_to_commit(id);
+    }
+
+    commit_parents(id)?.get(n - 1).copied()
+}
+
+fn nth_ancestor(mut id: ObjectId, n: usize) -> Option<ObjectId> {
+    for _ in 0..n {
+        id = nth_parent(id, 1)?;
+    }
+
+    Some(id)
+}
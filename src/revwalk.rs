@@ -0,0 +1,764 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::diff::diff_trees_in;
+use crate::graph::Graph;
+use crate::pathspec::Pathspec;
+use crate::refs;
+use crate::revparse::{peel_to_commit, resolve};
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+use crate::wildmatch::{is_glob, wildmatch, WildmatchFlags};
+
+/// Walks the commits reachable from some starting points but not from
+/// others, newest first by committer date, as `git rev-list` does.
+pub struct RevWalk {
+    graph: Graph,
+
+    /// Commits waiting to be shown, as (commit time, insertion order, id).
+    /// Commits made in the same second come out in the order they went in.
+    queue: BinaryHeap<(u64, Reverse<u64>, ObjectId)>,
+    inserted: u64,
+    seen: HashSet<ObjectId>,
+
+    /// Commits reachable from a hidden one, which are never shown
+    uninteresting: HashSet<ObjectId>,
+
+    /// The hidden commits themselves, which still count when deciding
+    /// whether a commit is the same as its parents
+    bottoms: HashSet<ObjectId>,
+
+    /// With hidden commits the walk has to be done up front, as a commit
+    /// can turn out to be reachable from one after it would be shown
+    limited: Option<std::vec::IntoIter<ObjectId>>,
+
+    /// With `record_sources`, the name of the starting point each commit
+    /// was first reached from
+    sources: Option<HashMap<ObjectId, Rc<str>>>,
+
+    /// The annotated tags starting points were peeled through to their
+    /// commits, with their names, in the order they were met, and whether
+    /// they're hidden
+    tags: Vec<(ObjectId, String, bool)>,
+
+    /// With `limit_to_paths`, only commits changing these paths are shown
+    pathspec: Option<Pathspec>,
+    simplified: HashMap<ObjectId, Simplified>,
+
+    /// With `simplify_merges`, the full history is walked and merges which
+    /// bring nothing to the paths are removed from it afterwards
+    simplify_merges: bool,
+    merge_states: HashMap<ObjectId, MergeState>,
+}
+
+/// How a commit's history is simplified down to the commits which change
+/// the paths the walk is limited to
+#[derive(Debug, Clone)]
+struct Simplified {
+    /// Whether the commit changes the paths: it differs in them from every
+    /// parent, or a root commit has any of them. With merges simplified,
+    /// merges joining histories which change them are shown too.
+    changes_paths: bool,
+
+    /// The parents worth following. A commit which is the same as one of
+    /// its parents in the paths only follows that parent, as whatever
+    /// happened on the others didn't end up in the paths.
+    parents: Vec<ObjectId>,
+}
+
+/// A commit of the full history while merges are simplified
+struct MergeState {
+    /// The parents, each rewritten to what it simplifies to
+    parents: Vec<ObjectId>,
+
+    /// Whether the commit is the same as each parent in the paths
+    same: Vec<bool>,
+
+    /// Whether the commit is the same as its relevant parents, or as all of
+    /// them when none is relevant. Like git, this is decided as the commit
+    /// is walked, and parents found to be hidden later can only make it so.
+    treesame: bool,
+}
+
+impl Default for RevWalk {
+    fn default() -> Self {
+        RevWalk::new()
+    }
+}
+
+impl RevWalk {
+    pub fn new() -> RevWalk {
+        RevWalk {
+            graph: Graph::new(),
+            queue: BinaryHeap::new(),
+            inserted: 0,
+            seen: HashSet::new(),
+            uninteresting: HashSet::new(),
+            bottoms: HashSet::new(),
+            limited: None,
+            sources: None,
+            tags: Vec::new(),
+            pathspec: None,
+            simplified: HashMap::new(),
+            simplify_merges: false,
+            merge_states: HashMap::new(),
+        }
+    }
+
+    /// Only shows commits which change the paths `pathspec` matches,
+    /// simplifying history as `git log -- <path>` does by default: where a
+    /// merge has a parent which is the same in the paths, only that side
+    /// is walked.
+    pub fn limit_to_paths(&mut self, pathspec: Pathspec) {
+        if !pathspec.is_empty() {
+            self.pathspec = Some(pathspec);
+        }
+    }
+
+    /// Simplifies path limited history further, as `--simplify-merges`
+    /// does: rather than following only a parent a merge is the same as,
+    /// the full history is walked, then every parent is replaced with the
+    /// nearest commit it leads to which matters, and merges left with a
+    /// single parent which matters are dropped. Commits come out in
+    /// topological order.
+    pub fn simplify_merges(&mut self) {
+        self.simplify_merges = true;
+    }
+
+    /// The parents of a shown commit, rewritten to skip the commits which
+    /// aren't shown: each is the nearest shown (or hidden) ancestor along
+    /// the simplified history, and parents which lead nowhere are dropped.
+    pub fn parents(&mut self, id: ObjectId) -> Vec<ObjectId> {
+        let mut rewritten = Vec::new();
+
+        for mut parent in self.simplify(id).parents {
+            loop {
+                if self.uninteresting.contains(&parent) || self.simplify(parent).changes_paths {
+                    if !rewritten.contains(&parent) {
+                        rewritten.push(parent);
+                    }
+
+                    break;
+                }
+
+                // A commit which changes nothing follows a single parent,
+                // or none if it's a root
+                match self.simplify(parent).parents.first() {
+                    Some(&next) => parent = next,
+                    None => break,
+                }
+            }
+        }
+
+        rewritten
+    }
+
+    /// Works out, once, whether `id` changes the paths and which of its
+    /// parents to follow. Without paths every commit is shown and every
+    /// parent followed.
+    fn simplify(&mut self, id: ObjectId) -> Simplified {
+        if let Some(simplified) = self.simplified.get(&id) {
+            return simplified.clone();
+        }
+
+        let parents = self.graph.parents(id);
+
+        let Some(pathspec) = &self.pathspec else {
+            return Simplified { changes_paths: true, parents };
+        };
+
+        let tree = self.graph.tree(id);
+
+        let simplified = match parents.is_empty() {
+            true => Simplified {
+                changes_paths: diff_trees_in(None, tree, pathspec).is_none_or(|changes| !changes.is_empty()),
+                parents,
+            },
+            false => {
+                let same_as = parents.iter().copied().find(|&parent| {
+                    let parent_tree = self.graph.tree(parent);
+
+                    parent_tree == tree
+                        || diff_trees_in(parent_tree, tree, pathspec).is_some_and(|changes| changes.is_empty())
+                });
+
+                match same_as {
+                    Some(parent) => Simplified { changes_paths: false, parents: vec![parent] },
+                    None => Simplified { changes_paths: true, parents },
+                }
+            }
+        };
+
+        self.simplified.insert(id, simplified.clone());
+        simplified
+    }
+
+    /// Whether `id` is shown, once it's been walked past
+    fn is_shown(&mut self, id: ObjectId) -> bool {
+        !self.uninteresting.contains(&id) && self.simplify(id).changes_paths
+    }
+
+    /// Remembers which starting point each commit is reached from, for
+    /// `source`. Only starting points pushed afterwards are recorded.
+    pub fn record_sources(&mut self) {
+        self.sources.get_or_insert_with(HashMap::new);
+    }
+
+    /// The name of the starting point `id` was first reached from, as it
+    /// was pushed
+    pub fn source(&self, id: ObjectId) -> Option<&str> {
+        self.sources.as_ref()?.get(&id).map(|source| &**source)
+    }
+
+    /// The annotated tags met peeling the starting points shown to their
+    /// commits, each with the name it has in itself (eg. "v1.0"), which
+    /// `rev-list --objects` lists along with the commits. A tag which is
+    /// also a hidden starting point, or on the way to one, isn't.
+    pub fn tags(&self) -> impl Iterator<Item = (ObjectId, &str)> {
+        self.tags.iter()
+            .filter(|(id, _, _)| !self.tags.iter().any(|(other, _, hidden)| other == id && *hidden))
+            .map(|(id, name, _)| (*id, name.as_str()))
+    }
+
+    /// The commit a starting point is, or peels to, recording the tags on
+    /// the way
+    fn peel_start(&mut self, id: ObjectId, hidden: bool) -> Option<ObjectId> {
+        let mut id = id;
+
+        while let Some(GitObjectData::Tag { object, tag, .. }) = GitObjectStore::get(id).map(|object| object.data) {
+            self.tags.push((id, tag, hidden));
+            id = object;
+        }
+
+        peel_to_commit(id)
+    }
+
+    /// Shows `id` and what's reachable from it
+    pub fn push(&mut self, id: ObjectId) {
+        self.enqueue(id);
+    }
+
+    /// Shows `id` and what's reachable from it, which is said to be
+    /// reached from `name` unless an earlier starting point reaches it
+    pub fn push_named(&mut self, id: ObjectId, name: &str) {
+        if let Some(sources) = &mut self.sources {
+            sources.entry(id).or_insert_with(|| name.into());
+        }
+
+        self.enqueue(id);
+    }
+
+    /// Shows what's reachable from every ref, and HEAD, each named in full
+    /// (eg. "refs/heads/main"), as `--all` does
+    pub fn push_all(&mut self) {
+        for (name, id) in refs::list_refs("refs/") {
+            if let Some(commit) = self.peel_start(id, false) {
+                self.push_named(commit, &name);
+            }
+        }
+
+        if let Some(head) = refs::resolve_ref("HEAD").and_then(peel_to_commit) {
+            self.push_named(head, "HEAD");
+        }
+    }
+
+    /// Shows what's reachable from the refs under `prefix` (eg.
+    /// "refs/heads/"), each named without the prefix, as `--branches`,
+    /// `--tags` and `--remotes` do.
+    ///
+    /// With a pattern only the refs it matches are pushed, a pattern
+    /// without any glob syntax matching the refs below it, so "feature"
+    /// is "feature/*".
+    pub fn push_refs(&mut self, prefix: &str, pattern: Option<&str>) {
+        let pattern = pattern.map(|pattern| match is_glob(pattern) {
+            true => format!("{prefix}{pattern}"),
+            false => format!("{prefix}{}/*", pattern.trim_end_matches('/')),
+        });
+
+        for (name, id) in refs::list_refs(prefix) {
+            if pattern.as_ref().is_some_and(|pattern| !wildmatch(pattern, &name, WildmatchFlags::default())) {
+                continue;
+            }
+
+            if let Some(commit) = self.peel_start(id, false) {
+                self.push_named(commit, &name[prefix.len()..]);
+            }
+        }
+    }
+
+    /// Whether the walk found `id` to be reachable from a hidden commit
+    pub fn is_hidden(&self, id: ObjectId) -> bool {
+        self.uninteresting.contains(&id)
+    }
+
+    /// Hides `id` and what's reachable from it
+    pub fn hide(&mut self, id: ObjectId) {
+        self.uninteresting.insert(id);
+        self.bottoms.insert(id);
+
+        // Its parents are hidden straight away, as git does, which decides
+        // which parents count when simplifying merges
+        for parent in self.graph.parents(id) {
+            self.uninteresting.insert(parent);
+        }
+
+        self.enqueue(id);
+    }
+
+    /// Adds a revision range argument to the walk:
+    ///   <rev>           what's reachable from rev
+    ///   '^' <rev>       hides what's reachable from rev
+    ///   <a> '..' <b>    reachable from b but not a, either defaults to HEAD
+    ///   <a> '...' <b>   reachable from either but not both
+    ///
+    /// Shown revisions are named as they are given.
+    pub fn push_range(&mut self, arg: &str) -> Option<()> {
+        fn named(rev: &str) -> &str {
+            if rev.is_empty() { "HEAD" } else { rev }
+        }
+
+        let commit = |walk: &mut RevWalk, rev: &str, hidden: bool| -> Option<ObjectId> {
+            resolve(rev).and_then(|id| walk.peel_start(id, hidden)).or_else(|| {
+                eprintln!("bad revision '{rev}'");
+                None
+            })
+        };
+
+        if let Some((a, b)) = arg.split_once("...") {
+            let (a, b) = (named(a), named(b));
+            let (a_id, b_id) = (commit(self, a, false)?, commit(self, b, false)?);
+
+            for base in self.graph.merge_bases(a_id, b_id) {
+                self.hide(base);
+            }
+
+            self.push_named(a_id, a);
+            self.push_named(b_id, b);
+        } else if let Some((a, b)) = arg.split_once("..") {
+            let (a, b) = (named(a), named(b));
+
+            let a_id = commit(self, a, true)?;
+            self.hide(a_id);
+
+            let b_id = commit(self, b, false)?;
+            self.push_named(b_id, b);
+        } else if let Some(rev) = arg.strip_prefix('^') {
+            let id = commit(self, rev, true)?;
+            self.hide(id);
+        } else {
+            let id = commit(self, arg, false)?;
+            self.push_named(id, arg);
+        }
+
+        Some(())
+    }
+
+    fn enqueue(&mut self, id: ObjectId) {
+        if self.seen.insert(id) {
+            let time = self.graph.commit_time(id);
+            self.queue.push((time, Reverse(self.inserted), id));
+            self.inserted += 1;
+        }
+    }
+
+    /// Pops the newest queued commit and queues the parents worth
+    /// following, which are uninteresting if it is, and reached from
+    /// wherever it was. Hidden commits aren't simplified, so that all of
+    /// their ancestors are hidden too, and nor is anything when merges are
+    /// simplified, which needs the full history.
+    fn step(&mut self) -> Option<ObjectId> {
+        let (_, _, id) = self.queue.pop()?;
+        let uninteresting = self.uninteresting.contains(&id);
+
+        let parents = match uninteresting || self.simplify_merges {
+            true => self.graph.parents(id),
+            false => self.simplify(id).parents,
+        };
+
+        for parent in parents {
+            // Like git, the parent's parents are looked at as it's hidden
+            if uninteresting {
+                self.uninteresting.insert(parent);
+
+                for grandparent in self.graph.parents(parent) {
+                    self.mark_uninteresting(grandparent);
+                }
+            }
+
+            if let Some(sources) = &mut self.sources {
+                if let Some(source) = sources.get(&id).cloned() {
+                    sources.entry(parent).or_insert(source);
+                }
+            }
+
+            self.enqueue(parent);
+        }
+
+        Some(id)
+    }
+
+    /// Marks `id` uninteresting, along with any of its ancestors which have
+    /// already been reached
+    fn mark_uninteresting(&mut self, id: ObjectId) {
+        let mut stack = vec![id];
+
+        while let Some(id) = stack.pop() {
+            if !self.uninteresting.insert(id) || !self.seen.contains(&id) {
+                continue;
+            }
+
+            stack.extend(self.graph.parents(id));
+        }
+    }
+
+    /// Walks until only uninteresting commits are left to look at, and
+    /// none of them is newer than a commit which would be shown
+    fn limit(&mut self) -> Vec<ObjectId> {
+        let mut shown = Vec::new();
+        let mut oldest_shown = u64::MAX;
+
+        while let Some(id) = self.step() {
+            // Simplifying merges needs every commit of the full history
+            let kept = match self.simplify_merges {
+                true => !self.uninteresting.contains(&id),
+                false => self.is_shown(id),
+            };
+
+            if kept && self.simplify_merges && self.pathspec.is_some() {
+                let state = self.merge_state(id);
+                self.merge_states.insert(id, state);
+            }
+
+            if kept {
+                shown.push(id);
+                oldest_shown = oldest_shown.min(self.graph.commit_time(id));
+            }
+
+            let everybody_uninteresting = self.queue.iter()
+                .all(|(_, _, id)| self.uninteresting.contains(id));
+
+            let newest_queued = self.queue.peek().map_or(0, |(time, _, _)| *time);
+
+            if everybody_uninteresting && newest_queued < oldest_shown {
+                break;
+            }
+        }
+
+        shown.retain(|id| !self.uninteresting.contains(id));
+        shown
+    }
+
+    /// Sorts commits so each comes before its parents, as git's
+    /// `--topo-order` does: depth first from the commits without children,
+    /// taken in the order they're given
+    fn topo_sort(&mut self, commits: Vec<ObjectId>) -> Vec<ObjectId> {
+        // One more than the number of children still to be sorted, zero
+        // once the commit is
+        let mut indegree: HashMap<ObjectId, usize> = commits.iter().map(|&id| (id, 1)).collect();
+
+        for &id in &commits {
+            for parent in self.graph.parents(id) {
+                if let Some(count) = indegree.get_mut(&parent) {
+                    *count += 1;
+                }
+            }
+        }
+
+        let mut stack: Vec<ObjectId> = commits.iter().copied().filter(|id| indegree[id] == 1).collect();
+        stack.reverse();
+
+        let mut sorted = Vec::new();
+
+        while let Some(id) = stack.pop() {
+            for parent in self.graph.parents(id) {
+                let Some(count) = indegree.get_mut(&parent).filter(|count| **count > 0) else {
+                    continue;
+                };
+
+                *count -= 1;
+
+                if *count == 1 {
+                    stack.push(parent);
+                }
+            }
+
+            indegree.insert(id, 0);
+            sorted.push(id);
+        }
+
+        sorted
+    }
+
+    /// Whether a parent counts when deciding if a commit is the same as
+    /// its parents: it isn't hidden, or it's one of the hidden commits
+    /// themselves
+    fn is_relevant(&self, id: ObjectId) -> bool {
+        !self.uninteresting.contains(&id) || self.bottoms.contains(&id)
+    }
+
+    /// Are the trees the same in the paths?
+    fn same_in_paths(&self, a: Option<ObjectId>, b: Option<ObjectId>) -> bool {
+        let pathspec = self.pathspec.as_ref().expect("only compared when limited to paths");
+
+        a == b || diff_trees_in(a, b, pathspec).is_some_and(|changes| changes.is_empty())
+    }
+
+    /// Simplifies the full history in `commits` as git's simplify_merges,
+    /// returning the commits to show in topological order and recording
+    /// each one's simplified parents for `parents`.
+    ///
+    /// Each commit simplifies to itself if it changes the paths, or is a
+    /// root or a merge whose parents don't simplify to a single one which
+    /// matters, and otherwise to what that parent simplifies to. Parents
+    /// are replaced with what they simplify to, dropping duplicates,
+    /// those which are ancestors of others and roots without the paths.
+    fn merge_simplified(&mut self, commits: Vec<ObjectId>) -> Vec<ObjectId> {
+        let commits = self.topo_sort(commits);
+
+        if self.pathspec.is_none() {
+            return commits;
+        }
+
+        let mut states = std::mem::take(&mut self.merge_states);
+        states.retain(|id, _| !self.uninteresting.contains(id));
+
+        // Parents hidden since a merge was walked may make it the same as
+        // the rest
+        for state in states.values_mut() {
+            if !state.treesame && state.parents.len() > 1 {
+                state.treesame = self.treesame(&state.parents, &state.same);
+            }
+        }
+
+        // Commits are simplified oldest first, any whose parents haven't
+        // been yet going round again after them
+        let mut simplified = HashMap::new();
+        let mut todo: Vec<ObjectId> = commits.iter().rev().copied().collect();
+
+        while !todo.is_empty() {
+            let mut later = Vec::new();
+
+            for id in todo {
+                self.simplify_one(id, &mut states, &mut simplified, &mut later);
+            }
+
+            todo = later;
+        }
+
+        let kept: Vec<ObjectId> = commits.into_iter().filter(|id| simplified[id] == *id).collect();
+        let mut shown = Vec::new();
+
+        for id in kept {
+            let state = &states[&id];
+
+            // A merge the same as its parents is still shown to join up
+            // histories which change the paths
+            let relevant_parents = state.parents.iter().filter(|&&parent| self.is_relevant(parent)).count();
+            let changes_paths = !state.treesame || relevant_parents >= 2;
+
+            self.simplified.insert(id, Simplified { changes_paths, parents: state.parents.clone() });
+
+            if changes_paths {
+                shown.push(id);
+            }
+        }
+
+        shown
+    }
+
+    /// The state of a commit as it's walked, for simplifying merges
+    fn merge_state(&mut self, id: ObjectId) -> MergeState {
+        let parents = self.graph.parents(id);
+        let tree = self.graph.tree(id);
+
+        let parent_trees: Vec<Option<ObjectId>> = parents.iter().map(|&parent| self.graph.tree(parent)).collect();
+        let same: Vec<bool> = parent_trees.into_iter().map(|parent_tree| self.same_in_paths(parent_tree, tree)).collect();
+
+        let treesame = match parents.is_empty() {
+            true => self.same_in_paths(None, tree),
+            false => self.treesame(&parents, &same),
+        };
+
+        MergeState { parents, same, treesame }
+    }
+
+    /// Is a commit the same as its parents? Parents which aren't relevant
+    /// only count when none are.
+    fn treesame(&self, parents: &[ObjectId], same: &[bool]) -> bool {
+        let relevant: Vec<bool> = parents.iter().map(|&parent| self.is_relevant(parent)).collect();
+
+        match relevant.contains(&true) {
+            true => (0..parents.len()).filter(|&i| relevant[i]).all(|i| same[i]),
+            false => same.iter().all(|&same| same),
+        }
+    }
+
+    /// Simplifies `id` once all its parents are, otherwise queues them and
+    /// then it onto `later`
+    fn simplify_one(
+        &mut self,
+        id: ObjectId,
+        states: &mut HashMap<ObjectId, MergeState>,
+        simplified: &mut HashMap<ObjectId, ObjectId>,
+        later: &mut Vec<ObjectId>,
+    ) {
+        if simplified.contains_key(&id) {
+            return;
+        }
+
+        // Hidden commits and roots simplify to themselves
+        let Some(state) = states.get(&id).filter(|state| !state.parents.is_empty()) else {
+            simplified.insert(id, id);
+            return;
+        };
+
+        let pending: Vec<ObjectId> = state.parents.iter().copied().filter(|parent| !simplified.contains_key(parent)).collect();
+
+        if !pending.is_empty() {
+            later.extend(pending);
+            later.push(id);
+            return;
+        }
+
+        // Being the same as a parent, a commit is the same as what it
+        // simplifies to, so `same` stays right
+        let mut parents: Vec<ObjectId> = state.parents.iter().map(|parent| simplified[parent]).collect();
+        let mut same = state.same.clone();
+        let mut treesame = state.treesame;
+
+        let mut i = 0;
+
+        while i < parents.len() {
+            match parents[..i].contains(&parents[i]) {
+                true => self.remove_parent(id, &mut parents, &mut same, &mut treesame, i),
+                false => i += 1,
+            }
+        }
+
+        // A side branch which doesn't change the paths leaves the merge
+        // with a parent which is an ancestor of another, and an unrelated
+        // history without the paths leaves it with a root without them
+        if parents.len() > 1 {
+            let mut marked: Vec<bool> = parents.iter()
+                .map(|&parent| {
+                    let is_empty_root = states.get(&parent).is_some_and(|state| state.parents.is_empty() && state.treesame);
+
+                    is_empty_root || parents.iter().any(|&other| other != parent && self.reaches(other, parent, states))
+                })
+                .collect();
+
+            // Being the same only as parents to be removed, the first of
+            // them is kept though, as default simplification would have
+            // followed it
+            let first_same = (0..parents.len()).find(|&i| same[i]);
+
+            if let Some(first_same) = first_same.filter(|&i| marked[i]) {
+                if !(0..parents.len()).any(|i| same[i] && !marked[i]) {
+                    marked[first_same] = false;
+                }
+            }
+
+            let removed = marked.iter().filter(|&&marked| marked).count();
+
+            for i in (0..parents.len()).rev() {
+                if marked[i] {
+                    self.remove_parent(id, &mut parents, &mut same, &mut treesame, i);
+                }
+            }
+
+            // Removing parents can only make it the same as the rest
+            if removed > 0 && !treesame && parents.len() > 1 {
+                treesame = self.treesame(&parents, &same);
+            }
+        }
+
+        let one_relevant_parent = match parents.len() {
+            1 => Some(parents[0]),
+            _ => {
+                let mut relevant = parents.iter().copied().filter(|&parent| self.is_relevant(parent));
+                relevant.next().filter(|_| relevant.next().is_none())
+            }
+        };
+
+        let simplifies_to = match one_relevant_parent {
+            Some(parent) if treesame => simplified[&parent],
+            _ => id,
+        };
+
+        simplified.insert(id, simplifies_to);
+
+        let state = states.get_mut(&id).expect("state looked up above");
+        state.parents = parents;
+        state.same = same;
+        state.treesame = treesame;
+    }
+
+    /// Drops parent `i` of `id`, a commit left with one parent being the
+    /// same as it if it's the same as that one, and one left without any
+    /// if it's the same as the empty tree
+    fn remove_parent(&mut self, id: ObjectId, parents: &mut Vec<ObjectId>, same: &mut Vec<bool>, treesame: &mut bool, i: usize) {
+        parents.remove(i);
+        same.remove(i);
+
+        match parents.len() {
+            0 => {
+                let tree = self.graph.tree(id);
+                *treesame = self.same_in_paths(None, tree);
+            }
+            1 => *treesame = same[0],
+            _ => {},
+        }
+    }
+
+    /// Is `target` reachable from `from` in the history as simplified so
+    /// far?
+    fn reaches(&mut self, from: ObjectId, target: ObjectId, states: &HashMap<ObjectId, MergeState>) -> bool {
+        let mut stack = vec![from];
+        let mut visited = HashSet::new();
+
+        while let Some(id) = stack.pop() {
+            if id == target {
+                return true;
+            }
+
+            if !visited.insert(id) {
+                continue;
+            }
+
+            match states.get(&id) {
+                Some(state) => stack.extend(&state.parents),
+                None => stack.extend(self.graph.parents(id)),
+            }
+        }
+
+        false
+    }
+}
+
+impl Iterator for RevWalk {
+    type Item = ObjectId;
+
+    fn next(&mut self) -> Option<ObjectId> {
+        if self.uninteresting.is_empty() && !self.simplify_merges {
+            loop {
+                let id = self.step()?;
+
+                if self.is_shown(id) {
+                    return Some(id);
+                }
+            }
+        }
+
+        if self.limited.is_none() {
+            let mut commits = self.limit();
+
+            if self.simplify_merges {
+                commits = self.merge_simplified(commits);
+            }
+
+            self.limited = Some(commits.into_iter());
+        }
+
+        self.limited.as_mut()?.next()
+    }
+}
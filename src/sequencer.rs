@@ -0,0 +1,628 @@
+use std::fmt;
+use std::fs;
+use std::io;
+
+use crate::autosquash::{autosquash, parse_squash_subject, SquashKind};
+use crate::checkout::{checkout_tree, TreeFiles};
+use crate::config::Config;
+use crate::ident::{ident, IdentRole};
+use crate::merge::{merge_trees, record_conflicts, ConflictStyle, TreeMerge, TreeMergeOptions};
+use crate::message::{stripspace, subject};
+use crate::refs::{self, RefTransaction};
+use crate::reflog::ReflogEntry;
+use crate::repo::git_path;
+use crate::store::index::Index;
+use crate::store::object::serialize_commit;
+use crate::store::tree::{flatten_tree, TreeBuilder};
+use crate::store::util::resolve_id;
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+
+/// Where the state of an operation in progress is kept
+const STATE_DIR: &str = "sequencer";
+
+/// What an instruction does with its commit
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Makes the commit's changes again on HEAD, with its message and author
+    Pick,
+
+    /// Undoes the commit's changes, as a new commit
+    Revert,
+
+    /// Squashes the commit's changes into HEAD, combining the messages
+    Squash,
+
+    /// Squashes the commit's changes into HEAD, keeping HEAD's message
+    Fixup,
+
+    /// Squashes the commit's changes into HEAD, replacing HEAD's message
+    /// with the commit's, "fixup -C" in a todo list
+    Amend,
+
+    /// Leaves the commit out
+    Drop,
+}
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Pick => "pick",
+            Action::Revert => "revert",
+            Action::Squash => "squash",
+            Action::Fixup => "fixup",
+            Action::Amend => "fixup -C",
+            Action::Drop => "drop",
+        }
+    }
+
+    /// Whether the action changes HEAD rather than making a commit on it
+    fn squashes(self) -> bool {
+        matches!(self, Action::Squash | Action::Fixup | Action::Amend)
+    }
+}
+
+impl From<SquashKind> for Action {
+    fn from(kind: SquashKind) -> Action {
+        match kind {
+            SquashKind::Fixup => Action::Fixup,
+            SquashKind::Amend => Action::Amend,
+            SquashKind::Squash => Action::Squash,
+        }
+    }
+}
+
+/// One line of a todo list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub action: Action,
+    pub commit: ObjectId,
+
+    /// The commit's subject, only there for whoever reads the list
+    pub subject: String,
+}
+
+impl Instruction {
+    /// An instruction to do `action` with `commit`
+    pub fn new(action: Action, commit: ObjectId) -> Option<Instruction> {
+        let subject = match GitObjectStore::get(commit)?.data {
+            GitObjectData::Commit { message, .. } => subject(&message),
+            _ => {
+                eprintln!("{commit} is not a commit");
+                return None;
+            }
+        };
+
+        Some(Instruction { action, commit, subject })
+    }
+
+    /// Instruction format:
+    ///   <action> ' ' <commit> [ ' ' <subject> ]
+    ///
+    /// eg. "pick 3f2a1b9 Add x". Actions may be given by their first
+    /// letter, as git allows, and commits by an abbreviated id.
+    pub fn parse(line: &str) -> Option<Instruction> {
+        let (word, rest) = line.trim().split_once(char::is_whitespace).unwrap_or((line.trim(), ""));
+
+        let mut action = match word {
+            "pick" | "p" => Action::Pick,
+            "revert" => Action::Revert,
+            "squash" | "s" => Action::Squash,
+            "fixup" | "f" => Action::Fixup,
+            "drop" | "d" => Action::Drop,
+            _ => return None,
+        };
+
+        let mut rest = rest.trim_start();
+
+        if action == Action::Fixup {
+            if let Some(after) = rest.strip_prefix("-C ") {
+                action = Action::Amend;
+                rest = after.trim_start();
+            }
+        }
+
+        let (id, subject) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+        let commit = match ObjectId::try_from(id.to_string()) {
+            Ok(commit) => commit,
+            Err(_) => resolve_id(id)?,
+        };
+
+        Some(Instruction { action, commit, subject: subject.trim().to_string() })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.action.name(), self.commit, self.subject)
+    }
+}
+
+/// Parses a todo list, one instruction per line. Blank lines and lines
+/// starting with '#' are skipped.
+pub fn parse_todo(todo: &str) -> Result<Vec<Instruction>, String> {
+    todo.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(n, line)| Instruction::parse(line).ok_or_else(|| format!("invalid line {}: {line}", n + 1)))
+        .collect()
+}
+
+/// Writes `instructions` out in the format `parse_todo` reads
+pub fn format_todo(instructions: &[Instruction]) -> String {
+    instructions.iter()
+        .map(|instruction| format!("{instruction}\n"))
+        .collect()
+}
+
+/// A todo list doing `action` with each of `commits`, in order
+pub fn todo(action: Action, commits: &[ObjectId]) -> Option<Vec<Instruction>> {
+    commits.iter()
+        .map(|&commit| Instruction::new(action, commit))
+        .collect()
+}
+
+/// A todo list picking `commits`, oldest first, with those squashing into
+/// earlier ones moved after them, as `rebase --autosquash` does
+pub fn autosquash_todo(commits: &[ObjectId]) -> Option<Vec<Instruction>> {
+    let picks = todo(Action::Pick, commits)?;
+
+    let subjects: Vec<(ObjectId, String)> = picks.iter()
+        .map(|pick| (pick.commit, pick.subject.clone()))
+        .collect();
+
+    let instructions = autosquash(&subjects).into_iter()
+        .map(|(commit, kind)| {
+            let pick = picks.iter().find(|pick| pick.commit == commit).expect("autosquash only reorders");
+
+            Instruction {
+                action: kind.map_or(Action::Pick, Action::from),
+                commit,
+                subject: pick.subject.clone(),
+            }
+        })
+        .collect();
+
+    Some(instructions)
+}
+
+/// Where a run of the sequencer got to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Progress {
+    /// Every instruction has been carried out and the state removed
+    Done,
+
+    /// `instruction` changes paths HEAD has changed too in ways which
+    /// couldn't be merged, which were left in the worktree with conflict
+    /// markers and conflicted in the index. The rest of its changes are in
+    /// the index and worktree, to be finished off before continuing.
+    Stopped {
+        instruction: Instruction,
+        conflicts: Vec<Vec<u8>>,
+    },
+}
+
+/// Carries out a todo list of picks, reverts and squashes one commit at a
+/// time, as cherry-pick, revert and rebase do, keeping its state in
+/// `.git/sequencer` so the operation can be continued or aborted later,
+/// by another process if need be.
+///
+/// Each instruction's changes are merged into HEAD as `merge` merges
+/// trees, the sequencer stopping where they conflict to have them
+/// resolved by hand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequencer {
+    /// What's left to do, next first
+    pub todo: Vec<Instruction>,
+
+    /// What's been done, including the instruction stopped at
+    pub done: Vec<Instruction>,
+
+    /// The instruction stopped at, waiting to be finished off
+    pub stopped: Option<Instruction>,
+
+    /// Where HEAD was when the operation started, for aborting
+    pub orig_head: ObjectId,
+
+    /// Where HEAD was last left, HEAD having moved since meaning it's
+    /// been committed to by hand
+    pub last_head: ObjectId,
+
+    /// What updates are logged as, eg. "cherry-pick"
+    pub reflog_action: String,
+}
+
+/// Whether a sequencer operation is in progress
+pub fn in_progress() -> bool {
+    git_path(STATE_DIR).is_dir()
+}
+
+impl Sequencer {
+    /// Starts carrying out `todo` from HEAD, saving the state. Nothing is
+    /// done until `run`.
+    pub fn start(todo: Vec<Instruction>, reflog_action: &str) -> io::Result<Sequencer> {
+        if in_progress() {
+            return Err(io::Error::other("a cherry-pick, revert or rebase is already in progress"));
+        }
+
+        let head = refs::resolve_ref("HEAD").ok_or_else(|| io::Error::other("cannot sequence commits on an unborn branch"))?;
+
+        let sequencer = Sequencer {
+            todo,
+            done: Vec::new(),
+            stopped: None,
+            orig_head: head,
+            last_head: head,
+            reflog_action: reflog_action.to_string(),
+        };
+
+        fs::create_dir(git_path(STATE_DIR))?;
+        sequencer.save()?;
+
+        Ok(sequencer)
+    }
+
+    /// The operation in progress, `None` if there isn't one
+    pub fn load() -> io::Result<Option<Sequencer>> {
+        if !in_progress() {
+            return Ok(None);
+        }
+
+        let read = |name: &str| fs::read_to_string(git_path(format!("{STATE_DIR}/{name}")));
+
+        let read_id = |name: &str| -> io::Result<ObjectId> {
+            ObjectId::try_from(read(name)?.trim().to_string())
+                .map_err(|_| io::Error::other(format!("invalid {STATE_DIR}/{name}")))
+        };
+
+        let read_todo = |name: &str| -> io::Result<Vec<Instruction>> {
+            parse_todo(&read(name)?).map_err(|err| io::Error::other(format!("{STATE_DIR}/{name}: {err}")))
+        };
+
+        let stopped = match read("stopped") {
+            Ok(line) => Some(Instruction::parse(&line).ok_or_else(|| io::Error::other(format!("invalid {STATE_DIR}/stopped")))?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        Ok(Some(Sequencer {
+            todo: read_todo("todo")?,
+            done: read_todo("done")?,
+            stopped,
+            orig_head: read_id("head")?,
+            last_head: read_id("abort-safety")?,
+            reflog_action: read("reflog-action")?.trim().to_string(),
+        }))
+    }
+
+    /// Writes out the state, for `load`
+    pub fn save(&self) -> io::Result<()> {
+        let write = |name: &str, contents: String| fs::write(git_path(format!("{STATE_DIR}/{name}")), contents);
+
+        write("todo", format_todo(&self.todo))?;
+        write("done", format_todo(&self.done))?;
+        write("head", format!("{}\n", self.orig_head))?;
+        write("abort-safety", format!("{}\n", self.last_head))?;
+        write("reflog-action", format!("{}\n", self.reflog_action))?;
+
+        let stopped = git_path(format!("{STATE_DIR}/stopped"));
+
+        match &self.stopped {
+            Some(instruction) => fs::write(stopped, format!("{instruction}\n")),
+            None => match fs::remove_file(stopped) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    /// Carries out the instructions left, saving the state after each,
+    /// until they're all done or one has to be finished off by hand
+    pub fn run(&mut self) -> io::Result<Progress> {
+        if let Some(instruction) = &self.stopped {
+            return Err(io::Error::other(format!("stopped at {}, which needs to be continued or skipped", short(instruction.commit))));
+        }
+
+        while !self.todo.is_empty() {
+            let instruction = self.todo.remove(0);
+            self.done.push(instruction.clone());
+
+            let conflicts = self.apply(&instruction)?;
+
+            if !conflicts.is_empty() {
+                self.stopped = Some(instruction.clone());
+                self.save()?;
+
+                return Ok(Progress::Stopped { instruction, conflicts });
+            }
+
+            self.save()?;
+        }
+
+        self.finish()?;
+
+        Ok(Progress::Done)
+    }
+
+    /// Finishes off the instruction stopped at with what's in the index,
+    /// then carries on. If HEAD's been committed to since stopping, that's
+    /// taken to have finished it off.
+    pub fn resume(&mut self) -> io::Result<Progress> {
+        if let Some(instruction) = self.stopped.clone() {
+            let head = head()?;
+
+            if head == self.last_head {
+                let index = Index::read().ok_or_else(|| io::Error::other("unable to read the index"))?;
+                let tree = index.write_tree(false).ok_or_else(|| io::Error::other("you need to resolve your current index first"))?;
+
+                if tree == read_commit(head)?.tree && !instruction.action.squashes() {
+                    return Err(io::Error::other(format!("{} is now empty, skip it to go on", short(instruction.commit))));
+                }
+
+                // The message may have been edited while stopped
+                let message = match fs::read(git_path("MERGE_MSG")) {
+                    Ok(message) => message,
+                    Err(_) => self.message(&instruction, head)?,
+                };
+
+                self.commit(&instruction, head, tree, &message)?;
+            }
+
+            let _ = fs::remove_file(git_path("MERGE_MSG"));
+
+            self.stopped = None;
+            self.last_head = refs::resolve_ref("HEAD").unwrap_or(self.last_head);
+            self.save()?;
+        }
+
+        self.run()
+    }
+
+    /// Gives up on the instruction stopped at, resetting the index and
+    /// worktree to HEAD, then carries on
+    pub fn skip(&mut self) -> io::Result<Progress> {
+        if self.stopped.is_some() {
+            let head_files = commit_files(head()?)?;
+
+            checkout_tree(&head_files, &head_files, true).ok_or_else(|| io::Error::other("unable to reset to HEAD"))?;
+
+            let _ = fs::remove_file(git_path("MERGE_MSG"));
+
+            self.stopped = None;
+            self.save()?;
+        }
+
+        self.run()
+    }
+
+    /// Gives up on the operation, putting HEAD, the index and worktree
+    /// back as they were when it started.
+    ///
+    /// HEAD having been moved by hand since the sequencer last left it,
+    /// it's left where it is, as git does.
+    pub fn abort(self) -> io::Result<()> {
+        let head = head()?;
+
+        if head != self.last_head {
+            eprintln!("warning: You seem to have moved HEAD. Not rewinding, check your HEAD!");
+            return self.finish();
+        }
+
+        let head_files = commit_files(head)?;
+        let orig_files = commit_files(self.orig_head)?;
+
+        checkout_tree(&head_files, &orig_files, true).ok_or_else(|| io::Error::other(format!("unable to reset to {}", short(self.orig_head))))?;
+
+        self.update_head(head, self.orig_head, format!("{}: aborting", self.reflog_action))?;
+
+        let _ = fs::remove_file(git_path("MERGE_MSG"));
+
+        self.finish()
+    }
+
+    /// Removes the state, the operation being over
+    fn finish(&self) -> io::Result<()> {
+        fs::remove_dir_all(git_path(STATE_DIR))
+    }
+
+    /// Carries out `instruction` on HEAD, returning the paths it couldn't
+    /// merge, leaving it to be finished off by hand if there are any
+    fn apply(&mut self, instruction: &Instruction) -> io::Result<Vec<Vec<u8>>> {
+        if instruction.action == Action::Drop {
+            return Ok(Vec::new());
+        }
+
+        if instruction.action.squashes() && self.done.len() < 2 {
+            return Err(io::Error::other(format!("cannot '{}' without a previous commit", instruction.action.name())));
+        }
+
+        let head = head()?;
+        let parents = read_commit(instruction.commit)?.parents;
+
+        if parents.len() > 1 {
+            return Err(io::Error::other(format!("commit {} is a merge, which can't be {} yet", instruction.commit, match instruction.action {
+                Action::Revert => "reverted",
+                _ => "picked",
+            })));
+        }
+
+        let parent_files = match parents.first() {
+            Some(&parent) => commit_files(parent)?,
+            None => TreeFiles::new(),
+        };
+
+        let picked_files = commit_files(instruction.commit)?;
+
+        let (base, theirs) = match instruction.action {
+            Action::Revert => (picked_files, parent_files),
+            _ => (parent_files, picked_files),
+        };
+
+        // Labelled as git labels them, the picked commit and its parent
+        let picked = format!("{}... {}", short(instruction.commit), instruction.subject);
+        let parent = format!("parent of {picked}");
+
+        let (base_label, theirs_label) = match instruction.action {
+            Action::Revert => (&picked, &parent),
+            _ => (&parent, &picked),
+        };
+
+        let style = ConflictStyle::from_config(&Config::load()).ok_or_else(|| io::Error::other("bad merge.conflictStyle"))?;
+        let options = TreeMergeOptions { ours_label: "HEAD", theirs_label, base_label, style };
+
+        let could_not_apply = || io::Error::other(format!("could not apply {picked}"));
+
+        let ours = commit_files(head)?;
+        let TreeMerge { files: merged, conflicts, .. } = merge_trees(&base, &ours, &theirs, &options).ok_or_else(could_not_apply)?;
+
+        checkout_tree(&ours, &merged, false).ok_or_else(could_not_apply)?;
+
+        let message = self.message(instruction, head)?;
+
+        if !conflicts.is_empty() {
+            let mut index = Index::read().ok_or_else(|| io::Error::other("unable to read the index"))?;
+            record_conflicts(&mut index, &conflicts);
+            index.write()?;
+
+            fs::write(git_path("MERGE_MSG"), &message)?;
+            return Ok(conflicts.into_iter().map(|conflict| conflict.path).collect());
+        }
+
+        let tree = write_tree(&merged)?;
+
+        // Changes already made upstream are left out, as rebase does
+        if tree == read_commit(head)?.tree && !instruction.action.squashes() {
+            return Ok(Vec::new());
+        }
+
+        self.commit(instruction, head, tree, &message)?;
+
+        Ok(Vec::new())
+    }
+
+    /// The message of the commit `instruction` makes on `head`
+    fn message(&self, instruction: &Instruction, head: ObjectId) -> io::Result<Vec<u8>> {
+        let message = read_commit(instruction.commit)?.message;
+
+        Ok(match instruction.action {
+            Action::Revert => {
+                format!("Revert \"{}\"\n\nThis reverts commit {}.\n", subject(&message), instruction.commit).into_bytes()
+            }
+            Action::Fixup => read_commit(head)?.message,
+            Action::Amend => {
+                // Past the "amend! <subject>" naming what it replaces
+                let body = match parse_squash_subject(&subject(&message)) {
+                    Some((SquashKind::Amend, _)) => message.splitn(2, |&byte| byte == b'\n').nth(1).unwrap_or_default(),
+                    _ => &message,
+                };
+
+                stripspace(body, None)
+            }
+            Action::Squash => {
+                // A "squash! <subject>" line only says where it goes
+                let body = match parse_squash_subject(&subject(&message)) {
+                    Some(_) => message.splitn(2, |&byte| byte == b'\n').nth(1).unwrap_or_default(),
+                    None => &message,
+                };
+
+                let mut combined = read_commit(head)?.message;
+                combined.extend_from_slice(b"\n\n");
+                combined.extend_from_slice(body);
+
+                stripspace(&combined, None)
+            }
+            Action::Pick | Action::Drop => message,
+        })
+    }
+
+    /// Commits `tree` for `instruction` on `head`, or in place of it when
+    /// squashing
+    fn commit(&mut self, instruction: &Instruction, head: ObjectId, tree: ObjectId, message: &[u8]) -> io::Result<()> {
+        let config = Config::load();
+
+        let committer = ident(IdentRole::Committer, &config)
+            .ok_or_else(|| io::Error::other("unable to determine the committer"))?
+            .to_string();
+
+        let (parents, author) = match instruction.action {
+            Action::Revert => {
+                let author = ident(IdentRole::Author, &config)
+                    .ok_or_else(|| io::Error::other("unable to determine the author"))?;
+
+                (vec![head], author.to_string())
+            }
+            action if action.squashes() => (read_commit(head)?.parents, read_commit(head)?.author),
+            _ => (vec![head], read_commit(instruction.commit)?.author),
+        };
+
+        let data = serialize_commit(&tree, &parents, &author, &committer, None, None, message);
+        let commit = GitObjectStore::write("commit", &data).ok_or_else(|| io::Error::other("unable to write commit"))?;
+
+        let how = match instruction.action {
+            Action::Pick | Action::Revert => self.reflog_action.clone(),
+            action => format!("{} ({})", self.reflog_action, action.name()),
+        };
+
+        self.update_head(head, commit, format!("{how}: {}", subject(message)))?;
+        self.last_head = commit;
+
+        Ok(())
+    }
+
+    /// Moves HEAD, or the branch it's on, from `old` to `new`
+    fn update_head(&self, old: ObjectId, new: ObjectId, message: String) -> io::Result<()> {
+        let committer = ident(IdentRole::Committer, &Config::load())
+            .ok_or_else(|| io::Error::other("unable to determine the committer"))?;
+
+        let target = refs::head_branch().unwrap_or_else(|| "HEAD".to_string());
+
+        let mut transaction = RefTransaction::new();
+
+        transaction.update(&target, new, Some(old)).reflog = Some(ReflogEntry {
+            old,
+            new,
+            committer: committer.to_string(),
+            message,
+        });
+
+        transaction.commit()
+    }
+}
+
+fn short(id: ObjectId) -> String {
+    id.to_string()[..7].to_string()
+}
+
+fn head() -> io::Result<ObjectId> {
+    refs::resolve_ref("HEAD").ok_or_else(|| io::Error::other("unable to resolve HEAD"))
+}
+
+/// The parts of a commit the sequencer needs
+struct CommitInfo {
+    tree: ObjectId,
+    parents: Vec<ObjectId>,
+    author: String,
+    message: Vec<u8>,
+}
+
+fn read_commit(commit: ObjectId) -> io::Result<CommitInfo> {
+    match GitObjectStore::get(commit).map(|object| object.data) {
+        Some(GitObjectData::Commit { tree, parents, author, message, .. }) => Ok(CommitInfo { tree, parents, author, message }),
+        _ => Err(io::Error::other(format!("unable to read commit {commit}"))),
+    }
+}
+
+fn commit_files(commit: ObjectId) -> io::Result<TreeFiles> {
+    flatten_tree(read_commit(commit)?.tree).ok_or_else(|| io::Error::other(format!("unable to read the tree of {commit}")))
+}
+
+fn write_tree(files: &TreeFiles) -> io::Result<ObjectId> {
+    let mut builder = TreeBuilder::new();
+
+    for (path, &(mode, id)) in files {
+        builder.insert(mode, path, id)
+            .ok_or_else(|| io::Error::other(format!("unable to write '{}' to a tree", String::from_utf8_lossy(path))))?;
+    }
+
+    builder.write().ok_or_else(|| io::Error::other("unable to write tree"))
+}
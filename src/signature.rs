@@ -0,0 +1,69 @@
+use std::io;
+
+use crate::config::Config;
+use crate::push_cert::{verify_signature, SIGNATURE_START};
+use crate::store::object::{serialize_commit, serialize_tag};
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+
+/// What gpg made of an object's signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureCheck {
+    /// As for `CertCheck::status`: 'G' good, 'U' good but untrusted, 'B'
+    /// bad, 'X' expired, 'Y' by an expired key, 'R' by a revoked key, 'E'
+    /// unverifiable, or 'N' unsigned
+    pub result: char,
+
+    pub signer: Option<String>,
+    pub key: Option<String>,
+}
+
+impl SignatureCheck {
+    fn unsigned() -> SignatureCheck {
+        SignatureCheck { result: 'N', signer: None, key: None }
+    }
+}
+
+/// Checks the signature in the commit `id`'s gpgsig header, which is over
+/// the commit as it would be without it
+pub fn check_commit_signature(config: &Config, id: ObjectId) -> io::Result<SignatureCheck> {
+    let Some(GitObjectData::Commit { tree, parents, author, committer, encoding, gpgsig, message }) =
+        GitObjectStore::get(id).map(|object| object.data)
+    else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("could not read commit {id}")));
+    };
+
+    let Some(signature) = gpgsig else {
+        return Ok(SignatureCheck::unsigned());
+    };
+
+    let payload = serialize_commit(&tree, &parents, &author, &committer, encoding.as_deref(), None, &message);
+
+    check(config, &payload, &signature)
+}
+
+/// Checks the signature at the end of the tag `id`'s message, which is
+/// over the tag up to where it starts
+pub fn check_tag_signature(config: &Config, id: ObjectId) -> io::Result<SignatureCheck> {
+    let Some(GitObjectData::Tag { object, kind, tag, tagger, message }) =
+        GitObjectStore::get(id).map(|object| object.data)
+    else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("could not read tag {id}")));
+    };
+
+    let start = (0..message.len())
+        .filter(|&pos| pos == 0 || message[pos - 1] == b'\n')
+        .find(|&pos| message[pos..].starts_with(SIGNATURE_START.as_bytes()));
+
+    let Some(start) = start else {
+        return Ok(SignatureCheck::unsigned());
+    };
+
+    let payload = serialize_tag(&object, &kind, &tag, tagger.as_deref(), &message[..start]);
+
+    check(config, &payload, &String::from_utf8_lossy(&message[start..]))
+}
+
+fn check(config: &Config, payload: &[u8], signature: &str) -> io::Result<SignatureCheck> {
+    let (result, signer, key) = verify_signature(config, payload, signature)?;
+    Ok(SignatureCheck { result, signer, key })
+}
@@ -0,0 +1,320 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+
+use crate::checkout::{checkout_tree, remove_worktree_file, write_worktree_file, TreeFiles};
+use crate::config::Config;
+use crate::ident::{ident, IdentRole};
+use crate::ignore::IgnoreRules;
+use crate::message::commit_subject;
+use crate::pathspec::Pathspec;
+use crate::refs;
+use crate::reflog::{append_reflog, ReflogEntry};
+use crate::revparse::peel_to_tree;
+use crate::status::{index_mtime, worktree_change, FileStatus};
+use crate::store::index::{Index, IndexEntry};
+use crate::store::object::serialize_commit;
+use crate::store::tree::{flatten_tree, TreeBuilder};
+use crate::store::{GitObjectStore, ObjectId};
+use crate::worktree;
+
+/// What a stash sets aside, and what it leaves behind
+#[derive(Debug, Clone, Default)]
+pub struct StashOptions {
+    /// Leave what's staged in the index and the worktree
+    pub keep_index: bool,
+
+    /// Stash untracked files too, those which aren't ignored, and remove
+    /// them
+    pub include_untracked: bool,
+
+    /// Only stash the paths this matches, leaving the rest as they are
+    pub pathspec: Pathspec,
+
+    /// Describes the stash as "On <branch>: <message>", rather than
+    /// "WIP on <branch>: <HEAD's abbreviated id and subject>"
+    pub message: Option<String>,
+}
+
+/// A stash commit, as `create_stash` makes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stash {
+    pub id: ObjectId,
+
+    /// The first line of its message, eg. "WIP on main: 1234567 Subject"
+    pub description: String,
+}
+
+/// The trees a stash is made from
+struct StashTrees {
+    head: ObjectId,
+    head_files: TreeFiles,
+    index_files: TreeFiles,
+
+    /// The index with the changes to the stashed paths in the worktree
+    work_files: TreeFiles,
+
+    /// Untracked files, if they're included
+    untracked_files: TreeFiles,
+}
+
+/// Makes a stash of the changes in the index and worktree, as `git stash
+/// create` does, without touching either. None if there's nothing to
+/// stash.
+///
+/// The stash commit's tree is the worktree and its parents are HEAD, a
+/// commit of the index and, if untracked files are included, a commit of
+/// those on its own. Only the worktree changes to paths the pathspec
+/// matches are stashed, the index is always stashed whole.
+pub fn create_stash(options: &StashOptions) -> io::Result<Option<Stash>> {
+    let Some(trees) = stash_trees(options)? else {
+        return Ok(None);
+    };
+
+    write_stash(&trees, options).map(Some)
+}
+
+/// Stashes the changes in the index and worktree to refs/stash, as `git
+/// stash push` does, and undoes them. None if there's nothing to stash.
+///
+/// With a pathspec only the paths it matches are reset to HEAD, and with
+/// `keep_index` those are then set back to what's staged.
+pub fn push_stash(options: &StashOptions) -> io::Result<Option<Stash>> {
+    let Some(trees) = stash_trees(options)? else {
+        return Ok(None);
+    };
+
+    let stash = write_stash(&trees, options)?;
+
+    store_stash(stash.id, &stash.description)?;
+
+    for path in trees.untracked_files.keys() {
+        remove_worktree_file(path)?;
+    }
+
+    let StashTrees { head_files, index_files, .. } = trees;
+
+    if options.pathspec.is_empty() {
+        checkout_tree(&head_files, &head_files, true).ok_or_else(|| io::Error::other("unable to reset to HEAD"))?;
+
+        if options.keep_index {
+            checkout_tree(&head_files, &index_files, true).ok_or_else(|| io::Error::other("unable to keep the index"))?;
+        }
+
+        return Ok(Some(stash));
+    }
+
+    // Each stashed path is put back as it is in HEAD, or as it's staged
+    let target = match options.keep_index {
+        true => &index_files,
+        false => &head_files,
+    };
+
+    let mut index = Index::read().ok_or_else(|| io::Error::other("unable to read the index"))?;
+    let index_mtime = index_mtime();
+
+    let paths: BTreeSet<Vec<u8>> = index.entries.iter()
+        .map(|entry| entry.path.clone())
+        .chain(head_files.keys().cloned())
+        .filter(|path| options.pathspec.matches(path))
+        .collect();
+
+    for path in paths {
+        let unchanged = index.get(&path).is_some_and(|entry| {
+            Some(&(entry.mode, entry.id)) == target.get(&path) && worktree_change(entry, index_mtime).is_none()
+        });
+
+        if unchanged {
+            continue;
+        }
+
+        match target.get(&path) {
+            Some(&(mode, id)) => {
+                write_worktree_file(&path, mode, id)?;
+                index.add(staged_entry(&path, mode, id, true)?);
+            }
+            None => {
+                remove_worktree_file(&path)?;
+                index.remove(&path);
+            }
+        }
+    }
+
+    index.write()?;
+
+    Ok(Some(stash))
+}
+
+/// Reads what a stash would be made from, None if there's nothing the
+/// options would stash
+fn stash_trees(options: &StashOptions) -> io::Result<Option<StashTrees>> {
+    let head = refs::resolve_ref("HEAD").ok_or_else(|| io::Error::other("You do not have the initial commit yet"))?;
+    let head_files = commit_files(head)?;
+
+    let index = Index::read().ok_or_else(|| io::Error::other("unable to read the index"))?;
+
+    if let Some(path) = index.conflicted_paths().first() {
+        return Err(io::Error::other(format!("{}: needs merge", String::from_utf8_lossy(path))));
+    }
+
+    let index_mtime = index_mtime();
+
+    let mut index_files = TreeFiles::new();
+    let mut work_files = TreeFiles::new();
+
+    for entry in index.entries.iter().filter(|entry| !entry.intent_to_add) {
+        index_files.insert(entry.path.clone(), (entry.mode, entry.id));
+
+        // Paths which aren't being stashed keep what's staged
+        if !options.pathspec.matches(&entry.path) {
+            work_files.insert(entry.path.clone(), (entry.mode, entry.id));
+            continue;
+        }
+
+        match worktree_change(entry, index_mtime) {
+            None => {
+                work_files.insert(entry.path.clone(), (entry.mode, entry.id));
+            }
+            Some(FileStatus::Deleted) => {}
+            Some(_) => {
+                let metadata = fs::symlink_metadata(worktree::os_path(&entry.path))?;
+
+                // Replaced by a directory, which is as good as deleted
+                if metadata.is_dir() {
+                    continue;
+                }
+
+                work_files.insert(entry.path.clone(), (worktree::file_mode(&metadata), write_blob(&entry.path)?));
+            }
+        }
+    }
+
+    let mut untracked_files = TreeFiles::new();
+
+    if options.include_untracked {
+        let mut rules = IgnoreRules::load(&Config::load());
+
+        // Nested repositories, listed as directories, are left alone
+        let untracked = worktree::list_untracked(&index, Some(&mut rules), false, false).into_iter()
+            .filter(|path| !path.ends_with('/') && options.pathspec.matches(path));
+
+        for path in untracked {
+            let metadata = fs::symlink_metadata(&path)?;
+            let id = write_blob(path.as_bytes())?;
+
+            untracked_files.insert(path.into_bytes(), (worktree::file_mode(&metadata), id));
+        }
+    }
+
+    // Only changes to the stashed paths count, though the index is stashed
+    // whole
+    let changed = head_files.keys()
+        .chain(index_files.keys())
+        .filter(|path| options.pathspec.matches(path))
+        .any(|path| index_files.get(path) != head_files.get(path) || work_files.get(path) != index_files.get(path));
+
+    if !changed && untracked_files.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(StashTrees { head, head_files, index_files, work_files, untracked_files }))
+}
+
+/// Writes the commits of a stash
+fn write_stash(trees: &StashTrees, options: &StashOptions) -> io::Result<Stash> {
+    let committer = ident(IdentRole::Committer, &Config::load())
+        .ok_or_else(|| io::Error::other("unable to determine the committer"))?
+        .to_string();
+
+    // Named as git names stashes, after the branch and commit they're on
+    let branch = match refs::head_branch() {
+        Some(branch) => refs::shorten_ref(&branch).to_string(),
+        None => "(no branch)".to_string(),
+    };
+
+    let head = trees.head;
+    let on_head = format!("{branch}: {} {}", &head.to_string()[..7], commit_subject(head));
+
+    let index_commit = write_commit(write_tree(&trees.index_files)?, &[head], &committer, &format!("index on {on_head}\n"))?;
+
+    let mut parents = vec![head, index_commit];
+
+    if !trees.untracked_files.is_empty() {
+        let message = format!("untracked files on {on_head}\n");
+        parents.push(write_commit(write_tree(&trees.untracked_files)?, &[], &committer, &message)?);
+    }
+
+    let description = match &options.message {
+        Some(message) => format!("On {branch}: {message}"),
+        None => format!("WIP on {on_head}"),
+    };
+
+    // Unlike its parents, git leaves the stash's message without a newline
+    let id = write_commit(write_tree(&trees.work_files)?, &parents, &committer, &description)?;
+
+    Ok(Stash { id, description })
+}
+
+/// Pushes `stash` onto the stash list, which is refs/stash's reflog,
+/// `message` describing it there
+pub(crate) fn store_stash(stash: ObjectId, message: &str) -> io::Result<()> {
+    let old = refs::resolve_ref("refs/stash");
+
+    let committer = ident(IdentRole::Committer, &Config::load())
+        .ok_or_else(|| io::Error::other("unable to determine the committer"))?;
+
+    refs::write_ref("refs/stash", stash, None)?;
+
+    // Logged whatever core.logAllRefUpdates says, or the stash is lost
+    append_reflog("refs/stash", &ReflogEntry {
+        old: old.unwrap_or(ObjectId::NULL),
+        new: stash,
+        committer: committer.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// An index entry for `path`, with the stat data of the worktree file if
+/// `in_worktree` says that's what's staged, otherwise none, so the file
+/// is always compared by content
+pub(crate) fn staged_entry(path: &[u8], mode: u32, id: ObjectId, in_worktree: bool) -> io::Result<IndexEntry> {
+    if in_worktree {
+        let mut entry = worktree::index_entry(path, &fs::symlink_metadata(worktree::os_path(path))?, id);
+        entry.mode = mode;
+
+        return Ok(entry);
+    }
+
+    Ok(IndexEntry::new(path, mode, id))
+}
+
+pub(crate) fn commit_files(commit: ObjectId) -> io::Result<TreeFiles> {
+    peel_to_tree(commit)
+        .and_then(flatten_tree)
+        .ok_or_else(|| io::Error::other(format!("unable to read the tree of {commit}")))
+}
+
+/// Stores the worktree file at `path` as a blob
+fn write_blob(path: &[u8]) -> io::Result<ObjectId> {
+    let path = worktree::os_path(path);
+    let contents = worktree::read_file(path).ok_or_else(|| io::Error::other(format!("unable to read '{}'", path.display())))?;
+
+    GitObjectStore::write("blob", &contents).ok_or_else(|| io::Error::other(format!("unable to stash '{}'", path.display())))
+}
+
+fn write_tree(files: &TreeFiles) -> io::Result<ObjectId> {
+    let mut builder = TreeBuilder::new();
+
+    for (path, &(mode, id)) in files {
+        builder.insert(mode, path, id)
+            .ok_or_else(|| io::Error::other(format!("unable to stash '{}'", String::from_utf8_lossy(path))))?;
+    }
+
+    builder.write().ok_or_else(|| io::Error::other("unable to write the stash's tree"))
+}
+
+fn write_commit(tree: ObjectId, parents: &[ObjectId], committer: &str, message: &str) -> io::Result<ObjectId> {
+    let data = serialize_commit(&tree, parents, committer, committer, None, None, message.as_bytes());
+
+    GitObjectStore::write("commit", &data).ok_or_else(|| io::Error::other("unable to write the stash commit"))
+}
@@ -0,0 +1,424 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+
+use crate::config::Config;
+use crate::ignore::IgnoreRules;
+use crate::refs::{self, Head};
+use crate::repo::git_path;
+use crate::revparse::peel_to_commit;
+use crate::store::{
+    index::{Index, IndexEntry},
+    tree::{flatten_tree, MODE_GITLINK},
+    GitObjectData,
+    GitObjectStore,
+    ObjectId,
+};
+use crate::submodule::{in_submodule, load_gitmodules, IgnoreSubmodules};
+use crate::worktree;
+
+/// How a path differs between two of HEAD, the index and the worktree
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    Added,
+    Modified,
+    Deleted,
+    TypeChanged,
+}
+
+impl FileStatus {
+    /// The letter used in porcelain output
+    pub fn code(&self) -> char {
+        match self {
+            FileStatus::Added => 'A',
+            FileStatus::Modified => 'M',
+            FileStatus::Deleted => 'D',
+            FileStatus::TypeChanged => 'T',
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FileStatus::Added => "new file",
+            FileStatus::Modified => "modified",
+            FileStatus::Deleted => "deleted",
+            FileStatus::TypeChanged => "typechange",
+        }
+    }
+}
+
+/// Which sides of a conflicted merge touched a path, decided by the
+/// stages present in the index.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    BothDeleted,
+    AddedByUs,
+    DeletedByThem,
+    AddedByThem,
+    DeletedByUs,
+    BothAdded,
+    BothModified,
+}
+
+impl Conflict {
+    fn from_stages(base: bool, ours: bool, theirs: bool) -> Conflict {
+        match (base, ours, theirs) {
+            (true, false, false) => Conflict::BothDeleted,
+            (false, true, false) => Conflict::AddedByUs,
+            (true, true, false) => Conflict::DeletedByThem,
+            (false, false, true) => Conflict::AddedByThem,
+            (true, false, true) => Conflict::DeletedByUs,
+            (false, true, true) => Conflict::BothAdded,
+            _ => Conflict::BothModified,
+        }
+    }
+
+    /// The two letter porcelain code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Conflict::BothDeleted => "DD",
+            Conflict::AddedByUs => "AU",
+            Conflict::DeletedByThem => "UD",
+            Conflict::AddedByThem => "UA",
+            Conflict::DeletedByUs => "DU",
+            Conflict::BothAdded => "AA",
+            Conflict::BothModified => "UU",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Conflict::BothDeleted => "both deleted",
+            Conflict::AddedByUs => "added by us",
+            Conflict::DeletedByThem => "deleted by them",
+            Conflict::AddedByThem => "added by them",
+            Conflict::DeletedByUs => "deleted by us",
+            Conflict::BothAdded => "both added",
+            Conflict::BothModified => "both modified",
+        }
+    }
+}
+
+/// Which untracked files status lists
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UntrackedFiles {
+    No,
+
+    /// Files, and directories with nothing tracked in them as a whole
+    #[default]
+    Normal,
+
+    /// Every file, however deep in an untracked directory
+    All,
+}
+
+impl UntrackedFiles {
+    pub fn parse(mode: &str) -> Option<UntrackedFiles> {
+        match mode {
+            "no" => Some(UntrackedFiles::No),
+            "normal" => Some(UntrackedFiles::Normal),
+            "all" => Some(UntrackedFiles::All),
+            _ => None,
+        }
+    }
+
+    /// The mode `status.showUntrackedFiles` asks for, the default if unset
+    pub fn from_config(config: &Config) -> Option<UntrackedFiles> {
+        let Some(mode) = config.get("status.showuntrackedfiles") else {
+            return Some(UntrackedFiles::default());
+        };
+
+        UntrackedFiles::parse(mode).or_else(|| {
+            eprintln!("Invalid untracked files mode '{mode}'");
+            None
+        })
+    }
+}
+
+/// How a submodule's checkout differs from the commit the index records
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SubmoduleChanges {
+    /// Another commit is checked out
+    pub new_commits: bool,
+
+    /// Tracked files in it have changed
+    pub modified_content: bool,
+
+    pub untracked_content: bool,
+}
+
+impl SubmoduleChanges {
+    pub fn any(&self) -> bool {
+        self.new_commits || self.modified_content || self.untracked_content
+    }
+
+    /// As the long format shows it after the path, eg. "new commits,
+    /// untracked content"
+    pub fn description(&self) -> String {
+        let changes = [
+            (self.new_commits, "new commits"),
+            (self.modified_content, "modified content"),
+            (self.untracked_content, "untracked content"),
+        ];
+
+        changes.iter()
+            .filter(|(changed, _)| *changed)
+            .map(|(_, description)| *description)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusEntry {
+    pub path: Vec<u8>,
+
+    /// HEAD compared to the index
+    pub staged: Option<FileStatus>,
+
+    /// The index compared to the worktree
+    pub unstaged: Option<FileStatus>,
+
+    pub conflict: Option<Conflict>,
+
+    /// For a submodule modified in the worktree, how
+    pub submodule: Option<SubmoduleChanges>,
+}
+
+#[derive(Debug)]
+pub struct Status {
+    /// The checked out branch, `None` when HEAD is detached
+    pub branch: Option<String>,
+
+    /// `None` on an unborn branch
+    pub head: Option<ObjectId>,
+
+    /// Tracked paths with changes, sorted by path
+    pub entries: Vec<StatusEntry>,
+
+    /// Worktree files neither in the index nor ignored, sorted by path.
+    /// Directories end with '/'.
+    pub untracked: Vec<String>,
+
+    /// How `untracked` was listed
+    pub untracked_files: UntrackedFiles,
+}
+
+/// Compares HEAD's tree, the index and the worktree, listing untracked
+/// files as `untracked_files` says.
+///
+/// Submodules' checkouts are looked inside, leaving out the changes
+/// `ignore_submodules` says to if given, otherwise those each one's
+/// config, `.gitmodules` or diff.ignoreSubmodules do. Only the first
+/// hides a submodule's commit changing in the index.
+pub fn status(untracked_files: UntrackedFiles, ignore_submodules: Option<IgnoreSubmodules>) -> Option<Status> {
+    let head = refs::read_head();
+
+    let branch = match &head {
+        Some(Head::Branch(name)) => Some(name.clone()),
+        _ => None,
+    };
+
+    let head = head.and_then(|head| head.commit());
+
+    let head_files = match head {
+        Some(head) => head_tree_files(head)?,
+        None => BTreeMap::new(),
+    };
+
+    // A missing index is read as an empty one, but a corrupt one isn't
+    let index = Index::read()?;
+    let index_entries = &index.entries[..];
+
+    let index_mtime = index_mtime();
+
+    let config = Config::load();
+    let submodules = load_gitmodules();
+
+    let ignore_default = config.get("diff.ignoresubmodules")
+        .and_then(IgnoreSubmodules::parse)
+        .unwrap_or_default();
+
+    let mut entries: BTreeMap<Vec<u8>, StatusEntry> = BTreeMap::new();
+    let mut conflicts: BTreeMap<&[u8], [bool; 3]> = BTreeMap::new();
+
+    for entry in index_entries {
+        if entry.stage != 0 {
+            conflicts.entry(entry.path.as_slice()).or_default()[entry.stage as usize - 1] = true;
+            continue;
+        }
+
+        // `add -N` records that a path will be added, without staging it
+        if entry.intent_to_add {
+            entry_for(&mut entries, &entry.path).unstaged = Some(FileStatus::Added);
+            continue;
+        }
+
+        // Whatever the config says, a submodule's new commit being staged
+        // is only hidden on request
+        let hide_new_commit = entry.mode == MODE_GITLINK && ignore_submodules == Some(IgnoreSubmodules::All);
+
+        match head_files.get(&entry.path) {
+            None => entry_for(&mut entries, &entry.path).staged = Some(FileStatus::Added),
+            Some(&(mode, id)) => {
+                if mode & 0o170000 != entry.mode & 0o170000 {
+                    entry_for(&mut entries, &entry.path).staged = Some(FileStatus::TypeChanged);
+                } else if (mode != entry.mode || id != entry.id) && !hide_new_commit {
+                    entry_for(&mut entries, &entry.path).staged = Some(FileStatus::Modified);
+                }
+            }
+        }
+
+        if let Some(change) = worktree_change(entry, index_mtime) {
+            entry_for(&mut entries, &entry.path).unstaged = Some(change);
+        } else if entry.mode == MODE_GITLINK {
+            let ignore = ignore_submodules.unwrap_or_else(|| {
+                submodules.iter()
+                    .find(|submodule| submodule.path.as_bytes() == entry.path)
+                    .and_then(|submodule| submodule.configured_ignore(&config))
+                    .unwrap_or(ignore_default)
+            });
+
+            // Nor is untracked content in submodules looked for when
+            // untracked files aren't
+            let ignore = match (ignore, untracked_files) {
+                (IgnoreSubmodules::None, UntrackedFiles::No) => IgnoreSubmodules::Untracked,
+                _ => ignore,
+            };
+
+            let changes = submodule_changes(&entry.path, entry.id, ignore);
+
+            if changes.any() {
+                let status_entry = entry_for(&mut entries, &entry.path);
+                status_entry.unstaged = Some(FileStatus::Modified);
+                status_entry.submodule = Some(changes);
+            }
+        }
+    }
+
+    let in_index: BTreeSet<&[u8]> = index_entries.iter()
+        .map(|entry| entry.path.as_slice())
+        .collect();
+
+    for path in head_files.keys() {
+        if !in_index.contains(path.as_slice()) {
+            entry_for(&mut entries, path).staged = Some(FileStatus::Deleted);
+        }
+    }
+
+    for (path, [base, ours, theirs]) in conflicts {
+        entry_for(&mut entries, path).conflict = Some(Conflict::from_stages(base, ours, theirs));
+    }
+
+    let mut rules = IgnoreRules::load(&config);
+
+    let untracked = match untracked_files {
+        UntrackedFiles::No => Vec::new(),
+        UntrackedFiles::Normal => worktree::list_untracked(&index, Some(&mut rules), true, true),
+        UntrackedFiles::All => worktree::list_untracked(&index, Some(&mut rules), false, false),
+    };
+
+    Some(Status {
+        branch,
+        head,
+        entries: entries.into_values().collect(),
+        untracked,
+        untracked_files,
+    })
+}
+
+fn entry_for<'a>(entries: &'a mut BTreeMap<Vec<u8>, StatusEntry>, path: &[u8]) -> &'a mut StatusEntry {
+    entries.entry(path.to_vec()).or_insert_with(|| StatusEntry {
+        path: path.to_vec(),
+        staged: None,
+        unstaged: None,
+        conflict: None,
+        submodule: None,
+    })
+}
+
+/// How the submodule checked out at `path` differs from `commit`, the
+/// one the index records, leaving out what `ignore` says to. One that
+/// isn't checked out hasn't changed.
+fn submodule_changes(path: &[u8], commit: ObjectId, ignore: IgnoreSubmodules) -> SubmoduleChanges {
+    if ignore == IgnoreSubmodules::All || !worktree::os_path(path).join(".git").exists() {
+        return SubmoduleChanges::default();
+    }
+
+    in_submodule(worktree::os_path(path), || {
+        let mut changes = SubmoduleChanges {
+            new_commits: refs::resolve_ref("HEAD") != Some(commit),
+            ..SubmoduleChanges::default()
+        };
+
+        let untracked_files = match ignore {
+            IgnoreSubmodules::Dirty => return changes,
+            IgnoreSubmodules::Untracked => UntrackedFiles::No,
+            _ => UntrackedFiles::Normal,
+        };
+
+        if let Some(status) = status(untracked_files, None) {
+            changes.modified_content = !status.entries.is_empty();
+            changes.untracked_content = !status.untracked.is_empty();
+        }
+
+        changes
+    }).unwrap_or_default()
+}
+
+fn head_tree_files(head: ObjectId) -> Option<BTreeMap<Vec<u8>, (u32, ObjectId)>> {
+    let commit = peel_to_commit(head)?;
+
+    let GitObjectData::Commit { tree, .. } = GitObjectStore::get(commit)?.data else {
+        return None;
+    };
+
+    flatten_tree(tree)
+}
+
+/// When the index was last written, as (seconds, nanoseconds).
+///
+/// Entries modified at or after then may have changed without their stat
+/// data changing, so can't be trusted.
+pub fn index_mtime() -> Option<(i64, i64)> {
+    fs::metadata(git_path("index"))
+        .map(|metadata| (metadata.mtime(), metadata.mtime_nsec()))
+        .ok()
+}
+
+/// How the worktree file differs from its index entry, if it does.
+/// `index_mtime` is from `index_mtime`.
+pub fn worktree_change(entry: &IndexEntry, index_mtime: Option<(i64, i64)>) -> Option<FileStatus> {
+    let Ok(metadata) = fs::symlink_metadata(worktree::os_path(&entry.path)) else {
+        return Some(FileStatus::Deleted);
+    };
+
+    // A submodule is checked out as a directory, what's in it is the
+    // submodule's business
+    if entry.mode == MODE_GITLINK {
+        return (!metadata.is_dir()).then_some(FileStatus::TypeChanged);
+    }
+
+    let mode = worktree::file_mode(&metadata);
+
+    if mode & 0o170000 != entry.mode & 0o170000 {
+        return Some(FileStatus::TypeChanged);
+    }
+
+    let racy = index_mtime.is_none_or(|index_mtime| {
+        (entry.mtime.secs as i64, entry.mtime.nsecs as i64) >= index_mtime
+    });
+
+    if !racy && worktree::stat_matches(entry, &metadata) {
+        return None;
+    }
+
+    if mode != entry.mode {
+        return Some(FileStatus::Modified);
+    }
+
+    match worktree::hash_file(worktree::os_path(&entry.path)) {
+        Some(id) if id == entry.id => None,
+        _ => Some(FileStatus::Modified),
+    }
+}
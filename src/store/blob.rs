@@ -0,0 +1,142 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use flate2::bufread::ZlibDecoder;
+
+use crate::store::{
+    delta::resolve_delta,
+    loose::open_loose_object,
+    pack::{load_pack_indexes, open_pack, GitPackIdx, read_kind_length_obj_header, ObjectKind, PackedObjectKind},
+    ObjectId,
+};
+
+/// Reads a blob's contents a chunk at a time, rather than all at once as
+/// `GitObjectStore::get` does.
+///
+/// Loose blobs and undeltified packed blobs are inflated as they're read.
+/// A deltified blob has to be resolved in memory first, as applying a
+/// delta needs all of its base.
+pub struct BlobReader {
+    size: u64,
+    remaining: u64,
+    source: BlobSource,
+}
+
+enum BlobSource {
+    Stream(Box<dyn Read>),
+    Resolved(Cursor<Vec<u8>>),
+}
+
+impl BlobReader {
+    /// The size of the blob's contents
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = match &mut self.source {
+            BlobSource::Stream(decoder) => decoder.read(buf)?,
+            BlobSource::Resolved(cursor) => cursor.read(buf)?,
+        };
+
+        if read as u64 > self.remaining {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "blob larger than its header says"));
+        }
+
+        if read == 0 && self.remaining > 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "blob smaller than its header says"));
+        }
+
+        self.remaining -= read as u64;
+
+        Ok(read)
+    }
+}
+
+/// Opens the blob `id`, None if it isn't in the store or isn't a blob
+pub fn open_blob(id: ObjectId) -> Option<BlobReader> {
+    open_loose_blob(id).or_else(|| open_packed_blob(id))
+}
+
+/// Loose objects are compressed streams of:
+///   <obj-type> ' ' <byte-size> '\0' <object-data>
+fn open_loose_blob(id: ObjectId) -> Option<BlobReader> {
+    let (_, mut decoder) = open_loose_object(id)?;
+
+    // The header is short, so it's read a byte at a time to avoid reading
+    // past it
+    let mut header = Vec::new();
+    let mut byte = [0u8];
+
+    loop {
+        decoder.read_exact(&mut byte).ok()?;
+
+        match byte[0] {
+            b'\0' => break,
+            _ if header.len() > 32 => return None,
+            byte => header.push(byte),
+        }
+    }
+
+    let size = std::str::from_utf8(&header).ok()?
+        .strip_prefix("blob ")?
+        .parse()
+        .ok()?;
+
+    Some(BlobReader {
+        size,
+        remaining: size,
+        source: BlobSource::Stream(decoder),
+    })
+}
+
+/// A concurrent repack can remove the pack while it's being looked for, in
+/// which case the pack directory is scanned again for the pack replacing it
+fn open_packed_blob(id: ObjectId) -> Option<BlobReader> {
+    let open = || {
+        let (pack_name, idx, offset) = load_pack_indexes()
+            .into_iter()
+            .find_map(|(name, idx)| {
+                let offset = *idx.locations.get(&id)?;
+                Some((name, idx, offset))
+            })?;
+
+        open_blob_in_pack(&pack_name, &idx, offset)
+    };
+
+    open().or_else(open)
+}
+
+fn open_blob_in_pack(pack_name: &str, idx: &GitPackIdx, offset: usize) -> Option<BlobReader> {
+    let mut reader = open_pack(pack_name, idx)?;
+    reader.seek(SeekFrom::Start(offset as u64)).ok()?;
+
+    let (kind, size) = read_kind_length_obj_header(&mut reader)?;
+
+    match kind {
+        PackedObjectKind::Object(ObjectKind::Blob) => Some(BlobReader {
+            size,
+            remaining: size,
+            source: BlobSource::Stream(Box::new(ZlibDecoder::new(reader))),
+        }),
+        PackedObjectKind::Delta(_) => {
+            reader.seek(SeekFrom::Start(offset as u64)).ok()?;
+
+            let (kind, data) = resolve_delta(&mut reader, idx)?;
+
+            if !matches!(kind, PackedObjectKind::Object(ObjectKind::Blob)) {
+                return None;
+            }
+
+            let size = data.len() as u64;
+
+            Some(BlobReader {
+                size,
+                remaining: size,
+                source: BlobSource::Resolved(Cursor::new(data)),
+            })
+        }
+        PackedObjectKind::Object(_) => None,
+    }
+}
@@ -0,0 +1,95 @@
+// Seeds for the two murmur3 hashes each key's bit positions derive from
+const SEED0: u32 = 0x293ae76f;
+const SEED1: u32 = 0x7e646e2c;
+
+/// How the changed-path Bloom filters in a commit-graph were built, from
+/// the header of its BDAT chunk
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BloomSettings {
+    /// 1 hashes path bytes as signed chars, as git first did by mistake,
+    /// 2 as unsigned ones
+    pub hash_version: u32,
+    pub num_hashes: u32,
+    pub bits_per_entry: u32,
+}
+
+/// The bit positions a path sets in a filter, before they're reduced
+/// modulo the filter's size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomKey {
+    hashes: Vec<u32>,
+}
+
+impl BloomKey {
+    pub fn new(path: &str, settings: &BloomSettings) -> BloomKey {
+        let signed = settings.hash_version == 1;
+
+        let hash0 = murmur3_seeded(SEED0, path.as_bytes(), signed);
+        let hash1 = murmur3_seeded(SEED1, path.as_bytes(), signed);
+
+        let hashes = (0..settings.num_hashes)
+            .map(|i| hash0.wrapping_add(i.wrapping_mul(hash1)))
+            .collect();
+
+        BloomKey { hashes }
+    }
+}
+
+/// Whether the changed-path filter `filter` might hold `key`. A false
+/// answer is certain, a true one may not be.
+pub fn filter_contains(filter: &[u8], key: &BloomKey) -> bool {
+    let bits = filter.len() as u64 * 8;
+
+    if bits == 0 {
+        return true;
+    }
+
+    key.hashes.iter().all(|&hash| {
+        let bit = hash as u64 % bits;
+        filter[(bit / 8) as usize] & (1 << (bit % 8)) != 0
+    })
+}
+
+/// 32-bit murmur3 of `data`, as git computes it for Bloom keys. With
+/// `signed` each byte is sign extended first, as `char` is on most of
+/// git's platforms.
+fn murmur3_seeded(mut seed: u32, data: &[u8], signed: bool) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+
+    let widen = |byte: u8| match signed {
+        true => byte as i8 as u32,
+        false => byte as u32,
+    };
+
+    let mut chunks = data.chunks_exact(4);
+
+    for chunk in &mut chunks {
+        let k = widen(chunk[0]) | widen(chunk[1]) << 8 | widen(chunk[2]) << 16 | widen(chunk[3]) << 24;
+        let k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+        seed ^= k;
+        seed = seed.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+
+    let tail = chunks.remainder();
+
+    if !tail.is_empty() {
+        let mut k = 0;
+
+        for (i, &byte) in tail.iter().enumerate().rev() {
+            k ^= widen(byte) << (8 * i);
+        }
+
+        seed ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    seed ^= data.len() as u32;
+    seed ^= seed >> 16;
+    seed = seed.wrapping_mul(0x85ebca6b);
+    seed ^= seed >> 13;
+    seed = seed.wrapping_mul(0xc2b2ae35);
+    seed ^= seed >> 16;
+
+    seed
+}
@@ -0,0 +1,365 @@
+use std::fs;
+use byteorder::{BigEndian, ByteOrder};
+use crate::repo::git_path;
+use crate::store::bloom::{filter_contains, BloomKey, BloomSettings};
+use crate::store::ObjectId;
+use crate::SHA1_HASH_SIZE;
+
+// A 4-byte signature "CGPH"
+const COMMIT_GRAPH_SIGNATURE: &[u8; 4] = b"CGPH";
+
+const CHUNK_OID_FANOUT: u32 = 0x4f494446; // "OIDF"
+const CHUNK_OID_LOOKUP: u32 = 0x4f49444c; // "OIDL"
+const CHUNK_COMMIT_DATA: u32 = 0x43444154; // "CDAT"
+const CHUNK_GENERATION_DATA: u32 = 0x47444132; // "GDA2"
+const CHUNK_GENERATION_DATA_OVERFLOW: u32 = 0x47444f32; // "GDO2"
+const CHUNK_EXTRA_EDGES: u32 = 0x45444745; // "EDGE"
+const CHUNK_BLOOM_INDEXES: u32 = 0x42494458; // "BIDX"
+const CHUNK_BLOOM_DATA: u32 = 0x42444154; // "BDAT"
+
+const PARENT_NONE: u32 = 0x70000000;
+const PARENT_OCTOPUS: u32 = 0x80000000;
+const EDGE_LAST: u32 = 0x80000000;
+const GENERATION_OVERFLOW: u32 = 0x80000000;
+
+// Each CDAT row: tree oid, two parent positions, generation + commit time
+const COMMIT_DATA_WIDTH: usize = SHA1_HASH_SIZE + 16;
+
+/// A commit as described by the commit-graph, without touching the
+/// object store.
+#[derive(Debug, Clone)]
+pub struct GraphCommit {
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+
+    /// Corrected commit date if every layer of the graph has generation
+    /// data, otherwise the topological level. Either way a commit's generation is always
+    /// greater than that of its parents.
+    pub generation: u64,
+
+    /// Committer time, seconds since the epoch
+    pub commit_time: u64,
+}
+
+/// The commit-graph, either a single `objects/info/commit-graph` file or
+/// a chain of incremental graph files in `objects/info/commit-graphs/`.
+#[derive(Debug)]
+pub struct CommitGraph {
+    /// Ordered base first, positions are global across the layers
+    layers: Vec<CommitGraphFile>,
+
+    /// Whether generations are corrected commit dates, which they can only
+    /// be if every layer has them, as they can't be compared with the
+    /// topological levels of layers without
+    corrected_dates: bool,
+}
+
+#[derive(Debug)]
+struct CommitGraphFile {
+    data: Vec<u8>,
+    num_commits: u32,
+
+    /// Number of commits in all the layers below this one
+    base_commits: u32,
+
+    oid_fanout: usize,
+    oid_lookup: usize,
+    commit_data: usize,
+    generation_data: Option<usize>,
+    generation_data_overflow: Option<usize>,
+    extra_edges: Option<usize>,
+
+    /// The changed-path Bloom filters, as the offset of their index, the
+    /// offset of the filters themselves and how they were built
+    bloom_filters: Option<(usize, usize, BloomSettings)>,
+}
+
+impl CommitGraph {
+    /// Loads the repositories commit-graph, if it has one
+    pub fn load() -> Option<CommitGraph> {
+        let chain_path = git_path("objects/info/commit-graphs/commit-graph-chain");
+
+        if let Ok(chain) = fs::read_to_string(chain_path) {
+            let mut layers = Vec::new();
+            let mut base_commits = 0;
+
+            for hash in chain.lines() {
+                let path = git_path(format!("objects/info/commit-graphs/graph-{hash}.graph"));
+                let layer = CommitGraphFile::parse(fs::read(path).ok()?, base_commits)?;
+
+                base_commits += layer.num_commits;
+                layers.push(layer);
+            }
+
+            return Some(CommitGraph::new(layers));
+        }
+
+        let data = fs::read(git_path("objects/info/commit-graph")).ok()?;
+
+        Some(CommitGraph::new(vec![CommitGraphFile::parse(data, 0)?]))
+    }
+
+    fn new(layers: Vec<CommitGraphFile>) -> CommitGraph {
+        let corrected_dates = layers.iter().all(|layer| layer.generation_data.is_some());
+
+        CommitGraph { layers, corrected_dates }
+    }
+
+    /// The global position of `id` in the graph
+    pub fn position(&self, id: &ObjectId) -> Option<u32> {
+        self.layers.iter()
+            .find_map(|layer| layer.position(id))
+    }
+
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.position(id).is_some()
+    }
+
+    pub fn get(&self, id: &ObjectId) -> Option<GraphCommit> {
+        self.commit_at(self.position(id)?)
+    }
+
+    /// How the changed-path Bloom filters were built, if the graph has
+    /// them. Layers built differently can't share keys, so then none are
+    /// used.
+    pub fn bloom_settings(&self) -> Option<BloomSettings> {
+        let settings = self.layers.first()?.bloom_filters?.2;
+
+        self.layers.iter()
+            .all(|layer| layer.bloom_filters.is_some_and(|(_, _, layer_settings)| layer_settings == settings))
+            .then_some(settings)
+    }
+
+    /// Might `id` have changed the path `key` was made for, compared to its
+    /// first parent? With no filter for the commit nothing can be ruled out.
+    pub fn maybe_changed(&self, id: &ObjectId, key: &BloomKey) -> bool {
+        let filter = self.position(id).and_then(|position| {
+            let layer = self.layer_for(position)?;
+            layer.bloom_filter(position - layer.base_commits)
+        });
+
+        filter.is_none_or(|filter| filter_contains(filter, key))
+    }
+
+    fn layer_for(&self, position: u32) -> Option<&CommitGraphFile> {
+        self.layers.iter()
+            .find(|layer| position < layer.base_commits + layer.num_commits)
+    }
+
+    fn id_at(&self, position: u32) -> Option<ObjectId> {
+        let layer = self.layer_for(position)?;
+        layer.id_at(position - layer.base_commits)
+    }
+
+    fn commit_at(&self, position: u32) -> Option<GraphCommit> {
+        let layer = self.layer_for(position)?;
+        let local = (position - layer.base_commits) as usize;
+
+        let row = &layer.data[layer.commit_data + local * COMMIT_DATA_WIDTH..][..COMMIT_DATA_WIDTH];
+
+        let tree: ObjectId = row[..SHA1_HASH_SIZE].try_into().ok()?;
+
+        let first_parent = BigEndian::read_u32(&row[SHA1_HASH_SIZE..]);
+        let second_parent = BigEndian::read_u32(&row[SHA1_HASH_SIZE + 4..]);
+
+        let mut parents = Vec::new();
+
+        if first_parent != PARENT_NONE {
+            parents.push(self.id_at(first_parent)?);
+        }
+
+        if second_parent & PARENT_OCTOPUS != 0 {
+            // The remaining parents are listed in the extra edges chunk
+            let mut edge = layer.extra_edges? + ((second_parent & !PARENT_OCTOPUS) as usize) * 4;
+
+            loop {
+                let position = BigEndian::read_u32(layer.data.get(edge..edge + 4)?);
+                parents.push(self.id_at(position & !EDGE_LAST)?);
+
+                if position & EDGE_LAST != 0 {
+                    break;
+                }
+
+                edge += 4;
+            }
+        } else if second_parent != PARENT_NONE {
+            parents.push(self.id_at(second_parent)?);
+        }
+
+        // 30-bit topological level, 34-bit commit time
+        let high = BigEndian::read_u32(&row[SHA1_HASH_SIZE + 8..]);
+        let low = BigEndian::read_u32(&row[SHA1_HASH_SIZE + 12..]);
+
+        let topological_level = (high >> 2) as u64;
+        let commit_time = (((high & 0b11) as u64) << 32) | low as u64;
+
+        let generation = match layer.generation_data.filter(|_| self.corrected_dates) {
+            Some(generation_data) => {
+                let start = generation_data + local * 4;
+                let offset = BigEndian::read_u32(layer.data.get(start..start + 4)?);
+
+                let offset = if offset & GENERATION_OVERFLOW != 0 {
+                    let overflow = layer.generation_data_overflow?
+                        + ((offset & !GENERATION_OVERFLOW) as usize) * 8;
+                    BigEndian::read_u64(layer.data.get(overflow..overflow + 8)?)
+                } else {
+                    offset as u64
+                };
+
+                commit_time + offset
+            },
+            None => topological_level,
+        };
+
+        Some(GraphCommit {
+            tree,
+            parents,
+            generation,
+            commit_time,
+        })
+    }
+}
+
+impl CommitGraphFile {
+    /// Commit-graph file format:
+    ///   4-byte signature "CGPH"
+    ///   1-byte version number (1)
+    ///   1-byte hash version (1 = SHA-1)
+    ///   1-byte number of chunks (C)
+    ///   1-byte number of base commit-graphs
+    ///   Chunk lookup: (C + 1) * (4-byte chunk id, 8-byte offset)
+    ///   Chunk data
+    ///   Hash checksum of the above
+    fn parse(data: Vec<u8>, base_commits: u32) -> Option<CommitGraphFile> {
+        if data.len() < 8 || &data[..4] != COMMIT_GRAPH_SIGNATURE {
+            eprintln!("commit-graph: bad signature");
+            return None;
+        }
+
+        if data[4] != 1 || data[5] != 1 {
+            eprintln!("Gitty currently supports only v1 SHA-1 commit-graphs");
+            return None;
+        }
+
+        let num_chunks = data[6] as usize;
+
+        let mut oid_fanout = None;
+        let mut oid_lookup = None;
+        let mut commit_data = None;
+        let mut generation_data = None;
+        let mut generation_data_overflow = None;
+        let mut extra_edges = None;
+        let mut bloom_indexes = None;
+        let mut bloom_data = None;
+
+        for chunk in 0..num_chunks {
+            let entry = data.get(8 + chunk * 12..8 + (chunk + 1) * 12)?;
+
+            let id = BigEndian::read_u32(entry);
+            let offset = Some(BigEndian::read_u64(&entry[4..]) as usize);
+
+            match id {
+                CHUNK_OID_FANOUT => oid_fanout = offset,
+                CHUNK_OID_LOOKUP => oid_lookup = offset,
+                CHUNK_COMMIT_DATA => commit_data = offset,
+                CHUNK_GENERATION_DATA => generation_data = offset,
+                CHUNK_GENERATION_DATA_OVERFLOW => generation_data_overflow = offset,
+                CHUNK_EXTRA_EDGES => extra_edges = offset,
+                CHUNK_BLOOM_INDEXES => bloom_indexes = offset,
+                CHUNK_BLOOM_DATA => bloom_data = offset,
+                // Base graph list etc.
+                _ => {}
+            }
+        }
+
+        let (Some(oid_fanout), Some(oid_lookup), Some(commit_data)) = (oid_fanout, oid_lookup, commit_data) else {
+            eprintln!("commit-graph: missing required chunk");
+            return None;
+        };
+
+        // The last fanout entry is the total number of commits
+        let num_commits = BigEndian::read_u32(data.get(oid_fanout + 255 * 4..oid_fanout + 256 * 4)?);
+
+        if data.len() < commit_data + num_commits as usize * COMMIT_DATA_WIDTH
+            || data.len() < oid_lookup + num_commits as usize * SHA1_HASH_SIZE {
+            eprintln!("commit-graph: file is truncated");
+            return None;
+        }
+
+        // BDAT header: hash version, number of hashes, bits per entry
+        let bloom_filters = bloom_indexes.zip(bloom_data).and_then(|(bloom_indexes, bloom_data)| {
+            let header = data.get(bloom_data..bloom_data + 12)?;
+
+            let settings = BloomSettings {
+                hash_version: BigEndian::read_u32(header),
+                num_hashes: BigEndian::read_u32(&header[4..]),
+                bits_per_entry: BigEndian::read_u32(&header[8..]),
+            };
+
+            let indexes_end = bloom_indexes + num_commits as usize * 4;
+
+            (matches!(settings.hash_version, 1 | 2) && data.len() >= indexes_end)
+                .then_some((bloom_indexes, bloom_data + 12, settings))
+        });
+
+        Some(CommitGraphFile {
+            data,
+            num_commits,
+            base_commits,
+            oid_fanout,
+            oid_lookup,
+            commit_data,
+            generation_data,
+            generation_data_overflow,
+            extra_edges,
+            bloom_filters,
+        })
+    }
+
+    /// The changed-path filter of the commit at `local`. BIDX holds where
+    /// each commit's filter ends in BDAT, the previous one's end is where
+    /// it starts.
+    fn bloom_filter(&self, local: u32) -> Option<&[u8]> {
+        let (indexes, filters, _) = self.bloom_filters?;
+        let local = local as usize;
+
+        let end = BigEndian::read_u32(&self.data[indexes + local * 4..]) as usize;
+
+        let start = match local {
+            0 => 0,
+            _ => BigEndian::read_u32(&self.data[indexes + (local - 1) * 4..]) as usize,
+        };
+
+        self.data.get(filters + start..filters + end)
+    }
+
+    fn id_at(&self, local: u32) -> Option<ObjectId> {
+        let start = self.oid_lookup + local as usize * SHA1_HASH_SIZE;
+        self.data.get(start..start + SHA1_HASH_SIZE)?.try_into().ok()
+    }
+
+    fn fanout(&self, byte: usize) -> u32 {
+        BigEndian::read_u32(&self.data[self.oid_fanout + byte * 4..])
+    }
+
+    // Binary search within the fanout bucket for the first byte of `id`
+    fn position(&self, id: &ObjectId) -> Option<u32> {
+        let first_byte = id[0] as usize;
+
+        let mut low = if first_byte == 0 { 0 } else { self.fanout(first_byte - 1) };
+        let mut high = self.fanout(first_byte);
+
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let mid_id = self.id_at(mid)?;
+
+            match mid_id[..].cmp(&id[..]) {
+                std::cmp::Ordering::Equal => return Some(self.base_commits + mid),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+
+        None
+    }
+}
@@ -6,12 +6,15 @@ use std::io::{
     Seek
 };
 use std::fs::File;
+use flate2::read::ZlibDecoder;
 use crate::store::pack::{
     read_kind_length_obj_header,
+    GitPackIdx,
     PackedObjectKind::{ self, Delta },
     DeltaKind,
 };
 use byteorder::ReadBytesExt;
+use crate::SHA1_HASH_SIZE;
 
 // Deltified object:
 // size-encoded n-byte integer:
@@ -36,7 +39,7 @@ struct DeltaStackItem {
     instructions: Box<[u8]>,
 }
 
-fn read_negative_relative_offset<R>(data: &mut R) -> Option<u64>
+pub(crate) fn read_negative_relative_offset<R>(data: &mut R) -> Option<u64>
 where
     R: Read,
 {
@@ -57,7 +60,9 @@ where
     Some(neg_relative_offs)
 }
 
-pub fn resolve_delta(delta_object: &mut BufReader<File>) -> Option<(PackedObjectKind, Vec<u8>)> {
+/// Resolves the delta at the reader's position, following its chain of
+/// bases, those named by OBJ_REF_DELTA being looked up in `idx`
+pub fn resolve_delta(delta_object: &mut BufReader<File>, idx: &GitPackIdx) -> Option<(PackedObjectKind, Vec<u8>)> {
     let mut delta_stack = Vec::new();
     let mut kind;
     let mut length;
@@ -74,37 +79,49 @@ pub fn resolve_delta(delta_object: &mut BufReader<File>) -> Option<(PackedObject
 
         use DeltaKind::*;
         match kind {
-            Delta(delta_kind) => match delta_kind {
-                Offset => {
-                    let mut delta_data = vec![0u8; length as usize];
-                    let mut instructions = Vec::new();
-
-                    // parse the base objects negative offset from us
-                    let negative_offset = read_negative_relative_offset(delta_object)?;
-
-                    // decompress the delta
-                    compress::zlib::Decoder::new(delta_object.by_ref())
-                        .read_exact(&mut delta_data).ok()?;
-
-                    let mut delta_reader = Cursor::new(delta_data);
-
-                    let base_size = size_decode(&mut delta_reader)?;
-                    let result_size = size_decode(&mut delta_reader)?;
-
-                    // the rest of the data are the encoded instructions
-                    delta_reader.read_to_end(&mut instructions).ok()?;
-
-                    delta_stack.push(DeltaStackItem {
-                        base_size,
-                        result_size,
-                        instructions: instructions.into_boxed_slice()
-                    });
-
-                    // jump to the base object
-                    let base_offset = start_offset - negative_offset;
-                    delta_object.seek(SeekFrom::Start(base_offset)).ok()?;
+            Delta(delta_kind) => {
+                let mut delta_data = vec![0u8; length as usize];
+                let mut instructions = Vec::new();
+
+                // the base is either a negative offset from us, or named
+                // and found through the pack's idx
+                let base_offset = match delta_kind {
+                    Offset => start_offset - read_negative_relative_offset(delta_object)?,
+                    Reference => {
+                        let mut base = [0u8; SHA1_HASH_SIZE];
+                        delta_object.read_exact(&mut base).ok()?;
+
+                        *idx.locations.get(&base.into())? as u64
+                    }
+                };
+
+                // decompress the delta
+                ZlibDecoder::new(delta_object.by_ref())
+                    .read_exact(&mut delta_data).ok()?;
+
+                let mut delta_reader = Cursor::new(delta_data);
+
+                let base_size = size_decode(&mut delta_reader)?;
+                let result_size = size_decode(&mut delta_reader)?;
+
+                // the rest of the data are the encoded instructions
+                delta_reader.read_to_end(&mut instructions).ok()?;
+
+                delta_stack.push(DeltaStackItem {
+                    base_size,
+                    result_size,
+                    instructions: instructions.into_boxed_slice()
+                });
+
+                // named bases can go round in circles in a corrupt pack,
+                // which a chain longer than the pack gives away
+                if delta_stack.len() > idx.locations.len() {
+                    eprintln!("Delta chain loops.");
+                    return None;
                 }
-                Reference => unimplemented!("OBJ_REF_DELTA"),
+
+                // jump to the base object
+                delta_object.seek(SeekFrom::Start(base_offset)).ok()?;
             },
             // found base object!
             _ => break
@@ -124,7 +141,7 @@ pub fn resolve_delta(delta_object: &mut BufReader<File>) -> Option<(PackedObject
     delta_object.seek(SeekFrom::Start(start_offset)).ok()?;
     let (kind, _) = read_kind_length_obj_header(delta_object)?;
 
-    compress::zlib::Decoder::new(delta_object.by_ref())
+    ZlibDecoder::new(delta_object.by_ref())
         .read_exact(&mut base_buffer).ok()?;
 
     apply_delta(&base_buffer, &mut dest_buffer, &initial_delta.instructions);
@@ -167,7 +184,7 @@ pub fn apply_delta(
                 let bitmask = 1 << field;
                 if instructions[ip] & bitmask != 0 {
                     data_pointer += 1;
-                    let field_data: u64 = instructions[data_pointer] as u64;
+                    let field_data: u64 = *instructions.get(data_pointer)? as u64;
                     match field {
                         0..=3=> { offset |= field_data << (field * 8); }
                         4.. => { size |= field_data << ((field - 4) * 8); }
@@ -176,10 +193,15 @@ pub fn apply_delta(
             }
             
             let offset = offset as usize;
-            let size = size as usize;
 
-            dest_buffer[dp..(dp + size)]
-                .copy_from_slice(&base_buffer[offset..(offset + size)]);
+            // A size of zero means 0x10000
+            let size = match size {
+                0 => 0x10000,
+                size => size as usize,
+            };
+
+            dest_buffer.get_mut(dp..dp + size)?
+                .copy_from_slice(base_buffer.get(offset..offset + size)?);
 
             dp += size;
             ip = data_pointer + 1;
@@ -192,9 +214,14 @@ pub fn apply_delta(
             //
             let size = instructions[ip] as usize;
 
+            // Zero is reserved
+            if size == 0 {
+                return None;
+            }
+
             let data_start = ip + 1;
-            dest_buffer[dp..(dp + size)]
-                .copy_from_slice(&instructions[data_start..(data_start + size)]);
+            dest_buffer.get_mut(dp..dp + size)?
+                .copy_from_slice(instructions.get(data_start..data_start + size)?);
 
             ip += 1;
             ip += size;
@@ -202,7 +229,26 @@ pub fn apply_delta(
         }
     }
 
-    Some(())
+    // Anything short of the size the delta gave is as corrupt
+    (dp == dest_buffer.len()).then_some(())
+}
+
+/// Applies a whole delta, as a pack entry holds it once inflated, to
+/// `base`. None if it's corrupt or meant for a base of another size.
+pub(crate) fn apply_delta_data(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = Cursor::new(delta);
+
+    let base_size = size_decode(&mut reader)?;
+    let result_size = size_decode(&mut reader)?;
+
+    if base_size != base.len() as u64 {
+        return None;
+    }
+
+    let mut result = vec![0; result_size as usize];
+    apply_delta(base, &mut result, &delta[reader.position() as usize..])?;
+
+    Some(result)
 }
 
 fn size_decode<R>(reader: &mut R) -> Option<u64>
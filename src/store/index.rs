@@ -0,0 +1,589 @@
+use std::fs;
+use std::io::{self, Cursor, Read, BufRead, Write};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use sha1::{Digest, Sha1};
+use crate::store::{
+    tree::{TreeBuilder, MODE_GITLINK},
+    util::find_backend,
+    ObjectId,
+};
+use crate::lockfile::LockFile;
+use crate::quote::quote_path;
+use crate::repo::git_path;
+use crate::SHA1_HASH_SIZE;
+
+// A 4-byte signature "DIRC" (stands for "dircache")
+const INDEX_SIGNATURE: &[u8; 4] = b"DIRC";
+
+// flags: 1-bit assume-valid, 1-bit extended, 2-bit stage, 12-bit name length
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_STAGE_SHIFT: u16 = 12;
+
+// extended flags (v3+): 1-bit reserved, 1-bit skip-worktree, 1-bit intent-to-add
+const EXT_FLAG_SKIP_WORKTREE: u16 = 0x4000;
+const EXT_FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+/// The staging area, as stored in `.git/index`
+#[derive(Debug)]
+pub struct Index {
+    pub version: u32,
+
+    /// Sorted by path, then by stage
+    pub entries: Vec<IndexEntry>,
+
+    /// Extensions we don't interpret, kept so they can be written back
+    pub extensions: Vec<IndexExtension>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct IndexTime {
+    pub secs: u32,
+    pub nsecs: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub ctime: IndexTime,
+    pub mtime: IndexTime,
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+
+    /// The on-disk size, truncated to 32-bits
+    pub size: u32,
+
+    pub id: ObjectId,
+
+    pub assume_valid: bool,
+
+    /// 0 for normal entries, 1-3 (base, ours, theirs) during a conflicted merge
+    pub stage: u8,
+
+    pub skip_worktree: bool,
+    pub intent_to_add: bool,
+
+    pub path: Vec<u8>,
+}
+
+impl IndexEntry {
+    /// An entry staging `id` at `path` without any stat data, so it's
+    /// always compared with the worktree by content
+    pub fn new<P: AsRef<[u8]>>(path: P, mode: u32, id: ObjectId) -> IndexEntry {
+        IndexEntry {
+            ctime: IndexTime::default(),
+            mtime: IndexTime::default(),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            id,
+            assume_valid: false,
+            stage: 0,
+            skip_worktree: false,
+            intent_to_add: false,
+            path: path.as_ref().to_vec(),
+        }
+    }
+
+    /// Whether the entry needs the v3 extended flags
+    pub fn has_extended_flags(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+}
+
+/// A conflicted path's base, ours and theirs versions as stages 1 to 3
+/// record them, (mode, id), None where a side doesn't have the path
+pub type Stages = [Option<(u32, ObjectId)>; 3];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexExtension {
+    pub signature: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl Index {
+    /// Reads the repositories index. A repository without one yet, eg.
+    /// straight after `init`, has an empty index.
+    pub fn read() -> Option<Index> {
+        let data = match fs::read(git_path("index")) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Some(Index::empty()),
+            Err(err) => {
+                eprintln!("Unable to read index: {err}");
+                return None;
+            }
+        };
+
+        Index::parse(&data)
+    }
+
+    pub fn empty() -> Index {
+        Index {
+            version: 2,
+            entries: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Index file format:
+    ///   12-byte header:
+    ///     4-byte signature "DIRC"
+    ///     4-byte version number (2, 3 or 4)
+    ///     32-bit number of index entries
+    ///   A number of sorted index entries
+    ///   Extensions:
+    ///     4-byte extension signature
+    ///     32-bit size of the extension
+    ///     Extension data
+    ///   Hash checksum over the content of the index file before this checksum
+    pub fn parse(data: &[u8]) -> Option<Index> {
+        if data.len() < 12 + SHA1_HASH_SIZE {
+            eprintln!("Index file too short");
+            return None;
+        }
+
+        let (content, checksum) = data.split_at(data.len() - SHA1_HASH_SIZE);
+
+        if Sha1::digest(content).as_slice() != checksum {
+            eprintln!("Index file corrupt: bad checksum");
+            return None;
+        }
+
+        let mut reader = Cursor::new(content);
+
+        let mut signature = [0u8; 4];
+        reader.read_exact(&mut signature).ok()?;
+
+        if &signature != INDEX_SIGNATURE {
+            eprintln!("Index file corrupt: bad signature");
+            return None;
+        }
+
+        let version = reader.read_u32::<BigEndian>().ok()?;
+
+        if !(2..=4).contains(&version) {
+            eprintln!("Gitty currently supports only index formats of v{{2,3,4}}");
+            return None;
+        }
+
+        let entry_count = reader.read_u32::<BigEndian>().ok()?;
+
+        let mut entries = Vec::with_capacity(entry_count as usize);
+        let mut previous_path = Vec::new();
+
+        for _ in 0..entry_count {
+            let entry = parse_index_entry(&mut reader, version, &mut previous_path)?;
+            entries.push(entry);
+        }
+
+        let mut extensions = Vec::new();
+
+        while (reader.position() as usize) < content.len() {
+            let mut signature = [0u8; 4];
+            reader.read_exact(&mut signature).ok()?;
+
+            let size = reader.read_u32::<BigEndian>().ok()?;
+
+            let mut data = vec![0u8; size as usize];
+            reader.read_exact(&mut data).ok()?;
+
+            extensions.push(IndexExtension {
+                signature,
+                data,
+            });
+        }
+
+        Some(Index {
+            version,
+            entries,
+            extensions,
+        })
+    }
+
+    /// Serializes the index in its `version`'s format, upgrading a v2 index
+    /// to v3 if any entry needs extended flags
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut version = self.version;
+
+        if version == 2 && self.entries.iter().any(IndexEntry::has_extended_flags) {
+            version = 3;
+        }
+
+        let mut out = Vec::new();
+
+        out.extend_from_slice(INDEX_SIGNATURE);
+        out.write_u32::<BigEndian>(version).unwrap();
+        out.write_u32::<BigEndian>(self.entries.len() as u32).unwrap();
+
+        let mut previous_path = Vec::new();
+
+        for entry in &self.entries {
+            write_index_entry(&mut out, entry, version, &mut previous_path);
+        }
+
+        for extension in &self.extensions {
+            out.extend_from_slice(&extension.signature);
+            out.write_u32::<BigEndian>(extension.data.len() as u32).unwrap();
+            out.extend_from_slice(&extension.data);
+        }
+
+        let checksum = Sha1::digest(&out);
+        out.extend_from_slice(&checksum);
+
+        out
+    }
+
+    /// Replaces `.git/index` with this index
+    pub fn write(&self) -> io::Result<()> {
+        let mut lock = LockFile::acquire(git_path("index"))?;
+        lock.write_all(&self.serialize())?;
+        lock.commit()
+    }
+
+    /// The position of `path` at `stage`, or where it would be inserted
+    fn position(&self, path: &[u8], stage: u8) -> Result<usize, usize> {
+        self.entries.binary_search_by(|entry| {
+            entry.path.as_slice()
+                .cmp(path)
+                .then(entry.stage.cmp(&stage))
+        })
+    }
+
+    pub fn get<P: AsRef<[u8]>>(&self, path: P) -> Option<&IndexEntry> {
+        self.position(path.as_ref(), 0).ok().map(|pos| &self.entries[pos])
+    }
+
+    /// Stages `entry`, replacing whatever was staged at its path, including
+    /// any conflicted stages
+    pub fn add(&mut self, entry: IndexEntry) {
+        self.remove(entry.path.clone());
+
+        let pos = self.position(&entry.path, entry.stage).unwrap_or_else(|pos| pos);
+        self.entries.insert(pos, entry);
+        self.invalidate_caches();
+    }
+
+    /// Unstages every stage of `path`, returning whether anything was removed
+    pub fn remove<P: AsRef<[u8]>>(&mut self, path: P) -> bool {
+        let path = path.as_ref();
+        let start = self.position(path, 0).unwrap_or_else(|pos| pos);
+        let end = self.entries[start..]
+            .iter()
+            .position(|entry| entry.path != path)
+            .map_or(self.entries.len(), |len| start + len);
+
+        if start == end {
+            return false;
+        }
+
+        self.entries.drain(start..end);
+        self.invalidate_caches();
+
+        true
+    }
+
+    /// The entry for `path` at `stage`, 1 to 3 for a conflicted path's
+    /// base, ours and theirs
+    pub fn get_stage<P: AsRef<[u8]>>(&self, path: P, stage: u8) -> Option<&IndexEntry> {
+        self.position(path.as_ref(), stage).ok().map(|pos| &self.entries[pos])
+    }
+
+    /// The stages of `path`, None if it isn't conflicted
+    pub fn conflict<P: AsRef<[u8]>>(&self, path: P) -> Option<Stages> {
+        let path = path.as_ref();
+        let stages = [1, 2, 3].map(|stage| self.get_stage(path, stage).map(|entry| (entry.mode, entry.id)));
+
+        stages.iter().any(Option::is_some).then_some(stages)
+    }
+
+    /// The conflicted paths, in index order
+    pub fn conflicted_paths(&self) -> Vec<&[u8]> {
+        let mut paths: Vec<&[u8]> = self.entries.iter()
+            .filter(|entry| entry.stage != 0)
+            .map(|entry| entry.path.as_slice())
+            .collect();
+
+        paths.dedup();
+        paths
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|entry| entry.stage != 0)
+    }
+
+    /// Replaces whatever is staged at `path` with the conflicted `stages`,
+    /// as a merge leaves a path it can't merge
+    pub fn add_conflict<P: AsRef<[u8]>>(&mut self, path: P, stages: Stages) {
+        let path = path.as_ref();
+        self.remove(path);
+
+        for (stage, version) in (1..).zip(stages) {
+            let Some((mode, id)) = version else {
+                continue;
+            };
+
+            let pos = self.position(path, stage).unwrap_or_else(|pos| pos);
+            self.entries.insert(pos, IndexEntry { stage, ..IndexEntry::new(path, mode, id) });
+        }
+
+        self.invalidate_caches();
+    }
+
+    /// Resolves the conflicted `path` as the version at `stage`, staging it
+    /// in place of the stages. False if there's no such version.
+    ///
+    /// Any other resolution is staged with `add`, which replaces the
+    /// stages as well.
+    pub fn resolve<P: AsRef<[u8]>>(&mut self, path: P, stage: u8) -> bool {
+        let Some(entry) = self.get_stage(path, stage).filter(|_| stage != 0).cloned() else {
+            return false;
+        };
+
+        self.add(IndexEntry { stage: 0, ..entry });
+
+        true
+    }
+
+    /// Drops the cache extensions (cached trees, untracked files) which
+    /// describe the entries as they were read
+    fn invalidate_caches(&mut self) {
+        self.extensions.retain(|extension| !matches!(&extension.signature, b"TREE" | b"UNTR"));
+    }
+
+    /// Stores the staged contents as trees, returning the root tree.
+    ///
+    /// Fails on unmerged entries, and unless `missing_ok`, on entries
+    /// whose object isn't in the store. Intent-to-add entries have no
+    /// content yet and are left out.
+    pub fn write_tree(&self, missing_ok: bool) -> Option<ObjectId> {
+        let mut builder = TreeBuilder::new();
+        let mut unmerged = false;
+
+        for entry in &self.entries {
+            if entry.stage != 0 {
+                eprintln!("{}: unmerged ({})", quote_path(&entry.path, true), entry.id);
+                unmerged = true;
+                continue;
+            }
+
+            if entry.intent_to_add {
+                continue;
+            }
+
+            // A submodule's commit is in the submodule
+            if !missing_ok && entry.mode != MODE_GITLINK && find_backend(entry.id).is_none() {
+                eprintln!("invalid object {:06o} {} for '{}'", entry.mode, entry.id, String::from_utf8_lossy(&entry.path));
+                return None;
+            }
+
+            builder.insert(entry.mode, &entry.path, entry.id)?;
+        }
+
+        if unmerged {
+            eprintln!("The index is unmerged");
+            return None;
+        }
+
+        builder.write()
+    }
+}
+
+fn read_index_time<R: Read>(reader: &mut R) -> Option<IndexTime> {
+    Some(IndexTime {
+        secs: reader.read_u32::<BigEndian>().ok()?,
+        nsecs: reader.read_u32::<BigEndian>().ok()?,
+    })
+}
+
+/// Index entry format:
+///   32-bit ctime seconds, 32-bit ctime nanoseconds
+///   32-bit mtime seconds, 32-bit mtime nanoseconds
+///   32-bit dev, 32-bit ino
+///   32-bit mode (4-bit object type, 3-bit unused, 9-bit unix permission)
+///   32-bit uid, 32-bit gid
+///   32-bit file size
+///   20-byte object name
+///   16-bit flags
+///   (v3+) 16-bit extended flags, if the extended flag is set
+///   Entry path name, either:
+///     v2/v3: NUL terminated, padded with 1-8 NULs to a multiple of 8 bytes
+///     v4: prefix compressed against the previous entry, NUL terminated, unpadded
+fn parse_index_entry(
+    reader: &mut Cursor<&[u8]>,
+    version: u32,
+    previous_path: &mut Vec<u8>,
+) -> Option<IndexEntry> {
+    let start_offset = reader.position();
+
+    let ctime = read_index_time(reader)?;
+    let mtime = read_index_time(reader)?;
+    let dev = reader.read_u32::<BigEndian>().ok()?;
+    let ino = reader.read_u32::<BigEndian>().ok()?;
+    let mode = reader.read_u32::<BigEndian>().ok()?;
+    let uid = reader.read_u32::<BigEndian>().ok()?;
+    let gid = reader.read_u32::<BigEndian>().ok()?;
+    let size = reader.read_u32::<BigEndian>().ok()?;
+
+    let mut id = [0u8; SHA1_HASH_SIZE];
+    reader.read_exact(&mut id).ok()?;
+
+    let flags = reader.read_u16::<BigEndian>().ok()?;
+
+    let extended_flags = if flags & FLAG_EXTENDED != 0 {
+        if version < 3 {
+            eprintln!("Index file corrupt: extended flags in a v2 index");
+            return None;
+        }
+        reader.read_u16::<BigEndian>().ok()?
+    } else {
+        0
+    };
+
+    let mut path = Vec::new();
+
+    if version == 4 {
+        // The number of bytes to remove from the end of the previous path
+        let strip = read_varint(reader)? as usize;
+
+        if strip > previous_path.len() {
+            eprintln!("Index file corrupt: bad path prefix compression");
+            return None;
+        }
+
+        path.extend_from_slice(&previous_path[..previous_path.len() - strip]);
+        reader.read_until(b'\0', &mut path).ok()?;
+        path.pop();
+    } else {
+        reader.read_until(b'\0', &mut path).ok()?;
+        path.pop();
+
+        // Padding, the entry (including the NUL we just read) ends on an
+        // 8-byte boundary
+        let entry_len = reader.position() - start_offset;
+        let padding = (8 - (entry_len % 8)) % 8;
+        reader.set_position(reader.position() + padding);
+    }
+
+    previous_path.clone_from(&path);
+
+    Some(IndexEntry {
+        ctime,
+        mtime,
+        dev,
+        ino,
+        mode,
+        uid,
+        gid,
+        size,
+        id: id.into(),
+        assume_valid: flags & FLAG_ASSUME_VALID != 0,
+        stage: ((flags & FLAG_STAGE_MASK) >> FLAG_STAGE_SHIFT) as u8,
+        skip_worktree: extended_flags & EXT_FLAG_SKIP_WORKTREE != 0,
+        intent_to_add: extended_flags & EXT_FLAG_INTENT_TO_ADD != 0,
+        path,
+    })
+}
+
+fn write_index_time(out: &mut Vec<u8>, time: IndexTime) {
+    out.write_u32::<BigEndian>(time.secs).unwrap();
+    out.write_u32::<BigEndian>(time.nsecs).unwrap();
+}
+
+/// The inverse of `parse_index_entry`
+fn write_index_entry(out: &mut Vec<u8>, entry: &IndexEntry, version: u32, previous_path: &mut Vec<u8>) {
+    let start_offset = out.len();
+
+    write_index_time(out, entry.ctime);
+    write_index_time(out, entry.mtime);
+    out.write_u32::<BigEndian>(entry.dev).unwrap();
+    out.write_u32::<BigEndian>(entry.ino).unwrap();
+    out.write_u32::<BigEndian>(entry.mode).unwrap();
+    out.write_u32::<BigEndian>(entry.uid).unwrap();
+    out.write_u32::<BigEndian>(entry.gid).unwrap();
+    out.write_u32::<BigEndian>(entry.size).unwrap();
+    out.extend_from_slice(&entry.id[..]);
+
+    let path = entry.path.as_slice();
+
+    // Longer names are stored with the maximum length and found by their NUL
+    let mut flags = path.len().min(0xfff) as u16;
+    flags |= ((entry.stage as u16) << FLAG_STAGE_SHIFT) & FLAG_STAGE_MASK;
+
+    if entry.assume_valid {
+        flags |= FLAG_ASSUME_VALID;
+    }
+
+    if entry.has_extended_flags() {
+        flags |= FLAG_EXTENDED;
+    }
+
+    out.write_u16::<BigEndian>(flags).unwrap();
+
+    if entry.has_extended_flags() {
+        let mut extended_flags = 0;
+
+        if entry.skip_worktree {
+            extended_flags |= EXT_FLAG_SKIP_WORKTREE;
+        }
+
+        if entry.intent_to_add {
+            extended_flags |= EXT_FLAG_INTENT_TO_ADD;
+        }
+
+        out.write_u16::<BigEndian>(extended_flags).unwrap();
+    }
+
+    if version == 4 {
+        let common = previous_path.iter()
+            .zip(path)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        write_varint(out, (previous_path.len() - common) as u64);
+        out.extend_from_slice(&path[common..]);
+        out.push(0);
+    } else {
+        out.extend_from_slice(path);
+
+        // At least one NUL, up to the next 8-byte boundary
+        let entry_len = out.len() - start_offset;
+        out.resize(out.len() + 8 - entry_len % 8, 0);
+    }
+
+    previous_path.clear();
+    previous_path.extend_from_slice(path);
+}
+
+// Same variable width encoding as the OBJ_OFS_DELTA base offset
+fn read_varint<R: Read>(reader: &mut R) -> Option<u64> {
+    let mut byte = reader.read_u8().ok()?;
+    let mut value = (byte & 0x7f) as u64;
+
+    while byte & 0x80 != 0 {
+        value += 1;
+        byte = reader.read_u8().ok()?;
+        value = (value << 7) | (byte & 0x7f) as u64;
+    }
+
+    Some(value)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+
+    while value != 0 {
+        value -= 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+
+    bytes.reverse();
+    out.extend_from_slice(&bytes);
+}
@@ -0,0 +1,394 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process;
+
+use sha1::{Digest, Sha1};
+
+use crate::store::{
+    delta::{apply_delta_data, read_negative_relative_offset},
+    pack::{read_kind_length_obj_header, DeltaKind, Inflater, ObjectKind, PackedObjectKind},
+    pack_stream::{PackEntryKind, PackStream},
+    util::hash_object,
+    ObjectId,
+};
+use crate::repo::git_path;
+use crate::SHA1_HASH_SIZE;
+
+/// Offsets past this don't fit an idx's 4-byte offset table, and go in
+/// the 8-byte one
+const LARGE_OFFSET: u64 = 0x7fffffff;
+
+/// How many bytes of objects read back from the pack are kept for the
+/// deltas against them, as git's core.deltaBaseCacheLimit defaults to
+const DELTA_BASE_CACHE_LIMIT: usize = 96 << 20;
+
+/// An entry of the pack, once its object is known
+struct IndexedEntry {
+    id: ObjectId,
+    offset: u64,
+    crc32: u32,
+}
+
+/// A delta of the pack, whose object is known once its base is
+struct PendingDelta {
+    offset: u64,
+    base: PackEntryKind,
+    crc32: u32,
+}
+
+/// Writes a v2 idx for the pack at `pack_path` to `idx_path`, as
+/// `index-pack` does, giving the pack's checksum.
+///
+/// Every delta has to have its base in the pack, so thin packs aren't
+/// taken. The whole pack is checked on the way, its checksum included.
+/// Only where each object is and its type are kept, the bases of deltas
+/// being read back from the pack as they're needed.
+pub fn index_pack(pack_path: &Path, idx_path: &Path) -> io::Result<[u8; SHA1_HASH_SIZE]> {
+    let mut stream = PackStream::open(pack_path)
+        .ok_or_else(|| io::Error::other(format!("unable to read {}", pack_path.display())))?;
+
+    let mut types: HashMap<u64, &'static str> = HashMap::new();
+    let mut deltas = Vec::new();
+    let mut indexed = Vec::new();
+
+    for entry in stream.by_ref() {
+        match entry.kind.type_str() {
+            Some(kind) => {
+                indexed.push(IndexedEntry { id: hash_object(kind, &entry.data), offset: entry.offset, crc32: entry.crc32 });
+                types.insert(entry.offset, kind);
+            }
+            None => deltas.push(PendingDelta { offset: entry.offset, base: entry.kind, crc32: entry.crc32 }),
+        }
+    }
+
+    if !stream.is_complete() {
+        return Err(io::Error::other(format!("{} is truncated or corrupt", pack_path.display())));
+    }
+
+    let mut offsets: HashMap<ObjectId, u64> = indexed.iter().map(|entry| (entry.id, entry.offset)).collect();
+    let mut objects = PackObjects::open(pack_path)?;
+
+    // A delta's base may be another delta, resolved in a later pass
+    while !deltas.is_empty() {
+        let count = deltas.len();
+        let mut unresolved = Vec::new();
+
+        for delta in deltas {
+            let base = match delta.base {
+                PackEntryKind::OfsDelta(offset) => Some(offset),
+                PackEntryKind::RefDelta(id) => offsets.get(&id).copied(),
+                _ => None,
+            };
+
+            let Some(&kind) = base.and_then(|base| types.get(&base)) else {
+                unresolved.push(delta);
+                continue;
+            };
+
+            let data = objects.read(delta.offset, &offsets)
+                .ok_or_else(|| io::Error::other(format!("pack has a bad delta at offset {}", delta.offset)))?;
+
+            let id = hash_object(kind, &data);
+
+            indexed.push(IndexedEntry { id, offset: delta.offset, crc32: delta.crc32 });
+            offsets.insert(id, delta.offset);
+            types.insert(delta.offset, kind);
+        }
+
+        if unresolved.len() == count {
+            return Err(io::Error::other(format!("pack has {count} unresolved deltas")));
+        }
+
+        deltas = unresolved;
+    }
+
+    let checksum = stream.trailer().ok_or_else(|| io::Error::other("pack has no checksum"))?;
+
+    fs::write(idx_path, idx_contents(indexed, checksum))?;
+
+    Ok(checksum)
+}
+
+/// Reads the objects of a pack being indexed back by offset, resolving
+/// deltas against the bases it's found, with the objects read most
+/// recently kept up to `DELTA_BASE_CACHE_LIMIT` bytes
+struct PackObjects {
+    pack: BufReader<File>,
+    inflater: Inflater,
+
+    cache: HashMap<u64, Vec<u8>>,
+
+    /// The offsets in the cache, the oldest first, and the bytes they hold
+    cached: VecDeque<u64>,
+    cached_size: usize,
+}
+
+impl PackObjects {
+    fn open(pack_path: &Path) -> io::Result<PackObjects> {
+        Ok(PackObjects {
+            pack: BufReader::new(File::open(pack_path)?),
+            inflater: Inflater::new(),
+            cache: HashMap::new(),
+            cached: VecDeque::new(),
+            cached_size: 0,
+        })
+    }
+
+    /// The contents of the object at `offset`, a REF_DELTA's base being
+    /// found among the resolved `offsets`. None if it's corrupt.
+    fn read(&mut self, offset: u64, offsets: &HashMap<ObjectId, u64>) -> Option<Vec<u8>> {
+        // The deltas from the object down to a base at hand, the first
+        // applied last
+        let mut chain = Vec::new();
+        let mut at = offset;
+
+        let mut data = loop {
+            if let Some(data) = self.cache.get(&at) {
+                break data.clone();
+            }
+
+            let (kind, data) = self.read_entry(at)?;
+
+            match kind {
+                PackEntryKind::OfsDelta(base) => at = base,
+                PackEntryKind::RefDelta(id) => at = *offsets.get(&id)?,
+                _ => {
+                    self.keep(at, data.clone());
+                    break data;
+                }
+            }
+
+            chain.push(data);
+        };
+
+        while let Some(delta) = chain.pop() {
+            data = apply_delta_data(&data, &delta)?;
+        }
+
+        self.keep(offset, data.clone());
+
+        Some(data)
+    }
+
+    /// The entry at `offset` as it's stored, its kind and inflated data
+    fn read_entry(&mut self, offset: u64) -> Option<(PackEntryKind, Vec<u8>)> {
+        self.pack.seek(SeekFrom::Start(offset)).ok()?;
+
+        let (kind, size) = read_kind_length_obj_header(&mut self.pack)?;
+
+        let kind = match kind {
+            PackedObjectKind::Object(ObjectKind::Commit) => PackEntryKind::Commit,
+            PackedObjectKind::Object(ObjectKind::Tree) => PackEntryKind::Tree,
+            PackedObjectKind::Object(ObjectKind::Blob) => PackEntryKind::Blob,
+            PackedObjectKind::Object(ObjectKind::Tag) => PackEntryKind::Tag,
+            PackedObjectKind::Delta(DeltaKind::Offset) => {
+                PackEntryKind::OfsDelta(offset.checked_sub(read_negative_relative_offset(&mut self.pack)?)?)
+            }
+            PackedObjectKind::Delta(DeltaKind::Reference) => {
+                let mut base = [0u8; SHA1_HASH_SIZE];
+                self.pack.read_exact(&mut base).ok()?;
+
+                PackEntryKind::RefDelta(base.into())
+            }
+        };
+
+        Some((kind, self.inflater.inflate(&mut self.pack, size as usize)?.to_vec()))
+    }
+
+    /// Caches the object at `offset`, dropping the oldest to make room
+    fn keep(&mut self, offset: u64, data: Vec<u8>) {
+        if data.len() > DELTA_BASE_CACHE_LIMIT || self.cache.contains_key(&offset) {
+            return;
+        }
+
+        self.cached_size += data.len();
+        self.cached.push_back(offset);
+        self.cache.insert(offset, data);
+
+        while self.cached_size > DELTA_BASE_CACHE_LIMIT {
+            let Some(oldest) = self.cached.pop_front() else {
+                break;
+            };
+
+            self.cached_size -= self.cache.remove(&oldest).map_or(0, |data| data.len());
+        }
+    }
+}
+
+/// Reads a whole pack from `pack` into the repository, indexed, giving
+/// its name: "pack-<checksum>", its files being that with ".pack" and
+/// ".idx". The pack is written to a temporary file first, and only takes
+/// its name once it's been indexed, before the idx takes its own.
+///
+/// Nothing past the end of the pack is read, so it needn't be followed by
+/// the end of the stream, as a pushed pack isn't.
+pub fn store_pack(pack: &mut impl Read) -> io::Result<String> {
+    let pack_dir = git_path("objects/pack");
+    fs::create_dir_all(&pack_dir)?;
+
+    let tmp_pack = pack_dir.join(format!("tmp_pack_{}", process::id()));
+    let tmp_idx = pack_dir.join(format!("tmp_idx_{}", process::id()));
+
+    let stored = (|| {
+        copy_pack(pack, File::create(&tmp_pack)?)?;
+
+        let name = format!("pack-{}", hex::encode(index_pack(&tmp_pack, &tmp_idx)?));
+
+        for (tmp, extension) in [(&tmp_pack, "pack"), (&tmp_idx, "idx")] {
+            fs::set_permissions(tmp, fs::Permissions::from_mode(0o444))?;
+            fs::rename(tmp, pack_dir.join(format!("{name}.{extension}")))?;
+        }
+
+        Ok(name)
+    })();
+
+    if stored.is_err() {
+        let _ = fs::remove_file(&tmp_pack);
+        let _ = fs::remove_file(&tmp_idx);
+    }
+
+    stored
+}
+
+/// Copies the pack at the start of `input` to `out`, reading its entries
+/// just far enough to tell where it ends. Whether they're any good is for
+/// indexing to find out.
+fn copy_pack(input: &mut impl Read, out: impl Write) -> io::Result<()> {
+    let malformed = |what: &str| io::Error::other(format!("malformed pack: {what}"));
+
+    let mut reader = CopyingReader { inner: BufReader::new(input), out: BufWriter::new(out), error: None };
+
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+
+    if &header[..4] != b"PACK" {
+        return Err(malformed("bad signature"));
+    }
+
+    let count = u32::from_be_bytes(header[8..].try_into().expect("four bytes"));
+    let mut inflater = Inflater::new();
+
+    for _ in 0..count {
+        let (kind, size) = read_kind_length_obj_header(&mut reader).ok_or_else(|| malformed("bad entry header"))?;
+
+        match kind {
+            PackedObjectKind::Delta(DeltaKind::Offset) => {
+                read_negative_relative_offset(&mut reader).ok_or_else(|| malformed("bad delta base offset"))?;
+            }
+            PackedObjectKind::Delta(DeltaKind::Reference) => reader.read_exact(&mut [0u8; SHA1_HASH_SIZE])?,
+            PackedObjectKind::Object(_) => {}
+        }
+
+        inflater.inflate(&mut reader, size as usize).ok_or_else(|| malformed("bad entry data"))?;
+    }
+
+    reader.read_exact(&mut [0u8; SHA1_HASH_SIZE])?;
+
+    match reader.error {
+        Some(err) => Err(err),
+        None => reader.out.flush(),
+    }
+}
+
+/// Writes what's consumed from `inner` to `out`, keeping the first error
+/// writing it for after
+struct CopyingReader<R, W: Write> {
+    inner: R,
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead, W: Write> BufRead for CopyingReader<R, W> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Ok(buf) = self.inner.fill_buf() {
+            let consumed = &buf[..amt.min(buf.len())];
+
+            if let Err(err) = self.out.write_all(consumed) {
+                self.error.get_or_insert(err);
+            }
+        }
+
+        self.inner.consume(amt);
+    }
+}
+
+impl<R: BufRead, W: Write> Read for CopyingReader<R, W> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let buf = self.fill_buf()?;
+        let len = buf.len().min(out.len());
+
+        out[..len].copy_from_slice(&buf[..len]);
+        self.consume(len);
+
+        Ok(len)
+    }
+}
+
+/// Pack idx v2:
+///   4-byte magic "\377tOc", 4-byte version 2
+///   256 4-byte fanout counts, the number of ids up to each first byte
+///   the ids, sorted
+///   a 4-byte CRC32 for each
+///   a 4-byte offset for each, or with the top bit set an index into
+///   the 8-byte offsets that follow
+///   the pack's checksum, then the idx's own
+fn idx_contents(mut entries: Vec<IndexedEntry>, pack_checksum: [u8; SHA1_HASH_SIZE]) -> Vec<u8> {
+    entries.sort_by_key(|entry| entry.id);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(b"\xfftOc");
+    out.extend_from_slice(&2u32.to_be_bytes());
+
+    let mut fanout = [0u32; 256];
+
+    for entry in &entries {
+        fanout[entry.id[0] as usize] += 1;
+    }
+
+    let mut total = 0;
+
+    for count in fanout {
+        total += count;
+        out.extend_from_slice(&total.to_be_bytes());
+    }
+
+    for entry in &entries {
+        out.extend_from_slice(&*entry.id);
+    }
+
+    for entry in &entries {
+        out.extend_from_slice(&entry.crc32.to_be_bytes());
+    }
+
+    let mut large_offsets = Vec::new();
+
+    for entry in &entries {
+        let offset = match entry.offset > LARGE_OFFSET {
+            true => {
+                large_offsets.push(entry.offset);
+                0x80000000 | (large_offsets.len() as u32 - 1)
+            }
+            false => entry.offset as u32,
+        };
+
+        out.extend_from_slice(&offset.to_be_bytes());
+    }
+
+    for offset in large_offsets {
+        out.extend_from_slice(&offset.to_be_bytes());
+    }
+
+    out.extend_from_slice(&pack_checksum);
+
+    let checksum = Sha1::digest(&out);
+    out.extend_from_slice(&checksum);
+
+    out
+}
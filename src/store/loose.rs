@@ -1,50 +1,104 @@
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use flate2::bufread::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use crate::config::Config;
+use crate::lockfile::LockFile;
+use crate::repo::git_path;
 use crate::store::{
-    object::{
-        parse_blob,
-        parse_commit,
-        parse_tree,
-        parse_tag,
-    },
+    object::parse_object,
+    util::hash_object,
     GitObject,
     ObjectId
 };
 
-pub fn get_loose_object(id: ObjectId) -> Option<GitObject> {
+/// Opts into writing loose objects in the zstd layout
+pub const ZSTD_EXTENSION: &str = "extensions.zstdLooseObjects";
+
+// A zstd loose object starts with the magic and the layout's version,
+// followed by a zstd frame of what a zlib one holds
+const ZSTD_MAGIC: &[u8; 4] = b"GZSD";
+const ZSTD_VERSION: u8 = 1;
+
+/// How a loose object is compressed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LooseFormat {
+    /// zlib, as git writes them
+    Zlib,
+
+    /// zstd behind a version header, which is quicker to read and write
+    /// but which git can't read
+    Zstd,
+}
+
+impl LooseFormat {
+    /// The format new loose objects are written in, zstd when the
+    /// repository has the zstd extension
+    pub fn configured(config: &Config) -> LooseFormat {
+        match config.get_bool(ZSTD_EXTENSION).unwrap_or(false) {
+            true => LooseFormat::Zstd,
+            false => LooseFormat::Zlib,
+        }
+    }
+}
+
+pub(crate) fn loose_object_path(id: ObjectId) -> PathBuf {
     let id_str = id.to_string();
+    git_path(format!("objects/{}/{}", &id_str[..2], &id_str[2..]))
+}
+
+/// Opens the loose object `id` for reading it decompressed, whichever
+/// format it's stored in
+pub(crate) fn open_loose_object(id: ObjectId) -> Option<(LooseFormat, Box<dyn Read>)> {
+    let mut reader = BufReader::new(File::open(loose_object_path(id)).ok()?);
+    let start = reader.fill_buf().ok()?;
+
+    if !start.starts_with(ZSTD_MAGIC) {
+        return Some((LooseFormat::Zlib, Box::new(ZlibDecoder::new(reader))));
+    }
+
+    if start.get(ZSTD_MAGIC.len()) != Some(&ZSTD_VERSION) {
+        eprintln!("Loose object {id} is in an unknown version of the zstd layout");
+        return None;
+    }
 
-    let obj_path = format!(".git/objects/{}/{}", &id_str[..2], &id_str[2..]);
-    let obj_stream = File::open(obj_path).ok()?;
+    reader.consume(ZSTD_MAGIC.len() + 1);
+
+    Some((LooseFormat::Zstd, Box::new(zstd::Decoder::with_buffer(reader).ok()?)))
+}
+
+/// The format the loose object `id` is stored in, its type, size and
+/// contents
+fn read_loose_object(id: ObjectId) -> Option<(LooseFormat, String, usize, Vec<u8>)> {
+    let (format, mut decoder) = open_loose_object(id)?;
 
     // Raw object
     let mut data = Vec::new();
 
     // Decompress
-    compress::zlib::Decoder::new(obj_stream)
-        .read_to_end(&mut data).ok()?;
+    decoder.read_to_end(&mut data).ok()?;
 
     // Git object TLV encoding:
     //  <obj-type> ' ' <byte-size> '\0' <object-data>
-    let [header, data] = data.splitn(2, |&b| b == b'\0')
-        .by_ref().collect::<Vec<&[u8]>>()[..] else {
-            return None;
-        };
-
-    let [kind, size] = header.splitn(2, |&b| b == b' ')
-        .by_ref().collect::<Vec<&[u8]>>()[..] else {
-            return None;
-        };
-
-    let size = String::from_utf8_lossy(size).parse::<usize>().ok()?;
-
-    let data = match kind {
-        b"blob" => parse_blob(data)?,
-        b"commit" => parse_commit(data)?,
-        b"tree" => parse_tree(data)?,
-        b"tag" => parse_tag(data)?,
-        _ => return None
-    };
+    let nul = data.iter().position(|&b| b == b'\0')?;
+    let header = std::str::from_utf8(&data[..nul]).ok()?;
+    let (kind, size) = header.split_once(' ')?;
+
+    let kind = kind.to_string();
+    let size = size.parse::<usize>().ok()?;
+
+    data.drain(..=nul);
+
+    Some((format, kind, size, data))
+}
+
+pub fn get_loose_object(id: ObjectId) -> Option<GitObject> {
+    let (_, kind, size, data) = read_loose_object(id)?;
+
+    let data = parse_object(&kind, &data)?;
 
     Some(GitObject {
         id,
@@ -52,3 +106,75 @@ pub fn get_loose_object(id: ObjectId) -> Option<GitObject> {
         data,
     })
 }
+
+/// What the stored contents of the loose object `id` hash to, `id`
+/// itself unless the object is corrupt
+pub fn hash_loose_object(id: ObjectId) -> Option<ObjectId> {
+    let (_, kind, _, data) = read_loose_object(id)?;
+
+    Some(hash_object(&kind, &data))
+}
+
+/// Compresses and stores an object, in the same encoding that
+/// `get_loose_object` reads and the format the repository is configured
+/// for. Objects are immutable, so they're left read-only like git does.
+pub fn write_loose_object(kind: &str, data: &[u8]) -> io::Result<ObjectId> {
+    write_loose_object_to(&git_path("objects"), kind, data, LooseFormat::configured(&Config::load()))
+}
+
+/// Like `write_loose_object`, for the object directory `objects_dir` and
+/// in `format`
+pub fn write_loose_object_to(objects_dir: &Path, kind: &str, data: &[u8], format: LooseFormat) -> io::Result<ObjectId> {
+    let id = hash_object(kind, data);
+
+    let id_str = id.to_string();
+    let obj_path = objects_dir.join(&id_str[..2]).join(&id_str[2..]);
+
+    let mut lock = LockFile::acquire(&obj_path)?;
+    let header = format!("{} {}\0", kind, data.len());
+
+    match format {
+        LooseFormat::Zlib => {
+            let mut encoder = ZlibEncoder::new(&mut lock, Compression::default());
+            encoder.write_all(header.as_bytes())?;
+            encoder.write_all(data)?;
+            encoder.finish()?;
+        }
+        LooseFormat::Zstd => {
+            lock.write_all(ZSTD_MAGIC)?;
+            lock.write_all(&[ZSTD_VERSION])?;
+
+            let mut encoder = zstd::Encoder::new(&mut lock, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            encoder.write_all(header.as_bytes())?;
+            encoder.write_all(data)?;
+            encoder.finish()?;
+        }
+    }
+
+    lock.commit()?;
+
+    fs::set_permissions(&obj_path, fs::Permissions::from_mode(0o444))?;
+
+    Ok(id)
+}
+
+/// Rewrites the loose object `id` in `format`, returning whether it
+/// needed rewriting. The object is checked against its id first, so a
+/// corrupt object isn't given a new lease of life.
+pub fn convert_loose_object(id: ObjectId, format: LooseFormat) -> io::Result<bool> {
+    let Some((current, kind, _, data)) = read_loose_object(id) else {
+        return Err(io::Error::other(format!("unable to read loose object {id}")));
+    };
+
+    if current == format {
+        return Ok(false);
+    }
+
+    if hash_object(&kind, &data) != id {
+        return Err(io::Error::other(format!("loose object {id} is corrupt")));
+    }
+
+    write_loose_object_to(&git_path("objects"), &kind, &data, format)?;
+
+    Ok(true)
+}
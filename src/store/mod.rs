@@ -1,17 +1,29 @@
-mod loose;
+pub(crate) mod loose;
 mod pack;
-mod object;
+pub mod pack_stream;
+pub mod object;
 mod delta;
+pub mod index;
+pub mod commit_graph;
+pub mod bloom;
+pub mod tree;
 pub mod util;
+pub mod blob;
+pub mod index_pack;
+pub mod pack_write;
 
 use std::fmt::Display;
+use std::io;
 use std::option::Option;
 
 use crate::store::{
-    loose::get_loose_object,
-    pack::get_packed_object
+    loose::{convert_loose_object, get_loose_object, hash_loose_object, loose_object_path, write_loose_object},
+    pack::{get_packed_object, get_packed_objects, load_pack_indexes},
 };
 
+pub use crate::store::loose::{LooseFormat, ZSTD_EXTENSION};
+
+use crate::quote::quote_path;
 use crate::SHA1_HASH_SIZE;
 
 /// The primary interface into the git object store
@@ -23,7 +35,7 @@ pub enum StoreBackend {
     Packed
 }
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
 pub struct ObjectId([u8; SHA1_HASH_SIZE]);
 
 #[derive(Debug)]
@@ -59,7 +71,8 @@ pub enum GitObjectData {
         object: ObjectId,
         kind: String,
         tag: String,
-        tagger: String,
+        // Very old tags predate the tagger header
+        tagger: Option<String>,
         // If signed, the signature resides in the message itself
         message: Vec<u8>,
     }
@@ -69,25 +82,85 @@ pub enum GitObjectData {
 #[derive(Debug, PartialEq)]
 pub struct TreeEntry {
     pub mode: u32,
-    pub kind: String,
-    pub path: String,
+
+    /// The type the mode says the object is, see `stored_kind` for the
+    /// type it's stored as
+    pub kind: &'static str,
+
+    /// The entry's name, which needn't be UTF-8
+    pub path: Vec<u8>,
     pub id: ObjectId,
 }
 
 impl GitObject {
-    pub fn type_str(&self) -> &str {
-        match self.data {
+    pub fn type_str(&self) -> &'static str {
+        self.data.type_str()
+    }
+}
+
+impl GitObjectData {
+    pub fn type_str(&self) -> &'static str {
+        match self {
             GitObjectData::Blob { .. } => "blob",
             GitObjectData::Tree { .. } => "tree",
             GitObjectData::Commit { .. } => "commit",
             GitObjectData::Tag { .. } => "tag",
         }
     }
+
+    /// Encodes the object's contents canonically, the inverse of parsing.
+    ///
+    /// For an object git wrote, hashing this with `type_str` gives back its
+    /// id. Tree entries are written in git's sort order.
+    pub fn serialize(&self) -> Vec<u8> {
+        match self {
+            GitObjectData::Blob { data } => data.clone(),
+            GitObjectData::Tree { entries } => {
+                let mut entries: Vec<_> = entries.iter()
+                    .map(|entry| (entry.mode, entry.path.clone(), entry.id))
+                    .collect();
+
+                tree::serialize_tree(&mut entries)
+            },
+            GitObjectData::Commit { tree, parents, author, committer, encoding, gpgsig, message } => {
+                object::serialize_commit(
+                    tree,
+                    parents,
+                    author,
+                    committer,
+                    encoding.as_deref(),
+                    gpgsig.as_deref(),
+                    message,
+                )
+            },
+            GitObjectData::Tag { object, kind, tag, tagger, message } => {
+                object::serialize_tag(object, kind, tag, tagger.as_deref(), message)
+            },
+        }
+    }
+}
+
+impl TreeEntry {
+    /// Whether the entry is a submodule, whose commit is in another
+    /// repository, so is never looked up in this one
+    pub fn is_gitlink(&self) -> bool {
+        self.mode == tree::MODE_GITLINK
+    }
+
+    /// The type of the object as it's stored, which should be `kind`.
+    /// None if it isn't in the store, as a submodule's commit never is.
+    pub fn stored_kind(&self) -> Option<&'static str> {
+        if self.is_gitlink() {
+            return None;
+        }
+
+        GitObjectStore::get(self.id).map(|object| object.type_str())
+    }
 }
 
 impl Display for TreeEntry {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:06o} {} {} {}", self.mode, self.kind, self.id, self.path)
+        write!(f, "{:06o} {} {} {}", self.mode, self.kind, self.id, quote_path(&self.path, true))
     }
 }
 
@@ -135,7 +208,9 @@ impl Display for GitObject {
                 writeln!(f, "object {}", object)?;
                 writeln!(f, "type {}", kind)?;
                 writeln!(f, "tag {}", tag)?;
-                writeln!(f, "tagger {}", tagger)?;
+                if let Some(tagger) = tagger {
+                    writeln!(f, "tagger {}", tagger)?;
+                }
                 writeln!(f)?;
                 write!(f, "{}", String::from_utf8_lossy(message))
             },
@@ -149,13 +224,113 @@ impl GitObjectStore {
     ///
     /// This will work reguardless of the format the object currently
     /// is stored in, eg. loose or packed.
+    ///
+    /// A concurrent gc can pack a loose object, or repack a packed one,
+    /// while the object is being looked for, so as git does, an object
+    /// which can't be found or read is looked for once more.
     pub fn get(id: ObjectId) -> Option<GitObject> {
         use StoreBackend::*;
 
-        match util::find_backend(id)? {
+        let read = || match util::find_backend(id)? {
             Loose => get_loose_object(id),
             Packed => get_packed_object(id)
+        };
+
+        read().or_else(read)
+    }
+
+    /// Whether `id` is in the store, from the loose object's path or the
+    /// pack idxs alone, without reading or parsing the object.
+    ///
+    /// Looked for twice if need be, as by `get`, in case a concurrent gc
+    /// moves it.
+    pub fn exists(id: ObjectId) -> bool {
+        let find = || {
+            loose_object_path(id).exists()
+                || load_pack_indexes().iter().any(|(_, idx)| idx.locations.contains_key(&id))
+        };
+
+        find() || find()
+    }
+
+    /// Opens the blob `id` for reading, without loading all of it into
+    /// memory as `get` does. None if it isn't in the store or isn't a blob.
+    pub fn open_blob(id: ObjectId) -> Option<blob::BlobReader> {
+        blob::open_blob(id)
+    }
+
+    /// Re-hashes the stored contents of the loose object `id`, giving
+    /// back `id` unless the object is corrupt. Packed objects are checked
+    /// by `pack_stream::verify_pack`.
+    pub fn hash_loose(id: ObjectId) -> Option<ObjectId> {
+        hash_loose_object(id)
+    }
+
+    /// Retrieves many objects at once, in the same order as `ids`.
+    ///
+    /// Much faster than calling `get` for each of them when most are
+    /// packed, as packed objects are read in one sequential pass over
+    /// each pack.
+    pub fn get_many(ids: &[ObjectId]) -> Vec<Option<GitObject>> {
+        let mut objects = get_packed_objects(ids);
+
+        for (object, &id) in objects.iter_mut().zip(ids) {
+            if object.is_none() {
+                *object = get_loose_object(id);
+            }
         }
+
+        // Loose objects which were packed and pruned by a concurrent gc
+        // after the packs were read are in the new pack
+        let missing: Vec<usize> = (0..ids.len()).filter(|&i| objects[i].is_none()).collect();
+
+        if !missing.is_empty() {
+            let retry: Vec<ObjectId> = missing.iter().map(|&i| ids[i]).collect();
+
+            for (i, object) in missing.into_iter().zip(get_packed_objects(&retry)) {
+                objects[i] = object;
+            }
+        }
+
+        objects
+    }
+
+    /// Stores an object of type `kind` ("blob", "tree", ...) whose encoded
+    /// contents are `data`, returning its id.
+    ///
+    /// New objects are always written loose, nothing is written if the
+    /// object is already in the store.
+    pub fn write(kind: &str, data: &[u8]) -> Option<ObjectId> {
+        let id = util::hash_object(kind, data);
+
+        if util::find_backend(id).is_some() {
+            return Some(id);
+        }
+
+        match write_loose_object(kind, data) {
+            Ok(id) => Some(id),
+            Err(err) => {
+                eprintln!("Unable to write {kind} {id}: {err}");
+                None
+            }
+        }
+    }
+
+    /// Rewrites every loose object in `format`, returning how many weren't
+    /// in it already. Packed objects are left as they are.
+    pub fn convert_loose_objects(format: LooseFormat) -> io::Result<usize> {
+        let mut ids = Vec::new();
+        util::visit_loose_ids(None, |id| ids.push(id));
+
+        let mut converted = 0;
+
+        for id in ids {
+            if convert_loose_object(id, format)? {
+                converted += 1;
+            }
+        }
+
+        Ok(converted)
     }
 
 }
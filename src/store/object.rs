@@ -1,14 +1,73 @@
+use std::fmt;
 use std::iter::Peekable;
 use std::collections::HashMap;
+use crate::date;
 use crate::store::{
+    tree::kind_for_mode,
     GitObjectData, 
-    GitObjectStore,
     TreeEntry,
     ObjectId
 };
 
 use crate::SHA1_HASH_SIZE;
 
+/// Who made a commit or tag and when, from its author, committer or
+/// tagger header.
+///
+/// Objects keep these headers as they were written, so they re-serialize
+/// exactly, this is parsed from them as needed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+
+    /// Seconds since the epoch
+    pub timestamp: i64,
+
+    /// Offset east of UTC in seconds
+    pub tz_offset: i32,
+}
+
+impl Identity {
+    /// Identity format:
+    ///   <name> ' ' '<' <email> '>' ' ' <timestamp> ' ' <tz>
+    ///
+    /// eg. "A U Thor <author@example.com> 1112911993 -0700"
+    ///
+    /// Parsed as leniently as git does, only the email's brackets are
+    /// required. A missing or malformed date is read as the epoch, in UTC.
+    pub fn parse(ident: &str) -> Option<Identity> {
+        let (name, rest) = ident.split_once('<')?;
+        let (email, _) = rest.split_once('>')?;
+
+        // The date follows the last '>', in case the email has another
+        let date = &rest[rest.rfind('>')? + 1..];
+        let mut fields = date.split_whitespace();
+
+        let timestamp = fields.next().and_then(|timestamp| timestamp.parse().ok());
+        let tz_offset = fields.next().and_then(date::parse_offset);
+
+        let (timestamp, tz_offset) = match (timestamp, tz_offset) {
+            (Some(timestamp), Some(tz_offset)) => (timestamp, tz_offset),
+            _ => (0, 0),
+        };
+
+        Some(Identity {
+            name: name.trim().to_string(),
+            email: email.trim().to_string(),
+            timestamp,
+            tz_offset,
+        })
+    }
+}
+
+/// The header value, in the format `Identity::parse` reads
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} <{}> {} {}", self.name, self.email, self.timestamp, date::format_offset(self.tz_offset))
+    }
+}
+
 /// Commit object format (general structure):
 ///   "tree " <tree-sha> \n
 ///   "parent " <parent-sha> \n (can have multiple parent headers)
@@ -83,6 +142,51 @@ pub fn parse_commit(data: &[u8]) -> Option<GitObjectData> {
     })
 }
 
+/// Encodes a commit in the format `parse_commit` reads.
+///
+/// Multiline header values are continued with a leading space on each
+/// following line.
+pub fn serialize_commit(
+    tree: &ObjectId,
+    parents: &[ObjectId],
+    author: &str,
+    committer: &str,
+    encoding: Option<&str>,
+    gpgsig: Option<&str>,
+    message: &[u8],
+) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    let mut header = |key: &str, value: &str| {
+        data.extend_from_slice(key.as_bytes());
+        data.push(b' ');
+        data.extend_from_slice(value.replace('\n', "\n ").as_bytes());
+        data.push(b'\n');
+    };
+
+    header("tree", &tree.to_string());
+
+    for parent in parents {
+        header("parent", &parent.to_string());
+    }
+
+    header("author", author);
+    header("committer", committer);
+
+    if let Some(encoding) = encoding {
+        header("encoding", encoding);
+    }
+
+    if let Some(gpgsig) = gpgsig {
+        header("gpgsig", gpgsig);
+    }
+
+    data.push(b'\n');
+    data.extend_from_slice(message);
+
+    data
+}
+
 /// Tree object format:
 ///   <tree-entry>
 ///
@@ -105,6 +209,24 @@ pub fn parse_tree(data: &[u8]) -> Option<GitObjectData> {
     })
 }
 
+/// Parses the contents of an object of type `kind` ("blob", "tree", ...)
+pub fn parse_object(kind: &str, data: &[u8]) -> Option<GitObjectData> {
+    match kind {
+        "blob" => parse_blob(data),
+        "tree" => parse_tree(data),
+        "commit" => parse_commit(data),
+        "tag" => parse_tag(data),
+        _ => None,
+    }
+}
+
+/// Tag object format:
+///   "object " <object-sha> \n
+///   "type " <object-type> \n
+///   "tag " <tag-name> \n
+///   "tagger " <user-info> \n (missing from very old tags)
+///   \n
+///   <tag-message>
 pub fn parse_tag(data: &[u8]) -> Option<GitObjectData> {
     let mut data = data.iter().peekable();
 
@@ -119,8 +241,8 @@ pub fn parse_tag(data: &[u8]) -> Option<GitObjectData> {
     let tag = headers.get("tag")?
         .first()?.to_string();
 
-    let tagger = headers.get("tagger")?
-        .first()?.to_string();
+    let tagger = headers.get("tagger")
+        .and_then(|t| t.first().map(String::to_string));
 
     let object: ObjectId = hex::decode(object)
         .ok()?.as_slice().try_into().ok()?;
@@ -141,6 +263,28 @@ pub fn parse_tag(data: &[u8]) -> Option<GitObjectData> {
     })
 }
 
+/// Encodes a tag in the format `parse_tag` reads
+pub fn serialize_tag(
+    object: &ObjectId,
+    kind: &str,
+    tag: &str,
+    tagger: Option<&str>,
+    message: &[u8],
+) -> Vec<u8> {
+    let mut data = format!("object {object}\ntype {kind}\ntag {tag}\n");
+
+    if let Some(tagger) = tagger {
+        data.push_str(&format!("tagger {tagger}\n"));
+    }
+
+    data.push('\n');
+
+    let mut data = data.into_bytes();
+    data.extend_from_slice(message);
+
+    data
+}
+
 fn parse_header<'a, I>(data: &mut Peekable<I>) -> Option<(String, String)>
 where
     I: Iterator<Item = &'a u8>
@@ -198,13 +342,10 @@ where
     let id: Vec<u8> = data.take(SHA1_HASH_SIZE).copied().collect();
 
     let mode = std::str::from_utf8(&mode[..]).ok()?;
-    let path = std::str::from_utf8(&path[..]).ok()?;
-
-    let path = path.to_string();
     let mode = u32::from_str_radix(mode, 8).ok()?;
 
     let id: ObjectId = id.as_slice().try_into().ok()?;
-    let kind = GitObjectStore::get(id)?.type_str().to_string();
+    let kind = kind_for_mode(mode);
 
     Some(TreeEntry {
         mode,
@@ -221,3 +362,113 @@ pub fn parse_blob(data: &[u8]) -> Option<GitObjectData> {
         data: data.to_vec(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tree::{serialize_tree, MODE_BLOB, MODE_EXECUTABLE, MODE_GITLINK, MODE_SYMLINK, MODE_TREE};
+    use crate::store::util::hash_object;
+    use crate::store::GitObjectStore;
+    use crate::testtools::TestRepo;
+
+    const TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+    const PARENT: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+    const AUTHOR: &str = "A U Thor <author@example.com> 1112911993 -0700";
+
+    fn id(hex: &str) -> ObjectId {
+        ObjectId::try_from(hex.to_string()).unwrap()
+    }
+
+    /// Parses `data` as a `kind`, checking it serializes back to the same
+    /// bytes, so the same id, and that those parse to the same object
+    fn assert_round_trips(kind: &str, data: &[u8]) {
+        let parsed = parse_object(kind, data).unwrap_or_else(|| panic!("unable to parse {kind}"));
+        let serialized = parsed.serialize();
+
+        assert_eq!(String::from_utf8_lossy(&serialized), String::from_utf8_lossy(data));
+        assert_eq!(hash_object(kind, &serialized), hash_object(kind, data));
+        assert_eq!(parse_object(kind, &serialized), Some(parsed));
+    }
+
+    #[test]
+    fn blobs_round_trip() {
+        assert_round_trips("blob", b"");
+        assert_round_trips("blob", b"hello\n");
+        assert_round_trips("blob", &[0, 0xff, b'\n', 0x80, b'\r']);
+    }
+
+    #[test]
+    fn trees_round_trip() {
+        assert_round_trips("tree", b"");
+
+        let mut entries = vec![
+            (MODE_BLOB, b"README".to_vec(), id(PARENT)),
+            (MODE_TREE, b"src".to_vec(), id(TREE)),
+            (MODE_EXECUTABLE, b"run.sh".to_vec(), id(PARENT)),
+            (MODE_SYMLINK, b"link".to_vec(), id(PARENT)),
+            (MODE_GITLINK, b"module".to_vec(), id(PARENT)),
+            (MODE_BLOB, b"caf\xe9".to_vec(), id(PARENT)),
+            // Sorts after "src", as trees sort as if their name ends in '/'
+            (MODE_BLOB, b"src.rs".to_vec(), id(PARENT)),
+        ];
+
+        assert_round_trips("tree", &serialize_tree(&mut entries));
+    }
+
+    #[test]
+    fn commits_round_trip() {
+        assert_round_trips("commit", format!("tree {TREE}\nauthor {AUTHOR}\ncommitter {AUTHOR}\n\nroot\n").as_bytes());
+
+        assert_round_trips("commit", format!(
+            "tree {TREE}\nparent {PARENT}\nparent {TREE}\nauthor {AUTHOR}\ncommitter {AUTHOR}\nencoding ISO-8859-1\n\n\
+             Merge\n\nWith a body\n\n  and indentation, but no newline at the end"
+        ).as_bytes());
+
+        assert_round_trips("commit", format!(
+            "tree {TREE}\nparent {PARENT}\nauthor {AUTHOR}\ncommitter {AUTHOR}\n\
+             gpgsig -----BEGIN PGP SIGNATURE-----\n \n iQEzBAABCAAdFiEE\n -----END PGP SIGNATURE-----\n\nsigned\n"
+        ).as_bytes());
+    }
+
+    #[test]
+    fn tags_round_trip() {
+        assert_round_trips("tag", format!("object {PARENT}\ntype commit\ntag v1.0\ntagger {AUTHOR}\n\nrelease\n").as_bytes());
+
+        // Very old tags have no tagger
+        assert_round_trips("tag", format!("object {TREE}\ntype tree\ntag old\n\n").as_bytes());
+    }
+
+    #[test]
+    fn stored_objects_rehash_to_their_ids() {
+        let repo = TestRepo::new().unwrap();
+
+        let first = repo.commit_files(&[("a", b"a\n")], "first").unwrap();
+        let second = repo.commit_files(&[("a", b"b\n"), ("dir/c", b"c\n")], "second\n\nwith a body").unwrap();
+        let tag = repo.tag("v1.0", second, "commit", "release").unwrap();
+
+        repo.pack_objects().unwrap();
+
+        // And some left loose
+        let loose = repo.blob(b"loose\n").unwrap();
+        let tree = repo.tree_from_files(&[("x", b"x\n")]).unwrap();
+        let _cwd = repo.enter().unwrap();
+
+        let mut ids = vec![first, second, tag, tree, loose];
+
+        for id in [first, second] {
+            let Some(GitObjectData::Commit { tree, .. }) = GitObjectStore::get(id).map(|object| object.data) else {
+                panic!("{id} isn't a commit");
+            };
+
+            ids.push(tree);
+        }
+
+        for id in ids {
+            let object = GitObjectStore::get(id).unwrap_or_else(|| panic!("unable to read {id}"));
+            let serialized = object.data.serialize();
+
+            assert_eq!(hash_object(object.type_str(), &serialized), id);
+            assert_eq!(parse_object(object.type_str(), &serialized).as_ref(), Some(&object.data));
+        }
+    }
+}
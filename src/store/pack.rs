@@ -1,20 +1,25 @@
 use std::fs::File;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use flate2::{Decompress, FlushDecompress, Status};
 use crate::store::{
     object,
     GitObjectData,
-    util,
     ObjectId,
     GitObject,
     delta::resolve_delta
 };
 use std::io::{
+    BufRead,
     BufReader,
     Read,
     Seek,
     SeekFrom,
 };
 use byteorder::{BigEndian, ReadBytesExt};
+use crate::repo::git_path;
 
 // A 4-byte magic number \377tOc
 const PACK_IDX_MAGIC: u32 = 0xff744f63;
@@ -25,18 +30,33 @@ const PACK_IDX_MAGIC: u32 = 0xff744f63;
 #[derive(Debug)]
 pub struct GitPackIdx {
     // A map of ObjectId's to object offsets within a packfile
-    pub locations: HashMap<ObjectId, usize>
+    pub locations: HashMap<ObjectId, usize>,
+
+    // CRC32s of each object's packed representation (v2 only)
+    pub crc32s: HashMap<ObjectId, u32>,
+
+    // The checksum at the end of the packfile this idx was written for
+    pub pack_checksum: [u8; 20],
 }
 
 pub fn parse_pack_idx(idx_file_stream: File) -> Option<GitPackIdx> {
+    // Both versions end with the pack's checksum followed by the idx's own
+    let mut pack_checksum = [0u8; 20];
+    let mut trailer_reader = idx_file_stream.try_clone().ok()?;
+    trailer_reader.seek(SeekFrom::End(-40)).ok()?;
+    trailer_reader.read_exact(&mut pack_checksum).ok()?;
+
     let mut idx_reader = BufReader::new(idx_file_stream);
+    idx_reader.seek(SeekFrom::Start(0)).ok()?;
 
     let first_word = idx_reader.read_u32::<BigEndian>().ok()?;
 
-    match first_word {
+    let idx = match first_word {
         PACK_IDX_MAGIC => parse_pack_idx_modern(idx_reader),
         _ => parse_pack_idx_legacy(idx_reader, first_word)
-    }
+    }?;
+
+    Some(GitPackIdx { pack_checksum, ..idx })
 }
 
 // Pack idx v1
@@ -72,7 +92,9 @@ pub fn parse_pack_idx_legacy(mut idx_reader: BufReader<File>, _fanout_zero: u32)
     }
 
     Some(GitPackIdx {
-        locations
+        locations,
+        crc32s: HashMap::new(),
+        pack_checksum: [0; 20],
     })
 }
 
@@ -117,8 +139,11 @@ pub fn parse_pack_idx_v2(mut idx_reader: BufReader<File>) -> Option<GitPackIdx>
     // A table of 4-byte CRC32 values of the packed object data. This is new in
     // v2 so compressed data can be copied directly from pack to pack during
     // repacking without undetected data corruption.
-    // TODO: implement validating these or something?
-    idx_reader.seek_relative((oid_entry_count * 4) as i64).ok()?;
+    let mut crc32s = HashMap::new();
+
+    for oid in &oids {
+        crc32s.insert(*oid, idx_reader.read_u32::<BigEndian>().ok()?);
+    }
 
     // A table of 4-byte offset values (in network byte order). These are usually
     // 31-bit pack file offsets, but large offsets are encoded as an index into
@@ -165,7 +190,9 @@ pub fn parse_pack_idx_v2(mut idx_reader: BufReader<File>) -> Option<GitPackIdx>
     }
 
     Some(GitPackIdx {
-        locations
+        locations,
+        crc32s,
+        pack_checksum: [0; 20],
     })
 }
 
@@ -191,26 +218,179 @@ pub enum PackedObjectKind {
 
 /// Fetch an object from some packfile
 pub fn get_packed_object(id: ObjectId) -> Option<GitObject> {
-    let mut pack_name = None;
+    get_packed_objects(&[id]).pop().flatten()
+}
+
+/// Fetches many objects at once, in the same order as `ids`, with `None`
+/// for any which aren't in a pack.
+///
+/// Each pack's idx is only parsed once, and objects are read in pack
+/// order so the pack is read sequentially rather than seeking back and
+/// forth. The inflater and its buffer are shared between objects.
+///
+/// Another process repacking meanwhile can remove a pack after its idx
+/// was read, so as git does, objects an idx lists but which can't be read
+/// are looked for again once in a fresh scan of the pack directory, where
+/// the pack that replaced it will be.
+pub fn get_packed_objects(ids: &[ObjectId]) -> Vec<Option<GitObject>> {
+    let (mut results, failed) = read_packed_objects(ids, &load_pack_indexes());
+
+    if failed.is_empty() {
+        return results;
+    }
+
+    let retry: Vec<ObjectId> = failed.iter().map(|&i| ids[i]).collect();
+    let (retried, _) = read_packed_objects(&retry, &load_pack_indexes());
+
+    for (i, object) in failed.into_iter().zip(retried) {
+        results[i] = object;
+    }
+
+    results
+}
+
+/// Reads `ids` from `packs`, also giving the positions of those which a
+/// pack's idx lists but couldn't be read
+fn read_packed_objects(ids: &[ObjectId], packs: &[(String, Arc<GitPackIdx>)]) -> (Vec<Option<GitObject>>, Vec<usize>) {
+    let mut results: Vec<Option<GitObject>> = ids.iter().map(|_| None).collect();
+    let mut failed = Vec::new();
+
+    // (pack, offset, position in `ids`)
+    let mut locations = Vec::new();
+
+    for (i, id) in ids.iter().enumerate() {
+        let location = packs.iter()
+            .enumerate()
+            .find_map(|(pack, (_, idx))| Some((pack, *idx.locations.get(id)?)));
+
+        if let Some((pack, offset)) = location {
+            locations.push((pack, offset, i));
+        }
+    }
+
+    locations.sort_unstable();
+
+    let mut inflater = Inflater::new();
+    let mut current_pack = None;
+    let mut pack_reader = None;
+
+    for (pack, offset, i) in locations {
+        if current_pack != Some(pack) {
+            current_pack = Some(pack);
+            pack_reader = open_pack(&packs[pack].0, &packs[pack].1);
+        }
+
+        let object = pack_reader.as_mut().and_then(|reader| {
+            reader.seek(SeekFrom::Start(offset as u64)).ok()?;
+            parse_packed_object_and_size(reader, &packs[pack].1, &mut inflater)
+        });
+
+        match object {
+            Some((data, size)) => results[i] = Some(GitObject { id: ids[i], size, data }),
+            None => failed.push(i),
+        }
+    }
+
+    (results, failed)
+}
+
+/// How many times the pack directory is read before settling for what
+/// was found, if packs keep disappearing while it's being read
+const PACK_SCAN_ATTEMPTS: usize = 3;
+
+/// The idxs this process has parsed, by path, with when each was last
+/// modified as it was parsed. An idx is only parsed again if it changes.
+static PARSED_INDEXES: Mutex<BTreeMap<PathBuf, (SystemTime, Arc<GitPackIdx>)>> = Mutex::new(BTreeMap::new());
+
+/// Every pack's name (eg. "pack-<hash>") with its parsed idx.
+///
+/// The directory is read each time, so new packs are found, but each
+/// idx is only parsed the first time it's seen.
+///
+/// A repack writes its new pack before removing the packs it replaces, so
+/// an idx which disappears while the directory is being read means the new
+/// pack may have been missed, and the directory is read again.
+pub(crate) fn load_pack_indexes() -> Vec<(String, Arc<GitPackIdx>)> {
+    let mut packs = Vec::new();
+
+    for _ in 0..PACK_SCAN_ATTEMPTS {
+        let complete;
+        (packs, complete) = scan_pack_indexes();
+
+        if complete {
+            break;
+        }
+    }
+
+    packs
+}
+
+/// Reads the pack directory once, also giving whether no idx disappeared
+/// part way through
+fn scan_pack_indexes() -> (Vec<(String, Arc<GitPackIdx>)>, bool) {
+    let Ok(pack_dir) = std::path::absolute(git_path("objects/pack")) else {
+        return (Vec::new(), true);
+    };
+
+    let Ok(entries) = std::fs::read_dir(&pack_dir) else {
+        return (Vec::new(), true);
+    };
 
-    util::visit_pack_ids(true, |desc| {
-        if desc.oid == id {
-            pack_name = desc.pack_name;
+    let mut parsed = PARSED_INDEXES.lock().unwrap_or_else(|err| err.into_inner());
+    let mut packs = Vec::new();
+    let mut complete = true;
+
+    for entry in entries.flatten() {
+        let Ok(filename) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        // Packs being written have temporary names, eg. a repack's
+        // ".tmp-<pid>-pack-<hash>.idx", and aren't complete yet
+        let Some(pack_name) = filename.strip_suffix(".idx").filter(|name| name.starts_with("pack-")) else {
+            continue;
+        };
+
+        let path = entry.path();
+        let modified = entry.metadata().and_then(|metadata| metadata.modified());
+
+        if let (Ok(modified), Some((parsed_modified, idx))) = (&modified, parsed.get(&path)) {
+            if modified == parsed_modified {
+                packs.push((pack_name.to_string(), idx.clone()));
+                continue;
+            }
         }
-    });
 
-    let pack_name = pack_name?;
+        let idx = match File::open(&path) {
+            Ok(file) => parse_pack_idx(file).map(Arc::new),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                complete = false;
+                continue;
+            }
+            Err(_) => None,
+        };
 
-    let pack_file = format!(".git/objects/pack/{}.pack", &pack_name);
-    let idx_file = format!(".git/objects/pack/{}.idx", &pack_name);
+        let Some(idx) = idx else {
+            eprintln!("Unable to read pack index {filename}");
+            continue;
+        };
 
-    let pack_file_stream = File::open(pack_file).ok()?;
-    let idx_file_stream = File::open(idx_file).ok()?;
+        if let Ok(modified) = modified {
+            parsed.insert(path, (modified, idx.clone()));
+        }
 
-    let pack_idx = parse_pack_idx(idx_file_stream)?;
+        packs.push((pack_name.to_string(), idx));
+    }
+
+    (packs, complete)
+}
 
-    let offset = *pack_idx.locations.get(&id)?;
-    let mut pack_reader = BufReader::new(pack_file_stream);
+/// Opens the pack `idx` was read from. None if it isn't that pack any
+/// more, as a concurrent repack can replace a pack with one of the same
+/// name but a different layout, which the idx's offsets don't fit.
+pub(crate) fn open_pack(pack_name: &str, idx: &GitPackIdx) -> Option<BufReader<File>> {
+    let pack_file = git_path(format!("objects/pack/{pack_name}.pack"));
+    let mut pack_reader = BufReader::new(File::open(pack_file).ok()?);
 
     let mut magic = [0u8; 4];
     pack_reader.read_exact(&mut magic).ok()?;
@@ -220,15 +400,90 @@ pub fn get_packed_object(id: ObjectId) -> Option<GitObject> {
         return None;
     }
 
-    pack_reader.seek(SeekFrom::Start(offset as u64)).ok()?;
+    let mut checksum = [0u8; 20];
+    pack_reader.seek(SeekFrom::End(-20)).ok()?;
+    pack_reader.read_exact(&mut checksum).ok()?;
 
-    let (data, size) = parse_packed_object_and_size(pack_reader)?;
+    if checksum != idx.pack_checksum {
+        return None;
+    }
 
-    Some(GitObject {
-        id,
-        size,
-        data
-    })
+    pack_reader.seek(SeekFrom::Start(4)).ok()?;
+
+    Some(pack_reader)
+}
+
+/// Inflates zlib streams embedded in a pack, reusing the decompressor and
+/// its output buffer from one object to the next.
+pub struct Inflater {
+    decompress: Decompress,
+    buffer: Vec<u8>,
+}
+
+impl Default for Inflater {
+    fn default() -> Self {
+        Inflater::new()
+    }
+}
+
+impl Inflater {
+    pub fn new() -> Inflater {
+        Inflater {
+            decompress: Decompress::new(true),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Inflates the stream at the reader's position, which must decompress
+    /// to exactly `size` bytes. Only the compressed bytes are consumed, so
+    /// the reader is left at the end of the stream.
+    pub fn inflate<R: BufRead>(&mut self, reader: &mut R, size: usize) -> Option<&[u8]> {
+        self.decompress.reset(true);
+        self.buffer.clear();
+
+        // One spare byte so an overlong stream is noticed
+        self.buffer.reserve(size + 1);
+
+        loop {
+            let input = reader.fill_buf().ok()?;
+            let at_eof = input.is_empty();
+
+            let total_in = self.decompress.total_in();
+            let total_out = self.decompress.total_out();
+
+            let status = self.decompress
+                .decompress_vec(input, &mut self.buffer, FlushDecompress::None)
+                .ok()?;
+
+            let consumed = (self.decompress.total_in() - total_in) as usize;
+            reader.consume(consumed);
+
+            if self.buffer.len() > size {
+                eprintln!("Pack object larger than its header says");
+                return None;
+            }
+
+            match status {
+                Status::StreamEnd => break,
+                _ if at_eof => {
+                    eprintln!("Pack truncated");
+                    return None;
+                },
+                _ if consumed == 0 && self.decompress.total_out() == total_out => {
+                    // Out of buffer space before the end of the stream
+                    self.buffer.reserve(1);
+                },
+                _ => {},
+            }
+        }
+
+        if self.buffer.len() != size {
+            eprintln!("Pack object smaller than its header says");
+            return None;
+        }
+
+        Some(&self.buffer)
+    }
 }
 
 fn parse_object(kind: ObjectKind, data: &[u8]) -> Option<GitObjectData> {
@@ -242,7 +497,7 @@ fn parse_object(kind: ObjectKind, data: &[u8]) -> Option<GitObjectData> {
     }
 }
 
-fn parse_packed_object_and_size(mut pack_reader: BufReader<File>)
+fn parse_packed_object_and_size(pack_reader: &mut BufReader<File>, idx: &GitPackIdx, inflater: &mut Inflater)
     -> Option<(GitObjectData, usize)>
 {
     use PackedObjectKind::*;
@@ -250,19 +505,15 @@ fn parse_packed_object_and_size(mut pack_reader: BufReader<File>)
     let start_offset = pack_reader.stream_position().ok()?;
 
     // n-byte type and length (3-bit type, (n-1)*7+4-bit length)
-    let (kind, length) = read_kind_length_obj_header(&mut pack_reader)?;
+    let (kind, length) = read_kind_length_obj_header(pack_reader)?;
 
     let object = match kind {
         // (undeltified representation)
         //   compressed data
         Object(object_kind) => {
-            // object buffer
-            let mut data = vec![0u8; length as usize];
-
-            let mut decomp_stream = compress::zlib::Decoder::new(&mut pack_reader);
-            decomp_stream.read_exact(&mut data).ok()?;
+            let data = inflater.inflate(pack_reader, length as usize)?;
 
-            parse_object(object_kind, &data)
+            parse_object(object_kind, data)
         },
         // (deltified representation)
         //   base object name if OBJ_REF_DELTA or a negative relative
@@ -272,7 +523,7 @@ fn parse_packed_object_and_size(mut pack_reader: BufReader<File>)
         Delta(_) => {
             pack_reader.seek(SeekFrom::Start(start_offset)).ok()?;
 
-            let (kind, resolved) = resolve_delta(&mut pack_reader)?;
+            let (kind, resolved) = resolve_delta(pack_reader, idx)?;
 
             match kind {
                 Object(object_kind) => { parse_object(object_kind, &resolved) },
@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use flate2::Crc;
+use sha1::{Digest, Sha1};
+
+use crate::store::{
+    delta::read_negative_relative_offset,
+    pack::{load_pack_indexes, parse_pack_idx, read_kind_length_obj_header, DeltaKind, Inflater, ObjectKind, PackedObjectKind},
+    util::hash_object,
+    ObjectId,
+};
+use crate::SHA1_HASH_SIZE;
+use crate::repo::git_path;
+
+/// What a pack entry holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackEntryKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+
+    /// A delta against the entry at this (absolute) offset
+    OfsDelta(u64),
+
+    /// A delta against the object with this id
+    RefDelta(ObjectId),
+}
+
+impl PackEntryKind {
+    /// The object type, None for deltas
+    pub fn type_str(&self) -> Option<&'static str> {
+        match self {
+            PackEntryKind::Commit => Some("commit"),
+            PackEntryKind::Tree => Some("tree"),
+            PackEntryKind::Blob => Some("blob"),
+            PackEntryKind::Tag => Some("tag"),
+            PackEntryKind::OfsDelta(_) | PackEntryKind::RefDelta(_) => None,
+        }
+    }
+}
+
+/// One entry of a pack, as it's stored
+#[derive(Debug)]
+pub struct PackEntry {
+    pub offset: u64,
+    pub kind: PackEntryKind,
+
+    /// The inflated data, the object's contents or for deltas the delta
+    /// itself
+    pub data: Vec<u8>,
+
+    /// The number of bytes the entry takes up in the pack, header included
+    pub packed_size: u64,
+
+    /// CRC32 of the packed entry, as recorded in a v2 idx
+    pub crc32: u32,
+}
+
+impl PackEntry {
+    /// The object's id, only known without resolving for undeltified entries
+    pub fn id(&self) -> Option<ObjectId> {
+        Some(hash_object(self.kind.type_str()?, &self.data))
+    }
+}
+
+/// Keeps track of where we are in the pack, and the checksums of what's
+/// been read so far
+struct TrackingReader<R> {
+    inner: R,
+    position: u64,
+    crc: Crc,
+    checksum: Sha1,
+}
+
+impl<R: BufRead> BufRead for TrackingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Ok(buf) = self.inner.fill_buf() {
+            let consumed = &buf[..amt.min(buf.len())];
+            self.crc.update(consumed);
+            self.checksum.update(consumed);
+        }
+
+        self.position += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+impl<R: BufRead> Read for TrackingReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let buf = self.fill_buf()?;
+        let len = buf.len().min(out.len());
+
+        out[..len].copy_from_slice(&buf[..len]);
+        self.consume(len);
+
+        Ok(len)
+    }
+}
+
+/// Reads every entry of a pack from start to finish, in the order they're
+/// stored.
+///
+/// Unlike looking objects up through the idx this never seeks, so a whole
+/// pack is read sequentially. Deltas are returned as they're stored rather
+/// than being resolved.
+///
+/// Errors are reported and end the iteration early, `is_complete` tells
+/// whether the whole pack was read and its trailing checksum matched.
+pub struct PackStream {
+    reader: TrackingReader<BufReader<File>>,
+    inflater: Inflater,
+    count: u32,
+    read: u32,
+    trailer: Option<[u8; SHA1_HASH_SIZE]>,
+    complete: bool,
+    failed: bool,
+}
+
+impl PackStream {
+    /// Pack header:
+    ///   4-byte signature "PACK"
+    ///   4-byte version number (2 or 3)
+    ///   4-byte number of objects
+    pub fn open<P: AsRef<Path>>(path: P) -> Option<PackStream> {
+        PackStream::open_at(path, 0)
+    }
+
+    /// Like `open`, for a pack starting `offset` bytes into the file, as it
+    /// does in a bundle. Entry offsets are relative to the pack's start.
+    pub fn open_at<P: AsRef<Path>>(path: P, offset: u64) -> Option<PackStream> {
+        let path = path.as_ref();
+
+        let mut file = File::open(path)
+            .map_err(|err| eprintln!("Unable to open {}: {err}", path.display()))
+            .ok()?;
+
+        file.seek(SeekFrom::Start(offset)).ok()?;
+
+        let mut reader = TrackingReader {
+            inner: BufReader::new(file),
+            position: 0,
+            crc: Crc::new(),
+            checksum: Sha1::new(),
+        };
+
+        let mut signature = [0u8; 4];
+        reader.read_exact(&mut signature).ok()?;
+
+        if &signature != b"PACK" {
+            eprintln!("{}: bad pack signature", path.display());
+            return None;
+        }
+
+        let version = reader.read_u32::<BigEndian>().ok()?;
+
+        if !(2..=3).contains(&version) {
+            eprintln!("{}: unsupported pack version {version}", path.display());
+            return None;
+        }
+
+        let count = reader.read_u32::<BigEndian>().ok()?;
+
+        Some(PackStream {
+            reader,
+            inflater: Inflater::new(),
+            count,
+            read: 0,
+            trailer: None,
+            complete: false,
+            failed: false,
+        })
+    }
+
+    /// The number of entries the pack's header says it has
+    pub fn object_count(&self) -> u32 {
+        self.count
+    }
+
+    /// Whether every entry has been read and the pack's checksum matched
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The checksum at the end of the pack, once every entry has been
+    /// read, whether or not it matched
+    pub fn trailer(&self) -> Option<[u8; SHA1_HASH_SIZE]> {
+        self.trailer
+    }
+
+    /// Pack entry:
+    ///   n-byte type and length (3-bit type, (n-1)*7+4-bit length)
+    ///   OBJ_OFS_DELTA: negative offset of the base from this entry
+    ///   OBJ_REF_DELTA: base object name
+    ///   compressed data
+    fn read_entry(&mut self) -> Option<PackEntry> {
+        let offset = self.reader.position;
+        self.reader.crc.reset();
+
+        let (kind, size) = read_kind_length_obj_header(&mut self.reader)?;
+
+        let kind = match kind {
+            PackedObjectKind::Object(ObjectKind::Commit) => PackEntryKind::Commit,
+            PackedObjectKind::Object(ObjectKind::Tree) => PackEntryKind::Tree,
+            PackedObjectKind::Object(ObjectKind::Blob) => PackEntryKind::Blob,
+            PackedObjectKind::Object(ObjectKind::Tag) => PackEntryKind::Tag,
+            PackedObjectKind::Delta(DeltaKind::Offset) => {
+                let negative_offset = read_negative_relative_offset(&mut self.reader)?;
+
+                if negative_offset == 0 || negative_offset > offset {
+                    eprintln!("Delta base offset out of bounds at {offset}");
+                    return None;
+                }
+
+                PackEntryKind::OfsDelta(offset - negative_offset)
+            }
+            PackedObjectKind::Delta(DeltaKind::Reference) => {
+                let mut base = [0u8; SHA1_HASH_SIZE];
+                self.reader.read_exact(&mut base).ok()?;
+
+                PackEntryKind::RefDelta(base.into())
+            }
+        };
+
+        let data = self.inflater.inflate(&mut self.reader, size as usize)?.to_vec();
+
+        Some(PackEntry {
+            offset,
+            kind,
+            data,
+            packed_size: self.reader.position - offset,
+            crc32: self.reader.crc.sum(),
+        })
+    }
+
+    /// Checks the trailing checksum over everything before it
+    fn read_trailer(&mut self) -> Option<()> {
+        let expected = self.reader.checksum.clone().finalize();
+
+        let mut checksum = [0u8; SHA1_HASH_SIZE];
+        self.reader.read_exact(&mut checksum).ok()?;
+        self.trailer = Some(checksum);
+
+        if expected.as_slice() != checksum {
+            eprintln!("Pack checksum mismatch");
+            return None;
+        }
+
+        if !self.reader.fill_buf().ok()?.is_empty() {
+            eprintln!("Pack has trailing garbage");
+            return None;
+        }
+
+        Some(())
+    }
+}
+
+impl Iterator for PackStream {
+    type Item = PackEntry;
+
+    fn next(&mut self) -> Option<PackEntry> {
+        if self.failed || self.complete {
+            return None;
+        }
+
+        if self.read == self.count {
+            match self.read_trailer() {
+                Some(()) => self.complete = true,
+                None => self.failed = true,
+            }
+
+            return None;
+        }
+
+        let offset = self.reader.position;
+
+        match self.read_entry() {
+            Some(entry) => {
+                self.read += 1;
+                Some(entry)
+            }
+            None => {
+                eprintln!("Unable to read pack entry {} of {} at offset {offset}", self.read + 1, self.count);
+                self.failed = true;
+                None
+            }
+        }
+    }
+}
+
+/// The name (eg. "pack-<hash>") of every pack with an idx
+pub fn list_packs() -> Vec<String> {
+    let mut names: Vec<String> = load_pack_indexes()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+
+    names.sort();
+    names
+}
+
+/// Files in the pack directory that aren't part of a usable pack, with
+/// why, as git's count-objects reports them: anything a pack doesn't
+/// have, and a pack's files when either its .pack or .idx is missing
+pub fn pack_garbage() -> Vec<(PathBuf, &'static str)> {
+    // Besides the pair itself, what a pack can have alongside it
+    const EXTENSIONS: [&str; 7] = ["pack", "idx", "keep", "bitmap", "promisor", "rev", "mtimes"];
+
+    let Ok(entries) = fs::read_dir(git_path("objects/pack")) else {
+        return Vec::new();
+    };
+
+    let mut garbage = Vec::new();
+    let mut packs: HashMap<String, Vec<(PathBuf, &str)>> = HashMap::new();
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let filename = entry.file_name().to_string_lossy().to_string();
+
+        // The multi-pack-index and its bitmaps cover every pack
+        if filename.starts_with("multi-pack-index") {
+            continue;
+        }
+
+        let known = filename.rsplit_once('.')
+            .and_then(|(base, ext)| EXTENSIONS.iter().find(|&&known| known == ext).map(|&ext| (base, ext)));
+
+        match known {
+            Some((base, ext)) => packs.entry(base.to_string()).or_default().push((path, ext)),
+            None => garbage.push((path, "garbage found")),
+        }
+    }
+
+    for files in packs.into_values() {
+        let has = |ext| files.iter().any(|&(_, file_ext)| file_ext == ext);
+
+        let reason = match (has("pack"), has("idx")) {
+            (true, true) => continue,
+            (true, false) => "no corresponding .idx",
+            (false, true) => "no corresponding .pack",
+            (false, false) => "no corresponding .idx or .pack",
+        };
+
+        garbage.extend(files.into_iter().map(|(path, _)| (path, reason)));
+    }
+
+    garbage.sort();
+    garbage
+}
+
+/// An entry of a verified pack, as `verify-pack -v` lists it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedEntry {
+    pub id: ObjectId,
+
+    /// The object's type, that of the base at the end of the chain for
+    /// deltas
+    pub kind: &'static str,
+
+    /// The inflated size of the entry, the delta's rather than the
+    /// object's for deltas
+    pub size: u64,
+
+    pub packed_size: u64,
+    pub offset: u64,
+
+    /// For deltas, the length of the chain down to an undeltified entry
+    /// and the object the delta applies to
+    pub delta: Option<(u32, ObjectId)>,
+}
+
+/// The outcome of checking a pack against its idx
+#[derive(Debug, Default)]
+pub struct PackVerification {
+    /// The entries read, in pack order
+    pub entries: Vec<VerifiedEntry>,
+
+    /// A description of each problem found
+    pub problems: Vec<String>,
+}
+
+/// Checks a pack in the repository against its idx, see `verify_pack_files`.
+///
+/// Returns a description of each problem found.
+pub fn verify_pack(pack_name: &str) -> Vec<String> {
+    let pack_dir = git_path("objects/pack");
+
+    verify_pack_files(
+        pack_dir.join(format!("{pack_name}.idx")),
+        pack_dir.join(format!("{pack_name}.pack")),
+    ).problems
+}
+
+/// Checks a pack against its idx in one sequential pass: every entry
+/// must be listed in the idx with a matching CRC, undeltified entries
+/// must hash to their listed id, every delta's base must be in the pack,
+/// the pack's checksum must match both its contents and the one the idx
+/// was written for, and the idx's own checksum must match.
+pub fn verify_pack_files<P: AsRef<Path>, Q: AsRef<Path>>(idx_path: P, pack_path: Q) -> PackVerification {
+    let (idx_path, pack_path) = (idx_path.as_ref(), pack_path.as_ref());
+    let mut verification = PackVerification::default();
+
+    let Some(idx) = File::open(idx_path).ok().and_then(parse_pack_idx) else {
+        verification.problems.push(format!("unable to read {}", idx_path.display()));
+        return verification;
+    };
+
+    if let Some(problem) = check_idx_checksum(idx_path) {
+        verification.problems.push(problem);
+    }
+
+    let Some(mut stream) = PackStream::open(pack_path) else {
+        verification.problems.push(format!("unable to read {}", pack_path.display()));
+        return verification;
+    };
+
+    let problems = &mut verification.problems;
+
+    if stream.object_count() as usize != idx.locations.len() {
+        problems.push(format!(
+            "pack has {} objects but its idx lists {}",
+            stream.object_count(),
+            idx.locations.len(),
+        ));
+    }
+
+    let ids_by_offset: HashMap<u64, ObjectId> = idx.locations.iter()
+        .map(|(&id, &offset)| (offset as u64, id))
+        .collect();
+
+    // Kept without their data, to follow delta chains once all are read
+    let mut entries = Vec::new();
+
+    for entry in stream.by_ref() {
+        let Some(&id) = ids_by_offset.get(&entry.offset) else {
+            problems.push(format!("entry at offset {} isn't in the idx", entry.offset));
+            continue;
+        };
+
+        if let Some(&crc32) = idx.crc32s.get(&id) {
+            if crc32 != entry.crc32 {
+                problems.push(format!("{id}: CRC mismatch at offset {}", entry.offset));
+            }
+        }
+
+        if let Some(actual) = entry.id() {
+            if actual != id {
+                problems.push(format!("{id}: hashes to {actual}"));
+            }
+        }
+
+        entries.push((id, entry.kind, entry.data.len() as u64, entry.packed_size, entry.offset));
+    }
+
+    if !stream.is_complete() {
+        problems.push("pack is truncated or corrupt".to_string());
+    } else if stream.trailer() != Some(idx.pack_checksum) {
+        problems.push(format!("{} does not match {}", pack_path.display(), idx_path.display()));
+    }
+
+    let kinds: HashMap<u64, &PackEntryKind> = entries.iter()
+        .map(|(_, kind, _, _, offset)| (*offset, kind))
+        .collect();
+
+    let base_offset = |kind: &PackEntryKind| match kind {
+        PackEntryKind::OfsDelta(offset) => Some(*offset),
+        PackEntryKind::RefDelta(id) => idx.locations.get(id).map(|&offset| offset as u64),
+        _ => None,
+    };
+
+    for (id, kind, size, packed_size, offset) in &entries {
+        let mut depth = 0;
+        let mut current = kind;
+
+        // Chains can't be longer than the pack, any that seems to be loops
+        let resolved = loop {
+            if let Some(kind) = current.type_str() {
+                break Some(kind);
+            }
+
+            match base_offset(current).and_then(|base| kinds.get(&base)) {
+                Some(base) if (depth as usize) < entries.len() => {
+                    depth += 1;
+                    current = base;
+                }
+                _ => break None,
+            }
+        };
+
+        let Some(resolved) = resolved else {
+            problems.push(format!("{id}: delta base isn't in the pack"));
+            continue;
+        };
+
+        let delta = base_offset(kind).map(|base| (depth, ids_by_offset[&base]));
+
+        verification.entries.push(VerifiedEntry {
+            id: *id,
+            kind: resolved,
+            size: *size,
+            packed_size: *packed_size,
+            offset: *offset,
+            delta,
+        });
+    }
+
+    verification
+}
+
+/// Checks the checksum at the end of an idx over everything before it
+fn check_idx_checksum(idx_path: &Path) -> Option<String> {
+    let Ok(contents) = fs::read(idx_path) else {
+        return Some(format!("unable to read {}", idx_path.display()));
+    };
+
+    let Some(split) = contents.len().checked_sub(SHA1_HASH_SIZE) else {
+        return Some(format!("{} is too short", idx_path.display()));
+    };
+
+    let (data, checksum) = contents.split_at(split);
+
+    match Sha1::digest(data).as_slice() == checksum {
+        true => None,
+        false => Some(format!("{} checksum mismatch", idx_path.display())),
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{self, Write};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha1::{Digest, Sha1};
+
+use crate::store::{GitObjectStore, ObjectId};
+
+/// Pack object type numbers
+fn pack_type(kind: &str) -> u8 {
+    match kind {
+        "commit" => 1,
+        "tree" => 2,
+        "blob" => 3,
+        _ => 4,
+    }
+}
+
+/// Writes everything through to `inner`, hashing it on the way
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha1,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the objects `ids` to `out` as a version 2 pack, as `pack-objects`
+/// streams one to the other side of a push. Every object is stored whole,
+/// so the pack is never thin, but bigger than a deltified one would be.
+///
+/// Pack format:
+///   "PACK" <version: u32> <object count: u32>
+///   (<type and size header> <zlib compressed contents>)*
+///   <SHA-1 of everything before it>
+pub fn write_pack<W: Write>(out: W, ids: &[ObjectId]) -> io::Result<()> {
+    let mut out = HashingWriter { inner: out, hasher: Sha1::new() };
+
+    out.write_all(b"PACK")?;
+    out.write_u32::<BigEndian>(2)?;
+    out.write_u32::<BigEndian>(ids.len() as u32)?;
+
+    for (object, &id) in GitObjectStore::get_many(ids).into_iter().zip(ids) {
+        let object = object.ok_or_else(|| io::Error::other(format!("unable to read {id}")))?;
+        let data = object.data.serialize();
+
+        // Object header: 3-bit type and a little endian base 128 size,
+        // 4 bits of which are in the first byte
+        let mut size = data.len();
+        let mut byte = (pack_type(object.type_str()) << 4) | (size & 0x0f) as u8;
+        size >>= 4;
+
+        while size != 0 {
+            out.write_all(&[byte | 0x80])?;
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+
+        out.write_all(&[byte])?;
+
+        let mut encoder = ZlibEncoder::new(&mut out, Compression::default());
+        encoder.write_all(&data)?;
+        encoder.finish()?;
+    }
+
+    let checksum = out.hasher.finalize();
+    out.inner.write_all(&checksum)?;
+    out.inner.flush()
+}
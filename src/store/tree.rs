@@ -0,0 +1,176 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use crate::store::{
+    GitObjectData,
+    GitObjectStore,
+    ObjectId,
+};
+
+pub const MODE_TREE: u32 = 0o040000;
+pub const MODE_BLOB: u32 = 0o100644;
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+pub const MODE_SYMLINK: u32 = 0o120000;
+pub const MODE_GITLINK: u32 = 0o160000;
+
+/// The type of object an entry with `mode` points at, known without
+/// looking the object up, which for a submodule's commit isn't even in
+/// this repository
+pub fn kind_for_mode(mode: u32) -> &'static str {
+    match mode {
+        MODE_TREE => "tree",
+        MODE_GITLINK => "commit",
+        _ => "blob",
+    }
+}
+
+/// Recursively lists every non-tree entry reachable from `tree`, keyed by
+/// its full slash separated path, as (mode, id) pairs. Paths are bytes,
+/// as git doesn't require them to be UTF-8.
+pub fn flatten_tree(tree: ObjectId) -> Option<BTreeMap<Vec<u8>, (u32, ObjectId)>> {
+    let mut files = BTreeMap::new();
+    flatten_tree_into(tree, b"", &mut files)?;
+    Some(files)
+}
+
+fn flatten_tree_into(
+    tree: ObjectId,
+    prefix: &[u8],
+    files: &mut BTreeMap<Vec<u8>, (u32, ObjectId)>,
+) -> Option<()> {
+    let GitObjectData::Tree { entries } = GitObjectStore::get(tree)?.data else {
+        eprintln!("{tree} is not a tree");
+        return None;
+    };
+
+    for entry in entries {
+        let path = [prefix, &entry.path].concat();
+
+        if entry.mode == MODE_TREE {
+            flatten_tree_into(entry.id, &[&path[..], b"/"].concat(), files)?;
+        } else {
+            files.insert(path, (entry.mode, entry.id));
+        }
+    }
+
+    Some(())
+}
+
+/// Git's tree entry order: byte-wise by name, except that trees sort as if
+/// their name had a trailing '/'.
+pub fn tree_entry_cmp(a: &[u8], a_mode: u32, b: &[u8], b_mode: u32) -> Ordering {
+    let suffix = |mode| if mode == MODE_TREE { Some(b'/') } else { None };
+
+    a.iter().copied().chain(suffix(a_mode))
+        .cmp(b.iter().copied().chain(suffix(b_mode)))
+}
+
+/// Encodes tree entries as (mode, name, id), sorting them first.
+///
+/// Tree entry format:
+///   <octal-mode> ' ' <name> '\0' <20-byte-id>
+///
+/// The mode has no leading zeros, so trees are "40000".
+pub fn serialize_tree(entries: &mut [(u32, Vec<u8>, ObjectId)]) -> Vec<u8> {
+    entries.sort_by(|(a_mode, a, _), (b_mode, b, _)| tree_entry_cmp(a, *a_mode, b, *b_mode));
+
+    let mut data = Vec::new();
+
+    for (mode, name, id) in entries.iter() {
+        data.extend_from_slice(format!("{mode:o} ").as_bytes());
+        data.extend_from_slice(name);
+        data.push(b'\0');
+        data.extend_from_slice(&id[..]);
+    }
+
+    data
+}
+
+enum TreeNode {
+    Leaf { mode: u32, id: ObjectId },
+    Tree(BTreeMap<Vec<u8>, TreeNode>),
+}
+
+/// Builds a hierarchy of trees from a flat list of paths.
+///
+/// Entries can be inserted in any order, intermediate trees are created
+/// as needed and `write` stores every tree bottom up, returning the root.
+#[derive(Default)]
+pub struct TreeBuilder {
+    root: BTreeMap<Vec<u8>, TreeNode>,
+}
+
+impl TreeBuilder {
+    pub fn new() -> TreeBuilder {
+        TreeBuilder::default()
+    }
+
+    /// Adds a non-tree entry at the slash separated `path`, `None` if the
+    /// path is invalid or something else is already in the way.
+    pub fn insert<P: AsRef<[u8]>>(&mut self, mode: u32, path: P, id: ObjectId) -> Option<()> {
+        let path = path.as_ref();
+        let shown = String::from_utf8_lossy(path);
+
+        let mut components: Vec<&[u8]> = path.split(|&byte| byte == b'/').collect();
+        let name = components.pop()?;
+
+        if components.iter().chain([&name]).any(|c| matches!(*c, b"" | b"." | b".." | b".git")) {
+            eprintln!("Invalid path '{shown}'");
+            return None;
+        }
+
+        let mut dir = &mut self.root;
+
+        for component in components {
+            let node = dir.entry(component.to_vec())
+                .or_insert_with(|| TreeNode::Tree(BTreeMap::new()));
+
+            dir = match node {
+                TreeNode::Tree(children) => children,
+                TreeNode::Leaf { .. } => {
+                    eprintln!("'{shown}' conflicts with the file '{}'", String::from_utf8_lossy(component));
+                    return None;
+                }
+            };
+        }
+
+        if dir.contains_key(name) {
+            eprintln!("'{shown}' is already in the tree");
+            return None;
+        }
+
+        dir.insert(name.to_vec(), TreeNode::Leaf { mode, id });
+
+        Some(())
+    }
+
+    /// Writes every tree to the store, returning the id of the root
+    pub fn write(&self) -> Option<ObjectId> {
+        self.write_with(&mut GitObjectStore::write)
+    }
+
+    /// Like `write`, storing each tree with `write_object(kind, data)`
+    pub fn write_with<F>(&self, write_object: &mut F) -> Option<ObjectId>
+    where
+        F: FnMut(&str, &[u8]) -> Option<ObjectId>,
+    {
+        write_tree_node(&self.root, write_object)
+    }
+}
+
+fn write_tree_node<F>(children: &BTreeMap<Vec<u8>, TreeNode>, write_object: &mut F) -> Option<ObjectId>
+where
+    F: FnMut(&str, &[u8]) -> Option<ObjectId>,
+{
+    let mut entries = Vec::new();
+
+    for (name, node) in children {
+        let (mode, id) = match node {
+            TreeNode::Leaf { mode, id } => (*mode, *id),
+            TreeNode::Tree(children) => (MODE_TREE, write_tree_node(children, write_object)?),
+        };
+
+        entries.push((mode, name.clone(), id));
+    }
+
+    write_object("tree", &serialize_tree(&mut entries))
+}
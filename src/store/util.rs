@@ -1,15 +1,18 @@
 use crate::MIN_USER_HASH_LEN;
-use std::fs::{read_dir, File};
+use std::fs::read_dir;
 use std::fmt;
 use std::ops::Deref;
 use hex::FromHexError;
-use std::path::Path;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use crate::repo::git_path;
 use crate::SHA1_HASH_SIZE;
 use std::array::TryFromSliceError;
+use sha1::{Digest, Sha1};
 use crate::store::{
     StoreBackend,
     ObjectId,
-    pack::parse_pack_idx
+    pack::load_pack_indexes
 };
 
 // Resolves an arbitrary length hex encoded string to an oid
@@ -73,6 +76,30 @@ pub fn resolve_id(id_str: &str) -> Option<ObjectId> {
 pub fn visit_loose_ids<T>(first_byte_hint: Option<u8>, mut visit: T) -> Option<()>
 where
     T: FnMut(ObjectId)
+{
+    visit_loose_files(first_byte_hint, false, |LooseFileDesc { oid, .. }| {
+        if let Some(oid) = oid {
+            visit(oid);
+        }
+    })
+}
+
+/// A file in one of the loose object fan-out directories
+pub struct LooseFileDesc {
+    /// None for files that aren't objects, eg. temporary files left by
+    /// an interrupted write
+    pub oid: Option<ObjectId>,
+    pub path: PathBuf,
+
+    /// The space the file takes up on disk, when asked for
+    pub disk_size: Option<u64>,
+}
+
+/// Like `visit_loose_ids`, but visits every file in the fan-out
+/// directories, with `include_size` also giving the space each takes up
+pub fn visit_loose_files<T>(first_byte_hint: Option<u8>, include_size: bool, mut visit: T) -> Option<()>
+where
+    T: FnMut(LooseFileDesc)
 {
     let mut visit_obj_dir = |obj_dir_path: &Path| -> Option<()> {
         let contents = read_dir(obj_dir_path).ok()?;
@@ -90,9 +117,19 @@ where
             let first_byte = &parent_path[parent_path.len()-2..];
 
             let id_str_full = format!("{first_byte}{filename}");
-            let id = id_str_full.try_into().ok()?;
 
-            visit(id);
+            // Blocks are 512 bytes whatever the filesystem's block size
+            let disk_size = match include_size {
+                true => Some(entry.metadata().ok()?.blocks() * 512),
+                false => None,
+            };
+
+            // Temporary and lock files can sit alongside objects
+            visit(LooseFileDesc {
+                oid: id_str_full.try_into().ok(),
+                path: obj_path,
+                disk_size,
+            });
         }
 
         Some(())
@@ -100,19 +137,24 @@ where
 
     match first_byte_hint {
         Some(first_byte) => {
-            let obj_dir = format!(".git/objects/{:02x}/", first_byte);
+            let obj_dir = git_path(format!("objects/{:02x}", first_byte));
 
-            visit_obj_dir(Path::new(&obj_dir))?;
+            visit_obj_dir(&obj_dir)?;
         },
         None => {
-            let store_dir = read_dir(".git/objects/").ok()?;
+            let store_dir = read_dir(git_path("objects")).ok()?;
 
             for dir_ent in store_dir {
                 let dir_ent = dir_ent.ok()?;
 
                 let file_type = dir_ent.file_type().ok()?;
 
-                if !file_type.is_dir() {
+                // Skip "info", "pack" and anything else that isn't a fan-out directory
+                let is_fanout = dir_ent.file_name()
+                    .to_str()
+                    .is_some_and(|name| name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit()));
+
+                if !file_type.is_dir() || !is_fanout {
                     continue;
                 }
 
@@ -135,45 +177,13 @@ pub fn visit_pack_ids<T>(include_pack_name: bool, mut visit: T) -> Option<()>
 where
     T: FnMut(PackObjectDesc)
 {
-    let idx_files = read_dir(".git/objects/pack/").ok()?;
-
-    for entry in idx_files {
-        let entry = entry.ok()?;
-
-        let filename = entry
-            .file_name()
-            .into_string()
-            .ok()?;
-
-        let is_idxfile = filename
-            .to_lowercase().ends_with(".idx");
-
-        if !is_idxfile {
-            continue;
-        }
-
-        let idx_path = format!(".git/objects/pack/{}", filename);
-
-        let file_stream = File::open(idx_path).ok()?;
-
+    for (pack_name, pack_idx) in load_pack_indexes() {
         // TODO: fix: we disregard offsets, and therefore do unnecessary work here :(
-        let pack_idx = parse_pack_idx(file_stream)?;
-
-        let objectids: Vec<ObjectId> = pack_idx.locations
-            .into_keys()
-            .collect();
-
-        for oid in objectids {
-            let pack_object_descriptor = PackObjectDesc {
+        for &oid in pack_idx.locations.keys() {
+            visit(PackObjectDesc {
                 oid,
-                pack_name: if include_pack_name {
-                    filename.strip_suffix(".idx").map(|f| f.to_string())
-                } else {
-                    None
-                }
-            };
-
-            visit(pack_object_descriptor)
+                pack_name: include_pack_name.then(|| pack_name.clone()),
+            })
         }
     }
 
@@ -192,15 +202,37 @@ pub fn find_backend(id: ObjectId) -> Option<StoreBackend> {
         }
     });
 
-    visit_pack_ids(false, |PackObjectDesc { oid, .. }| {
-        if oid == id {
-            backend = Some(StoreBackend::Packed);
-        }
-    });
+    if load_pack_indexes().iter().any(|(_, idx)| idx.locations.contains_key(&id)) {
+        backend = Some(StoreBackend::Packed);
+    }
 
     backend
 }
 
+/// Computes the id an object would be stored under, without storing it.
+///
+/// The hash covers the same "<kind> <size>\0<data>" encoding used by
+/// loose objects.
+pub fn hash_object(kind: &str, data: &[u8]) -> ObjectId {
+    let mut hasher = Sha1::new();
+
+    hasher.update(format!("{} {}\0", kind, data.len()));
+    hasher.update(data);
+
+    let id: [u8; SHA1_HASH_SIZE] = hasher.finalize().into();
+    id.into()
+}
+
+impl ObjectId {
+    /// The all-zero id, used where there is no object, eg. the old side of
+    /// a reflog entry that created its ref
+    pub const NULL: ObjectId = ObjectId([0; SHA1_HASH_SIZE]);
+
+    pub fn is_null(&self) -> bool {
+        *self == ObjectId::NULL
+    }
+}
+
 /// From a hex string
 impl TryFrom<String> for ObjectId {
     type Error = hex::FromHexError;
@@ -0,0 +1,271 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::env;
+use std::io;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::graph::Graph;
+use crate::pathspec::Pathspec;
+use crate::refs;
+use crate::revparse::peel_to_commit;
+use crate::store::{GitObjectData, GitObjectStore, ObjectId};
+
+/// A submodule as `.gitmodules` describes it, eg.
+///
+///   [submodule "lib"]
+///       path = vendor/lib
+///       url = https://example.com/lib.git
+///       branch = main
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submodule {
+    /// The name config is kept under, usually the path it was added at
+    pub name: String,
+
+    /// Where it's checked out, relative to the worktree root
+    pub path: String,
+
+    pub url: Option<String>,
+
+    /// The branch `submodule update --remote` follows
+    pub branch: Option<String>,
+
+    /// Which changes to its checkout status leaves out
+    pub ignore: Option<IgnoreSubmodules>,
+}
+
+/// Which changes to a submodule's checkout status leaves out, as
+/// --ignore-submodules and `submodule.<name>.ignore` name them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoreSubmodules {
+    /// Nothing, any change is shown
+    #[default]
+    None,
+
+    /// Untracked files in it
+    Untracked,
+
+    /// Any change to its worktree, so only a different commit checked out
+    /// is shown
+    Dirty,
+
+    /// Everything, it's never looked at
+    All,
+}
+
+impl IgnoreSubmodules {
+    pub fn parse(when: &str) -> Option<IgnoreSubmodules> {
+        match when {
+            "none" => Some(IgnoreSubmodules::None),
+            "untracked" => Some(IgnoreSubmodules::Untracked),
+            "dirty" => Some(IgnoreSubmodules::Dirty),
+            "all" => Some(IgnoreSubmodules::All),
+            _ => None,
+        }
+    }
+}
+
+impl Submodule {
+    /// Whether the submodule is wanted in this repository, going by
+    /// `submodule.<name>.active`, failing that `submodule.active`'s
+    /// pathspecs, failing that whether `submodule.<name>.url` is set, as
+    /// `submodule init` does
+    pub fn is_active(&self, config: &Config) -> bool {
+        if let Some(active) = config.get_bool(&format!("submodule.{}.active", self.name)) {
+            return active;
+        }
+
+        let pathspecs: Vec<String> = config.get_all("submodule.active").into_iter()
+            .map(str::to_string)
+            .collect();
+
+        if !pathspecs.is_empty() {
+            return Pathspec::parse(&pathspecs).is_some_and(|pathspec| pathspec.matches(&self.path));
+        }
+
+        config.get(&format!("submodule.{}.url", self.name)).is_some()
+    }
+
+    /// Which changes status leaves out going by `submodule.<name>.ignore`,
+    /// failing that the ignore setting in `.gitmodules`
+    pub fn configured_ignore(&self, config: &Config) -> Option<IgnoreSubmodules> {
+        config.get(&format!("submodule.{}.ignore", self.name))
+            .and_then(IgnoreSubmodules::parse)
+            .or(self.ignore)
+    }
+
+    /// Whether there's a repository checked out at the submodule's path
+    pub fn is_populated(&self) -> bool {
+        Path::new(&self.path).join(".git").exists()
+    }
+
+    /// The commit checked out in the submodule, `None` if it isn't
+    /// populated or is on an unborn branch
+    pub fn head(&self) -> Option<ObjectId> {
+        if !self.is_populated() {
+            return None;
+        }
+
+        in_submodule(&self.path, || refs::resolve_ref("HEAD")).flatten()
+    }
+
+    /// Names `commit` after the nearest ref in the submodule reaching it,
+    /// as `submodule status` does with `git describe`: annotated tags are
+    /// tried, then any tag, then any ref, eg. "v1.0-2-g1a2b3c4" two
+    /// commits past v1.0, or "heads/main" right at main. Failing those,
+    /// its abbreviated id.
+    pub fn describe(&self, commit: ObjectId) -> String {
+        let name = in_submodule(&self.path, || {
+            let tags = refs::list_refs("refs/tags/");
+
+            let annotated: Vec<(String, ObjectId)> = tags.iter()
+                .filter(|(_, id)| matches!(GitObjectStore::get(*id).map(|object| object.data), Some(GitObjectData::Tag { .. })))
+                .cloned()
+                .collect();
+
+            let short = |refs: Vec<(String, ObjectId)>, prefix: &str| -> Vec<(String, ObjectId)> {
+                refs.into_iter()
+                    .filter_map(|(name, id)| Some((name.strip_prefix(prefix)?.to_string(), id)))
+                    .collect()
+            };
+
+            let mut graph = Graph::new();
+
+            describe(&mut graph, commit, &short(annotated, "refs/tags/"))
+                .or_else(|| describe(&mut graph, commit, &short(tags, "refs/tags/")))
+                .or_else(|| describe(&mut graph, commit, &short(refs::list_refs("refs/"), "refs/")))
+        });
+
+        name.flatten().unwrap_or_else(|| abbrev(commit))
+    }
+}
+
+/// Names `commit` after the first of `refs` found walking back from it,
+/// newest commits first, followed by how many commits it's past that and
+/// its abbreviated id unless it's right there
+fn describe(graph: &mut Graph, commit: ObjectId, refs: &[(String, ObjectId)]) -> Option<String> {
+    let mut names: HashMap<ObjectId, &str> = HashMap::new();
+
+    // Of refs to the same commit the first by name wins
+    for (name, id) in refs {
+        if let Some(id) = peel_to_commit(*id) {
+            names.entry(id).or_insert(name);
+        }
+    }
+
+    let mut queue = BinaryHeap::from([(graph.commit_time(commit), commit)]);
+    let mut seen = HashSet::from([commit]);
+
+    let (name, found) = loop {
+        let (_, id) = queue.pop()?;
+
+        if let Some(name) = names.get(&id) {
+            break (*name, id);
+        }
+
+        for parent in graph.parents(id) {
+            if seen.insert(parent) {
+                queue.push((graph.commit_time(parent), parent));
+            }
+        }
+    };
+
+    if found == commit {
+        return Some(name.to_string());
+    }
+
+    let past = ancestors(graph, commit).difference(&ancestors(graph, found)).count();
+
+    Some(format!("{name}-{past}-g{}", abbrev(commit)))
+}
+
+/// `commit` and every commit reachable from it
+fn ancestors(graph: &mut Graph, commit: ObjectId) -> HashSet<ObjectId> {
+    let mut seen = HashSet::from([commit]);
+    let mut pending = vec![commit];
+
+    while let Some(id) = pending.pop() {
+        for parent in graph.parents(id) {
+            if seen.insert(parent) {
+                pending.push(parent);
+            }
+        }
+    }
+
+    seen
+}
+
+fn abbrev(commit: ObjectId) -> String {
+    commit.to_string()[..7].to_string()
+}
+
+/// The submodules `gitmodules` describes, a config parsed from a
+/// `.gitmodules` file, in the order they first appear. Those without a
+/// path aren't submodules git would use, so are left out.
+pub fn parse_gitmodules(gitmodules: &Config) -> Vec<Submodule> {
+    let mut names: Vec<&str> = Vec::new();
+
+    for entry in gitmodules.entries() {
+        // The name may have dots of its own, the variable can't
+        let Some((name, _)) = entry.key.strip_prefix("submodule.").and_then(|key| key.rsplit_once('.')) else {
+            continue;
+        };
+
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    names.into_iter()
+        .filter_map(|name| {
+            let value = |variable: &str| gitmodules.get(&format!("submodule.{name}.{variable}")).map(str::to_string);
+
+            Some(Submodule {
+                name: name.to_string(),
+                path: value("path")?.trim_end_matches('/').to_string(),
+                url: value("url"),
+                branch: value("branch"),
+                ignore: value("ignore").as_deref().and_then(IgnoreSubmodules::parse),
+            })
+        })
+        .collect()
+}
+
+/// The submodules in the worktree's `.gitmodules`, none if there isn't
+/// one, or it can't be parsed. Where two claim the same path the last
+/// wins, as in git.
+pub fn load_gitmodules() -> Vec<Submodule> {
+    let gitmodules = match Config::from_file(".gitmodules") {
+        Ok(gitmodules) => gitmodules,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                eprintln!("warning: {err}");
+            }
+
+            return Vec::new();
+        }
+    };
+
+    let submodules = parse_gitmodules(&gitmodules);
+
+    submodules.iter()
+        .enumerate()
+        .filter(|(i, submodule)| !submodules[i + 1..].iter().any(|later| later.path == submodule.path))
+        .map(|(_, submodule)| submodule.clone())
+        .collect()
+}
+
+/// Runs `f` with the submodule at `path` as the current repository, so
+/// the library reads its refs and objects rather than ours
+pub(crate) fn in_submodule<T, P: AsRef<Path>>(path: P, f: impl FnOnce() -> T) -> Option<T> {
+    let previous = env::current_dir().ok()?;
+    env::set_current_dir(path).ok()?;
+
+    let result = f();
+
+    if let Err(err) = env::set_current_dir(&previous) {
+        eprintln!("unable to return to {}: {err}", previous.display());
+        return None;
+    }
+
+    Some(result)
+}
@@ -0,0 +1,446 @@
+//! Builders for throwaway repositories, for end-to-end tests of gitty and
+//! of code built on it. Enabled with the `testtools` feature, which gitty's
+//! own tests turn on.
+//!
+//! Repositories are created with `init`, and trees and loose objects are
+//! written with gitty's tree serialization and loose object writer, so
+//! tests of those build on them rather than on this. Refs, packed-refs, the
+//! index and packs are written directly into the repository's files, so
+//! fixtures don't depend on gitty reading or writing those correctly.
+//!
+//! Since gitty finds the repository relative to the current directory, use
+//! `TestRepo::enter` before calling into the rest of the library.
+
+mod pack;
+
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use sha1::{Digest, Sha1};
+
+use crate::init::{init, DEFAULT_BRANCH};
+use crate::store::loose::{write_loose_object_to, LooseFormat};
+use crate::store::util::hash_object;
+use crate::store::tree::{serialize_tree, TreeBuilder, MODE_BLOB};
+use crate::store::ObjectId;
+
+/// The time of the first fixture commit, each one after is a minute later
+pub const TEST_EPOCH: i64 = 1112911993;
+
+pub const TEST_AUTHOR: &str = "A U Thor <author@example.com>";
+pub const TEST_COMMITTER: &str = "C O Mitter <committer@example.com>";
+
+static NEXT_REPO: AtomicUsize = AtomicUsize::new(0);
+
+/// Serializes tests which change the current directory
+static CWD_LOCK: Mutex<()> = Mutex::new(());
+
+/// Ways to damage a file on purpose
+#[derive(Debug, Copy, Clone)]
+pub enum Corruption {
+    /// Cut the file down to this many bytes
+    Truncate(usize),
+
+    /// Invert every bit of the byte at this offset, counting back from the
+    /// end if negative
+    FlipByte(isize),
+
+    /// Replace the contents with nothing at all
+    Empty,
+}
+
+/// A repository in a fresh temporary directory, deleted when dropped.
+///
+/// Set `GITTY_KEEP_TEST_REPOS` to leave them behind for inspection.
+pub struct TestRepo {
+    root: PathBuf,
+    git_dir: PathBuf,
+    time: Cell<i64>,
+}
+
+impl TestRepo {
+    /// An empty repository with a working tree
+    pub fn new() -> io::Result<TestRepo> {
+        TestRepo::create(false)
+    }
+
+    /// An empty bare repository
+    pub fn new_bare() -> io::Result<TestRepo> {
+        TestRepo::create(true)
+    }
+
+    fn create(bare: bool) -> io::Result<TestRepo> {
+        let root = env::temp_dir().join(format!(
+            "gitty-test-{}-{}",
+            std::process::id(),
+            NEXT_REPO.fetch_add(1, Ordering::Relaxed),
+        ));
+
+        if root.exists() {
+            fs::remove_dir_all(&root)?;
+        }
+
+        fs::create_dir_all(&root)?;
+
+        let git_dir = init(&root, bare, DEFAULT_BRANCH)?.git_dir;
+        let root = fs::canonicalize(&root)?;
+
+        Ok(TestRepo {
+            root,
+            git_dir,
+            time: Cell::new(TEST_EPOCH),
+        })
+    }
+
+    /// The working tree, or the git directory of a bare repository
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    /// Makes the repository the current directory until the guard is
+    /// dropped. Only one repository can be entered at a time, other
+    /// callers block until the guard is released.
+    pub fn enter(&self) -> io::Result<CwdGuard> {
+        let lock = CWD_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let previous = env::current_dir()?;
+
+        env::set_current_dir(&self.root)?;
+
+        Ok(CwdGuard {
+            previous,
+            _lock: lock,
+        })
+    }
+
+    /// Advances the clock used for fixture identities, returning the new
+    /// time, like git's `test_tick`
+    pub fn tick(&self) -> i64 {
+        self.time.set(self.time.get() + 60);
+        self.time.get()
+    }
+
+    /// Runs `program`, eg. gitty's binary or git, in the repository as the
+    /// test identities. An error if it fails, with what it wrote to stderr.
+    pub fn run(&self, program: &str, args: &[&str]) -> io::Result<Output> {
+        let mut command = Command::new(program);
+        command.args(args).current_dir(&self.root);
+
+        for (role, ident) in [("AUTHOR", TEST_AUTHOR), ("COMMITTER", TEST_COMMITTER)] {
+            let (name, email) = ident.split_once(" <").expect("name <email>");
+
+            command.env(format!("GIT_{role}_NAME"), name);
+            command.env(format!("GIT_{role}_EMAIL"), email.trim_end_matches('>'));
+        }
+
+        let output = command.output()?;
+
+        if !output.status.success() {
+            return Err(io::Error::other(format!("{program} {args:?} failed: {}", String::from_utf8_lossy(&output.stderr))));
+        }
+
+        Ok(output)
+    }
+
+    /// Stores an object loose, `kind` is "blob", "tree", "commit" or "tag"
+    pub fn write_object(&self, kind: &str, data: &[u8]) -> io::Result<ObjectId> {
+        let objects_dir = self.git_dir.join("objects");
+        let id = hash_object(kind, data);
+
+        let id_str = id.to_string();
+        if objects_dir.join(&id_str[..2]).join(&id_str[2..]).exists() {
+            return Ok(id);
+        }
+
+        write_loose_object_to(&objects_dir, kind, data, LooseFormat::Zlib)
+    }
+
+    pub fn blob(&self, data: &[u8]) -> io::Result<ObjectId> {
+        self.write_object("blob", data)
+    }
+
+    /// A single tree from (mode, name, id) entries, in any order
+    /// The names needn't be UTF-8, as they needn't be in git.
+    pub fn tree(&self, entries: &[(u32, &[u8], ObjectId)]) -> io::Result<ObjectId> {
+        let mut entries: Vec<_> = entries.iter()
+            .map(|&(mode, name, id)| (mode, name.to_vec(), id))
+            .collect();
+
+        self.write_object("tree", &serialize_tree(&mut entries))
+    }
+
+    /// Nested trees holding `files`, as (path, contents) pairs of regular files
+    pub fn tree_from_files(&self, files: &[(&str, &[u8])]) -> io::Result<ObjectId> {
+        let mut builder = TreeBuilder::new();
+
+        for &(path, contents) in files {
+            builder.insert(MODE_BLOB, path, self.blob(contents)?)
+                .ok_or_else(|| invalid_input(format!("can't add '{path}' to the tree")))?;
+        }
+
+        builder.write_with(&mut |kind, data| self.write_object(kind, data).ok())
+            .ok_or_else(|| io::Error::other("unable to write tree"))
+    }
+
+    /// A commit by the test identities, one tick after the last one
+    pub fn commit(&self, tree: ObjectId, parents: &[ObjectId], message: &str) -> io::Result<ObjectId> {
+        let time = self.tick();
+
+        let mut data = format!("tree {tree}\n");
+
+        for parent in parents {
+            data.push_str(&format!("parent {parent}\n"));
+        }
+
+        data.push_str(&format!("author {TEST_AUTHOR} {time} +0000\n"));
+        data.push_str(&format!("committer {TEST_COMMITTER} {time} +0000\n"));
+        data.push_str(&format!("\n{message}\n"));
+
+        self.write_object("commit", data.as_bytes())
+    }
+
+    /// Commits `files` on top of the current branch and advances it
+    pub fn commit_files(&self, files: &[(&str, &[u8])], message: &str) -> io::Result<ObjectId> {
+        let tree = self.tree_from_files(files)?;
+
+        let head = self.head_target()?;
+        let parents: Vec<_> = self.read_ref(&head).into_iter().collect();
+
+        let commit = self.commit(tree, &parents, message)?;
+        self.set_ref(&head, commit)?;
+
+        Ok(commit)
+    }
+
+    /// An annotated tag object pointing at `target`, and `refs/tags/<name>`
+    pub fn tag(&self, name: &str, target: ObjectId, kind: &str, message: &str) -> io::Result<ObjectId> {
+        let time = self.tick();
+
+        let data = format!(
+            "object {target}\ntype {kind}\ntag {name}\ntagger {TEST_COMMITTER} {time} +0000\n\n{message}\n"
+        );
+
+        let tag = self.write_object("tag", data.as_bytes())?;
+        self.set_ref(&format!("refs/tags/{name}"), tag)?;
+
+        Ok(tag)
+    }
+
+    /// Points the loose ref `name` (eg. "refs/heads/main") at `id`
+    pub fn set_ref(&self, name: &str, id: ObjectId) -> io::Result<()> {
+        self.write_git_file(name, format!("{id}\n").as_bytes())
+    }
+
+    pub fn set_symbolic_ref(&self, name: &str, target: &str) -> io::Result<()> {
+        self.write_git_file(name, format!("ref: {target}\n").as_bytes())
+    }
+
+    /// Reads a loose ref, without following symbolic refs
+    pub fn read_ref(&self, name: &str) -> Option<ObjectId> {
+        let contents = fs::read_to_string(self.git_dir.join(name)).ok()?;
+        ObjectId::try_from(contents.trim().to_string()).ok()
+    }
+
+    /// The ref HEAD points at
+    fn head_target(&self) -> io::Result<String> {
+        let head = fs::read_to_string(self.git_dir.join("HEAD"))?;
+
+        head.trim().strip_prefix("ref: ")
+            .map(|target| target.to_string())
+            .ok_or_else(|| invalid_input("HEAD is detached".to_string()))
+    }
+
+    /// Writes a packed-refs file with `refs`, as (name, id, peeled) triples
+    pub fn write_packed_refs(&self, refs: &[(&str, ObjectId, Option<ObjectId>)]) -> io::Result<()> {
+        let mut refs = refs.to_vec();
+        refs.sort_by_key(|(name, _, _)| name.to_string());
+
+        let mut contents = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+
+        for (name, id, peeled) in refs {
+            contents.push_str(&format!("{id} {name}\n"));
+
+            if let Some(peeled) = peeled {
+                contents.push_str(&format!("^{peeled}\n"));
+            }
+        }
+
+        self.write_git_file("packed-refs", contents.as_bytes())
+    }
+
+    /// Moves every loose object into a single new pack, returning its name
+    pub fn pack_objects(&self) -> io::Result<String> {
+        let objects_dir = self.git_dir.join("objects");
+        let mut objects = Vec::new();
+        let mut paths = Vec::new();
+
+        for dir in fs::read_dir(&objects_dir)?.flatten() {
+            let dir_name = dir.file_name().to_string_lossy().to_string();
+
+            if dir_name.len() != 2 || !dir.path().is_dir() {
+                continue;
+            }
+
+            for file in fs::read_dir(dir.path())?.flatten() {
+                let hex = format!("{dir_name}{}", file.file_name().to_string_lossy());
+
+                let Ok(id) = ObjectId::try_from(hex) else {
+                    continue;
+                };
+
+                let (kind, data) = pack::read_loose(&file.path())?;
+
+                objects.push((id, kind, data));
+                paths.push(file.path());
+            }
+        }
+
+        objects.sort_by_key(|(id, _, _)| *id);
+
+        let name = pack::write_pack(&objects_dir.join("pack"), &objects)?;
+
+        for path in paths {
+            fs::remove_file(path)?;
+        }
+
+        Ok(name)
+    }
+
+    /// Writes a version 2 index of (mode, path, id) entries at stage 0
+    /// with zeroed stat data, so every entry looks modified to a stat check.
+    pub fn write_index(&self, entries: &[(u32, &str, ObjectId)]) -> io::Result<()> {
+        let mut entries = entries.to_vec();
+        entries.sort_by(|(_, a, _), (_, b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+        let mut index = Vec::new();
+
+        index.extend_from_slice(b"DIRC");
+        index.write_u32::<BigEndian>(2)?;
+        index.write_u32::<BigEndian>(entries.len() as u32)?;
+
+        for (mode, path, id) in entries {
+            // ctime, mtime, dev, ino
+            index.extend_from_slice(&[0; 24]);
+            index.write_u32::<BigEndian>(mode)?;
+            // uid, gid, size
+            index.extend_from_slice(&[0; 12]);
+            index.extend_from_slice(&id[..]);
+            index.write_u16::<BigEndian>(path.len().min(0xfff) as u16)?;
+            index.extend_from_slice(path.as_bytes());
+
+            // NUL terminated, padded to a multiple of 8 bytes
+            let entry_len = 62 + path.len();
+            index.resize(index.len() + 8 - entry_len % 8, 0);
+        }
+
+        let checksum: [u8; 20] = Sha1::digest(&index).into();
+        index.extend_from_slice(&checksum);
+
+        self.write_git_file("index", &index)
+    }
+
+    /// Writes a file into the working tree, creating directories as needed
+    pub fn write_file(&self, path: &str, contents: &[u8]) -> io::Result<()> {
+        let path = self.root.join(path);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, contents)
+    }
+
+    pub fn write_executable(&self, path: &str, contents: &[u8]) -> io::Result<()> {
+        self.write_file(path, contents)?;
+        fs::set_permissions(self.root.join(path), fs::Permissions::from_mode(0o755))
+    }
+
+    pub fn write_symlink(&self, path: &str, target: &str) -> io::Result<()> {
+        symlink(target, self.root.join(path))
+    }
+
+    /// Writes any file in the git directory, eg. "config" or "refs/heads/x"
+    pub fn write_git_file(&self, path: &str, contents: &[u8]) -> io::Result<()> {
+        let path = self.git_dir.join(path);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, contents)
+    }
+
+    /// Damages the loose object `id`
+    pub fn corrupt_object(&self, id: ObjectId, corruption: Corruption) -> io::Result<()> {
+        let id_str = id.to_string();
+        self.corrupt_git_file(&format!("objects/{}/{}", &id_str[..2], &id_str[2..]), corruption)
+    }
+
+    /// Damages any file in the git directory, eg. "index" or a pack
+    pub fn corrupt_git_file(&self, path: &str, corruption: Corruption) -> io::Result<()> {
+        let path = self.git_dir.join(path);
+        let mut contents = fs::read(&path)?;
+
+        match corruption {
+            Corruption::Truncate(len) => contents.truncate(len),
+            Corruption::FlipByte(offset) => {
+                let offset = if offset < 0 {
+                    contents.len().checked_sub(offset.unsigned_abs())
+                } else {
+                    Some(offset as usize)
+                };
+
+                let byte = offset.and_then(|offset| contents.get_mut(offset))
+                    .ok_or_else(|| invalid_input(format!("offset out of range for {}", path.display())))?;
+
+                *byte = !*byte;
+            },
+            Corruption::Empty => contents.clear(),
+        }
+
+        // Objects are written read-only
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+
+        let mut file = fs::File::create(&path)?;
+        file.write_all(&contents)
+    }
+
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        if env::var_os("GITTY_KEEP_TEST_REPOS").is_some() {
+            eprintln!("Keeping test repository {}", self.root.display());
+            return;
+        }
+
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Restores the previous current directory when dropped
+pub struct CwdGuard {
+    previous: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Drop for CwdGuard {
+    fn drop(&mut self) {
+        let _ = env::set_current_dir(&self.previous);
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use byteorder::{BigEndian, WriteBytesExt};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use sha1::{Digest, Sha1};
+
+use crate::store::ObjectId;
+
+/// Pack object type numbers
+fn pack_type(kind: &str) -> Option<u8> {
+    match kind {
+        "commit" => Some(1),
+        "tree" => Some(2),
+        "blob" => Some(3),
+        "tag" => Some(4),
+        _ => None,
+    }
+}
+
+/// Reads a loose object back as (kind, data)
+pub fn read_loose(path: &Path) -> io::Result<(String, Vec<u8>)> {
+    let mut raw = Vec::new();
+    ZlibDecoder::new(fs::File::open(path)?).read_to_end(&mut raw)?;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed loose object");
+
+    let nul = raw.iter().position(|&b| b == 0).ok_or_else(invalid)?;
+    let header = std::str::from_utf8(&raw[..nul]).map_err(|_| invalid())?;
+    let (kind, _) = header.split_once(' ').ok_or_else(invalid)?;
+
+    Ok((kind.to_string(), raw[nul + 1..].to_vec()))
+}
+
+/// Writes `objects` as an undeltified version 2 pack with a version 2 idx
+/// into `pack_dir`, returning the pack's name ("pack-<checksum>").
+pub fn write_pack(pack_dir: &Path, objects: &[(ObjectId, String, Vec<u8>)]) -> io::Result<String> {
+    let mut pack = Vec::new();
+
+    // Header: "PACK", version, object count
+    pack.extend_from_slice(b"PACK");
+    pack.write_u32::<BigEndian>(2)?;
+    pack.write_u32::<BigEndian>(objects.len() as u32)?;
+
+    // (id, crc32 of the packed representation, offset)
+    let mut entries = Vec::new();
+
+    for (id, kind, data) in objects {
+        let offset = pack.len();
+
+        let kind = pack_type(kind)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("can't pack a {kind}")))?;
+
+        // Object header: 3-bit type and a little endian base 128 size,
+        // 4 bits of which are in the first byte
+        let mut size = data.len();
+        let mut byte = (kind << 4) | (size & 0x0f) as u8;
+        size >>= 4;
+
+        while size != 0 {
+            pack.push(byte | 0x80);
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+
+        pack.push(byte);
+
+        let mut encoder = ZlibEncoder::new(&mut pack, Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()?;
+
+        let mut crc = Crc::new();
+        crc.update(&pack[offset..]);
+
+        entries.push((*id, crc.sum(), offset));
+    }
+
+    let checksum: [u8; 20] = Sha1::digest(&pack).into();
+    pack.extend_from_slice(&checksum);
+
+    entries.sort_by_key(|(id, _, _)| *id);
+
+    let mut idx = Vec::new();
+
+    idx.write_u32::<BigEndian>(0xff744f63)?;
+    idx.write_u32::<BigEndian>(2)?;
+
+    // Fan-out: number of objects whose first byte is <= n
+    for n in 0..=255u8 {
+        let count = entries.iter().filter(|(id, _, _)| id[0] <= n).count();
+        idx.write_u32::<BigEndian>(count as u32)?;
+    }
+
+    for (id, _, _) in &entries {
+        idx.extend_from_slice(&id[..]);
+    }
+
+    for (_, crc, _) in &entries {
+        idx.write_u32::<BigEndian>(*crc)?;
+    }
+
+    for (_, _, offset) in &entries {
+        idx.write_u32::<BigEndian>(*offset as u32)?;
+    }
+
+    idx.extend_from_slice(&checksum);
+
+    let idx_checksum: [u8; 20] = Sha1::digest(&idx).into();
+    idx.extend_from_slice(&idx_checksum);
+
+    let name = format!("pack-{}", hex::encode(checksum));
+
+    fs::create_dir_all(pack_dir)?;
+    fs::write(pack_dir.join(format!("{name}.pack")), pack)?;
+    fs::write(pack_dir.join(format!("{name}.idx")), idx)?;
+
+    Ok(name)
+}
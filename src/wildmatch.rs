@@ -0,0 +1,233 @@
+/// Git's glob dialect, shared by ref patterns, pathspecs and ignore rules.
+///
+///   '?'     any single character
+///   '*'     any run of characters
+///   '[...]' a character class, '!' or '^' negates, ranges like 'a-z' and
+///           classes like '[:alpha:]' are supported
+///   '\'     escapes the next character
+///
+/// In pathname mode '*', '?' and classes never match '/', but "**" does
+/// when it makes up a whole path component:
+///   "**/x"   matches "x" in any directory
+///   "x/**"   matches everything inside "x"
+///   "a/**/b" matches "a/b", "a/x/b", "a/x/y/b", ...
+#[derive(Debug, Copy, Clone, Default)]
+pub struct WildmatchFlags {
+    pub pathname: bool,
+    pub casefold: bool,
+}
+
+pub fn wildmatch<T: AsRef<[u8]>>(pattern: &str, text: T, flags: WildmatchFlags) -> bool {
+    match_here(pattern.as_bytes(), text.as_ref(), flags) == MatchResult::Match
+}
+
+/// Does the pattern use any glob syntax at all?
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '\\'])
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MatchResult {
+    Match,
+    NoMatch,
+    // No match, and no match is possible by consuming more of the text
+    // with an enclosing '*' either
+    AbortAll,
+    // As above, but a "**" higher up could still match across a '/'
+    AbortToStarStar,
+}
+
+fn chars_eq(a: u8, b: u8, flags: WildmatchFlags) -> bool {
+    if flags.casefold {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+fn match_here(pattern: &[u8], text: &[u8], flags: WildmatchFlags) -> MatchResult {
+    let mut p = 0;
+    let mut t = 0;
+
+    while p < pattern.len() {
+        let pc = pattern[p];
+
+        if t >= text.len() && pc != b'*' {
+            return MatchResult::AbortAll;
+        }
+
+        match pc {
+            b'\\' => {
+                p += 1;
+                if p >= pattern.len() || !chars_eq(pattern[p], text[t], flags) {
+                    return MatchResult::NoMatch;
+                }
+            },
+            b'?' => {
+                if flags.pathname && text[t] == b'/' {
+                    return MatchResult::NoMatch;
+                }
+            },
+            b'*' => {
+                let star_start = p;
+                p += 1;
+
+                let mut match_slash = !flags.pathname;
+
+                if p < pattern.len() && pattern[p] == b'*' {
+                    while p < pattern.len() && pattern[p] == b'*' {
+                        p += 1;
+                    }
+
+                    let prev_is_slash = star_start == 0 || pattern[star_start - 1] == b'/';
+                    let next_is_slash_or_end = p == pattern.len() || pattern[p] == b'/';
+
+                    if !flags.pathname {
+                        match_slash = true;
+                    } else if prev_is_slash && next_is_slash_or_end {
+                        // "**/" can also match nothing at all
+                        if p < pattern.len()
+                            && match_here(&pattern[p + 1..], &text[t..], flags) == MatchResult::Match {
+                            return MatchResult::Match;
+                        }
+                        match_slash = true;
+                    }
+                }
+
+                if p == pattern.len() {
+                    // A trailing star matches the rest, unless it would
+                    // have to cross a directory
+                    if !match_slash && text[t..].contains(&b'/') {
+                        return MatchResult::AbortToStarStar;
+                    }
+                    return MatchResult::Match;
+                }
+
+                loop {
+                    if t > text.len() {
+                        break;
+                    }
+
+                    match match_here(&pattern[p..], &text[t..], flags) {
+                        MatchResult::NoMatch => {},
+                        MatchResult::AbortToStarStar if match_slash => {},
+                        result => return result,
+                    }
+
+                    if t == text.len() || (!match_slash && text[t] == b'/') {
+                        break;
+                    }
+
+                    t += 1;
+                }
+
+                return if match_slash {
+                    MatchResult::AbortAll
+                } else {
+                    MatchResult::AbortToStarStar
+                };
+            },
+            b'[' => {
+                let Some((matched, class_end)) = match_class(&pattern[p..], text[t], flags) else {
+                    // An unterminated class never matches
+                    return MatchResult::AbortAll;
+                };
+
+                if !matched || (flags.pathname && text[t] == b'/') {
+                    return MatchResult::NoMatch;
+                }
+
+                p += class_end - 1;
+            },
+            _ => {
+                if !chars_eq(pc, text[t], flags) {
+                    return MatchResult::NoMatch;
+                }
+            }
+        }
+
+        p += 1;
+        t += 1;
+    }
+
+    if t == text.len() {
+        MatchResult::Match
+    } else {
+        MatchResult::NoMatch
+    }
+}
+
+/// Matches `c` against the class at the start of `pattern`, returning
+/// whether it matched and the length of the class including brackets.
+fn match_class(pattern: &[u8], c: u8, flags: WildmatchFlags) -> Option<(bool, usize)> {
+    let mut p = 1;
+
+    let negated = matches!(pattern.get(p), Some(b'!') | Some(b'^'));
+    if negated {
+        p += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        let pc = *pattern.get(p)?;
+
+        if pc == b']' && !first {
+            break;
+        }
+
+        first = false;
+
+        if pc == b'[' && pattern.get(p + 1) == Some(&b':') {
+            let name_start = p + 2;
+            let name_len = pattern[name_start..].windows(2).position(|w| w == b":]")?;
+            let name = &pattern[name_start..name_start + name_len];
+
+            matched |= match name {
+                b"alnum" => c.is_ascii_alphanumeric(),
+                b"alpha" => c.is_ascii_alphabetic(),
+                b"blank" => c == b' ' || c == b'\t',
+                b"cntrl" => c.is_ascii_control(),
+                b"digit" => c.is_ascii_digit(),
+                b"graph" => c.is_ascii_graphic(),
+                b"lower" => c.is_ascii_lowercase() || (flags.casefold && c.is_ascii_uppercase()),
+                b"print" => c.is_ascii_graphic() || c == b' ',
+                b"punct" => c.is_ascii_punctuation(),
+                b"space" => c.is_ascii_whitespace(),
+                b"upper" => c.is_ascii_uppercase() || (flags.casefold && c.is_ascii_lowercase()),
+                b"xdigit" => c.is_ascii_hexdigit(),
+                _ => return None,
+            };
+
+            p = name_start + name_len + 2;
+            continue;
+        }
+
+        let mut low = pc;
+        if low == b'\\' {
+            p += 1;
+            low = *pattern.get(p)?;
+        }
+
+        if pattern.get(p + 1) == Some(&b'-') && pattern.get(p + 2).is_some_and(|&end| end != b']') {
+            let mut high = pattern[p + 2];
+            p += 2;
+
+            if high == b'\\' {
+                p += 1;
+                high = *pattern.get(p)?;
+            }
+
+            matched |= (low..=high).contains(&c)
+                || (flags.casefold && (low..=high).contains(&c.to_ascii_lowercase()))
+                || (flags.casefold && (low..=high).contains(&c.to_ascii_uppercase()));
+        } else {
+            matched |= chars_eq(low, c, flags);
+        }
+
+        p += 1;
+    }
+
+    Some((matched != negated, p + 1))
+}
@@ -0,0 +1,260 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::{self, Metadata};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use crate::ignore::IgnoreRules;
+use crate::store::{
+    index::{Index, IndexEntry, IndexTime},
+    tree::{MODE_BLOB, MODE_EXECUTABLE, MODE_SYMLINK, MODE_TREE},
+    util::hash_object,
+    ObjectId,
+};
+
+/// The worktree files not in `index`, as sorted slash separated paths
+/// relative to its root. Those `rules` ignore are left out, and ignored
+/// directories aren't looked inside.
+///
+/// With `directories`, a directory with nothing tracked in it is listed
+/// once as "dir/" rather than file by file, and with `hide_empty` only if
+/// there's something in it which isn't ignored. A nested repository is
+/// always listed as a directory.
+pub fn list_untracked(index: &Index, rules: Option<&mut IgnoreRules>, directories: bool, hide_empty: bool) -> Vec<String> {
+    let mut scan = UntrackedScan {
+        tracked: index.entries.iter().map(|entry| entry.path.as_slice()).collect(),
+        tracked_dirs: index.entries.iter()
+            .flat_map(|entry| {
+                let slashes = entry.path.iter().enumerate().filter(|(_, &byte)| byte == b'/');
+                slashes.map(|(pos, _)| &entry.path[..pos])
+            })
+            .collect(),
+        rules,
+        directories,
+        hide_empty,
+        paths: Vec::new(),
+    };
+
+    scan.visit_dir("");
+    scan.paths.sort();
+    scan.paths
+}
+
+struct UntrackedScan<'a> {
+    /// Paths in the index, submodules included
+    tracked: HashSet<&'a [u8]>,
+
+    /// Every directory something tracked is in, which are looked inside
+    /// rather than listed whole
+    tracked_dirs: HashSet<&'a [u8]>,
+
+    rules: Option<&'a mut IgnoreRules>,
+    directories: bool,
+    hide_empty: bool,
+    paths: Vec<String>,
+}
+
+impl UntrackedScan<'_> {
+    /// Lists what's untracked in the directory `prefix` ("" or "dir/")
+    fn visit_dir(&mut self, prefix: &str) {
+        for (path, is_dir) in dir_entries(prefix) {
+            if self.tracked.contains(path.as_bytes()) {
+                continue;
+            }
+
+            if !is_dir {
+                if !self.is_ignored(&path, false) {
+                    self.paths.push(path);
+                }
+            } else if self.tracked_dirs.contains(path.as_bytes()) {
+                self.visit_dir(&format!("{path}/"));
+            } else if self.is_ignored(&path, true) {
+                continue;
+            } else if is_repository(&path) {
+                self.paths.push(format!("{path}/"));
+            } else if !self.directories {
+                self.visit_dir(&format!("{path}/"));
+            } else if !self.hide_empty || self.has_untracked(&format!("{path}/")) {
+                self.paths.push(format!("{path}/"));
+            }
+        }
+    }
+
+    /// Is there anything which isn't ignored in the untracked directory
+    /// `prefix`?
+    fn has_untracked(&mut self, prefix: &str) -> bool {
+        dir_entries(prefix).into_iter().any(|(path, is_dir)| {
+            match is_dir {
+                true if self.is_ignored(&path, true) => false,
+                true => is_repository(&path) || self.has_untracked(&format!("{path}/")),
+                false => !self.is_ignored(&path, false),
+            }
+        })
+    }
+
+    fn is_ignored(&mut self, path: &str, is_dir: bool) -> bool {
+        self.rules.as_mut().is_some_and(|rules| rules.is_ignored(path, is_dir))
+    }
+}
+
+/// The paths in the directory `prefix` ("" for the worktree root, or
+/// "dir/") and whether each is a directory, less `.git`
+fn dir_entries(prefix: &str) -> Vec<(String, bool)> {
+    let dir = match prefix {
+        "" => Path::new("."),
+        prefix => Path::new(prefix),
+    };
+
+    let Ok(contents) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+
+    for entry in contents.flatten() {
+        let Ok(filename) = entry.file_name().into_string() else {
+            eprintln!("Skipping non UTF-8 path in {}", dir.display());
+            continue;
+        };
+
+        if filename == ".git" {
+            continue;
+        }
+
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        entries.push((format!("{prefix}{filename}"), file_type.is_dir()));
+    }
+
+    entries
+}
+
+/// Is the worktree directory `path` the root of another repository?
+fn is_repository(path: &str) -> bool {
+    Path::new(path).join(".git").exists()
+}
+
+/// Adds every file inside the directory `dir` (relative to the worktree
+/// root) to `files`, unsorted
+pub fn list_files_in(dir: &str, files: &mut Vec<String>) {
+    visit_dir(&format!("{dir}/"), files);
+}
+
+fn visit_dir(prefix: &str, files: &mut Vec<String>) {
+    for (path, is_dir) in dir_entries(prefix) {
+        match is_dir {
+            true => visit_dir(&format!("{path}/"), files),
+            false => files.push(path),
+        }
+    }
+}
+
+/// The git mode for a file on disk, git only tracks the executable bit
+pub fn file_mode(metadata: &Metadata) -> u32 {
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        MODE_SYMLINK
+    } else if file_type.is_dir() {
+        MODE_TREE
+    } else if metadata.permissions().mode() & 0o111 != 0 {
+        MODE_EXECUTABLE
+    } else {
+        MODE_BLOB
+    }
+}
+
+/// The worktree path of a tree or index path, which needn't be UTF-8
+pub fn os_path(path: &[u8]) -> &Path {
+    Path::new(OsStr::from_bytes(path))
+}
+
+/// The contents git would store for a worktree path, symlinks are stored
+/// as their target.
+pub fn read_file<P: AsRef<Path>>(path: P) -> Option<Vec<u8>> {
+    let path = path.as_ref();
+    let metadata = fs::symlink_metadata(path).ok()?;
+
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(path).ok()?;
+        return Some(target.as_os_str().as_bytes().to_vec());
+    }
+
+    fs::read(path).ok()
+}
+
+/// The blob id a worktree file would have if it were added
+pub fn hash_file<P: AsRef<Path>>(path: P) -> Option<ObjectId> {
+    Some(hash_object("blob", &read_file(path)?))
+}
+
+/// Whether the cached stat data in the index still matches the file,
+/// in which case its contents can be assumed unchanged without hashing.
+pub fn stat_matches(entry: &IndexEntry, metadata: &Metadata) -> bool {
+    entry.mtime.secs == metadata.mtime() as u32
+        && entry.mtime.nsecs == metadata.mtime_nsec() as u32
+        && entry.ctime.secs == metadata.ctime() as u32
+        && entry.ctime.nsecs == metadata.ctime_nsec() as u32
+        && entry.ino == metadata.ino() as u32
+        && entry.size == metadata.size() as u32
+        && entry.mode == file_mode(metadata)
+}
+
+/// An index entry staging `id` at `path`, with the file's current stat data
+pub fn index_entry<P: AsRef<[u8]>>(path: P, metadata: &Metadata, id: ObjectId) -> IndexEntry {
+    IndexEntry {
+        ctime: IndexTime {
+            secs: metadata.ctime() as u32,
+            nsecs: metadata.ctime_nsec() as u32,
+        },
+        mtime: IndexTime {
+            secs: metadata.mtime() as u32,
+            nsecs: metadata.mtime_nsec() as u32,
+        },
+        dev: metadata.dev() as u32,
+        ino: metadata.ino() as u32,
+        mode: file_mode(metadata),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        size: metadata.size() as u32,
+        id,
+        assume_valid: false,
+        stage: 0,
+        skip_worktree: false,
+        intent_to_add: false,
+        path: path.as_ref().to_vec(),
+    }
+}
+
+/// The path relative to the repository root, "" for the root itself
+pub fn normalize_path(path: &str) -> Option<String> {
+    let mut components = Vec::new();
+
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            _ => components.push(component),
+        }
+    }
+
+    if path.starts_with('/') {
+        return None;
+    }
+
+    Some(components.join("/"))
+}
+
+/// Is `path` the file `pathspec` names, or inside the directory it names?
+pub fn in_pathspec<P: AsRef<[u8]>>(path: P, pathspec: &str) -> bool {
+    let path = path.as_ref();
+
+    pathspec.is_empty()
+        || path == pathspec.as_bytes()
+        || path.strip_prefix(pathspec.as_bytes()).is_some_and(|rest| rest.starts_with(b"/"))
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+use gitty::testtools::TestRepo;
+
+/// A tar entry, as (name, mode, mtime, contents)
+type TarEntry = (String, u32, i64, Vec<u8>);
+
+/// The entries of a ustar archive, less pax headers
+fn tar_entries(tar: &[u8]) -> Vec<TarEntry> {
+    let field = |header: &[u8], start: usize, len: usize| {
+        let field = &header[start..start + len];
+        String::from_utf8_lossy(&field[..field.iter().position(|&byte| byte == 0).unwrap_or(len)]).into_owned()
+    };
+
+    let octal = |header: &[u8], start: usize, len: usize| i64::from_str_radix(field(header, start, len).trim(), 8).unwrap();
+
+    let mut entries = Vec::new();
+    let mut offset = 0;
+
+    while offset + 512 <= tar.len() && tar[offset..offset + 512].iter().any(|&byte| byte != 0) {
+        let header = &tar[offset..offset + 512];
+        let size = octal(header, 124, 12) as usize;
+        let data = tar[offset + 512..offset + 512 + size].to_vec();
+
+        if !matches!(header[156], b'g' | b'x') {
+            entries.push((field(header, 0, 100), octal(header, 100, 8) as u32, octal(header, 136, 12), data));
+        }
+
+        offset += 512 + size.next_multiple_of(512);
+    }
+
+    entries
+}
+
+#[test]
+fn adds_files_and_sets_the_mtime() {
+    let repo = TestRepo::new().unwrap();
+    repo.commit_files(&[("a", b"a\n")], "initial").unwrap();
+
+    repo.write_file("extra/VERSION", b"1.0\n").unwrap();
+    repo.write_file("extra/build.sh", b"#!/bin/sh\n").unwrap();
+
+    let script = repo.path().join("extra/build.sh");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gitty"))
+        .args(["archive", "--prefix", "p/", "--add-file", "extra/VERSION", "--add-file", "extra/build.sh", "--mtime", "@1234567890", "HEAD"])
+        .current_dir(repo.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let entries = tar_entries(&output.stdout);
+
+    // The added files go in after the tree, under the prefix by name
+    let names: Vec<&str> = entries.iter().map(|(name, ..)| name.as_str()).collect();
+    assert_eq!(names, ["p/", "p/a", "p/VERSION", "p/build.sh"]);
+
+    assert!(entries.iter().all(|&(_, _, mtime, _)| mtime == 1234567890));
+
+    assert_eq!(entries[2].1, 0o664);
+    assert_eq!(entries[2].3, b"1.0\n");
+    assert_eq!(entries[3].1, 0o775);
+}
@@ -0,0 +1,18 @@
+use std::process::Command;
+
+use gitty::testtools::TestRepo;
+
+#[test]
+fn v_inverts_the_match() {
+    let repo = TestRepo::new().unwrap();
+    repo.commit_files(&[("f", b"one\ntwo\nthree\n")], "initial").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_gitty"))
+        .args(["grep", "-v", "o", "HEAD"])
+        .current_dir(repo.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(output.stdout, b"HEAD:f:three\n");
+}
@@ -0,0 +1,148 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+
+use gitty::config::set_value;
+use gitty::init::DEFAULT_BRANCH;
+use gitty::protocol::pktline::{Packet, PacketReader, PacketWriter, SidebandReader};
+use gitty::store::{GitObjectData, GitObjectStore};
+use gitty::testtools::TestRepo;
+
+fn branch() -> String {
+    format!("refs/heads/{DEFAULT_BRANCH}")
+}
+
+#[test]
+fn pulls_a_branch_from_another_repository() {
+    let upstream = TestRepo::new().unwrap();
+    upstream.commit_files(&[("a", b"one\n")], "one").unwrap();
+    let two = upstream.commit_files(&[("a", b"two\n")], "two").unwrap();
+
+    let repo = TestRepo::new().unwrap();
+    let url = upstream.path().to_str().unwrap();
+
+    repo.run(env!("CARGO_BIN_EXE_gitty"), &["pull", url, DEFAULT_BRANCH]).unwrap();
+
+    assert_eq!(repo.read_ref(&branch()), Some(two));
+    assert_eq!(fs::read(repo.path().join("a")).unwrap(), b"two\n");
+
+    let fetch_head = fs::read_to_string(repo.git_dir().join("FETCH_HEAD")).unwrap();
+    assert_eq!(fetch_head, format!("{two}\t\tbranch '{DEFAULT_BRANCH}' of {url}\n"));
+}
+
+#[test]
+fn pulls_the_upstream_with_the_remotes_refspecs() {
+    let upstream = TestRepo::new().unwrap();
+    let one = upstream.commit_files(&[("a", b"one\n")], "one").unwrap();
+
+    let repo = TestRepo::new().unwrap();
+    let url = format!("file://{}", upstream.path().display());
+
+    repo.run(env!("CARGO_BIN_EXE_gitty"), &["remote", "add", "origin", &url]).unwrap();
+
+    let config = repo.git_dir().join("config");
+    set_value(&config, &format!("branch.{DEFAULT_BRANCH}.remote"), Some("origin")).unwrap();
+    set_value(&config, &format!("branch.{DEFAULT_BRANCH}.merge"), Some(&branch())).unwrap();
+
+    repo.run(env!("CARGO_BIN_EXE_gitty"), &["pull"]).unwrap();
+
+    assert_eq!(repo.read_ref(&branch()), Some(one));
+
+    let two = upstream.commit_files(&[("a", b"two\n"), ("b", b"new\n")], "two").unwrap();
+
+    repo.run(env!("CARGO_BIN_EXE_gitty"), &["pull"]).unwrap();
+
+    assert_eq!(repo.read_ref(&format!("refs/remotes/origin/{DEFAULT_BRANCH}")), Some(two));
+    assert_eq!(repo.read_ref(&branch()), Some(two));
+    assert_eq!(fs::read(repo.path().join("a")).unwrap(), b"two\n");
+    assert_eq!(fs::read(repo.path().join("b")).unwrap(), b"new\n");
+}
+
+#[test]
+fn rebases_diverged_branches_stashing_as_rebase_autostash_says() {
+    let upstream = TestRepo::new().unwrap();
+    upstream.commit_files(&[("a", b"one\n")], "one").unwrap();
+
+    let repo = TestRepo::new().unwrap();
+    let gitty = env!("CARGO_BIN_EXE_gitty");
+    let url = upstream.path().to_str().unwrap();
+
+    repo.run(gitty, &["pull", url, DEFAULT_BRANCH]).unwrap();
+
+    repo.write_file("b", b"local\n").unwrap();
+    repo.run(gitty, &["add", "b"]).unwrap();
+    repo.run(gitty, &["commit", "-m", "local"]).unwrap();
+
+    let two = upstream.commit_files(&[("a", b"two\n")], "two").unwrap();
+
+    // Rebasing stashes local changes if rebase.autoStash says to
+    set_value(&repo.git_dir().join("config"), "rebase.autoStash", Some("true")).unwrap();
+    repo.write_file("b", b"changed\n").unwrap();
+
+    let output = repo.run(gitty, &["pull", "--rebase", url, DEFAULT_BRANCH]).unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Created autostash"));
+
+    // The local commit is picked onto what was pulled
+    let _cwd = repo.enter().unwrap();
+    let head = repo.read_ref(&branch()).unwrap();
+
+    let Some(GitObjectData::Commit { parents, message, .. }) = GitObjectStore::get(head).map(|object| object.data) else {
+        panic!("{head} isn't a commit");
+    };
+
+    assert_eq!((parents, message), (vec![two], b"local\n".to_vec()));
+    assert_eq!(fs::read(repo.path().join("a")).unwrap(), b"two\n");
+    assert_eq!(fs::read(repo.path().join("b")).unwrap(), b"changed\n");
+}
+
+#[test]
+fn upload_pack_serves_what_the_fetcher_is_missing_and_counts_it() {
+    let upstream = TestRepo::new().unwrap();
+    let one = upstream.commit_files(&[("a", b"one\n")], "one").unwrap();
+    let two = upstream.commit_files(&[("a", b"two\n")], "two").unwrap();
+
+    let metrics = upstream.path().join("metrics");
+    set_value(&upstream.git_dir().join("config"), "gitty.metricsFile", Some(metrics.to_str().unwrap())).unwrap();
+
+    let mut request = PacketWriter::new(Vec::new());
+    request.write_line(&format!("want {two} side-band-64k ofs-delta")).unwrap();
+    request.write_flush().unwrap();
+    request.write_line(&format!("have {one}")).unwrap();
+    request.write_flush().unwrap();
+    request.write_line("done").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gitty"))
+        .args(["upload-pack", upstream.path().to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(&request.into_inner()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let mut packets = PacketReader::new(&output.stdout[..]);
+    packets.read_until_flush().unwrap();
+    assert_eq!(packets.read_packet().unwrap(), Some(Packet::Data(format!("ACK {one}\n").into_bytes())));
+
+    let mut pack = Vec::new();
+    SidebandReader::new(packets, |_: &[u8]| {}).read_to_end(&mut pack).unwrap();
+
+    // The second commit, its tree and its blob
+    assert_eq!(&pack[..4], b"PACK");
+    assert_eq!(pack[8..12], 3u32.to_be_bytes());
+
+    let metrics = fs::read_to_string(metrics).unwrap();
+    assert!(metrics.contains("\ngitty_objects_served_total 3\n"), "{metrics}");
+    assert!(metrics.contains(&format!("\ngitty_pack_bytes_sent_total {}\n", pack.len())), "{metrics}");
+    assert!(metrics.contains("\ngitty_negotiation_rounds_total 2\n"), "{metrics}");
+}
+
+#[test]
+fn https_urls_are_refused_as_only_plain_http_is_spoken() {
+    let repo = TestRepo::new().unwrap();
+
+    let err = repo.run(env!("CARGO_BIN_EXE_gitty"), &["fetch", "https://example.com/repo.git", DEFAULT_BRANCH]).unwrap_err();
+    assert!(err.to_string().contains("https isn't supported, only plain http://"), "{err}");
+}
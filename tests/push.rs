@@ -0,0 +1,147 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+use gitty::config::set_value;
+use gitty::init::DEFAULT_BRANCH;
+use gitty::testtools::TestRepo;
+
+/// Signs anything and finds every signature good, standing in for gpg
+const FAKE_GPG: &str = r#"#!/bin/sh
+cat > /dev/null
+
+case "$*" in
+    *--verify*) echo "[GNUPG:] GOODSIG 0123456789ABCDEF C O Mitter <committer@example.com>" ;;
+    *)
+        echo "[GNUPG:] SIG_CREATED D 1 2 00 1 0123456789ABCDEF" >&2
+        printf -- '-----BEGIN PGP SIGNATURE-----\n\nsigned\n-----END PGP SIGNATURE-----\n'
+        ;;
+esac
+"#;
+
+fn write_script(path: &Path, contents: &str) {
+    fs::write(path, contents).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// A repository asking for signed pushes, with a pre-receive hook which
+/// writes what it's told about the certificate and the updates to
+/// "pushed" in its worktree
+fn signed_remote() -> TestRepo {
+    let remote = TestRepo::new().unwrap();
+    remote.commit_files(&[("a", b"one\n")], "one").unwrap();
+
+    let gpg = remote.path().join("gpg");
+    write_script(&gpg, FAKE_GPG);
+
+    let config = remote.git_dir().join("config");
+    set_value(&config, "receive.certNonceSeed", Some("seed")).unwrap();
+    set_value(&config, "gpg.program", Some(gpg.to_str().unwrap())).unwrap();
+
+    let pushed = remote.path().join("pushed");
+    let hook = format!("#!/bin/sh\necho \"$GIT_PUSH_CERT_STATUS $GIT_PUSH_CERT_NONCE_STATUS\" > '{}'\ncat >> '{0}'\n", pushed.display());
+    fs::create_dir_all(remote.git_dir().join("hooks")).unwrap();
+    write_script(&remote.git_dir().join("hooks/pre-receive"), &hook);
+
+    remote
+}
+
+/// A clone of `remote` with a commit on top, to push, and `gpg.program`
+/// set to sign with
+fn pusher(remote: &TestRepo) -> (TestRepo, String) {
+    let repo = TestRepo::new().unwrap();
+    repo.run(env!("CARGO_BIN_EXE_gitty"), &["pull", remote.path().to_str().unwrap(), DEFAULT_BRANCH]).unwrap();
+
+    let commit = repo.commit_files(&[("a", b"two\n")], "two").unwrap();
+
+    let gpg = remote.path().join("gpg");
+    set_value(&repo.git_dir().join("config"), "gpg.program", Some(gpg.to_str().unwrap())).unwrap();
+
+    (repo, commit.to_string())
+}
+
+#[test]
+fn pushes_signed_with_a_certificate() {
+    let remote = signed_remote();
+    let (repo, commit) = pusher(&remote);
+
+    repo.run(env!("CARGO_BIN_EXE_gitty"), &["push", "--signed", remote.path().to_str().unwrap(), &format!("{DEFAULT_BRANCH}:refs/heads/topic")]).unwrap();
+
+    assert_eq!(remote.read_ref("refs/heads/topic").map(|id| id.to_string()), Some(commit.clone()));
+
+    let pushed = fs::read_to_string(remote.path().join("pushed")).unwrap();
+    assert!(pushed.starts_with("G OK\n"), "{pushed}");
+    assert!(pushed.ends_with(&format!(" {commit} refs/heads/topic\n")), "{pushed}");
+}
+
+#[test]
+fn receives_signed_pushes() {
+    // This checks receive-pack against git's own push, which may not be
+    // installed
+    if Command::new("git").arg("--version").output().is_err() {
+        eprintln!("skipping: git isn't installed");
+        return;
+    }
+
+    let remote = signed_remote();
+    let (repo, commit) = pusher(&remote);
+
+    let metrics = remote.path().join("metrics");
+    set_value(&remote.git_dir().join("config"), "gitty.metricsFile", Some(metrics.to_str().unwrap())).unwrap();
+
+    let receive_pack = format!("{} receive-pack", env!("CARGO_BIN_EXE_gitty"));
+    let url = remote.path().to_str().unwrap();
+
+    repo.run("git", &["push", "-q", "--signed", "--receive-pack", &receive_pack, url, &format!("{DEFAULT_BRANCH}:refs/heads/topic")]).unwrap();
+
+    assert_eq!(remote.read_ref("refs/heads/topic").map(|id| id.to_string()), Some(commit.clone()));
+
+    let pushed = fs::read_to_string(remote.path().join("pushed")).unwrap();
+    assert!(pushed.starts_with("G OK\n"), "{pushed}");
+    assert!(pushed.ends_with(&format!(" {commit} refs/heads/topic\n")), "{pushed}");
+
+    // The commit, its tree and blob came in a pack
+    let metrics = fs::read_to_string(remote.path().join("metrics")).unwrap();
+    assert!(metrics.contains("\ngitty_received_packs_total 1\n"), "{metrics}");
+    assert!(metrics.contains("\ngitty_received_objects_total 3\n"), "{metrics}");
+
+    // The branch checked out in the remote's worktree is left alone
+    let output = Command::new("git")
+        .args(["push", "--receive-pack", &receive_pack, url, DEFAULT_BRANCH])
+        .current_dir(repo.path())
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("branch is currently checked out"));
+}
+
+#[test]
+fn receive_fsck_objects_refuses_broken_objects() {
+    let remote = TestRepo::new().unwrap();
+    let config = remote.git_dir().join("config");
+    set_value(&config, "receive.fsckObjects", Some("true")).unwrap();
+
+    // A commit whose author has no email
+    let repo = TestRepo::new().unwrap();
+    let tree = repo.tree_from_files(&[("a", b"one\n")]).unwrap();
+    let data = format!("tree {tree}\nauthor A U Thor 1234567890 +0000\ncommitter C O Mitter <committer@example.com> 1234567890 +0000\n\nbroken\n");
+    let broken = repo.write_object("commit", data.as_bytes()).unwrap();
+    repo.set_ref("refs/heads/broken", broken).unwrap();
+
+    let gitty = env!("CARGO_BIN_EXE_gitty");
+    let url = remote.path().to_str().unwrap();
+
+    let err = repo.run(gitty, &["push", url, "broken:refs/heads/broken"]).unwrap_err();
+    assert!(err.to_string().contains("remote unpack failed: error in commit"), "{err}");
+
+    assert_eq!(remote.read_ref("refs/heads/broken"), None);
+    assert!(fs::read_dir(remote.git_dir().join("objects/pack")).unwrap().next().is_none());
+
+    set_value(&config, "receive.fsck.missingEmail", Some("ignore")).unwrap();
+
+    repo.run(gitty, &["push", url, "broken:refs/heads/broken"]).unwrap();
+
+    assert_eq!(remote.read_ref("refs/heads/broken"), Some(broken));
+}
@@ -0,0 +1,193 @@
+use std::io::{Read, Write};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha1::{Digest, Sha1};
+
+use gitty::config::Config;
+use gitty::merge::{merge_trees, ConflictStyle, TreeMergeOptions};
+use gitty::refs;
+use gitty::revparse;
+use gitty::sequencer::{self, Action, Progress, Sequencer};
+use gitty::store::index::{Index, IndexEntry};
+use gitty::store::index_pack::store_pack;
+use gitty::store::tree::{flatten_tree, MODE_BLOB};
+use gitty::store::util::hash_object;
+use gitty::store::{GitObjectData, GitObjectStore};
+use gitty::testtools::{Corruption, TestRepo};
+
+#[test]
+fn reads_packed_objects() {
+    let repo = TestRepo::new().unwrap();
+    let commit = repo.commit_files(&[("README", b"hello\n"), ("src/main.rs", b"fn main() {}\n")], "initial").unwrap();
+
+    repo.pack_objects().unwrap();
+
+    let _cwd = repo.enter().unwrap();
+
+    let Some(GitObjectData::Commit { tree, message, .. }) = GitObjectStore::get(commit).map(|object| object.data) else {
+        panic!("commit {commit} isn't readable from the pack");
+    };
+
+    assert_eq!(message, b"initial\n");
+    assert_eq!(revparse::resolve("HEAD"), Some(commit));
+    assert_eq!(revparse::resolve("HEAD^{tree}"), Some(tree));
+
+    let blob = revparse::resolve("HEAD:src/main.rs").unwrap();
+    assert!(matches!(GitObjectStore::get(blob).unwrap().data, GitObjectData::Blob { data } if data == b"fn main() {}\n"));
+}
+
+#[test]
+fn reads_deltas_against_bases_named_by_id() {
+    let repo = TestRepo::new().unwrap();
+    let _cwd = repo.enter().unwrap();
+
+    let base = hash_object("blob", b"hello\n");
+    let id = hash_object("blob", b"hello\nworld\n");
+
+    // The sizes of the base and the result, then a copy of the whole base
+    // and an insert of the rest
+    let delta = [&[6, 12, 0x90, 6, 6][..], b"world\n"].concat();
+
+    let mut pack = b"PACK\0\0\0\x02\0\0\0\x02".to_vec();
+
+    for (header, data) in [(vec![0x36], &b"hello\n"[..]), ([&[0x70 | delta.len() as u8][..], &base[..]].concat(), &delta[..])] {
+        pack.extend_from_slice(&header);
+
+        let mut encoder = ZlibEncoder::new(&mut pack, Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    let checksum = Sha1::digest(&pack);
+    pack.extend_from_slice(&checksum);
+
+    store_pack(&mut &pack[..]).unwrap();
+
+    assert!(matches!(GitObjectStore::get(id).unwrap().data, GitObjectData::Blob { data } if data == b"hello\nworld\n"));
+
+    let mut data = Vec::new();
+    GitObjectStore::open_blob(id).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello\nworld\n");
+}
+
+#[test]
+fn resolves_packed_refs_and_peels_tags() {
+    let repo = TestRepo::new().unwrap();
+    let commit = repo.commit_files(&[("a", b"a\n")], "initial").unwrap();
+    let tag = repo.tag("v1.0", commit, "commit", "release").unwrap();
+
+    repo.write_packed_refs(&[("refs/heads/old", commit, None), ("refs/tags/v1.0", tag, Some(commit))]).unwrap();
+    std::fs::remove_file(repo.git_dir().join("refs/tags/v1.0")).unwrap();
+
+    let _cwd = repo.enter().unwrap();
+
+    assert_eq!(refs::resolve_ref("refs/heads/old"), Some(commit));
+    assert_eq!(revparse::resolve("v1.0"), Some(tag));
+    assert_eq!(revparse::resolve("v1.0^{commit}"), Some(commit));
+}
+
+#[test]
+fn reads_the_index() {
+    let repo = TestRepo::new().unwrap();
+    let a = repo.blob(b"a\n").unwrap();
+    let b = repo.blob(b"b\n").unwrap();
+
+    repo.write_index(&[(MODE_BLOB, "dir/b", b), (MODE_BLOB, "a", a)]).unwrap();
+
+    let _cwd = repo.enter().unwrap();
+    let index = Index::read().unwrap();
+
+    assert_eq!(index.get("a").map(|entry| entry.id), Some(a));
+    assert_eq!(index.get("dir/b").map(|entry| entry.id), Some(b));
+    assert!(index.get("b").is_none());
+}
+
+#[test]
+fn keeps_paths_which_arent_utf8() {
+    let repo = TestRepo::new().unwrap();
+    let a = repo.blob(b"a\n").unwrap();
+    let b = repo.blob(b"b\n").unwrap();
+
+    let base = repo.tree(&[(MODE_BLOB, b"caf\xe9", a), (MODE_BLOB, b"x", a)]).unwrap();
+    let ours = repo.tree(&[(MODE_BLOB, b"caf\xe9", a), (MODE_BLOB, b"x", b)]).unwrap();
+
+    let _cwd = repo.enter().unwrap();
+
+    let options = TreeMergeOptions {
+        ours_label: "ours",
+        theirs_label: "theirs",
+        base_label: "base",
+        style: ConflictStyle::default(),
+    };
+
+    let (base, ours) = (flatten_tree(base).unwrap(), flatten_tree(ours).unwrap());
+    let merge = merge_trees(&base, &ours, &base, &options).unwrap();
+
+    assert_eq!(merge.files.get(b"caf\xe9".as_slice()), Some(&(MODE_BLOB, a)));
+    assert_eq!(merge.files.get(b"x".as_slice()), Some(&(MODE_BLOB, b)));
+
+    let mut index = Index::read().unwrap();
+    index.add(IndexEntry::new(b"caf\xe9", MODE_BLOB, a));
+    index.write().unwrap();
+
+    assert_eq!(Index::read().unwrap().get(b"caf\xe9").map(|entry| entry.id), Some(a));
+}
+
+#[test]
+fn corrupt_objects_are_unreadable() {
+    let repo = TestRepo::new().unwrap();
+    let blob = repo.blob(b"some contents\n").unwrap();
+
+    repo.corrupt_object(blob, Corruption::Truncate(4)).unwrap();
+
+    let _cwd = repo.enter().unwrap();
+
+    assert!(GitObjectStore::exists(blob));
+    assert!(GitObjectStore::get(blob).is_none());
+}
+
+#[test]
+fn sequencer_stops_at_conflicts_with_them_marked_up() {
+    let repo = TestRepo::new().unwrap();
+    let base = repo.commit_files(&[("a", b"1\n2\n3\n"), ("b", b"b\n")], "base").unwrap();
+
+    let theirs = repo.tree_from_files(&[("a", b"1\ntheirs\n3\n"), ("b", b"changed\n")]).unwrap();
+    let picked = repo.commit(theirs, &[base], "theirs").unwrap();
+
+    repo.commit_files(&[("a", b"1\nours\n3\n"), ("b", b"b\n")], "ours").unwrap();
+
+    repo.write_file("a", b"1\nours\n3\n").unwrap();
+    repo.write_file("b", b"b\n").unwrap();
+    repo.write_index(&[(MODE_BLOB, "a", repo.blob(b"1\nours\n3\n").unwrap()), (MODE_BLOB, "b", repo.blob(b"b\n").unwrap())]).unwrap();
+
+    let _cwd = repo.enter().unwrap();
+
+    let todo = sequencer::todo(Action::Pick, &[picked]).unwrap();
+    let progress = Sequencer::start(todo, "cherry-pick").unwrap().run().unwrap();
+
+    assert!(matches!(progress, Progress::Stopped { conflicts, .. } if conflicts == [b"a"]));
+
+    let a = std::fs::read(repo.path().join("a")).unwrap();
+    let short = &picked.to_string()[..7];
+    assert_eq!(a, format!("1\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> {short}... theirs\n3\n").into_bytes());
+    assert_eq!(std::fs::read(repo.path().join("b")).unwrap(), b"changed\n");
+
+    let index = Index::read().unwrap();
+    assert_eq!(index.conflicted_paths(), [b"a"]);
+    assert!((1..=3).all(|stage| index.get_stage(b"a", stage).is_some()));
+}
+
+#[test]
+fn bad_config_files_say_where() {
+    let repo = TestRepo::new().unwrap();
+    let config = repo.git_dir().join("config");
+
+    std::fs::write(&config, "[core]\n\tbare = false\n[remote \"origin\"\n\turl = x\n").unwrap();
+
+    let err = Config::from_file(&config).unwrap_err();
+    assert_eq!(err.to_string(), format!("bad config line 3 in file {}", config.display()));
+
+    let _cwd = repo.enter().unwrap();
+    assert_eq!(Config::try_load().unwrap_err().to_string(), "bad config line 3 in file .git/config");
+}
@@ -0,0 +1,56 @@
+use std::process::Command;
+
+use gitty::testtools::TestRepo;
+
+fn rev_list_objects(repo: &TestRepo, revisions: &[&str]) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_gitty"))
+        .args(["rev-list", "--objects"])
+        .args(revisions)
+        .current_dir(repo.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    String::from_utf8(output.stdout).unwrap().lines().map(str::to_string).collect()
+}
+
+#[test]
+fn lists_the_tags_starting_points_are_given_as() {
+    let repo = TestRepo::new().unwrap();
+    let one = repo.commit_files(&[("a", b"one\n")], "one").unwrap();
+    let two = repo.commit_files(&[("a", b"two\n")], "two").unwrap();
+
+    let v1 = repo.tag("v1", one, "commit", "v1").unwrap();
+    let v2 = repo.tag("v2", two, "commit", "v2").unwrap();
+    let nested = repo.tag("nested", v2, "tag", "a tag of a tag").unwrap();
+
+    // After the commits, each tag on the way to them, then the trees
+    let objects = rev_list_objects(&repo, &["nested"]);
+
+    assert_eq!(objects[..4], [two.to_string(), one.to_string(), format!("{nested} nested"), format!("{v2} v2")]);
+    assert!(!objects.iter().any(|line| line.starts_with(&v1.to_string())));
+
+    // A hidden starting point's tags aren't listed, even if reached from
+    // one which is shown
+    let objects = rev_list_objects(&repo, &["nested", "^v2"]);
+    assert_eq!(objects, [format!("{nested} nested")]);
+}
+
+#[test]
+fn bare_repositories_without_a_mailmap_say_nothing_of_it() {
+    let repo = TestRepo::new_bare().unwrap();
+    let commit = repo.commit_files(&[("a", b"a\n")], "initial").unwrap();
+
+    // mailmap.blob defaults to HEAD:.mailmap in a bare repository
+    let output = Command::new(env!("CARGO_BIN_EXE_gitty"))
+        .args(["rev-list", "HEAD"])
+        .current_dir(repo.path())
+        .env("GIT_DIR", ".")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{commit}\n"));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+}